@@ -0,0 +1,377 @@
+use crate::liquidate::mint_account;
+use crate::positions::format_details;
+use anyhow::{anyhow, bail, Context};
+use clap::{Args, Subcommand};
+use liquidation_engine::internals::{
+    associated_token_account, borrow_instruction, create_token_account_instruction, decode_market_account,
+    decode_position_account, deposit_collateral_instruction, initialize_position_instruction, market_address,
+    position_address, AmountExt, MarketAccount, PositionAccount, PositionHealth, RateLimiter,
+};
+use liquidation_engine::{OracleProvider, PythOracle};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signer},
+    transaction::Transaction,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Arguments of the `account` command
+#[derive(Args, Debug)]
+pub struct AccountArgs {
+    /// Solana RPC URL
+    #[arg(long, global = true, default_value = "https://api.devnet.solana.com")]
+    rpc_url: String,
+
+    /// Path to the position owner's keypair file
+    #[arg(long, global = true, default_value = "./local_keypair.json")]
+    keypair: String,
+
+    /// Id of the market the position is in
+    #[arg(long, global = true, default_value_t = 0)]
+    market_id: u64,
+
+    /// Print the transaction instead of sending it
+    #[arg(long, global = true, default_value_t = false)]
+    dry_run: bool,
+
+    #[command(subcommand)]
+    action: AccountAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum AccountAction {
+    /// Create the keypair's position account
+    Init,
+    /// Deposit collateral into the keypair's position
+    Deposit {
+        /// Collateral to deposit, in the mint's base units
+        amount: u64,
+
+        /// Token account the collateral comes from (default: the keypair's
+        /// associated token account for the collateral mint)
+        #[arg(long)]
+        token_account: Option<Pubkey>,
+    },
+    /// Borrow against the keypair's position
+    Borrow {
+        /// Debt to take on, in the mint's base units
+        amount: u64,
+
+        /// Token account receiving the loan (default: the keypair's associated
+        /// token account for the debt mint, created if missing)
+        #[arg(long)]
+        token_account: Option<Pubkey>,
+    },
+    /// Print the keypair's position and its health at the market's oracle price
+    Status {
+        /// Symbol the market's oracle prices
+        #[arg(long, default_value = "SOL/USD")]
+        collateral_symbol: String,
+
+        /// Print JSON instead of a table
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Request an airdrop of SOL if the keypair's balance is low
+    Airdrop {
+        /// Balance in SOL below which an airdrop is requested
+        #[arg(long, default_value_t = 1.0)]
+        min_balance: f64,
+
+        /// SOL to request
+        #[arg(long, default_value_t = 1.0)]
+        amount: f64,
+    },
+}
+
+/// Render the instructions of a transaction that wasn't sent, with each
+/// account's signer and writable flags and the data in hex
+pub fn format_transaction(payer: &Pubkey, instructions: &[Instruction]) -> String {
+    let mut lines = vec![format!("Dry run: transaction paid by {}", payer)];
+    for (index, instruction) in instructions.iter().enumerate() {
+        lines.push(format!("Instruction {}: program {}", index, instruction.program_id));
+        for meta in &instruction.accounts {
+            let flags = match (meta.is_signer, meta.is_writable) {
+                (true, true) => "signer, writable",
+                (true, false) => "signer",
+                (false, true) => "writable",
+                (false, false) => "readonly",
+            };
+            lines.push(format!("  {} ({})", meta.pubkey, flags));
+        }
+        let data: String = instruction.data.iter().map(|byte| format!("{:02x}", byte)).collect();
+        lines.push(format!("  data: {}", data));
+    }
+    lines.join("\n")
+}
+
+/// Render a position account's health followed by its raw fields
+pub fn format_status(account: &PositionAccount, health: &PositionHealth) -> String {
+    let flagged = match account.flagged_at {
+        0 => "no".to_string(),
+        flagged_at => format!("at {}", flagged_at),
+    };
+    [
+        format_details(health),
+        format!("Collateral units:        {}", account.collateral),
+        format!("Debt units:              {}", account.debt),
+        format!("Flagged:                 {}", flagged),
+        format!("Closed:                  {}", if account.closed { "yes" } else { "no" }),
+    ]
+    .join("\n")
+}
+
+/// Sign and send `instructions`, or render them when `dry_run`
+async fn submit(
+    rpc: &RpcClient,
+    payer: &Keypair,
+    instructions: &[Instruction],
+    dry_run: bool,
+) -> anyhow::Result<String> {
+    if dry_run {
+        return Ok(format_transaction(&payer.pubkey(), instructions));
+    }
+    let blockhash = rpc.get_latest_blockhash().await?;
+    let transaction = Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &[payer], blockhash);
+    let signature = rpc.send_and_confirm_transaction(&transaction).await?;
+    Ok(signature.to_string())
+}
+
+async fn market_account(rpc: &RpcClient, market_id: u64) -> anyhow::Result<MarketAccount> {
+    let data = rpc.get_account_data(&market_address(market_id)).await?;
+    decode_market_account(&data).context("Market account")
+}
+
+/// Run the `account` command, returning what to print
+///
+/// `dry_run` is set when the top-level `--dry-run` flag was given.
+pub async fn run(args: AccountArgs, dry_run: bool) -> anyhow::Result<String> {
+    let dry_run = dry_run || args.dry_run;
+    let owner = read_keypair_file(&args.keypair)
+        .map_err(|e| anyhow!("Failed to read keypair {}: {}", args.keypair, e))?;
+    let rpc = RpcClient::new(args.rpc_url.clone());
+    let position = position_address(&market_address(args.market_id), &owner.pubkey());
+
+    match args.action {
+        AccountAction::Init => {
+            let existing = rpc.get_account_with_commitment(&position, rpc.commitment()).await?.value;
+            if existing.is_some() {
+                return Ok(format!("Position {} already exists", position));
+            }
+            let instruction = initialize_position_instruction(args.market_id, &owner.pubkey());
+            let output = submit(&rpc, &owner, &[instruction], dry_run).await?;
+            if dry_run {
+                return Ok(output);
+            }
+            Ok(format!("Created position {}: {}", position, output))
+        }
+        AccountAction::Deposit { amount, token_account } => {
+            let market = market_account(&rpc, args.market_id).await?;
+            let (_, token_program) = mint_account(&rpc, &market.collateral_mint).await?;
+            let token_account = token_account
+                .unwrap_or_else(|| associated_token_account(&owner.pubkey(), &market.collateral_mint, &token_program));
+            let instruction =
+                deposit_collateral_instruction(&market, &owner.pubkey(), &token_account, &token_program, amount);
+            let output = submit(&rpc, &owner, &[instruction], dry_run).await?;
+            if dry_run {
+                return Ok(output);
+            }
+            Ok(format!("Deposited {} into position {}: {}", amount, position, output))
+        }
+        AccountAction::Borrow { amount, token_account } => {
+            let market = market_account(&rpc, args.market_id).await?;
+            let (_, token_program) = mint_account(&rpc, &market.debt_mint).await?;
+            let mut instructions = Vec::new();
+            let token_account = match token_account {
+                Some(token_account) => token_account,
+                None => {
+                    instructions.push(create_token_account_instruction(
+                        &owner.pubkey(),
+                        &market.debt_mint,
+                        &token_program,
+                    ));
+                    associated_token_account(&owner.pubkey(), &market.debt_mint, &token_program)
+                }
+            };
+            instructions.push(borrow_instruction(&market, &owner.pubkey(), &token_account, &token_program, amount));
+            let output = submit(&rpc, &owner, &instructions, dry_run).await?;
+            if dry_run {
+                return Ok(output);
+            }
+            Ok(format!("Borrowed {} against position {}: {}", amount, position, output))
+        }
+        AccountAction::Status { collateral_symbol, json } => {
+            let data = rpc
+                .get_account_with_commitment(&position, rpc.commitment())
+                .await?
+                .value
+                .with_context(|| format!("Position {} doesn't exist; create it with `account init`", position))?
+                .data;
+            let account = decode_position_account(&data).with_context(|| format!("Position account {}", position))?;
+            let market = market_account(&rpc, args.market_id).await?;
+            let oracle = PythOracle::new(
+                args.rpc_url.as_str(),
+                HashMap::from([(collateral_symbol.clone(), market.oracle.into())]),
+                None,
+                Arc::new(RateLimiter::default()),
+            );
+            let price = oracle
+                .get_price(&collateral_symbol)
+                .await
+                .with_context(|| format!("Failed to price {}", collateral_symbol))?;
+            let health = PositionHealth::from_account(position, &account, Some(&collateral_symbol), price.as_f64());
+            if json {
+                Ok(serde_json::to_string_pretty(&health)?)
+            } else {
+                Ok(format_status(&account, &health))
+            }
+        }
+        AccountAction::Airdrop { min_balance, amount } => {
+            if !amount.is_finite() || amount <= 0.0 {
+                bail!("Airdrop amount must be positive, got {}", amount);
+            }
+            let balance = rpc.get_balance(&owner.pubkey()).await?;
+            if balance >= sol_to_lamports(min_balance) {
+                return Ok(format!(
+                    "Balance of {} is {} SOL, no airdrop needed",
+                    owner.pubkey(),
+                    lamports_to_sol(balance)
+                ));
+            }
+            if dry_run {
+                return Ok(format!("Dry run: would request {} SOL for {}", amount, owner.pubkey()));
+            }
+            let signature = rpc.request_airdrop(&owner.pubkey(), sol_to_lamports(amount)).await?;
+            rpc.poll_for_signature(&signature).await?;
+            Ok(format!("Airdropped {} SOL to {}: {}", amount, owner.pubkey(), signature))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use solana_sdk::signature::write_keypair_file;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        account: AccountArgs,
+    }
+
+    #[test]
+    fn test_arguments() {
+        let args = Cli::parse_from(["account", "init"]).account;
+        assert_eq!(args.rpc_url, "https://api.devnet.solana.com");
+        assert_eq!(args.keypair, "./local_keypair.json");
+        assert_eq!(args.market_id, 0);
+        assert!(!args.dry_run);
+        assert!(matches!(args.action, AccountAction::Init));
+
+        // Global options may follow the action
+        let args = Cli::parse_from([
+            "account", "deposit", "500", "--keypair", "owner.json", "--market-id", "2", "--dry-run",
+        ])
+        .account;
+        assert_eq!((args.keypair.as_str(), args.market_id), ("owner.json", 2));
+        assert!(args.dry_run);
+        assert!(matches!(args.action, AccountAction::Deposit { amount: 500, token_account: None }));
+
+        let token_account = Pubkey::new_from_array([1; 32]);
+        let args =
+            Cli::parse_from(["account", "borrow", "20", "--token-account", &token_account.to_string()]).account;
+        match args.action {
+            AccountAction::Borrow { amount, token_account: account } => {
+                assert_eq!((amount, account), (20, Some(token_account)))
+            }
+            other => panic!("expected a borrow, got {:?}", other),
+        }
+
+        let args = Cli::parse_from(["account", "airdrop", "--min-balance", "0.5"]).account;
+        match args.action {
+            AccountAction::Airdrop { min_balance, amount } => assert_eq!((min_balance, amount), (0.5, 1.0)),
+            other => panic!("expected an airdrop, got {:?}", other),
+        }
+        let args = Cli::parse_from(["account", "status", "--json"]).account;
+        assert!(matches!(args.action, AccountAction::Status { json: true, .. }));
+
+        // Amounts are base units of a token, never negative or fractional
+        assert!(Cli::try_parse_from(["account", "deposit"]).is_err());
+        assert!(Cli::try_parse_from(["account", "deposit", "-5"]).is_err());
+        assert!(Cli::try_parse_from(["account", "borrow", "1.5"]).is_err());
+        assert!(Cli::try_parse_from(["account"]).is_err());
+    }
+
+    #[test]
+    fn test_dry_run_output() {
+        let owner = Pubkey::new_from_array([2; 32]);
+        let output = format_transaction(&owner, &[initialize_position_instruction(0, &owner)]);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], format!("Dry run: transaction paid by {}", owner));
+        assert_eq!(lines[1], format!("Instruction 0: program {}", liquidation_engine::internals::PROGRAM_ID));
+        assert_eq!(lines[2], format!("  {} (readonly)", market_address(0)));
+        assert_eq!(lines[3], format!("  {} (writable)", position_address(&market_address(0), &owner)));
+        assert_eq!(lines[4], format!("  {} (signer, writable)", owner));
+        assert_eq!(lines[5], format!("  {} (readonly)", solana_sdk::system_program::ID));
+        let data: String =
+            initialize_position_instruction(0, &owner).data.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(lines[6], format!("  data: {}", data));
+        assert_eq!(data.len(), 16);
+    }
+
+    #[test]
+    fn test_status_output() {
+        let address = Pubkey::new_from_array([3; 32]);
+        let account = PositionAccount {
+            owner: Pubkey::new_from_array([4; 32]),
+            bump: 255,
+            collateral: 100,
+            debt: 150,
+            closed: false,
+            flagged_at: 1_700_000_000,
+            flag_price: 0,
+            flag_price_expo: 0,
+        };
+        let health = PositionHealth::from_account(address, &account, Some("SOL/USD"), 2.0);
+        let output = format_status(&account, &health);
+        assert!(output.starts_with(&format_details(&health)));
+        assert!(output.contains("Collateral units:        100\n"));
+        assert!(output.contains("Debt units:              150\n"));
+        assert!(output.contains("Flagged:                 at 1700000000\n"));
+        assert!(output.ends_with("Closed:                  no"));
+        assert!(output.contains("Liquidatable:            no\n"));
+
+        // At 1.70 the 170 of collateral covers the 150 of debt, but the
+        // program only counts 85% of it
+        let health = PositionHealth::from_account(address, &account, Some("SOL/USD"), 1.7);
+        let output = format_status(&account, &health);
+        assert!(output.contains("Liquidatable:            yes\n"));
+        assert!(output.contains("Liquidation price:       1.76\n"));
+    }
+
+    /// Dry-runs every action against devnet with the keypair at
+    /// `LIQUIDATION_DEVNET_KEYPAIR`; skipped unless it's set
+    #[tokio::test]
+    async fn test_devnet_dry_run() {
+        let Ok(keypair) = std::env::var("LIQUIDATION_DEVNET_KEYPAIR") else {
+            return;
+        };
+        for action in [vec!["init"], vec!["deposit", "1"], vec!["borrow", "1"], vec!["airdrop"]] {
+            let cli = Cli::parse_from(["account", "--keypair", &keypair, "--dry-run"].into_iter().chain(action));
+            let output = run(cli.account, false).await.unwrap();
+            assert!(!output.is_empty());
+        }
+
+        // A fresh keypair has no position to show
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fresh.json");
+        write_keypair_file(&Keypair::new(), &path).unwrap();
+        let cli = Cli::parse_from(["account", "status", "--keypair", path.to_str().unwrap()]);
+        assert!(run(cli.account, false).await.unwrap_err().to_string().contains("doesn't exist"));
+    }
+}
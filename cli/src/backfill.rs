@@ -0,0 +1,193 @@
+use crate::oracle::load_config;
+use anyhow::Context;
+use clap::Args;
+use liquidation_engine::backfill::{BackfillBound, BackfillRange, BackfillStats};
+use liquidation_engine::storage::LiquidationStore;
+use liquidation_engine::internals::{RateLimiter, PROGRAM_ID};
+use liquidation_engine::{LiquidationConfig, LiquidationEngine, PythOracle};
+use solana_sdk::pubkey::Pubkey;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Arguments of the `backfill` command
+#[derive(Args, Debug)]
+pub struct BackfillArgs {
+    /// SQLite database the liquidations are recorded to, as the engine's
+    /// database_path
+    #[arg(long)]
+    database_path: PathBuf,
+
+    /// Solana RPC URL, for the program's transactions
+    #[arg(long, default_value = "https://api.devnet.solana.com")]
+    rpc_url: String,
+
+    /// Liquidation program whose liquidations are backfilled
+    #[arg(long, default_value_t = PROGRAM_ID)]
+    program_id: Pubkey,
+
+    /// Symbol of the program's positions
+    #[arg(long, default_value = "SOL/USD")]
+    collateral_symbol: String,
+
+    /// First slot backfilled
+    #[arg(long, conflicts_with = "from_time")]
+    from_slot: Option<u64>,
+
+    /// Slot backfilled up to, excluding it
+    #[arg(long, conflicts_with = "to_time")]
+    to_slot: Option<u64>,
+
+    /// First time backfilled, as RFC 3339 or Unix seconds
+    #[arg(long, value_parser = parse_time)]
+    from_time: Option<i64>,
+
+    /// Time backfilled up to, excluding it, as RFC 3339 or Unix seconds
+    #[arg(long, value_parser = parse_time)]
+    to_time: Option<i64>,
+
+    /// Print JSON instead of a summary
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+impl BackfillArgs {
+    /// Range of transactions the arguments select
+    fn range(&self) -> BackfillRange {
+        BackfillRange {
+            start: (self.from_slot.map(BackfillBound::Slot)).or(self.from_time.map(BackfillBound::Timestamp)),
+            end: (self.to_slot.map(BackfillBound::Slot)).or(self.to_time.map(BackfillBound::Timestamp)),
+        }
+    }
+}
+
+/// Parse a time given as RFC 3339, e.g. 2024-06-01T00:00:00Z, or Unix seconds
+fn parse_time(value: &str) -> Result<i64, String> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|time| time.timestamp())
+        .map_err(|e| format!("expected RFC 3339 or Unix seconds: {}", e))
+}
+
+/// Render what a backfill went through and recorded
+pub fn format_stats(program_id: &Pubkey, stats: &BackfillStats) -> String {
+    let fields = [
+        ("Resumed after", stats.resumed_after.clone().unwrap_or_else(|| "-".to_string())),
+        ("Signatures listed", stats.signatures.to_string()),
+        ("Transactions parsed", stats.transactions.to_string()),
+        ("Liquidations recorded", stats.recorded.to_string()),
+        ("Already recorded", stats.duplicates.to_string()),
+        ("Missing events", stats.missing_events.to_string()),
+    ];
+    let width = fields.iter().map(|(name, _)| name.len() + 1).max().unwrap_or(0);
+    let mut lines = vec![format!("Backfilled liquidations of program {}", program_id)];
+    lines.extend(fields.iter().map(|(name, value)| format!("{:<width$} {}", format!("{}:", name), value)));
+    lines.join("\n")
+}
+
+/// Run the `backfill` command, returning what to print
+///
+/// The configuration file at `config` sets the liquidation fee rewards are
+/// estimated with and the RPC request budget.
+pub async fn run(args: BackfillArgs, config: Option<&Path>) -> anyhow::Result<String> {
+    let config = load_config(config)?;
+    let rate_limiter = Arc::new(RateLimiter::new(config.rate_limit.clone()));
+    let oracle = PythOracle::new(
+        args.rpc_url.as_str(),
+        config.oracle_feeds(),
+        Some(config.oracle_config()),
+        rate_limiter.clone(),
+    );
+    // Backfilling sends nothing, so no keypair is needed
+    let engine = LiquidationEngine::builder()
+        .rpc_client(args.rpc_url.as_str())
+        .oracle(Arc::new(oracle))
+        .rate_limiter(rate_limiter)
+        .config(LiquidationConfig { dry_run: true, ..config })
+        .build()?;
+    let store = LiquidationStore::open(&args.database_path)
+        .with_context(|| format!("Failed to open {}", args.database_path.display()))?;
+
+    let stats = engine
+        .backfill_liquidations(&store, &args.program_id, &args.collateral_symbol, &args.range())
+        .await
+        .context("Backfill interrupted; run it again to resume")?;
+    if args.json {
+        Ok(serde_json::to_string_pretty(&stats)?)
+    } else {
+        Ok(format_stats(&args.program_id, &stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        backfill: BackfillArgs,
+    }
+
+    #[test]
+    fn test_arguments() {
+        let args = Cli::parse_from(["backfill", "--database-path", "liquidations.db"]).backfill;
+        assert_eq!(args.program_id, PROGRAM_ID);
+        assert_eq!(args.range(), BackfillRange::default());
+
+        let args = Cli::parse_from([
+            "backfill",
+            "--database-path",
+            "liquidations.db",
+            "--from-slot",
+            "250000000",
+            "--to-time",
+            "2024-06-01T00:00:00Z",
+        ])
+        .backfill;
+        assert_eq!(
+            args.range(),
+            BackfillRange {
+                start: Some(BackfillBound::Slot(250_000_000)),
+                end: Some(BackfillBound::Timestamp(1_717_200_000)),
+            }
+        );
+        let args =
+            Cli::parse_from(["backfill", "--database-path", "l.db", "--from-time", "1717200000"]).backfill;
+        assert_eq!(args.range().start, Some(BackfillBound::Timestamp(1_717_200_000)));
+
+        // A bound is a slot or a time, not both
+        assert!(Cli::try_parse_from(["backfill", "--database-path", "l.db", "--from-slot", "1", "--from-time", "2"])
+            .is_err());
+        assert!(Cli::try_parse_from(["backfill", "--database-path", "l.db", "--to-time", "yesterday"]).is_err());
+        assert!(Cli::try_parse_from(["backfill"]).is_err());
+    }
+
+    #[test]
+    fn test_stats_output() {
+        let stats = BackfillStats {
+            resumed_after: None,
+            signatures: 1_007,
+            transactions: 996,
+            recorded: 1_990,
+            duplicates: 2,
+            missing_events: 1,
+        };
+        let output = format_stats(&PROGRAM_ID, &stats);
+        assert_eq!(
+            output,
+            format!(
+                "Backfilled liquidations of program {}\n\
+                 Resumed after:         -\n\
+                 Signatures listed:     1007\n\
+                 Transactions parsed:   996\n\
+                 Liquidations recorded: 1990\n\
+                 Already recorded:      2\n\
+                 Missing events:        1",
+                PROGRAM_ID
+            )
+        );
+    }
+}
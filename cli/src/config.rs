@@ -0,0 +1,360 @@
+use anyhow::{anyhow, bail, Context};
+use clap::{Args, Subcommand};
+use liquidation_engine::internals::{ConfigViolation, FeedAddress, PYTH_DEVNET_PROGRAM_ID, PYTH_MAINNET_PROGRAM_ID};
+use liquidation_engine::LiquidationConfig;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The default configuration with every setting documented
+pub const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+/// Arguments of the `config` command
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    action: ConfigAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Write the default configuration, with every setting documented
+    Init {
+        /// File to write
+        #[arg(default_value = "liquidation.toml")]
+        path: PathBuf,
+
+        /// Overwrite the file if it exists
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Check a configuration file, failing if any setting is invalid
+    Validate {
+        /// The configuration file
+        path: PathBuf,
+
+        /// Also check the configured price accounts on the cluster behind this
+        /// RPC URL
+        #[arg(long)]
+        rpc_url: Option<String>,
+    },
+    /// Show the settings of a configuration file that differ from the defaults
+    Diff {
+        /// The configuration file
+        path: PathBuf,
+    },
+}
+
+/// Problems found in a configuration file
+#[derive(Debug, Default, PartialEq)]
+pub struct Validation {
+    /// Settings that make the configuration unusable
+    pub violations: Vec<ConfigViolation>,
+    /// Settings that are usable but likely mistaken
+    pub warnings: Vec<String>,
+}
+
+fn price_account_field(symbol: &str) -> String {
+    format!("price_accounts.\"{}\"", symbol)
+}
+
+/// Parse and validate a configuration file's contents
+///
+/// Malformed files fail outright. Otherwise every invalid price account pubkey
+/// and every setting failing validation is reported, along with the
+/// configuration the rest of the file describes.
+pub fn check(toml: &str) -> anyhow::Result<(LiquidationConfig, Validation)> {
+    let mut table: toml::Table = toml.parse().context("Invalid TOML")?;
+    let mut validation = Validation::default();
+
+    // Report every bad pubkey rather than just the first deserialization hits
+    if let Some(toml::Value::Table(accounts)) = table.get_mut("price_accounts") {
+        accounts.retain(|symbol, value| {
+            let valid = value.as_str().is_some_and(|pubkey| Pubkey::from_str(pubkey).is_ok());
+            if !valid {
+                validation
+                    .violations
+                    .push(ConfigViolation::new(price_account_field(symbol), format!("is not a valid pubkey: {}", value)));
+            }
+            valid
+        });
+    }
+
+    let config: LiquidationConfig = toml::Value::Table(table)
+        .try_into()
+        .context("Invalid configuration")?;
+    validation.violations.extend(config.violations());
+    Ok((config, validation))
+}
+
+/// Check a configured price account against how it exists on the cluster
+///
+/// `owner` is the program owning the account, or `None` if there's no such
+/// account.
+pub fn check_price_account(
+    symbol: &str,
+    pubkey: &FeedAddress,
+    owner: Option<&Pubkey>,
+    use_mainnet: bool,
+    validation: &mut Validation,
+) {
+    let field = price_account_field(symbol);
+    match owner {
+        None => validation
+            .violations
+            .push(ConfigViolation::new(field, format!("account {} doesn't exist on the cluster", pubkey))),
+        Some(owner) if *owner == PYTH_MAINNET_PROGRAM_ID && !use_mainnet => validation
+            .warnings
+            .push(format!("{} is a mainnet Pyth feed but use_mainnet is false", field)),
+        Some(owner) if *owner == PYTH_DEVNET_PROGRAM_ID && use_mainnet => validation
+            .warnings
+            .push(format!("{} is a devnet Pyth feed but use_mainnet is true", field)),
+        Some(owner) if *owner == PYTH_MAINNET_PROGRAM_ID || *owner == PYTH_DEVNET_PROGRAM_ID => {}
+        Some(owner) => validation.violations.push(ConfigViolation::new(
+            field,
+            format!("account {} isn't a Pyth price account (owned by {})", pubkey, owner),
+        )),
+    }
+}
+
+/// Check every configured price account on the cluster behind `rpc`
+async fn check_price_accounts(rpc: &RpcClient, config: &LiquidationConfig, validation: &mut Validation) -> anyhow::Result<()> {
+    let mut accounts: Vec<(&String, &FeedAddress)> = config.price_accounts.iter().collect();
+    accounts.sort();
+    let pubkeys: Vec<Pubkey> = accounts.iter().map(|(_, pubkey)| pubkey.pubkey()).collect();
+    let fetched = rpc
+        .get_multiple_accounts(&pubkeys)
+        .await
+        .context("Failed to fetch the price accounts")?;
+    for ((symbol, pubkey), account) in accounts.into_iter().zip(fetched) {
+        let owner = account.as_ref().map(|account| &account.owner);
+        check_price_account(symbol, pubkey, owner, config.use_mainnet, validation);
+    }
+    Ok(())
+}
+
+/// Render a validation of the file at `path`
+pub fn format_validation(path: &Path, validation: &Validation) -> String {
+    let mut lines = Vec::new();
+    if validation.violations.is_empty() {
+        lines.push(format!("{}: valid", path.display()));
+    } else {
+        lines.push(format!("{}: {} invalid setting(s)", path.display(), validation.violations.len()));
+        lines.extend(validation.violations.iter().map(|violation| format!("  {}", violation)));
+    }
+    lines.extend(validation.warnings.iter().map(|warning| format!("warning: {}", warning)));
+    lines.join("\n")
+}
+
+fn read(path: &Path) -> anyhow::Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Render the settings of `config` that differ from the defaults
+pub fn format_diff(config: &LiquidationConfig) -> String {
+    let changes = LiquidationConfig::default().diff(config);
+    if changes.is_empty() {
+        return "No settings differ from the defaults".to_string();
+    }
+    changes
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Run the `config` command, returning what to print
+///
+/// Fails when validation finds invalid settings, so deployments can be gated
+/// on the exit code.
+pub async fn run(args: ConfigArgs) -> anyhow::Result<String> {
+    match args.action {
+        ConfigAction::Init { path, force } => {
+            if path.exists() && !force {
+                bail!("{} already exists, pass --force to overwrite it", path.display());
+            }
+            std::fs::write(&path, DEFAULT_CONFIG).with_context(|| format!("Failed to write {}", path.display()))?;
+            Ok(format!("Wrote the default configuration to {}", path.display()))
+        }
+        ConfigAction::Validate { path, rpc_url } => {
+            let (config, mut validation) = check(&read(&path)?).with_context(|| path.display().to_string())?;
+            if let Some(rpc_url) = rpc_url {
+                check_price_accounts(&RpcClient::new(rpc_url), &config, &mut validation).await?;
+            }
+            let report = format_validation(&path, &validation);
+            if validation.violations.is_empty() {
+                Ok(report)
+            } else {
+                Err(anyhow!(report))
+            }
+        }
+        ConfigAction::Diff { path } => {
+            let (config, _) = check(&read(&path)?).with_context(|| path.display().to_string())?;
+            Ok(format_diff(&config))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    const VALID: &str = include_str!("../fixtures/config/valid.toml");
+    const INVALID: &str = include_str!("../fixtures/config/invalid.toml");
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        config: ConfigArgs,
+    }
+
+    async fn run_args(args: &[&str]) -> anyhow::Result<String> {
+        run(Cli::try_parse_from(std::iter::once("config").chain(args.iter().copied()))?.config).await
+    }
+
+    /// Keys of a TOML table and its nested tables, as dotted paths
+    fn collect_keys(table: &toml::Table, prefix: &str, keys: &mut Vec<String>) {
+        for (key, value) in table {
+            let path = format!("{}{}", prefix, key);
+            match value {
+                toml::Value::Table(table) if !table.is_empty() => collect_keys(table, &format!("{}.", path), keys),
+                _ => keys.push(path),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_init() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("liquidation.toml");
+        let path = path.to_str().unwrap();
+        run_args(&["init", path]).await.unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), DEFAULT_CONFIG);
+        // An existing file is only replaced when asked to
+        assert!(run_args(&["init", path]).await.is_err());
+        run_args(&["init", path, "--force"]).await.unwrap();
+
+        // The written file is the default configuration, setting for setting
+        let (config, validation) = check(DEFAULT_CONFIG).unwrap();
+        assert_eq!(validation, Validation::default());
+        assert!(LiquidationConfig::default().diff(&config).is_empty());
+        let (mut documented, mut expected) = (Vec::new(), Vec::new());
+        collect_keys(&DEFAULT_CONFIG.parse().unwrap(), "", &mut documented);
+        collect_keys(&toml::Table::try_from(LiquidationConfig::default()).unwrap(), "", &mut expected);
+        documented.sort();
+        expected.sort();
+        assert_eq!(documented, expected);
+
+        // Every setting comes with a comment
+        let lines: Vec<&str> = DEFAULT_CONFIG.lines().collect();
+        for (i, line) in lines.iter().enumerate().skip(1) {
+            if !line.is_empty() && !line.starts_with('#') && !line.starts_with('[') {
+                assert!(lines[i - 1].starts_with('#') || lines[i - 1].starts_with('['), "{} is undocumented", line);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let dir = tempfile::tempdir().unwrap();
+        let valid = dir.path().join("valid.toml");
+        let invalid = dir.path().join("invalid.toml");
+        std::fs::write(&valid, VALID).unwrap();
+        std::fs::write(&invalid, INVALID).unwrap();
+
+        let report = run_args(&["validate", valid.to_str().unwrap()]).await.unwrap();
+        assert_eq!(report, format!("{}: valid", valid.display()));
+
+        let error = run_args(&["validate", invalid.to_str().unwrap()]).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}: 3 invalid setting(s)\n  \
+                 price_accounts.\"BTC/USD\" is not a valid pubkey: \"HovQMDrbAgAYPCmHVSrezcSmkMtXSSUsLDFANExrZh0\"\n  \
+                 maintenance_margin must be between 0 and 1, got 5\n  \
+                 rate_limit.burst must be at least 1",
+                invalid.display()
+            )
+        );
+
+        assert!(run_args(&["validate", "missing.toml"]).await.is_err());
+        assert!(check("maintenance_margin = ").is_err());
+        assert!(check("maintenance_margin = \"high\"").is_err());
+    }
+
+    #[test]
+    fn test_validated_file_configures_engine() {
+        // A file the CLI validates configures the library's engine, the same one
+        // the engine binary runs
+        let (config, validation) = check(VALID).unwrap();
+        assert_eq!(validation, Validation::default());
+        let oracle = liquidation_engine::PythOracle::new(
+            "https://api.devnet.solana.com",
+            config.oracle_feeds(),
+            Some(config.oracle_config()),
+            std::sync::Arc::new(liquidation_engine::internals::RateLimiter::default()),
+        );
+        let engine = liquidation_engine::LiquidationEngine::builder()
+            .rpc_client("https://api.devnet.solana.com")
+            .oracle(std::sync::Arc::new(oracle))
+            .keypair(solana_sdk::signature::Keypair::new())
+            .config(config.clone())
+            .build()
+            .unwrap();
+        assert!(config.diff(&engine.config()).is_empty());
+        assert!(!engine.config().dry_run);
+    }
+
+    #[test]
+    fn test_price_account_checks() {
+        let sol = FeedAddress::new_unique();
+        let mut validation = Validation::default();
+        check_price_account("SOL/USD", &sol, Some(&PYTH_DEVNET_PROGRAM_ID), false, &mut validation);
+        check_price_account("SOL/USD", &sol, Some(&PYTH_MAINNET_PROGRAM_ID), true, &mut validation);
+        assert_eq!(validation, Validation::default());
+
+        check_price_account("SOL/USD", &sol, Some(&PYTH_MAINNET_PROGRAM_ID), false, &mut validation);
+        check_price_account("SOL/USD", &sol, Some(&PYTH_DEVNET_PROGRAM_ID), true, &mut validation);
+        assert_eq!(
+            validation.warnings,
+            [
+                "price_accounts.\"SOL/USD\" is a mainnet Pyth feed but use_mainnet is false",
+                "price_accounts.\"SOL/USD\" is a devnet Pyth feed but use_mainnet is true",
+            ]
+        );
+        assert!(validation.violations.is_empty());
+
+        check_price_account("SOL/USD", &sol, None, false, &mut validation);
+        check_price_account("SOL/USD", &sol, Some(&Pubkey::new_unique()), false, &mut validation);
+        let fields: Vec<&str> = validation.violations.iter().map(|violation| violation.field.as_str()).collect();
+        assert_eq!(fields, ["price_accounts.\"SOL/USD\""; 2]);
+        assert!(validation.violations[0].message.contains("doesn't exist"));
+        assert!(validation.violations[1].message.contains("isn't a Pyth price account"));
+    }
+
+    #[tokio::test]
+    async fn test_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("valid.toml");
+        std::fs::write(&path, VALID).unwrap();
+        let diff = run_args(&["diff", path.to_str().unwrap()]).await.unwrap();
+        assert_eq!(
+            diff,
+            [
+                "dry_run: true -> false",
+                "maintenance_margin: 0.05 -> 0.08",
+                "price_accounts.SOL/USD: null -> \"J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix\"",
+                "rate_limit.burst: 20 -> 5",
+                "whitelisted_symbols: [\"BTC/USD\",\"ETH/USD\"] -> [\"SOL/USD\"]",
+            ]
+            .join("\n")
+        );
+
+        assert_eq!(format_diff(&check(DEFAULT_CONFIG).unwrap().0), "No settings differ from the defaults");
+        // Invalid settings still show up, so the diff explains a failed validation
+        let (config, _) = check(INVALID).unwrap();
+        assert!(format_diff(&config).contains("maintenance_margin: 0.05 -> 5.0"));
+    }
+}
@@ -0,0 +1,483 @@
+use anyhow::{bail, Context};
+use clap::Args;
+use liquidation_engine::internals::{
+    associated_token_account, decode_market_account, decode_position_account, liquidate_instruction, market_address,
+    max_repay_amount, token_program_of, transfer_fee, AmountExt, LiquidateAccounts, LiquidationOutcome,
+    PositionAccount, RateLimiter, INSUFFICIENT_FUNDS_ERROR, KEEPER_NOT_WHITELISTED_ERROR, POSITION_HEALTHY_ERROR,
+};
+use liquidation_engine::{OracleProvider, PythOracle};
+use solana_client::client_error::ClientError;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Arguments of the `liquidate` command
+#[derive(Args, Debug)]
+pub struct LiquidateArgs {
+    /// The position account to liquidate
+    position: Pubkey,
+
+    /// Solana RPC URL
+    #[arg(long, default_value = "https://api.devnet.solana.com")]
+    rpc_url: String,
+
+    /// Path to the liquidator's keypair file
+    #[arg(long, default_value = "./local_keypair.json")]
+    keypair: String,
+
+    /// Id of the position's market in the program
+    #[arg(long, default_value_t = 0)]
+    market_id: u64,
+
+    /// Token vault holding the position's collateral
+    #[arg(long)]
+    vault: Pubkey,
+
+    /// Token vault debt is borrowed from and repaid into
+    #[arg(long)]
+    debt_vault: Pubkey,
+
+    /// Insurance fund token vault
+    #[arg(long)]
+    insurance_fund_vault: Pubkey,
+
+    /// Pyth price account for the collateral, passed to the program
+    #[arg(long)]
+    oracle: Pubkey,
+
+    /// Symbol the oracle prices, for checking the position's health and valuing
+    /// the seized collateral
+    #[arg(long, default_value = "SOL/USD")]
+    collateral_symbol: String,
+
+    /// Token account paying the repayment (default: the keypair's associated
+    /// token account for the debt vault's mint)
+    #[arg(long)]
+    liquidator_token_account: Option<Pubkey>,
+
+    /// Token account receiving the seized collateral (default: the keypair's associated
+    /// token account for the vault's mint)
+    #[arg(long)]
+    liquidator_collateral_account: Option<Pubkey>,
+
+    /// Debt to repay (default: the most the market's close factor allows)
+    #[arg(long)]
+    repay_amount: Option<u64>,
+
+    /// Send even if the position looks healthy locally
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// Simulate the liquidation and print its expected outcome instead of sending it
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+/// Why a liquidation was refused or failed
+#[derive(Debug, thiserror::Error)]
+pub enum LiquidateError {
+    #[error("Position {position} doesn't exist; it may have been closed")]
+    PositionNotFound { position: Pubkey },
+    #[error("Position {position} is healthy: collateral {collateral} covers debt {debt} (use --force to send anyway)")]
+    PositionHealthy { position: Pubkey, collateral: u64, debt: u64 },
+    #[error("Repay amount {requested} exceeds the {max} the close factor allows for position {position}")]
+    CloseFactorExceeded { position: Pubkey, requested: u64, max: u64 },
+    #[error("Liquidator {keeper} is not a whitelisted keeper of the market")]
+    KeeperNotWhitelisted { keeper: Pubkey },
+    #[error("Insufficient liquidator token balance in {account}: {balance} available, {required} needed")]
+    InsufficientBalance { account: Pubkey, balance: u64, required: u64 },
+    #[error("RPC request failed: {0}")]
+    Rpc(String),
+    #[error("Liquidation transaction failed: {0}")]
+    Transaction(TransactionError),
+}
+
+/// What a liquidation failure is judged against
+struct Attempt<'a> {
+    position: Pubkey,
+    account: &'a PositionAccount,
+    liquidator: Pubkey,
+    liquidator_token_account: Pubkey,
+    balance: u64,
+    repay_amount: u64,
+}
+
+impl Attempt<'_> {
+    /// Name the failure of a transaction the node rejected
+    fn transaction_error(&self, error: TransactionError) -> LiquidateError {
+        match error {
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) if code == POSITION_HEALTHY_ERROR => {
+                LiquidateError::PositionHealthy {
+                    position: self.position,
+                    collateral: self.account.collateral,
+                    debt: self.account.debt,
+                }
+            }
+            TransactionError::InstructionError(_, InstructionError::Custom(code))
+                if code == KEEPER_NOT_WHITELISTED_ERROR =>
+            {
+                LiquidateError::KeeperNotWhitelisted { keeper: self.liquidator }
+            }
+            TransactionError::InstructionError(_, InstructionError::Custom(code))
+                if code == INSUFFICIENT_FUNDS_ERROR =>
+            {
+                LiquidateError::InsufficientBalance {
+                    account: self.liquidator_token_account,
+                    balance: self.balance,
+                    required: self.repay_amount,
+                }
+            }
+            error => LiquidateError::Transaction(error),
+        }
+    }
+
+    fn client_error(&self, error: ClientError) -> LiquidateError {
+        match error.get_transaction_error() {
+            Some(error) => self.transaction_error(error),
+            None => LiquidateError::Rpc(error.to_string()),
+        }
+    }
+}
+
+fn rpc_error(error: ClientError) -> LiquidateError {
+    LiquidateError::Rpc(error.to_string())
+}
+
+/// A mint account with the token program owning it
+pub(crate) async fn mint_account(rpc: &RpcClient, mint: &Pubkey) -> anyhow::Result<(Account, Pubkey)> {
+    let account = rpc.get_account(mint).await.map_err(rpc_error)?;
+    let token_program = token_program_of(&account).with_context(|| format!("Mint {}", mint))?;
+    Ok((account, token_program))
+}
+
+/// Refuse to liquidate a position the program would reject, unless forced
+///
+/// The program liquidates once the collateral's value at the oracle price no
+/// longer covers debt. Without a price the program is left to judge.
+pub fn check_liquidatable(
+    position: &Pubkey,
+    account: &PositionAccount,
+    collateral_price: Option<f64>,
+    force: bool,
+) -> Result<(), LiquidateError> {
+    let liquidatable = collateral_price.is_none_or(|price| (account.collateral as f64) * price < account.debt as f64);
+    if force || liquidatable {
+        return Ok(());
+    }
+    Err(LiquidateError::PositionHealthy {
+        position: *position,
+        collateral: account.collateral,
+        debt: account.debt,
+    })
+}
+
+/// Render the expected outcome of a simulated liquidation, valuing the seized
+/// collateral at the price of the collateral, quoted as `symbol`, and showing
+/// any transfer fee the debt mint withholds from the repayment
+pub fn format_dry_run(
+    position: &Pubkey,
+    outcome: &LiquidationOutcome,
+    symbol: &str,
+    units_consumed: Option<u64>,
+) -> String {
+    let price = outcome.collateral_price;
+    let seized = format!("{} ({:.2} at {} {:.2})", outcome.seized, outcome.seized as f64 * price, symbol, price);
+    let simulation = match units_consumed {
+        Some(units) => format!("ok, {} compute units", units),
+        None => "ok".to_string(),
+    };
+    let mut lines = vec![
+        format!("Dry run: liquidating position {}", position),
+        format!("Repay amount:         {}", outcome.repay_amount),
+    ];
+    if outcome.repay_fee > 0 {
+        lines.push(format!("Transfer fee:         {}", outcome.repay_fee));
+    }
+    lines.extend([
+        format!("Collateral seized:    {}", seized),
+        format!("Remaining collateral: {}", outcome.remaining_collateral),
+        format!("Remaining debt:       {}", outcome.remaining_debt),
+        format!("Simulation:           {}", simulation),
+    ]);
+    lines.join("\n")
+}
+
+/// Run the `liquidate` command, returning what to print
+///
+/// `dry_run` is set when the top-level `--dry-run` flag was given.
+pub async fn run(args: LiquidateArgs, dry_run: bool) -> anyhow::Result<String> {
+    let dry_run = dry_run || args.dry_run;
+    let payer = read_keypair_file(&args.keypair)
+        .map_err(|e| anyhow::anyhow!("Failed to read keypair {}: {}", args.keypair, e))?;
+    let rpc = RpcClient::new(args.rpc_url.clone());
+
+    let data = rpc
+        .get_account_with_commitment(&args.position, rpc.commitment())
+        .await
+        .map_err(rpc_error)?
+        .value
+        .ok_or(LiquidateError::PositionNotFound { position: args.position })?
+        .data;
+    let account = decode_position_account(&data).with_context(|| format!("Position account {}", args.position))?;
+    let oracle = PythOracle::new(
+        args.rpc_url.as_str(),
+        HashMap::from([(args.collateral_symbol.clone(), args.oracle.into())]),
+        None,
+        Arc::new(RateLimiter::default()),
+    );
+    let price = match oracle.get_price(&args.collateral_symbol).await {
+        Ok(price) => Some(price.as_f64()),
+        Err(e) => {
+            log::warn!("Couldn't price the collateral: {}", e);
+            None
+        }
+    };
+    check_liquidatable(&args.position, &account, price, args.force)?;
+
+    let market_data = rpc.get_account_data(&market_address(args.market_id)).await.map_err(rpc_error)?;
+    let market = decode_market_account(&market_data).context("Market account")?;
+    if !market.allows_liquidator(&payer.pubkey()) {
+        return Err(LiquidateError::KeeperNotWhitelisted { keeper: payer.pubkey() }.into());
+    }
+    let max_repay = max_repay_amount(&account, market.close_factor_bps);
+    let repay_amount = args.repay_amount.unwrap_or(max_repay);
+    if repay_amount == 0 {
+        bail!("Position {} has no debt the close factor allows repaying", args.position);
+    }
+    if repay_amount > max_repay {
+        return Err(LiquidateError::CloseFactorExceeded {
+            position: args.position,
+            requested: repay_amount,
+            max: max_repay,
+        }
+        .into());
+    }
+
+    // Either mint may belong to the classic token program or Token-2022
+    let (_, collateral_token_program) = mint_account(&rpc, &market.collateral_mint).await?;
+    let (debt_mint, debt_token_program) = mint_account(&rpc, &market.debt_mint).await?;
+    let liquidator_token_account = match args.liquidator_token_account {
+        Some(account) => account,
+        None => associated_token_account(&payer.pubkey(), &market.debt_mint, &debt_token_program),
+    };
+    let liquidator_collateral_account = match args.liquidator_collateral_account {
+        Some(account) => account,
+        None => associated_token_account(&payer.pubkey(), &market.collateral_mint, &collateral_token_program),
+    };
+    let balance = rpc
+        .get_token_account_balance(&liquidator_token_account)
+        .await
+        .map_err(rpc_error)?
+        .amount
+        .parse::<u64>()
+        .context("Invalid token balance")?;
+    if balance < repay_amount {
+        return Err(LiquidateError::InsufficientBalance {
+            account: liquidator_token_account,
+            balance,
+            required: repay_amount,
+        }
+        .into());
+    }
+    let attempt = Attempt {
+        position: args.position,
+        account: &account,
+        liquidator: payer.pubkey(),
+        liquidator_token_account,
+        balance,
+        repay_amount,
+    };
+
+    let accounts = LiquidateAccounts {
+        position: args.position,
+        vault: args.vault,
+        debt_vault: args.debt_vault,
+        liquidator_token_account,
+        liquidator_collateral_account,
+        insurance_fund_vault: args.insurance_fund_vault,
+        collateral_mint: market.collateral_mint,
+        debt_mint: market.debt_mint,
+        oracle: args.oracle,
+        collateral_token_program,
+        debt_token_program,
+        liquidator: payer.pubkey(),
+        market_id: args.market_id,
+    };
+    let blockhash = rpc.get_latest_blockhash().await.map_err(rpc_error)?;
+    let transaction = Transaction::new_signed_with_payer(
+        &[liquidate_instruction(&accounts, repay_amount)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        blockhash,
+    );
+
+    if dry_run {
+        let simulation = rpc
+            .simulate_transaction(&transaction)
+            .await
+            .map_err(|e| attempt.client_error(e))?
+            .value;
+        if let Some(error) = simulation.err {
+            return Err(attempt.transaction_error(error).into());
+        }
+        let price = price.context("Couldn't price the collateral to value the seizure")?;
+        let epoch = rpc.get_epoch_info().await.map_err(rpc_error)?.epoch;
+        let repay_fee = transfer_fee(&debt_mint, epoch, repay_amount)?;
+        let outcome =
+            LiquidationOutcome::with_repay_fee(&account, repay_amount, repay_fee, price, market.liquidation_bonus_bps);
+        return Ok(format_dry_run(&args.position, &outcome, &args.collateral_symbol, simulation.units_consumed));
+    }
+
+    let signature = rpc
+        .send_and_confirm_transaction(&transaction)
+        .await
+        .map_err(|e| attempt.client_error(e))?;
+    Ok(format!("Liquidated position {}: {}", args.position, signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        liquidate: LiquidateArgs,
+    }
+
+    fn create_account(collateral: u64, debt: u64) -> PositionAccount {
+        PositionAccount {
+            owner: Pubkey::new_unique(),
+            bump: 255,
+            collateral,
+            debt,
+            closed: false,
+            flagged_at: 0,
+            flag_price: 0,
+            flag_price_expo: 0,
+        }
+    }
+
+    #[test]
+    fn test_arguments() {
+        let [position, vault, debt_vault, insurance_fund_vault, oracle] =
+            [1u8, 2, 3, 4, 5].map(|seed| Pubkey::new_from_array([seed; 32]));
+        let required = [
+            "liquidate".to_string(),
+            position.to_string(),
+            "--vault".to_string(),
+            vault.to_string(),
+            "--debt-vault".to_string(),
+            debt_vault.to_string(),
+            "--insurance-fund-vault".to_string(),
+            insurance_fund_vault.to_string(),
+            "--oracle".to_string(),
+            oracle.to_string(),
+        ];
+
+        let args = Cli::parse_from(&required).liquidate;
+        assert_eq!((args.position, args.vault, args.debt_vault, args.oracle), (position, vault, debt_vault, oracle));
+        assert_eq!((args.repay_amount, args.market_id), (None, 0));
+        assert_eq!((args.liquidator_token_account, args.liquidator_collateral_account), (None, None));
+        assert!(!args.force && !args.dry_run);
+
+        let flags = ["--repay-amount", "55", "--market-id", "3", "--force", "--dry-run"];
+        let args = Cli::parse_from(required.iter().map(String::as_str).chain(flags)).liquidate;
+        assert_eq!((args.repay_amount, args.market_id), (Some(55), 3));
+        assert!(args.force && args.dry_run);
+
+        // Every account the instruction needs must be given
+        assert!(Cli::try_parse_from(&required[..required.len() - 2]).is_err());
+        assert!(Cli::try_parse_from(required.iter().map(String::as_str).chain(["--repay-amount", "-1"])).is_err());
+    }
+
+    #[test]
+    fn test_dry_run_output() {
+        let position = Pubkey::new_from_array([1; 32]);
+        let account = create_account(50, 10_000);
+        let outcome = LiquidationOutcome::new(&account, max_repay_amount(&account, 5_000), 150.0, 500);
+
+        let output = format_dry_run(&position, &outcome, "SOL/USD", Some(4_200));
+        assert_eq!(
+            output,
+            format!(
+                "Dry run: liquidating position {}\n\
+                 Repay amount:         5000\n\
+                 Collateral seized:    35 (5250.00 at SOL/USD 150.00)\n\
+                 Remaining collateral: 15\n\
+                 Remaining debt:       5000\n\
+                 Simulation:           ok, 4200 compute units",
+                position
+            )
+        );
+
+        let output = format_dry_run(&position, &outcome, "SOL/USD", None);
+        assert!(output.ends_with("Simulation:           ok"));
+
+        // A Token-2022 debt mint's fee is shown, and repays nothing
+        let outcome = LiquidationOutcome::with_repay_fee(&account, 5_000, 50, 150.0, 500);
+        let output = format_dry_run(&position, &outcome, "SOL/USD", None);
+        assert!(output.contains("Repay amount:         5000\nTransfer fee:         50\n"));
+        assert!(output.contains("Remaining debt:       5050"));
+    }
+
+    #[test]
+    fn test_healthy_position_refused() {
+        let position = Pubkey::new_unique();
+        let healthy = create_account(100, 100);
+        let error = check_liquidatable(&position, &healthy, Some(1.0), false).unwrap_err();
+        assert!(matches!(
+            error,
+            LiquidateError::PositionHealthy { collateral: 100, debt: 100, .. }
+        ));
+        assert!(error.to_string().contains("is healthy"));
+
+        assert!(check_liquidatable(&position, &healthy, Some(1.0), true).is_ok());
+        assert!(check_liquidatable(&position, &create_account(99, 100), Some(1.0), false).is_ok());
+        // Priced collateral, not its units, is held against debt
+        assert!(check_liquidatable(&position, &create_account(1_000, 999), Some(0.5), false).is_ok());
+        assert!(check_liquidatable(&position, &create_account(60, 100), Some(2.0), false).is_err());
+        assert!(check_liquidatable(&position, &healthy, None, false).is_ok());
+    }
+
+    #[test]
+    fn test_transaction_errors_named() {
+        let account = create_account(50, 100);
+        let attempt = Attempt {
+            position: Pubkey::new_unique(),
+            account: &account,
+            liquidator: Pubkey::new_unique(),
+            liquidator_token_account: Pubkey::new_unique(),
+            balance: 10,
+            repay_amount: 55,
+        };
+        let custom = |code| TransactionError::InstructionError(0, InstructionError::Custom(code));
+
+        assert!(matches!(
+            attempt.transaction_error(custom(POSITION_HEALTHY_ERROR)),
+            LiquidateError::PositionHealthy { .. }
+        ));
+        assert!(matches!(
+            attempt.transaction_error(custom(INSUFFICIENT_FUNDS_ERROR)),
+            LiquidateError::InsufficientBalance { balance: 10, required: 55, .. }
+        ));
+        match attempt.transaction_error(custom(KEEPER_NOT_WHITELISTED_ERROR)) {
+            LiquidateError::KeeperNotWhitelisted { keeper } => assert_eq!(keeper, attempt.liquidator),
+            other => panic!("expected a whitelist rejection, got {:?}", other),
+        }
+        assert!(matches!(
+            attempt.transaction_error(TransactionError::BlockhashNotFound),
+            LiquidateError::Transaction(_)
+        ));
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "connection refused");
+        assert!(matches!(attempt.client_error(refused.into()), LiquidateError::Rpc(_)));
+    }
+}
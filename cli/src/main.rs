@@ -1,6 +1,16 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+mod account;
+mod backfill;
+mod config;
+mod liquidate;
+mod oracle;
+mod positions;
+mod simulate;
+mod snapshot;
+mod watch;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -15,6 +25,31 @@ struct Args {
     /// Log level (error, warn, info, debug, trace)
     #[arg(long, default_value = "info")]
     log_level: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List and inspect positions
+    Positions(positions::PositionsArgs),
+    /// Liquidate a single position by hand
+    Liquidate(liquidate::LiquidateArgs),
+    /// Simulate liquidations of the book at hypothetical prices
+    Simulate(simulate::SimulateArgs),
+    /// Watch at-risk positions on a live dashboard
+    Watch(watch::WatchArgs),
+    /// Generate, validate and compare configuration files
+    Config(config::ConfigArgs),
+    /// Save and load snapshots of monitored positions
+    Snapshot(snapshot::SnapshotArgs),
+    /// Inspect and check oracle price feeds
+    Oracle(oracle::OracleArgs),
+    /// Create and fund a position of your own, e.g. to test against on devnet
+    Account(account::AccountArgs),
+    /// Record past liquidations of the program to the database
+    Backfill(backfill::BackfillArgs),
 }
 
 #[tokio::main]
@@ -29,7 +64,18 @@ async fn main() -> anyhow::Result<()> {
     log::info!("Starting liquidation CLI...");
     log::info!("Dry run: {}", args.dry_run);
     
-    // TODO: Implement CLI functionality
+    match args.command {
+        Some(Command::Positions(positions)) => println!("{}", positions::run(positions).await?),
+        Some(Command::Liquidate(liquidate)) => println!("{}", liquidate::run(liquidate, args.dry_run).await?),
+        Some(Command::Simulate(simulate)) => println!("{}", simulate::run(simulate).await?),
+        Some(Command::Watch(watch)) => watch::run(watch).await?,
+        Some(Command::Config(config)) => println!("{}", config::run(config).await?),
+        Some(Command::Snapshot(snapshot)) => println!("{}", snapshot::run(snapshot).await?),
+        Some(Command::Oracle(oracle)) => println!("{}", oracle::run(oracle, args.config.as_deref()).await?),
+        Some(Command::Account(account)) => println!("{}", account::run(account, args.dry_run).await?),
+        Some(Command::Backfill(backfill)) => println!("{}", backfill::run(backfill, args.config.as_deref()).await?),
+        None => log::info!("No command given, see --help"),
+    }
     
     Ok(())
 }
@@ -0,0 +1,778 @@
+use crate::positions::{align_columns, format_percent, format_price, parse_price_account};
+use anyhow::{anyhow, bail, Context};
+use clap::{Args, Subcommand, ValueEnum};
+use liquidation_engine::internals::{
+    check_staleness, decode_feed_account, decode_pyth_price_account, AmountExt, ChainlinkRound, FeedAddress,
+    HttpOracle, HttpOracleConfig, OracleConfig, PriceData, PythPriceFeed, TradingStatus,
+};
+use liquidation_engine::{amount, LiquidationConfig, LiquidationError};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// Arguments of the `oracle` command
+#[derive(Args, Debug)]
+pub struct OracleArgs {
+    /// Oracle the prices are read from
+    #[arg(long, global = true, value_enum, default_value_t = Provider::Pyth)]
+    provider: Provider,
+
+    /// Solana RPC URL, for Pyth and Chainlink feed accounts
+    #[arg(long, global = true, default_value = "https://api.devnet.solana.com")]
+    rpc_url: String,
+
+    /// Feed account for a symbol, as SYMBOL=PUBKEY (repeatable); takes
+    /// precedence over the configured one
+    #[arg(long = "price-account", global = true, value_parser = parse_price_account)]
+    price_accounts: Vec<(String, FeedAddress)>,
+
+    /// Base URL of the price API the http provider reads
+    #[arg(long, global = true)]
+    price_api_url: Option<String>,
+
+    /// Path of a symbol's price under the price API's URL, in which `{symbol}`
+    /// is replaced by the symbol
+    #[arg(long, global = true, default_value = "/price/{symbol}")]
+    price_api_path: String,
+
+    /// Print JSON instead of a table
+    #[arg(long, global = true, default_value_t = false)]
+    json: bool,
+
+    #[command(subcommand)]
+    action: OracleAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum OracleAction {
+    /// List every configured symbol with its latest price
+    List,
+    /// Print the decoded fields of a symbol's feed
+    Get {
+        /// The symbol, e.g. SOL/USD
+        symbol: String,
+    },
+    /// Print a symbol's price as it updates, until interrupted
+    Watch {
+        /// The symbol, e.g. SOL/USD
+        symbol: String,
+
+        /// Milliseconds between reads (default: the configured check interval)
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        interval_ms: Option<u64>,
+    },
+    /// Check every configured symbol's price as the engine does before using
+    /// it, failing if any is rejected
+    Check,
+}
+
+/// Oracle prices are read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Provider {
+    /// Pyth price accounts
+    Pyth,
+    /// Chainlink feed accounts of the OCR2 store program
+    Chainlink,
+    /// An HTTP price API
+    Http,
+}
+
+/// A feed's latest price, decoded as its provider stores it
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum Reading {
+    Pyth(PythPriceFeed),
+    Chainlink(ChainlinkRound),
+    Http { price: f64, publish_time: i64 },
+}
+
+impl Reading {
+    /// The price data the engine would read from the feed
+    pub fn price_data(&self, oracle: &OracleConfig) -> Result<PriceData, LiquidationError> {
+        match self {
+            Reading::Pyth(feed) => feed.price_data(oracle.price_source),
+            Reading::Chainlink(round) => round.quote(),
+            Reading::Http { price, publish_time } => {
+                Ok(PriceData::from_price(amount::from_f64(*price)?, *publish_time))
+            }
+        }
+    }
+
+    /// Trading status of the price, for providers that report one
+    pub fn status(&self) -> Option<TradingStatus> {
+        match self {
+            Reading::Pyth(feed) => Some(feed.status),
+            Reading::Chainlink(_) | Reading::Http { .. } => None,
+        }
+    }
+
+    /// Confidence interval as a ratio of the price, for providers whose
+    /// interval the engine bounds
+    pub fn confidence_ratio(&self) -> Option<f64> {
+        match self {
+            Reading::Pyth(feed) => Some(feed.confidence_ratio()),
+            Reading::Chainlink(_) | Reading::Http { .. } => None,
+        }
+    }
+
+    /// Check the price at `now` with the engine's own validation
+    pub fn check(&self, symbol: &str, oracle: &OracleConfig, now: i64) -> Result<(), LiquidationError> {
+        match self {
+            Reading::Pyth(feed) => oracle.check_feed(symbol, feed, now),
+            Reading::Chainlink(round) => round.price_data(symbol, now, oracle.max_price_age_secs).map(drop),
+            Reading::Http { publish_time, .. } => {
+                check_staleness(symbol, *publish_time, now, oracle.max_price_age_secs).map(drop)
+            }
+        }
+    }
+
+    /// The decoded fields, named as the provider names them
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Reading::Pyth(feed) => vec![
+                ("Price", feed.price.to_string()),
+                ("Confidence", feed.confidence.to_string()),
+                ("Exponent", feed.expo.to_string()),
+                ("EMA price", feed.ema_price.to_string()),
+                ("EMA confidence", feed.ema_confidence.to_string()),
+                ("Status", feed.status.to_string()),
+                ("Publish slot", feed.publish_slot.to_string()),
+                ("Publish time", feed.publish_time.to_string()),
+                ("Publishers", feed.num_publishers.to_string()),
+            ],
+            Reading::Chainlink(round) => vec![
+                ("Round", round.round_id.to_string()),
+                ("Slot", round.slot.to_string()),
+                ("Observations timestamp", round.observations_timestamp.to_string()),
+                ("Answer", round.answer.to_string()),
+                ("Decimals", round.decimals.to_string()),
+                ("Flagging threshold", round.flagging_threshold.to_string()),
+            ],
+            Reading::Http { price, publish_time } => {
+                vec![("Price", price.to_string()), ("Publish time", publish_time.to_string())]
+            }
+        }
+    }
+}
+
+/// Seconds between a price's publication and `now`, zero for prices published
+/// after it
+fn age_secs(publish_time: i64, now: i64) -> u64 {
+    now.saturating_sub(publish_time).max(0) as u64
+}
+
+/// Reads the latest price of each symbol from one provider
+pub struct Feeds {
+    provider: Provider,
+    rpc: RpcClient,
+    http: Option<HttpOracle>,
+    /// Feed account of each symbol, for providers reading accounts
+    accounts: BTreeMap<String, FeedAddress>,
+    /// Symbols to list and check
+    symbols: Vec<String>,
+}
+
+impl Feeds {
+    fn new(args: &OracleArgs, config: &LiquidationConfig, oracle: &OracleConfig) -> anyhow::Result<Self> {
+        let mut accounts: BTreeMap<String, FeedAddress> = config.oracle_feeds().into_iter().collect();
+        accounts.extend(args.price_accounts.iter().cloned());
+        let mut symbols: Vec<String> = accounts.keys().cloned().collect();
+        symbols.extend(config.markets.iter().map(|market| market.symbol.clone()));
+        symbols.sort();
+        symbols.dedup();
+
+        let http = match (args.provider, &args.price_api_url) {
+            (Provider::Http, Some(url)) => Some(HttpOracle::new(HttpOracleConfig {
+                base_url: url.clone(),
+                path_template: args.price_api_path.clone(),
+                max_price_age_secs: oracle.max_price_age_secs,
+                ..Default::default()
+            })?),
+            (Provider::Http, None) => bail!("The http provider needs --price-api-url"),
+            _ => None,
+        };
+        Ok(Self {
+            provider: args.provider,
+            rpc: RpcClient::new(args.rpc_url.clone()),
+            http,
+            accounts,
+            symbols,
+        })
+    }
+
+    /// Feed account of a symbol, for providers reading accounts
+    fn account(&self, symbol: &str) -> Option<FeedAddress> {
+        match self.provider {
+            Provider::Pyth | Provider::Chainlink => self.accounts.get(symbol).copied(),
+            Provider::Http => None,
+        }
+    }
+
+    /// Read a symbol's latest price, whatever its age or confidence
+    async fn read(&self, symbol: &str) -> anyhow::Result<Reading> {
+        if let Some(http) = &self.http {
+            let (price, publish_time) = http.quote(symbol).await?;
+            return Ok(Reading::Http { price, publish_time });
+        }
+        let account = self
+            .account(symbol)
+            .ok_or_else(|| anyhow!("No feed account for {}; pass --price-account {}=PUBKEY", symbol, symbol))?;
+        let data = self
+            .rpc
+            .get_account_data(account.as_ref())
+            .await
+            .with_context(|| format!("Failed to fetch feed account {} of {}", account, symbol))?;
+        let reading = match self.provider {
+            Provider::Chainlink => Reading::Chainlink(decode_feed_account(&data)?),
+            _ => Reading::Pyth(decode_pyth_price_account(&data)?),
+        };
+        Ok(reading)
+    }
+}
+
+/// A symbol's latest price, as listed
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FeedSummary {
+    pub symbol: String,
+    pub provider: Provider,
+    /// The feed account, for providers reading accounts
+    pub feed: Option<String>,
+    pub price: Option<f64>,
+    pub confidence: Option<f64>,
+    /// Seconds since the price was published
+    pub age_secs: Option<u64>,
+    pub status: Option<TradingStatus>,
+    /// Why the price couldn't be read
+    pub error: Option<String>,
+}
+
+impl FeedSummary {
+    pub fn new(
+        symbol: &str,
+        provider: Provider,
+        feed: Option<FeedAddress>,
+        reading: &anyhow::Result<Reading>,
+        oracle: &OracleConfig,
+        now: i64,
+    ) -> Self {
+        let mut summary = Self {
+            symbol: symbol.to_string(),
+            provider,
+            feed: feed.map(|feed| feed.to_string()),
+            price: None,
+            confidence: None,
+            age_secs: None,
+            status: None,
+            error: None,
+        };
+        match reading {
+            Ok(reading) => match reading.price_data(oracle) {
+                Ok(data) => {
+                    summary.price = Some(data.price.as_f64());
+                    summary.confidence = Some(data.confidence);
+                    summary.age_secs = Some(age_secs(data.publish_time, now));
+                    summary.status = reading.status();
+                }
+                Err(e) => summary.error = Some(e.to_string()),
+            },
+            Err(e) => summary.error = Some(format!("{:#}", e)),
+        }
+        summary
+    }
+}
+
+/// Outcome of checking a symbol's price as the engine does
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FeedCheck {
+    pub symbol: String,
+    pub passed: bool,
+    /// Seconds since the price was published
+    pub age_secs: Option<u64>,
+    /// Confidence interval as a ratio of the price, for providers whose
+    /// interval is bounded
+    pub confidence_ratio: Option<f64>,
+    /// The setting the price failed, with its value
+    pub threshold: Option<String>,
+    /// Why the price was rejected or couldn't be read
+    pub error: Option<String>,
+}
+
+impl FeedCheck {
+    pub fn new(symbol: &str, reading: &anyhow::Result<Reading>, oracle: &OracleConfig, now: i64) -> Self {
+        let reading = match reading {
+            Ok(reading) => reading,
+            Err(e) => {
+                return Self {
+                    symbol: symbol.to_string(),
+                    passed: false,
+                    age_secs: None,
+                    confidence_ratio: None,
+                    threshold: None,
+                    error: Some(format!("{:#}", e)),
+                }
+            }
+        };
+        let result = reading.check(symbol, oracle, now);
+        Self {
+            symbol: symbol.to_string(),
+            passed: result.is_ok(),
+            age_secs: reading.price_data(oracle).ok().map(|data| age_secs(data.publish_time, now)),
+            confidence_ratio: reading.confidence_ratio(),
+            threshold: result.as_ref().err().and_then(|e| failed_threshold(e, oracle)),
+            error: result.err().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// The setting a price was rejected for, with its value
+pub fn failed_threshold(error: &LiquidationError, oracle: &OracleConfig) -> Option<String> {
+    match error {
+        LiquidationError::StalePrice { max_age_secs, .. } => Some(format!("max_price_age_secs = {}", max_age_secs)),
+        LiquidationError::LowConfidencePrice(_) => {
+            Some(format!("min_confidence_interval = {}", oracle.min_confidence_interval))
+        }
+        LiquidationError::HighConfidenceInterval(_) => {
+            Some(format!("max_confidence_interval = {}", oracle.max_confidence_interval))
+        }
+        _ => None,
+    }
+}
+
+/// A price read while watching a symbol
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Tick {
+    pub symbol: String,
+    /// When the price was read (Unix timestamp)
+    pub read_at: i64,
+    pub price: Option<f64>,
+    /// Change since the previous price read
+    pub delta: Option<f64>,
+    pub confidence: Option<f64>,
+    /// Seconds since the price was published
+    pub age_secs: Option<u64>,
+    /// Why the price couldn't be read
+    pub error: Option<String>,
+}
+
+impl Tick {
+    pub fn new(
+        symbol: &str,
+        reading: &anyhow::Result<Reading>,
+        previous: Option<f64>,
+        oracle: &OracleConfig,
+        now: i64,
+    ) -> Self {
+        let data = match reading {
+            Ok(reading) => reading.price_data(oracle).map_err(|e| e.to_string()),
+            Err(e) => Err(format!("{:#}", e)),
+        };
+        let price = data.as_ref().ok().map(|data| data.price.as_f64());
+        Self {
+            symbol: symbol.to_string(),
+            read_at: now,
+            price,
+            delta: price.zip(previous).map(|(price, previous)| price - previous),
+            confidence: data.as_ref().ok().map(|data| data.confidence),
+            age_secs: data.as_ref().ok().map(|data| age_secs(data.publish_time, now)),
+            error: data.err(),
+        }
+    }
+}
+
+/// Read the configuration file at `path`, or the defaults without one
+pub(crate) fn load_config(path: Option<&Path>) -> anyhow::Result<LiquidationConfig> {
+    let Some(path) = path else {
+        return Ok(LiquidationConfig::default());
+    };
+    let toml = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    LiquidationConfig::from_toml(&toml).with_context(|| path.display().to_string())
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Run the `oracle` command, returning what to print
+///
+/// Symbols and their feeds come from the configuration file at `config`,
+/// which also sets the thresholds prices are checked against. Fails when the
+/// check rejects a price, so scripts can act on the exit code.
+pub async fn run(args: OracleArgs, config: Option<&Path>) -> anyhow::Result<String> {
+    let config = load_config(config)?;
+    let oracle = config.oracle_config();
+    let feeds = Feeds::new(&args, &config, &oracle)?;
+
+    match &args.action {
+        OracleAction::List => {
+            let mut summaries = Vec::new();
+            for symbol in &feeds.symbols {
+                let reading = feeds.read(symbol).await;
+                summaries.push(FeedSummary::new(symbol, feeds.provider, feeds.account(symbol), &reading, &oracle, now()));
+            }
+            if args.json {
+                Ok(serde_json::to_string_pretty(&summaries)?)
+            } else {
+                Ok(format_list(&summaries))
+            }
+        }
+        OracleAction::Get { symbol } => {
+            let reading = feeds.read(symbol).await?;
+            if args.json {
+                Ok(serde_json::to_string_pretty(&reading)?)
+            } else {
+                Ok(format_reading(symbol, feeds.account(symbol), &reading))
+            }
+        }
+        OracleAction::Watch { symbol, interval_ms } => {
+            let interval_ms = interval_ms.unwrap_or(config.check_interval_ms);
+            let mut interval = tokio::time::interval(Duration::from_millis(interval_ms));
+            let mut previous = None;
+            let mut ticks = 0;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let reading = feeds.read(symbol).await;
+                        let tick = Tick::new(symbol, &reading, previous, &oracle, now());
+                        // Deltas span failed reads, from the last price read
+                        previous = tick.price.or(previous);
+                        ticks += 1;
+                        if args.json {
+                            println!("{}", serde_json::to_string(&tick)?);
+                        } else {
+                            println!("{}", format_tick(&tick));
+                        }
+                    }
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+            }
+            Ok(format!("Read {} {} time(s)", symbol, ticks))
+        }
+        OracleAction::Check => {
+            let mut checks = Vec::new();
+            for symbol in &feeds.symbols {
+                let reading = feeds.read(symbol).await;
+                checks.push(FeedCheck::new(symbol, &reading, &oracle, now()));
+            }
+            let report = if args.json {
+                serde_json::to_string_pretty(&checks)?
+            } else {
+                format_checks(&checks)
+            };
+            if checks.iter().all(|check| check.passed) {
+                Ok(report)
+            } else {
+                Err(anyhow!(report))
+            }
+        }
+    }
+}
+
+fn format_age(age_secs: Option<u64>) -> String {
+    age_secs.map_or_else(|| "-".to_string(), |age| format!("{}s", age))
+}
+
+/// Render listed prices as a table, with the reasons prices couldn't be read
+/// below it
+pub fn format_list(summaries: &[FeedSummary]) -> String {
+    const HEADER: [&str; 7] = ["SYMBOL", "FEED", "PROVIDER", "STATUS", "PRICE", "CONFIDENCE", "AGE"];
+    let rows: Vec<[String; 7]> = summaries
+        .iter()
+        .map(|summary| {
+            let status = match (&summary.error, summary.status) {
+                (Some(_), _) => "error".to_string(),
+                (None, Some(status)) => status.to_string(),
+                (None, None) => "-".to_string(),
+            };
+            [
+                summary.symbol.clone(),
+                summary.feed.clone().unwrap_or_else(|| "-".to_string()),
+                provider_name(summary.provider).to_string(),
+                status,
+                format_price(summary.price),
+                summary.confidence.map_or_else(|| "-".to_string(), |confidence| format!("{:.4}", confidence)),
+                format_age(summary.age_secs),
+            ]
+        })
+        .collect();
+
+    let mut lines = vec![align_columns(HEADER, &rows, 4)];
+    for summary in summaries {
+        if let Some(error) = &summary.error {
+            lines.push(format!("{}: {}", summary.symbol, error));
+        }
+    }
+    lines.join("\n")
+}
+
+fn provider_name(provider: Provider) -> &'static str {
+    match provider {
+        Provider::Pyth => "pyth",
+        Provider::Chainlink => "chainlink",
+        Provider::Http => "http",
+    }
+}
+
+/// Render a feed's decoded fields
+pub fn format_reading(symbol: &str, feed: Option<FeedAddress>, reading: &Reading) -> String {
+    let mut fields = vec![("Symbol", symbol.to_string())];
+    if let Some(feed) = feed {
+        fields.push(("Feed", feed.to_string()));
+    }
+    fields.extend(reading.fields());
+    let width = fields.iter().map(|(name, _)| name.len() + 1).max().unwrap_or(0);
+    fields
+        .iter()
+        .map(|(name, value)| format!("{:<width$} {}", format!("{}:", name), value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render a watched price on one line, with its change since the last one
+pub fn format_tick(tick: &Tick) -> String {
+    let read_at = chrono::DateTime::from_timestamp(tick.read_at, 0)
+        .map_or_else(|| tick.read_at.to_string(), |time| time.format("%H:%M:%S").to_string());
+    if let Some(error) = &tick.error {
+        return format!("{}  {}  error: {}", read_at, tick.symbol, error);
+    }
+    let delta = match (tick.delta, tick.price) {
+        (Some(delta), Some(price)) if price != delta => {
+            format!("{:+.4} ({:+.4}%)", delta, delta / (price - delta) * 100.0)
+        }
+        _ => "-".to_string(),
+    };
+    format!(
+        "{}  {}  {}  {}  ±{:.4}  age {}",
+        read_at,
+        tick.symbol,
+        format_price(tick.price),
+        delta,
+        tick.confidence.unwrap_or_default(),
+        format_age(tick.age_secs)
+    )
+}
+
+/// Render price checks as a table, with the reasons prices failed below it
+pub fn format_checks(checks: &[FeedCheck]) -> String {
+    const HEADER: [&str; 5] = ["SYMBOL", "RESULT", "FAILED THRESHOLD", "AGE", "CONFIDENCE"];
+    let rows: Vec<[String; 5]> = checks
+        .iter()
+        .map(|check| {
+            [
+                check.symbol.clone(),
+                if check.passed { "pass" } else { "fail" }.to_string(),
+                check.threshold.clone().unwrap_or_else(|| "-".to_string()),
+                format_age(check.age_secs),
+                format_percent(check.confidence_ratio),
+            ]
+        })
+        .collect();
+
+    let mut lines = vec![align_columns(HEADER, &rows, 3)];
+    for check in checks {
+        if let Some(error) = &check.error {
+            lines.push(format!("{}: {}", check.symbol, error));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use solana_sdk::pubkey::Pubkey;
+
+    /// SOL/USD at 145.23456789 within 0.29046913, published at `PUBLISHED_AT`
+    const PYTH_FEED: &[u8] = include_bytes!("../../engine/fixtures/accounts/pyth_price.bin");
+
+    /// SOL/USD at 145.23456789 with a 1% flagging threshold, observed at
+    /// `PUBLISHED_AT`
+    const CHAINLINK_FEED: &[u8] = include_bytes!("../../engine/fixtures/accounts/chainlink_feed.bin");
+
+    const PUBLISHED_AT: i64 = 1_760_000_000;
+
+    // Copied into heap buffers as the RPC client's are, which Pyth's loader
+    // needs aligned
+    fn pyth() -> Reading {
+        Reading::Pyth(decode_pyth_price_account(&PYTH_FEED.to_vec()).unwrap())
+    }
+
+    fn chainlink() -> Reading {
+        Reading::Chainlink(decode_feed_account(CHAINLINK_FEED).unwrap())
+    }
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        oracle: OracleArgs,
+    }
+
+    #[test]
+    fn test_checks_match_the_engine() {
+        let oracle = OracleConfig::default();
+        for reading in [pyth(), chainlink()] {
+            for now in [PUBLISHED_AT + 10, PUBLISHED_AT + 31] {
+                let check = FeedCheck::new("SOL/USD", &Ok(reading.clone()), &oracle, now);
+                let engine = match &reading {
+                    Reading::Pyth(feed) => oracle.check_feed("SOL/USD", feed, now),
+                    Reading::Chainlink(round) => round.price_data("SOL/USD", now, oracle.max_price_age_secs).map(drop),
+                    Reading::Http { .. } => unreachable!(),
+                };
+                assert_eq!(check.passed, engine.is_ok());
+                assert_eq!(check.error, engine.err().map(|e| e.to_string()));
+            }
+        }
+
+        let check = FeedCheck::new("SOL/USD", &Ok(pyth()), &oracle, PUBLISHED_AT + 31);
+        assert_eq!(check.age_secs, Some(31));
+        assert_eq!(check.threshold.as_deref(), Some("max_price_age_secs = 30"));
+
+        // The 0.2% interval is wider than a 0.1% cap, and narrower than a 0.5% floor
+        let strict = OracleConfig {
+            max_confidence_interval: 0.001,
+            ..OracleConfig::default()
+        };
+        let check = FeedCheck::new("SOL/USD", &Ok(pyth()), &strict, PUBLISHED_AT);
+        assert!(!check.passed);
+        assert_eq!(check.threshold.as_deref(), Some("max_confidence_interval = 0.001"));
+        let narrow = OracleConfig {
+            min_confidence_interval: 0.005,
+            ..OracleConfig::default()
+        };
+        let check = FeedCheck::new("SOL/USD", &Ok(pyth()), &narrow, PUBLISHED_AT);
+        assert_eq!(check.threshold.as_deref(), Some("min_confidence_interval = 0.005"));
+        // Chainlink intervals aren't bounded
+        assert!(FeedCheck::new("SOL/USD", &Ok(chainlink()), &strict, PUBLISHED_AT).passed);
+
+        let unread = FeedCheck::new("BTC/USD", &Err(anyhow!("No feed account for BTC/USD")), &oracle, PUBLISHED_AT);
+        assert!(!unread.passed);
+        assert_eq!((unread.threshold, unread.error.as_deref()), (None, Some("No feed account for BTC/USD")));
+    }
+
+    #[test]
+    fn test_check_formatting() {
+        let oracle = OracleConfig::default();
+        let checks = [
+            FeedCheck::new("SOL/USD", &Ok(pyth()), &oracle, PUBLISHED_AT + 5),
+            FeedCheck::new("SOL/USD", &Ok(pyth()), &oracle, PUBLISHED_AT + 45),
+            FeedCheck::new("BTC/USD", &Err(anyhow!("No feed account for BTC/USD")), &oracle, PUBLISHED_AT),
+        ];
+        let table = format_checks(&checks);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "SYMBOL   RESULT  FAILED THRESHOLD         AGE  CONFIDENCE");
+        assert_eq!(lines[1], "SOL/USD  pass    -                         5s       0.20%");
+        assert_eq!(lines[2], "SOL/USD  fail    max_price_age_secs = 30  45s       0.20%");
+        assert_eq!(lines[3], "BTC/USD  fail    -                          -           -");
+        assert_eq!(lines[4], "SOL/USD: Stale price for SOL/USD: 45s old, max 30s");
+        assert_eq!(lines[5], "BTC/USD: No feed account for BTC/USD");
+
+        let json: serde_json::Value = serde_json::to_value(&checks).unwrap();
+        assert_eq!(json[1]["passed"], false);
+        assert_eq!(json[1]["age_secs"], 45);
+        assert_eq!(json[1]["threshold"], "max_price_age_secs = 30");
+    }
+
+    #[test]
+    fn test_list_formatting() {
+        let oracle = OracleConfig::default();
+        let feed = FeedAddress(Pubkey::new_from_array([7; 32]));
+        let summaries = [
+            FeedSummary::new("SOL/USD", Provider::Pyth, Some(feed), &Ok(pyth()), &oracle, PUBLISHED_AT + 3),
+            FeedSummary::new("BTC/USD", Provider::Pyth, None, &Err(anyhow!("No feed account for BTC/USD")), &oracle, 0),
+        ];
+        let table = format_list(&summaries);
+        let lines: Vec<&str> = table.lines().collect();
+        let width = feed.to_string().len();
+        assert_eq!(
+            lines[0],
+            format!("SYMBOL   {:<width$}  PROVIDER  STATUS    PRICE  CONFIDENCE  AGE", "FEED")
+        );
+        assert_eq!(lines[1], format!("SOL/USD  {}  pyth      trading  145.23      0.2905   3s", feed));
+        assert_eq!(lines[2], format!("BTC/USD  {:<width$}  pyth      error         -           -    -", "-"));
+        assert_eq!(lines[3], "BTC/USD: No feed account for BTC/USD");
+
+        let json: serde_json::Value = serde_json::to_value(&summaries).unwrap();
+        assert_eq!(json[0]["provider"], "pyth");
+        assert_eq!(json[0]["status"], "trading");
+        assert_eq!(json[0]["feed"], feed.to_string());
+        assert_eq!(json[1]["price"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_reading_formatting() {
+        let details = format_reading("SOL/USD", None, &pyth());
+        let lines: Vec<&str> = details.lines().collect();
+        assert_eq!(lines[0], "Symbol:         SOL/USD");
+        assert_eq!(lines[1], "Price:          14523456789");
+        assert_eq!(lines[3], "Exponent:       -8");
+        assert_eq!(lines[6], "Status:         trading");
+
+        let details = format_reading("SOL/USD", None, &chainlink());
+        assert!(details.lines().any(|line| line == "Answer:                 14523456789"));
+
+        let json: serde_json::Value = serde_json::to_value(pyth()).unwrap();
+        assert_eq!(json["provider"], "pyth");
+        assert_eq!(json["price"], 14_523_456_789i64);
+        assert_eq!(json["expo"], -8);
+        assert_eq!(json["status"], "trading");
+        let json: serde_json::Value = serde_json::to_value(chainlink()).unwrap();
+        assert_eq!(json["provider"], "chainlink");
+        assert_eq!(json["round_id"], 1_234);
+    }
+
+    #[test]
+    fn test_ticks_show_price_deltas() {
+        let oracle = OracleConfig::default();
+        let first = Tick::new("SOL/USD", &Ok(pyth()), None, &oracle, PUBLISHED_AT + 1);
+        assert_eq!(first.delta, None);
+        let http = Reading::Http { price: 150.0, publish_time: PUBLISHED_AT };
+        let second = Tick::new("SOL/USD", &Ok(http), Some(120.0), &oracle, PUBLISHED_AT + 2);
+        assert_eq!((second.delta, second.age_secs), (Some(30.0), Some(2)));
+        assert!(format_tick(&second).ends_with("SOL/USD  150.00  +30.0000 (+25.0000%)  ±0.0000  age 2s"));
+        assert!(format_tick(&first).contains("SOL/USD  145.23  -  ±0.2905  age 1s"));
+
+        let failed = Tick::new("SOL/USD", &Err(anyhow!("timed out")), Some(120.0), &oracle, PUBLISHED_AT);
+        assert!(format_tick(&failed).ends_with("SOL/USD  error: timed out"));
+    }
+
+    #[test]
+    fn test_arguments() {
+        let cli = Cli::parse_from([
+            "oracle",
+            "--provider",
+            "chainlink",
+            "--price-account",
+            &format!("SOL/USD={}", Pubkey::new_from_array([7; 32])),
+            "watch",
+            "SOL/USD",
+            "--interval-ms",
+            "500",
+            "--json",
+        ]);
+        assert_eq!(cli.oracle.provider, Provider::Chainlink);
+        assert!(cli.oracle.json);
+        assert!(matches!(
+            &cli.oracle.action,
+            OracleAction::Watch { symbol, interval_ms: Some(500) } if symbol == "SOL/USD"
+        ));
+
+        // Configured feeds are listed alongside those passed, which take precedence
+        let config = LiquidationConfig {
+            price_accounts: [
+                ("SOL/USD".to_string(), FeedAddress(Pubkey::new_from_array([1; 32]))),
+                ("BTC/USD".to_string(), FeedAddress(Pubkey::new_from_array([2; 32]))),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let feeds = Feeds::new(&cli.oracle, &config, &config.oracle_config()).unwrap();
+        assert_eq!(feeds.symbols, ["BTC/USD", "SOL/USD"]);
+        assert_eq!(feeds.account("SOL/USD"), Some(FeedAddress(Pubkey::new_from_array([7; 32]))));
+
+        assert!(Cli::try_parse_from(["oracle", "--provider", "switchboard", "list"]).is_err());
+        let http = Cli::parse_from(["oracle", "--provider", "http", "check"]);
+        assert!(Feeds::new(&http.oracle, &config, &config.oracle_config()).is_err());
+    }
+}
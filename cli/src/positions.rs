@@ -0,0 +1,596 @@
+use anyhow::{anyhow, Context};
+use clap::{Args, Subcommand, ValueEnum};
+use liquidation_engine::internals::{
+    decode_position_account, position_account_filters, position_from_account, AmountExt, FeedAddress, MarginParams,
+    MarginTierSchedule, MintDecimals, OwnerAddress, PositionAccount, PositionHealth, RateLimiter, PROGRAM_ID,
+};
+use liquidation_engine::{Amount, LiquidationConfig, OracleProvider, Position, PythOracle};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Positions within this fraction of their liquidation price count as at risk
+const AT_RISK_DISTANCE: f64 = 0.1;
+
+/// Arguments of the `positions` command
+#[derive(Args, Debug)]
+pub struct PositionsArgs {
+    /// Solana RPC URL, for position accounts and oracle prices
+    #[arg(long, global = true, default_value = "https://api.devnet.solana.com")]
+    rpc_url: String,
+
+    /// Read the positions monitored by a running engine's admin API instead of
+    /// the program's accounts
+    #[arg(long, global = true)]
+    engine_url: Option<String>,
+
+    /// Liquidation program owning the position accounts
+    #[arg(long, global = true, default_value_t = PROGRAM_ID)]
+    program_id: Pubkey,
+
+    /// Pyth price account for a symbol, as SYMBOL=PUBKEY (repeatable)
+    #[arg(long = "price-account", global = true, value_parser = parse_price_account)]
+    price_accounts: Vec<(String, FeedAddress)>,
+
+    /// Symbol to price on-chain collateral in; without one, collateral counts
+    /// one for one against debt as the program does
+    #[arg(long, global = true)]
+    collateral_symbol: Option<String>,
+
+    /// Print JSON instead of a table
+    #[arg(long, global = true, default_value_t = false)]
+    json: bool,
+
+    #[command(subcommand)]
+    action: PositionsAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum PositionsAction {
+    /// List positions with their health
+    List {
+        /// Order of the listed positions
+        #[arg(long, value_enum, default_value_t = SortKey::Health)]
+        sort: SortKey,
+
+        /// Only positions held by this owner
+        #[arg(long)]
+        owner: Option<OwnerAddress>,
+
+        /// Only positions that are liquidatable or close to it
+        #[arg(long, default_value_t = false)]
+        at_risk: bool,
+    },
+    /// Show a detailed breakdown of one position
+    Show {
+        /// The position account
+        pubkey: Pubkey,
+    },
+}
+
+/// Order of listed positions
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Lowest margin ratio first
+    Health,
+    /// Largest debt first
+    Size,
+}
+
+pub(crate) fn parse_price_account(value: &str) -> Result<(String, FeedAddress), String> {
+    let (symbol, pubkey) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected SYMBOL=PUBKEY, got {}", value))?;
+    let pubkey = Pubkey::from_str(pubkey).map_err(|e| format!("invalid price account {}: {}", pubkey, e))?;
+    Ok((symbol.to_string(), pubkey.into()))
+}
+
+/// A position as read from its source, before it's priced
+pub(crate) enum Fetched {
+    /// A position account of the on-chain program
+    Account(Pubkey, PositionAccount),
+    /// A position monitored by the engine
+    Monitored(Position),
+}
+
+/// Where positions are read from
+pub(crate) enum Source {
+    Chain { rpc: RpcClient, program_id: Pubkey },
+    Engine { client: reqwest::Client, url: String },
+}
+
+/// The part of the engine's configuration needed to judge its positions
+#[derive(serde::Deserialize)]
+pub(crate) struct EngineConfig {
+    pub(crate) maintenance_margin: f64,
+    #[serde(default)]
+    pub(crate) margin_tiers: HashMap<String, MarginTierSchedule>,
+}
+
+impl Source {
+    /// The engine's admin API at `engine_url` if given, the program's accounts
+    /// otherwise
+    pub(crate) fn new(rpc_url: &str, engine_url: Option<&str>, program_id: Pubkey) -> Self {
+        match engine_url {
+            Some(url) => Source::Engine {
+                client: reqwest::Client::new(),
+                url: url.trim_end_matches('/').to_string(),
+            },
+            None => Source::Chain {
+                rpc: RpcClient::new(rpc_url.to_string()),
+                program_id,
+            },
+        }
+    }
+
+    pub(crate) async fn list(&self, owner: Option<&OwnerAddress>) -> anyhow::Result<Vec<Fetched>> {
+        match self {
+            Source::Chain { rpc, program_id } => {
+                let config = RpcProgramAccountsConfig {
+                    filters: Some(position_account_filters(owner.map(AsRef::as_ref))),
+                    account_config: RpcAccountInfoConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                let accounts = rpc
+                    .get_program_accounts_with_config(program_id, config)
+                    .await
+                    .context("Failed to fetch position accounts")?;
+                accounts
+                    .into_iter()
+                    .map(|(address, account)| {
+                        let position = decode_position_account(&account.data)
+                            .with_context(|| format!("Position account {}", address))?;
+                        Ok(Fetched::Account(address, position))
+                    })
+                    .collect()
+            }
+            Source::Engine { client, url } => {
+                let mut request = client.get(format!("{}/positions", url));
+                if let Some(owner) = owner {
+                    request = request.query(&[("owner", owner.to_string())]);
+                }
+                let positions: Vec<Position> = fetch_json(request).await?;
+                Ok(positions.into_iter().map(Fetched::Monitored).collect())
+            }
+        }
+    }
+
+    async fn get(&self, address: &Pubkey) -> anyhow::Result<Fetched> {
+        match self {
+            Source::Chain { rpc, .. } => {
+                // Closed accounts vanish, which isn't a failure of the node
+                let account = rpc
+                    .get_account_with_commitment(address, rpc.commitment())
+                    .await
+                    .with_context(|| format!("Failed to fetch position account {}", address))?
+                    .value
+                    .ok_or_else(|| anyhow!("Position account {} doesn't exist; it may have been closed", address))?;
+                let position = decode_position_account(&account.data)
+                    .with_context(|| format!("Position account {}", address))?;
+                Ok(Fetched::Account(*address, position))
+            }
+            Source::Engine { client, url } => {
+                let request = client.get(format!("{}/positions/{}", url, address));
+                Ok(Fetched::Monitored(fetch_json(request).await?))
+            }
+        }
+    }
+
+    /// Maintenance margin monitored positions are judged against
+    pub(crate) async fn maintenance_margin(&self) -> anyhow::Result<f64> {
+        Ok(self.engine_config().await?.maintenance_margin)
+    }
+
+    /// Margin parameters monitored positions are judged against: the engine's,
+    /// or the defaults for accounts
+    pub(crate) async fn engine_config(&self) -> anyhow::Result<EngineConfig> {
+        match self {
+            Source::Chain { .. } => Ok(EngineConfig {
+                maintenance_margin: LiquidationConfig::default().maintenance_margin,
+                margin_tiers: HashMap::new(),
+            }),
+            Source::Engine { client, url } => fetch_json(client.get(format!("{}/config", url))).await,
+        }
+    }
+}
+
+/// Positions as the engine models them, with on-chain collateral priced in
+/// `collateral_symbol`
+///
+/// Amounts stay in base units, as the program counts them. Closed accounts
+/// and those without collateral are skipped.
+pub(crate) async fn list_positions(source: &Source, collateral_symbol: &str) -> anyhow::Result<Vec<Position>> {
+    let mut positions = Vec::new();
+    for fetched in source.list(None).await? {
+        match fetched {
+            Fetched::Monitored(position) => positions.push(position),
+            Fetched::Account(address, account) => {
+                match position_from_account(address, &account, collateral_symbol, MintDecimals::default()) {
+                    Some(position) => positions.push(position),
+                    None => log::warn!("Skipping closed or empty position account {}", address),
+                }
+            }
+        }
+    }
+    Ok(positions)
+}
+
+pub(crate) async fn fetch_json<T: serde::de::DeserializeOwned>(request: reqwest::RequestBuilder) -> anyhow::Result<T> {
+    let response = request.send().await.context("Failed to reach the engine")?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("Engine returned {}: {}", status, body));
+    }
+    response.json().await.context("Invalid response from the engine")
+}
+
+/// Prices fetched positions, looking each symbol up once
+pub(crate) struct Pricer {
+    oracle: PythOracle,
+    collateral_symbol: Option<String>,
+    maintenance_margin: f64,
+    prices: HashMap<String, Amount>,
+}
+
+impl Pricer {
+    pub(crate) fn new(
+        rpc_url: &str,
+        price_accounts: &[(String, FeedAddress)],
+        collateral_symbol: Option<String>,
+        maintenance_margin: f64,
+    ) -> Self {
+        Self {
+            oracle: PythOracle::new(
+                rpc_url,
+                price_accounts.iter().cloned().collect(),
+                None,
+                Arc::new(RateLimiter::default()),
+            ),
+            collateral_symbol,
+            maintenance_margin,
+            prices: HashMap::new(),
+        }
+    }
+
+    /// Forget the prices looked up so far, so they're fetched again
+    pub(crate) fn clear(&mut self) {
+        self.prices.clear();
+    }
+
+    async fn price(&mut self, symbol: &str) -> anyhow::Result<Amount> {
+        if let Some(price) = self.prices.get(symbol) {
+            return Ok(*price);
+        }
+        let price = self
+            .oracle
+            .get_price(symbol)
+            .await
+            .with_context(|| format!("Failed to price {}", symbol))?;
+        self.prices.insert(symbol.to_string(), price);
+        Ok(price)
+    }
+
+    pub(crate) async fn health(&mut self, fetched: &Fetched) -> anyhow::Result<PositionHealth> {
+        match fetched {
+            Fetched::Account(address, account) => {
+                let symbol = self.collateral_symbol.clone();
+                let price = match &symbol {
+                    Some(symbol) => self.price(symbol).await?.as_f64(),
+                    None => 1.0,
+                };
+                Ok(PositionHealth::from_account(*address, account, symbol.as_deref(), price))
+            }
+            Fetched::Monitored(position) => {
+                let price = self.price(&position.symbol).await?;
+                Ok(PositionHealth::from_position(position, price, MarginParams::shared(self.maintenance_margin)))
+            }
+        }
+    }
+}
+
+/// Run the `positions` command, returning what to print
+pub async fn run(args: PositionsArgs) -> anyhow::Result<String> {
+    let source = Source::new(&args.rpc_url, args.engine_url.as_deref(), args.program_id);
+    let mut pricer = Pricer::new(
+        &args.rpc_url,
+        &args.price_accounts,
+        args.collateral_symbol.clone(),
+        source.maintenance_margin().await?,
+    );
+
+    match args.action {
+        PositionsAction::List { sort, owner, at_risk } => {
+            let mut positions = Vec::new();
+            for fetched in source.list(owner.as_ref()).await? {
+                let health = pricer.health(&fetched).await?;
+                if !at_risk || is_at_risk(&health) {
+                    positions.push(health);
+                }
+            }
+            sort_positions(&mut positions, sort);
+            if args.json {
+                Ok(serde_json::to_string_pretty(&positions)?)
+            } else {
+                Ok(format_table(&positions))
+            }
+        }
+        PositionsAction::Show { pubkey } => {
+            let health = pricer.health(&source.get(&pubkey).await?).await?;
+            if args.json {
+                Ok(serde_json::to_string_pretty(&health)?)
+            } else {
+                Ok(format_details(&health))
+            }
+        }
+    }
+}
+
+/// Whether a position is liquidatable or within `AT_RISK_DISTANCE` of it
+pub fn is_at_risk(health: &PositionHealth) -> bool {
+    health.liquidatable
+        || health
+            .distance_to_liquidation
+            .is_some_and(|distance| distance < AT_RISK_DISTANCE)
+}
+
+/// Order positions by `key`; positions without debt sort as healthiest
+pub fn sort_positions(positions: &mut [PositionHealth], key: SortKey) {
+    match key {
+        SortKey::Health => positions.sort_by(|a, b| {
+            let ratio = |health: &PositionHealth| health.margin_ratio.unwrap_or(f64::INFINITY);
+            ratio(a).total_cmp(&ratio(b))
+        }),
+        SortKey::Size => positions.sort_by(|a, b| b.debt.total_cmp(&a.debt)),
+    }
+}
+
+pub(crate) fn format_percent(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| format!("{:.2}%", value * 100.0))
+}
+
+pub(crate) fn format_price(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| format!("{:.2}", value))
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Render positions as a table, one row per position
+pub fn format_table(positions: &[PositionHealth]) -> String {
+    const HEADER: [&str; 6] = ["PUBKEY", "OWNER", "COLLATERAL", "DEBT", "MARGIN RATIO", "LIQUIDATABLE"];
+    let rows: Vec<[String; 6]> = positions
+        .iter()
+        .map(|health| {
+            [
+                health.address.to_string(),
+                health.owner.to_string(),
+                format!("{:.2}", health.collateral),
+                format!("{:.2}", health.debt),
+                format_percent(health.margin_ratio),
+                yes_no(health.liquidatable).to_string(),
+            ]
+        })
+        .collect();
+
+    align_columns(HEADER, &rows, 2)
+}
+
+/// Lay out a header and rows in aligned columns, the first `key_columns`
+/// left-aligned and the rest right-aligned
+pub(crate) fn align_columns<const N: usize>(header: [&str; N], rows: &[[String; N]], key_columns: usize) -> String {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_row = |cells: [&str; N]| {
+        let line = cells
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(column, (cell, width))| {
+                // Keys read left to right, numbers line up on the right
+                if column < key_columns {
+                    format!("{:<width$}", cell)
+                } else {
+                    format!("{:>width$}", cell)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        line.trim_end().to_string()
+    };
+
+    let mut lines = vec![format_row(header)];
+    for row in rows {
+        lines.push(format_row(row.each_ref().map(String::as_str)));
+    }
+    lines.join("\n")
+}
+
+/// Render one position's health in detail
+pub fn format_details(health: &PositionHealth) -> String {
+    let fields = [
+        ("Address", health.address.to_string()),
+        ("Owner", health.owner.to_string()),
+        ("Symbol", health.symbol.clone().unwrap_or_else(|| "-".to_string())),
+        ("Mark price", format!("{:.2}", health.mark_price)),
+        ("Collateral", format!("{:.2}", health.collateral)),
+        ("Debt", format!("{:.2}", health.debt)),
+        ("Margin ratio", format_percent(health.margin_ratio)),
+        ("Maintenance margin", format_percent(Some(health.maintenance_margin))),
+        ("Liquidatable", yes_no(health.liquidatable).to_string()),
+        ("Liquidation price", format_price(health.liquidation_price)),
+        ("Distance to liquidation", format_percent(health.distance_to_liquidation)),
+    ];
+    let width = fields.iter().map(|(name, _)| name.len() + 1).max().unwrap_or(0);
+    fields
+        .iter()
+        .map(|(name, value)| format!("{:<width$} {}", format!("{}:", name), value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use liquidation_engine::internals::encode_position_account;
+
+    /// Position accounts as fetched from the chain, with fixed addresses; the
+    /// last one's collateral covers its debt, but not at the program's 85%
+    fn fixture_positions() -> Vec<PositionHealth> {
+        [(1u8, 150u64, 100u64), (2, 90, 100), (3, 500, 0), (4, 110, 100)]
+            .into_iter()
+            .map(|(seed, collateral, debt)| {
+                let account = PositionAccount {
+                    owner: Pubkey::new_from_array([seed + 100; 32]),
+                    bump: 255,
+                    collateral,
+                    debt,
+                    closed: false,
+                    flagged_at: 0,
+                    flag_price: 0,
+                    flag_price_expo: 0,
+                };
+                let data = encode_position_account(&account);
+                let account = decode_position_account(&data).unwrap();
+                PositionHealth::from_account(Pubkey::new_from_array([seed; 32]), &account, None, 1.0)
+            })
+            .collect()
+    }
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        positions: PositionsArgs,
+    }
+
+    #[test]
+    fn test_table_formatting() {
+        let positions = fixture_positions();
+        let table = format_table(&positions);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 5);
+        let address = Pubkey::new_from_array([1; 32]).to_string();
+        let owner = Pubkey::new_from_array([101; 32]).to_string();
+        let width = |key: fn(&PositionHealth) -> String| {
+            positions.iter().map(|health| key(health).len()).max().unwrap()
+        };
+        let (address_width, owner_width) =
+            (width(|health| health.address.to_string()), width(|health| health.owner.to_string()));
+        assert_eq!(
+            lines[0],
+            format!(
+                "{:<address_width$}  {:<owner_width$}  COLLATERAL    DEBT  MARGIN RATIO  LIQUIDATABLE",
+                "PUBKEY", "OWNER"
+            )
+        );
+        assert_eq!(
+            lines[1],
+            format!(
+                "{:<address_width$}  {:<owner_width$}      150.00  100.00       150.00%            no",
+                address, owner
+            )
+        );
+        assert!(lines[2].ends_with("90.00  100.00        90.00%           yes"));
+        assert!(lines[3].ends_with("500.00    0.00             -            no"));
+        assert!(lines[4].ends_with("110.00  100.00       110.00%           yes"));
+        // Columns line up whatever the cell widths
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+
+        assert_eq!(format_table(&[]).lines().count(), 1);
+    }
+
+    #[test]
+    fn test_details_formatting() {
+        let details = format_details(&fixture_positions()[0]);
+        let lines: Vec<&str> = details.lines().collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[2], "Symbol:                  -");
+        assert_eq!(lines[6], "Margin ratio:            150.00%");
+        assert_eq!(lines[7], "Maintenance margin:      117.65%");
+        assert_eq!(lines[9], "Liquidation price:       0.78");
+        assert_eq!(lines[10], "Distance to liquidation: 21.57%");
+    }
+
+    #[test]
+    fn test_json_output() {
+        let positions = fixture_positions();
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string_pretty(&positions).unwrap()).unwrap();
+        let first = &json[0];
+        assert_eq!(first["address"], Pubkey::new_from_array([1; 32]).to_string());
+        assert_eq!(first["owner"], Pubkey::new_from_array([101; 32]).to_string());
+        assert_eq!(first["collateral"], 150.0);
+        assert_eq!(first["debt"], 100.0);
+        assert_eq!(first["margin_ratio"], 1.5);
+        assert_eq!(first["liquidatable"], false);
+        assert_eq!(json[2]["margin_ratio"], serde_json::Value::Null);
+        assert_eq!(json[3]["liquidatable"], true);
+
+        let parsed: Vec<PositionHealth> = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, positions);
+    }
+
+    #[test]
+    fn test_sort_and_filter() {
+        let mut positions = fixture_positions();
+        sort_positions(&mut positions, SortKey::Health);
+        let order: Vec<u64> = positions.iter().map(|health| health.collateral as u64).collect();
+        assert_eq!(order, [90, 110, 150, 500]);
+
+        // Equal debts keep their order
+        sort_positions(&mut positions, SortKey::Size);
+        let order: Vec<u64> = positions.iter().map(|health| health.collateral as u64).collect();
+        assert_eq!(order, [90, 110, 150, 500]);
+
+        let at_risk: Vec<bool> = positions.iter().map(is_at_risk).collect();
+        assert_eq!(at_risk, [true, true, false, false]);
+    }
+
+    #[test]
+    fn test_arguments() {
+        let cli = Cli::parse_from([
+            "positions",
+            "--price-account",
+            &format!("SOL/USD={}", Pubkey::new_from_array([7; 32])),
+            "list",
+            "--sort",
+            "size",
+            "--at-risk",
+            "--json",
+        ]);
+        assert!(cli.positions.json);
+        assert_eq!(cli.positions.program_id, PROGRAM_ID);
+        assert_eq!(
+            cli.positions.price_accounts,
+            [("SOL/USD".to_string(), FeedAddress(Pubkey::new_from_array([7; 32])))]
+        );
+        assert!(matches!(
+            cli.positions.action,
+            PositionsAction::List {
+                sort: SortKey::Size,
+                owner: None,
+                at_risk: true
+            }
+        ));
+
+        assert!(Cli::try_parse_from(["positions", "--price-account", "SOL/USD", "list"]).is_err());
+        assert!(Cli::try_parse_from(["positions", "show", "not-a-pubkey"]).is_err());
+    }
+}
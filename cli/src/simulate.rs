@@ -0,0 +1,632 @@
+use crate::positions::{align_columns, format_percent, format_price, list_positions, parse_price_account, Source};
+use anyhow::{anyhow, Context};
+use clap::{Args, ValueEnum};
+use liquidation_engine::internals::{
+    read_report, simulate, DryRunLiquidation, FeedAddress, PositionSnapshot, RateLimiter, SimulatedLiquidation,
+    SimulationReport, PROGRAM_ID,
+};
+use liquidation_engine::{amount, Amount, LiquidationConfig, OracleProvider, Position, PythOracle};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Arguments of the `simulate` command
+#[derive(Args, Debug)]
+pub struct SimulateArgs {
+    /// Read positions from a JSON snapshot, as `snapshot save` writes them or
+    /// the engine's admin API lists them, instead of the chain
+    #[arg(long, conflicts_with = "engine_url")]
+    snapshot: Option<PathBuf>,
+
+    /// Read the positions monitored by a running engine's admin API instead of
+    /// the program's accounts
+    #[arg(long)]
+    engine_url: Option<String>,
+
+    /// Aggregate the liquidations a dry-running engine reported to its
+    /// dry_run_report_path instead of simulating prices (repeatable)
+    #[arg(long = "report", conflicts_with_all = ["snapshot", "engine_url"])]
+    reports: Vec<PathBuf>,
+
+    /// Solana RPC URL, for position accounts and current prices
+    #[arg(long, default_value = "https://api.devnet.solana.com")]
+    rpc_url: String,
+
+    /// Liquidation program owning the position accounts
+    #[arg(long, default_value_t = PROGRAM_ID)]
+    program_id: Pubkey,
+
+    /// Symbol on-chain collateral is priced in
+    #[arg(long, default_value = "SOL/USD")]
+    collateral_symbol: String,
+
+    /// Pyth price account for a symbol, as SYMBOL=PUBKEY (repeatable)
+    #[arg(long = "price-account", value_parser = parse_price_account)]
+    price_accounts: Vec<(String, FeedAddress)>,
+
+    /// Price to assume for a symbol instead of its oracle price, as
+    /// SYMBOL=PRICE (repeatable)
+    #[arg(long = "price", value_parser = parse_price)]
+    prices: Vec<(String, f64)>,
+
+    /// Relative moves applied to prices, as SYMBOL:PERCENT, e.g.
+    /// "BTC/USD:-12%,ETH/USD:-15%"
+    #[arg(long = "shock", value_parser = parse_shock, value_delimiter = ',')]
+    shocks: Vec<(String, f64)>,
+
+    /// Maintenance margin to judge positions against; defaults to the engine's
+    /// for its positions and the program's for accounts
+    #[arg(long)]
+    maintenance_margin: Option<f64>,
+
+    /// Insurance fund balance bad debt is charged against (in quote currency)
+    #[arg(long, default_value_t = 0.0)]
+    insurance_fund_balance: f64,
+
+    /// Format of the report
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+}
+
+/// Format of a simulation report
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+fn parse_price(value: &str) -> Result<(String, f64), String> {
+    let (symbol, price) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected SYMBOL=PRICE, got {}", value))?;
+    let price: f64 = price.parse().map_err(|e| format!("invalid price {}: {}", price, e))?;
+    if !(price.is_finite() && price > 0.0) {
+        return Err(format!("price must be positive, got {}", price));
+    }
+    Ok((symbol.to_string(), price))
+}
+
+fn parse_shock(value: &str) -> Result<(String, f64), String> {
+    // Split on the last colon, symbols may contain one
+    let (symbol, percent) = value
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected SYMBOL:PERCENT, got {}", value))?;
+    let percent = percent.trim();
+    let percent: f64 = percent
+        .strip_suffix('%')
+        .unwrap_or(percent)
+        .parse()
+        .map_err(|e| format!("invalid shock {}: {}", percent, e))?;
+    if !(percent.is_finite() && percent > -100.0) {
+        return Err(format!("shock must be above -100%, got {}%", percent));
+    }
+    Ok((symbol.trim().to_string(), percent / 100.0))
+}
+
+/// Liquidations of one symbol in dry run reports
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ReportedTotals {
+    pub symbol: String,
+    pub liquidations: usize,
+    /// Notional that would have been liquidated
+    pub notional: f64,
+    /// Rewards the liquidator expected
+    pub rewards: f64,
+    /// Bad debt that would have been realized
+    pub bad_debt: f64,
+}
+
+impl ReportedTotals {
+    fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            liquidations: 0,
+            notional: 0.0,
+            rewards: 0.0,
+            bad_debt: 0.0,
+        }
+    }
+
+    fn add(&mut self, row: &DryRunLiquidation) {
+        self.liquidations += 1;
+        self.notional += row.amount * row.price;
+        self.rewards += row.reward;
+        self.bad_debt += row.bad_debt;
+    }
+}
+
+/// Liquidations a dry-running engine reported, totalled per symbol
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ReportSummary {
+    /// Timestamp of the first reported liquidation
+    pub from: Option<i64>,
+    /// Timestamp of the last reported liquidation
+    pub to: Option<i64>,
+    /// Totals per symbol, in symbol order
+    pub symbols: Vec<ReportedTotals>,
+    pub total: ReportedTotals,
+}
+
+/// Total reported liquidations per symbol
+pub fn summarize_reports(rows: &[DryRunLiquidation]) -> ReportSummary {
+    let mut symbols: BTreeMap<&str, ReportedTotals> = BTreeMap::new();
+    let mut total = ReportedTotals::new("total");
+    for row in rows {
+        symbols
+            .entry(&row.symbol)
+            .or_insert_with(|| ReportedTotals::new(&row.symbol))
+            .add(row);
+        total.add(row);
+    }
+    ReportSummary {
+        from: rows.iter().map(|row| row.timestamp).min(),
+        to: rows.iter().map(|row| row.timestamp).max(),
+        symbols: symbols.into_values().collect(),
+        total,
+    }
+}
+
+/// Apply relative shocks to prices, failing for symbols without a price
+pub fn apply_shocks(prices: &mut HashMap<String, Amount>, shocks: &[(String, f64)]) -> anyhow::Result<()> {
+    for (symbol, shock) in shocks {
+        let price = prices
+            .get_mut(symbol)
+            .ok_or_else(|| anyhow!("No price to shock for {}", symbol))?;
+        *price *= amount::from_f64_lossy(1.0 + shock);
+    }
+    Ok(())
+}
+
+/// Load the book to simulate with the maintenance margin and margin tiers its
+/// source judges it by
+async fn load(args: &SimulateArgs) -> anyhow::Result<(Vec<Position>, LiquidationConfig)> {
+    if let Some(path) = &args.snapshot {
+        return Ok((read_snapshot(path)?, LiquidationConfig::default()));
+    }
+
+    let source = Source::new(&args.rpc_url, args.engine_url.as_deref(), args.program_id);
+    let positions = list_positions(&source, &args.collateral_symbol).await?;
+    let config = match args.engine_url {
+        Some(_) => {
+            let engine = source.engine_config().await?;
+            LiquidationConfig {
+                maintenance_margin: engine.maintenance_margin,
+                margin_tiers: engine.margin_tiers,
+                ..Default::default()
+            }
+        }
+        // Accounts are modelled to be liquidatable at a maintenance margin of zero
+        None => LiquidationConfig {
+            maintenance_margin: 0.0,
+            ..Default::default()
+        },
+    };
+    Ok((positions, config))
+}
+
+/// Positions of a snapshot file, either a versioned snapshot or a plain list
+fn read_snapshot(path: &Path) -> anyhow::Result<Vec<Position>> {
+    let contents = std::fs::read(path).with_context(|| format!("Failed to read snapshot {}", path.display()))?;
+    if contents.trim_ascii_start().starts_with(b"[") {
+        return serde_json::from_slice(&contents).with_context(|| format!("Invalid snapshot {}", path.display()));
+    }
+    Ok(PositionSnapshot::load(path)?.positions)
+}
+
+/// Run the `simulate` command, returning what to print
+///
+/// Only reads positions and prices; nothing is submitted.
+pub async fn run(args: SimulateArgs) -> anyhow::Result<String> {
+    if !args.reports.is_empty() {
+        let mut rows = Vec::new();
+        for path in &args.reports {
+            rows.extend(read_report(path)?);
+        }
+        let summary = summarize_reports(&rows);
+        return Ok(match args.output {
+            OutputFormat::Table => format_summary(&summary),
+            OutputFormat::Json => serde_json::to_string_pretty(&summary)?,
+            OutputFormat::Csv => format_summary_csv(&summary),
+        });
+    }
+
+    let (positions, mut config) = load(&args).await?;
+    if let Some(maintenance_margin) = args.maintenance_margin {
+        config.maintenance_margin = maintenance_margin;
+    }
+
+    // Overridden prices stand in for the oracle's, the rest are fetched
+    let mut prices: HashMap<String, Amount> = HashMap::new();
+    for (symbol, price) in &args.prices {
+        prices.insert(symbol.clone(), amount::from_f64(*price)?);
+    }
+    let missing: BTreeSet<&str> = positions
+        .iter()
+        .map(|position| position.symbol.as_str())
+        .filter(|symbol| !prices.contains_key(*symbol))
+        .collect();
+    if !missing.is_empty() {
+        let oracle = PythOracle::new(
+            args.rpc_url.as_str(),
+            args.price_accounts.iter().cloned().collect(),
+            None,
+            Arc::new(RateLimiter::default()),
+        );
+        for symbol in missing {
+            let price = oracle
+                .get_price(symbol)
+                .await
+                .with_context(|| format!("Failed to price {}", symbol))?;
+            prices.insert(symbol.to_string(), price);
+        }
+    }
+    apply_shocks(&mut prices, &args.shocks)?;
+
+    let report = simulate(&positions, &prices, &config, args.insurance_fund_balance)?;
+    Ok(match args.output {
+        OutputFormat::Table => format_report(&report),
+        OutputFormat::Json => serde_json::to_string_pretty(&report)?,
+        OutputFormat::Csv => format_csv(&report),
+    })
+}
+
+fn liquidation_cells(liquidation: &SimulatedLiquidation) -> [String; 10] {
+    [
+        liquidation.address.to_string(),
+        liquidation.owner.to_string(),
+        liquidation.symbol.clone(),
+        format!("{:.2}", liquidation.price),
+        format_percent(Some(liquidation.margin_ratio)),
+        format_price(liquidation.liquidation_price),
+        format_price(liquidation.bankruptcy_price),
+        format!("{:.2}", liquidation.notional),
+        format!("{:.2}", liquidation.reward),
+        format!("{:.2}", liquidation.bad_debt),
+    ]
+}
+
+/// Render a report as a summary followed by a table of the liquidations
+pub fn format_report(report: &SimulationReport) -> String {
+    const HEADER: [&str; 10] = [
+        "PUBKEY",
+        "OWNER",
+        "SYMBOL",
+        "PRICE",
+        "MARGIN RATIO",
+        "LIQ PRICE",
+        "BANKRUPTCY PRICE",
+        "NOTIONAL",
+        "REWARD",
+        "BAD DEBT",
+    ];
+    let summary = [
+        ("Positions", report.positions.to_string()),
+        ("Liquidatable", report.liquidations.len().to_string()),
+        ("Notional liquidated", format!("{:.2}", report.total_notional)),
+        ("Liquidator rewards", format!("{:.2}", report.total_rewards)),
+        ("Bad debt", format!("{:.2}", report.total_bad_debt)),
+        ("Insurance fund", format!("{:.2}", report.insurance.balance)),
+        ("Insurance remaining", format!("{:.2}", report.insurance.remaining)),
+        ("Insurance shortfall", format!("{:.2}", report.insurance.shortfall)),
+    ];
+    let width = summary.iter().map(|(name, _)| name.len() + 1).max().unwrap_or(0);
+    let mut lines: Vec<String> = summary
+        .iter()
+        .map(|(name, value)| format!("{:<width$} {}", format!("{}:", name), value))
+        .collect();
+    if !report.liquidations.is_empty() {
+        let rows: Vec<[String; 10]> = report.liquidations.iter().map(liquidation_cells).collect();
+        lines.push(String::new());
+        lines.push(align_columns(HEADER, &rows, 3));
+    }
+    lines.join("\n")
+}
+
+/// Render a report's liquidations as CSV, one row per position and a final
+/// row of totals
+pub fn format_csv(report: &SimulationReport) -> String {
+    let mut lines = vec![
+        "address,owner,symbol,price,margin_ratio,liquidation_price,bankruptcy_price,notional,reward,bad_debt".to_string(),
+    ];
+    let optional = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+    for liquidation in &report.liquidations {
+        lines.push(format!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            liquidation.address,
+            liquidation.owner,
+            liquidation.symbol,
+            liquidation.price,
+            liquidation.margin_ratio,
+            optional(liquidation.liquidation_price),
+            optional(liquidation.bankruptcy_price),
+            liquidation.notional,
+            liquidation.reward,
+            liquidation.bad_debt,
+        ));
+    }
+    lines.push(format!(
+        "total,,,,,,,{},{},{}",
+        report.total_notional, report.total_rewards, report.total_bad_debt
+    ));
+    lines.join("\n")
+}
+
+fn totals_cells(totals: &ReportedTotals) -> [String; 5] {
+    [
+        totals.symbol.clone(),
+        totals.liquidations.to_string(),
+        format!("{:.2}", totals.notional),
+        format!("{:.2}", totals.rewards),
+        format!("{:.2}", totals.bad_debt),
+    ]
+}
+
+/// Render a summary of dry run reports as the period covered followed by a
+/// table of totals per symbol
+pub fn format_summary(summary: &ReportSummary) -> String {
+    const HEADER: [&str; 5] = ["SYMBOL", "LIQUIDATIONS", "NOTIONAL", "REWARDS", "BAD DEBT"];
+    let format_ts = |ts: Option<i64>| {
+        ts.and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+            .map_or_else(|| "-".to_string(), |time| time.to_rfc3339())
+    };
+    let mut rows: Vec<[String; 5]> = summary.symbols.iter().map(totals_cells).collect();
+    rows.push(totals_cells(&summary.total));
+    [
+        format!("From: {}", format_ts(summary.from)),
+        format!("To:   {}", format_ts(summary.to)),
+        String::new(),
+        align_columns(HEADER, &rows, 1),
+    ]
+    .join("\n")
+}
+
+/// Render a summary of dry run reports as CSV, one row per symbol and a final
+/// row of totals
+pub fn format_summary_csv(summary: &ReportSummary) -> String {
+    let mut lines = vec!["symbol,liquidations,notional,rewards,bad_debt".to_string()];
+    for totals in summary.symbols.iter().chain([&summary.total]) {
+        lines.push(format!(
+            "{},{},{},{},{}",
+            totals.symbol, totals.liquidations, totals.notional, totals.rewards, totals.bad_debt
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use liquidation_engine::internals::{
+        MarginTier, MarginTierSchedule, OwnerAddress, PositionAddress, REPORT_CSV_HEADER,
+    };
+
+    /// A book of 20 positions: BTC longs of growing margin, and ETH positions
+    /// alternating long and short
+    fn fixture_book() -> Vec<Position> {
+        let btc = (1..=10u8).map(|i| {
+            Position::new(
+                Pubkey::new_from_array([i; 32]),
+                Pubkey::new_from_array([i + 100; 32]),
+                "BTC/USD",
+                1.0,
+                50_000.0,
+                1_000.0 * i as f64,
+                true,
+            )
+        });
+        let eth = (1..=10u8).map(|j| {
+            Position::new(
+                Pubkey::new_from_array([j + 10; 32]),
+                Pubkey::new_from_array([j + 110; 32]),
+                "ETH/USD",
+                10.0,
+                3_000.0,
+                600.0 * j as f64,
+                j % 2 == 1,
+            )
+        });
+        btc.chain(eth).collect()
+    }
+
+    fn fixture_report() -> SimulationReport {
+        let mut prices = HashMap::from([("BTC/USD".to_string(), 50_000.0), ("ETH/USD".to_string(), 3_000.0)]);
+        apply_shocks(
+            &mut prices,
+            &[("BTC/USD".to_string(), -0.12), ("ETH/USD".to_string(), -0.15)],
+        )
+        .unwrap();
+        simulate(&fixture_book(), &prices, &LiquidationConfig::default(), 20_000.0).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        simulate: SimulateArgs,
+    }
+
+    #[test]
+    fn test_simulated_book() {
+        let report = fixture_report();
+        assert_close(report.prices["BTC/USD"], 44_000.0);
+        assert_close(report.prices["ETH/USD"], 2_550.0);
+        assert_eq!(report.positions, 20);
+        // BTC longs with up to 8000 of margin and every ETH long
+        assert_eq!(report.liquidations.len(), 13);
+        assert_close(report.total_notional, 400_750.0);
+        assert_close(report.total_rewards, 40_075.0);
+        assert_close(report.total_bad_debt, 23_400.0);
+        assert_close(report.insurance.remaining, 0.0);
+        assert_close(report.insurance.shortfall, 3_400.0);
+
+        // Every position liquidated is past its liquidation price
+        for liquidation in &report.liquidations {
+            assert!(liquidation.price < liquidation.liquidation_price.unwrap());
+            assert!(liquidation.margin_ratio < LiquidationConfig::default().maintenance_margin);
+        }
+
+        // Without a shock only the thinnest margins are liquidatable, partially
+        // and without bad debt
+        let prices = HashMap::from([("BTC/USD".to_string(), 50_000.0), ("ETH/USD".to_string(), 3_000.0)]);
+        let report = simulate(&fixture_book(), &prices, &LiquidationConfig::default(), 20_000.0).unwrap();
+        assert_eq!(report.liquidations.len(), 4);
+        assert_close(report.total_notional, 80_000.0);
+        assert_eq!(report.total_bad_debt, 0.0);
+        assert_eq!(report.insurance.remaining, 20_000.0);
+
+        // A margin tier holding 50,000 BTC positions to 10% catches two more
+        let config = LiquidationConfig {
+            margin_tiers: HashMap::from([(
+                "BTC/USD".to_string(),
+                MarginTierSchedule::new(vec![MarginTier {
+                    notional_threshold: 40_000.0,
+                    maintenance_margin: 0.1,
+                    max_leverage: 10.0,
+                }]),
+            )]),
+            ..Default::default()
+        };
+        let report = simulate(&fixture_book(), &prices, &config, 20_000.0).unwrap();
+        assert_eq!(report.liquidations.len(), 6);
+
+        assert!(simulate(&fixture_book(), &HashMap::new(), &LiquidationConfig::default(), 0.0).is_err());
+    }
+
+    #[test]
+    fn test_report_formats() {
+        let report = fixture_report();
+        let table = format_report(&report);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[1], "Liquidatable:        13");
+        assert_eq!(lines[7], "Insurance shortfall: 3400.00");
+        assert!(lines[9].starts_with("PUBKEY"));
+        assert_eq!(lines.len(), 10 + 13);
+
+        let csv = format_csv(&report);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 13 + 1);
+        assert!(lines.iter().all(|line| line.split(',').count() == 10));
+        let totals: Vec<f64> = lines[14].split(',').skip(7).map(|cell| cell.parse().unwrap()).collect();
+        assert!(lines[14].starts_with("total,"));
+        for (total, expected) in totals.into_iter().zip([400_750.0, 40_075.0, 23_400.0]) {
+            assert_close(total, expected);
+        }
+
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        assert_eq!(json["liquidations"][0]["address"], Pubkey::new_from_array([1; 32]).to_string());
+        assert_eq!(json["insurance"]["shortfall"], 3_400.0);
+    }
+
+    #[test]
+    fn test_read_snapshot_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let positions = vec![Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 50000.0, 0.0, true)];
+
+        let list = dir.path().join("list.json");
+        std::fs::write(&list, serde_json::to_vec(&positions).unwrap()).unwrap();
+        assert_eq!(read_snapshot(&list).unwrap(), positions);
+
+        let versioned = dir.path().join("snapshot.json");
+        PositionSnapshot::new(positions.clone(), 0).save(&versioned).unwrap();
+        assert_eq!(read_snapshot(&versioned).unwrap(), positions);
+
+        std::fs::write(&versioned, "{\"version\": 2}").unwrap();
+        let error = read_snapshot(&versioned).unwrap_err().to_string();
+        assert!(error.contains("version 2 isn't supported"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_reports_aggregated() {
+        let dir = tempfile::tempdir().unwrap();
+        let row = |symbol: &str, timestamp: i64, price: f64, bad_debt: f64| DryRunLiquidation {
+            timestamp,
+            position: PositionAddress::new_unique(),
+            owner: OwnerAddress::new_unique(),
+            symbol: symbol.to_string(),
+            price,
+            margin_ratio: 0.04,
+            amount: 0.5,
+            reward: price * 0.05,
+            bad_debt,
+        };
+        let csv = dir.path().join("dry_run-2023-11-15.csv");
+        let json = dir.path().join("dry_run-2023-11-16.jsonl");
+        let rows = [
+            row("BTC/USD", 1_700_006_400, 50_000.0, 0.0),
+            row("ETH/USD", 1_700_006_500, 3_000.0, 100.0),
+            row("BTC/USD", 1_700_092_800, 48_000.0, 250.0),
+        ];
+        let csv_rows: Vec<String> = rows[..2]
+            .iter()
+            .map(|row| {
+                format!(
+                    "{},{},{},{},{},{},{},{},{}",
+                    row.timestamp, row.position, row.owner, row.symbol, row.price, row.margin_ratio, row.amount,
+                    row.reward, row.bad_debt
+                )
+            })
+            .collect();
+        std::fs::write(&csv, format!("{}\n{}\n", REPORT_CSV_HEADER, csv_rows.join("\n"))).unwrap();
+        std::fs::write(&json, serde_json::to_string(&rows[2]).unwrap()).unwrap();
+
+        let cli = Cli::parse_from([
+            "simulate",
+            "--report",
+            csv.to_str().unwrap(),
+            "--report",
+            json.to_str().unwrap(),
+            "--output",
+            "json",
+        ]);
+        let summary: serde_json::Value = serde_json::from_str(&run(cli.simulate).await.unwrap()).unwrap();
+        assert_eq!(summary["from"], 1_700_006_400);
+        assert_eq!(summary["to"], 1_700_092_800);
+        assert_eq!(summary["symbols"][0]["symbol"], "BTC/USD");
+        assert_eq!(summary["symbols"][0]["liquidations"], 2);
+        assert_eq!(summary["symbols"][0]["notional"], 49_000.0);
+        assert_eq!(summary["symbols"][1]["bad_debt"], 100.0);
+        assert_eq!(summary["total"]["liquidations"], 3);
+        assert_eq!(summary["total"]["bad_debt"], 350.0);
+
+        let summary = summarize_reports(&rows);
+        let table = format_summary(&summary);
+        assert_eq!(table.lines().next(), Some("From: 2023-11-15T00:00:00+00:00"));
+        assert!(table.lines().last().unwrap().starts_with("total"));
+        let csv = format_summary_csv(&summary);
+        assert_eq!(csv.lines().nth(3), Some("total,3,50500,5050,350"));
+
+        assert!(Cli::try_parse_from(["simulate", "--report", "a.csv", "--snapshot", "book.json"]).is_err());
+    }
+
+    #[test]
+    fn test_arguments() {
+        let cli = Cli::parse_from([
+            "simulate",
+            "--snapshot",
+            "book.json",
+            "--price",
+            "BTC/USD=50000",
+            "--shock",
+            "BTC/USD:-12%,ETH/USD:-15%",
+            "--output",
+            "csv",
+        ]);
+        assert_eq!(cli.simulate.prices, [("BTC/USD".to_string(), 50_000.0)]);
+        assert_eq!(
+            cli.simulate.shocks,
+            [("BTC/USD".to_string(), -0.12), ("ETH/USD".to_string(), -0.15)]
+        );
+        assert_eq!(cli.simulate.output, OutputFormat::Csv);
+
+        assert!(Cli::try_parse_from(["simulate", "--shock", "BTC/USD-12%"]).is_err());
+        assert!(Cli::try_parse_from(["simulate", "--shock", "BTC/USD:-120%"]).is_err());
+        assert!(Cli::try_parse_from(["simulate", "--price", "BTC/USD=-1"]).is_err());
+        assert!(Cli::try_parse_from(["simulate", "--snapshot", "book.json", "--engine-url", "http://engine"]).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::positions::{fetch_json, list_positions, Source};
+use anyhow::{anyhow, Context};
+use clap::{Args, Subcommand};
+use liquidation_engine::internals::{PositionSnapshot, PROGRAM_ID};
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+/// Arguments of the `snapshot` command
+#[derive(Args, Debug)]
+pub struct SnapshotArgs {
+    /// Solana RPC URL, for position accounts
+    #[arg(long, global = true, default_value = "https://api.devnet.solana.com")]
+    rpc_url: String,
+
+    /// Running engine's admin API to snapshot or load into; snapshots are taken
+    /// of the program's accounts without one
+    #[arg(long, global = true)]
+    engine_url: Option<String>,
+
+    /// Liquidation program owning the position accounts
+    #[arg(long, global = true, default_value_t = PROGRAM_ID)]
+    program_id: Pubkey,
+
+    /// Symbol on-chain collateral is priced in
+    #[arg(long, global = true, default_value = "SOL/USD")]
+    collateral_symbol: String,
+
+    #[command(subcommand)]
+    action: SnapshotAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotAction {
+    /// Write the engine's monitored positions, or the program's accounts, to a file
+    Save {
+        /// Snapshot file to write
+        path: PathBuf,
+    },
+    /// Load a snapshot file into a running engine
+    Load {
+        /// Snapshot file to read
+        path: PathBuf,
+
+        /// Add the snapshot's positions to the engine's instead of replacing them
+        #[arg(long, default_value_t = false)]
+        merge: bool,
+    },
+}
+
+/// The engine's response to `PUT /snapshot`
+#[derive(serde::Deserialize)]
+struct Restored {
+    imported: usize,
+    monitored: usize,
+}
+
+/// Run the `snapshot` command, returning what to print
+pub async fn run(args: SnapshotArgs) -> anyhow::Result<String> {
+    let source = Source::new(&args.rpc_url, args.engine_url.as_deref(), args.program_id);
+    match args.action {
+        SnapshotAction::Save { path } => {
+            let snapshot = match &source {
+                Source::Engine { client, url } => fetch_json(client.get(format!("{}/snapshot", url))).await?,
+                Source::Chain { .. } => PositionSnapshot::new(
+                    list_positions(&source, &args.collateral_symbol).await?,
+                    chrono::Utc::now().timestamp(),
+                ),
+            };
+            snapshot
+                .save(&path)
+                .with_context(|| format!("Failed to write snapshot {}", path.display()))?;
+            Ok(format!("Saved {} positions to {}", snapshot.positions.len(), path.display()))
+        }
+        SnapshotAction::Load { path, merge } => {
+            // Checked before anything is sent, so problems are reported against the file
+            let snapshot = PositionSnapshot::load(&path)?;
+            let Source::Engine { client, url } = &source else {
+                return Err(anyhow!("Loading a snapshot needs --engine-url; on-chain positions can't be written"));
+            };
+            let request = client
+                .put(format!("{}/snapshot", url))
+                .query(&[("merge", merge)])
+                .json(&snapshot);
+            let restored: Restored = fetch_json(request).await?;
+            Ok(format!(
+                "Loaded {} positions into the engine, which now monitors {}",
+                restored.imported, restored.monitored
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use liquidation_engine::Position;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        snapshot: SnapshotArgs,
+    }
+
+    #[tokio::test]
+    async fn test_load_checks_file_and_needs_engine() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positions.json");
+        let path_arg = path.to_str().unwrap();
+
+        std::fs::write(&path, "{\"version\": 1, \"positions\": [").unwrap();
+        let cli = Cli::parse_from(["snapshot", "load", path_arg, "--engine-url", "http://127.0.0.1:9"]);
+        let error = run(cli.snapshot).await.unwrap_err().to_string();
+        assert!(error.contains("Invalid position snapshot"), "{}", error);
+
+        let position = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 50000.0, 0.0, true);
+        PositionSnapshot::new(vec![position], 0).save(&path).unwrap();
+        let cli = Cli::parse_from(["snapshot", "load", path_arg, "--merge"]);
+        let error = run(cli.snapshot).await.unwrap_err().to_string();
+        assert!(error.contains("needs --engine-url"), "{}", error);
+    }
+}
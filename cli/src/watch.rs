@@ -0,0 +1,451 @@
+use crate::positions::{fetch_json, format_percent, format_price, parse_price_account, Fetched, Pricer, Source};
+use chrono::{DateTime, Utc};
+use clap::Args;
+use liquidation_engine::internals::{EngineStats, FeedAddress, PositionAddress, PositionHealth, PROGRAM_ID};
+use liquidation_engine::{Position, PositionStatus};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table};
+use ratatui::Frame;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Every status, in the order the header counts them
+const STATUSES: [PositionStatus; 5] = [
+    PositionStatus::Active,
+    PositionStatus::AtRisk,
+    PositionStatus::Liquidating,
+    PositionStatus::Liquidated,
+    PositionStatus::Closed,
+];
+
+/// Arguments of the `watch` command
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    /// Solana RPC URL, for position accounts and oracle prices
+    #[arg(long, default_value = "https://api.devnet.solana.com")]
+    rpc_url: String,
+
+    /// Watch the positions monitored by a running engine's admin API instead of
+    /// the program's accounts
+    #[arg(long)]
+    engine_url: Option<String>,
+
+    /// Liquidation program owning the position accounts
+    #[arg(long, default_value_t = PROGRAM_ID)]
+    program_id: Pubkey,
+
+    /// Pyth price account for a symbol, as SYMBOL=PUBKEY (repeatable)
+    #[arg(long = "price-account", value_parser = parse_price_account)]
+    price_accounts: Vec<(String, FeedAddress)>,
+
+    /// Symbol to price on-chain collateral in; without one, collateral counts
+    /// one for one against debt as the program does
+    #[arg(long)]
+    collateral_symbol: Option<String>,
+
+    /// Seconds between refreshes
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+
+    /// Number of positions shown, closest to liquidation first
+    #[arg(long, default_value_t = 20)]
+    top: usize,
+
+    /// Only positions in this symbol (repeatable)
+    #[arg(long = "symbol")]
+    symbols: Vec<String>,
+}
+
+/// A watched position with its status
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchRow {
+    pub health: PositionHealth,
+    pub status: PositionStatus,
+    /// Seconds until the current drift of its price would liquidate it, as
+    /// estimated by the engine for the positions closest to liquidation
+    pub time_to_liquidation_secs: Option<f64>,
+}
+
+/// What the dashboard shows, as of the last check
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Watched positions, closest to liquidation first
+    pub rows: Vec<WatchRow>,
+    /// When positions were last read, if they ever were
+    pub checked_at: Option<DateTime<Utc>>,
+    /// Why the last check failed, if it did
+    pub error: Option<String>,
+}
+
+impl Snapshot {
+    /// Snapshot of rows checked at `checked_at`, putting them in display order
+    pub fn new(mut rows: Vec<WatchRow>, checked_at: DateTime<Utc>) -> Self {
+        sort_by_proximity(&mut rows);
+        Self {
+            rows,
+            checked_at: Some(checked_at),
+            error: None,
+        }
+    }
+
+    /// Number of positions with each status
+    pub fn status_counts(&self) -> [(PositionStatus, usize); 5] {
+        STATUSES.map(|status| (status, self.rows.iter().filter(|row| row.status == status).count()))
+    }
+}
+
+/// Order rows closest to liquidation first; rows that can't be liquidated last
+pub fn sort_by_proximity(rows: &mut [WatchRow]) {
+    rows.sort_by(|a, b| {
+        let distance = |row: &WatchRow| row.health.distance_to_liquidation.unwrap_or(f64::INFINITY);
+        distance(a).total_cmp(&distance(b))
+    });
+}
+
+fn status_label(status: PositionStatus) -> &'static str {
+    match status {
+        PositionStatus::Active => "Active",
+        PositionStatus::AtRisk => "At risk",
+        PositionStatus::Liquidating => "Liquidating",
+        PositionStatus::Liquidated => "Liquidated",
+        PositionStatus::Closed => "Closed",
+    }
+}
+
+fn status_style(status: PositionStatus) -> Style {
+    let color = match status {
+        PositionStatus::Active => Color::Green,
+        PositionStatus::AtRisk => Color::Yellow,
+        PositionStatus::Liquidating => Color::Red,
+        PositionStatus::Liquidated => Color::Magenta,
+        PositionStatus::Closed => Color::DarkGray,
+    };
+    Style::default().fg(color)
+}
+
+/// Shorten an address to its first and last four characters
+fn abbreviate(address: &PositionAddress) -> String {
+    let address = address.to_string();
+    format!("{}..{}", &address[..4], &address[address.len() - 4..])
+}
+
+/// Distance to liquidation in basis points of the mark price
+fn format_bps(distance: Option<f64>) -> String {
+    distance.map_or_else(|| "-".to_string(), |distance| format!("{:.0} bps", distance * 10_000.0))
+}
+
+/// A time to liquidation in its two largest units
+fn format_time_to_liquidation(secs: Option<f64>) -> String {
+    let Some(secs) = secs else {
+        return "-".to_string();
+    };
+    let secs = secs.round() as u64;
+    match secs {
+        0 => "now".to_string(),
+        1..60 => format!("{}s", secs),
+        60..3_600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        3_600..86_400 => format!("{}h {:02}m", secs / 3_600, secs % 3_600 / 60),
+        _ => format!("{}d {:02}h", secs / 86_400, secs % 86_400 / 3_600),
+    }
+}
+
+/// Draw the dashboard: a header with status counts and the last check, then
+/// the `top` positions closest to liquidation
+pub fn render(frame: &mut Frame, snapshot: &Snapshot, top: usize) {
+    let [header_area, table_area] = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+
+    let mut header: Vec<Span> = Vec::new();
+    for (status, count) in snapshot.status_counts() {
+        header.push(Span::styled(format!("{}: {}", status_label(status), count), status_style(status)));
+        header.push(Span::raw("  "));
+    }
+    let checked_at = snapshot
+        .checked_at
+        .map_or_else(|| "never".to_string(), |time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string());
+    header.push(Span::raw(format!("Last check: {}", checked_at)));
+    if let Some(error) = &snapshot.error {
+        header.push(Span::styled(format!("  Error: {}", error), Style::default().fg(Color::Red)));
+    }
+    frame.render_widget(Paragraph::new(Line::from(header)), header_area);
+
+    let rows = snapshot.rows.iter().take(top).map(|row| {
+        let health = &row.health;
+        Row::new([
+            Cell::from(abbreviate(&health.address)),
+            Cell::from(health.symbol.clone().unwrap_or_else(|| "-".to_string())),
+            Cell::from(format!("{:.2}", health.mark_price)),
+            Cell::from(format_percent(health.margin_ratio)),
+            Cell::from(format_price(health.liquidation_price)),
+            Cell::from(format_bps(health.distance_to_liquidation)),
+            Cell::from(format_time_to_liquidation(row.time_to_liquidation_secs)),
+            Cell::from(status_label(row.status)).style(status_style(row.status)),
+        ])
+    });
+    let widths = [
+        Constraint::Length(10),
+        Constraint::Length(10),
+        Constraint::Length(12),
+        Constraint::Length(12),
+        Constraint::Length(12),
+        Constraint::Length(10),
+        Constraint::Length(11),
+        Constraint::Length(11),
+    ];
+    let header = Row::new(["POSITION", "SYMBOL", "PRICE", "MARGIN", "LIQ PRICE", "DISTANCE", "TIME TO LIQ", "STATUS"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let title = format!(
+        " Closest to liquidation ({} of {}) - q to quit ",
+        snapshot.rows.len().min(top),
+        snapshot.rows.len()
+    );
+    let table = Table::new(rows, widths).header(header).block(Block::bordered().title(title));
+    frame.render_widget(table, table_area);
+}
+
+/// Symbol a fetched position is priced in
+fn symbol<'a>(fetched: &'a Fetched, collateral_symbol: Option<&'a str>) -> Option<&'a str> {
+    match fetched {
+        Fetched::Account(..) => collateral_symbol,
+        Fetched::Monitored(position) => Some(&position.symbol),
+    }
+}
+
+/// Status of each position monitored by an engine, or `None` when reading the
+/// chain, whose accounts carry no status
+async fn engine_statuses(source: &Source) -> anyhow::Result<Option<HashMap<PositionAddress, PositionStatus>>> {
+    let Source::Engine { client, url } = source else {
+        return Ok(None);
+    };
+    let mut statuses = HashMap::new();
+    for status in STATUSES {
+        let request = client.get(format!("{}/positions", url)).query(&[("status", status)]);
+        let positions: Vec<Position> = fetch_json(request).await?;
+        statuses.extend(positions.into_iter().map(|position| (position.address, status)));
+    }
+    Ok(Some(statuses))
+}
+
+/// Estimated time to liquidation of the positions an engine lists as closest
+/// to it, or nothing when reading the chain
+async fn engine_times_to_liquidation(source: &Source) -> anyhow::Result<HashMap<PositionAddress, f64>> {
+    let Source::Engine { client, url } = source else {
+        return Ok(HashMap::new());
+    };
+    let stats: EngineStats = fetch_json(client.get(format!("{}/stats", url))).await?;
+    Ok(stats
+        .closest_to_liquidation
+        .into_iter()
+        .filter_map(|update| Some((update.address, update.estimated_time_to_liquidation_secs?)))
+        .collect())
+}
+
+/// Read and price the watched positions
+async fn check(source: &Source, pricer: &mut Pricer, args: &WatchArgs) -> anyhow::Result<Vec<WatchRow>> {
+    pricer.clear();
+    let statuses = engine_statuses(source).await?;
+    let times_to_liquidation = engine_times_to_liquidation(source).await?;
+    let mut rows = Vec::new();
+    for fetched in source.list(None).await? {
+        let symbol = symbol(&fetched, args.collateral_symbol.as_deref());
+        if !args.symbols.is_empty() && !symbol.is_some_and(|symbol| args.symbols.iter().any(|s| s == symbol)) {
+            continue;
+        }
+        let health = pricer.health(&fetched).await?;
+        // Without the engine's view, positions are at risk once liquidatable
+        let status = statuses
+            .as_ref()
+            .and_then(|statuses| statuses.get(&health.address).copied())
+            .unwrap_or(if health.liquidatable {
+                PositionStatus::AtRisk
+            } else {
+                PositionStatus::Active
+            });
+        let time_to_liquidation_secs = times_to_liquidation.get(&health.address).copied();
+        rows.push(WatchRow {
+            health,
+            status,
+            time_to_liquidation_secs,
+        });
+    }
+    Ok(rows)
+}
+
+/// Whether a key press ends the watch: q, Esc or ctrl-c
+fn is_quit(key: &KeyEvent) -> bool {
+    key.kind == KeyEventKind::Press
+        && (matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+            || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)))
+}
+
+/// Run the `watch` command until the user quits
+pub async fn run(args: WatchArgs) -> anyhow::Result<()> {
+    let source = Source::new(&args.rpc_url, args.engine_url.as_deref(), args.program_id);
+    let mut pricer = Pricer::new(
+        &args.rpc_url,
+        &args.price_accounts,
+        args.collateral_symbol.clone(),
+        source.maintenance_margin().await?,
+    );
+
+    let mut terminal = ratatui::init();
+    // Terminal events block, so they're read on their own thread
+    let (sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut interval = tokio::time::interval(Duration::from_secs(args.interval));
+    let mut snapshot = Snapshot::default();
+    let result = loop {
+        tokio::select! {
+            _ = interval.tick() => match check(&source, &mut pricer, &args).await {
+                Ok(rows) => snapshot = Snapshot::new(rows, Utc::now()),
+                // Keep showing the last positions read
+                Err(e) => snapshot.error = Some(format!("{:#}", e)),
+            },
+            event = events.recv() => match event {
+                Some(Event::Key(key)) if is_quit(&key) => break Ok(()),
+                // Anything else, resizes included, just redraws
+                Some(_) => {}
+                None => break Ok(()),
+            },
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+        }
+        if let Err(e) = terminal.draw(|frame| render(frame, &snapshot, args.top)) {
+            break Err(e.into());
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use clap::Parser;
+    use liquidation_engine::internals::PositionAccount;
+    use ratatui::backend::TestBackend;
+    use ratatui::buffer::Buffer;
+    use ratatui::Terminal;
+
+    fn fixture_snapshot() -> Snapshot {
+        let rows = [
+            (1u8, 150u64, 100u64, PositionStatus::Active, Some(200_000.0)),
+            (2, 90, 100, PositionStatus::Liquidating, Some(5_400.0)),
+            (3, 500, 0, PositionStatus::Active, None),
+            (4, 105, 100, PositionStatus::AtRisk, None),
+        ]
+        .into_iter()
+        .map(|(seed, collateral, debt, status, time_to_liquidation_secs)| {
+            let account = PositionAccount {
+                owner: Pubkey::new_from_array([seed + 100; 32]),
+                bump: 255,
+                collateral,
+                debt,
+                closed: false,
+                flagged_at: 0,
+                flag_price: 0,
+                flag_price_expo: 0,
+            };
+            let health = PositionHealth::from_account(Pubkey::new_from_array([seed; 32]), &account, Some("SOL/USD"), 1.5);
+            WatchRow {
+                health,
+                status,
+                time_to_liquidation_secs,
+            }
+        })
+        .collect();
+        Snapshot::new(rows, Utc.with_ymd_and_hms(2026, 10, 14, 12, 30, 0).unwrap())
+    }
+
+    fn buffer_lines(buffer: &Buffer) -> Vec<String> {
+        let width = buffer.area.width as usize;
+        buffer
+            .content
+            .chunks(width)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>().trim_end().to_string())
+            .collect()
+    }
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        watch: WatchArgs,
+    }
+
+    #[test]
+    fn test_rendered_frame() {
+        let mut terminal = Terminal::new(TestBackend::new(110, 8)).unwrap();
+        let snapshot = fixture_snapshot();
+        terminal.draw(|frame| render(frame, &snapshot, 3)).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let lines = buffer_lines(buffer);
+        let address = |seed: u8| abbreviate(&PositionAddress(Pubkey::new_from_array([seed; 32])));
+        let expected = [
+            "Active: 2  At risk: 1  Liquidating: 1  Liquidated: 0  Closed: 0  Last check: 2026-10-14 12:30:00 UTC".to_string(),
+            "┌ Closest to liquidation (3 of 4) - q to quit ───────────────────────────────────────────────────────────────┐".to_string(),
+            "│POSITION   SYMBOL     PRICE        MARGIN       LIQ PRICE    DISTANCE   TIME TO LIQ STATUS                  │".to_string(),
+            format!("│{} SOL/USD    1.50         135.00%      1.31         1285 bps   1h 30m      Liquidating             │", address(2)),
+            format!("│{} SOL/USD    1.50         157.50%      1.12         2530 bps   -           At risk                 │", address(4)),
+            format!("│{} SOL/USD    1.50         225.00%      0.78         4771 bps   2d 07h      Active                  │", address(1)),
+            "│                                                                                                            │".to_string(),
+            "└────────────────────────────────────────────────────────────────────────────────────────────────────────────┘".to_string(),
+        ];
+        assert_eq!(lines, expected);
+
+        // Statuses are color-coded
+        let status_column = 85;
+        assert_eq!(buffer[(status_column, 3)].symbol(), "L");
+        assert_eq!(buffer[(status_column, 3)].fg, Color::Red);
+        assert_eq!(buffer[(0, 0)].fg, Color::Green);
+    }
+
+    #[test]
+    fn test_time_to_liquidation_format() {
+        assert_eq!(format_time_to_liquidation(None), "-");
+        assert_eq!(format_time_to_liquidation(Some(0.2)), "now");
+        assert_eq!(format_time_to_liquidation(Some(45.0)), "45s");
+        assert_eq!(format_time_to_liquidation(Some(725.0)), "12m 05s");
+        assert_eq!(format_time_to_liquidation(Some(7_260.0)), "2h 01m");
+        assert_eq!(format_time_to_liquidation(Some(3.0 * 86_400.0 + 7_200.0)), "3d 02h");
+        assert_eq!(format_bps(Some(-0.0185)), "-185 bps");
+    }
+
+    #[test]
+    fn test_empty_frame() {
+        let mut terminal = Terminal::new(TestBackend::new(110, 4)).unwrap();
+        let snapshot = Snapshot {
+            error: Some("Connection refused".to_string()),
+            ..Snapshot::default()
+        };
+        terminal.draw(|frame| render(frame, &snapshot, 3)).unwrap();
+        let lines = buffer_lines(terminal.backend().buffer());
+        assert!(lines[0].ends_with("Last check: never  Error: Connection refused"));
+        assert!(lines[1].contains("(0 of 0)"));
+    }
+
+    #[test]
+    fn test_quit_keys() {
+        assert!(is_quit(&KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE)));
+        assert!(is_quit(&KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+        assert!(!is_quit(&KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn test_arguments() {
+        let cli = Cli::parse_from(["watch", "--interval", "2", "--top", "5", "--symbol", "BTC/USD", "--symbol", "ETH/USD"]);
+        assert_eq!((cli.watch.interval, cli.watch.top), (2, 5));
+        assert_eq!(cli.watch.symbols, ["BTC/USD", "ETH/USD"]);
+        assert!(Cli::try_parse_from(["watch", "--interval", "0"]).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+//! Embed the liquidation engine in another program, pricing positions with
+//! that program's own oracle
+//!
+//! The engine is built through the library's public API alone, the same way the
+//! `liquidation-engine` binary builds it. It runs one dry run check cycle over
+//! a position added by hand, so nothing is sent to the cluster:
+//!
+//! ```text
+//! cargo run -p liquidation-engine --example embed
+//! ```
+
+use async_trait::async_trait;
+use liquidation_engine::{
+    Amount, LiquidationConfig, LiquidationEngine, LiquidationError, OracleProvider, Position, PositionStatus,
+};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Prices from a fixed table, standing in for an in-house price service
+#[derive(Debug)]
+struct TablePrices(HashMap<String, Amount>);
+
+#[async_trait]
+impl OracleProvider for TablePrices {
+    async fn get_price(&self, symbol: &str) -> Result<Amount, LiquidationError> {
+        self.0
+            .get(symbol)
+            .copied()
+            .ok_or_else(|| LiquidationError::OracleError(format!("No price for {}", symbol)))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), LiquidationError> {
+    let oracle = TablePrices(HashMap::from([("BTC/USD".to_string(), Amount::from(55000))]));
+    let engine = LiquidationEngine::builder()
+        .rpc_client("https://api.devnet.solana.com")
+        .oracle(Arc::new(oracle))
+        .config(LiquidationConfig {
+            dry_run: true,
+            ..Default::default()
+        })
+        .build()?;
+
+    // A 1 BTC long from 60,000 with 5,000 of margin is underwater at 55,000
+    let (size, entry_price, margin) = (Amount::from(1), Amount::from(60000), Amount::from(5000));
+    let (address, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let position = Position::new(address, owner, "BTC/USD", size, entry_price, margin, true);
+    engine.add_position(position.clone()).await?;
+    assert_eq!(engine.position_status(&position).await, PositionStatus::AtRisk);
+
+    for result in engine.check_positions().await? {
+        println!("{}", result);
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use crate::error::LiquidationError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+
+/// Defines a [`Pubkey`] newtype for one kind of address, so a position's
+/// address can't be passed where its owner's is expected, or the other way
+/// around, without the compiler noticing
+///
+/// The types print, parse and serialize as base58 like the key they wrap, and
+/// convert to and from it for the `solana_sdk` APIs that take one.
+macro_rules! address_type {
+    ($(#[$doc:meta])* $name:ident, $kind:literal) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        #[repr(transparent)]
+        pub struct $name(pub Pubkey);
+
+        impl $name {
+            /// A random address, for tests
+            pub fn new_unique() -> Self {
+                Self(Pubkey::new_unique())
+            }
+
+            /// The key the address wraps
+            pub const fn pubkey(&self) -> Pubkey {
+                self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.0, f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = LiquidationError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Pubkey::from_str(s)
+                    .map(Self)
+                    .map_err(|e| LiquidationError::ConfigError(format!("Invalid {} address {:?}: {}", $kind, s, e)))
+            }
+        }
+
+        impl From<Pubkey> for $name {
+            fn from(pubkey: Pubkey) -> Self {
+                Self(pubkey)
+            }
+        }
+
+        impl From<$name> for Pubkey {
+            fn from(address: $name) -> Self {
+                address.0
+            }
+        }
+
+        impl AsRef<Pubkey> for $name {
+            fn as_ref(&self) -> &Pubkey {
+                &self.0
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+address_type!(
+    /// Address of a position account
+    PositionAddress,
+    "position"
+);
+
+address_type!(
+    /// Address of the wallet owning positions
+    OwnerAddress,
+    "owner"
+);
+
+address_type!(
+    /// Address of an oracle's price feed account
+    FeedAddress,
+    "feed"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_addresses_round_trip_as_base58() {
+        let address = PositionAddress::new_unique();
+        let json = serde_json::to_string(&address).unwrap();
+        assert_eq!(json, format!("\"{}\"", address.pubkey()));
+        assert_eq!(serde_json::from_str::<PositionAddress>(&json).unwrap(), address);
+
+        // Keys of maps serialize as the same strings
+        let owners = HashMap::from([(OwnerAddress::new_unique(), 1u32)]);
+        let json = serde_json::to_string(&owners).unwrap();
+        assert_eq!(serde_json::from_str::<HashMap<OwnerAddress, u32>>(&json).unwrap(), owners);
+
+        let feed = FeedAddress::new_unique();
+        assert_eq!(feed.to_string(), feed.pubkey().to_string());
+        assert_eq!(format!("{:?}", feed), format!("{:?}", feed.pubkey()));
+        assert_eq!(feed.to_string().parse::<FeedAddress>().unwrap(), feed);
+    }
+
+    #[test]
+    fn test_unparseable_address_names_the_string() {
+        let err = "not-a-key".parse::<OwnerAddress>().unwrap_err();
+        match err {
+            LiquidationError::ConfigError(msg) => assert!(msg.contains("owner") && msg.contains("\"not-a-key\""), "{}", msg),
+            other => panic!("expected a configuration error, got {:?}", other),
+        }
+        assert!(serde_json::from_str::<PositionAddress>("\"not-a-key\"").is_err());
+    }
+
+    #[test]
+    fn test_addresses_convert_to_and_from_pubkeys() {
+        let pubkey = Pubkey::new_unique();
+        let address = PositionAddress::from(pubkey);
+        assert_eq!(Pubkey::from(address), pubkey);
+        assert_eq!(address.as_ref(), &pubkey);
+        let owner: OwnerAddress = pubkey.into();
+        assert_eq!(owner.pubkey(), pubkey);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::address::{OwnerAddress, PositionAddress};
+use crate::amount::{Amount, AmountExt};
+use crate::position::Position;
+use std::collections::HashMap;
+
+/// A profitable position's place in its symbol's auto-deleveraging queue
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AdlEntry {
+    /// The position's address
+    pub position: PositionAddress,
+    /// The owner's address
+    pub owner: OwnerAddress,
+    /// The current size (in base currency)
+    pub size: f64,
+    /// Unrealized profit at the queue's prices (in quote currency)
+    pub unrealized_pnl: f64,
+    /// Position value over equity
+    pub leverage: f64,
+    /// Profit as a share of margin, times leverage; higher scores are deleveraged first
+    pub score: f64,
+    /// Share of the side's queue ranked at or behind the position, so 1.0 is
+    /// first in line
+    pub quantile: f64,
+}
+
+/// Profitable positions on each side of a symbol, in the order they'd be
+/// auto-deleveraged
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AdlQueue {
+    /// The trading pair symbol
+    pub symbol: String,
+    /// Profitable longs, deleveraged to cover bankrupt shorts
+    pub longs: Vec<AdlEntry>,
+    /// Profitable shorts, deleveraged to cover bankrupt longs
+    pub shorts: Vec<AdlEntry>,
+}
+
+impl AdlQueue {
+    /// Queue of the given side, first in line first
+    pub fn side(&self, is_long: bool) -> &[AdlEntry] {
+        if is_long { &self.longs } else { &self.shorts }
+    }
+}
+
+/// A counterparty position cut back by an auto-deleveraging plan
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AdlReduction {
+    /// The position's address
+    pub position: PositionAddress,
+    /// Size closed (in base currency)
+    pub size: f64,
+    /// Profit given up to cover bad debt (in quote currency)
+    pub pnl_absorbed: f64,
+}
+
+/// Counterparty reductions that would absorb bad debt the insurance fund can't
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AdlPlan {
+    /// The trading pair symbol
+    pub symbol: String,
+    /// Whether the bankrupt position is long, so shorts are deleveraged
+    pub bankrupt_is_long: bool,
+    /// Bad debt to absorb (in quote currency)
+    pub bad_debt: f64,
+    /// Part of the bad debt covered by the insurance fund (in quote currency)
+    pub insurance_covered: f64,
+    /// Reductions in queue order
+    pub reductions: Vec<AdlReduction>,
+    /// Bad debt left once the whole queue is deleveraged (in quote currency)
+    pub uncovered: f64,
+}
+
+/// Auto-deleveraging profit-times-leverage score of a position at a price, or
+/// `None` if it isn't profitable
+pub(crate) fn adl_score(position: &Position, price: Amount) -> Option<(f64, f64)> {
+    let pnl = position.unrealized_pnl(price).as_f64();
+    let margin = position.effective_margin().as_f64();
+    let value = position.value(price).as_f64();
+    if !(pnl > 0.0 && margin > 0.0 && value > 0.0) {
+        return None;
+    }
+    let leverage = value / (margin + pnl);
+    Some((pnl / margin * leverage, leverage))
+}
+
+/// Ranks positions for auto-deleveraging and plans how bad debt would be absorbed
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AdlPlanner {
+    queues: HashMap<String, AdlQueue>,
+}
+
+impl AdlPlanner {
+    /// Rank the positions of every priced symbol at `prices`
+    ///
+    /// Equal scores are ordered by address, so queues don't depend on the order
+    /// of `positions`.
+    pub fn new(positions: &[Position], prices: &HashMap<String, Amount>) -> Self {
+        let mut queues: HashMap<String, AdlQueue> = HashMap::new();
+        for position in positions {
+            let Some(&price) = prices.get(&position.symbol) else {
+                continue;
+            };
+            let queue = queues.entry(position.symbol.clone()).or_insert_with(|| AdlQueue {
+                symbol: position.symbol.clone(),
+                ..Default::default()
+            });
+            let Some((score, leverage)) = adl_score(position, price) else {
+                continue;
+            };
+            let side = if position.is_long { &mut queue.longs } else { &mut queue.shorts };
+            side.push(AdlEntry {
+                position: position.address,
+                owner: position.owner,
+                size: position.size.as_f64(),
+                unrealized_pnl: position.unrealized_pnl(price).as_f64(),
+                leverage,
+                score,
+                quantile: 0.0,
+            });
+        }
+
+        for queue in queues.values_mut() {
+            for side in [&mut queue.longs, &mut queue.shorts] {
+                side.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.position.cmp(&b.position)));
+                let len = side.len() as f64;
+                for (rank, entry) in side.iter_mut().enumerate() {
+                    entry.quantile = 1.0 - rank as f64 / len;
+                }
+            }
+        }
+        Self { queues }
+    }
+
+    /// Queue of a symbol, if any of its positions were priced
+    pub fn queue(&self, symbol: &str) -> Option<&AdlQueue> {
+        self.queues.get(symbol)
+    }
+
+    /// A position's ranking quantile in its side's queue, if it's profitable
+    pub fn quantile(&self, position: &Position) -> Option<f64> {
+        self.queue(&position.symbol)?
+            .side(position.is_long)
+            .iter()
+            .find(|entry| entry.position == position.address)
+            .map(|entry| entry.quantile)
+    }
+
+    /// Plan the counterparty reductions absorbing `bad_debt` of a bankrupt position
+    /// beyond what `insurance_fund` covers
+    ///
+    /// Counterparties are closed in queue order, each giving up as much of its
+    /// profit as is still needed. Returns `None` for symbols without a queue.
+    pub fn plan(&self, symbol: &str, bankrupt_is_long: bool, bad_debt: f64, insurance_fund: f64) -> Option<AdlPlan> {
+        let queue = self.queue(symbol)?;
+        let bad_debt = bad_debt.max(0.0);
+        let insurance_covered = bad_debt.min(insurance_fund.max(0.0));
+        let mut remaining = bad_debt - insurance_covered;
+
+        let mut reductions = Vec::new();
+        for entry in queue.side(!bankrupt_is_long) {
+            if remaining <= 0.0 {
+                break;
+            }
+            let pnl_absorbed = remaining.min(entry.unrealized_pnl);
+            reductions.push(AdlReduction {
+                position: entry.position,
+                size: entry.size * pnl_absorbed / entry.unrealized_pnl,
+                pnl_absorbed,
+            });
+            remaining -= pnl_absorbed;
+        }
+        Some(AdlPlan {
+            symbol: symbol.to_string(),
+            bankrupt_is_long,
+            bad_debt,
+            insurance_covered,
+            reductions,
+            uncovered: remaining.max(0.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_position(size: f64, entry_price: f64, margin: f64, is_long: bool) -> Position {
+        Position::new(
+            PositionAddress::new_unique(),
+            OwnerAddress::new_unique(),
+            "SOL/USD",
+            size,
+            entry_price,
+            margin,
+            is_long,
+        )
+    }
+
+    #[test]
+    fn test_ranking_and_plan() {
+        // At 110: 100 of profit on 100 of margin at 5.5x
+        let levered = create_position(10.0, 100.0, 100.0, true);
+        // 100 on 500 at 1.83x
+        let cautious = create_position(10.0, 100.0, 500.0, true);
+        // 60 on 10 at 1.57x
+        let early = create_position(1.0, 50.0, 10.0, true);
+        let losing_long = create_position(10.0, 120.0, 200.0, true);
+        // 100 on 100 at 2.75x
+        let short = create_position(5.0, 130.0, 100.0, false);
+        let losing_short = create_position(5.0, 100.0, 100.0, false);
+        let book = [&levered, &cautious, &early, &losing_long, &short, &losing_short].map(Clone::clone);
+        let planner = AdlPlanner::new(&book, &HashMap::from([("SOL/USD".to_string(), 110.0)]));
+
+        let queue = planner.queue("SOL/USD").unwrap();
+        let ranked: Vec<PositionAddress> = queue.longs.iter().map(|entry| entry.position).collect();
+        assert_eq!(ranked, [early.address, levered.address, cautious.address]);
+        assert!((queue.longs[0].score - 6.0 * 110.0 / 70.0).abs() < 1e-9);
+        assert!((queue.longs[1].score - 5.5).abs() < 1e-9);
+        assert!((queue.longs[2].score - 0.2 * 1100.0 / 600.0).abs() < 1e-9);
+        assert_eq!(queue.shorts.len(), 1);
+        assert!((queue.shorts[0].score - 2.75).abs() < 1e-9);
+
+        assert_eq!(planner.quantile(&early), Some(1.0));
+        assert!((planner.quantile(&levered).unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        assert!((planner.quantile(&cautious).unwrap() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(planner.quantile(&short), Some(1.0));
+        assert_eq!(planner.quantile(&losing_long), None);
+        assert!(planner.queue("BTC/USD").is_none());
+
+        // A bankrupt short's 200 of bad debt, 50 of it insured, takes all of the
+        // first long's profit and 90% of the second's
+        let plan = planner.plan("SOL/USD", false, 200.0, 50.0).unwrap();
+        assert_eq!(plan.insurance_covered, 50.0);
+        assert_eq!(plan.reductions.len(), 2);
+        let first = AdlReduction {
+            position: early.address,
+            size: 1.0,
+            pnl_absorbed: 60.0,
+        };
+        assert_eq!(plan.reductions[0], first);
+        assert_eq!(plan.reductions[1].position, levered.address);
+        assert!((plan.reductions[1].size - 9.0).abs() < 1e-9);
+        assert!((plan.reductions[1].pnl_absorbed - 90.0).abs() < 1e-9);
+        assert_eq!(plan.uncovered, 0.0);
+
+        // More than every long's profit leaves some uncovered
+        let plan = planner.plan("SOL/USD", false, 1000.0, 0.0).unwrap();
+        assert_eq!(plan.reductions.len(), 3);
+        assert!((plan.uncovered - 740.0).abs() < 1e-9);
+        // Insured bad debt needs no deleveraging
+        assert!(planner.plan("SOL/USD", true, 40.0, 50.0).unwrap().reductions.is_empty());
+    }
+}
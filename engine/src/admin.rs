@@ -0,0 +1,1519 @@
+//! HTTP admin API, enabled with the `admin` feature
+//!
+//! Lets operators inspect and adjust a running engine without redeploying:
+//!
+//! - `GET /positions` lists monitored positions, filtered by `symbol`, `owner` or `status`
+//! - `GET /positions/{pubkey}`, `POST /positions` and `DELETE /positions/{pubkey}`
+//!   inspect, inject and stop monitoring individual positions; injected positions may
+//!   carry `metadata`, e.g. `{"external_id": "pos-8812"}`, echoed in their updates and
+//!   events
+//! - `POST /positions?version=N` instead writes the position at a version, replacing
+//!   one already monitored unless it holds a newer version (409 Conflict). Requests
+//!   with an `Idempotency-Key` header are applied once, their retries answering with
+//!   the position as it is
+//! - `GET /positions/{pubkey}/health` returns a position's risk metrics at the latest
+//!   prices, including its health factor (liquidatable below 1.0)
+//! - `GET /adl?symbol=BTC/USD` returns a symbol's auto-deleveraging queue as ranked at
+//!   the last check cycle, and `GET /adl/plan?symbol=BTC/USD&bad_debt=1000&bankrupt_is_long=true`
+//!   the counterparties that would be deleveraged to absorb bad debt the insurance
+//!   fund can't
+//! - `GET /config` returns the active configuration and `PATCH /config` stages
+//!   changes to the hot-tunable fields for the next check cycle
+//! - `POST /liquidate/{pubkey}` checks one position immediately
+//! - `GET /audit/{pubkey}` returns the [`AuditRecord`]s of every liquidation attempt
+//!   on a position, oldest first, with the oracle reading, margin parameters and
+//!   configuration hash each was decided on (400 unless `audit_log_path` is set)
+//! - `GET /confirmations` counts submitted liquidations by outcome (confirmed,
+//!   failed on chain, expired or still pending after the timeout) and reports
+//!   their confirmation latency
+//! - `GET /markets` lists the monitored markets, `PUT /markets` adds a market or
+//!   replaces the one in its symbol, and `DELETE /markets?symbol=BTC/USD` removes
+//!   one, each taking effect at the next check cycle
+//! - `GET /verification` counts the liquidations whose position was read from chain
+//!   before submission and those called off because the chain didn't back them,
+//!   with the health factors of the latest divergence
+//! - `GET /dedup` counts the liquidation events dropped because the submission
+//!   path or the program's logs had already reported them (`deduped_events`)
+//! - `GET /pnl` returns the liquidator's rewards net of network fees, in total, per
+//!   symbol, per day and per liquidator key, with dry runs' estimates counted as
+//!   simulated
+//! - `GET /keys` lists the liquidator keys with their last read balances, whether
+//!   they're below a balance floor, and their use; `PUT /keys/{pubkey}` with
+//!   `{"enabled": false}` stops one from signing liquidations, and `true` lets it again
+//! - `GET /stats` returns the book's margin ratio histogram, open notional per symbol
+//!   and side, positions per status, the positions closest to liquidation and the bad
+//!   debt of ±5/10/20% price shocks, recomputed at most every `stats.cache_secs`,
+//!   with the positions, buffers and queues held in memory and what was evicted
+//!   or dropped to bound them
+//! - `GET /throttle` reports the last check cycle's liquidation caps and whether the
+//!   circuit breaker has paused liquidation, and `POST /resume` resumes it
+//! - `GET /quarantine` lists the positions left out of check cycles after their
+//!   check panicked or their values couldn't be evaluated, and counts the panics;
+//!   `DELETE /quarantine/{pubkey}` lets one back in and `DELETE /quarantine` all
+//! - `GET /suspects` lists the positions monitored despite breaking the ingest rules
+//!   (see `ingest_policy`), which `GET /positions` marks `"suspect": true` and which
+//!   aren't liquidated until `DELETE /suspects/{pubkey}` clears them; it also counts
+//!   the positions refused and flagged
+//! - `GET /mode` reports whether the engine is running, paused or monitor-only, and
+//!   `PUT /mode` switches it, e.g. `{"mode": "monitor_only", "actor": "alice"}`
+//! - `GET /slot` reports the cluster's slot as last seen, when it last advanced and
+//!   whether it stalled for longer than `max_slot_silence_secs`, which holds
+//!   liquidations back
+//! - `GET /health/live` answers while the engine's process is responsive, and
+//!   `GET /health/ready` answers 200 once it has warmed up and liquidates, or 503
+//!   with the markets, prices and cycles it still waits for
+//! - `GET /snapshot` exports the monitored positions as a [`PositionSnapshot`] and
+//!   `PUT /snapshot` replaces them with one, or adds its positions with `?merge=true`
+//! - `GET /ws` upgrades to a WebSocket streaming position updates and liquidation events
+//!
+//! All responses are JSON. Errors are returned as `{"error": "..."}`.
+//!
+//! WebSocket clients start by sending a [`Subscription`], e.g.
+//! `{"symbols": ["BTC/USD"], "owners": []}` (empty lists match everything). The server
+//! acknowledges with `{"type": "subscribed", "data": ...}` and then pushes matching
+//! [`EngineEvent`]s; sending another subscription replaces the filter. Clients that
+//! fall behind are disconnected rather than slowing the engine down.
+
+use crate::{
+    address::{OwnerAddress, PositionAddress},
+    adl::{AdlPlan, AdlQueue},
+    amount::{Amount, AmountExt},
+    audit::AuditRecord,
+    confirm::ConfirmationStats,
+    dedup::DedupStats,
+    error::LiquidationError,
+    ingest::{IngestStats, SuspectPosition},
+    key_pool::KeyStatus,
+    liquidation::LiquidationEngine,
+    market::MarketConfig,
+    position::Position,
+    quarantine::{QuarantineStats, QuarantinedPosition},
+    rewards::PnlSummary,
+    slot::SlotStatus,
+    snapshot::PositionSnapshot,
+    stats::EngineStats,
+    types::{
+        ConfigUpdate, EngineEvent, EngineMode, LiquidationConfig, LiquidationResult, ModeStatus, PositionStatus,
+        PositionUpdate, ThrottleStats, VerificationStats, WarmUpStatus,
+    },
+    versions::{PositionWrite, WriteOutcome},
+};
+use axum::{
+    Json, Router,
+    extract::{
+        DefaultBodyLimit, Path, Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+};
+use futures::{SinkExt, StreamExt};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{info, warn};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+/// How long a WebSocket send may take before the client is considered too slow
+const WS_SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest snapshot `PUT /snapshot` accepts, well above the default body limit
+/// so whole books fit
+const MAX_SNAPSHOT_BYTES: usize = 256 * 1024 * 1024;
+
+/// Header carrying the idempotency key of a `POST /positions`
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Error returned by an admin endpoint
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<LiquidationError> for ApiError {
+    fn from(err: LiquidationError) -> Self {
+        let status = match err {
+            LiquidationError::PositionNotFound(_) => StatusCode::NOT_FOUND,
+            LiquidationError::ConfigError(_) | LiquidationError::Ingest(_) => StatusCode::BAD_REQUEST,
+            LiquidationError::StaleUpdate { .. } => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, err.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(serde_json::json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Filters accepted by `GET /positions`
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct PositionFilter {
+    /// Only positions in this symbol
+    pub symbol: Option<String>,
+    /// Only positions held by this owner
+    #[serde(default)]
+    pub owner: Option<OwnerAddress>,
+    /// Only positions with this status
+    pub status: Option<PositionStatus>,
+}
+
+/// A position as listed by `GET /positions`
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ListedPosition {
+    /// The position
+    #[serde(flatten)]
+    pub position: Position,
+    /// Whether the position broke the ingest rules and isn't liquidated until
+    /// cleared through `DELETE /suspects/{pubkey}`
+    pub suspect: bool,
+}
+
+/// Query of `POST /positions`
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct PositionWriteQuery {
+    /// Version of the position written, e.g. the slot it was read at
+    pub version: Option<u64>,
+}
+
+/// Query of `GET /adl`
+#[derive(Debug, serde::Deserialize)]
+pub struct AdlQuery {
+    /// Symbol whose queue to return
+    pub symbol: String,
+}
+
+/// Query of `DELETE /markets`
+#[derive(Debug, serde::Deserialize)]
+pub struct MarketQuery {
+    /// Symbol of the market to remove
+    pub symbol: String,
+}
+
+/// Query of `GET /adl/plan`
+#[derive(Debug, serde::Deserialize)]
+pub struct AdlPlanQuery {
+    /// Symbol of the bankrupt position
+    pub symbol: String,
+    /// Bad debt to absorb (in quote currency)
+    pub bad_debt: f64,
+    /// Whether the bankrupt position is long, so shorts are deleveraged
+    pub bankrupt_is_long: bool,
+}
+
+/// Body of `PUT /mode`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ModeChange {
+    pub mode: EngineMode,
+    /// Operator making the change, logged with it
+    #[serde(default)]
+    pub actor: Option<String>,
+}
+
+/// Body of `PUT /keys/{pubkey}`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct KeyChange {
+    /// Whether the key signs liquidations
+    pub enabled: bool,
+}
+
+/// Options of `PUT /snapshot`
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct RestoreOptions {
+    /// Add the snapshot's positions to the monitored ones instead of replacing them
+    #[serde(default)]
+    pub merge: bool,
+}
+
+/// Response of `PUT /snapshot`
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Restored {
+    /// Positions the snapshot held
+    pub imported: usize,
+    /// Positions monitored afterwards
+    pub monitored: usize,
+}
+
+/// Filter sent by WebSocket clients to choose which events they receive
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Subscription {
+    /// Only events for these symbols (all symbols if empty)
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    /// Only events for positions held by these owners (all owners if empty)
+    #[serde(default)]
+    pub owners: Vec<OwnerAddress>,
+}
+
+impl Subscription {
+    /// Whether an event passes the filter
+    pub fn matches(&self, event: &EngineEvent) -> bool {
+        (self.symbols.is_empty() || self.symbols.iter().any(|symbol| symbol == event.symbol()))
+            && (self.owners.is_empty() || self.owners.contains(&event.owner()))
+    }
+}
+
+/// Build the admin API router for an engine
+pub fn router(engine: Arc<LiquidationEngine>) -> Router {
+    Router::new()
+        .route("/positions", get(list_positions).post(add_position))
+        .route("/positions/{pubkey}", get(get_position).delete(remove_position))
+        .route("/positions/{pubkey}/health", get(get_position_health))
+        .route("/adl", get(get_adl_queue))
+        .route("/adl/plan", get(plan_adl))
+        .route("/config", get(get_config).patch(update_config))
+        .route("/liquidate/{pubkey}", post(liquidate))
+        .route("/audit/{pubkey}", get(get_audit_records))
+        .route("/confirmations", get(get_confirmations))
+        .route("/markets", get(list_markets).put(upsert_market).delete(remove_market))
+        .route("/verification", get(get_verification))
+        .route("/dedup", get(get_dedup))
+        .route("/pnl", get(get_pnl))
+        .route("/keys", get(list_keys))
+        .route("/keys/{pubkey}", put(set_key_enabled))
+        .route("/stats", get(get_stats))
+        .route("/throttle", get(get_throttle))
+        .route("/resume", post(resume))
+        .route("/quarantine", get(get_quarantine).delete(clear_quarantine))
+        .route("/quarantine/{pubkey}", delete(release_quarantined))
+        .route("/suspects", get(get_suspects))
+        .route("/suspects/{pubkey}", delete(clear_suspect))
+        .route("/mode", get(get_mode).put(set_mode))
+        .route("/health/live", get(get_liveness))
+        .route("/health/ready", get(get_readiness))
+        .route("/slot", get(get_slot))
+        .route(
+            "/snapshot",
+            get(get_snapshot)
+                .put(restore_snapshot)
+                .layer(DefaultBodyLimit::max(MAX_SNAPSHOT_BYTES)),
+        )
+        .route("/ws", get(subscribe))
+        .with_state(engine)
+}
+
+/// Serve the admin API on `addr` until the process exits
+pub async fn serve(engine: Arc<LiquidationEngine>, addr: SocketAddr) -> Result<(), LiquidationError> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Admin API listening on {}", listener.local_addr()?);
+    axum::serve(listener, router(engine)).await?;
+    Ok(())
+}
+
+async fn list_positions(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Query(filter): Query<PositionFilter>,
+) -> Json<Vec<ListedPosition>> {
+    let mut positions = Vec::new();
+    for position in engine.get_positions().await {
+        if filter.symbol.as_ref().is_some_and(|symbol| *symbol != position.symbol)
+            || filter.owner.is_some_and(|owner| owner != position.owner)
+        {
+            continue;
+        }
+        if let Some(status) = filter.status
+            && engine.position_status(&position).await != status
+        {
+            continue;
+        }
+        let suspect = engine.is_suspect(&position.address);
+        positions.push(ListedPosition { position, suspect });
+    }
+    positions.sort_by_key(|listed| listed.position.address);
+    Json(positions)
+}
+
+async fn get_position(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Path(pubkey): Path<String>,
+) -> ApiResult<Json<Position>> {
+    let address: PositionAddress = pubkey.parse()?;
+    engine
+        .get_position(&address)
+        .await
+        .map(Json)
+        .ok_or_else(|| LiquidationError::PositionNotFound(address).into())
+}
+
+async fn get_position_health(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Path(pubkey): Path<String>,
+) -> ApiResult<Json<PositionUpdate>> {
+    let address: PositionAddress = pubkey.parse()?;
+    Ok(Json(engine.position_update(&address).await?))
+}
+
+async fn add_position(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Query(query): Query<PositionWriteQuery>,
+    headers: HeaderMap,
+    Json(position): Json<Position>,
+) -> ApiResult<(StatusCode, Json<Position>)> {
+    if !(position.size > Amount::ZERO && position.entry_price > Amount::ZERO && position.margin >= Amount::ZERO) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "size and entry_price must be positive and margin non-negative",
+        ));
+    }
+    if let Some(problem) = position.metadata_problem() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, problem));
+    }
+    let idempotency_key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|key| key.to_str().map(str::to_string))
+        .transpose()
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Idempotency-Key must be visible ASCII"))?;
+    let write = PositionWrite {
+        version: query.version,
+        idempotency_key,
+    };
+    if write != PositionWrite::default() {
+        return write_position(&engine, position, &write).await;
+    }
+    if engine.get_position(&position.address).await.is_some() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!("Position {} is already monitored", position.address),
+        ));
+    }
+
+    engine.add_position(position.clone()).await.map_err(LiquidationError::from)?;
+    info!("Monitoring position {} added through the admin API", position.address);
+    Ok((StatusCode::CREATED, Json(position)))
+}
+
+/// Write a position at a version or under an idempotency key, answering
+/// with the position as monitored afterwards
+async fn write_position(
+    engine: &LiquidationEngine,
+    position: Position,
+    write: &PositionWrite,
+) -> ApiResult<(StatusCode, Json<Position>)> {
+    let address = position.address;
+    let monitored = engine.get_position(&address).await.is_some();
+    let status = match engine.write_position(position.clone(), write).await? {
+        WriteOutcome::Applied => {
+            info!("Position {} written through the admin API at version {:?}", address, write.version);
+            if monitored {
+                StatusCode::OK
+            } else {
+                StatusCode::CREATED
+            }
+        }
+        WriteOutcome::Duplicate => StatusCode::OK,
+    };
+    Ok((status, Json(engine.get_position(&address).await.unwrap_or(position))))
+}
+
+async fn remove_position(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Path(pubkey): Path<String>,
+) -> ApiResult<Json<Position>> {
+    let address: PositionAddress = pubkey.parse()?;
+    let position = engine
+        .remove_position(&address)
+        .await
+        .ok_or(LiquidationError::PositionNotFound(address))?;
+    info!("Stopped monitoring position {} through the admin API", address);
+    Ok(Json(position))
+}
+
+async fn get_adl_queue(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Query(query): Query<AdlQuery>,
+) -> ApiResult<Json<AdlQueue>> {
+    engine
+        .get_adl_queue(&query.symbol)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("No ADL queue for {}", query.symbol)))
+}
+
+async fn plan_adl(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Query(query): Query<AdlPlanQuery>,
+) -> ApiResult<Json<AdlPlan>> {
+    if !(query.bad_debt.is_finite() && query.bad_debt >= 0.0) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "bad_debt must not be negative"));
+    }
+    engine
+        .plan_adl(&query.symbol, query.bankrupt_is_long, query.bad_debt)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("No ADL queue for {}", query.symbol)))
+}
+
+async fn get_config(State(engine): State<Arc<LiquidationEngine>>) -> Json<LiquidationConfig> {
+    Json((*engine.config()).clone())
+}
+
+async fn update_config(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Json(update): Json<ConfigUpdate>,
+) -> ApiResult<Json<LiquidationConfig>> {
+    let config = engine.update_config(&update)?;
+    info!("Configuration update staged through the admin API: {:?}", update);
+    Ok(Json(config))
+}
+
+async fn list_markets(State(engine): State<Arc<LiquidationEngine>>) -> Json<Vec<MarketConfig>> {
+    Json(engine.markets())
+}
+
+async fn upsert_market(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Json(market): Json<MarketConfig>,
+) -> ApiResult<Json<LiquidationConfig>> {
+    let symbol = market.symbol.clone();
+    let config = engine.upsert_market(market)?;
+    info!("Market {} staged through the admin API", symbol);
+    Ok(Json(config))
+}
+
+async fn remove_market(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Query(query): Query<MarketQuery>,
+) -> ApiResult<Json<MarketConfig>> {
+    let market = engine
+        .remove_market(&query.symbol)?
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("No market for {}", query.symbol)))?;
+    info!("Market {} removal staged through the admin API", query.symbol);
+    Ok(Json(market))
+}
+
+async fn liquidate(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Path(pubkey): Path<String>,
+) -> ApiResult<Json<Option<LiquidationResult>>> {
+    let address: PositionAddress = pubkey.parse()?;
+    Ok(Json(engine.check_position_now(&address).await?))
+}
+
+async fn get_audit_records(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Path(pubkey): Path<String>,
+) -> ApiResult<Json<Vec<AuditRecord>>> {
+    let address: PositionAddress = pubkey.parse()?;
+    Ok(Json(engine.audit_records(&address).await?))
+}
+
+async fn get_confirmations(State(engine): State<Arc<LiquidationEngine>>) -> Json<ConfirmationStats> {
+    Json(engine.confirmation_stats())
+}
+
+async fn get_verification(State(engine): State<Arc<LiquidationEngine>>) -> Json<VerificationStats> {
+    Json(engine.verification_stats())
+}
+
+async fn get_dedup(State(engine): State<Arc<LiquidationEngine>>) -> Json<DedupStats> {
+    Json(engine.dedup_stats())
+}
+
+async fn get_pnl(State(engine): State<Arc<LiquidationEngine>>) -> Json<PnlSummary> {
+    Json(engine.get_pnl_summary())
+}
+
+async fn list_keys(State(engine): State<Arc<LiquidationEngine>>) -> Json<Vec<KeyStatus>> {
+    Json(engine.key_status())
+}
+
+async fn set_key_enabled(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Path(pubkey): Path<String>,
+    Json(change): Json<KeyChange>,
+) -> ApiResult<Json<KeyStatus>> {
+    let key: Pubkey = pubkey
+        .parse()
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid key {:?}: {}", pubkey, e)))?;
+    let status = engine
+        .set_key_enabled(&key, change.enabled)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("{} isn't a liquidator key", key)))?;
+    info!(
+        "{} liquidator key {} through the admin API",
+        if change.enabled { "Enabled" } else { "Disabled" },
+        key
+    );
+    Ok(Json(status))
+}
+
+async fn get_stats(State(engine): State<Arc<LiquidationEngine>>) -> ApiResult<Json<EngineStats>> {
+    Ok(Json(engine.get_stats().await?))
+}
+
+async fn get_throttle(State(engine): State<Arc<LiquidationEngine>>) -> Json<ThrottleStats> {
+    Json(engine.throttle_stats())
+}
+
+async fn resume(State(engine): State<Arc<LiquidationEngine>>) -> Json<ThrottleStats> {
+    if engine.resume() {
+        info!("Liquidation resumed through the admin API");
+    }
+    Json(engine.throttle_stats())
+}
+
+async fn get_quarantine(State(engine): State<Arc<LiquidationEngine>>) -> Json<QuarantineStats> {
+    Json(engine.quarantine_stats())
+}
+
+async fn release_quarantined(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Path(pubkey): Path<String>,
+) -> ApiResult<Json<QuarantinedPosition>> {
+    let address: PositionAddress = pubkey.parse()?;
+    let released = engine
+        .release_quarantined(&address)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Position {} isn't quarantined", address)))?;
+    info!("Released position {} from quarantine through the admin API", address);
+    Ok(Json(released))
+}
+
+async fn clear_quarantine(State(engine): State<Arc<LiquidationEngine>>) -> Json<QuarantineStats> {
+    let released = engine.clear_quarantine();
+    info!("Released {} positions from quarantine through the admin API", released);
+    Json(engine.quarantine_stats())
+}
+
+async fn get_suspects(State(engine): State<Arc<LiquidationEngine>>) -> Json<IngestStats> {
+    Json(engine.ingest_stats())
+}
+
+async fn clear_suspect(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Path(pubkey): Path<String>,
+) -> ApiResult<Json<SuspectPosition>> {
+    let address: PositionAddress = pubkey.parse()?;
+    let cleared = engine
+        .clear_suspect(&address)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Position {} isn't suspect", address)))?;
+    info!("Cleared suspect position {} through the admin API", address);
+    Ok(Json(cleared))
+}
+
+async fn get_liveness(State(engine): State<Arc<LiquidationEngine>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "alive", "mode": engine.mode() }))
+}
+
+async fn get_readiness(State(engine): State<Arc<LiquidationEngine>>) -> (StatusCode, Json<WarmUpStatus>) {
+    let status = engine.warm_up_status();
+    let code = if status.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(status))
+}
+
+async fn get_slot(State(engine): State<Arc<LiquidationEngine>>) -> Json<SlotStatus> {
+    Json(engine.slot_status())
+}
+
+async fn get_mode(State(engine): State<Arc<LiquidationEngine>>) -> Json<ModeStatus> {
+    Json(engine.mode_status())
+}
+
+async fn set_mode(State(engine): State<Arc<LiquidationEngine>>, Json(change): Json<ModeChange>) -> Json<ModeStatus> {
+    let source = match &change.actor {
+        Some(actor) => format!("admin API ({})", actor),
+        None => "admin API".to_string(),
+    };
+    engine.set_mode(change.mode, &source);
+    Json(engine.mode_status())
+}
+
+async fn get_snapshot(State(engine): State<Arc<LiquidationEngine>>) -> Json<PositionSnapshot> {
+    Json(engine.snapshot_positions().await)
+}
+
+async fn restore_snapshot(
+    State(engine): State<Arc<LiquidationEngine>>,
+    Query(options): Query<RestoreOptions>,
+    Json(snapshot): Json<PositionSnapshot>,
+) -> ApiResult<Json<Restored>> {
+    let imported = engine.restore_positions(snapshot, options.merge).await?;
+    info!(
+        "Restored {} positions from a snapshot through the admin API, merge: {}",
+        imported, options.merge
+    );
+    Ok(Json(Restored {
+        imported,
+        monitored: engine.get_positions().await.len(),
+    }))
+}
+
+async fn subscribe(State(engine): State<Arc<LiquidationEngine>>, ws: WebSocketUpgrade) -> Response {
+    let events = engine.subscribe();
+    ws.on_upgrade(move |socket| stream_events(socket, events))
+}
+
+/// Send one JSON message, failing if the client doesn't take it in time
+async fn send_json(
+    sender: &mut futures::stream::SplitSink<WebSocket, Message>,
+    value: &impl serde::Serialize,
+) -> Result<(), String> {
+    let text = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    match tokio::time::timeout(WS_SEND_TIMEOUT, sender.send(Message::Text(text.into()))).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("send timed out".to_string()),
+    }
+}
+
+/// Forward engine events to a WebSocket client according to its subscription
+async fn stream_events(socket: WebSocket, mut events: tokio::sync::broadcast::Receiver<EngineEvent>) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut subscription: Option<Subscription> = None;
+
+    loop {
+        tokio::select! {
+            message = receiver.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = match serde_json::from_str::<Subscription>(&text) {
+                        Ok(update) => {
+                            let reply = serde_json::json!({ "type": "subscribed", "data": update });
+                            subscription = Some(update);
+                            reply
+                        }
+                        Err(e) => serde_json::json!({ "type": "error", "data": format!("Invalid subscription: {}", e) }),
+                    };
+                    if let Err(e) = send_json(&mut sender, &reply).await {
+                        warn!("Dropping WebSocket client: {}", e);
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if !subscription.as_ref().is_some_and(|subscription| subscription.matches(&event)) {
+                        continue;
+                    }
+                    if let Err(e) = send_json(&mut sender, &event).await {
+                        warn!("Dropping WebSocket client: {}", e);
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Dropping WebSocket client that fell {} events behind", missed);
+                    let _ = sender.send(Message::Close(None)).await;
+                    break;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::{IngestPolicy, IngestViolation};
+    use crate::oracle::MockOracle;
+    use crate::position::MAX_METADATA_KEYS;
+    use crate::rate_limit::RateLimiter;
+    use crate::stats::PRICE_SHOCKS;
+    use reqwest::StatusCode;
+    use serde_json::{Value, json};
+    use solana_client::rpc_client::RpcClient;
+    use solana_sdk::signature::{Keypair, Signer};
+    use std::collections::HashMap;
+    use std::future::IntoFuture;
+
+    /// Start an admin server for an engine pricing BTC at 50,000 and SOL at 100
+    async fn spawn_server() -> (Arc<LiquidationEngine>, String) {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        spawn_server_with(oracle).await
+    }
+
+    async fn spawn_server_with(oracle: MockOracle) -> (Arc<LiquidationEngine>, String) {
+        spawn_server_with_config(oracle, LiquidationConfig::default()).await
+    }
+
+    async fn spawn_server_with_config(oracle: MockOracle, config: LiquidationConfig) -> (Arc<LiquidationEngine>, String) {
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = Arc::new(LiquidationEngine::new(
+            rpc_client,
+            Arc::new(oracle),
+            config,
+            Arc::new(RateLimiter::default()),
+        ));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, router(engine.clone())).into_future());
+        (engine, base_url)
+    }
+
+    fn create_position(owner: OwnerAddress, margin: f64) -> Position {
+        Position::new(Pubkey::new_unique(), owner, "BTC/USD", 1.0, 52000.0, margin, true)
+    }
+
+    #[tokio::test]
+    async fn test_position_lifecycle() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        let position = create_position(OwnerAddress::new_unique(), 10000.0).with_metadata("external_id", "pos-8812");
+
+        let response = client
+            .post(format!("{}/positions", base_url))
+            .json(&json!({
+                "address": position.address.to_string(),
+                "owner": position.owner.to_string(),
+                "symbol": "BTC/USD",
+                "size": 1.0,
+                "entry_price": 52000.0,
+                "margin": 10000.0,
+                "is_long": true,
+                "metadata": {"external_id": "pos-8812"},
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(engine.get_position(&position.address).await, Some(position.clone()));
+
+        // Metadata beyond the limits is refused
+        let mut oversized = create_position(OwnerAddress::new_unique(), 10000.0);
+        oversized.metadata = (0..=MAX_METADATA_KEYS).map(|i| (format!("key{}", i), String::new())).collect();
+        let response = client.post(format!("{}/positions", base_url)).json(&oversized).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: Value = response.json().await.unwrap();
+        assert!(body["error"].as_str().unwrap().contains("keys, over the limit"));
+        assert!(engine.get_position(&oversized.address).await.is_none());
+
+        // Injecting the same position twice is a conflict
+        let response = client
+            .post(format!("{}/positions", base_url))
+            .json(&position)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        // Versioned writes replace it unless it holds a newer version, and
+        // retries of one are applied once
+        let write = |position: &Position, version: u64, key: &str| {
+            client
+                .post(format!("{}/positions?version={}", base_url, version))
+                .header(IDEMPOTENCY_KEY_HEADER, key)
+                .json(position)
+                .send()
+        };
+        let resized = Position { size: 2.0, ..position.clone() };
+        let response = write(&resized, 20, "req-1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.json::<Position>().await.unwrap().size, 2.0);
+        let response = write(&position, 10, "req-2").await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "Stale update: version 10 is older than applied version 20");
+        let response = write(&position, 20, "req-1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.json::<Position>().await.unwrap().size, 2.0);
+        assert_eq!(engine.get_position(&position.address).await.unwrap().size, 2.0);
+        engine.add_position(position.clone()).await.unwrap();
+
+        let url = format!("{}/positions/{}", base_url, position.address);
+        let fetched: Position = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(fetched, position);
+
+        let response = client.delete(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(engine.get_position(&position.address).await.is_none());
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: Value = response.json().await.unwrap();
+        assert!(body["error"].as_str().unwrap().contains("not monitored"));
+
+        let response = client.get(format!("{}/positions/not-a-key", base_url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_export_and_restore() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        let url = format!("{}/snapshot", base_url);
+        let exported = [
+            create_position(OwnerAddress::new_unique(), 10000.0),
+            create_position(OwnerAddress::new_unique(), 0.0),
+        ];
+        for position in &exported {
+            engine.add_position(position.clone()).await.unwrap();
+        }
+
+        let snapshot: PositionSnapshot = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(snapshot.positions.len(), 2);
+        assert!(snapshot.validate().is_ok());
+
+        let extra = create_position(OwnerAddress::new_unique(), 5000.0);
+        engine.add_position(extra.clone()).await.unwrap();
+        let restored: Restored = client
+            .put(format!("{}?merge=true", url))
+            .json(&snapshot)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(restored, Restored { imported: 2, monitored: 3 });
+
+        let restored: Restored = client.put(&url).json(&snapshot).send().await.unwrap().json().await.unwrap();
+        assert_eq!(restored, Restored { imported: 2, monitored: 2 });
+        assert!(engine.get_position(&extra.address).await.is_none());
+
+        // Snapshots of another version are refused
+        let mut newer = snapshot.clone();
+        newer.version += 1;
+        let response = client.put(&url).json(&newer).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: Value = response.json().await.unwrap();
+        assert!(body["error"].as_str().unwrap().contains("isn't supported"));
+        assert_eq!(engine.get_positions().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_position_health() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        // 8,000 and 1,000 of equity at 50,000 against 2,500 of maintenance margin
+        let healthy = create_position(OwnerAddress::new_unique(), 10000.0);
+        let at_risk = create_position(OwnerAddress::new_unique(), 3000.0);
+        engine.add_position(healthy.clone()).await.unwrap();
+        engine.add_position(at_risk.clone()).await.unwrap();
+
+        let health = |address: PositionAddress| {
+            let request = client.get(format!("{}/positions/{}/health", base_url, address));
+            async move { request.send().await.unwrap() }
+        };
+        let healthy: Value = health(healthy.address).await.json().await.unwrap();
+        assert!((healthy["health_factor"].as_f64().unwrap() - 3.2).abs() < 1e-12);
+        assert_eq!(healthy["status"], "active");
+        let at_risk: Value = health(at_risk.address).await.json().await.unwrap();
+        assert!((at_risk["health_factor"].as_f64().unwrap() - 0.4).abs() < 1e-12);
+        assert_eq!(at_risk["status"], "at_risk");
+
+        assert_eq!(health(PositionAddress::new_unique()).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_adl_queue_and_plan() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        // Shorts from 52,000 profit at 50,000: 2,000 on 10,000 and on 3,000 of margin
+        let mut cautious = create_position(OwnerAddress::new_unique(), 10000.0);
+        let mut levered = create_position(OwnerAddress::new_unique(), 3000.0);
+        for position in [&mut cautious, &mut levered] {
+            position.is_long = false;
+            engine.add_position(position.clone()).await.unwrap();
+        }
+        let get = |path: String| {
+            let request = client.get(format!("{}{}", base_url, path));
+            async move { request.send().await.unwrap() }
+        };
+        // Queues are ranked by check cycles
+        assert_eq!(get("/adl?symbol=BTC/USD".to_string()).await.status(), StatusCode::NOT_FOUND);
+        engine.check_positions().await.unwrap();
+
+        let queue: AdlQueue = get("/adl?symbol=BTC/USD".to_string()).await.json().await.unwrap();
+        let ranked: Vec<PositionAddress> = queue.shorts.iter().map(|entry| entry.position).collect();
+        assert_eq!(ranked, [levered.address, cautious.address]);
+
+        let plan: AdlPlan = get("/adl/plan?symbol=BTC/USD&bad_debt=1000&bankrupt_is_long=true".to_string())
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(plan.reductions.len(), 1);
+        assert_eq!(plan.reductions[0].position, levered.address);
+        assert_eq!(plan.reductions[0].pnl_absorbed, 1000.0);
+        let response = get("/adl/plan?symbol=BTC/USD&bad_debt=-1&bankrupt_is_long=true".to_string()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let (engine, base_url) = spawn_server().await;
+        engine.add_position(create_position(OwnerAddress::new_unique(), 3000.0)).await.unwrap();
+        engine.add_position(create_position(OwnerAddress::new_unique(), 10000.0)).await.unwrap();
+
+        let stats: Value = reqwest::get(format!("{}/stats", base_url)).await.unwrap().json().await.unwrap();
+        assert_eq!(stats["positions"], 2);
+        assert_eq!(stats["statuses"]["at_risk"], 1);
+        assert_eq!(stats["open_notional"]["BTC/USD"]["long"], 100000.0);
+        assert_eq!(stats["closest_to_liquidation"].as_array().unwrap().len(), 2);
+        assert_eq!(stats["price_shocks"].as_array().unwrap().len(), PRICE_SHOCKS.len());
+    }
+
+    #[tokio::test]
+    async fn test_mode_switch() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        let url = format!("{}/mode", base_url);
+        let status: ModeStatus = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!((status.mode, status.changes), (EngineMode::Running, 0));
+
+        let status: ModeStatus = client
+            .put(&url)
+            .json(&json!({"mode": "monitor_only", "actor": "alice"}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(status.mode, EngineMode::MonitorOnly);
+        assert_eq!((status.source.as_str(), status.changes), ("admin API (alice)", 1));
+        assert_eq!(engine.mode(), EngineMode::MonitorOnly);
+
+        let response = client.put(&url).json(&json!({"mode": "stopped"})).send().await.unwrap();
+        assert!(response.status().is_client_error());
+        assert_eq!(engine.mode(), EngineMode::MonitorOnly);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_follows_warm_up() {
+        let (engine, base_url) = spawn_server().await;
+        engine.add_position(create_position(OwnerAddress::new_unique(), 10000.0)).await.unwrap();
+        let live = reqwest::get(format!("{}/health/live", base_url)).await.unwrap();
+        assert_eq!(live.status(), StatusCode::OK);
+
+        // Not ready before starting, nor while warming up
+        let ready = || async { reqwest::get(format!("{}/health/ready", base_url)).await.unwrap() };
+        assert_eq!(ready().await.status(), StatusCode::SERVICE_UNAVAILABLE);
+        engine.begin_warm_up();
+        for _ in 0..LiquidationConfig::default().warmup_cycles {
+            engine.check_positions().await.unwrap();
+        }
+        let response = ready().await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let status: WarmUpStatus = response.json().await.unwrap();
+        assert_eq!((status.cycles_completed, status.cycles_required), (2, 2));
+
+        engine.check_positions().await.unwrap();
+        let response = ready().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.json::<WarmUpStatus>().await.unwrap().is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_throttle_and_resume() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let config = LiquidationConfig {
+            circuit_breaker_liquidation_count: Some(1),
+            ..Default::default()
+        };
+        let engine = Arc::new(LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default())));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, router(engine.clone())).into_future());
+        let client = reqwest::Client::new();
+        // Both liquidatable; the second liquidation trips the breaker
+        for _ in 0..2 {
+            engine.add_position(create_position(OwnerAddress::new_unique(), 3000.0)).await.unwrap();
+        }
+        engine.check_positions().await.unwrap();
+
+        let stats: ThrottleStats = client
+            .get(format!("{}/throttle", base_url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(stats.cycle_liquidations["BTC/USD"], 2);
+        assert!(stats.paused_since.is_some());
+
+        let stats: ThrottleStats = client
+            .post(format!("{}/resume", base_url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(stats.paused_since, None);
+        assert_eq!(engine.paused_since(), None);
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_released() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        // Positions that can't be evaluated are only monitored as suspects
+        let config = LiquidationConfig {
+            ingest_policy: IngestPolicy::Flag,
+            ..Default::default()
+        };
+        let (engine, base_url) = spawn_server_with_config(oracle, config).await;
+        let client = reqwest::Client::new();
+        let mut broken = Vec::new();
+        for _ in 0..2 {
+            let mut position = create_position(OwnerAddress::new_unique(), 10000.0);
+            position.entry_price = f64::NAN;
+            broken.push(position.address);
+            engine.add_position(position).await.unwrap();
+        }
+        engine.check_positions().await.unwrap();
+
+        let stats: QuarantineStats =
+            client.get(format!("{}/quarantine", base_url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(stats.positions.len(), 2);
+        assert_eq!(stats.positions[0].reason, "non-finite entry_price");
+
+        let url = format!("{}/quarantine/{}", base_url, broken[0]);
+        let released: QuarantinedPosition = client.delete(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(released.address, broken[0]);
+        assert_eq!(client.delete(&url).send().await.unwrap().status(), StatusCode::NOT_FOUND);
+        let stats: QuarantineStats =
+            client.delete(format!("{}/quarantine", base_url)).send().await.unwrap().json().await.unwrap();
+        assert!(stats.positions.is_empty());
+        assert_eq!(stats.total_quarantined, 2);
+    }
+
+    #[tokio::test]
+    async fn test_keys_listed_and_disabled() {
+        let keypairs = [Keypair::new(), Keypair::new()];
+        let pubkeys: Vec<Pubkey> = keypairs.iter().map(Keypair::pubkey).collect();
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = Arc::new(
+            LiquidationEngine::new(
+                rpc_client,
+                Arc::new(MockOracle::new()),
+                LiquidationConfig::default(),
+                Arc::new(RateLimiter::default()),
+            )
+            .with_payers(keypairs.into()),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, router(engine.clone())).into_future());
+        let client = reqwest::Client::new();
+
+        let keys: Vec<KeyStatus> = client.get(format!("{}/keys", base_url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(keys.iter().map(|key| key.pubkey).collect::<Vec<_>>(), pubkeys);
+        assert!(keys.iter().all(|key| key.enabled && key.liquidations == 0));
+
+        let url = format!("{}/keys/{}", base_url, pubkeys[1]);
+        let disabled: KeyStatus =
+            client.put(&url).json(&json!({"enabled": false})).send().await.unwrap().json().await.unwrap();
+        assert!(!disabled.enabled);
+        assert!(!engine.key_status()[1].enabled);
+        assert!(engine.key_status()[0].enabled);
+
+        let response = client
+            .put(format!("{}/keys/{}", base_url, Pubkey::new_unique()))
+            .json(&json!({"enabled": false}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = client
+            .put(format!("{}/keys/not-a-key", base_url))
+            .json(&json!({"enabled": false}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_excessive_leverage_refused_or_flagged() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        // A margin typo of 0.0001 on 52,000 of notional
+        let typo = create_position(OwnerAddress::new_unique(), 0.0001);
+        let response = client.post(format!("{}/positions", base_url)).json(&typo).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(
+            body["error"],
+            format!("Position {} rejected: implied leverage 520000000.00x at entry exceeds 1000x", typo.address)
+        );
+        assert!(engine.get_position(&typo.address).await.is_none());
+        assert_eq!(engine.ingest_stats().total_rejected, 1);
+
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        let config = LiquidationConfig {
+            ingest_policy: IngestPolicy::Flag,
+            ..Default::default()
+        };
+        let (engine, base_url) = spawn_server_with_config(oracle, config).await;
+        let response = client.post(format!("{}/positions", base_url)).json(&typo).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let sound = create_position(OwnerAddress::new_unique(), 10000.0);
+        engine.add_position(sound.clone()).await.unwrap();
+
+        let suspects = |positions: Vec<ListedPosition>| {
+            positions.into_iter().map(|listed| (listed.position.address, listed.suspect)).collect::<HashMap<_, _>>()
+        };
+        let listed: Vec<ListedPosition> =
+            client.get(format!("{}/positions", base_url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(suspects(listed), HashMap::from([(typo.address, true), (sound.address, false)]));
+        let stats: IngestStats = client.get(format!("{}/suspects", base_url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(stats.suspects.len(), 1);
+        assert!(matches!(
+            stats.suspects[0].violations[..],
+            [IngestViolation::ExcessiveLeverage { max_leverage, .. }] if max_leverage == 1000.0
+        ));
+        assert_eq!((stats.total_rejected, stats.total_flagged), (0, 1));
+
+        let url = format!("{}/suspects/{}", base_url, typo.address);
+        let cleared: SuspectPosition = client.delete(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(cleared.address, typo.address);
+        assert_eq!(client.delete(&url).send().await.unwrap().status(), StatusCode::NOT_FOUND);
+        let listed: Vec<ListedPosition> =
+            client.get(format!("{}/positions", base_url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(suspects(listed), HashMap::from([(typo.address, false), (sound.address, false)]));
+    }
+
+    #[tokio::test]
+    async fn test_list_positions_filters() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        let owner = OwnerAddress::new_unique();
+        let healthy = create_position(owner, 10000.0);
+        let at_risk = create_position(OwnerAddress::new_unique(), 3000.0);
+        let mut eth = create_position(owner, 10000.0);
+        eth.symbol = "ETH/USD".to_string();
+        for position in [&healthy, &at_risk, &eth] {
+            engine.add_position(position.clone()).await.unwrap();
+        }
+
+        let list = |query: String| {
+            let client = client.clone();
+            let base_url = base_url.clone();
+            async move {
+                let positions: Vec<Position> = client
+                    .get(format!("{}/positions{}", base_url, query))
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap();
+                let mut addresses: Vec<PositionAddress> = positions.iter().map(|position| position.address).collect();
+                addresses.sort();
+                addresses
+            }
+        };
+        let sorted = |mut addresses: Vec<PositionAddress>| {
+            addresses.sort();
+            addresses
+        };
+
+        assert_eq!(list(String::new()).await.len(), 3);
+        assert_eq!(list("?symbol=ETH/USD".to_string()).await, vec![eth.address]);
+        assert_eq!(
+            list(format!("?owner={}", owner)).await,
+            sorted(vec![healthy.address, eth.address])
+        );
+        assert_eq!(list("?status=at_risk".to_string()).await, vec![at_risk.address]);
+        // ETH can't be priced, so it's reported as active
+        assert_eq!(
+            list("?status=active".to_string()).await,
+            sorted(vec![healthy.address, eth.address])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_config_updates_are_validated_and_staged() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        let url = format!("{}/config", base_url);
+
+        let config: LiquidationConfig = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert!(config.dry_run);
+        assert_eq!(config.maintenance_margin, 0.05);
+
+        let response = client
+            .patch(&url)
+            .json(&json!({ "maintenance_margin": 0.1, "max_concurrent_liquidations": 4 }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let staged: LiquidationConfig = response.json().await.unwrap();
+        assert_eq!(staged.maintenance_margin, 0.1);
+        assert_eq!(staged.max_concurrent_liquidations, 4);
+
+        // Nothing changes until the next tick
+        assert_eq!(engine.config().maintenance_margin, 0.05);
+        engine.check_positions().await.unwrap();
+        let config: LiquidationConfig = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(config.maintenance_margin, 0.1);
+        assert_eq!(config.max_concurrent_liquidations, 4);
+
+        let response = client
+            .patch(&url)
+            .json(&json!({ "maintenance_margin": 2.0 }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Only the hot-tunable fields can be changed
+        let response = client
+            .patch(&url)
+            .json(&json!({ "check_interval_ms": 1 }))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_client_error());
+    }
+
+    #[tokio::test]
+    async fn test_markets_take_effect_next_cycle() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        let url = format!("{}/markets", base_url);
+        // 3,000 of equity on 50,000 of value clears the global 5% margin but not 10%
+        let position = create_position(OwnerAddress::new_unique(), 5000.0);
+        engine.add_position(position.clone()).await.unwrap();
+        let market = MarketConfig {
+            enabled: false,
+            ..MarketConfig::new(Pubkey::new_unique(), "BTC/USD", 0.1)
+        };
+
+        let response = client.put(&url).json(&market).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let markets: Vec<MarketConfig> = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(markets, std::slice::from_ref(&market));
+        assert!(engine.config().markets.is_empty());
+
+        // Disabled markets aren't liquidated, whatever their margin
+        assert!(engine.check_positions().await.unwrap().is_empty());
+        assert_eq!(engine.config().markets, std::slice::from_ref(&market));
+
+        let enabled = MarketConfig { enabled: true, ..market };
+        client.put(&url).json(&enabled).send().await.unwrap();
+        let results = engine.check_positions().await.unwrap();
+        assert!(matches!(results[..], [LiquidationResult::Success { position: address, .. }] if address == position.address));
+
+        let invalid = MarketConfig {
+            maintenance_margin: 2.0,
+            ..enabled.clone()
+        };
+        let response = client.put(&url).json(&invalid).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client.delete(format!("{}?symbol=BTC/USD", url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let removed: MarketConfig = response.json().await.unwrap();
+        assert_eq!(removed, enabled);
+        let response = client.delete(format!("{}?symbol=BTC/USD", url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_audit_records_of_a_position() {
+        use crate::audit::{AuditWriter, DEFAULT_AUDIT_QUEUE_CAPACITY};
+
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        let at_risk = create_position(OwnerAddress::new_unique(), 3000.0);
+        let url = format!("{}/audit/{}", base_url, at_risk.address);
+        // Without an audit log there's nothing to read
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        drop(engine);
+
+        let dir = tempfile::tempdir().unwrap();
+        let (writer, _) = AuditWriter::spawn(dir.path().join("audit.jsonl"), DEFAULT_AUDIT_QUEUE_CAPACITY);
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        let engine = Arc::new(
+            LiquidationEngine::new(
+                Arc::new(RpcClient::new("https://api.devnet.solana.com")),
+                Arc::new(oracle),
+                LiquidationConfig::default(),
+                Arc::new(RateLimiter::default()),
+            )
+            .with_audit(writer),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, router(engine.clone())).into_future());
+        engine.add_position(at_risk.clone()).await.unwrap();
+        engine.check_position_now(&at_risk.address).await.unwrap();
+
+        let url = format!("{}/audit/{}", base_url, at_risk.address);
+        let records: Vec<Value> = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["position"], at_risk.address.to_string());
+        assert_eq!(records[0]["oracle_price"], 50000.0);
+        assert_eq!(records[0]["config_hash"], engine.config().config_hash());
+        assert_eq!(records[0]["signature"], "dry-run");
+
+        let response = client.get(format!("{}/audit/not-a-pubkey", base_url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_force_liquidation() {
+        let (engine, base_url) = spawn_server().await;
+        let client = reqwest::Client::new();
+        let healthy = create_position(OwnerAddress::new_unique(), 10000.0);
+        let at_risk = create_position(OwnerAddress::new_unique(), 3000.0);
+        engine.add_position(healthy.clone()).await.unwrap();
+        engine.add_position(at_risk.clone()).await.unwrap();
+
+        let result: Value = client
+            .post(format!("{}/liquidate/{}", base_url, at_risk.address))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(result["result"], "success");
+        assert_eq!(result["position"], at_risk.address.to_string());
+        assert_eq!(result["signature"], "dry-run");
+
+        let result: Value = client
+            .post(format!("{}/liquidate/{}", base_url, healthy.address))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(result.is_null());
+
+        // The dry run's reward is counted as simulated
+        let pnl: Value = client.get(format!("{}/pnl", base_url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(pnl["total"]["liquidations"], 1);
+        assert_eq!(pnl["total"]["simulated"], 1);
+        assert_eq!(pnl["by_symbol"]["BTC/USD"]["liquidations"], 1);
+
+        // Dry runs submit nothing to confirm
+        let confirmations: ConfirmationStats = client
+            .get(format!("{}/confirmations", base_url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(confirmations, ConfirmationStats::default());
+
+        let response = client
+            .post(format!("{}/liquidate/{}", base_url, Pubkey::new_unique()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    type WsClient = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+    /// Next JSON message from the server, or `None` once it goes quiet
+    async fn next_message(client: &mut WsClient) -> Option<Value> {
+        let message = tokio::time::timeout(Duration::from_millis(500), client.next())
+            .await
+            .ok()??
+            .unwrap();
+        Some(serde_json::from_str(message.to_text().unwrap()).unwrap())
+    }
+
+    async fn connect(base_url: &str, subscription: Value) -> WsClient {
+        let url = format!("{}/ws", base_url.replace("http://", "ws://"));
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        client
+            .send(tokio_tungstenite::tungstenite::Message::Text(subscription.to_string().into()))
+            .await
+            .unwrap();
+        let ack = next_message(&mut client).await.unwrap();
+        assert_eq!(ack["type"], "subscribed");
+        client
+    }
+
+    /// Drain messages as (type, position) pairs
+    async fn received(client: &mut WsClient) -> Vec<(String, String)> {
+        let mut messages = Vec::new();
+        while let Some(message) = next_message(client).await {
+            let position = match message["type"].as_str().unwrap() {
+                "position_update" => &message["data"]["address"],
+                _ => &message["data"]["position"],
+            };
+            messages.push((
+                message["type"].as_str().unwrap().to_string(),
+                position.as_str().unwrap().to_string(),
+            ));
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn test_websocket_subscriptions_filter_events() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("ETH/USD", 3000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let (engine, base_url) = spawn_server_with(oracle).await;
+
+        let (owner, other_owner) = (OwnerAddress::new_unique(), OwnerAddress::new_unique());
+        let at_risk = create_position(owner, 3000.0);
+        let healthy = create_position(other_owner, 10000.0);
+        let eth = Position::new(Pubkey::new_unique(), other_owner, "ETH/USD", 1.0, 3000.0, 600.0, true);
+        for position in [&at_risk, &healthy, &eth] {
+            engine.add_position(position.clone()).await.unwrap();
+        }
+
+        let mut eth_client = connect(&base_url, json!({ "symbols": ["ETH/USD"] })).await;
+        let mut owner_client = connect(&base_url, json!({ "owners": [owner.to_string()] })).await;
+        engine.check_positions().await.unwrap();
+
+        assert_eq!(
+            received(&mut eth_client).await,
+            vec![("position_update".to_string(), eth.address.to_string())]
+        );
+        assert_eq!(
+            received(&mut owner_client).await,
+            vec![
+                ("position_update".to_string(), at_risk.address.to_string()),
+                ("liquidation".to_string(), at_risk.address.to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_websocket_rejects_invalid_subscription() {
+        let (_engine, base_url) = spawn_server().await;
+        let url = format!("{}/ws", base_url.replace("http://", "ws://"));
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        client
+            .send(tokio_tungstenite::tungstenite::Message::Text(r#"{"owners": ["nope"]}"#.into()))
+            .await
+            .unwrap();
+
+        let reply = next_message(&mut client).await.unwrap();
+        assert_eq!(reply["type"], "error");
+    }
+}
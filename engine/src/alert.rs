@@ -0,0 +1,687 @@
+//! Human-readable alerts to the operators running the engine
+//!
+//! Where webhooks tell position owners about their own positions, alerts tell
+//! the risk team about the engine: a tripped circuit breaker, bad debt, an
+//! oracle price rejected as an outlier, a liquidator running low on funds, a
+//! run of failed liquidations, or the engine warming up or being paused.
+//!
+//! Alerts are queued and handed to a [`Notifier`] by a dedicated task, so a
+//! slow or failing destination never holds up the engine. Each trigger sends at
+//! most one message per window; alerts raised while it's closed are counted,
+//! and once the window ends the latest of them is sent with that count appended.
+
+use crate::error::{ConfigViolation, LiquidationError, first_violation};
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Default number of alerts waiting for delivery before the oldest is dropped
+pub(crate) const DEFAULT_ALERT_QUEUE_CAPACITY: usize = 64;
+
+/// Time allowed for one alert to be delivered
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How urgently an alert needs a human
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertLevel {
+    /// Worth knowing, e.g. the engine finished warming up
+    Info,
+    /// Needs a look soon, e.g. an oracle price was rejected
+    Warning,
+    /// Needs a look now, e.g. liquidation stopped or bad debt was taken on
+    Critical,
+}
+
+impl fmt::Display for AlertLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Info => write!(f, "info"),
+            Self::Warning => write!(f, "warning"),
+            Self::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// What an alert is raised for, each rate-limited on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertTrigger {
+    /// The circuit breaker tripped and paused liquidation
+    CircuitBreaker,
+    /// A position was found beyond its bankruptcy price
+    BadDebt,
+    /// An oracle price was rejected for jumping away from recent prices
+    OracleDivergence,
+    /// The liquidator's balance to repay with fell below its floor
+    LowBalance,
+    /// `consecutive_failures` liquidations in a row failed
+    ConsecutiveFailures,
+    /// The engine started or finished warming up
+    WarmUp,
+    /// The engine was paused or unpaused
+    Paused,
+}
+
+impl AlertTrigger {
+    /// Every trigger
+    pub const ALL: [AlertTrigger; 7] = [
+        Self::CircuitBreaker,
+        Self::BadDebt,
+        Self::OracleDivergence,
+        Self::LowBalance,
+        Self::ConsecutiveFailures,
+        Self::WarmUp,
+        Self::Paused,
+    ];
+}
+
+impl fmt::Display for AlertTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CircuitBreaker => write!(f, "circuit_breaker"),
+            Self::BadDebt => write!(f, "bad_debt"),
+            Self::OracleDivergence => write!(f, "oracle_divergence"),
+            Self::LowBalance => write!(f, "low_balance"),
+            Self::ConsecutiveFailures => write!(f, "consecutive_failures"),
+            Self::WarmUp => write!(f, "warm_up"),
+            Self::Paused => write!(f, "paused"),
+        }
+    }
+}
+
+/// Where alerts are sent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifierKind {
+    /// Nowhere
+    #[default]
+    Off,
+    /// The engine's own log
+    Log,
+    /// A Slack incoming webhook
+    Slack,
+}
+
+/// Settings for alerting operators
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AlertConfig {
+    /// Where alerts are sent
+    pub notifier: NotifierKind,
+    /// Incoming webhook URL of the Slack channel alerts are posted to
+    pub slack_webhook_url: Option<String>,
+    /// Triggers alerts are raised for
+    pub triggers: Vec<AlertTrigger>,
+    /// Shortest time between two messages for the same trigger (in seconds)
+    pub window_secs: u64,
+    /// Liquidations failing in a row before it's alerted on
+    pub consecutive_failures: u32,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            notifier: NotifierKind::Off,
+            slack_webhook_url: None,
+            triggers: AlertTrigger::ALL.to_vec(),
+            window_secs: 300,
+            consecutive_failures: 3,
+        }
+    }
+}
+
+impl AlertConfig {
+    /// Check that the alert settings are usable
+    pub fn validate(&self) -> Result<(), LiquidationError> {
+        first_violation(self.violations())
+    }
+
+    /// Every problem with the settings
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        match (self.notifier, self.slack_webhook_url.as_deref()) {
+            (NotifierKind::Slack, None) => {
+                violations.push(ConfigViolation::new("slack_webhook_url", "is required by the slack notifier"));
+            }
+            (_, Some("")) => violations.push(ConfigViolation::new("slack_webhook_url", "must not be empty")),
+            _ => {}
+        }
+        if self.window_secs == 0 {
+            violations.push(ConfigViolation::new("window_secs", "must be positive"));
+        }
+        if self.consecutive_failures == 0 {
+            violations.push(ConfigViolation::new("consecutive_failures", "must be at least 1"));
+        }
+        violations
+    }
+
+    /// The configured notifier, or `None` when alerts are off
+    pub fn notifier(&self) -> Option<Arc<dyn Notifier>> {
+        match self.notifier {
+            NotifierKind::Off => None,
+            NotifierKind::Log => Some(Arc::new(LogNotifier)),
+            NotifierKind::Slack => self
+                .slack_webhook_url
+                .as_ref()
+                .map(|url| Arc::new(SlackNotifier::new(url.clone())) as Arc<dyn Notifier>),
+        }
+    }
+
+    /// Shortest time between two messages for the same trigger
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+}
+
+/// Delivers alerts to wherever operators read them
+#[async_trait]
+pub trait Notifier: Send + Sync + fmt::Debug {
+    /// Send one alert, returning once it's been accepted
+    async fn notify(&self, level: AlertLevel, title: &str, body: &str) -> Result<(), LiquidationError>;
+}
+
+/// Writes alerts to the engine's log at their level
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, level: AlertLevel, title: &str, body: &str) -> Result<(), LiquidationError> {
+        match level {
+            AlertLevel::Info => info!(alert = title, "{}", body),
+            AlertLevel::Warning => warn!(alert = title, "{}", body),
+            AlertLevel::Critical => error!(alert = title, "{}", body),
+        }
+        Ok(())
+    }
+}
+
+/// Posts alerts to a Slack channel through an incoming webhook
+#[derive(Clone)]
+pub(crate) struct SlackNotifier {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl SlackNotifier {
+    /// Notifier posting to the incoming webhook at `webhook_url`
+    pub fn new(webhook_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            webhook_url: webhook_url.into(),
+        }
+    }
+
+    /// Message posted for an alert: a bold headline tagged with its level above
+    /// the body, with `text` doubling as the plain fallback shown in
+    /// notifications
+    pub fn payload(level: AlertLevel, title: &str, body: &str) -> serde_json::Value {
+        let emoji = match level {
+            AlertLevel::Info => ":information_source:",
+            AlertLevel::Warning => ":warning:",
+            AlertLevel::Critical => ":rotating_light:",
+        };
+        let headline = format!("{} [{}] {}", emoji, level.to_string().to_uppercase(), escape(title));
+        serde_json::json!({
+            "text": format!("{}\n{}", headline, escape(body)),
+            "blocks": [
+                {"type": "section", "text": {"type": "mrkdwn", "text": format!("*{}*", headline)}},
+                {"type": "section", "text": {"type": "mrkdwn", "text": escape(body)}},
+            ],
+        })
+    }
+}
+
+/// The webhook URL is a secret, so it's left out
+impl fmt::Debug for SlackNotifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlackNotifier").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn notify(&self, level: AlertLevel, title: &str, body: &str) -> Result<(), LiquidationError> {
+        self.client
+            .post(&self.webhook_url)
+            .json(&Self::payload(level, title, body))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| LiquidationError::Other(format!("Slack rejected alert {:?}: {}", title, e.without_url())))?;
+        Ok(())
+    }
+}
+
+/// Text with the characters Slack reads as markup escaped
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// An alert raised by the engine
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub trigger: AlertTrigger,
+    pub level: AlertLevel,
+    pub title: String,
+    pub body: String,
+}
+
+impl Alert {
+    pub fn new(trigger: AlertTrigger, level: AlertLevel, title: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            trigger,
+            level,
+            title: title.into(),
+            body: body.into(),
+        }
+    }
+}
+
+/// Counts of alerts by how they ended up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct AlertStats {
+    /// Messages the notifier accepted, summaries of suppressed alerts included
+    pub sent: u64,
+    /// Alerts held back by their trigger's window
+    pub suppressed: u64,
+    /// Messages the notifier failed to deliver
+    pub failed: u64,
+    /// Alerts dropped from a full queue before they were looked at
+    pub dropped: u64,
+}
+
+/// A trigger's current window and the alerts it held back
+#[derive(Debug)]
+struct Window {
+    ends_at: Instant,
+    suppressed: u64,
+    /// The latest alert held back, most severe level held back
+    latest: Option<Alert>,
+}
+
+/// Per-trigger windows allowing one message each
+#[derive(Debug)]
+struct AlertWindows {
+    window: Duration,
+    windows: HashMap<AlertTrigger, Window>,
+}
+
+impl AlertWindows {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            windows: HashMap::new(),
+        }
+    }
+
+    /// The alert if its trigger's window is open at `now`, opening a new one,
+    /// or `None` having counted it as suppressed
+    fn admit(&mut self, alert: Alert, now: Instant) -> Option<Alert> {
+        if let Some(window) = self.windows.get_mut(&alert.trigger)
+            && now < window.ends_at
+        {
+            window.suppressed += 1;
+            let level = window.latest.as_ref().map_or(alert.level, |latest| latest.level.max(alert.level));
+            window.latest = Some(Alert { level, ..alert });
+            return None;
+        }
+        self.open(alert.trigger, now);
+        Some(alert)
+    }
+
+    fn open(&mut self, trigger: AlertTrigger, now: Instant) {
+        let window = Window {
+            ends_at: now + self.window,
+            suppressed: 0,
+            latest: None,
+        };
+        self.windows.insert(trigger, window);
+    }
+
+    /// When the earliest window holding suppressed alerts ends
+    fn next_end(&self) -> Option<Instant> {
+        self.windows
+            .values()
+            .filter(|window| window.suppressed > 0)
+            .map(|window| window.ends_at)
+            .min()
+    }
+
+    /// Close the windows ended by `now`, returning a summary of each one's
+    /// suppressed alerts: the latest with their count appended, which opens
+    /// the trigger's next window
+    fn close(&mut self, now: Instant) -> Vec<Alert> {
+        let ended: Vec<AlertTrigger> = self
+            .windows
+            .iter()
+            .filter(|(_, window)| window.ends_at <= now)
+            .map(|(trigger, _)| *trigger)
+            .collect();
+        let mut summaries = Vec::new();
+        for trigger in ended {
+            let Some(window) = self.windows.remove(&trigger) else {
+                continue;
+            };
+            if let Some(mut latest) = window.latest {
+                latest.body = format!(
+                    "{}\n({} similar alert{} suppressed in the last {:?})",
+                    latest.body,
+                    window.suppressed,
+                    if window.suppressed == 1 { "" } else { "s" },
+                    self.window
+                );
+                self.open(trigger, now);
+                summaries.push(latest);
+            }
+        }
+        summaries
+    }
+}
+
+/// State shared between the alerter handles and the delivery task
+#[derive(Debug)]
+struct Shared {
+    notifier: Arc<dyn Notifier>,
+    triggers: HashSet<AlertTrigger>,
+    queue: Mutex<VecDeque<Alert>>,
+    capacity: usize,
+    queued: Notify,
+    sent: AtomicU64,
+    suppressed: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Shared {
+    fn pop(&self) -> Option<Alert> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner).pop_front()
+    }
+}
+
+/// Handle for raising alerts, delivered by a dedicated task
+#[derive(Debug, Clone)]
+pub struct Alerter {
+    shared: Arc<Shared>,
+}
+
+impl Alerter {
+    /// Start a task sending alerts of `triggers` through `notifier`, at most
+    /// one per trigger every `window` and keeping up to `capacity` waiting
+    pub fn spawn(
+        notifier: Arc<dyn Notifier>,
+        triggers: impl IntoIterator<Item = AlertTrigger>,
+        window: Duration,
+        capacity: usize,
+    ) -> (Self, JoinHandle<()>) {
+        let shared = Arc::new(Shared {
+            notifier,
+            triggers: triggers.into_iter().collect(),
+            queue: Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+            queued: Notify::new(),
+            sent: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        });
+        let handle = tokio::spawn(deliver_queued(shared.clone(), AlertWindows::new(window)));
+        (Self { shared }, handle)
+    }
+
+    /// Start a task sending alerts as configured, unless alerts are off
+    pub fn from_config(config: &AlertConfig) -> Option<(Self, JoinHandle<()>)> {
+        let notifier = config.notifier()?;
+        Some(Self::spawn(
+            notifier,
+            config.triggers.iter().copied(),
+            config.window(),
+            DEFAULT_ALERT_QUEUE_CAPACITY,
+        ))
+    }
+
+    /// Queue an alert without waiting, dropping the oldest waiting one if the
+    /// queue is full
+    ///
+    /// Alerts of triggers that aren't enabled are ignored.
+    pub fn alert(&self, alert: Alert) {
+        if !self.shared.triggers.contains(&alert.trigger) {
+            return;
+        }
+        let mut queue = self.shared.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        if queue.len() >= self.shared.capacity
+            && let Some(oldest) = queue.pop_front()
+        {
+            let dropped = self.shared.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(dropped, "Alert queue full, dropping {} alert {:?}", oldest.trigger, oldest.title);
+        }
+        queue.push_back(alert);
+        drop(queue);
+        self.shared.queued.notify_one();
+    }
+
+    /// Counts of sent, suppressed, failed and dropped alerts so far
+    pub fn stats(&self) -> AlertStats {
+        AlertStats {
+            sent: self.shared.sent.load(Ordering::Relaxed),
+            suppressed: self.shared.suppressed.load(Ordering::Relaxed),
+            failed: self.shared.failed.load(Ordering::Relaxed),
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Send queued alerts through their triggers' windows, and the summaries of
+/// windows as they end, for as long as the process runs
+async fn deliver_queued(shared: Arc<Shared>, mut windows: AlertWindows) {
+    loop {
+        for summary in windows.close(Instant::now()) {
+            send(&shared, summary).await;
+        }
+        let Some(alert) = shared.pop() else {
+            match windows.next_end() {
+                Some(ends_at) => {
+                    tokio::select! {
+                        _ = shared.queued.notified() => {}
+                        _ = tokio::time::sleep_until(ends_at) => {}
+                    }
+                }
+                None => shared.queued.notified().await,
+            }
+            continue;
+        };
+        match windows.admit(alert, Instant::now()) {
+            Some(alert) => send(&shared, alert).await,
+            None => {
+                shared.suppressed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Hand an alert to the notifier, logging rather than retrying a failure
+async fn send(shared: &Shared, alert: Alert) {
+    let notified = tokio::time::timeout(
+        DELIVERY_TIMEOUT,
+        shared.notifier.notify(alert.level, &alert.title, &alert.body),
+    )
+    .await
+    .unwrap_or_else(|_| Err(LiquidationError::Other(format!("timed out after {:?}", DELIVERY_TIMEOUT))));
+    match notified {
+        Ok(()) => {
+            shared.sent.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(e) => {
+            shared.failed.fetch_add(1, Ordering::Relaxed);
+            error!("Failed to send {} alert {:?}: {}", alert.trigger, alert.title, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn slack_server(status: u16) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/services/T000/B000/XXXX"))
+            .respond_with(ResponseTemplate::new(status))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    async fn posted(server: &MockServer) -> Vec<serde_json::Value> {
+        let requests = server.received_requests().await.unwrap();
+        requests.iter().map(|request| request.body_json().unwrap()).collect()
+    }
+
+    async fn wait_for(alerter: &Alerter, settled: u64) -> AlertStats {
+        for _ in 0..500 {
+            let stats = alerter.stats();
+            if stats.sent + stats.failed >= settled {
+                return stats;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("alerts not settled: {:?}", alerter.stats());
+    }
+
+    fn tripped() -> Alert {
+        Alert::new(
+            AlertTrigger::CircuitBreaker,
+            AlertLevel::Critical,
+            "Circuit breaker tripped",
+            "More than 5 liquidations within 60 seconds; liquidation paused until resumed",
+        )
+    }
+
+    #[tokio::test]
+    async fn test_slack_payload_posted() {
+        let server = slack_server(200).await;
+        let notifier = SlackNotifier::new(format!("{}/services/T000/B000/XXXX", server.uri()));
+        let title = "Oracle divergence on SOL/USD";
+        notifier.notify(AlertLevel::Warning, title, "210 is 2500 bps from <150>").await.unwrap();
+
+        let posted = posted(&server).await;
+        assert_eq!(posted.len(), 1);
+        assert_eq!(
+            posted[0],
+            serde_json::json!({
+                "text": ":warning: [WARNING] Oracle divergence on SOL/USD\n210 is 2500 bps from &lt;150&gt;",
+                "blocks": [
+                    {"type": "section", "text": {"type": "mrkdwn", "text": "*:warning: [WARNING] Oracle divergence on SOL/USD*"}},
+                    {"type": "section", "text": {"type": "mrkdwn", "text": "210 is 2500 bps from &lt;150&gt;"}},
+                ],
+            })
+        );
+        // The URL carries the channel's secret, so neither it nor errors show it
+        assert!(!format!("{:?}", notifier).contains("XXXX"));
+        let failing = slack_server(500).await;
+        let notifier = SlackNotifier::new(format!("{}/services/T000/B000/XXXX", failing.uri()));
+        let error = notifier.notify(AlertLevel::Info, "t", "b").await.unwrap_err().to_string();
+        assert!(error.contains("500") && !error.contains("XXXX"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_alerts_rate_limited_per_trigger() {
+        let server = slack_server(200).await;
+        let notifier = Arc::new(SlackNotifier::new(format!("{}/services/T000/B000/XXXX", server.uri())));
+        let triggers = [AlertTrigger::CircuitBreaker, AlertTrigger::BadDebt];
+        let (alerter, _) = Alerter::spawn(notifier, triggers, Duration::from_millis(300), DEFAULT_ALERT_QUEUE_CAPACITY);
+
+        // The first of each trigger goes out at once, the rest wait out the window
+        alerter.alert(tripped());
+        alerter.alert(tripped());
+        alerter.alert(tripped());
+        alerter.alert(Alert::new(AlertTrigger::BadDebt, AlertLevel::Critical, "Bad debt", "2105.68 on BTC/USD"));
+        alerter.alert(Alert::new(AlertTrigger::WarmUp, AlertLevel::Info, "Warming up", "not enabled"));
+        assert_eq!(wait_for(&alerter, 2).await, AlertStats { sent: 2, suppressed: 2, ..Default::default() });
+        let titles: Vec<String> = posted(&server).await.iter().map(|body| body["text"].to_string()).collect();
+        assert!(titles[0].contains("Circuit breaker tripped") && titles[1].contains("Bad debt"), "{:?}", titles);
+
+        // Once the window ends the suppressed ones are summed up in one message
+        assert_eq!(wait_for(&alerter, 3).await.sent, 3);
+        let posted = posted(&server).await;
+        let summary = posted[2]["text"].as_str().unwrap();
+        assert!(summary.starts_with(":rotating_light: [CRITICAL] Circuit breaker tripped"), "{}", summary);
+        assert!(summary.ends_with("(2 similar alerts suppressed in the last 300ms)"), "{}", summary);
+
+        // Nothing was held back in the summary's window, so it closes quietly
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(alerter.stats().sent, 3);
+        alerter.alert(tripped());
+        assert_eq!(wait_for(&alerter, 4).await, AlertStats { sent: 4, suppressed: 2, ..Default::default() });
+    }
+
+    #[tokio::test]
+    async fn test_failing_notifier_never_blocks_alerts() {
+        let server = slack_server(500).await;
+        let notifier = Arc::new(SlackNotifier::new(format!("{}/services/T000/B000/XXXX", server.uri())));
+        let (alerter, _) = Alerter::spawn(notifier, AlertTrigger::ALL, Duration::from_secs(60), 2);
+        // Nothing is delivered until this test yields, so the first is dropped
+        for trigger in [AlertTrigger::LowBalance, AlertTrigger::Paused, AlertTrigger::WarmUp] {
+            alerter.alert(Alert::new(trigger, AlertLevel::Warning, trigger.to_string(), ""));
+        }
+        assert_eq!(alerter.stats().dropped, 1);
+        assert_eq!(
+            wait_for(&alerter, 2).await,
+            AlertStats { failed: 2, dropped: 1, ..Default::default() }
+        );
+    }
+
+    #[test]
+    fn test_windows_keep_most_severe_level() {
+        let start = Instant::now();
+        let mut windows = AlertWindows::new(Duration::from_secs(60));
+        let divergence = |level| Alert::new(AlertTrigger::OracleDivergence, level, "Oracle divergence", "SOL/USD");
+        assert!(windows.admit(divergence(AlertLevel::Warning), start).is_some());
+        assert!(windows.admit(divergence(AlertLevel::Critical), start + Duration::from_secs(1)).is_none());
+        assert!(windows.admit(divergence(AlertLevel::Warning), start + Duration::from_secs(2)).is_none());
+        assert_eq!(windows.next_end(), Some(start + Duration::from_secs(60)));
+        assert!(windows.close(start + Duration::from_secs(59)).is_empty());
+
+        let summaries = windows.close(start + Duration::from_secs(60));
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].level, AlertLevel::Critical);
+        assert_eq!(summaries[0].body, "SOL/USD\n(2 similar alerts suppressed in the last 60s)");
+        // The summary opened the next window, and one holding nothing back closes quietly
+        assert!(windows.admit(divergence(AlertLevel::Info), start + Duration::from_secs(61)).is_none());
+        let summaries = windows.close(start + Duration::from_secs(120));
+        assert_eq!(summaries[0].body, "SOL/USD\n(1 similar alert suppressed in the last 60s)");
+        assert!(windows.close(start + Duration::from_secs(180)).is_empty());
+        assert!(windows.windows.is_empty());
+    }
+
+    #[test]
+    fn test_config_validated() {
+        assert!(AlertConfig::default().validate().is_ok());
+        assert!(AlertConfig::default().notifier().is_none());
+        let slack = AlertConfig {
+            notifier: NotifierKind::Slack,
+            ..Default::default()
+        };
+        assert!(slack.validate().unwrap_err().to_string().contains("slack_webhook_url"));
+        let config = AlertConfig {
+            notifier: NotifierKind::Log,
+            window_secs: 0,
+            consecutive_failures: 0,
+            ..Default::default()
+        };
+        let fields: Vec<String> = config.violations().into_iter().map(|violation| violation.field).collect();
+        assert_eq!(fields, ["window_secs", "consecutive_failures"]);
+    }
+}
@@ -0,0 +1,254 @@
+//! Numeric type positions are accounted in
+//!
+//! Sizes, prices and margins are [`Amount`]s: f64 by default, and
+//! `rust_decimal::Decimal` with the `decimal` feature, so that liquidation decisions
+//! are deterministic right at the maintenance margin boundary. In fixed point sums,
+//! differences and products are exact; only divisions round, and they always round
+//! against the trader:
+//!
+//! - margin ratios and health factors round toward negative infinity, so a position
+//!   is never rounded out of liquidation
+//! - leverage rounds toward positive infinity
+//! - long liquidation and bankruptcy prices round up and short ones down, so the
+//!   trigger is never rounded past the exact boundary
+//!
+//! As f64 the rounding is a no-op. The engine's estimates and heuristics (fees,
+//! priorities, depth, statistics) stay f64, converting with [`AmountExt::as_f64`]
+//! and [`from_f64`]; f64 values also remain the display format.
+
+use crate::error::LiquidationError;
+
+/// Sizes, prices and margins of positions
+#[cfg(not(feature = "decimal"))]
+pub type Amount = f64;
+
+/// Sizes, prices and margins of positions
+#[cfg(feature = "decimal")]
+pub type Amount = rust_decimal::Decimal;
+
+/// Decimal places kept for ratios (margin ratio, health factor, leverage)
+pub const RATIO_SCALE: u32 = 12;
+
+/// Decimal places kept for derived prices
+pub const PRICE_SCALE: u32 = 8;
+
+/// Direction a division's result is rounded in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Toward negative infinity
+    Down,
+    /// Toward positive infinity
+    Up,
+}
+
+/// Operations [`Amount`] has whichever type it is
+pub trait AmountExt: Copy {
+    /// Zero
+    const ZERO: Self;
+    /// One
+    const ONE: Self;
+    /// Bound of values that can't be evaluated, e.g. the margin ratio of a NaN
+    /// entry price: infinity as f64, the largest decimal in fixed point
+    const UNBOUNDED: Self;
+
+    /// The amount as an f64, for display and the engine's f64 heuristics
+    fn as_f64(self) -> f64;
+
+    /// Round to `scale` decimal places in the `rounding` direction
+    fn round_to(self, scale: u32, rounding: Rounding) -> Self;
+
+    /// Divide, `None` where fixed point can't: by zero or past its range
+    ///
+    /// As f64 the quotient is always returned, infinite or NaN as it may be.
+    fn checked_div(self, divisor: Self) -> Option<Self>;
+
+    /// Whether the amount is a number; fixed point amounts always are
+    fn is_nan(self) -> bool;
+
+    /// Whether the amount is finite; fixed point amounts always are
+    fn is_finite(self) -> bool;
+}
+
+#[cfg(not(feature = "decimal"))]
+impl AmountExt for f64 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+    const UNBOUNDED: Self = f64::INFINITY;
+
+    fn as_f64(self) -> f64 {
+        self
+    }
+
+    fn round_to(self, _scale: u32, _rounding: Rounding) -> Self {
+        self
+    }
+
+    fn checked_div(self, divisor: Self) -> Option<Self> {
+        Some(self / divisor)
+    }
+
+    fn is_nan(self) -> bool {
+        f64::is_nan(self)
+    }
+
+    fn is_finite(self) -> bool {
+        f64::is_finite(self)
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl AmountExt for rust_decimal::Decimal {
+    const ZERO: Self = rust_decimal::Decimal::ZERO;
+    const ONE: Self = rust_decimal::Decimal::ONE;
+    const UNBOUNDED: Self = rust_decimal::Decimal::MAX;
+
+    fn as_f64(self) -> f64 {
+        rust_decimal::prelude::ToPrimitive::to_f64(&self).unwrap_or(f64::NAN)
+    }
+
+    fn round_to(self, scale: u32, rounding: Rounding) -> Self {
+        let strategy = match rounding {
+            Rounding::Down => rust_decimal::RoundingStrategy::ToNegativeInfinity,
+            Rounding::Up => rust_decimal::RoundingStrategy::ToPositiveInfinity,
+        };
+        self.round_dp_with_strategy(scale, strategy)
+    }
+
+    fn checked_div(self, divisor: Self) -> Option<Self> {
+        rust_decimal::Decimal::checked_div(self, divisor)
+    }
+
+    fn is_nan(self) -> bool {
+        false
+    }
+
+    fn is_finite(self) -> bool {
+        true
+    }
+}
+
+/// Convert an f64, e.g. read off the wire, into an amount
+///
+/// Fixed point rejects NaN and infinite values; as f64 they pass through, for
+/// the checks that flag them (see [`Position::non_finite_field`]).
+///
+/// [`Position::non_finite_field`]: crate::Position::non_finite_field
+pub fn from_f64(value: f64) -> Result<Amount, LiquidationError> {
+    #[cfg(feature = "decimal")]
+    {
+        rust_decimal::prelude::FromPrimitive::from_f64(value)
+            .ok_or_else(|| LiquidationError::Other(format!("Cannot represent {} as a decimal", value)))
+    }
+    #[cfg(not(feature = "decimal"))]
+    {
+        Ok(value)
+    }
+}
+
+/// Convert an f64 that has already been validated, e.g. a configured ratio,
+/// into an amount, zero if fixed point can't represent it
+pub fn from_f64_lossy(value: f64) -> Amount {
+    from_f64(value).unwrap_or(Amount::ZERO)
+}
+
+/// Convert a mantissa scaled by `10^-expo`, as Pyth reports prices, into an amount
+///
+/// Exact in fixed point, which rejects exponents past its 28 decimal places and
+/// values past its 96 bit range rather than rounding them.
+pub fn from_mantissa(mantissa: i64, expo: i32) -> Result<Amount, LiquidationError> {
+    #[cfg(feature = "decimal")]
+    {
+        let out_of_range =
+            || LiquidationError::OracleError(format!("Cannot represent {}e{} as a decimal", mantissa, expo));
+        let (mantissa, scale) = if expo <= 0 {
+            (i128::from(mantissa), expo.unsigned_abs())
+        } else {
+            let factor = 10i128.checked_pow(expo.unsigned_abs()).ok_or_else(out_of_range)?;
+            (i128::from(mantissa).checked_mul(factor).ok_or_else(out_of_range)?, 0)
+        };
+        rust_decimal::Decimal::try_from_i128_with_scale(mantissa, scale).map_err(|_| out_of_range())
+    }
+    #[cfg(not(feature = "decimal"))]
+    {
+        Ok(mantissa as f64 * 10f64.powi(expo))
+    }
+}
+
+/// Convert an on-chain amount of a mint's base units into whole tokens of its
+/// `decimals`
+///
+/// Exact in fixed point up to its 28 decimal places.
+pub fn from_units(units: u64, decimals: u8) -> Amount {
+    #[cfg(feature = "decimal")]
+    {
+        rust_decimal::Decimal::try_from_i128_with_scale(i128::from(units), u32::from(decimals))
+            .unwrap_or_else(|_| from_f64_lossy(units as f64 / 10f64.powi(i32::from(decimals))))
+    }
+    #[cfg(not(feature = "decimal"))]
+    {
+        units as f64 / 10f64.powi(i32::from(decimals))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_mantissa() {
+        assert!((from_mantissa(6_000_012_345_678, -8).unwrap().as_f64() - 60000.12345678).abs() < 1e-9);
+        assert_eq!(from_mantissa(15, 2).unwrap(), Amount::from(1500));
+        assert_eq!(from_mantissa(-42, 0).unwrap(), Amount::from(-42));
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn test_from_mantissa_rejects_out_of_range() {
+        use std::str::FromStr;
+
+        assert_eq!(
+            from_mantissa(6_000_012_345_678, -8).unwrap(),
+            Amount::from_str("60000.12345678").unwrap()
+        );
+        assert_eq!(from_mantissa(1, -28).unwrap(), Amount::from_str("0.0000000000000000000000000001").unwrap());
+        assert!(from_mantissa(1, -29).is_err());
+        assert!(from_mantissa(1, 19).is_ok());
+        assert!(from_mantissa(i64::MAX, 19).is_err());
+        assert!(from_mantissa(1, 39).is_err());
+        assert!(from_mantissa(1, i32::MIN).is_err());
+    }
+
+    #[test]
+    fn test_from_units() {
+        assert_eq!(from_units(567_000_000_000, 9), Amount::from(567));
+        assert_eq!(from_units(1_500, 3), from_mantissa(15, -1).unwrap());
+        assert_eq!(from_units(42, 0), Amount::from(42));
+    }
+
+    #[test]
+    fn test_from_f64() {
+        assert_eq!(from_f64(0.5).unwrap(), Amount::ONE / Amount::from(2));
+        #[cfg(feature = "decimal")]
+        {
+            assert!(from_f64(f64::NAN).is_err());
+            assert!(from_f64(f64::INFINITY).is_err());
+            assert_eq!(from_f64_lossy(f64::NAN), Amount::ZERO);
+        }
+    }
+
+    #[test]
+    fn test_rounding() {
+        let third = Amount::ONE.checked_div(Amount::from(3)).unwrap();
+        #[cfg(feature = "decimal")]
+        {
+            use std::str::FromStr;
+
+            assert_eq!(third.round_to(4, Rounding::Down), Amount::from_str("0.3333").unwrap());
+            assert_eq!(third.round_to(4, Rounding::Up), Amount::from_str("0.3334").unwrap());
+            assert_eq!((-third).round_to(4, Rounding::Down), Amount::from_str("-0.3334").unwrap());
+            assert_eq!(Amount::ONE.checked_div(Amount::ZERO), None);
+        }
+        #[cfg(not(feature = "decimal"))]
+        assert_eq!(third.round_to(4, Rounding::Down), third);
+    }
+}
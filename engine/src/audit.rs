@@ -0,0 +1,321 @@
+//! Audit log of every liquidation attempt and the inputs it was decided on
+//!
+//! Each attempt, whether it succeeded or failed, leaves an [`AuditRecord`] of
+//! the oracle reading, margin parameters and margin ratio it was judged at,
+//! together with the hash of the configuration in effect, so a liquidation can
+//! be explained long after the fact. Records are appended as JSON lines to one
+//! file per UTC day, named as dry run reports are (`audit.jsonl` is written as
+//! `audit-2024-03-01.jsonl`). The engine only queues records; a dedicated task
+//! writes them, and the engine flushes it before it reports being stopped.
+
+use crate::address::{OwnerAddress, PositionAddress};
+use crate::error::LiquidationError;
+use crate::report::{day_of, report_path_for_day};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Default capacity of the audit writer's queue, beyond which records are
+/// dropped and counted rather than waited for
+pub const DEFAULT_AUDIT_QUEUE_CAPACITY: usize = 1024;
+
+/// A liquidation attempt and everything it was decided on
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AuditRecord {
+    /// When the attempt was decided
+    pub timestamp: i64,
+    /// The position liquidated
+    pub position: PositionAddress,
+    /// The position's owner
+    pub owner: OwnerAddress,
+    /// The trading pair symbol
+    pub symbol: String,
+    /// Check cycle whose price snapshot the position was evaluated at, if any
+    pub cycle_id: Option<String>,
+    /// Correlation ID tagging everything logged about the attempt
+    pub correlation_id: String,
+    /// Hash of the configuration in effect (see [`LiquidationConfig::config_hash`](crate::LiquidationConfig::config_hash))
+    pub config_hash: String,
+    /// Oracle price the position was judged at
+    pub oracle_price: f64,
+    /// Confidence interval of the oracle price
+    pub oracle_confidence: f64,
+    /// Oracle EMA price, checked when TWAP confirmation is required
+    pub oracle_ema_price: f64,
+    /// Unix timestamp the oracle price was published at
+    pub oracle_publish_time: i64,
+    /// Whether the position is long
+    pub is_long: bool,
+    /// Size of the position before the attempt (in base currency)
+    pub size: f64,
+    /// Entry price of the position
+    pub entry_price: f64,
+    /// Margin of the position, including unsettled funding (in quote currency)
+    pub margin: f64,
+    /// Maintenance margin ratio of the position's side at the oracle price
+    pub maintenance_margin: f64,
+    /// Margin ratio at the oracle price (collateral / position value)
+    pub margin_ratio: f64,
+    /// Amount liquidated (in base currency)
+    pub amount: f64,
+    /// Bad debt realized by the attempt (in quote currency)
+    pub bad_debt: f64,
+    /// Whether the attempt was only simulated
+    pub dry_run: bool,
+    /// Submissions made, including retries
+    pub attempts: u8,
+    /// Signature of the liquidation transaction, if it succeeded
+    pub signature: Option<String>,
+    /// Why the attempt failed, if it did
+    pub error: Option<String>,
+}
+
+/// Read the records of an audit file
+pub(crate) fn read_audit(path: impl AsRef<Path>) -> Result<Vec<AuditRecord>, LiquidationError> {
+    let path = path.as_ref();
+    let invalid = |e: String| LiquidationError::Other(format!("Invalid audit log {}: {}", path.display(), e));
+    let contents = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| serde_json::from_str(line).map_err(|e| invalid(format!("line {}: {}", index + 1, e))))
+        .collect()
+}
+
+/// Daily files of the audit log at `path`, oldest first
+fn audit_files(path: &Path) -> Result<Vec<PathBuf>, LiquidationError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    // Daily files are named as `path` with a day between its stem and extension
+    let template = report_path_for_day(path, "");
+    let template = template.file_name().unwrap_or_default().to_string_lossy();
+    let (prefix, suffix) = template.split_at(path.file_stem().map_or(0, |stem| stem.len() + 1));
+    let is_daily = |name: &str| {
+        name.strip_prefix(prefix)
+            .and_then(|name| name.strip_suffix(suffix))
+            .is_some_and(|day| chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d").is_ok())
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(LiquidationError::Other(format!("Unable to list {}: {}", dir.display(), e))),
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|file| file.file_name().is_some_and(|name| is_daily(&name.to_string_lossy())))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Appends records to the file of their day, switching files as days change
+struct AuditFile {
+    path: PathBuf,
+    /// Day being written and its file
+    current: Option<(String, BufWriter<File>)>,
+}
+
+impl AuditFile {
+    fn write(&mut self, record: &AuditRecord) -> std::io::Result<()> {
+        let day = day_of(record.timestamp);
+        if self.current.as_ref().is_none_or(|(current, _)| *current != day) {
+            self.flush()?;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(report_path_for_day(&self.path, &day))?;
+            self.current = Some((day, BufWriter::new(file)));
+        }
+        let Some((_, writer)) = &mut self.current else {
+            unreachable!("a file is open for the record's day");
+        };
+        writeln!(writer, "{}", serde_json::to_string(record)?)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.current {
+            Some((_, writer)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+enum AuditCommand {
+    Record(Box<AuditRecord>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle for queueing records to the audit log's writer task
+#[derive(Debug, Clone)]
+pub struct AuditWriter {
+    path: PathBuf,
+    tx: mpsc::Sender<AuditCommand>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl AuditWriter {
+    /// Start a writer task appending records to the daily files of `path`, with
+    /// a queue of `capacity` records
+    ///
+    /// Records are flushed whenever the queue runs empty. The task finishes,
+    /// flushing what's left, once every `AuditWriter` handle has been dropped.
+    pub fn spawn(path: impl Into<PathBuf>, capacity: usize) -> (Self, JoinHandle<()>) {
+        let path = path.into();
+        let (tx, mut rx) = mpsc::channel::<AuditCommand>(capacity);
+        let mut file = AuditFile {
+            path: path.clone(),
+            current: None,
+        };
+        let handle = tokio::task::spawn_blocking(move || {
+            while let Some(command) = rx.blocking_recv() {
+                match command {
+                    AuditCommand::Record(record) => {
+                        if let Err(e) = file.write(&record) {
+                            error!("Failed to audit liquidation attempt on {}: {}", record.position, e);
+                        }
+                    }
+                    AuditCommand::Flush(done) => {
+                        if let Err(e) = file.flush() {
+                            error!("Failed to flush audit log: {}", e);
+                        }
+                        let _ = done.send(());
+                    }
+                }
+                if rx.is_empty()
+                    && let Err(e) = file.flush()
+                {
+                    error!("Failed to flush audit log: {}", e);
+                }
+            }
+            if let Err(e) = file.flush() {
+                error!("Failed to flush audit log: {}", e);
+            }
+        });
+        let dropped = Arc::new(AtomicU64::new(0));
+        (Self { path, tx, dropped }, handle)
+    }
+
+    /// Queue a record without waiting, dropping and counting it if the queue is full
+    pub fn record(&self, record: AuditRecord) {
+        if let Err(e) = self.tx.try_send(AuditCommand::Record(Box::new(record))) {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(dropped, "Dropping audit record, queue unavailable: {}", e);
+        }
+    }
+
+    /// Records dropped from a full or closed queue so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Wait until every record queued so far has been written out
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.tx.send(AuditCommand::Flush(done)).await.is_ok() {
+            let _ = flushed.await;
+        }
+    }
+
+    /// Every record of `position` written so far, oldest first, including those
+    /// still queued
+    pub async fn records_for(&self, position: &PositionAddress) -> Result<Vec<AuditRecord>, LiquidationError> {
+        self.flush().await;
+        let path = self.path.clone();
+        let position = *position;
+        tokio::task::spawn_blocking(move || {
+            let mut records = Vec::new();
+            for file in audit_files(&path)? {
+                records.extend(read_audit(file)?.into_iter().filter(|record| record.position == position));
+            }
+            Ok(records)
+        })
+        .await
+        .map_err(|e| LiquidationError::Other(format!("Audit log read failed: {}", e)))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_record(position: PositionAddress, timestamp: i64) -> AuditRecord {
+        AuditRecord {
+            timestamp,
+            position,
+            owner: OwnerAddress::new_unique(),
+            symbol: "BTC/USD".to_string(),
+            cycle_id: Some("cycle-1".to_string()),
+            correlation_id: "attempt-1".to_string(),
+            config_hash: "ab12".to_string(),
+            oracle_price: 50_000.0,
+            oracle_confidence: 25.0,
+            oracle_ema_price: 50_100.0,
+            oracle_publish_time: timestamp - 1,
+            is_long: true,
+            size: 1.0,
+            entry_price: 52_000.0,
+            margin: 2_400.0,
+            maintenance_margin: 0.05,
+            margin_ratio: 0.008,
+            amount: 0.5,
+            bad_debt: 0.0,
+            dry_run: false,
+            attempts: 1,
+            signature: Some("5ig".to_string()),
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_rotate_daily_and_are_read_by_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let (writer, handle) = AuditWriter::spawn(&path, DEFAULT_AUDIT_QUEUE_CAPACITY);
+        let (position, other) = (PositionAddress::new_unique(), PositionAddress::new_unique());
+        let day = 1_700_006_400; // 2023-11-15T00:00:00Z
+        let failed = AuditRecord {
+            signature: None,
+            error: Some("Transaction failed".to_string()),
+            ..create_record(position, day + 86_400)
+        };
+        let records = [create_record(position, day + 10), create_record(other, day + 20), failed];
+        for record in &records {
+            writer.record(record.clone());
+        }
+        // Files of other logs in the directory are left out
+        std::fs::write(dir.path().join("audit-old.jsonl"), "not json").unwrap();
+
+        // Queued records are read back, across days
+        let read = writer.records_for(&position).await.unwrap();
+        assert_eq!(read, [records[0].clone(), records[2].clone()]);
+        assert_eq!(read_audit(report_path_for_day(&path, "2023-11-15")).unwrap(), records[..2]);
+        assert_eq!(writer.records_for(&PositionAddress::new_unique()).await.unwrap(), []);
+
+        // Dropping the last handle flushes what's left
+        writer.record(create_record(other, day + 86_500));
+        drop(writer);
+        handle.await.unwrap();
+        assert_eq!(read_audit(report_path_for_day(&path, "2023-11-16")).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_missing_log_has_no_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let (writer, _) = AuditWriter::spawn(dir.path().join("missing/audit.jsonl"), DEFAULT_AUDIT_QUEUE_CAPACITY);
+        assert_eq!(writer.records_for(&PositionAddress::new_unique()).await.unwrap(), []);
+
+        let path = dir.path().join("audit-2023-11-15.jsonl");
+        std::fs::write(&path, "{\"position\":1}\n").unwrap();
+        let error = read_audit(&path).unwrap_err().to_string();
+        assert!(error.contains("line 1"), "{}", error);
+    }
+}
@@ -0,0 +1,534 @@
+//! Backfill of liquidations that landed before the engine was watching,
+//! enabled with the `storage` feature
+//!
+//! The program's transactions are listed with `getSignaturesForAddress`, newest
+//! first and a page at a time, and each successful one in the range is fetched
+//! and its `PositionLiquidated` events recorded to the store. Events are
+//! recorded once per position and signature, so liquidations the engine made
+//! itself, or a backfill already recorded, are skipped. The last transaction
+//! processed is kept in the store until the backfill finishes, so one that was
+//! interrupted resumes where it stopped.
+
+use crate::amount::{self, Amount, AmountExt};
+use crate::error::LiquidationError;
+use crate::events::{self, ProgramEvent};
+use crate::race;
+use crate::rate_limit::RateLimiter;
+use crate::rewards;
+use crate::rpc_pool::RpcPool;
+use crate::storage::{BackfillCursor, LiquidationStore};
+use crate::types::LiquidationEvent;
+use async_trait::async_trait;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Most signatures `getSignaturesForAddress` returns per request
+pub const SIGNATURES_PAGE_LIMIT: usize = 1000;
+
+/// One end of the range of transactions backfilled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackfillBound {
+    /// A slot
+    Slot(u64),
+    /// A Unix timestamp, compared with transactions' block times
+    Timestamp(i64),
+}
+
+impl BackfillBound {
+    /// Whether a transaction that landed at `slot` and `block_time` precedes
+    /// this bound, or `None` if its block time is needed and unknown
+    fn precedes(&self, slot: u64, block_time: Option<i64>) -> Option<bool> {
+        match *self {
+            Self::Slot(bound) => Some(slot < bound),
+            Self::Timestamp(bound) => block_time.map(|time| time < bound),
+        }
+    }
+}
+
+impl fmt::Display for BackfillBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Slot(slot) => write!(f, "slot {}", slot),
+            Self::Timestamp(timestamp) => write!(f, "time {}", timestamp),
+        }
+    }
+}
+
+/// Transactions backfilled: those landed from `start` on and before `end`
+///
+/// A missing bound leaves the range open on that side. Transactions without a
+/// block time are kept when bounded by time, as they can't be placed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackfillRange {
+    /// First slot or time included
+    pub start: Option<BackfillBound>,
+    /// First slot or time excluded
+    pub end: Option<BackfillBound>,
+}
+
+impl BackfillRange {
+    /// Whether a transaction landed too late to be backfilled
+    fn is_after_end(&self, status: &RpcConfirmedTransactionStatusWithSignature) -> bool {
+        self.end
+            .and_then(|end| end.precedes(status.slot, status.block_time))
+            .is_some_and(|precedes| !precedes)
+    }
+
+    /// Whether a transaction landed too early, and with it every one listed after it
+    fn is_before_start(&self, status: &RpcConfirmedTransactionStatusWithSignature) -> bool {
+        self.start
+            .and_then(|start| start.precedes(status.slot, status.block_time))
+            .unwrap_or(false)
+    }
+
+    /// Key the cursor of a backfill of this range from `program_id` is stored under
+    pub fn cursor_key(&self, program_id: &Pubkey) -> String {
+        let bound = |bound: Option<BackfillBound>| bound.map_or_else(|| "open".to_string(), |bound| bound.to_string());
+        format!("{} from {} to {}", program_id, bound(self.start), bound(self.end))
+    }
+}
+
+/// Deployment of the program liquidations are backfilled from
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackfillSource {
+    /// Program the liquidations were made with
+    pub program_id: Pubkey,
+    /// Symbol of the program's positions
+    pub symbol: String,
+    /// Liquidation fee rewarding the liquidator, in basis points of the debt repaid
+    pub liquidation_fee_bps: u16,
+}
+
+/// What a backfill went through and recorded
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct BackfillStats {
+    /// Signature the backfill resumed after, if it was interrupted before
+    pub resumed_after: Option<String>,
+    /// Signatures listed, in the range or not
+    pub signatures: u64,
+    /// Successful transactions in the range fetched and parsed
+    pub transactions: u64,
+    /// Liquidations recorded to the store
+    pub recorded: u64,
+    /// Liquidations the store already held
+    pub duplicates: u64,
+    /// `liquidate` instructions without a matching event, as when the
+    /// transaction's logs were truncated or it predates the events
+    pub missing_events: u64,
+}
+
+/// Past transactions of an address, as the RPC node keeps them
+#[async_trait]
+pub trait TransactionHistory: Send + Sync {
+    /// Up to `limit` signatures of transactions mentioning `address`, newest
+    /// first, starting after `before` if given
+    async fn signatures(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, LiquidationError>;
+
+    /// A confirmed transaction with `jsonParsed` encoding
+    async fn transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta, LiquidationError>;
+}
+
+/// Transaction history read from the healthiest of a pool of RPC endpoints
+#[derive(Debug, Clone)]
+pub struct RpcHistory {
+    rpc: RpcPool,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl RpcHistory {
+    /// Create a history asking the given RPC endpoints, within `rate_limiter`'s budget
+    pub fn new(rpc: RpcPool, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self { rpc, rate_limiter }
+    }
+}
+
+#[async_trait]
+impl TransactionHistory for RpcHistory {
+    async fn signatures(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, LiquidationError> {
+        let address = *address;
+        self.rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client
+                    .get_signatures_for_address_with_config(
+                        &address,
+                        GetConfirmedSignaturesForAddress2Config {
+                            before,
+                            until: None,
+                            limit: Some(limit.min(SIGNATURES_PAGE_LIMIT)),
+                            commitment: Some(CommitmentConfig::confirmed()),
+                        },
+                    )
+                    .map_err(LiquidationError::from)
+            })
+            .await
+    }
+
+    async fn transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta, LiquidationError> {
+        rewards::fetch_transaction(&self.rpc, &self.rate_limiter, signature).await
+    }
+}
+
+/// Liquidation events of a `jsonParsed` transaction, and how many of its
+/// `liquidate` instructions emitted no event to make one from
+///
+/// Each `PositionLiquidated` event the program emitted becomes a liquidation
+/// event, marked as made by the signer of the instruction that liquidated the
+/// position. The position's owner and what was left of it aren't logged, so
+/// those are left at their defaults, and the reward is the liquidation fee on
+/// the debt repaid.
+pub fn liquidation_events(
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    signature: &str,
+    source: &BackfillSource,
+) -> (Vec<LiquidationEvent>, u64) {
+    let Some(meta) = transaction.transaction.meta.as_ref().filter(|meta| meta.err.is_none()) else {
+        return (Vec::new(), 0);
+    };
+    let logs: &[String] = match &meta.log_messages {
+        OptionSerializer::Some(logs) => logs,
+        _ => &[],
+    };
+    let mut signers: HashMap<Pubkey, VecDeque<Option<Pubkey>>> = HashMap::new();
+    let instructions = race::liquidate_instructions(transaction, &source.program_id);
+    for (position, signer) in &instructions {
+        signers.entry(*position).or_default().push_back(*signer);
+    }
+
+    let mut liquidations = Vec::new();
+    for event in events::parse_program_events(logs, &source.program_id) {
+        let ProgramEvent::Liquidated(logged) = event else {
+            continue;
+        };
+        // Instructions liquidating the same position emit their events in order
+        let signer = signers.get_mut(&logged.position).and_then(VecDeque::pop_front).flatten();
+        let seized = amount::from_units(logged.collateral_seized, 0);
+        let repaid = amount::from_units(logged.repay_amount, 0);
+        liquidations.push(LiquidationEvent {
+            position: logged.position.into(),
+            owner: Pubkey::default().into(),
+            liquidator: signer.unwrap_or(logged.liquidator),
+            amount: seized,
+            remaining_size: Amount::ZERO,
+            remaining_margin: Amount::ZERO,
+            liquidation_price: repaid.checked_div(seized).filter(|_| seized > Amount::ZERO).unwrap_or(Amount::ZERO),
+            timestamp: logged.timestamp,
+            signature: signature.to_string(),
+            bad_debt: Amount::ZERO,
+            symbol: source.symbol.clone(),
+            reward: repaid * Amount::from(source.liquidation_fee_bps) / Amount::from(10_000),
+            dry_run: false,
+            error: None,
+            priority_fee_micro_lamports: 0,
+            metadata: HashMap::from([("remaining_debt".to_string(), logged.remaining_debt.to_string())]),
+        });
+    }
+    let missing = instructions.len().saturating_sub(liquidations.len()) as u64;
+    (liquidations, missing)
+}
+
+/// Record the liquidations `source`'s program made in `range` to `store`,
+/// reading transactions from `history`
+///
+/// Resumes after the last transaction a previous, interrupted backfill of the
+/// same range processed, and forgets it once finished.
+pub async fn backfill(
+    history: &dyn TransactionHistory,
+    store: &LiquidationStore,
+    source: &BackfillSource,
+    range: &BackfillRange,
+) -> Result<BackfillStats, LiquidationError> {
+    let key = range.cursor_key(&source.program_id);
+    let mut stats = BackfillStats::default();
+    let mut before = match store.backfill_cursor(&key)? {
+        Some(cursor) => {
+            info!("Resuming backfill of {} after {} (slot {})", key, cursor.signature, cursor.slot);
+            stats.resumed_after = Some(cursor.signature.clone());
+            Some(parse_signature(&cursor.signature)?)
+        }
+        None => None,
+    };
+
+    'pages: loop {
+        let page = history
+            .signatures(&source.program_id, before, SIGNATURES_PAGE_LIMIT)
+            .await?;
+        for status in &page {
+            let signature = parse_signature(&status.signature)?;
+            stats.signatures += 1;
+            if range.is_before_start(status) {
+                break 'pages;
+            }
+            if !range.is_after_end(status) && status.err.is_none() {
+                let transaction = history.transaction(&signature).await?;
+                let (events, missing) = liquidation_events(&transaction, &status.signature, source);
+                stats.transactions += 1;
+                stats.missing_events += missing;
+                if missing > 0 {
+                    warn!("{} liquidations in {} emitted no event to backfill", missing, status.signature);
+                }
+                for event in &events {
+                    if store.insert_event_once(event)? {
+                        stats.recorded += 1;
+                    } else {
+                        stats.duplicates += 1;
+                    }
+                }
+            }
+            let cursor = BackfillCursor {
+                signature: status.signature.clone(),
+                slot: status.slot,
+            };
+            store.set_backfill_cursor(&key, &cursor)?;
+            before = Some(signature);
+        }
+        // A short page is the last: the node holds nothing older
+        if page.len() < SIGNATURES_PAGE_LIMIT {
+            break;
+        }
+    }
+    store.clear_backfill_cursor(&key)?;
+    Ok(stats)
+}
+
+fn parse_signature(signature: &str) -> Result<Signature, LiquidationError> {
+    Signature::from_str(signature)
+        .map_err(|e| LiquidationError::Other(format!("invalid signature {}: {}", signature, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::PROGRAM_ID;
+    use std::sync::Mutex;
+
+    const TRANSACTION: &str = include_str!("../fixtures/backfill/liquidation_tx.json");
+    const SIGNATURE: &str = "53Zwtjf1fYGJubvxqcoEEWsNRDu9R5jv1mZ3YmznqE39cggfia3Bjuq5rNuV29Si4siA3fr2KU5Fgnuq5PCizjTG";
+    const FIRST_POSITION: &str = "A8gURbKkT4ueX6hpjNKKqzvt7VHuTuzfAwnhA4WccZS6";
+    const SECOND_POSITION: &str = "EDxHNLZTTKeJhTdEuwHyL6aU9sv8dHLu44CFmjdrF1dM";
+    const LIQUIDATOR: &str = "BaEXKhLPmWZh4myCBpwM2Ex7WbGpAN1AE4Q6YiB9s6bN";
+
+    fn source() -> BackfillSource {
+        BackfillSource {
+            program_id: PROGRAM_ID,
+            symbol: "BTC/USD".to_string(),
+            liquidation_fee_bps: 1_000,
+        }
+    }
+
+    fn fixture() -> EncodedConfirmedTransactionWithStatusMeta {
+        serde_json::from_str(TRANSACTION).unwrap()
+    }
+
+    /// History of `count` successful transactions, one per slot from 10,000
+    /// down, each the fixture's liquidations; fetching the one at
+    /// `fail_at_slot` fails
+    struct MockHistory {
+        signatures: Vec<RpcConfirmedTransactionStatusWithSignature>,
+        fail_at_slot: Mutex<Option<u64>>,
+        requests: Mutex<Vec<Option<Signature>>>,
+    }
+
+    impl MockHistory {
+        fn new(count: u64) -> Self {
+            let signatures = (0..count)
+                .map(|index| RpcConfirmedTransactionStatusWithSignature {
+                    signature: Signature::new_unique().to_string(),
+                    slot: 10_000 - index,
+                    err: None,
+                    memo: None,
+                    block_time: Some(1_760_000_000 - index as i64),
+                    confirmation_status: None,
+                })
+                .collect();
+            Self {
+                signatures,
+                fail_at_slot: Mutex::new(None),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TransactionHistory for MockHistory {
+        async fn signatures(
+            &self,
+            _address: &Pubkey,
+            before: Option<Signature>,
+            limit: usize,
+        ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, LiquidationError> {
+            self.requests.lock().unwrap().push(before);
+            let start = match before {
+                Some(before) => {
+                    let before = before.to_string();
+                    self.signatures.iter().position(|status| status.signature == before).unwrap() + 1
+                }
+                None => 0,
+            };
+            Ok(self.signatures.iter().skip(start).take(limit).cloned().collect())
+        }
+
+        async fn transaction(
+            &self,
+            signature: &Signature,
+        ) -> Result<EncodedConfirmedTransactionWithStatusMeta, LiquidationError> {
+            let signature = signature.to_string();
+            let status = self.signatures.iter().find(|status| status.signature == signature).unwrap();
+            if *self.fail_at_slot.lock().unwrap() == Some(status.slot) {
+                return Err(LiquidationError::RpcUnavailable("connection reset".to_string()));
+            }
+            let mut transaction = fixture();
+            transaction.slot = status.slot;
+            Ok(transaction)
+        }
+    }
+
+    #[test]
+    fn test_liquidation_events_from_transaction() {
+        let (events, missing) = liquidation_events(&fixture(), SIGNATURE, &source());
+        assert_eq!(missing, 0);
+        assert_eq!(events.len(), 2);
+
+        let first = &events[0];
+        assert_eq!(first.position.to_string(), FIRST_POSITION);
+        assert_eq!(first.liquidator.to_string(), LIQUIDATOR);
+        assert_eq!(first.signature, SIGNATURE);
+        assert_eq!((first.amount, first.timestamp), (55_000_000.0, 1_760_180_000));
+        assert!((first.liquidation_price - 2_500_000_000.0 / 55_000_000.0).abs() < 1e-9);
+        assert_eq!(first.reward, 250_000_000.0);
+        assert_eq!(first.metadata["remaining_debt"], "7500000000");
+        assert_eq!(first.symbol, "BTC/USD");
+        assert!(!first.dry_run && first.error.is_none());
+
+        let second = &events[1];
+        assert_eq!(second.position.to_string(), SECOND_POSITION);
+        assert_eq!((second.amount, second.reward), (20_000.0, 100_000.0));
+        assert_eq!(second.metadata["remaining_debt"], "0");
+
+        // Another deployment's transactions hold none of its liquidations
+        let other = BackfillSource {
+            program_id: Pubkey::new_unique(),
+            ..source()
+        };
+        let (events, missing) = liquidation_events(&fixture(), SIGNATURE, &other);
+        assert!(events.is_empty() && missing == 0);
+    }
+
+    #[test]
+    fn test_liquidations_without_events_counted() {
+        // The node truncated the logs before the second liquidation's event
+        let mut transaction = fixture();
+        let meta = transaction.transaction.meta.as_mut().unwrap();
+        let OptionSerializer::Some(logs) = &mut meta.log_messages else {
+            panic!("fixture has logs");
+        };
+        let truncated = logs.iter().rposition(|line| line.starts_with("Program data: ")).unwrap();
+        logs.truncate(truncated);
+        logs.push("Log truncated".to_string());
+        let (events, missing) = liquidation_events(&transaction, SIGNATURE, &source());
+        assert_eq!((events.len(), missing), (1, 1));
+
+        // A failed transaction liquidated nothing
+        let mut failed = fixture();
+        failed.transaction.meta.as_mut().unwrap().err = Some(solana_sdk::transaction::TransactionError::AccountInUse);
+        let (events, missing) = liquidation_events(&failed, SIGNATURE, &source());
+        assert!(events.is_empty() && missing == 0);
+    }
+
+    #[test]
+    fn test_range() {
+        let status = |slot, block_time| RpcConfirmedTransactionStatusWithSignature {
+            signature: String::new(),
+            slot,
+            err: None,
+            memo: None,
+            block_time,
+            confirmation_status: None,
+        };
+        let range = BackfillRange {
+            start: Some(BackfillBound::Slot(100)),
+            end: Some(BackfillBound::Timestamp(2_000)),
+        };
+        assert!(range.is_before_start(&status(99, Some(1_000))));
+        assert!(!range.is_before_start(&status(100, Some(1_000))));
+        assert!(range.is_after_end(&status(150, Some(2_000))));
+        assert!(!range.is_after_end(&status(150, Some(1_999))));
+        // A transaction without a block time can't be placed by time
+        assert!(!range.is_after_end(&status(150, None)));
+        assert!(!BackfillRange::default().is_before_start(&status(0, None)));
+
+        assert_eq!(range.cursor_key(&PROGRAM_ID), format!("{} from slot 100 to time 2000", PROGRAM_ID));
+        assert_ne!(BackfillRange::default().cursor_key(&PROGRAM_ID), range.cursor_key(&PROGRAM_ID));
+    }
+
+    #[tokio::test]
+    async fn test_backfill_pages_through_range() {
+        let store = LiquidationStore::open_in_memory().unwrap();
+        let history = MockHistory::new(SIGNATURES_PAGE_LIMIT as u64 + 10);
+        // Slots 10,000 down to 9,991 land after the range, which starts on the
+        // second page
+        let range = BackfillRange {
+            start: Some(BackfillBound::Slot(8_995)),
+            end: Some(BackfillBound::Slot(9_991)),
+        };
+        let stats = backfill(&history, &store, &source(), &range).await.unwrap();
+        assert_eq!(stats.transactions, 996);
+        assert_eq!(stats.recorded, 2 * 996);
+        // Listing stops at the first transaction before the range
+        assert_eq!(stats.signatures, 1_007);
+        assert_eq!(history.requests.lock().unwrap().len(), 2);
+        assert_eq!(store.backfill_cursor(&range.cursor_key(&PROGRAM_ID)).unwrap(), None);
+
+        // Running it again records nothing twice
+        let stats = backfill(&history, &store, &source(), &range).await.unwrap();
+        assert_eq!((stats.resumed_after, stats.recorded, stats.duplicates), (None, 0, 2 * 996));
+    }
+
+    #[tokio::test]
+    async fn test_backfill_resumes_after_interruption() {
+        let store = LiquidationStore::open_in_memory().unwrap();
+        let history = MockHistory::new(20);
+        let range = BackfillRange::default();
+        *history.fail_at_slot.lock().unwrap() = Some(9_990);
+        assert!(backfill(&history, &store, &source(), &range).await.is_err());
+
+        // Slots 10,000 down to 9,991 were recorded before the failure
+        let cursor = store.backfill_cursor(&range.cursor_key(&PROGRAM_ID)).unwrap().unwrap();
+        assert_eq!(cursor.slot, 9_991);
+        assert_eq!(store.events_between(0, i64::MAX).unwrap().len(), 20);
+
+        *history.fail_at_slot.lock().unwrap() = None;
+        history.requests.lock().unwrap().clear();
+        let stats = backfill(&history, &store, &source(), &range).await.unwrap();
+        assert_eq!(stats.resumed_after, Some(cursor.signature.clone()));
+        assert_eq!(history.requests.lock().unwrap()[0], Some(parse_signature(&cursor.signature).unwrap()));
+        assert_eq!((stats.transactions, stats.recorded, stats.duplicates), (10, 20, 0));
+        assert_eq!(store.events_between(0, i64::MAX).unwrap().len(), 40);
+        assert_eq!(store.backfill_cursor(&range.cursor_key(&PROGRAM_ID)).unwrap(), None);
+    }
+}
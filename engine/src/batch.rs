@@ -0,0 +1,315 @@
+use crate::compute::MAX_COMPUTE_UNIT_LIMIT;
+use crate::instruction::{self, LiquidateAccounts};
+use solana_sdk::{
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    transaction::TransactionError,
+};
+
+/// A liquidation ready to be packed into a transaction alongside others
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BatchEntry {
+    /// The position being liquidated
+    pub position: Pubkey,
+    /// The program's `liquidate` instruction for it
+    pub instruction: Instruction,
+    /// Accounts no other liquidation in the same transaction may use
+    pub exclusive: Vec<Pubkey>,
+    /// Compute units the liquidation needs
+    pub compute_units: u32,
+}
+
+impl BatchEntry {
+    /// Liquidation repaying `repay_amount` of a position's debt
+    pub fn new(accounts: &LiquidateAccounts, repay_amount: u64, compute_units: u32) -> Self {
+        Self::from_instruction(
+            accounts.position,
+            instruction::liquidate_instruction(accounts, repay_amount),
+            compute_units,
+        )
+    }
+
+    /// Liquidation of `position` by its `liquidate` instruction
+    ///
+    /// Only the position is exclusive, so a transaction never liquidates it
+    /// twice. The market's vaults are shared by all its positions and, like
+    /// any writable account, only lock the transaction's accounts as a whole.
+    pub fn from_instruction(position: Pubkey, instruction: Instruction, compute_units: u32) -> Self {
+        Self {
+            position,
+            instruction,
+            exclusive: vec![position],
+            compute_units,
+        }
+    }
+}
+
+/// Liquidations packed into one transaction, in the order they run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TransactionBatch {
+    pub entries: Vec<BatchEntry>,
+}
+
+impl TransactionBatch {
+    /// Compute units the batch's liquidations need together
+    pub fn compute_units(&self) -> u32 {
+        self.entries
+            .iter()
+            .fold(0u32, |total, entry| total.saturating_add(entry.compute_units))
+    }
+
+    /// Positions liquidated by the batch, in order
+    pub fn positions(&self) -> Vec<Pubkey> {
+        self.entries.iter().map(|entry| entry.position).collect()
+    }
+
+    /// Instructions of the batch's transaction: `prefix` followed by every liquidation
+    pub fn instructions(&self, prefix: &[Instruction]) -> Vec<Instruction> {
+        prefix
+            .iter()
+            .cloned()
+            .chain(self.entries.iter().map(|entry| entry.instruction.clone()))
+            .collect()
+    }
+
+    /// Whether `entry` uses an account exclusive to a liquidation in the batch,
+    /// or the other way around
+    fn conflicts(&self, entry: &BatchEntry) -> bool {
+        self.entries.iter().any(|packed| {
+            packed.exclusive.iter().any(|account| uses(&entry.instruction, account))
+                || entry.exclusive.iter().any(|account| uses(&packed.instruction, account))
+        })
+    }
+
+    /// Index of the liquidation whose instruction failed the batch's transaction
+    /// with `err`, when `prefix_len` instructions ran before the batch's
+    ///
+    /// `None` if the transaction failed as a whole or in one of the prefix's
+    /// instructions.
+    pub fn failed_entry(&self, err: &TransactionError, prefix_len: usize) -> Option<usize> {
+        match err {
+            TransactionError::InstructionError(index, _) => usize::from(*index)
+                .checked_sub(prefix_len)
+                .filter(|index| *index < self.entries.len()),
+            _ => None,
+        }
+    }
+
+    /// Split the batch after its transaction failed with `err`: the liquidation
+    /// that failed it, if one did, and the rest to submit on their own
+    ///
+    /// The failed liquidation's error is reported with the index it has in a
+    /// transaction of its own, so it maps the same as an individual failure.
+    pub fn split_failure(
+        self,
+        err: &TransactionError,
+        prefix_len: usize,
+    ) -> (Option<(BatchEntry, TransactionError)>, Vec<BatchEntry>) {
+        let Some(failed) = self.failed_entry(err, prefix_len) else {
+            return (None, self.entries);
+        };
+        let mut rest = self.entries;
+        let entry = rest.remove(failed);
+        let err = match err {
+            TransactionError::InstructionError(_, inner) => {
+                TransactionError::InstructionError(instruction_index(prefix_len), inner.clone())
+            }
+            err => err.clone(),
+        };
+        (Some((entry, err)), rest)
+    }
+}
+
+/// Index of the first liquidation in a transaction with `prefix_len` instructions before it
+fn instruction_index(prefix_len: usize) -> u8 {
+    u8::try_from(prefix_len).unwrap_or(u8::MAX)
+}
+
+/// Whether `instruction` reads or writes `account`
+fn uses(instruction: &Instruction, account: &Pubkey) -> bool {
+    instruction.accounts.iter().any(|meta| meta.pubkey == *account)
+}
+
+/// Size of a transaction made of `instructions` and paid for by `payer` once
+/// signed (in bytes)
+pub(crate) fn transaction_size(instructions: &[Instruction], payer: &Pubkey) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    // Signatures are preceded by their count, a single byte below 128
+    1 + 64 * usize::from(message.header.num_required_signatures) + message.serialize().len()
+}
+
+/// Pack liquidations into as few transactions as they fit
+///
+/// Each liquidation joins the first batch that has fewer than
+/// `max_instructions` liquidations, shares none of its exclusive accounts,
+/// stays within the compute limit, and, with `prefix` run before it, still fits
+/// a packet; otherwise it starts a new batch. A liquidation too large to fit a
+/// packet even on its own is left alone in its batch, to fail on submission.
+pub(crate) fn pack(
+    entries: Vec<BatchEntry>,
+    prefix: &[Instruction],
+    payer: &Pubkey,
+    max_instructions: usize,
+) -> Vec<TransactionBatch> {
+    let mut batches: Vec<TransactionBatch> = Vec::new();
+    for entry in entries {
+        let fits = |batch: &TransactionBatch| {
+            if batch.entries.len() >= max_instructions
+                || batch.compute_units().saturating_add(entry.compute_units) > MAX_COMPUTE_UNIT_LIMIT
+                || batch.conflicts(&entry)
+            {
+                return false;
+            }
+            let mut instructions = batch.instructions(prefix);
+            instructions.push(entry.instruction.clone());
+            transaction_size(&instructions, payer) <= PACKET_DATA_SIZE
+        };
+        match batches.iter().position(fits) {
+            Some(index) => batches[index].entries.push(entry),
+            None => batches.push(TransactionBatch { entries: vec![entry] }),
+        }
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute;
+    use solana_sdk::{instruction::InstructionError, transaction::Transaction};
+
+    fn create_entry() -> BatchEntry {
+        let liquidator = Pubkey::new_unique();
+        let accounts = LiquidateAccounts {
+            position: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+            debt_vault: Pubkey::new_unique(),
+            liquidator_token_account: Pubkey::new_unique(),
+            liquidator_collateral_account: Pubkey::new_unique(),
+            insurance_fund_vault: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            debt_mint: Pubkey::new_unique(),
+            oracle: Pubkey::new_unique(),
+            collateral_token_program: anchor_spl::token::ID,
+            debt_token_program: anchor_spl::token::ID,
+            liquidator,
+            market_id: 0,
+        };
+        BatchEntry::new(&accounts, 1_000, 50_000)
+    }
+
+    /// Entries of one market, sharing everything but their position, as the
+    /// market's single vault of each mint has them
+    fn create_market_entries(count: usize) -> Vec<BatchEntry> {
+        let template = create_entry();
+        (0..count)
+            .map(|_| {
+                let mut entry = template.clone();
+                let position = Pubkey::new_unique();
+                entry.instruction.accounts[0].pubkey = position;
+                entry.position = position;
+                entry.exclusive = vec![position];
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pack_respects_packet_size() {
+        let payer = Pubkey::new_unique();
+        let prefix = compute::budget_instructions(1_400_000, 1_000);
+        let entries = create_market_entries(20);
+
+        let batches = pack(entries.clone(), &prefix, &payer, usize::MAX);
+        assert!(batches.len() > 1, "20 liquidations can't share a packet");
+        assert!(batches[0].entries.len() > 1);
+        for batch in &batches {
+            assert!(transaction_size(&batch.instructions(&prefix), &payer) <= PACKET_DATA_SIZE);
+        }
+        // Every liquidation is packed once, in order
+        let packed: Vec<Pubkey> = batches.iter().flat_map(TransactionBatch::positions).collect();
+        let positions: Vec<Pubkey> = entries.iter().map(|entry| entry.position).collect();
+        assert_eq!(packed, positions);
+
+        // The instruction cap binds before the packet does
+        let batches = pack(entries, &prefix, &payer, 2);
+        assert_eq!(batches.len(), 10);
+    }
+
+    #[test]
+    fn test_transaction_size_matches_serialized_transaction() {
+        let payer = Pubkey::new_unique();
+        let instructions = create_market_entries(3)
+            .into_iter()
+            .map(|entry| entry.instruction)
+            .collect::<Vec<_>>();
+        // Unsigned transactions hold placeholders for every required signature
+        let transaction = Transaction::new_unsigned(Message::new(&instructions, Some(&payer)));
+        assert_eq!(
+            transaction_size(&instructions, &payer),
+            bincode::serialize(&transaction).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_pack_shares_market_vaults() {
+        let payer = Pubkey::new_unique();
+        let entries = create_market_entries(3);
+        assert_eq!(entries[0].exclusive, vec![entries[0].position]);
+        assert!(entries.iter().all(|entry| entry.instruction.accounts[1] == entries[0].instruction.accounts[1]));
+
+        // Liquidations drawing on the market's one vault share a transaction
+        let batches = pack(entries.clone(), &[], &payer, 8);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].positions(), entries.iter().map(|entry| entry.position).collect::<Vec<_>>());
+
+        // The same position is never liquidated twice in one transaction
+        let twice = vec![entries[0].clone(), entries[1].clone(), entries[0].clone()];
+        let batches = pack(twice, &[], &payer, 8);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].positions(), vec![entries[0].position, entries[1].position]);
+        assert_eq!(batches[1].positions(), vec![entries[0].position]);
+    }
+
+    #[test]
+    fn test_pack_respects_compute_limit() {
+        let payer = Pubkey::new_unique();
+        let mut entries = create_market_entries(3);
+        for entry in &mut entries {
+            entry.compute_units = 600_000;
+        }
+        let batches = pack(entries, &[], &payer, 8);
+        assert_eq!(batches.iter().map(|batch| batch.entries.len()).collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_split_failure_isolates_failed_instruction() {
+        let batch = TransactionBatch {
+            entries: create_market_entries(3),
+        };
+        let positions = batch.positions();
+        // Three prefix instructions run before the batch's
+        let err = TransactionError::InstructionError(4, InstructionError::Custom(6002));
+        assert_eq!(batch.failed_entry(&err, 3), Some(1));
+
+        let (failed, rest) = batch.clone().split_failure(&err, 3);
+        let (entry, err) = failed.unwrap();
+        assert_eq!(entry.position, positions[1]);
+        assert_eq!(err, TransactionError::InstructionError(3, InstructionError::Custom(6002)));
+        assert_eq!(
+            rest.iter().map(|entry| entry.position).collect::<Vec<_>>(),
+            vec![positions[0], positions[2]]
+        );
+
+        // Failures outside the batch's instructions blame none of them
+        let err = TransactionError::InstructionError(0, InstructionError::InvalidAccountData);
+        let (failed, rest) = batch.clone().split_failure(&err, 3);
+        assert!(failed.is_none());
+        assert_eq!(rest.len(), 3);
+        let (failed, rest) = batch.split_failure(&TransactionError::AccountInUse, 3);
+        assert!(failed.is_none());
+        assert_eq!(rest.len(), 3);
+    }
+}
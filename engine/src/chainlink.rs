@@ -0,0 +1,298 @@
+use crate::address::FeedAddress;
+use crate::amount::Amount;
+use crate::error::LiquidationError;
+use crate::oracle::{OracleConfig, OracleProvider, PriceData, check_staleness};
+use crate::rate_limit::RateLimiter;
+use crate::rpc_pool::RpcPool;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Anchor discriminator of the store program's `Transmissions` feed accounts
+pub(crate) const TRANSMISSIONS_DISCRIMINATOR: [u8; 8] = [96, 179, 69, 66, 128, 129, 73, 117];
+
+/// Length of a feed account's header, discriminator included (in bytes); the
+/// ring buffer of live rounds follows it
+const HEADER_LEN: usize = 8 + 192;
+
+/// Length of one round in the ring buffer (in bytes)
+const TRANSMISSION_LEN: usize = 48;
+
+/// Offsets of the header fields read, from the start of the account
+const DECIMALS_OFFSET: usize = 138;
+const FLAGGING_THRESHOLD_OFFSET: usize = 139;
+const LATEST_ROUND_ID_OFFSET: usize = 143;
+const LIVE_LENGTH_OFFSET: usize = 148;
+const LIVE_CURSOR_OFFSET: usize = 152;
+
+/// Flagging thresholds are fractions of the previous answer, in units of 1/100000
+const THRESHOLD_MULTIPLIER: f64 = 100_000.0;
+
+/// Latest round of a Chainlink feed, with the header fields needed to price it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChainlinkRound {
+    /// Identifier of the round
+    pub round_id: u32,
+    /// Slot the round was written in
+    pub slot: u64,
+    /// Unix timestamp of the observations the answer aggregates
+    pub observations_timestamp: u32,
+    /// The answer, scaled by `10^decimals`
+    pub answer: i128,
+    /// Decimals of the answer
+    pub decimals: u8,
+    /// Deviation from the previous answer beyond which the feed flags a round
+    /// (in units of 1/100000, zero when the feed sets none)
+    pub flagging_threshold: u32,
+}
+
+impl ChainlinkRound {
+    /// The answer as a price
+    ///
+    /// Exact in fixed point, which rejects answers past its range or decimals
+    /// past its 28 places.
+    pub fn price(&self) -> Result<Amount, LiquidationError> {
+        #[cfg(feature = "decimal")]
+        {
+            rust_decimal::Decimal::try_from_i128_with_scale(self.answer, u32::from(self.decimals))
+                .map_err(|e| LiquidationError::OracleError(format!("Chainlink answer out of range: {}", e)))
+        }
+        #[cfg(not(feature = "decimal"))]
+        {
+            Ok(self.answer as f64 / 10f64.powi(i32::from(self.decimals)))
+        }
+    }
+
+    /// Width of the band the feed lets the answer deviate in unflagged, used
+    /// as the price's confidence interval; zero when the feed sets no threshold
+    pub fn confidence(&self) -> f64 {
+        let price = self.answer as f64 / 10f64.powi(i32::from(self.decimals));
+        price.abs() * f64::from(self.flagging_threshold) / THRESHOLD_MULTIPLIER
+    }
+
+    /// The round as price data at `now`, rejecting answers observed more than
+    /// `max_age_secs` before it
+    pub fn price_data(&self, symbol: &str, now: i64, max_age_secs: u64) -> Result<PriceData, LiquidationError> {
+        check_staleness(symbol, i64::from(self.observations_timestamp), now, max_age_secs)?;
+        self.quote()
+    }
+
+    /// The round as price data, whatever its age
+    ///
+    /// Chainlink feeds have no EMA, so it mirrors the price.
+    pub fn quote(&self) -> Result<PriceData, LiquidationError> {
+        let mut data = PriceData::from_price(self.price()?, i64::from(self.observations_timestamp));
+        data.confidence = self.confidence();
+        data.ema_confidence = data.confidence;
+        data.publish_slot = Some(self.slot);
+        Ok(data)
+    }
+}
+
+/// Decode the latest round of a store program feed account, checking its
+/// length and discriminator
+///
+/// Feeds that haven't been written a round yet fail with an oracle error.
+pub fn decode_feed_account(data: &[u8]) -> Result<ChainlinkRound, LiquidationError> {
+    if data.len() < HEADER_LEN {
+        return Err(LiquidationError::AccountTooShort {
+            expected: HEADER_LEN,
+            actual: data.len(),
+        });
+    }
+    let mut actual = [0; 8];
+    actual.copy_from_slice(&data[..8]);
+    if actual != TRANSMISSIONS_DISCRIMINATOR {
+        return Err(LiquidationError::AccountDiscriminatorMismatch {
+            expected: TRANSMISSIONS_DISCRIMINATOR,
+            actual,
+        });
+    }
+    let latest_round_id = read_u32(data, LATEST_ROUND_ID_OFFSET);
+    let live_length = read_u32(data, LIVE_LENGTH_OFFSET) as usize;
+    if latest_round_id == 0 || live_length == 0 {
+        return Err(LiquidationError::OracleError("Chainlink feed has no rounds".to_string()));
+    }
+    let expected = HEADER_LEN + live_length * TRANSMISSION_LEN;
+    if data.len() < expected {
+        return Err(LiquidationError::AccountTooShort {
+            expected,
+            actual: data.len(),
+        });
+    }
+    // The cursor points at the slot the next round is written to
+    let cursor = read_u32(data, LIVE_CURSOR_OFFSET) as usize % live_length;
+    let offset = HEADER_LEN + (cursor + live_length - 1) % live_length * TRANSMISSION_LEN;
+    let transmission = &data[offset..offset + TRANSMISSION_LEN];
+    let mut answer = [0; 16];
+    answer.copy_from_slice(&transmission[16..32]);
+    Ok(ChainlinkRound {
+        round_id: latest_round_id,
+        slot: u64::from_le_bytes(transmission[..8].try_into().expect("slice is 8 bytes")),
+        observations_timestamp: read_u32(transmission, 8),
+        answer: i128::from_le_bytes(answer),
+        decimals: data[DECIMALS_OFFSET],
+        flagging_threshold: read_u32(data, FLAGGING_THRESHOLD_OFFSET),
+    })
+}
+
+/// Little-endian `u32` at `offset` of data known to be long enough
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().expect("slice is 4 bytes"))
+}
+
+/// Chainlink oracle reading OCR2 feed accounts of the store program
+#[derive(Debug, Clone)]
+pub struct ChainlinkOracle {
+    /// RPC endpoints for Solana
+    rpc: RpcPool,
+    /// Paces requests to the RPC endpoint
+    rate_limiter: Arc<RateLimiter>,
+    /// Feed account of each symbol
+    feed_accounts: Arc<RwLock<HashMap<String, FeedAddress>>>,
+    /// Price feed configuration; only the maximum price age applies
+    config: OracleConfig,
+}
+
+impl ChainlinkOracle {
+    /// Create a new ChainlinkOracle instance
+    ///
+    /// `rpc` is a URL or an [`RpcPool`]; pass the engine's pool and rate limiter
+    /// when both use the same RPC endpoints.
+    pub fn new(
+        rpc: impl Into<RpcPool>,
+        feed_accounts: HashMap<String, FeedAddress>,
+        config: Option<OracleConfig>,
+        rate_limiter: Arc<RateLimiter>,
+    ) -> Self {
+        Self {
+            rpc: rpc.into(),
+            rate_limiter,
+            feed_accounts: Arc::new(RwLock::new(feed_accounts)),
+            config: config.unwrap_or_default(),
+        }
+    }
+
+    /// Add or update a feed account
+    pub async fn add_feed_account(&self, symbol: &str, feed: FeedAddress) {
+        let mut accounts = self.feed_accounts.write().await;
+        accounts.insert(symbol.to_string(), feed);
+    }
+
+    /// Get the feed account for a symbol
+    pub async fn get_feed_account(&self, symbol: &str) -> Option<FeedAddress> {
+        let accounts = self.feed_accounts.read().await;
+        accounts.get(symbol).copied()
+    }
+
+    /// Fetch and decode the latest round of a symbol's feed
+    async fn load_round(&self, symbol: &str) -> Result<ChainlinkRound, LiquidationError> {
+        let feed_account = self
+            .get_feed_account(symbol)
+            .await
+            .ok_or_else(|| LiquidationError::OracleError(format!("No Chainlink feed for {}", symbol)))?;
+        let account_data = self
+            .rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client
+                    .get_account_data(feed_account.as_ref())
+                    .map_err(LiquidationError::from)
+            })
+            .await?;
+        decode_feed_account(&account_data)
+    }
+}
+
+#[async_trait]
+impl OracleProvider for ChainlinkOracle {
+    async fn get_price(&self, symbol: &str) -> Result<Amount, LiquidationError> {
+        Ok(self.get_price_data(symbol).await?.price)
+    }
+
+    async fn get_price_data(&self, symbol: &str) -> Result<PriceData, LiquidationError> {
+        self.load_round(symbol)
+            .await?
+            .price_data(symbol, chrono::Utc::now().timestamp(), self.config.max_price_age_secs)
+    }
+
+    async fn last_update_time(&self, symbol: &str) -> Result<u64, LiquidationError> {
+        Ok(u64::from(self.load_round(symbol).await?.observations_timestamp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::AmountExt;
+
+    /// SOL/USD feed at 8 decimals with a 1% flagging threshold, whose three
+    /// slot ring buffer has wrapped around: the latest round sits in its last slot
+    const FEED: &[u8] = include_bytes!("../fixtures/accounts/chainlink_feed.bin");
+
+    /// Observations timestamp of the fixture's latest round
+    const OBSERVED_AT: i64 = 1_760_000_000;
+
+    #[test]
+    fn test_decode_feed_fixture() {
+        let round = decode_feed_account(FEED).unwrap();
+        assert_eq!(
+            round,
+            ChainlinkRound {
+                round_id: 1_234,
+                slot: 370_000_002,
+                observations_timestamp: OBSERVED_AT as u32,
+                answer: 14_523_456_789,
+                decimals: 8,
+                flagging_threshold: 1_000,
+            }
+        );
+        let price = round.price().unwrap();
+        assert!((price.as_f64() - 145.23456789).abs() < 1e-9);
+        assert!((round.confidence() - 1.4523456789).abs() < 1e-9);
+
+        let data = round.price_data("SOL/USD", OBSERVED_AT + 10, 30).unwrap();
+        assert_eq!((data.price, data.ema_price, data.publish_time), (price, price.as_f64(), OBSERVED_AT));
+        assert_eq!(data.confidence, round.confidence());
+
+        // Feeds without a threshold report no confidence interval
+        let round = ChainlinkRound { flagging_threshold: 0, ..round };
+        assert_eq!(round.price_data("SOL/USD", OBSERVED_AT, 30).unwrap().confidence, 0.0);
+    }
+
+    #[test]
+    fn test_stale_rounds_rejected() {
+        let round = decode_feed_account(FEED).unwrap();
+        assert!(round.price_data("SOL/USD", OBSERVED_AT + 30, 30).is_ok());
+        match round.price_data("SOL/USD", OBSERVED_AT + 31, 30) {
+            Err(LiquidationError::StalePrice { symbol, age_secs, max_age_secs }) => {
+                assert_eq!((symbol.as_str(), age_secs, max_age_secs), ("SOL/USD", 31, 30));
+            }
+            other => panic!("expected a stale price, got {:?}", other),
+        }
+        // Clocks running behind the feed don't make its rounds stale
+        assert!(round.price_data("SOL/USD", OBSERVED_AT - 5, 0).is_ok());
+    }
+
+    #[test]
+    fn test_decode_rejects_other_accounts() {
+        assert!(matches!(
+            decode_feed_account(&FEED[..HEADER_LEN - 1]),
+            Err(LiquidationError::AccountTooShort { expected: HEADER_LEN, .. })
+        ));
+        // The ring buffer must fit the account
+        assert!(matches!(
+            decode_feed_account(&FEED[..FEED.len() - 1]),
+            Err(LiquidationError::AccountTooShort { .. })
+        ));
+        let mut data = FEED.to_vec();
+        data[0] ^= 1;
+        assert!(matches!(
+            decode_feed_account(&data),
+            Err(LiquidationError::AccountDiscriminatorMismatch { .. })
+        ));
+        let mut data = FEED.to_vec();
+        data[LATEST_ROUND_ID_OFFSET..LATEST_ROUND_ID_OFFSET + 4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(decode_feed_account(&data), Err(LiquidationError::OracleError(_))));
+    }
+}
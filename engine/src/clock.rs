@@ -0,0 +1,84 @@
+use crate::error::LiquidationError;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// Source of the time the engine judges cooldowns, funding and bad debt windows by
+///
+/// Engines read the [`SystemClock`] unless given another, such as a [`ManualClock`]
+/// that only moves when told to, so cooldowns can be tested and recorded prices
+/// replayed without waiting on real time.
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    /// Current Unix timestamp (in seconds)
+    fn now_ts(&self) -> i64;
+
+    /// Move the clock to `now`, for clocks that can be moved
+    fn set_ts(&self, now: i64) -> Result<(), LiquidationError> {
+        let _ = now;
+        Err(LiquidationError::ConfigError(format!("{:?} can't be set", self)))
+    }
+}
+
+/// The system's wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ts(&self) -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+}
+
+/// A clock that stands still until set or advanced, shared by all its clones
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicI64>,
+}
+
+impl ManualClock {
+    /// A clock reading `now`
+    pub fn new(now: i64) -> Self {
+        Self {
+            now: Arc::new(AtomicI64::new(now)),
+        }
+    }
+
+    /// Move the clock to `now`
+    pub fn set(&self, now: i64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    /// Move the clock forward by `secs`
+    pub fn advance(&self, secs: i64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_ts(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+
+    fn set_ts(&self, now: i64) -> Result<(), LiquidationError> {
+        self.set(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_shared_between_clones() {
+        let clock = ManualClock::new(1_700_000_000);
+        let reader: Arc<dyn Clock> = Arc::new(clock.clone());
+        assert_eq!(reader.now_ts(), 1_700_000_000);
+        clock.advance(60);
+        assert_eq!(reader.now_ts(), 1_700_000_060);
+        reader.set_ts(1_700_000_000).unwrap();
+        assert_eq!(clock.now_ts(), 1_700_000_000);
+
+        assert!(SystemClock.set_ts(0).is_err());
+        assert!(SystemClock.now_ts() > 1_700_000_000);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::error::LiquidationError;
+use crate::rate_limit::RateLimiter;
+use crate::rpc_pool::RpcPool;
+use async_trait::async_trait;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction, pubkey::Pubkey, transaction::Transaction,
+};
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(any(test, feature = "testing"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Most compute units a transaction may request
+pub(crate) const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Compute unit limit leaving `headroom_percent` on top of what a simulation consumed
+///
+/// Rounds up and never exceeds `MAX_COMPUTE_UNIT_LIMIT`.
+pub(crate) fn with_headroom(units_consumed: u64, headroom_percent: u16) -> u32 {
+    let limit = units_consumed
+        .saturating_mul(100 + headroom_percent as u64)
+        .div_ceil(100);
+    limit.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+}
+
+/// Instructions setting a transaction's compute unit limit and priority fee
+/// (in microlamports per compute unit)
+pub(crate) fn budget_instructions(compute_unit_limit: u32, priority_fee_micro_lamports: u64) -> Vec<Instruction> {
+    vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit),
+        ComputeBudgetInstruction::set_compute_unit_price(priority_fee_micro_lamports),
+    ]
+}
+
+/// Simulates transactions to measure their compute usage
+#[async_trait]
+pub trait TransactionSimulator: Send + Sync + fmt::Debug {
+    /// Simulate a transaction made of `instructions` and paid for by `payer`,
+    /// returning the compute units it consumed
+    async fn simulate_units(&self, instructions: &[Instruction], payer: &Pubkey) -> Result<u64, LiquidationError>;
+}
+
+/// Simulator using the `simulateTransaction` RPC method
+#[derive(Debug, Clone)]
+pub(crate) struct RpcSimulator {
+    rpc: RpcPool,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl RpcSimulator {
+    /// Create a simulator backed by the given RPC endpoints
+    pub fn new(rpc: RpcPool, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self { rpc, rate_limiter }
+    }
+}
+
+#[async_trait]
+impl TransactionSimulator for RpcSimulator {
+    async fn simulate_units(&self, instructions: &[Instruction], payer: &Pubkey) -> Result<u64, LiquidationError> {
+        let transaction = Transaction::new_with_payer(instructions, Some(payer));
+        let result = self
+            .rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                // The transaction is unsigned, so skip signature checks and let the
+                // node fill in a current blockhash
+                let config = RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    ..Default::default()
+                };
+                rpc_client
+                    .simulate_transaction_with_config(&transaction, config)
+                    .map_err(LiquidationError::from)
+            })
+            .await?
+            .value;
+
+        if let Some(err) = result.err {
+            let logs = result.logs.unwrap_or_default().join("; ");
+            return Err(LiquidationError::SimulationFailed(format!("{} ({})", err, logs)));
+        }
+        result
+            .units_consumed
+            .ok_or_else(|| LiquidationError::SimulationFailed("no compute units reported".to_string()))
+    }
+}
+
+/// Mock simulator for testing
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Clone)]
+pub struct MockSimulator {
+    units: Arc<Mutex<Result<u64, String>>>,
+    simulations: Arc<AtomicUsize>,
+    simulated: Arc<Mutex<Vec<Vec<Instruction>>>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockSimulator {
+    /// Create a mock simulator reporting the given compute usage
+    pub fn new(units_consumed: u64) -> Self {
+        Self {
+            units: Arc::new(Mutex::new(Ok(units_consumed))),
+            simulations: Arc::new(AtomicUsize::new(0)),
+            simulated: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Change the compute usage reported by later simulations
+    pub fn set_units(&self, units_consumed: u64) {
+        *self.units.lock().unwrap_or_else(PoisonError::into_inner) = Ok(units_consumed);
+    }
+
+    /// Make later simulations fail with the given error
+    pub fn set_error(&self, error: &str) {
+        *self.units.lock().unwrap_or_else(PoisonError::into_inner) = Err(error.to_string());
+    }
+
+    /// Number of simulations run so far
+    pub fn simulations(&self) -> usize {
+        self.simulations.load(Ordering::SeqCst)
+    }
+
+    /// Instructions of every simulation run so far, in order
+    pub fn simulated(&self) -> Vec<Vec<Instruction>> {
+        self.simulated.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[async_trait]
+impl TransactionSimulator for MockSimulator {
+    async fn simulate_units(&self, instructions: &[Instruction], _payer: &Pubkey) -> Result<u64, LiquidationError> {
+        self.simulations.fetch_add(1, Ordering::SeqCst);
+        self.simulated
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(instructions.to_vec());
+        self.units
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .map_err(LiquidationError::SimulationFailed)
+    }
+}
+
+/// Cached compute unit limit for one instruction shape
+#[derive(Debug, Clone, Copy)]
+struct CachedLimit {
+    limit: u32,
+    failures: u32,
+}
+
+/// Compute unit limits estimated by simulation, cached per instruction shape
+#[derive(Debug, Default)]
+pub(crate) struct ComputeUnitCache {
+    limits: Mutex<HashMap<String, CachedLimit>>,
+}
+
+impl ComputeUnitCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cached limit for an instruction shape
+    pub fn get(&self, shape: &str) -> Option<u32> {
+        self.limits().get(shape).map(|cached| cached.limit)
+    }
+
+    /// Cache a freshly estimated limit
+    pub fn insert(&self, shape: &str, limit: u32) {
+        self.limits()
+            .insert(shape.to_string(), CachedLimit { limit, failures: 0 });
+    }
+
+    /// Count a transaction that used the cached limit and succeeded
+    pub fn record_success(&self, shape: &str) {
+        if let Some(cached) = self.limits().get_mut(shape) {
+            cached.failures = 0;
+        }
+    }
+
+    /// Count a transaction that used the cached limit and failed
+    ///
+    /// The estimate is dropped after `max_failures` consecutive failures so the
+    /// next transaction is simulated afresh. Returns whether it was dropped.
+    pub fn record_failure(&self, shape: &str, max_failures: u32) -> bool {
+        let mut limits = self.limits();
+        let Some(cached) = limits.get_mut(shape) else {
+            return false;
+        };
+        cached.failures += 1;
+        if cached.failures >= max_failures {
+            limits.remove(shape);
+            return true;
+        }
+        false
+    }
+
+    fn limits(&self) -> std::sync::MutexGuard<'_, HashMap<String, CachedLimit>> {
+        self.limits.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headroom_math() {
+        assert_eq!(with_headroom(100_000, 20), 120_000);
+        assert_eq!(with_headroom(100_000, 0), 100_000);
+        // Rounds up so the headroom is never short
+        assert_eq!(with_headroom(33_333, 10), 36_667);
+        assert_eq!(with_headroom(1_200_000, 50), MAX_COMPUTE_UNIT_LIMIT);
+        assert_eq!(with_headroom(u64::MAX, 20), MAX_COMPUTE_UNIT_LIMIT);
+    }
+
+    #[test]
+    fn test_cache_invalidated_after_consecutive_failures() {
+        let cache = ComputeUnitCache::new();
+        cache.insert("BTC/USD", 120_000);
+        cache.insert("ETH/USD", 90_000);
+
+        assert!(!cache.record_failure("BTC/USD", 3));
+        assert!(!cache.record_failure("BTC/USD", 3));
+        // A success resets the count
+        cache.record_success("BTC/USD");
+        assert!(!cache.record_failure("BTC/USD", 3));
+        assert!(!cache.record_failure("BTC/USD", 3));
+        assert_eq!(cache.get("BTC/USD"), Some(120_000));
+
+        assert!(cache.record_failure("BTC/USD", 3));
+        assert_eq!(cache.get("BTC/USD"), None);
+        assert_eq!(cache.get("ETH/USD"), Some(90_000));
+        assert!(!cache.record_failure("SOL/USD", 3));
+    }
+
+    #[tokio::test]
+    async fn test_mock_simulator() {
+        let simulator = MockSimulator::new(50_000);
+        assert_eq!(simulator.simulate_units(&[], &Pubkey::default()).await.unwrap(), 50_000);
+
+        simulator.set_error("blockhash not found");
+        assert!(matches!(
+            simulator.simulate_units(&[], &Pubkey::default()).await,
+            Err(LiquidationError::SimulationFailed(_))
+        ));
+        assert_eq!(simulator.simulations(), 2);
+    }
+}
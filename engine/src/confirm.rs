@@ -0,0 +1,477 @@
+use crate::address::PositionAddress;
+use crate::error::LiquidationError;
+use crate::rate_limit::RateLimiter;
+use crate::rpc_pool::RpcPool;
+use async_trait::async_trait;
+use solana_sdk::{commitment_config::CommitmentConfig, hash::Hash, signature::Signature, transaction::TransactionError};
+use std::collections::HashMap;
+#[cfg(any(test, feature = "testing"))]
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How often a submitted transaction's status is polled while it's awaited
+pub(crate) const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long an unconfirmed liquidation signed against an unknown blockhash is
+/// awaited before it's treated as dropped (in seconds)
+///
+/// Comfortably longer than a blockhash stays valid, after which the transaction can
+/// no longer land.
+pub(crate) const PENDING_SIGNATURE_EXPIRY_SECS: i64 = 150;
+
+/// Status of a submitted transaction as reported by the cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// Not seen yet, or not yet confirmed by a supermajority
+    Pending,
+    /// Landed successfully at `confirmed` or `finalized` commitment
+    Confirmed,
+    /// Landed at `confirmed` or `finalized` commitment but failed
+    Failed(TransactionError),
+}
+
+/// Trait for looking up the status of submitted transactions
+#[async_trait]
+pub trait StatusPoller: Send + Sync + fmt::Debug {
+    /// Get the status of a transaction by its signature
+    async fn signature_status(&self, signature: &Signature) -> Result<SignatureStatus, LiquidationError>;
+
+    /// Whether transactions signed against `blockhash` can still land
+    async fn is_blockhash_valid(&self, blockhash: &Hash) -> Result<bool, LiquidationError>;
+}
+
+/// Status poller asking the healthiest of a pool of RPC endpoints with
+/// `getSignatureStatuses` and `isBlockhashValid`
+#[derive(Debug, Clone)]
+pub(crate) struct RpcStatusPoller {
+    rpc: RpcPool,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl RpcStatusPoller {
+    /// Create a poller asking the given RPC endpoints
+    pub fn new(rpc: RpcPool, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self { rpc, rate_limiter }
+    }
+}
+
+#[async_trait]
+impl StatusPoller for RpcStatusPoller {
+    async fn signature_status(&self, signature: &Signature) -> Result<SignatureStatus, LiquidationError> {
+        let signature = *signature;
+        let statuses = self
+            .rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client
+                    .get_signature_statuses(&[signature])
+                    .map_err(LiquidationError::from)
+            })
+            .await?;
+        Ok(match statuses.value.into_iter().next().flatten() {
+            Some(status) if status.satisfies_commitment(CommitmentConfig::confirmed()) => match status.err {
+                Some(err) => SignatureStatus::Failed(err),
+                None => SignatureStatus::Confirmed,
+            },
+            _ => SignatureStatus::Pending,
+        })
+    }
+
+    async fn is_blockhash_valid(&self, blockhash: &Hash) -> Result<bool, LiquidationError> {
+        let blockhash = *blockhash;
+        self.rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client
+                    .is_blockhash_valid(&blockhash, CommitmentConfig::processed())
+                    .map_err(LiquidationError::from)
+            })
+            .await
+    }
+}
+
+/// Mock status poller for testing
+///
+/// Reports queued statuses first, then the default status (confirmed unless
+/// set), for any signature.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Clone)]
+pub struct MockStatusPoller {
+    statuses: Arc<Mutex<VecDeque<SignatureStatus>>>,
+    status: Arc<Mutex<SignatureStatus>>,
+    expired: Arc<Mutex<HashSet<Hash>>>,
+    polls: Arc<Mutex<usize>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Default for MockStatusPoller {
+    fn default() -> Self {
+        Self {
+            statuses: Arc::default(),
+            status: Arc::new(Mutex::new(SignatureStatus::Confirmed)),
+            expired: Arc::default(),
+            polls: Arc::default(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockStatusPoller {
+    /// Create a mock poller confirming every transaction
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report `status` once it has reported every status queued before it
+    pub fn push_status(&self, status: SignatureStatus) {
+        self.statuses.lock().unwrap_or_else(PoisonError::into_inner).push_back(status);
+    }
+
+    /// Report `status` whenever no queued status is left
+    pub fn set_status(&self, status: SignatureStatus) {
+        *self.status.lock().unwrap_or_else(PoisonError::into_inner) = status;
+    }
+
+    /// Report `blockhash` as no longer valid
+    pub fn expire_blockhash(&self, blockhash: Hash) {
+        self.expired.lock().unwrap_or_else(PoisonError::into_inner).insert(blockhash);
+    }
+
+    /// Number of signature statuses looked up so far
+    pub fn polls(&self) -> usize {
+        *self.polls.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[async_trait]
+impl StatusPoller for MockStatusPoller {
+    async fn signature_status(&self, _signature: &Signature) -> Result<SignatureStatus, LiquidationError> {
+        *self.polls.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+        let queued = self.statuses.lock().unwrap_or_else(PoisonError::into_inner).pop_front();
+        Ok(queued.unwrap_or_else(|| self.status.lock().unwrap_or_else(PoisonError::into_inner).clone()))
+    }
+
+    async fn is_blockhash_valid(&self, blockhash: &Hash) -> Result<bool, LiquidationError> {
+        Ok(!self.expired.lock().unwrap_or_else(PoisonError::into_inner).contains(blockhash))
+    }
+}
+
+/// A submitted liquidation transaction that hasn't been confirmed yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PendingSignature {
+    pub signature: Signature,
+    /// Blockhash the transaction was signed against, `None` when it can't
+    /// expire by blockhash (durable nonces) or isn't known (after a restart)
+    pub blockhash: Option<Hash>,
+    /// When the transaction was submitted (Unix timestamp)
+    pub submitted_at: i64,
+}
+
+impl PendingSignature {
+    /// Whether the transaction is old enough to have been dropped even though
+    /// its blockhash isn't known to have expired
+    pub fn is_stale(&self, now: i64) -> bool {
+        self.blockhash.is_none() && now.saturating_sub(self.submitted_at) > PENDING_SIGNATURE_EXPIRY_SECS
+    }
+}
+
+/// What became of a pending transaction when it was last polled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Resolution {
+    /// May still land
+    Pending,
+    /// Landed successfully
+    Confirmed,
+    /// Landed but failed
+    Failed(TransactionError),
+    /// Its blockhash expired before it landed, so it never will and it's safe
+    /// to resubmit
+    Expired,
+}
+
+/// Outcomes of awaited liquidation transactions
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ConfirmationStats {
+    /// Transactions awaiting confirmation
+    pub pending: usize,
+    /// Transactions confirmed since the engine started
+    pub confirmed: u64,
+    /// Transactions that landed but failed
+    pub failed: u64,
+    /// Transactions whose blockhash expired before they landed
+    pub expired: u64,
+    /// Times a transaction wasn't confirmed within `confirmation_timeout_secs`,
+    /// leaving it pending
+    pub timed_out: u64,
+    /// Time from submission to confirmation of the last confirmed transaction
+    /// (in milliseconds)
+    pub last_latency_ms: Option<u64>,
+    /// Average time from submission to confirmation (in milliseconds)
+    pub average_latency_ms: Option<f64>,
+    /// Longest time from submission to confirmation (in milliseconds)
+    pub max_latency_ms: Option<u64>,
+}
+
+impl ConfirmationStats {
+    fn record_latency(&mut self, latency: Duration) {
+        let latency_ms = latency.as_millis() as u64;
+        self.confirmed += 1;
+        self.last_latency_ms = Some(latency_ms);
+        self.max_latency_ms = Some(self.max_latency_ms.unwrap_or_default().max(latency_ms));
+        let average = self.average_latency_ms.unwrap_or_default();
+        self.average_latency_ms = Some(average + (latency_ms as f64 - average) / self.confirmed as f64);
+    }
+}
+
+/// Tracks submitted liquidation transactions until they're confirmed, fail
+/// on chain or can no longer land
+///
+/// A position with a pending transaction mustn't be liquidated again, as
+/// both transactions could land.
+#[derive(Debug)]
+pub(crate) struct ConfirmationTracker {
+    poller: Arc<dyn StatusPoller>,
+    pending: Mutex<HashMap<PositionAddress, PendingSignature>>,
+    stats: Mutex<ConfirmationStats>,
+}
+
+impl ConfirmationTracker {
+    /// Create a tracker looking statuses up with `poller`
+    pub fn new(poller: Arc<dyn StatusPoller>) -> Self {
+        Self {
+            poller,
+            pending: Mutex::new(HashMap::new()),
+            stats: Mutex::new(ConfirmationStats::default()),
+        }
+    }
+
+    /// Look statuses up with `poller` instead, keeping the transactions tracked
+    pub fn with_poller(mut self, poller: Arc<dyn StatusPoller>) -> Self {
+        self.poller = poller;
+        self
+    }
+
+    /// Track a transaction submitted to liquidate `position`
+    pub fn track(&self, position: PositionAddress, pending: PendingSignature) {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner).insert(position, pending);
+    }
+
+    /// The transaction pending for a position, if any
+    pub fn pending(&self, position: &PositionAddress) -> Option<PendingSignature> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner).get(position).copied()
+    }
+
+    /// Whether a transaction is pending for a position
+    pub fn is_pending(&self, position: &PositionAddress) -> bool {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner).contains_key(position)
+    }
+
+    /// Poll the status of a pending transaction once
+    ///
+    /// A transaction that hasn't landed is expired once its blockhash is no
+    /// longer valid, or once stale at `now` if its blockhash isn't known.
+    pub async fn resolve(&self, pending: &PendingSignature, now: i64) -> Result<Resolution, LiquidationError> {
+        match self.poller.signature_status(&pending.signature).await? {
+            SignatureStatus::Confirmed => return Ok(Resolution::Confirmed),
+            SignatureStatus::Failed(err) => return Ok(Resolution::Failed(err)),
+            SignatureStatus::Pending => {}
+        }
+        let expired = match &pending.blockhash {
+            Some(blockhash) => !self.poller.is_blockhash_valid(blockhash).await?,
+            None => pending.is_stale(now),
+        };
+        Ok(if expired { Resolution::Expired } else { Resolution::Pending })
+    }
+
+    /// Stop tracking a position's transaction now that it's resolved, counting
+    /// its outcome and, for confirmed ones, the time it took to confirm
+    pub fn finish(&self, position: &PositionAddress, resolution: &Resolution, latency: Duration) {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner).remove(position);
+        let mut stats = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        match resolution {
+            Resolution::Pending => {}
+            Resolution::Confirmed => stats.record_latency(latency),
+            Resolution::Failed(_) => stats.failed += 1,
+            Resolution::Expired => stats.expired += 1,
+        }
+    }
+
+    /// Await confirmation of a transaction submitted to liquidate `position`,
+    /// polling until `timeout` passes, and return how long it took
+    ///
+    /// Fails with the program's error mapped by
+    /// [`LiquidationError::transaction_failed`] if the transaction failed on
+    /// chain, and with [`LiquidationError::BlockhashExpired`] if it can no
+    /// longer land. Either way the transaction stops being tracked. A
+    /// transaction still pending at the timeout stays tracked, as it may yet
+    /// land, and fails with [`LiquidationError::ConfirmationTimeout`].
+    pub async fn await_confirmation(
+        &self,
+        position: PositionAddress,
+        pending: PendingSignature,
+        timeout: Duration,
+    ) -> Result<Duration, LiquidationError> {
+        let (resolution, latency) = self.await_resolution(position, pending, timeout).await?;
+        match resolution {
+            Resolution::Failed(err) => Err(LiquidationError::transaction_failed(position, err)),
+            Resolution::Expired => Err(LiquidationError::BlockhashExpired(pending.signature.to_string())),
+            _ => Ok(latency),
+        }
+    }
+
+    /// Await the resolution of a transaction submitted to liquidate `position`,
+    /// polling until `timeout` passes, and return it with how long it took
+    ///
+    /// Unlike [`Self::await_confirmation`], a failed transaction resolves with
+    /// its error as is, so callers can tell which instruction failed it.
+    pub async fn await_resolution(
+        &self,
+        position: PositionAddress,
+        pending: PendingSignature,
+        timeout: Duration,
+    ) -> Result<(Resolution, Duration), LiquidationError> {
+        self.track(position, pending);
+        let started = Instant::now();
+        let deadline = started + timeout;
+        loop {
+            // Transactions without a known blockhash only time out while awaited
+            match self.resolve(&pending, pending.submitted_at).await {
+                Ok(Resolution::Pending) => {}
+                Ok(resolution) => {
+                    let latency = started.elapsed();
+                    self.finish(&position, &resolution, latency);
+                    if resolution == Resolution::Confirmed {
+                        info!("Transaction {} confirmed in {:?}", pending.signature, latency);
+                    }
+                    return Ok((resolution, latency));
+                }
+                Err(e) => warn!("Unable to check the status of {}: {}", pending.signature, e),
+            }
+            if Instant::now() + CONFIRMATION_POLL_INTERVAL > deadline {
+                break;
+            }
+            tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+        }
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner).timed_out += 1;
+        Err(LiquidationError::ConfirmationTimeout)
+    }
+
+    /// Outcomes of the transactions awaited so far
+    pub fn stats(&self) -> ConfirmationStats {
+        ConfirmationStats {
+            pending: self.pending.lock().unwrap_or_else(PoisonError::into_inner).len(),
+            ..self.stats.lock().unwrap_or_else(PoisonError::into_inner).clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::POSITION_HEALTHY_ERROR;
+    use solana_sdk::instruction::InstructionError;
+
+    fn create_tracker() -> (ConfirmationTracker, MockStatusPoller) {
+        let poller = MockStatusPoller::new();
+        let tracker = ConfirmationTracker::new(Arc::new(poller.clone()));
+        (tracker, poller)
+    }
+
+    fn create_pending() -> PendingSignature {
+        PendingSignature {
+            signature: Signature::new_unique(),
+            blockhash: Some(Hash::new_unique()),
+            submitted_at: 1_700_000_000,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_confirmed_after_pending_polls() {
+        let (tracker, poller) = create_tracker();
+        poller.push_status(SignatureStatus::Pending);
+        poller.push_status(SignatureStatus::Pending);
+        let position = PositionAddress::new_unique();
+
+        let latency = tracker
+            .await_confirmation(position, create_pending(), Duration::from_secs(30))
+            .await
+            .unwrap();
+        assert_eq!(latency, CONFIRMATION_POLL_INTERVAL * 2);
+        assert_eq!(poller.polls(), 3);
+        assert!(!tracker.is_pending(&position));
+        let stats = tracker.stats();
+        assert_eq!((stats.confirmed, stats.pending), (1, 0));
+        assert_eq!(stats.last_latency_ms, Some(1_000));
+        assert_eq!(stats.average_latency_ms, Some(1_000.0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_program_error_mapped() {
+        let (tracker, poller) = create_tracker();
+        let position = PositionAddress::new_unique();
+        let healthy = TransactionError::InstructionError(0, InstructionError::Custom(POSITION_HEALTHY_ERROR));
+        poller.set_status(SignatureStatus::Failed(healthy));
+
+        let result = tracker.await_confirmation(position, create_pending(), Duration::from_secs(30)).await;
+        assert!(matches!(result, Err(LiquidationError::PositionNotLiquidatable(address)) if address == position));
+
+        poller.set_status(SignatureStatus::Failed(TransactionError::InsufficientFundsForFee));
+        let result = tracker.await_confirmation(position, create_pending(), Duration::from_secs(30)).await;
+        assert!(matches!(result, Err(LiquidationError::LiquidationFailed(_))));
+        assert!(!tracker.is_pending(&position));
+        assert_eq!(tracker.stats().failed, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_expired_blockhash_is_safe_to_retry() {
+        let (tracker, poller) = create_tracker();
+        poller.set_status(SignatureStatus::Pending);
+        let position = PositionAddress::new_unique();
+        let pending = create_pending();
+        poller.expire_blockhash(pending.blockhash.unwrap());
+
+        let result = tracker.await_confirmation(position, pending, Duration::from_secs(30)).await;
+        assert!(matches!(&result, Err(LiquidationError::BlockhashExpired(signature)) if *signature == pending.signature.to_string()));
+        assert!(result.unwrap_err().is_retryable());
+        assert!(!tracker.is_pending(&position));
+        assert_eq!(tracker.stats().expired, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout_leaves_signature_pending() {
+        let (tracker, poller) = create_tracker();
+        poller.set_status(SignatureStatus::Pending);
+        let position = PositionAddress::new_unique();
+        let pending = create_pending();
+
+        let started = Instant::now();
+        let result = tracker.await_confirmation(position, pending, Duration::from_secs(5)).await;
+        assert!(matches!(result, Err(LiquidationError::ConfirmationTimeout)));
+        assert!(started.elapsed() <= Duration::from_secs(5));
+        assert_eq!(tracker.pending(&position), Some(pending));
+        assert_eq!((tracker.stats().timed_out, tracker.stats().pending), (1, 1));
+
+        // Once it lands a later poll confirms it
+        poller.set_status(SignatureStatus::Confirmed);
+        assert_eq!(tracker.resolve(&pending, pending.submitted_at + 10).await.unwrap(), Resolution::Confirmed);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_blockhash_expires_with_age() {
+        let (tracker, poller) = create_tracker();
+        poller.set_status(SignatureStatus::Pending);
+        let pending = PendingSignature {
+            blockhash: None,
+            ..create_pending()
+        };
+        let submitted_at = pending.submitted_at;
+
+        assert_eq!(tracker.resolve(&pending, submitted_at + 10).await.unwrap(), Resolution::Pending);
+        assert_eq!(
+            tracker.resolve(&pending, submitted_at + PENDING_SIGNATURE_EXPIRY_SECS + 1).await.unwrap(),
+            Resolution::Expired
+        );
+    }
+}
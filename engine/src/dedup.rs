@@ -0,0 +1,157 @@
+use crate::types::LiquidationEvent;
+use std::collections::HashMap;
+
+/// Signature of the liquidations a dry run simulated rather than submitted
+pub(crate) const DRY_RUN_SIGNATURE: &str = "dry-run";
+
+/// Liquidation events dropped as duplicates, and those remembered to catch them
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DedupStats {
+    /// Events dropped since the engine started because one with the same key
+    /// was already published
+    pub deduped_events: u64,
+    /// Keys remembered within the retention window
+    pub tracked_events: usize,
+}
+
+/// Key a liquidation event is deduplicated by, `None` for failed attempts
+///
+/// Submitted liquidations are keyed by their transaction signature, which both
+/// the submission path and the program's logs report. Dry runs have no
+/// signature, so they're keyed by their position and timestamp.
+pub(crate) fn event_key(event: &LiquidationEvent) -> Option<String> {
+    if event.error.is_some() {
+        return None;
+    }
+    if event.dry_run || event.signature.is_empty() || event.signature == DRY_RUN_SIGNATURE {
+        return Some(format!("{}:{}@{}", DRY_RUN_SIGNATURE, event.position, event.timestamp));
+    }
+    Some(event.signature.clone())
+}
+
+/// Keys of the liquidation events published within the retention window
+///
+/// Each event is published once to subscribers, webhooks and the store,
+/// whichever of the submission path and the program log listener reports it
+/// first; the engine persists the keys in its state file so replays after a
+/// restart are caught too.
+#[derive(Debug, Default)]
+pub(crate) struct EventDedup {
+    retention_secs: u64,
+    seen: HashMap<String, i64>,
+    deduped: u64,
+}
+
+impl EventDedup {
+    /// Remember keys for `retention_secs`
+    pub(crate) fn new(retention_secs: u64) -> Self {
+        Self {
+            retention_secs,
+            ..Self::default()
+        }
+    }
+
+    /// How long keys are remembered (in seconds)
+    pub(crate) fn retention_secs(&self) -> u64 {
+        self.retention_secs
+    }
+
+    /// Remember keys seen before a restart, at the time each was seen
+    pub(crate) fn restore<'a>(&mut self, seen: impl IntoIterator<Item = (&'a str, i64)>) {
+        self.seen.extend(seen.into_iter().map(|(key, seen_at)| (key.to_string(), seen_at)));
+    }
+
+    /// Whether `key` wasn't seen within the retention window, remembering it
+    /// at `now` if so; duplicates are counted
+    pub(crate) fn admit(&mut self, key: &str, now: i64) -> bool {
+        self.prune(now);
+        if self.seen.contains_key(key) {
+            self.deduped += 1;
+            return false;
+        }
+        self.seen.insert(key.to_string(), now);
+        true
+    }
+
+    /// Forget keys seen longer than the retention window before `now`
+    pub(crate) fn prune(&mut self, now: i64) {
+        let cutoff = now.saturating_sub(self.retention_secs as i64);
+        self.seen.retain(|_, seen_at| *seen_at > cutoff);
+    }
+
+    /// Duplicates dropped and keys remembered
+    pub(crate) fn stats(&self) -> DedupStats {
+        DedupStats {
+            deduped_events: self.deduped,
+            tracked_events: self.seen.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::{OwnerAddress, PositionAddress};
+    use solana_sdk::pubkey::Pubkey;
+
+    fn event(signature: &str, dry_run: bool) -> LiquidationEvent {
+        LiquidationEvent {
+            position: PositionAddress::new_unique(),
+            owner: OwnerAddress::new_unique(),
+            liquidator: Pubkey::new_unique(),
+            amount: 0.5,
+            remaining_size: 0.5,
+            remaining_margin: 100.0,
+            liquidation_price: 50000.0,
+            timestamp: 1_000,
+            signature: signature.to_string(),
+            bad_debt: 0.0,
+            symbol: "BTC/USD".to_string(),
+            reward: 10.0,
+            dry_run,
+            error: None,
+            priority_fee_micro_lamports: 0,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_event_keys() {
+        assert_eq!(event_key(&event("5sig", false)).as_deref(), Some("5sig"));
+
+        let dry_run = event(DRY_RUN_SIGNATURE, true);
+        assert_eq!(event_key(&dry_run), Some(format!("dry-run:{}@1000", dry_run.position)));
+
+        let mut failed = event("", false);
+        failed.error = Some("blockhash not found".to_string());
+        assert_eq!(event_key(&failed), None);
+    }
+
+    #[test]
+    fn test_duplicates_dropped_within_retention() {
+        let mut dedup = EventDedup::new(600);
+        assert!(dedup.admit("5sig", 1_000));
+        assert!(!dedup.admit("5sig", 1_100));
+        assert!(dedup.admit("6sig", 1_100));
+        assert_eq!(
+            dedup.stats(),
+            DedupStats {
+                deduped_events: 1,
+                tracked_events: 2
+            }
+        );
+
+        // Once the window has passed, the key is forgotten
+        assert!(dedup.admit("5sig", 1_601));
+        assert_eq!(dedup.stats().tracked_events, 2);
+    }
+
+    #[test]
+    fn test_restored_keys_are_duplicates() {
+        let mut dedup = EventDedup::new(600);
+        dedup.restore([("5sig", 900), ("6sig", 100)]);
+        assert!(!dedup.admit("5sig", 1_000));
+        assert!(dedup.admit("6sig", 1_000));
+        assert_eq!(dedup.stats().deduped_events, 1);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::error::LiquidationError;
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+/// A price level of an order book
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DepthLevel {
+    /// Price of the level
+    pub price: f64,
+    /// Size available at the price (in base currency)
+    pub size: f64,
+}
+
+/// Liquidity a liquidation trades against
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketDepth {
+    /// Order book ladders, best level first
+    Ladder {
+        bids: Vec<DepthLevel>,
+        asks: Vec<DepthLevel>,
+    },
+    /// Linear price impact: trading `liquidity` units moves the price by 100%
+    Constant { liquidity: f64 },
+}
+
+impl MarketDepth {
+    /// Estimated impact of trading `size`, selling into the bids if `sell` and
+    /// buying from the asks otherwise (in basis points)
+    ///
+    /// Ladders measure the average fill price against the best level. Returns
+    /// `None` if there isn't enough depth to fill `size`.
+    pub fn impact_bps(&self, size: f64, sell: bool) -> Option<f64> {
+        match self {
+            Self::Constant { liquidity } => (*liquidity > 0.0).then(|| size / liquidity * 10_000.0),
+            Self::Ladder { .. } => {
+                let levels = self.levels(sell);
+                let best = levels.first()?.price;
+                if size <= 0.0 {
+                    return Some(0.0);
+                }
+                let (mut remaining, mut cost) = (size, 0.0);
+                for level in levels {
+                    let fill = remaining.min(level.size);
+                    cost += fill * level.price;
+                    remaining -= fill;
+                    if remaining <= 0.0 {
+                        return Some((cost / size - best).abs() / best * 10_000.0);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Largest size that can be traded with an impact of at most `max_bps`,
+    /// selling if `sell` and buying otherwise
+    pub fn max_size_within(&self, max_bps: f64, sell: bool) -> f64 {
+        match self {
+            Self::Constant { liquidity } => liquidity.max(0.0) * max_bps / 10_000.0,
+            Self::Ladder { .. } => {
+                let levels = self.levels(sell);
+                let Some(best) = levels.first().map(|level| level.price) else {
+                    return 0.0;
+                };
+                // Worst average fill price within the bound
+                let limit = if sell {
+                    best * (1.0 - max_bps / 10_000.0)
+                } else {
+                    best * (1.0 + max_bps / 10_000.0)
+                };
+                let within = |average: f64| if sell { average >= limit } else { average <= limit };
+
+                let (mut filled, mut cost) = (0.0, 0.0);
+                for level in levels {
+                    let (next_filled, next_cost) = (filled + level.size, cost + level.size * level.price);
+                    if within(next_cost / next_filled) {
+                        (filled, cost) = (next_filled, next_cost);
+                        continue;
+                    }
+                    // Take as much of the level as keeps the average at the limit:
+                    // (cost + x * price) / (filled + x) = limit
+                    let partial = (limit * filled - cost) / (level.price - limit);
+                    return filled + partial.clamp(0.0, level.size);
+                }
+                filled
+            }
+        }
+    }
+
+    fn levels(&self, sell: bool) -> &[DepthLevel] {
+        match self {
+            Self::Ladder { bids, .. } if sell => bids,
+            Self::Ladder { asks, .. } => asks,
+            Self::Constant { .. } => &[],
+        }
+    }
+}
+
+/// Source of market depth, for sizing liquidations within the slippage bound
+#[async_trait]
+pub trait DepthProvider: Send + Sync + std::fmt::Debug {
+    /// Current depth of a symbol's market, or `None` if it isn't known
+    async fn get_depth(&self, symbol: &str) -> Result<Option<MarketDepth>, LiquidationError>;
+}
+
+/// Depth provider with a fixed liquidity per symbol
+#[derive(Debug, Clone, Default)]
+pub struct ConstantLiquidity {
+    liquidity: HashMap<String, f64>,
+}
+
+impl ConstantLiquidity {
+    /// A provider with the units of each symbol that move its price by 100%
+    pub fn new(liquidity: HashMap<String, f64>) -> Self {
+        Self { liquidity }
+    }
+}
+
+#[async_trait]
+impl DepthProvider for ConstantLiquidity {
+    async fn get_depth(&self, symbol: &str) -> Result<Option<MarketDepth>, LiquidationError> {
+        Ok(self.liquidity.get(symbol).map(|&liquidity| MarketDepth::Constant { liquidity }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_ladder() -> MarketDepth {
+        let levels = |levels: &[(f64, f64)]| {
+            levels
+                .iter()
+                .map(|&(price, size)| DepthLevel { price, size })
+                .collect()
+        };
+        MarketDepth::Ladder {
+            bids: levels(&[(100.0, 1.0), (99.0, 2.0), (98.0, 5.0)]),
+            asks: levels(&[(101.0, 4.0), (103.0, 4.0)]),
+        }
+    }
+
+    #[test]
+    fn test_ladder_impact() {
+        let ladder = create_ladder();
+        assert_eq!(ladder.impact_bps(1.0, true), Some(0.0));
+        // Two units average 99.5 against a best bid of 100
+        assert!((ladder.impact_bps(2.0, true).unwrap() - 50.0).abs() < 1e-9);
+        assert!(ladder.impact_bps(8.5, true).is_none());
+        // Buying 8 averages 102 against a best ask of 101
+        assert!((ladder.impact_bps(8.0, false).unwrap() - 1.0 / 101.0 * 10_000.0).abs() < 1e-9);
+
+        assert!((ladder.max_size_within(50.0, true) - 2.0).abs() < 1e-9);
+        assert!((ladder.max_size_within(0.0, true) - 1.0).abs() < 1e-9);
+        // The whole book is within a wide enough bound
+        assert_eq!(ladder.max_size_within(5_000.0, false), 8.0);
+        let empty = MarketDepth::Ladder { bids: Vec::new(), asks: Vec::new() };
+        assert_eq!(empty.max_size_within(50.0, true), 0.0);
+        assert!(empty.impact_bps(1.0, true).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_constant_liquidity() {
+        let provider = ConstantLiquidity::new(HashMap::from([("BTC/USD".to_string(), 10_000.0)]));
+        let depth = provider.get_depth("BTC/USD").await.unwrap().unwrap();
+        assert_eq!(depth.max_size_within(50.0, true), 50.0);
+        assert_eq!(depth.impact_bps(50.0, false), Some(50.0));
+        assert!(provider.get_depth("SOL/USD").await.unwrap().is_none());
+    }
+}
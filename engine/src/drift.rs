@@ -0,0 +1,116 @@
+/// Drift and realized volatility of a symbol's price, estimated from its recent
+/// prices as a geometric random walk
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct DriftEstimate {
+    /// Mean log return per second
+    pub drift_per_sec: f64,
+    /// Standard deviation of log returns over one second
+    pub volatility_per_sqrt_sec: f64,
+}
+
+impl DriftEstimate {
+    /// Estimate from prices with when they were read (Unix timestamps), oldest
+    /// first
+    ///
+    /// The drift is the log return from the first price to the last over the
+    /// time between them, and the volatility that of the returns between
+    /// consecutive prices around it. Returns `None` with a non-positive price
+    /// or no time between the first price and the last.
+    pub fn from_prices(prices: &[(i64, f64)]) -> Option<Self> {
+        let (&(first_at, first), &(last_at, last)) = (prices.first()?, prices.last()?);
+        if last_at <= first_at || prices.iter().any(|&(_, price)| !(price.is_finite() && price > 0.0)) {
+            return None;
+        }
+        let drift_per_sec = (last / first).ln() / (last_at - first_at) as f64;
+        // Each return less its expected drift, scaled to one second
+        let variances: Vec<f64> = prices
+            .windows(2)
+            .filter(|pair| pair[1].0 > pair[0].0)
+            .map(|pair| {
+                let secs = (pair[1].0 - pair[0].0) as f64;
+                let surprise = (pair[1].1 / pair[0].1).ln() - drift_per_sec * secs;
+                surprise * surprise / secs
+            })
+            .collect();
+        let variance = variances.iter().sum::<f64>() / variances.len() as f64;
+        Some(Self {
+            drift_per_sec,
+            volatility_per_sqrt_sec: variance.sqrt(),
+        })
+    }
+
+    /// Seconds until a price drifting at the estimated rate moves from `price`
+    /// to `target`, or `None` if it drifts away from it or not at all
+    pub fn time_to_reach(&self, price: f64, target: f64) -> Option<f64> {
+        if !(price > 0.0 && target > 0.0) {
+            return None;
+        }
+        let distance = (target / price).ln();
+        if distance == 0.0 {
+            return Some(0.0);
+        }
+        let secs = distance / self.drift_per_sec;
+        (secs.is_finite() && secs > 0.0).then_some(secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Prices a minute apart along a drift of `drift_per_sec`, alternately
+    /// `wobble` above and below it in log terms
+    fn series(drift_per_sec: f64, wobble: f64, len: i64) -> Vec<(i64, f64)> {
+        (0..len)
+            .map(|minute| {
+                let at = minute * 60;
+                let sign = if minute % 2 == 0 { 1.0 } else { -1.0 };
+                (at, 100.0 * (drift_per_sec * at as f64 + sign * wobble).exp())
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() <= expected.abs() * 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_estimate_of_known_drift() {
+        // A steady 1% fall per 1,000 seconds, without noise
+        let drift = (0.99f64).ln() / 1_000.0;
+        let estimate = DriftEstimate::from_prices(&series(drift, 0.0, 11)).unwrap();
+        assert_close(estimate.drift_per_sec, drift);
+        assert!(estimate.volatility_per_sqrt_sec < 1e-12);
+        // 2% below the last price is two falls of 1% away, compounded
+        let last = 100.0 * (drift * 600.0).exp();
+        assert_close(estimate.time_to_reach(last, last * 0.99 * 0.99).unwrap(), 2_000.0);
+        // and a price above it is never reached
+        assert_eq!(estimate.time_to_reach(last, last * 1.01), None);
+        assert_eq!(estimate.time_to_reach(last, last), Some(0.0));
+    }
+
+    #[test]
+    fn test_estimate_of_noisy_drift() {
+        // Every return strays 2 * wobble from the drift, which the odd number
+        // of prices cancels out of the first and last
+        let (drift, wobble) = (2e-5, 0.003);
+        let estimate = DriftEstimate::from_prices(&series(drift, wobble, 21)).unwrap();
+        assert_close(estimate.drift_per_sec, drift);
+        assert_close(estimate.volatility_per_sqrt_sec, 2.0 * wobble / 60f64.sqrt());
+        // Rising prices reach shorts' liquidation prices above, not longs' below
+        assert!(estimate.time_to_reach(100.0, 110.0).unwrap() > 0.0);
+        assert_eq!(estimate.time_to_reach(100.0, 90.0), None);
+    }
+
+    #[test]
+    fn test_estimate_needs_time_and_prices() {
+        assert_eq!(DriftEstimate::from_prices(&[]), None);
+        assert_eq!(DriftEstimate::from_prices(&[(60, 100.0)]), None);
+        assert_eq!(DriftEstimate::from_prices(&[(60, 100.0), (60, 101.0)]), None);
+        assert_eq!(DriftEstimate::from_prices(&[(0, 100.0), (60, 0.0)]), None);
+        // A flat price doesn't drift anywhere
+        let flat = DriftEstimate::from_prices(&[(0, 100.0), (60, 100.0)]).unwrap();
+        assert_eq!(flat.drift_per_sec, 0.0);
+        assert_eq!(flat.time_to_reach(100.0, 90.0), None);
+    }
+}
@@ -1,85 +1,426 @@
-use solana_client::client_error::ClientError;
-use solana_sdk::{program_error::ProgramError, pubkey::Pubkey};
+use crate::address::PositionAddress;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_sdk::{instruction::InstructionError, program_error::ProgramError, transaction::TransactionError};
 use std::fmt;
 
 /// Custom error type for the liquidation engine
-#[derive(Debug)]
+///
+/// Display texts match those logged before the error was given structured fields,
+/// except that [`StalePrice`](Self::StalePrice) now ends with the price's age and
+/// the limit it exceeded (`Stale price for BTC/USD: 75s old, max 60s`).
+#[derive(Debug, thiserror::Error)]
 pub enum LiquidationError {
     /// Error from Solana RPC client
-    RpcError(String),
+    #[error("RPC error: {message}")]
+    RpcError {
+        /// URL of the endpoint that answered, when known
+        endpoint: Option<String>,
+        /// What went wrong talking to the endpoint
+        kind: RpcErrorKind,
+        /// The client's description of the failure
+        message: String,
+    },
+    
+    /// RPC node kept throttling requests after backing off
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+    
+    /// RPC endpoint couldn't be reached or is unhealthy
+    #[error("RPC endpoint unavailable: {0}")]
+    RpcUnavailable(String),
     
     /// Error from Solana program
-    ProgramError(ProgramError),
+    #[error("Program error: {0}")]
+    ProgramError(#[from] ProgramError),
     
     /// Error from oracle service
+    #[error("Oracle error: {0}")]
     OracleError(String),
     
-    /// Price is stale (older than allowed threshold)
-    StalePrice(String),
+    /// Oracle didn't answer within its timeout
+    #[error("Oracle timed out pricing {symbol} after {timeout_ms}ms")]
+    OracleTimeout {
+        /// Symbol that was priced
+        symbol: String,
+        /// Time the oracle was given (in milliseconds)
+        timeout_ms: u64,
+    },
+    
+    /// Price is older than allowed
+    #[error("Stale price for {symbol}: {age_secs}s old, max {max_age_secs}s")]
+    StalePrice {
+        /// Symbol that was priced
+        symbol: String,
+        /// Age of the price (in seconds)
+        age_secs: u64,
+        /// Oldest price allowed (in seconds)
+        max_age_secs: u64,
+    },
+    
+    /// Price was published more slots before the cluster's current slot than
+    /// allowed
+    #[error("Stale price for {symbol}: published {age_slots} slots ago, max {max_age_slots}")]
+    StalePriceSlot {
+        /// Symbol that was priced
+        symbol: String,
+        /// Slots since the price was published
+        age_slots: u64,
+        /// Oldest price allowed (in slots)
+        max_age_slots: u64,
+    },
     
     /// Price confidence is too low
+    #[error("Low confidence price for {0}")]
     LowConfidencePrice(String),
     
     /// Price confidence interval is too wide
+    #[error("High confidence interval for {0}")]
     HighConfidenceInterval(String),
     
+    /// Price moved further from the last accepted one than allowed, and hasn't
+    /// persisted long enough to be believed
+    #[error("Price outlier for {symbol}: {price} is {deviation_bps:.0} bps from last accepted {last_accepted}")]
+    PriceOutlier {
+        /// Symbol that was priced
+        symbol: String,
+        /// The rejected price
+        price: f64,
+        /// Last price accepted for the symbol
+        last_accepted: f64,
+        /// Move from the last accepted price (in basis points)
+        deviation_bps: f64,
+    },
+    
     /// Position is not liquidatable
-    PositionNotLiquidatable(Pubkey),
+    #[error("Position {0} is not liquidatable")]
+    PositionNotLiquidatable(PositionAddress),
+    
+    /// Position is not being monitored
+    #[error("Position {0} is not monitored")]
+    PositionNotFound(PositionAddress),
     
     /// Liquidation failed
+    #[error("Liquidation failed: {0}")]
     LiquidationFailed(String),
     
     /// Transaction simulation failed
+    #[error("Simulation failed: {0}")]
     SimulationFailed(String),
     
     /// Transaction confirmation timeout
+    #[error("Transaction confirmation timed out")]
     ConfirmationTimeout,
     
+    /// Transaction's blockhash expired before it landed, so it never will
+    #[error("Blockhash of transaction {0} expired before it landed")]
+    BlockhashExpired(String),
+    
+    /// The program only lets whitelisted keepers liquidate, and our keypair isn't one
+    #[error("Liquidator keypair is not a whitelisted keeper of the market")]
+    KeeperNotWhitelisted,
+    
+    /// Operators set the engine to a mode that sends no transactions
+    #[error("Engine is {0}, not submitting transactions")]
+    SubmissionsDisabled(crate::types::EngineMode),
+    
+    /// Account data is shorter than the account's layout
+    #[error("Account data too short: {actual} bytes, expected {expected}")]
+    AccountTooShort {
+        /// Length of the account's layout (in bytes)
+        expected: usize,
+        /// Length of the data (in bytes)
+        actual: usize,
+    },
+    
+    /// Account data doesn't start with the account's Anchor discriminator
+    #[error("Account discriminator mismatch: expected {}, got {}", hex::encode(expected), hex::encode(actual))]
+    AccountDiscriminatorMismatch {
+        /// Discriminator of the expected account type
+        expected: [u8; 8],
+        /// Discriminator the data starts with
+        actual: [u8; 8],
+    },
+    
+    /// Write of a position older than the version already applied to it
+    #[error("Stale update: version {attempted_version} is older than applied version {current_version}")]
+    StaleUpdate {
+        /// Version of the position the engine holds
+        current_version: u64,
+        /// Version of the rejected write
+        attempted_version: u64,
+    },
+    
+    /// Position broke the ingest rules and wasn't monitored
+    #[error(transparent)]
+    Ingest(#[from] crate::ingest::IngestError),
+    
     /// Invalid configuration
+    #[error("Configuration error: {0}")]
     ConfigError(String),
     
+    /// Error from the liquidation store
+    #[error("Storage error: {0}")]
+    StorageError(String),
+    
     /// Other errors
+    #[error("Error: {0}")]
     Other(String),
 }
 
-impl fmt::Display for LiquidationError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// What went wrong talking to an RPC endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcErrorKind {
+    /// The request or response didn't make it across the network
+    Transport,
+    /// The node answered with an error
+    Response,
+    /// The response couldn't be decoded
+    Decode,
+    /// The transaction couldn't be signed
+    Signing,
+    /// The node rejected the transaction
+    Transaction,
+    /// Anything else
+    Other,
+}
+
+impl From<&ClientErrorKind> for RpcErrorKind {
+    fn from(kind: &ClientErrorKind) -> Self {
+        match kind {
+            ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => Self::Transport,
+            ClientErrorKind::RpcError(_) => Self::Response,
+            ClientErrorKind::SerdeJson(_) => Self::Decode,
+            ClientErrorKind::SigningError(_) => Self::Signing,
+            ClientErrorKind::TransactionError(_) => Self::Transaction,
+            ClientErrorKind::Custom(_) => Self::Other,
+        }
+    }
+}
+
+/// Class of a [`LiquidationError`], one per variant, for labelling logs and
+/// deciding what to retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Rpc,
+    RateLimited,
+    RpcUnavailable,
+    Program,
+    Oracle,
+    OracleTimeout,
+    StalePrice,
+    LowConfidencePrice,
+    HighConfidenceInterval,
+    PriceOutlier,
+    PositionNotLiquidatable,
+    PositionNotFound,
+    LiquidationFailed,
+    SimulationFailed,
+    ConfirmationTimeout,
+    BlockhashExpired,
+    KeeperNotWhitelisted,
+    SubmissionsDisabled,
+    AccountTooShort,
+    AccountDiscriminatorMismatch,
+    StaleUpdate,
+    Ingest,
+    Config,
+    Storage,
+    Other,
+}
+
+impl ErrorKind {
+    /// Label of the kind, as it's serialized
+    pub fn as_str(self) -> &'static str {
         match self {
-            Self::RpcError(msg) => write!(f, "RPC error: {}", msg),
-            Self::ProgramError(err) => write!(f, "Program error: {}", err),
-            Self::OracleError(msg) => write!(f, "Oracle error: {}", msg),
-            Self::StalePrice(symbol) => write!(f, "Stale price for {}", symbol),
-            Self::LowConfidencePrice(symbol) => write!(f, "Low confidence price for {}", symbol),
-            Self::HighConfidenceInterval(symbol) => write!(f, "High confidence interval for {}", symbol),
-            Self::PositionNotLiquidatable(address) => write!(f, "Position {} is not liquidatable", address),
-            Self::LiquidationFailed(msg) => write!(f, "Liquidation failed: {}", msg),
-            Self::SimulationFailed(msg) => write!(f, "Simulation failed: {}", msg),
-            Self::ConfirmationTimeout => write!(f, "Transaction confirmation timed out"),
-            Self::ConfigError(msg) => write!(f, "Configuration error: {}", msg),
-            Self::Other(msg) => write!(f, "Error: {}", msg),
+            Self::Rpc => "rpc",
+            Self::RateLimited => "rate_limited",
+            Self::RpcUnavailable => "rpc_unavailable",
+            Self::Program => "program",
+            Self::Oracle => "oracle",
+            Self::OracleTimeout => "oracle_timeout",
+            Self::StalePrice => "stale_price",
+            Self::LowConfidencePrice => "low_confidence_price",
+            Self::HighConfidenceInterval => "high_confidence_interval",
+            Self::PriceOutlier => "price_outlier",
+            Self::PositionNotLiquidatable => "position_not_liquidatable",
+            Self::PositionNotFound => "position_not_found",
+            Self::LiquidationFailed => "liquidation_failed",
+            Self::SimulationFailed => "simulation_failed",
+            Self::ConfirmationTimeout => "confirmation_timeout",
+            Self::BlockhashExpired => "blockhash_expired",
+            Self::KeeperNotWhitelisted => "keeper_not_whitelisted",
+            Self::SubmissionsDisabled => "submissions_disabled",
+            Self::AccountTooShort => "account_too_short",
+            Self::AccountDiscriminatorMismatch => "account_discriminator_mismatch",
+            Self::StaleUpdate => "stale_update",
+            Self::Ingest => "ingest",
+            Self::Config => "config",
+            Self::Storage => "storage",
+            Self::Other => "other",
         }
     }
 }
 
-impl std::error::Error for LiquidationError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl LiquidationError {
+    /// An RPC error with no endpoint attached yet
+    pub fn rpc(kind: RpcErrorKind, message: impl Into<String>) -> Self {
+        Self::RpcError {
+            endpoint: None,
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// Attach the endpoint that answered to an RPC error without one
+    pub fn at_endpoint(self, url: &str) -> Self {
         match self {
-            Self::RpcError(_) => None,
-            Self::ProgramError(e) => Some(e),
-            Self::OracleError(_) => None,
-            Self::StalePrice(_) => None,
-            Self::LowConfidencePrice(_) => None,
-            Self::HighConfidenceInterval(_) => None,
-            Self::PositionNotLiquidatable(_) => None,
-            Self::LiquidationFailed(_) => None,
-            Self::SimulationFailed(_) => None,
-            Self::ConfirmationTimeout => None,
-            Self::ConfigError(_) => None,
-            Self::Other(_) => None,
+            Self::RpcError { endpoint: None, kind, message } => Self::RpcError {
+                endpoint: Some(url.to_string()),
+                kind,
+                message,
+            },
+            err => err,
+        }
+    }
+
+    /// The error's class
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::RpcError { .. } => ErrorKind::Rpc,
+            Self::RateLimited(_) => ErrorKind::RateLimited,
+            Self::RpcUnavailable(_) => ErrorKind::RpcUnavailable,
+            Self::ProgramError(_) => ErrorKind::Program,
+            Self::OracleError(_) => ErrorKind::Oracle,
+            Self::OracleTimeout { .. } => ErrorKind::OracleTimeout,
+            Self::StalePrice { .. } | Self::StalePriceSlot { .. } => ErrorKind::StalePrice,
+            Self::LowConfidencePrice(_) => ErrorKind::LowConfidencePrice,
+            Self::HighConfidenceInterval(_) => ErrorKind::HighConfidenceInterval,
+            Self::PriceOutlier { .. } => ErrorKind::PriceOutlier,
+            Self::PositionNotLiquidatable(_) => ErrorKind::PositionNotLiquidatable,
+            Self::PositionNotFound(_) => ErrorKind::PositionNotFound,
+            Self::LiquidationFailed(_) => ErrorKind::LiquidationFailed,
+            Self::SimulationFailed(_) => ErrorKind::SimulationFailed,
+            Self::ConfirmationTimeout => ErrorKind::ConfirmationTimeout,
+            Self::BlockhashExpired(_) => ErrorKind::BlockhashExpired,
+            Self::KeeperNotWhitelisted => ErrorKind::KeeperNotWhitelisted,
+            Self::SubmissionsDisabled(_) => ErrorKind::SubmissionsDisabled,
+            Self::AccountTooShort { .. } => ErrorKind::AccountTooShort,
+            Self::AccountDiscriminatorMismatch { .. } => ErrorKind::AccountDiscriminatorMismatch,
+            Self::StaleUpdate { .. } => ErrorKind::StaleUpdate,
+            Self::Ingest(_) => ErrorKind::Ingest,
+            Self::ConfigError(_) => ErrorKind::Config,
+            Self::StorageError(_) => ErrorKind::Storage,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Error of a liquidation of `position` whose transaction landed but failed
+    ///
+    /// The program's refusals of a healthy position and of a liquidator that
+    /// isn't a whitelisted keeper get their own variants.
+    pub fn transaction_failed(position: PositionAddress, err: TransactionError) -> Self {
+        match err {
+            TransactionError::InstructionError(_, InstructionError::Custom(code))
+                if code == crate::instruction::POSITION_HEALTHY_ERROR =>
+            {
+                Self::PositionNotLiquidatable(position)
+            }
+            err if is_keeper_not_whitelisted(&err) => Self::KeeperNotWhitelisted,
+            err => Self::LiquidationFailed(format!("transaction failed on chain: {}", err)),
+        }
+    }
+
+    /// Whether trying the same operation again could succeed
+    ///
+    /// Network trouble, throttling, prices that haven't settled and transactions
+    /// that failed to land are transient. Refusals that depend only on our
+    /// configuration, keypair or the position itself aren't.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RpcError { kind, .. } => !matches!(kind, RpcErrorKind::Decode | RpcErrorKind::Signing),
+            Self::RateLimited(_)
+            | Self::RpcUnavailable(_)
+            | Self::OracleError(_)
+            | Self::OracleTimeout { .. }
+            | Self::StalePrice { .. }
+            | Self::StalePriceSlot { .. }
+            | Self::LowConfidencePrice(_)
+            | Self::HighConfidenceInterval(_)
+            | Self::PriceOutlier { .. }
+            | Self::LiquidationFailed(_)
+            | Self::SimulationFailed(_)
+            | Self::ConfirmationTimeout
+            | Self::BlockhashExpired(_)
+            | Self::Other(_) => true,
+            Self::ProgramError(_)
+            | Self::PositionNotLiquidatable(_)
+            | Self::PositionNotFound(_)
+            | Self::KeeperNotWhitelisted
+            | Self::SubmissionsDisabled(_)
+            | Self::AccountTooShort { .. }
+            | Self::AccountDiscriminatorMismatch { .. }
+            | Self::StaleUpdate { .. }
+            | Self::Ingest(_)
+            | Self::ConfigError(_)
+            | Self::StorageError(_) => false,
         }
     }
 }
 
+/// A configuration value that fails validation
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ConfigViolation {
+    /// Dotted path of the offending field, e.g. `rate_limit.burst`
+    pub field: String,
+    /// What's wrong with the value, read after the field name
+    pub message: String,
+}
+
+impl ConfigViolation {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    /// The same violation for a field nested under `parent`
+    pub fn nested(self, parent: &str) -> Self {
+        Self {
+            field: format!("{}.{}", parent, self.field),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for ConfigViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
+impl From<ConfigViolation> for LiquidationError {
+    fn from(violation: ConfigViolation) -> Self {
+        Self::ConfigError(violation.to_string())
+    }
+}
+
+/// Fail with the first of a configuration's violations, if it has any
+pub(crate) fn first_violation(violations: Vec<ConfigViolation>) -> Result<(), LiquidationError> {
+    match violations.into_iter().next() {
+        Some(violation) => Err(violation.into()),
+        None => Ok(()),
+    }
+}
+
 impl From<Box<dyn std::error::Error>> for LiquidationError {
     fn from(err: Box<dyn std::error::Error>) -> Self {
         Self::Other(err.to_string())
@@ -92,15 +433,27 @@ impl From<String> for LiquidationError {
     }
 }
 
-impl From<ClientError> for LiquidationError {
-    fn from(err: ClientError) -> Self {
-        Self::RpcError(err.to_string())
-    }
+/// Whether a transaction failed because the program refused a liquidator
+/// that isn't a whitelisted keeper
+fn is_keeper_not_whitelisted(err: &TransactionError) -> bool {
+    matches!(
+        err,
+        TransactionError::InstructionError(_, InstructionError::Custom(code))
+            if *code == crate::instruction::KEEPER_NOT_WHITELISTED_ERROR
+    )
 }
 
-impl From<ProgramError> for LiquidationError {
-    fn from(err: ProgramError) -> Self {
-        Self::ProgramError(err)
+impl From<ClientError> for LiquidationError {
+    fn from(err: ClientError) -> Self {
+        if err.get_transaction_error().is_some_and(|err| is_keeper_not_whitelisted(&err)) {
+            Self::KeeperNotWhitelisted
+        } else if crate::rate_limit::is_throttled(&err) {
+            Self::RateLimited(err.to_string())
+        } else if crate::rpc_pool::is_endpoint_failure(&err) {
+            Self::RpcUnavailable(err.to_string())
+        } else {
+            Self::rpc(RpcErrorKind::from(err.kind()), err.to_string())
+        }
     }
 }
 
@@ -122,8 +475,11 @@ impl From<solana_sdk::signature::SignerError> for LiquidationError {
     }
 }
 
-impl From<solana_sdk::transaction::TransactionError> for LiquidationError {
-    fn from(err: solana_sdk::transaction::TransactionError) -> Self {
+impl From<TransactionError> for LiquidationError {
+    fn from(err: TransactionError) -> Self {
+        if is_keeper_not_whitelisted(&err) {
+            return Self::KeeperNotWhitelisted;
+        }
         Self::Other(err.to_string())
     }
 }
@@ -134,6 +490,20 @@ impl From<solana_sdk::pubkey::ParsePubkeyError> for LiquidationError {
     }
 }
 
+#[cfg(feature = "storage")]
+impl From<rusqlite::Error> for LiquidationError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::StorageError(err.to_string())
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<tonic::transport::Error> for LiquidationError {
+    fn from(err: tonic::transport::Error) -> Self {
+        Self::Other(err.to_string())
+    }
+}
+
 impl From<std::num::ParseIntError> for LiquidationError {
     fn from(err: std::num::ParseIntError) -> Self {
         Self::ConfigError(err.to_string())
@@ -158,11 +528,30 @@ mod tests {
     
     #[test]
     fn test_error_display() {
-        let error = LiquidationError::RpcError("Connection failed".to_string());
+        let error = LiquidationError::rpc(RpcErrorKind::Transport, "Connection failed");
         assert_eq!(error.to_string(), "RPC error: Connection failed");
         
-        let error = LiquidationError::StalePrice("BTC/USD".to_string());
-        assert_eq!(error.to_string(), "Stale price for BTC/USD");
+        let error = LiquidationError::StalePrice {
+            symbol: "BTC/USD".to_string(),
+            age_secs: 75,
+            max_age_secs: 60,
+        };
+        assert_eq!(error.to_string(), "Stale price for BTC/USD: 75s old, max 60s");
+        
+        let error = LiquidationError::StalePriceSlot {
+            symbol: "BTC/USD".to_string(),
+            age_slots: 200,
+            max_age_slots: 150,
+        };
+        assert_eq!(error.to_string(), "Stale price for BTC/USD: published 200 slots ago, max 150");
+        
+        let error = LiquidationError::PriceOutlier {
+            symbol: "BTC/USD".to_string(),
+            price: 70_000.0,
+            last_accepted: 50_000.0,
+            deviation_bps: 4_000.0,
+        };
+        assert_eq!(error.to_string(), "Price outlier for BTC/USD: 70000 is 4000 bps from last accepted 50000");
         
         let error = LiquidationError::Other("Something went wrong".to_string());
         assert_eq!(error.to_string(), "Error: Something went wrong");
@@ -170,12 +559,131 @@ mod tests {
     
     #[test]
     fn test_error_conversions() {
-        let io_error = std::io::Error::new(std::io::ErrorKind::Other, "IO error");
+        let io_error = std::io::Error::other("IO error");
         let error: LiquidationError = io_error.into();
         assert!(matches!(error, LiquidationError::Other(_)));
         
         let parse_int_error = "not a number".parse::<i32>().unwrap_err();
         let error: LiquidationError = parse_int_error.into();
         assert!(matches!(error, LiquidationError::ConfigError(_)));
+        
+        // The program's whitelist rejection is told apart from other failures
+        let custom = |code| TransactionError::InstructionError(0, InstructionError::Custom(code));
+        let error: LiquidationError = custom(crate::instruction::KEEPER_NOT_WHITELISTED_ERROR).into();
+        assert!(matches!(error, LiquidationError::KeeperNotWhitelisted));
+        let error: LiquidationError = ClientError::from(custom(crate::instruction::KEEPER_NOT_WHITELISTED_ERROR)).into();
+        assert!(matches!(error, LiquidationError::KeeperNotWhitelisted));
+        let error: LiquidationError = ClientError::from(custom(crate::instruction::POSITION_HEALTHY_ERROR)).into();
+        assert!(matches!(
+            error,
+            LiquidationError::RpcError { endpoint: None, kind: RpcErrorKind::Transaction, .. }
+        ));
+        
+        // Transactions that landed but failed are told apart by the program's error
+        let position = PositionAddress::new_unique();
+        let error = LiquidationError::transaction_failed(position, custom(crate::instruction::POSITION_HEALTHY_ERROR));
+        assert!(matches!(error, LiquidationError::PositionNotLiquidatable(address) if address == position));
+        let error = LiquidationError::transaction_failed(position, custom(crate::instruction::KEEPER_NOT_WHITELISTED_ERROR));
+        assert!(matches!(error, LiquidationError::KeeperNotWhitelisted));
+        let error = LiquidationError::transaction_failed(position, custom(crate::instruction::INSUFFICIENT_FUNDS_ERROR));
+        assert!(matches!(error, LiquidationError::LiquidationFailed(_)));
+        
+        let answered = solana_client::rpc_request::RpcError::ForUser("account not found".to_string());
+        let error: LiquidationError = ClientError::from(ClientErrorKind::RpcError(answered)).into();
+        let error = error.at_endpoint("https://api.devnet.solana.com");
+        let LiquidationError::RpcError { endpoint, kind, message } = &error else {
+            panic!("unexpected error {:?}", error);
+        };
+        assert_eq!(endpoint.as_deref(), Some("https://api.devnet.solana.com"));
+        assert_eq!(*kind, RpcErrorKind::Response);
+        assert!(message.contains("account not found"), "{}", message);
+        // An endpoint already attached isn't replaced
+        assert!(matches!(
+            error.at_endpoint("https://other.example"),
+            LiquidationError::RpcError { endpoint: Some(url), .. } if url == "https://api.devnet.solana.com"
+        ));
+        
+        let error: LiquidationError = ProgramError::InvalidArgument.into();
+        assert!(std::error::Error::source(&error).is_some());
+    }
+    
+    #[test]
+    fn test_retryability() {
+        let address = PositionAddress::new_unique();
+        let stale = LiquidationError::StalePrice {
+            symbol: "BTC/USD".to_string(),
+            age_secs: 75,
+            max_age_secs: 60,
+        };
+        let outlier = LiquidationError::PriceOutlier {
+            symbol: "BTC/USD".to_string(),
+            price: 70_000.0,
+            last_accepted: 50_000.0,
+            deviation_bps: 4_000.0,
+        };
+        let cases = [
+            (LiquidationError::rpc(RpcErrorKind::Transport, "reset"), ErrorKind::Rpc, true),
+            (LiquidationError::rpc(RpcErrorKind::Response, "node behind"), ErrorKind::Rpc, true),
+            (LiquidationError::rpc(RpcErrorKind::Transaction, "blockhash not found"), ErrorKind::Rpc, true),
+            (LiquidationError::rpc(RpcErrorKind::Other, "custom"), ErrorKind::Rpc, true),
+            (LiquidationError::rpc(RpcErrorKind::Decode, "bad account"), ErrorKind::Rpc, false),
+            (LiquidationError::rpc(RpcErrorKind::Signing, "no signer"), ErrorKind::Rpc, false),
+            (LiquidationError::RateLimited(String::new()), ErrorKind::RateLimited, true),
+            (LiquidationError::RpcUnavailable(String::new()), ErrorKind::RpcUnavailable, true),
+            (ProgramError::InvalidArgument.into(), ErrorKind::Program, false),
+            (LiquidationError::OracleError(String::new()), ErrorKind::Oracle, true),
+            (
+                LiquidationError::OracleTimeout { symbol: "BTC/USD".to_string(), timeout_ms: 2_000 },
+                ErrorKind::OracleTimeout,
+                true,
+            ),
+            (stale, ErrorKind::StalePrice, true),
+            (
+                LiquidationError::StalePriceSlot { symbol: "BTC/USD".to_string(), age_slots: 200, max_age_slots: 150 },
+                ErrorKind::StalePrice,
+                true,
+            ),
+            (LiquidationError::LowConfidencePrice(String::new()), ErrorKind::LowConfidencePrice, true),
+            (LiquidationError::HighConfidenceInterval(String::new()), ErrorKind::HighConfidenceInterval, true),
+            (outlier, ErrorKind::PriceOutlier, true),
+            (LiquidationError::PositionNotLiquidatable(address), ErrorKind::PositionNotLiquidatable, false),
+            (LiquidationError::PositionNotFound(address), ErrorKind::PositionNotFound, false),
+            (LiquidationError::LiquidationFailed(String::new()), ErrorKind::LiquidationFailed, true),
+            (LiquidationError::SimulationFailed(String::new()), ErrorKind::SimulationFailed, true),
+            (LiquidationError::ConfirmationTimeout, ErrorKind::ConfirmationTimeout, true),
+            (LiquidationError::BlockhashExpired(String::new()), ErrorKind::BlockhashExpired, true),
+            (LiquidationError::KeeperNotWhitelisted, ErrorKind::KeeperNotWhitelisted, false),
+            (
+                LiquidationError::SubmissionsDisabled(crate::types::EngineMode::MonitorOnly),
+                ErrorKind::SubmissionsDisabled,
+                false,
+            ),
+            (LiquidationError::AccountTooShort { expected: 58, actual: 20 }, ErrorKind::AccountTooShort, false),
+            (
+                LiquidationError::AccountDiscriminatorMismatch { expected: [1; 8], actual: [2; 8] },
+                ErrorKind::AccountDiscriminatorMismatch,
+                false,
+            ),
+            (
+                LiquidationError::StaleUpdate { current_version: 7, attempted_version: 5 },
+                ErrorKind::StaleUpdate,
+                false,
+            ),
+            (
+                crate::ingest::IngestError { address, violations: Vec::new() }.into(),
+                ErrorKind::Ingest,
+                false,
+            ),
+            (LiquidationError::ConfigError(String::new()), ErrorKind::Config, false),
+            (LiquidationError::StorageError(String::new()), ErrorKind::Storage, false),
+            (LiquidationError::Other(String::new()), ErrorKind::Other, true),
+        ];
+        for (error, kind, retryable) in cases {
+            assert_eq!(error.kind(), kind, "{:?}", error);
+            assert_eq!(error.is_retryable(), retryable, "{:?}", error);
+            // Labels are what the kind serializes to
+            assert_eq!(serde_json::to_value(kind).unwrap(), kind.as_str());
+            assert_eq!(serde_json::from_value::<ErrorKind>(kind.as_str().into()).unwrap(), kind);
+        }
     }
 }
@@ -0,0 +1,277 @@
+use crate::address::PositionAddress;
+use crate::amount::{self, Amount, AmountExt};
+use crate::position::Position;
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use base64::Engine;
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+
+pub use liquidation_program::{PositionClosed, PositionDeposited, PositionLiquidated};
+
+/// Prefix of the log lines Anchor's `emit!` writes events to
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+/// Event emitted by the on-chain liquidation program
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgramEvent {
+    /// Collateral was deposited into a position
+    Deposited(PositionDeposited),
+    /// A position was liquidated, by us or another keeper
+    Liquidated(PositionLiquidated),
+    /// A position account was closed, by its owner or once fully liquidated
+    Closed(PositionClosed),
+}
+
+impl ProgramEvent {
+    /// Decode an event from the data of a `Program data:` line, or `None` if
+    /// it isn't one of ours
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 8 {
+            return None;
+        }
+        let (discriminator, mut body) = data.split_at(8);
+        if discriminator == PositionDeposited::DISCRIMINATOR {
+            PositionDeposited::deserialize(&mut body).ok().map(Self::Deposited)
+        } else if discriminator == PositionLiquidated::DISCRIMINATOR {
+            PositionLiquidated::deserialize(&mut body).ok().map(Self::Liquidated)
+        } else if discriminator == PositionClosed::DISCRIMINATOR {
+            PositionClosed::deserialize(&mut body).ok().map(Self::Closed)
+        } else {
+            None
+        }
+    }
+
+    /// Address of the position account the event is about
+    pub fn position(&self) -> PositionAddress {
+        match self {
+            Self::Deposited(event) => event.position.into(),
+            Self::Liquidated(event) => event.position.into(),
+            Self::Closed(event) => event.position.into(),
+        }
+    }
+
+    /// A position modelled from its account (see
+    /// [`position_from_account`](crate::health::position_from_account)) as the
+    /// event left it, or `None` once it holds no collateral or no longer exists
+    ///
+    /// Liquidations also start the position's cooldown from the on-chain time.
+    pub fn apply(&self, position: &Position) -> Option<Position> {
+        let mut position = position.clone();
+        let (collateral, debt) = match self {
+            Self::Deposited(event) => {
+                (amount::from_units(event.new_collateral, 0), position.size * position.entry_price)
+            }
+            Self::Liquidated(event) => {
+                position.last_liquidated = Some(event.timestamp);
+                let seized = amount::from_units(event.collateral_seized, 0);
+                (position.size - seized, amount::from_units(event.remaining_debt, 0))
+            }
+            Self::Closed(_) => return None,
+        };
+        if collateral <= Amount::ZERO {
+            return None;
+        }
+        position.size = collateral;
+        position.entry_price = debt.checked_div(collateral)?;
+        Some(position)
+    }
+}
+
+/// Events the liquidation program deployed at `program_id` emitted in a
+/// transaction's logs
+///
+/// Only `Program data:` lines written while the program itself is executing
+/// count, so other programs can't forge its events.
+pub(crate) fn parse_program_events(logs: &[String], program_id: &Pubkey) -> Vec<ProgramEvent> {
+    let program_id = program_id.to_string();
+    let mut invocations: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+    for line in logs {
+        if let Some(data) = line.strip_prefix(PROGRAM_DATA_PREFIX) {
+            if invocations.last() != Some(&program_id.as_str()) {
+                continue;
+            }
+            if let Some(event) = base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .ok()
+                .and_then(|data| ProgramEvent::decode(&data))
+            {
+                events.push(event);
+            }
+        } else if let Some(rest) = line.strip_prefix("Program ") {
+            let mut words = rest.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some(program), Some("invoke")) => invocations.push(program),
+                (Some(_), Some("success" | "failed:")) => {
+                    invocations.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+    events
+}
+
+/// `logsSubscribe` filter selecting transactions that mention `program_id`
+pub(crate) fn program_logs_filter(program_id: &Pubkey) -> RpcTransactionLogsFilter {
+    RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()])
+}
+
+/// `logsSubscribe` config only reporting confirmed transactions
+pub(crate) fn program_logs_config() -> RpcTransactionLogsConfig {
+    RpcTransactionLogsConfig {
+        commitment: Some(CommitmentConfig::confirmed()),
+    }
+}
+
+/// WebSocket URL of an RPC node's pubsub service for its HTTP URL
+pub fn websocket_url(rpc_url: &str) -> String {
+    if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::PROGRAM_ID;
+    use anchor_lang::Event;
+
+    /// Logs of a liquidation by another keeper, captured with the token
+    /// program's CPIs in between
+    fn liquidation_logs() -> Vec<String> {
+        let program = PROGRAM_ID.to_string();
+        let token = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+        [
+            format!("Program {} invoke [1]", program),
+            "Program log: Instruction: Liquidate".to_string(),
+            format!("Program {} invoke [2]", token),
+            "Program log: Instruction: Transfer".to_string(),
+            format!("Program {} consumed 4645 of 170000 compute units", token),
+            format!("Program {} success", token),
+            format!("Program {} invoke [2]", token),
+            "Program log: Instruction: Transfer".to_string(),
+            format!("Program {} consumed 4736 of 160000 compute units", token),
+            format!("Program {} success", token),
+            "Program data: KGta1mAePYABAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgICIE4AAAAAAADpAAAAAAAAAGDqAAAAAAAAAPFTZQAAAAA=".to_string(),
+            format!("Program {} consumed 41210 of 200000 compute units", program),
+            format!("Program {} success", program),
+        ]
+        .into()
+    }
+
+    fn create_position(collateral: f64, debt: f64) -> Position {
+        let address = Pubkey::new_from_array([1; 32]);
+        Position::new(address, Pubkey::new_unique(), "SOL/USD", collateral, debt / collateral, 0.0, true)
+    }
+
+    #[test]
+    fn test_parse_liquidation_logs() {
+        // Another deployment's logs don't carry the events
+        assert!(parse_program_events(&liquidation_logs(), &Pubkey::new_unique()).is_empty());
+        let events = parse_program_events(&liquidation_logs(), &PROGRAM_ID);
+        assert_eq!(
+            events,
+            [ProgramEvent::Liquidated(PositionLiquidated {
+                position: Pubkey::new_from_array([1; 32]),
+                liquidator: Pubkey::new_from_array([2; 32]),
+                repay_amount: 20_000,
+                collateral_seized: 233,
+                remaining_debt: 60_000,
+                timestamp: 1_700_000_000,
+            })]
+        );
+    }
+
+    #[test]
+    fn test_parse_deposit_logs() {
+        let program = PROGRAM_ID.to_string();
+        let logs = [
+            format!("Program {} invoke [1]", program),
+            "Program log: Instruction: DepositCollateral".to_string(),
+            "Program data: xmjpPBJdZDIBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDkAEAAAAAAADoAwAAAAAAAA==".to_string(),
+            format!("Program {} success", program),
+        ];
+        let events = parse_program_events(&logs, &PROGRAM_ID);
+        assert_eq!(events.len(), 1);
+        let ProgramEvent::Deposited(event) = &events[0] else {
+            panic!("expected a deposit, got {:?}", events[0]);
+        };
+        assert_eq!((event.owner, event.amount, event.new_collateral), (Pubkey::new_from_array([3; 32]), 400, 1_000));
+    }
+
+    #[test]
+    fn test_events_from_other_programs_ignored() {
+        let mut logs = liquidation_logs();
+        // The same data written by a program the liquidation program invoked,
+        // or outside it entirely, isn't trusted
+        let data = logs.remove(10);
+        logs.insert(8, data.clone());
+        logs.push(data);
+        logs.push("Program data: not base64".to_string());
+        assert!(parse_program_events(&logs, &PROGRAM_ID).is_empty());
+    }
+
+    #[test]
+    fn test_apply_events() {
+        let position = create_position(800.0, 60_000.0);
+
+        let deposit = ProgramEvent::Deposited(PositionDeposited {
+            position: position.address.into(),
+            owner: position.owner.into(),
+            amount: 200,
+            new_collateral: 1_000,
+        });
+        let deposited = deposit.apply(&position).unwrap();
+        assert_eq!((deposited.size, deposited.entry_price), (1_000.0, 60.0));
+        assert_eq!(deposited.last_liquidated, None);
+
+        let mut liquidation = PositionLiquidated {
+            position: position.address.into(),
+            liquidator: Pubkey::new_unique(),
+            repay_amount: 20_000,
+            collateral_seized: 233,
+            remaining_debt: 40_000,
+            timestamp: 1_700_000_000,
+        };
+        let liquidated = ProgramEvent::Liquidated(liquidation.clone()).apply(&position).unwrap();
+        assert_eq!(liquidated.size, 567.0);
+        assert!((liquidated.size * liquidated.entry_price - 40_000.0).abs() < 1e-9);
+        assert_eq!(liquidated.last_liquidated, Some(1_700_000_000));
+
+        // Seizing everything leaves nothing to monitor
+        liquidation.collateral_seized = 800;
+        liquidation.remaining_debt = 0;
+        assert!(ProgramEvent::Liquidated(liquidation).apply(&position).is_none());
+    }
+
+    #[test]
+    fn test_closed_position_dropped() {
+        let position = create_position(600.0, 0.0);
+        let closed = PositionClosed {
+            position: position.address.into(),
+            owner: position.owner.into(),
+            collateral_returned: 600,
+        };
+        let program = PROGRAM_ID.to_string();
+        let logs = [
+            format!("Program {} invoke [1]", program),
+            format!("Program data: {}", base64::engine::general_purpose::STANDARD.encode(closed.data())),
+            format!("Program {} success", program),
+        ];
+        let events = parse_program_events(&logs, &PROGRAM_ID);
+        assert_eq!(events, [ProgramEvent::Closed(closed)]);
+        assert!(events[0].apply(&position).is_none());
+    }
+
+    #[test]
+    fn test_websocket_url() {
+        assert_eq!(websocket_url("https://api.devnet.solana.com"), "wss://api.devnet.solana.com");
+        assert_eq!(websocket_url("http://127.0.0.1:8899"), "ws://127.0.0.1:8899");
+        assert_eq!(websocket_url("ws://127.0.0.1:8900"), "ws://127.0.0.1:8900");
+    }
+}
@@ -0,0 +1,273 @@
+#[cfg(any(test, feature = "testing"))]
+use crate::error::RpcErrorKind;
+use crate::error::{ConfigViolation, LiquidationError, first_violation};
+use crate::rate_limit::RateLimiter;
+use crate::rpc_pool::RpcPool;
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::sync::Arc;
+#[cfg(any(test, feature = "testing"))]
+use tokio::sync::RwLock;
+use tracing::warn;
+
+/// How the priority fee of a liquidation transaction is chosen
+///
+/// All fees are in microlamports per compute unit.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriorityFeeStrategy {
+    /// Always pay the same fee
+    Static(u64),
+    /// Pay a percentile of the fees recently paid to write the accounts a
+    /// liquidation locks, scaled by a multiplier
+    Percentile {
+        /// Percentile of the recent fee samples (0-100)
+        percentile: u8,
+        /// Factor applied to the percentile fee
+        multiplier: f64,
+    },
+    /// Start at a base fee and bump it on every retry
+    Aggressive {
+        /// Fee paid on the first attempt
+        base: u64,
+        /// Fee added for each retry
+        escalation_per_retry: u64,
+    },
+}
+
+impl PriorityFeeStrategy {
+    /// Fee for the given attempt (1 for the first submission), capped at `max_fee`
+    ///
+    /// `accounts` are the writable accounts of the liquidation transaction.
+    /// When recent fees can't be looked up, `max_fee` is paid rather than
+    /// holding the liquidation back.
+    pub async fn fee(&self, source: &dyn RecentFeeSource, accounts: &[Pubkey], attempt: u8, max_fee: u64) -> u64 {
+        let fee = match *self {
+            Self::Static(fee) => fee,
+            Self::Percentile { percentile, multiplier } => match source.recent_prioritization_fees(accounts).await {
+                Ok(samples) => percentile_fee(&samples, percentile, multiplier),
+                Err(e) => {
+                    warn!("Unable to look up recent priority fees, paying the maximum of {}: {}", max_fee, e);
+                    max_fee
+                }
+            },
+            Self::Aggressive { base, escalation_per_retry } => {
+                let retries = attempt.saturating_sub(1) as u64;
+                base.saturating_add(escalation_per_retry.saturating_mul(retries))
+            }
+        };
+        fee.min(max_fee)
+    }
+
+    /// Check that the strategy's parameters are usable
+    pub fn validate(&self) -> Result<(), LiquidationError> {
+        first_violation(self.violations())
+    }
+
+    /// Every problem with the strategy's parameters, with fields named as in
+    /// the configuration, under the strategy's name
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if let Self::Percentile { percentile, multiplier } = *self {
+            if percentile > 100 {
+                violations.push(ConfigViolation::new(
+                    "percentile.percentile",
+                    format!("must be at most 100, got {}", percentile),
+                ));
+            }
+            if !multiplier.is_finite() || multiplier < 0.0 {
+                violations.push(ConfigViolation::new(
+                    "percentile.multiplier",
+                    format!("must be a non-negative number, got {}", multiplier),
+                ));
+            }
+        }
+        violations
+    }
+}
+
+/// Nearest-rank `percentile` of the fee samples, scaled by `multiplier`
+///
+/// Returns zero when there are no samples, i.e. nobody has recently paid to write
+/// the accounts.
+pub fn percentile_fee(samples: &[u64], percentile: u8, multiplier: f64) -> u64 {
+    if samples.is_empty() {
+        return 0;
+    }
+
+    let mut sorted = samples.to_vec();
+    sorted.sort_unstable();
+    let rank = (percentile.min(100) as f64 / 100.0 * sorted.len() as f64).ceil() as usize;
+    let fee = sorted[rank.clamp(1, sorted.len()) - 1];
+    (fee as f64 * multiplier).round() as u64
+}
+
+/// Source of recently paid priority fees
+#[async_trait]
+pub trait RecentFeeSource: Send + Sync + fmt::Debug {
+    /// Priority fees paid in recent slots by transactions writing any of `accounts`
+    async fn recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>, LiquidationError>;
+}
+
+/// Recent fees from the `getRecentPrioritizationFees` RPC method
+#[derive(Debug, Clone)]
+pub(crate) struct RpcFeeSource {
+    rpc: RpcPool,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl RpcFeeSource {
+    /// Create a fee source querying the given RPC endpoints
+    pub fn new(rpc: RpcPool, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self { rpc, rate_limiter }
+    }
+}
+
+#[async_trait]
+impl RecentFeeSource for RpcFeeSource {
+    async fn recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>, LiquidationError> {
+        let accounts = accounts.to_vec();
+        let fees = self
+            .rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client
+                    .get_recent_prioritization_fees(&accounts)
+                    .map_err(LiquidationError::from)
+            })
+            .await?;
+        Ok(fees.into_iter().map(|fee| fee.prioritization_fee).collect())
+    }
+}
+
+/// Mock fee source for testing
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Clone, Default)]
+pub struct MockFeeSource {
+    fees: Arc<RwLock<Vec<u64>>>,
+    error: Arc<RwLock<Option<String>>>,
+    queries: Arc<RwLock<Vec<Vec<Pubkey>>>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockFeeSource {
+    /// Create a mock fee source with no recent fees
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the recent fee samples returned for any accounts
+    pub async fn set_fees(&self, fees: Vec<u64>) {
+        *self.fees.write().await = fees;
+    }
+
+    /// Make later lookups fail with the given error, or succeed again with `None`
+    pub async fn set_error(&self, error: Option<&str>) {
+        *self.error.write().await = error.map(str::to_string);
+    }
+
+    /// Accounts of every lookup so far, in order
+    pub async fn queries(&self) -> Vec<Vec<Pubkey>> {
+        self.queries.read().await.clone()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[async_trait]
+impl RecentFeeSource for MockFeeSource {
+    async fn recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>, LiquidationError> {
+        self.queries.write().await.push(accounts.to_vec());
+        if let Some(error) = self.error.read().await.clone() {
+            return Err(LiquidationError::rpc(RpcErrorKind::Response, error));
+        }
+        Ok(self.fees.read().await.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fees seen over 20 slots of a moderately busy market
+    const RECENT_FEES: [u64; 20] = [
+        0, 0, 0, 100, 100, 250, 500, 500, 1_000, 1_000, 1_200, 1_500, 2_000, 2_500, 5_000, 5_000, 7_500, 10_000,
+        20_000, 50_000,
+    ];
+
+    #[test]
+    fn test_percentile_math() {
+        assert_eq!(percentile_fee(&RECENT_FEES, 0, 1.0), 0);
+        assert_eq!(percentile_fee(&RECENT_FEES, 50, 1.0), 1_000);
+        assert_eq!(percentile_fee(&RECENT_FEES, 75, 1.0), 5_000);
+        assert_eq!(percentile_fee(&RECENT_FEES, 90, 1.0), 10_000);
+        assert_eq!(percentile_fee(&RECENT_FEES, 100, 1.0), 50_000);
+        assert_eq!(percentile_fee(&RECENT_FEES, 90, 1.5), 15_000);
+
+        // Sample order doesn't matter
+        let mut shuffled = RECENT_FEES;
+        shuffled.reverse();
+        assert_eq!(percentile_fee(&shuffled, 75, 1.0), 5_000);
+
+        assert_eq!(percentile_fee(&[], 75, 2.0), 0);
+        assert_eq!(percentile_fee(&[700], 1, 1.0), 700);
+    }
+
+    #[tokio::test]
+    async fn test_percentile_strategy_uses_recent_fees() {
+        let source = MockFeeSource::new();
+        source.set_fees(RECENT_FEES.to_vec()).await;
+        let strategy = PriorityFeeStrategy::Percentile {
+            percentile: 75,
+            multiplier: 1.2,
+        };
+
+        let fee = strategy.fee(&source, &[Pubkey::new_unique()], 1, u64::MAX).await;
+        assert_eq!(fee, 6_000);
+        assert_eq!(strategy.fee(&source, &[], 1, 4_000).await, 4_000);
+
+        // Without recent fees the liquidation still goes ahead, at the cap
+        source.set_error(Some("Method not found")).await;
+        assert_eq!(strategy.fee(&source, &[], 1, 4_000).await, 4_000);
+        assert_eq!(source.queries().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_aggressive_fee_escalates_across_retries() {
+        let source = MockFeeSource::new();
+        let strategy = PriorityFeeStrategy::Aggressive {
+            base: 1_000,
+            escalation_per_retry: 2_500,
+        };
+
+        let mut fees = Vec::new();
+        for attempt in 1..=5 {
+            fees.push(strategy.fee(&source, &[], attempt, 8_000).await);
+        }
+        assert_eq!(fees, vec![1_000, 3_500, 6_000, 8_000, 8_000]);
+
+        // Escalation never overflows
+        let strategy = PriorityFeeStrategy::Aggressive {
+            base: u64::MAX - 1,
+            escalation_per_retry: u64::MAX,
+        };
+        assert_eq!(strategy.fee(&source, &[], u8::MAX, u64::MAX).await, u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_static_fee_is_capped() {
+        let source = MockFeeSource::new();
+        let strategy = PriorityFeeStrategy::Static(5_000);
+
+        assert_eq!(strategy.fee(&source, &[], 3, 10_000).await, 5_000);
+        assert_eq!(strategy.fee(&source, &[], 1, 2_000).await, 2_000);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(PriorityFeeStrategy::Static(0).validate().is_ok());
+        assert!(PriorityFeeStrategy::Percentile { percentile: 100, multiplier: 1.0 }.validate().is_ok());
+        assert!(PriorityFeeStrategy::Percentile { percentile: 101, multiplier: 1.0 }.validate().is_err());
+        assert!(PriorityFeeStrategy::Percentile { percentile: 50, multiplier: -1.0 }.validate().is_err());
+        assert!(PriorityFeeStrategy::Percentile { percentile: 50, multiplier: f64::NAN }.validate().is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::error::LiquidationError;
+use async_trait::async_trait;
+#[cfg(any(test, feature = "testing"))]
+use std::collections::HashMap;
+#[cfg(any(test, feature = "testing"))]
+use std::sync::Arc;
+#[cfg(any(test, feature = "testing"))]
+use tokio::sync::RwLock;
+
+/// Trait for funding rate sources
+#[async_trait]
+pub trait FundingSource: Send + Sync + std::fmt::Debug {
+    /// Get the current funding rate per hour for a symbol
+    ///
+    /// Positive rates are paid by longs to shorts, negative rates by shorts to longs.
+    async fn funding_rate_per_hour(&self, symbol: &str) -> Result<f64, LiquidationError>;
+}
+
+/// Cumulative funding index for a symbol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct FundingIndex {
+    /// Cumulative funding per unit of notional since the index started
+    pub cumulative: f64,
+    /// Hourly funding rate the index last accrued at
+    pub rate_per_hour: f64,
+    /// Timestamp of the last index update
+    pub updated_at: i64,
+}
+
+impl FundingIndex {
+    /// Create a new index starting at zero
+    pub fn new(now: i64) -> Self {
+        Self {
+            cumulative: 0.0,
+            rate_per_hour: 0.0,
+            updated_at: now,
+        }
+    }
+
+    /// Accrue funding at the given hourly rate up to `now`
+    pub fn accrue(&mut self, funding_rate_per_hour: f64, now: i64) {
+        let hours = now.saturating_sub(self.updated_at).max(0) as f64 / 3600.0;
+        self.cumulative += funding_rate_per_hour * hours;
+        self.rate_per_hour = funding_rate_per_hour;
+        self.updated_at = now;
+    }
+}
+
+/// Funding source returning the same rate for every symbol
+#[derive(Debug, Clone, Copy)]
+pub struct FixedRateFunding {
+    funding_rate_per_hour: f64,
+}
+
+impl FixedRateFunding {
+    /// Create a new fixed-rate funding source
+    pub fn new(funding_rate_per_hour: f64) -> Self {
+        Self { funding_rate_per_hour }
+    }
+}
+
+#[async_trait]
+impl FundingSource for FixedRateFunding {
+    async fn funding_rate_per_hour(&self, _symbol: &str) -> Result<f64, LiquidationError> {
+        Ok(self.funding_rate_per_hour)
+    }
+}
+
+/// Mock funding source for testing
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Clone, Default)]
+pub struct MockFundingSource {
+    rates: Arc<RwLock<HashMap<String, f64>>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockFundingSource {
+    /// Create a new mock funding source
+    pub fn new() -> Self {
+        Self {
+            rates: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Set the hourly funding rate for a symbol
+    pub async fn set_rate(&self, symbol: &str, funding_rate_per_hour: f64) {
+        let mut rates = self.rates.write().await;
+        rates.insert(symbol.to_string(), funding_rate_per_hour);
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[async_trait]
+impl FundingSource for MockFundingSource {
+    async fn funding_rate_per_hour(&self, symbol: &str) -> Result<f64, LiquidationError> {
+        self.rates
+            .read()
+            .await
+            .get(symbol)
+            .copied()
+            .ok_or_else(|| LiquidationError::Other(format!("No funding rate for {}", symbol)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_funding_source() {
+        let funding = MockFundingSource::new();
+        funding.set_rate("BTC/USD", 0.0001).await;
+
+        assert_eq!(funding.funding_rate_per_hour("BTC/USD").await.unwrap(), 0.0001);
+        assert!(funding.funding_rate_per_hour("NON_EXISTENT").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fixed_rate_funding() {
+        let funding = FixedRateFunding::new(0.0002);
+        assert_eq!(funding.funding_rate_per_hour("BTC/USD").await.unwrap(), 0.0002);
+        assert_eq!(funding.funding_rate_per_hour("ETH/USD").await.unwrap(), 0.0002);
+    }
+
+    #[test]
+    fn test_funding_index_accrual() {
+        let mut index = FundingIndex::new(0);
+        index.accrue(0.001, 8 * 3600);
+        assert!((index.cumulative - 0.008).abs() < 1e-12);
+        assert_eq!(index.updated_at, 8 * 3600);
+        assert_eq!(index.rate_per_hour, 0.001);
+
+        // Time going backwards accrues nothing
+        index.accrue(0.001, 0);
+        assert!((index.cumulative - 0.008).abs() < 1e-12);
+    }
+}
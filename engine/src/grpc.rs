@@ -0,0 +1,349 @@
+//! gRPC API, enabled with the `grpc` feature
+//!
+//! Serves the `liquidation.v1.LiquidationService` defined in
+//! `proto/liquidation.proto` alongside the engine it shares:
+//!
+//! - `ListPositions` streams the risk metrics of the monitored positions,
+//!   filtered by symbol and owner (empty fields match everything)
+//! - `GetHealth` returns one position's risk metrics at the latest prices
+//! - `SubscribeLiquidations` streams every liquidation attempted from then on
+//! - `ForceCheck` checks one position for liquidation immediately
+//!
+//! Messages mirror the engine's JSON types with pubkeys as base58 strings.
+//! Subscribers that fall behind are dropped with `RESOURCE_EXHAUSTED` rather
+//! than slowing the engine down, and the server stops with the engine's
+//! [`LiquidationEngine::shutdown`].
+
+use crate::{
+    address::{OwnerAddress, PositionAddress},
+    amount::{Amount, AmountExt},
+    error::LiquidationError,
+    liquidation::LiquidationEngine,
+    types::{EngineEvent, LiquidationEvent, LiquidationResult, PositionStatus, PositionUpdate},
+};
+use futures::stream::{BoxStream, StreamExt};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status, transport::Server};
+use tracing::{info, warn};
+
+use proto::liquidation_service_server::{LiquidationService, LiquidationServiceServer};
+
+/// Types generated from `proto/liquidation.proto`
+pub mod proto {
+    tonic::include_proto!("liquidation.v1");
+}
+
+/// Liquidations buffered for a subscriber before it counts as falling behind
+const SUBSCRIPTION_BUFFER: usize = 64;
+
+impl From<PositionStatus> for proto::PositionStatus {
+    fn from(status: PositionStatus) -> Self {
+        match status {
+            PositionStatus::Active => Self::Active,
+            PositionStatus::AtRisk => Self::AtRisk,
+            PositionStatus::Liquidating => Self::Liquidating,
+            PositionStatus::Liquidated => Self::Liquidated,
+            PositionStatus::Closed => Self::Closed,
+        }
+    }
+}
+
+impl From<PositionUpdate> for proto::PositionUpdate {
+    fn from(update: PositionUpdate) -> Self {
+        Self {
+            address: update.address.to_string(),
+            owner: update.owner.to_string(),
+            symbol: update.symbol,
+            size: update.size.as_f64(),
+            entry_price: update.entry_price.as_f64(),
+            margin: update.margin.as_f64(),
+            is_long: update.is_long,
+            status: proto::PositionStatus::from(update.status).into(),
+            at_risk_reason: update.at_risk_reason.map(|reason| reason.to_string()),
+            leverage: update.leverage.as_f64(),
+            liquidation_price: update.liquidation_price.map(Amount::as_f64),
+            distance_to_liquidation_bps: update.distance_to_liquidation_bps,
+            estimated_time_to_liquidation_secs: update.estimated_time_to_liquidation_secs,
+            funding_projections: update
+                .funding_projections
+                .into_iter()
+                .map(|projection| proto::FundingProjection {
+                    hours: projection.hours,
+                    liquidation_price: projection.liquidation_price.map(Amount::as_f64),
+                })
+                .collect(),
+            mark_price: update.mark_price.as_f64(),
+            unrealized_pnl: update.unrealized_pnl.as_f64(),
+            margin_ratio: update.margin_ratio,
+            maintenance_margin: update.maintenance_margin,
+            health_factor: update.health_factor,
+            adl_quantile: update.adl_quantile,
+            timestamp: update.timestamp,
+            exceeds_max_leverage: update.exceeds_max_leverage,
+            metadata: update.metadata,
+        }
+    }
+}
+
+impl From<LiquidationEvent> for proto::LiquidationEvent {
+    fn from(event: LiquidationEvent) -> Self {
+        Self {
+            position: event.position.to_string(),
+            owner: event.owner.to_string(),
+            liquidator: event.liquidator.to_string(),
+            amount: event.amount.as_f64(),
+            remaining_size: event.remaining_size.as_f64(),
+            remaining_margin: event.remaining_margin.as_f64(),
+            liquidation_price: event.liquidation_price.as_f64(),
+            timestamp: event.timestamp,
+            signature: event.signature,
+            bad_debt: event.bad_debt.as_f64(),
+            symbol: event.symbol,
+            reward: event.reward.as_f64(),
+            dry_run: event.dry_run,
+            error: event.error,
+            priority_fee_micro_lamports: event.priority_fee_micro_lamports,
+            metadata: event.metadata,
+        }
+    }
+}
+
+impl From<Option<LiquidationResult>> for proto::CheckResult {
+    fn from(result: Option<LiquidationResult>) -> Self {
+        let kind = match &result {
+            None => "healthy",
+            Some(LiquidationResult::Success { .. }) => "success",
+            Some(LiquidationResult::Failure { .. }) => "failure",
+            Some(LiquidationResult::Skipped { .. }) => "skipped",
+        };
+        Self {
+            result: kind.to_string(),
+            detail: result.map(|result| result.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<LiquidationError> for Status {
+    fn from(err: LiquidationError) -> Self {
+        match err {
+            LiquidationError::PositionNotFound(_) => Status::not_found(err.to_string()),
+            LiquidationError::ConfigError(_) | LiquidationError::Ingest(_) => Status::invalid_argument(err.to_string()),
+            LiquidationError::StaleUpdate { .. } => Status::aborted(err.to_string()),
+            _ => Status::internal(err.to_string()),
+        }
+    }
+}
+
+/// [`LiquidationService`] backed by a shared engine
+pub struct GrpcService {
+    engine: Arc<LiquidationEngine>,
+}
+
+impl GrpcService {
+    /// Serve `engine` over gRPC
+    pub fn new(engine: Arc<LiquidationEngine>) -> Self {
+        Self { engine }
+    }
+}
+
+#[tonic::async_trait]
+impl LiquidationService for GrpcService {
+    type ListPositionsStream = BoxStream<'static, Result<proto::PositionUpdate, Status>>;
+    type SubscribeLiquidationsStream = ReceiverStream<Result<proto::LiquidationEvent, Status>>;
+
+    async fn list_positions(
+        &self,
+        request: Request<proto::PositionFilter>,
+    ) -> Result<Response<Self::ListPositionsStream>, Status> {
+        let filter = request.into_inner();
+        let owner = match filter.owner.as_str() {
+            "" => None,
+            owner => Some(owner.parse::<OwnerAddress>()?),
+        };
+        let mut addresses: Vec<PositionAddress> = self
+            .engine
+            .get_positions()
+            .await
+            .into_iter()
+            .filter(|position| filter.symbol.is_empty() || position.symbol == filter.symbol)
+            .filter(|position| owner.is_none_or(|owner| owner == position.owner))
+            .map(|position| position.address)
+            .collect();
+        addresses.sort();
+
+        // Price positions as they're sent, skipping any removed in the meantime
+        let engine = self.engine.clone();
+        let updates = futures::stream::iter(addresses)
+            .then(move |address| {
+                let engine = engine.clone();
+                async move { engine.position_update(&address).await }
+            })
+            .filter_map(|update| async move {
+                match update {
+                    Ok(update) => Some(Ok(update.into())),
+                    Err(LiquidationError::PositionNotFound(_)) => None,
+                    Err(e) => Some(Err(e.into())),
+                }
+            });
+        Ok(Response::new(updates.boxed()))
+    }
+
+    async fn get_health(
+        &self,
+        request: Request<proto::PositionRequest>,
+    ) -> Result<Response<proto::PositionUpdate>, Status> {
+        let address: PositionAddress = request.into_inner().address.parse()?;
+        Ok(Response::new(self.engine.position_update(&address).await?.into()))
+    }
+
+    async fn subscribe_liquidations(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<Self::SubscribeLiquidationsStream>, Status> {
+        let mut events = self.engine.subscribe();
+        let shutdown = self.engine.shutdown_token();
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = sender.closed() => break,
+                    event = events.recv() => match event {
+                        Ok(EngineEvent::Liquidation(event)) => {
+                            if sender.send(Ok(event.into())).await.is_err() {
+                                break;
+                            }
+                        }
+                        Ok(EngineEvent::PositionUpdate(_)) => {}
+                        Err(RecvError::Lagged(missed)) => {
+                            warn!("Dropping gRPC subscriber that fell {} events behind", missed);
+                            let status = Status::resource_exhausted(format!("Fell {} events behind", missed));
+                            let _ = sender.send(Err(status)).await;
+                            break;
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn force_check(
+        &self,
+        request: Request<proto::PositionRequest>,
+    ) -> Result<Response<proto::CheckResult>, Status> {
+        let address: PositionAddress = request.into_inner().address.parse()?;
+        Ok(Response::new(self.engine.check_position_now(&address).await?.into()))
+    }
+}
+
+/// Serve the gRPC API on `addr` until the engine shuts down
+pub async fn serve(engine: Arc<LiquidationEngine>, addr: SocketAddr) -> Result<(), LiquidationError> {
+    let listener = TcpListener::bind(addr).await?;
+    serve_with_listener(engine, listener).await
+}
+
+/// Serve the gRPC API on a bound listener until the engine shuts down
+pub async fn serve_with_listener(engine: Arc<LiquidationEngine>, listener: TcpListener) -> Result<(), LiquidationError> {
+    info!("gRPC API listening on {}", listener.local_addr()?);
+    let shutdown = engine.shutdown_token();
+    Server::builder()
+        .add_service(LiquidationServiceServer::new(GrpcService::new(engine)))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown.cancelled_owned())
+        .await?;
+    info!("gRPC API stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::MockOracle;
+    use crate::position::Position;
+    use crate::rate_limit::RateLimiter;
+    use crate::types::LiquidationConfig;
+    use proto::liquidation_service_client::LiquidationServiceClient;
+    use solana_client::rpc_client::RpcClient;
+    use solana_sdk::pubkey::Pubkey;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_subscription_streams_liquidations() {
+        let oracle = Arc::new(MockOracle::new());
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = Arc::new(LiquidationEngine::new(
+            rpc_client,
+            oracle.clone(),
+            LiquidationConfig::default(),
+            Arc::new(RateLimiter::default()),
+        ));
+        let position = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 52000.0, 5000.0, true);
+        engine.add_position(position.clone()).await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_with_listener(engine.clone(), listener));
+        let mut client = LiquidationServiceClient::connect(url).await.unwrap();
+
+        let health = client
+            .get_health(proto::PositionRequest {
+                address: position.address.to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(health.owner, position.owner.to_string());
+        assert_eq!(health.status(), proto::PositionStatus::Active);
+        let listed: Vec<_> = client
+            .list_positions(proto::PositionFilter {
+                symbol: "BTC/USD".to_string(),
+                owner: String::new(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .collect()
+            .await;
+        assert_eq!(listed.len(), 1);
+        let status = client
+            .get_health(proto::PositionRequest {
+                address: "not-a-key".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        // The subscriber sees the liquidation a price drop triggers
+        let mut liquidations = client.subscribe_liquidations(proto::Empty {}).await.unwrap().into_inner();
+        oracle.set_price("BTC/USD", 45000.0).await;
+        let result = client
+            .force_check(proto::PositionRequest {
+                address: position.address.to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(result.result, "success");
+        let event = tokio::time::timeout(Duration::from_secs(5), liquidations.message())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.position, position.address.to_string());
+        assert_eq!(event.liquidation_price, 45000.0);
+        assert!(event.dry_run);
+
+        // Shutting the engine down ends the subscription and the server
+        engine.shutdown();
+        let ended = tokio::time::timeout(Duration::from_secs(5), liquidations.message()).await.unwrap();
+        assert!(matches!(ended, Ok(None)));
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,604 @@
+use crate::{
+    address::PositionAddress,
+    amount::{self, Amount},
+    audit::{AuditWriter, DEFAULT_AUDIT_QUEUE_CAPACITY},
+    clock::{Clock, ManualClock},
+    error::LiquidationError,
+    liquidation::LiquidationEngine,
+    position::Position,
+    replay::{PricePoint, ReplayOracle, ReplayReport, ReplayedResult},
+    types::{EngineEvent, LiquidationConfig, LiquidationResult, PositionStatus},
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Virtual time scenarios start at, unless given another
+pub const SCENARIO_START_TS: i64 = 1_700_000_000;
+
+/// Symbols of synthetic positions, with the prices they're opened around
+pub const SYNTHETIC_PRICES: [(&str, f64); 3] = [("BTC/USD", 60000.0), ("ETH/USD", 3000.0), ("SOL/USD", 150.0)];
+
+/// A book of `count` positions spread over [`SYNTHETIC_PRICES`], the same for
+/// the same `seed`
+///
+/// Positions are long or short, worth 1,000 to 100,000 at up to 10x leverage,
+/// and entered within 1% of their symbol's price, so at those prices none is
+/// under the default maintenance margin.
+pub fn synthetic_positions(count: usize, seed: u64) -> Vec<Position> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| {
+            let (symbol, price) = SYNTHETIC_PRICES[rng.gen_range(0..SYNTHETIC_PRICES.len())];
+            let entry_price = price * rng.gen_range(0.99..1.01);
+            let notional = rng.gen_range(1_000.0..100_000.0);
+            let leverage = rng.gen_range(1.0..10.0);
+            Position::new(
+                Pubkey::new_from_array(rng.r#gen()),
+                Pubkey::new_from_array(rng.r#gen()),
+                symbol,
+                amount::from_f64_lossy(notional / entry_price),
+                amount::from_f64_lossy(entry_price),
+                amount::from_f64_lossy(notional / leverage),
+                rng.gen_bool(0.5),
+            )
+        })
+        .collect()
+}
+
+/// A deterministic liquidation scenario: positions, a scripted price path per
+/// symbol, and the configuration the engine runs them with
+///
+/// Each price path gives one price per tick; a symbol whose path ends early holds
+/// its last price for the remaining ticks. The engine always dry runs, checking
+/// every position once per tick on a [`ManualClock`] moved `tick_secs` forward each
+/// time, and liquidations shrink the positions as the program would.
+#[derive(Debug, Clone)]
+pub struct ScenarioBuilder {
+    config: LiquidationConfig,
+    start_ts: i64,
+    tick_secs: u64,
+    positions: Vec<Position>,
+    price_paths: BTreeMap<String, Vec<f64>>,
+    audit_path: Option<PathBuf>,
+}
+
+impl Default for ScenarioBuilder {
+    fn default() -> Self {
+        Self {
+            config: LiquidationConfig::default(),
+            start_ts: SCENARIO_START_TS,
+            tick_secs: 60,
+            positions: Vec::new(),
+            price_paths: BTreeMap::new(),
+            audit_path: None,
+        }
+    }
+}
+
+impl ScenarioBuilder {
+    /// An empty scenario with the default configuration and one-minute ticks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configuration the engine runs with (default: [`LiquidationConfig::default`]);
+    /// `dry_run` is always set
+    pub fn config(&mut self, config: LiquidationConfig) -> &mut Self {
+        self.config = config;
+        self
+    }
+
+    /// Virtual time of the first tick (default: [`SCENARIO_START_TS`])
+    pub fn start_ts(&mut self, start_ts: i64) -> &mut Self {
+        self.start_ts = start_ts;
+        self
+    }
+
+    /// Virtual time between ticks (in seconds, default: 60)
+    pub fn tick_secs(&mut self, tick_secs: u64) -> &mut Self {
+        self.tick_secs = tick_secs;
+        self
+    }
+
+    /// Open a position of `size` in `symbol` at `entry_price` with `leverage`
+    /// times its margin, returning its address
+    pub fn position(
+        &mut self,
+        symbol: &str,
+        is_long: bool,
+        size: f64,
+        entry_price: f64,
+        leverage: f64,
+    ) -> PositionAddress {
+        let margin = size * entry_price / leverage;
+        let position = Position::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            symbol,
+            amount::from_f64_lossy(size),
+            amount::from_f64_lossy(entry_price),
+            amount::from_f64_lossy(margin),
+            is_long,
+        );
+        let address = position.address;
+        self.positions.push(position);
+        address
+    }
+
+    /// Price of `symbol` at each tick
+    pub fn prices(&mut self, symbol: &str, path: &[f64]) -> &mut Self {
+        self.price_paths.insert(symbol.to_string(), path.to_vec());
+        self
+    }
+
+    /// Audit every liquidation attempt to the daily files of `path` (default:
+    /// no audit log)
+    pub fn audit_path(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.audit_path = Some(path.into());
+        self
+    }
+
+    /// Number of ticks, set by the longest price path
+    pub fn ticks(&self) -> usize {
+        self.price_paths.values().map(Vec::len).max().unwrap_or(0)
+    }
+
+    /// Run the scenario end to end
+    pub async fn run(&self) -> Result<ScenarioOutcome, LiquidationError> {
+        let ticks = self.ticks();
+        if ticks == 0 {
+            return Err(LiquidationError::ConfigError("scenario has no prices".to_string()));
+        }
+        let step = i64::try_from(self.tick_secs)
+            .ok()
+            .filter(|step| *step > 0)
+            .ok_or_else(|| LiquidationError::ConfigError(format!("invalid tick of {} seconds", self.tick_secs)))?;
+        let tick_ts = |tick: usize| self.start_ts + step * tick as i64;
+
+        let clock = ManualClock::new(self.start_ts);
+        let series = self
+            .price_paths
+            .iter()
+            .map(|(symbol, path)| {
+                let points = path
+                    .iter()
+                    .enumerate()
+                    .map(|(tick, price)| PricePoint {
+                        timestamp: tick_ts(tick),
+                        price: amount::from_f64_lossy(*price),
+                        confidence: 0.0,
+                    })
+                    .collect();
+                (symbol.clone(), points)
+            })
+            .collect();
+        let oracle = ReplayOracle::new(series, Arc::new(clock.clone()));
+        let config = LiquidationConfig {
+            dry_run: true,
+            ..self.config.clone()
+        };
+        // Room for every position's update and liquidation at every tick, so
+        // no event is dropped before it's collected
+        let event_capacity = (ticks * self.positions.len() * 2).max(1);
+        let mut builder = LiquidationEngine::builder();
+        builder
+            .rpc_client(Arc::new(RpcClient::new("https://api.devnet.solana.com")))
+            .oracle(Arc::new(oracle))
+            .config(config)
+            .clock(Arc::new(clock.clone()))
+            .event_capacity(event_capacity);
+        if let Some(path) = &self.audit_path {
+            builder.audit(AuditWriter::spawn(path, DEFAULT_AUDIT_QUEUE_CAPACITY).0);
+        }
+        let engine = builder.build()?;
+        for position in &self.positions {
+            engine.add_position(position.clone()).await?;
+        }
+
+        let mut receiver = engine.subscribe();
+        let report = engine.run_replay(self.start_ts, tick_ts(ticks - 1), self.tick_secs).await?;
+        let mut events = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        Ok(ScenarioOutcome {
+            report,
+            events,
+            engine,
+            clock,
+        })
+    }
+}
+
+/// What a scenario's engine did, tick by tick
+pub struct ScenarioOutcome {
+    /// Every result, in the order of the ticks
+    pub report: ReplayReport,
+    /// Every event the engine emitted, in order
+    pub events: Vec<EngineEvent>,
+    /// The engine, as the last tick left it
+    pub engine: LiquidationEngine,
+    /// The clock the engine ran on, at the last tick
+    pub clock: ManualClock,
+}
+
+impl ScenarioOutcome {
+    /// Every result, with the time of the tick it came from
+    pub fn results(&self) -> &[ReplayedResult] {
+        &self.report.results
+    }
+
+    /// Successful liquidations, in order
+    pub fn liquidations(&self) -> Vec<&ReplayedResult> {
+        self.results()
+            .iter()
+            .filter(|replayed| matches!(replayed.result, LiquidationResult::Success { .. }))
+            .collect()
+    }
+
+    /// Results skipped for a reason labeled `label` (see
+    /// [`SkipReason::label`](crate::types::SkipReason::label)),
+    /// in order
+    pub fn skipped(&self, label: &str) -> Vec<&ReplayedResult> {
+        self.results()
+            .iter()
+            .filter(|replayed| {
+                matches!(&replayed.result, LiquidationResult::Skipped { reason, .. } if reason.label() == label)
+            })
+            .collect()
+    }
+
+    /// Statuses a position was reported in, in order, without repeats
+    pub fn statuses(&self, address: &PositionAddress) -> Vec<PositionStatus> {
+        let mut statuses: Vec<PositionStatus> = Vec::new();
+        for event in &self.events {
+            if let EngineEvent::PositionUpdate(update) = event
+                && update.address == *address
+                && statuses.last() != Some(&update.status)
+            {
+                statuses.push(update.status);
+            }
+        }
+        statuses
+    }
+
+    /// Size left of each position still monitored
+    pub async fn remaining_sizes(&self) -> HashMap<PositionAddress, Amount> {
+        self.engine
+            .get_positions()
+            .await
+            .into_iter()
+            .map(|position| (position.address, position.size))
+            .collect()
+    }
+
+    /// Virtual time of the last tick
+    pub fn now(&self) -> i64 {
+        self.clock.now_ts()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rounding::SymbolSpec;
+    use crate::types::SkipReason;
+
+    #[test]
+    fn test_synthetic_positions() {
+        let positions = synthetic_positions(1_000, 7);
+        assert_eq!(positions, synthetic_positions(1_000, 7));
+        assert_ne!(positions, synthetic_positions(1_000, 8));
+        let config = LiquidationConfig::default();
+        for position in &positions {
+            let (_, price) = SYNTHETIC_PRICES.iter().find(|(symbol, _)| *symbol == position.symbol).unwrap();
+            let params = config.margin_params_at(position, *price);
+            assert!(!position.is_undercollateralized(*price, params), "{:?}", position);
+        }
+        assert!(positions.iter().any(|position| position.is_long));
+        assert!(positions.iter().any(|position| !position.is_long));
+    }
+
+    #[tokio::test]
+    async fn test_gradual_decline_liquidates_in_parts() {
+        let mut scenario = ScenarioBuilder::new();
+        scenario.config(LiquidationConfig {
+            liquidation_cooldown_secs: 120,
+            min_liquidation_interval_secs: 120,
+            ..Default::default()
+        });
+        // Liquidatable below ~56,842 and bankrupt at 54,000
+        let long = scenario.position("BTC/USD", true, 1.0, 60000.0, 10.0);
+        let healthy = scenario.position("BTC/USD", true, 1.0, 60000.0, 2.0);
+        scenario.prices(
+            "BTC/USD",
+            &[60000.0, 59000.0, 58000.0, 57000.0, 56500.0, 56000.0, 55800.0, 55600.0, 55400.0],
+        );
+        let outcome = scenario.run().await.unwrap();
+
+        // Half the position goes every cooldown once it falls below maintenance
+        let liquidated: Vec<(i64, f64)> = outcome
+            .liquidations()
+            .iter()
+            .map(|replayed| match &replayed.result {
+                LiquidationResult::Success { position, amount, .. } => {
+                    assert_eq!(*position, long);
+                    (replayed.timestamp - SCENARIO_START_TS, *amount)
+                }
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(liquidated, [(240, 0.5), (360, 0.25), (480, 0.125)]);
+        let sizes = outcome.remaining_sizes().await;
+        assert_eq!(sizes[&long], 0.125);
+        assert_eq!(sizes[&healthy], 1.0);
+        assert_eq!(outcome.now(), SCENARIO_START_TS + 480);
+
+        let liquidation_events = outcome
+            .events
+            .iter()
+            .filter(|event| matches!(event, EngineEvent::Liquidation(event) if event.dry_run))
+            .count();
+        assert_eq!(liquidation_events, 3);
+    }
+
+    #[tokio::test]
+    async fn test_liquidations_audited_with_their_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LiquidationConfig {
+            liquidation_cooldown_secs: 120,
+            min_liquidation_interval_secs: 120,
+            ..Default::default()
+        };
+        let mut scenario = ScenarioBuilder::new();
+        scenario.config(config.clone()).audit_path(dir.path().join("audit.jsonl"));
+        let long = scenario.position("BTC/USD", true, 1.0, 60000.0, 10.0);
+        let healthy = scenario.position("BTC/USD", true, 1.0, 60000.0, 2.0);
+        scenario.prices("BTC/USD", &[60000.0, 59000.0, 58000.0, 57000.0, 56500.0, 56000.0, 55800.0]);
+        let outcome = scenario.run().await.unwrap();
+
+        let records = outcome.engine.audit_records(&long).await.unwrap();
+        let audited: Vec<(i64, f64, f64)> = records
+            .iter()
+            .map(|record| (record.timestamp - SCENARIO_START_TS, record.oracle_price, record.amount))
+            .collect();
+        assert_eq!(audited, [(240, 56500.0, 0.5), (360, 55800.0, 0.25)]);
+        // The scenario's engine always dry runs
+        let config_hash = LiquidationConfig { dry_run: true, ..config }.config_hash();
+        let first = &records[0];
+        assert_eq!(first.config_hash, config_hash);
+        assert_eq!(first.config_hash, outcome.engine.config().config_hash());
+        assert_eq!((first.oracle_publish_time, first.oracle_confidence), (first.timestamp, 0.0));
+        assert_eq!(first.maintenance_margin, 0.05);
+        let position = Position::new(long, first.owner, "BTC/USD", 1.0, 60000.0, 6000.0, true);
+        assert!((first.margin_ratio - position.margin_ratio(56500.0)).abs() < 1e-12);
+        assert!(first.margin_ratio < first.maintenance_margin);
+        assert_eq!((first.size, records[1].size), (1.0, 0.5));
+        assert!(first.dry_run && first.error.is_none() && first.attempts == 1);
+        assert!(first.cycle_id.is_some());
+        assert_ne!(first.correlation_id, records[1].correlation_id);
+
+        assert_eq!(outcome.engine.audit_records(&healthy).await.unwrap(), []);
+    }
+
+    #[tokio::test]
+    async fn test_worsening_position_bypasses_cooldown() {
+        let scenario = |cooldown_bypass_delta| {
+            let mut scenario = ScenarioBuilder::new();
+            scenario.config(LiquidationConfig {
+                cooldown_bypass_delta,
+                ..Default::default()
+            });
+            scenario.position("BTC/USD", true, 1.0, 60000.0, 10.0);
+            // Margin ratio of 4.4% at 56,500, then 2.7% at 55,500
+            scenario.prices("BTC/USD", &[60000.0, 56500.0, 56500.0, 55500.0]);
+            scenario
+        };
+        let liquidated_at = |outcome: &ScenarioOutcome| -> Vec<i64> {
+            outcome
+                .liquidations()
+                .iter()
+                .map(|replayed| replayed.timestamp - SCENARIO_START_TS)
+                .collect()
+        };
+
+        // Half goes at 56,500; holding there keeps the rest in cooldown, but
+        // falling past the delta cuts it short well within the 300 seconds
+        let outcome = scenario(Some(0.01)).run().await.unwrap();
+        assert_eq!(liquidated_at(&outcome), [60, 180]);
+        assert_eq!(outcome.skipped("cooldown").len(), 1);
+        assert_eq!(outcome.skipped("cooldown")[0].timestamp, SCENARIO_START_TS + 120);
+
+        // A fall within the delta, or no delta at all, waits the cooldown out
+        let outcome = scenario(Some(0.02)).run().await.unwrap();
+        assert_eq!(liquidated_at(&outcome), [60]);
+        let outcome = scenario(None).run().await.unwrap();
+        assert_eq!(liquidated_at(&outcome), [60]);
+        assert_eq!(outcome.skipped("cooldown").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_flash_crash_trips_circuit_breaker() {
+        let mut scenario = ScenarioBuilder::new();
+        scenario.config(LiquidationConfig {
+            circuit_breaker_liquidation_count: Some(2),
+            circuit_breaker_window_secs: 300,
+            ..Default::default()
+        });
+        let longs: Vec<PositionAddress> =
+            (0..5).map(|_| scenario.position("BTC/USD", true, 1.0, 60000.0, 10.0)).collect();
+        scenario.prices("BTC/USD", &[60000.0, 55000.0, 55500.0, 56000.0]);
+        let outcome = scenario.run().await.unwrap();
+
+        // Three liquidations trip the breaker in the crash, holding the rest back
+        // for as long as the scenario runs
+        let crash_ts = SCENARIO_START_TS + 60;
+        let liquidations = outcome.liquidations();
+        assert_eq!(liquidations.len(), 3);
+        assert!(liquidations.iter().all(|replayed| replayed.timestamp == crash_ts));
+        let paused = outcome.skipped("circuit_breaker");
+        assert_eq!(paused.iter().filter(|replayed| replayed.timestamp == crash_ts).count(), 2);
+        assert!(paused.iter().any(|replayed| replayed.timestamp == outcome.now()));
+        assert_eq!(outcome.engine.paused_since(), Some(crash_ts));
+
+        let sizes = outcome.remaining_sizes().await;
+        assert_eq!(longs.iter().filter(|long| sizes[long] == 1.0).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_recovery_never_liquidates_at_risk_positions() {
+        let mut scenario = ScenarioBuilder::new();
+        let long = scenario.position("BTC/USD", true, 1.0, 60000.0, 10.0);
+        let short = scenario.position("ETH/USD", false, 10.0, 3000.0, 10.0);
+        // Both dip to a health factor of ~1.05 and recover
+        scenario.prices("BTC/USD", &[60000.0, 58500.0, 57000.0, 57000.0, 58500.0, 60000.0]);
+        scenario.prices("ETH/USD", &[3000.0, 3075.0, 3140.0, 3075.0, 3000.0]);
+        let outcome = scenario.run().await.unwrap();
+
+        assert!(outcome.liquidations().is_empty());
+        assert_eq!(outcome.report.steps, 6);
+        let round_trip = [PositionStatus::Active, PositionStatus::AtRisk, PositionStatus::Active];
+        assert_eq!(outcome.statuses(&long), round_trip);
+        assert_eq!(outcome.statuses(&short), round_trip);
+        let sizes = outcome.remaining_sizes().await;
+        assert_eq!((sizes[&long], sizes[&short]), (1.0, 10.0));
+    }
+    
+    #[tokio::test]
+    async fn test_skip_paths_report_their_reasons() {
+        // Longs liquidatable below ~56,842 and bankrupt at 54,000, falling to
+        // `price` at the second tick
+        let skips = |config: LiquidationConfig, longs: usize, price: f64| async move {
+            let mut scenario = ScenarioBuilder::new();
+            scenario.config(config);
+            for _ in 0..longs {
+                scenario.position("BTC/USD", true, 1.0, 60000.0, 10.0);
+            }
+            scenario.prices("BTC/USD", &[60000.0, price]);
+            scenario.prices("SOL/USD", &[100.0]);
+            let outcome = scenario.run().await.unwrap();
+            let reasons: Vec<SkipReason> = outcome
+                .results()
+                .iter()
+                .filter_map(|replayed| match &replayed.result {
+                    LiquidationResult::Skipped { reason, .. } => Some(reason.clone()),
+                    _ => None,
+                })
+                .collect();
+            (outcome, reasons)
+        };
+
+        let spec = SymbolSpec {
+            min_notional: Some(1_000_000.0),
+            ..Default::default()
+        };
+        let (outcome, reasons) = skips(
+            LiquidationConfig {
+                symbol_specs: HashMap::from([("BTC/USD".to_string(), spec)]),
+                ..Default::default()
+            },
+            1,
+            56000.0,
+        )
+        .await;
+        assert!(outcome.liquidations().is_empty());
+        assert_eq!(
+            reasons,
+            [SkipReason::BelowMinSize {
+                amount: 0.5,
+                symbol: "BTC/USD".to_string()
+            }]
+        );
+
+        let (outcome, reasons) = skips(
+            LiquidationConfig {
+                min_profit_quote: 1_000_000.0,
+                ..Default::default()
+            },
+            1,
+            56000.0,
+        )
+        .await;
+        assert!(outcome.liquidations().is_empty());
+        assert!(matches!(reasons.as_slice(), [SkipReason::Unprofitable(_)]), "{:?}", reasons);
+
+        let (outcome, reasons) = skips(
+            LiquidationConfig {
+                max_window_bad_debt: Some(100.0),
+                ..Default::default()
+            },
+            1,
+            53000.0,
+        )
+        .await;
+        assert!(outcome.liquidations().is_empty());
+        assert!(matches!(reasons.as_slice(), [SkipReason::BadDebtLimit { .. }]), "{:?}", reasons);
+
+        // One liquidation per cycle leaves the second long to the next
+        let (outcome, reasons) = skips(
+            LiquidationConfig {
+                max_liquidations_per_symbol_per_cycle: Some(1),
+                ..Default::default()
+            },
+            2,
+            56000.0,
+        )
+        .await;
+        assert_eq!(outcome.liquidations().len(), 1);
+        assert_eq!(reasons, [SkipReason::Throttled]);
+        assert_eq!(outcome.skipped("throttled").len(), 1);
+        assert_eq!(outcome.engine.throttle_stats().total_skipped, BTreeMap::from([("throttled".to_string(), 1)]));
+    }
+
+    #[tokio::test]
+    async fn test_margin_call_flags_then_liquidates_after_grace() {
+        let config = LiquidationConfig {
+            margin_call_grace_secs: 120,
+            ..Default::default()
+        };
+        let mut scenario = ScenarioBuilder::new();
+        scenario.config(config.clone());
+        let flagged = scenario.position("BTC/USD", true, 1.0, 60000.0, 10.0);
+        // Liquidatable from the second tick at a health of ~0.95, above the instant
+        // threshold
+        scenario.prices("BTC/USD", &[60000.0, 56700.0, 56700.0, 56700.0]);
+        scenario.prices("SOL/USD", &[100.0]);
+        let outcome = scenario.run().await.unwrap();
+        let grace: Vec<(i64, &SkipReason)> = outcome
+            .skipped("grace_period_active")
+            .into_iter()
+            .filter_map(|replayed| match &replayed.result {
+                LiquidationResult::Skipped { reason, .. } => Some((replayed.timestamp, reason)),
+                _ => None,
+            })
+            .collect();
+        // Flagged at the first liquidatable tick, and waiting at the next
+        let flagged_at = SCENARIO_START_TS + 60;
+        let grace_ends_at = flagged_at + 120;
+        assert_eq!(
+            grace,
+            [
+                (flagged_at, &SkipReason::GracePeriodActive { grace_ends_at }),
+                (flagged_at + 60, &SkipReason::GracePeriodActive { grace_ends_at }),
+            ]
+        );
+        let liquidations = outcome.liquidations();
+        assert_eq!(liquidations.len(), 1);
+        assert_eq!(liquidations[0].timestamp, grace_ends_at);
+        assert!(matches!(liquidations[0].result, LiquidationResult::Success { position, .. } if position == flagged));
+
+        // Below the instant threshold there's no grace period
+        let mut scenario = ScenarioBuilder::new();
+        scenario.config(config);
+        scenario.position("BTC/USD", true, 1.0, 60000.0, 10.0);
+        scenario.prices("BTC/USD", &[60000.0, 55000.0]);
+        scenario.prices("SOL/USD", &[100.0]);
+        let outcome = scenario.run().await.unwrap();
+        assert!(outcome.skipped("grace_period_active").is_empty());
+        assert_eq!(outcome.liquidations().len(), 1);
+    }
+}
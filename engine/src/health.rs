@@ -0,0 +1,384 @@
+use crate::address::{OwnerAddress, PositionAddress};
+use crate::amount::{self, Amount, AmountExt};
+use crate::error::LiquidationError;
+use crate::position::{MarginParams, Position};
+use anchor_lang::{AccountDeserialize, AccountSerialize, Discriminator};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::pubkey::Pubkey;
+
+/// Position account stored by the on-chain liquidation program
+pub use liquidation_program::Position as PositionAccount;
+
+/// Market config account of the on-chain liquidation program
+pub use liquidation_program::Market as MarketAccount;
+
+/// Address of the on-chain liquidation program
+pub const PROGRAM_ID: Pubkey = liquidation_program::ID;
+
+/// Offset of the owner in a position account, after the account discriminator
+const OWNER_OFFSET: usize = 8;
+
+/// Share of the collateral's value the program counts against debt: it
+/// liquidates once debt exceeds this much of it
+const LIQUIDATION_THRESHOLD: f64 = liquidation_program::LIQUIDATION_THRESHOLD_BPS as f64 / 10_000.0;
+
+/// Length of a position account: its discriminator, owner, bump, collateral,
+/// debt, closed flag and margin call flag
+pub(crate) const POSITION_ACCOUNT_LEN: usize = PositionAccount::SPACE;
+
+/// Length of a position account created before margin calls, without the
+/// margin call flag
+pub(crate) const LEGACY_POSITION_ACCOUNT_LEN: usize = PositionAccount::LEGACY_SPACE;
+
+/// Decimals of a market's collateral and debt mints, scaling account amounts
+/// to whole tokens
+///
+/// The default of zero leaves amounts in base units, as the program counts them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MintDecimals {
+    /// Decimals of the collateral mint
+    pub collateral: u8,
+    /// Decimals of the debt mint
+    pub debt: u8,
+}
+
+/// Decode a position account's data, checking its length and discriminator
+///
+/// Accounts not yet migrated to the margin call layout decode as unflagged.
+/// Data too short for the legacy layout fails with
+/// [`LiquidationError::AccountTooShort`] and data of another account type with
+/// [`LiquidationError::AccountDiscriminatorMismatch`].
+pub fn decode_position_account(data: &[u8]) -> Result<PositionAccount, LiquidationError> {
+    if data.len() < LEGACY_POSITION_ACCOUNT_LEN {
+        return Err(LiquidationError::AccountTooShort {
+            expected: LEGACY_POSITION_ACCOUNT_LEN,
+            actual: data.len(),
+        });
+    }
+    let mut actual = [0; 8];
+    actual.copy_from_slice(&data[..8]);
+    if actual != PositionAccount::DISCRIMINATOR {
+        return Err(LiquidationError::AccountDiscriminatorMismatch {
+            expected: PositionAccount::DISCRIMINATOR,
+            actual,
+        });
+    }
+    let mut padded = data.to_vec();
+    padded.resize(padded.len().max(POSITION_ACCOUNT_LEN), 0);
+    PositionAccount::try_deserialize(&mut padded.as_slice())
+        .map_err(|e| LiquidationError::Other(format!("Invalid position account: {}", e)))
+}
+
+/// Decode the program's market config account, checking its discriminator
+pub fn decode_market_account(data: &[u8]) -> Result<MarketAccount, LiquidationError> {
+    let mut data = data;
+    MarketAccount::try_deserialize(&mut data)
+        .map_err(|e| LiquidationError::Other(format!("Invalid market account: {}", e)))
+}
+
+/// Encode a position account the way the program stores it
+pub fn encode_position_account(account: &PositionAccount) -> Vec<u8> {
+    let mut data = Vec::new();
+    account
+        .try_serialize(&mut data)
+        .expect("serializing into a Vec can't fail");
+    data
+}
+
+/// `getProgramAccounts` filters selecting position accounts, optionally only
+/// those held by `owner`
+pub fn position_account_filters(owner: Option<&Pubkey>) -> Vec<RpcFilterType> {
+    let mut filters = vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+        0,
+        &PositionAccount::DISCRIMINATOR,
+    ))];
+    if let Some(owner) = owner {
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(OWNER_OFFSET, owner.as_ref())));
+    }
+    filters
+}
+
+/// `getProgramAccounts` and `programSubscribe` config selecting every position
+/// account, base64 encoded
+pub(crate) fn position_accounts_config() -> RpcProgramAccountsConfig {
+    RpcProgramAccountsConfig {
+        filters: Some(position_account_filters(None)),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Model a position account as a monitored position in its collateral, priced
+/// in `symbol`, with amounts scaled by the market's `decimals`
+///
+/// The account becomes a long of its collateral entered where the collateral
+/// exactly covers the debt, with no margin: its equity is collateral value less
+/// debt, so it's bankrupt once the collateral no longer covers the debt, and at
+/// a maintenance margin of 15%, one less the program's liquidation threshold,
+/// liquidatable exactly when the program allows liquidation. Returns `None` for
+/// closed accounts and those without collateral, which no price can make cover
+/// the debt.
+pub fn position_from_account(
+    address: Pubkey,
+    account: &PositionAccount,
+    symbol: &str,
+    decimals: MintDecimals,
+) -> Option<Position> {
+    if account.closed || account.collateral == 0 {
+        return None;
+    }
+    let size = amount::from_units(account.collateral, decimals.collateral);
+    let debt = amount::from_units(account.debt, decimals.debt);
+    Some(Position::new(address, account.owner, symbol, size, debt.checked_div(size)?, Amount::ZERO, true))
+}
+
+/// Health of a position at a given price
+///
+/// On-chain accounts hold collateral against debt; monitored positions hold
+/// equity (margin plus PnL) against their notional.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PositionHealth {
+    /// The address of the position account
+    pub address: PositionAddress,
+    /// The owner of the position
+    pub owner: OwnerAddress,
+    /// Symbol the collateral is priced in, or `None` if it's counted one for
+    /// one against the debt as the program does
+    pub symbol: Option<String>,
+    /// Price the health was computed at
+    pub mark_price: f64,
+    /// Value of the collateral (equity for monitored positions)
+    pub collateral: f64,
+    /// Debt owed (notional for monitored positions)
+    pub debt: f64,
+    /// Collateral over debt, or `None` without debt
+    pub margin_ratio: Option<f64>,
+    /// Margin ratio below which the position can be liquidated
+    pub maintenance_margin: f64,
+    /// Whether the position can be liquidated at the mark price
+    pub liquidatable: bool,
+    /// Price at which the position becomes liquidatable, if any
+    pub liquidation_price: Option<f64>,
+    /// Fraction of the mark price the price must move against the position to
+    /// reach the liquidation price; negative once past it
+    pub distance_to_liquidation: Option<f64>,
+}
+
+impl PositionHealth {
+    /// Health of an on-chain position account with its collateral priced at
+    /// `collateral_price`
+    ///
+    /// The program liquidates once debt exceeds 85% of the collateral's value
+    /// (`LIQUIDATION_THRESHOLD_BPS`), so the maintenance margin is 1 / 85%,
+    /// about 117.6%, and positions are liquidatable while collateral still
+    /// covers debt.
+    pub fn from_account(
+        address: Pubkey,
+        account: &PositionAccount,
+        symbol: Option<&str>,
+        collateral_price: f64,
+    ) -> Self {
+        let units = account.collateral as f64;
+        let collateral = units * collateral_price;
+        let debt = account.debt as f64;
+        let liquidation_price = (units > 0.0 && debt > 0.0).then(|| debt / (units * LIQUIDATION_THRESHOLD));
+        Self {
+            address: address.into(),
+            owner: account.owner.into(),
+            symbol: symbol.map(str::to_string),
+            mark_price: collateral_price,
+            collateral,
+            debt,
+            margin_ratio: (debt > 0.0).then(|| collateral / debt),
+            maintenance_margin: 1.0 / LIQUIDATION_THRESHOLD,
+            liquidatable: collateral * LIQUIDATION_THRESHOLD < debt,
+            liquidation_price,
+            distance_to_liquidation: distance(collateral_price, liquidation_price, true),
+        }
+    }
+
+    /// Health of a monitored position at `mark_price`, held to the maintenance
+    /// margin `params` require of its side
+    pub fn from_position(position: &Position, mark_price: Amount, params: MarginParams) -> Self {
+        let debt = position.value(mark_price).as_f64();
+        let liquidation_price = position.liquidation_price(params).map(Amount::as_f64);
+        Self {
+            address: position.address,
+            owner: position.owner,
+            symbol: Some(position.symbol.clone()),
+            mark_price: mark_price.as_f64(),
+            collateral: (position.effective_margin() + position.unrealized_pnl(mark_price)).as_f64(),
+            debt,
+            margin_ratio: (debt > 0.0).then(|| position.margin_ratio(mark_price).as_f64()),
+            maintenance_margin: position.maintenance_margin(params).as_f64(),
+            liquidatable: position.is_undercollateralized(mark_price, params),
+            liquidation_price,
+            distance_to_liquidation: distance(mark_price.as_f64(), liquidation_price, position.is_long),
+        }
+    }
+}
+
+/// Relative move from `price` to `liquidation_price`, positive while it's
+/// still ahead: below the price for longs, above for shorts
+fn distance(price: f64, liquidation_price: Option<f64>, is_long: bool) -> Option<f64> {
+    let liquidation_price = liquidation_price?;
+    if price <= 0.0 {
+        return None;
+    }
+    let distance = (price - liquidation_price) / price;
+    Some(if is_long { distance } else { -distance })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_account(collateral: u64, debt: u64) -> PositionAccount {
+        PositionAccount {
+            owner: Pubkey::new_unique(),
+            bump: 255,
+            collateral,
+            debt,
+            closed: false,
+            flagged_at: 0,
+            flag_price: 0,
+            flag_price_expo: 0,
+        }
+    }
+
+    #[test]
+    fn test_position_account_round_trip() {
+        let account = create_account(1_500, 1_000);
+        let data = encode_position_account(&account);
+        assert_eq!(&data[..8], &PositionAccount::DISCRIMINATOR);
+        assert_eq!(&data[OWNER_OFFSET..OWNER_OFFSET + 32], account.owner.as_ref());
+
+        let decoded = decode_position_account(&data).unwrap();
+        assert_eq!(
+            (decoded.owner, decoded.bump, decoded.collateral, decoded.debt),
+            (account.owner, 255, 1_500, 1_000)
+        );
+
+        let mut other = data.clone();
+        other[0] ^= 1;
+        assert!(matches!(
+            decode_position_account(&other),
+            Err(LiquidationError::AccountDiscriminatorMismatch { expected, actual })
+                if expected == PositionAccount::DISCRIMINATOR && actual[0] == expected[0] ^ 1
+        ));
+        assert!(matches!(
+            decode_position_account(&data[..20]),
+            Err(LiquidationError::AccountTooShort { expected: LEGACY_POSITION_ACCOUNT_LEN, actual: 20 })
+        ));
+        // A market account is long enough but of another type
+        assert!(matches!(
+            decode_position_account(&[MarketAccount::DISCRIMINATOR.as_slice(), &[0; 64]].concat()),
+            Err(LiquidationError::AccountDiscriminatorMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_program_fixture() {
+        // Serialized by the program's own tests, in SOL at 9 decimals against
+        // USDC at 6
+        let data = include_bytes!("../fixtures/accounts/position.bin");
+        assert_eq!(data.len(), POSITION_ACCOUNT_LEN);
+        let account = decode_position_account(data).unwrap();
+        assert_eq!(account.owner, Pubkey::new_from_array([7; 32]));
+        assert_eq!((account.bump, account.collateral, account.debt), (254, 567_000_000_000, 60_000_000_000));
+        assert!(!account.closed);
+        assert_eq!(encode_position_account(&account), data);
+
+        let address = Pubkey::new_unique();
+        let decimals = MintDecimals { collateral: 9, debt: 6 };
+        let position = position_from_account(address, &account, "SOL/USD", decimals).unwrap();
+        assert_eq!((position.symbol.as_str(), position.size), ("SOL/USD", 567.0));
+        assert!((position.size * position.entry_price - 60_000.0).abs() < 1e-6);
+
+        let closed = PositionAccount { closed: true, ..account };
+        assert!(position_from_account(address, &closed, "SOL/USD", decimals).is_none());
+
+        // Accounts created before margin calls decode as unflagged
+        let legacy = include_bytes!("../fixtures/accounts/position_legacy.bin");
+        assert_eq!(legacy.len(), LEGACY_POSITION_ACCOUNT_LEN);
+        assert_eq!(&data[..LEGACY_POSITION_ACCOUNT_LEN], legacy);
+        let account = decode_position_account(legacy).unwrap();
+        assert_eq!((account.collateral, account.debt), (567_000_000_000, 60_000_000_000));
+        assert!(!account.is_flagged());
+    }
+
+    #[test]
+    fn test_account_health() {
+        let account = create_account(100, 150);
+        let address = Pubkey::new_unique();
+
+        // 200 of collateral counts for 170 against 150 of debt, as the
+        // program counts it
+        let health = PositionHealth::from_account(address, &account, Some("SOL/USD"), 2.0);
+        assert_eq!((health.collateral, health.debt), (200.0, 150.0));
+        assert_eq!(health.margin_ratio, Some(200.0 / 150.0));
+        assert!((health.maintenance_margin - 1.0 / 0.85).abs() < 1e-12);
+        assert!(!health.liquidatable);
+        let liquidation_price = health.liquidation_price.unwrap();
+        assert!((liquidation_price - 150.0 / 85.0).abs() < 1e-12);
+        assert!((health.distance_to_liquidation.unwrap() - (2.0 - liquidation_price) / 2.0).abs() < 1e-12);
+
+        // At 1.70 the collateral still covers the debt, but counts for only
+        // 144.50 of it
+        let health = PositionHealth::from_account(address, &account, None, 1.7);
+        assert!(health.collateral > health.debt);
+        assert!(health.liquidatable);
+        assert!(health.distance_to_liquidation.unwrap() < 0.0);
+
+        let health = PositionHealth::from_account(address, &account, None, 1.0);
+        assert!(health.liquidatable);
+
+        let health = PositionHealth::from_account(address, &create_account(100, 0), None, 1.0);
+        assert_eq!(health.margin_ratio, None);
+        assert_eq!(health.liquidation_price, None);
+        assert!(!health.liquidatable);
+    }
+
+    #[test]
+    fn test_position_from_account() {
+        let address = Pubkey::new_unique();
+        let account = create_account(100, 150);
+        let position = position_from_account(address, &account, "SOL/USD", MintDecimals::default()).unwrap();
+        assert_eq!((position.address, position.owner), (PositionAddress(address), OwnerAddress(account.owner)));
+        assert_eq!(position.entry_price, 1.5);
+        // Liquidatable and bankrupt below the price where collateral covers debt
+        assert!(!position.is_undercollateralized(1.6, MarginParams::shared(0.0)));
+        assert!(position.is_undercollateralized(1.4, MarginParams::shared(0.0)));
+        assert_eq!(position.bankruptcy_price(), Some(1.5));
+        assert!((position.bad_debt(1.0) - 50.0).abs() < 1e-9);
+        assert!(position_from_account(address, &create_account(0, 150), "SOL/USD", MintDecimals::default()).is_none());
+    }
+
+    #[test]
+    fn test_position_health() {
+        let long = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 50_000.0, 5_000.0, true);
+        let health = PositionHealth::from_position(&long, 50_000.0, MarginParams::shared(0.05));
+        assert_eq!((health.collateral, health.debt), (5_000.0, 50_000.0));
+        assert_eq!(health.margin_ratio, Some(0.1));
+        assert!(!health.liquidatable);
+        // margin + (p - entry) = 0.05 * p
+        let liquidation_price = health.liquidation_price.unwrap();
+        assert!((liquidation_price - 45_000.0 / 0.95).abs() < 1e-6);
+        assert!(health.distance_to_liquidation.unwrap() > 0.0);
+
+        let short = Position { is_long: false, ..long };
+        let health = PositionHealth::from_position(&short, 60_000.0, MarginParams::shared(0.05));
+        assert!(health.liquidatable);
+        assert!(health.distance_to_liquidation.unwrap() < 0.0);
+
+        let empty = Position { size: 0.0, ..short };
+        let health = PositionHealth::from_position(&empty, 60_000.0, MarginParams::shared(0.05));
+        assert_eq!(health.margin_ratio, None);
+        assert_eq!(health.distance_to_liquidation, None);
+    }
+}
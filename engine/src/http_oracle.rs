@@ -0,0 +1,393 @@
+use crate::amount::{self, Amount};
+use crate::error::LiquidationError;
+use crate::oracle::{OracleProvider, PriceData, check_staleness};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Header sent with every request to a price API, e.g. its API key
+#[derive(Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+// Header values are usually secrets, so they stay out of logs
+impl fmt::Debug for HttpHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpHeader")
+            .field("name", &self.name)
+            .field("value", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Unit of numeric timestamps in price responses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampUnit {
+    #[default]
+    Seconds,
+    Milliseconds,
+}
+
+/// Configuration of an [`HttpOracle`]
+///
+/// Responses are JSON objects holding the price and the time it was computed
+/// at, found by dotted paths such as `data.price` (array elements by index,
+/// e.g. `data.0.price`). Prices may be numbers or numeric strings, and
+/// timestamps numbers in `timestamp_unit` or RFC 3339 strings.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct HttpOracleConfig {
+    /// Base URL of the price API, e.g. `https://prices.example.com`
+    pub base_url: String,
+    /// Path of a symbol's price under the base URL, in which `{symbol}` is
+    /// replaced by the symbol (percent-encoded) and `{base}` and `{quote}` by
+    /// the assets on either side of its `/`, e.g. `/v1/index/{base}-{quote}`
+    pub path_template: String,
+    /// Paths of the symbols the template doesn't fit, by symbol
+    pub symbol_paths: HashMap<String, String>,
+    /// Header sent with every request
+    pub auth_header: Option<HttpHeader>,
+    /// Time a request may take, response included (in milliseconds)
+    pub timeout_ms: u64,
+    /// Maximum age of a price by its response timestamp (in seconds)
+    pub max_price_age_secs: u64,
+    /// Path of the price in a response
+    pub price_field: String,
+    /// Path of the time the price was computed at in a response
+    pub timestamp_field: String,
+    /// Unit of numeric timestamps
+    pub timestamp_unit: TimestampUnit,
+}
+
+impl Default for HttpOracleConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "http://127.0.0.1:8080".to_string(),
+            path_template: "/price/{symbol}".to_string(),
+            symbol_paths: HashMap::new(),
+            auth_header: None,
+            timeout_ms: 2_000,
+            max_price_age_secs: 30,
+            price_field: "price".to_string(),
+            timestamp_field: "timestamp".to_string(),
+            timestamp_unit: TimestampUnit::Seconds,
+        }
+    }
+}
+
+/// Oracle reading index prices computed off chain from an HTTP API
+///
+/// Clones share one connection pool; [`with_client`](Self::with_client) shares
+/// a pool with other users of the API too.
+#[derive(Debug, Clone)]
+pub struct HttpOracle {
+    client: reqwest::Client,
+    config: HttpOracleConfig,
+}
+
+impl HttpOracle {
+    /// Create an oracle with a connection pool of its own
+    pub fn new(config: HttpOracleConfig) -> Result<Self, LiquidationError> {
+        let client = reqwest::Client::builder()
+            .build()
+            .map_err(|e| LiquidationError::ConfigError(format!("Failed to build the price API client: {}", e)))?;
+        Self::with_client(config, client)
+    }
+
+    /// Create an oracle sending its requests through `client`
+    pub fn with_client(config: HttpOracleConfig, client: reqwest::Client) -> Result<Self, LiquidationError> {
+        reqwest::Url::parse(&config.base_url)
+            .map_err(|e| LiquidationError::ConfigError(format!("Invalid price API URL {}: {}", config.base_url, e)))?;
+        if config.timeout_ms == 0 {
+            return Err(LiquidationError::ConfigError("Price API timeout must be positive".to_string()));
+        }
+        Ok(Self { client, config })
+    }
+
+    /// URL of a symbol's price
+    pub fn url(&self, symbol: &str) -> String {
+        let path = match self.config.symbol_paths.get(symbol) {
+            Some(path) => path.clone(),
+            None => {
+                let (base, quote) = symbol.split_once('/').unwrap_or((symbol, ""));
+                self.config
+                    .path_template
+                    .replace("{symbol}", &encode_component(symbol))
+                    .replace("{base}", &encode_component(base))
+                    .replace("{quote}", &encode_component(quote))
+            }
+        };
+        format!("{}{}", self.config.base_url.trim_end_matches('/'), path)
+    }
+
+    /// Fetch a symbol's price and the time it was computed at, rejecting
+    /// stale ones
+    async fn fetch(&self, symbol: &str) -> Result<(f64, i64), LiquidationError> {
+        let (price, publish_time) = self.quote(symbol).await?;
+        check_staleness(symbol, publish_time, chrono::Utc::now().timestamp(), self.config.max_price_age_secs)?;
+        Ok((price, publish_time))
+    }
+
+    /// Fetch a symbol's price and the time it was computed at, whatever its age
+    pub async fn quote(&self, symbol: &str) -> Result<(f64, i64), LiquidationError> {
+        let url = self.url(symbol);
+        let mut request = self.client.get(&url).timeout(Duration::from_millis(self.config.timeout_ms));
+        if let Some(header) = &self.config.auth_header {
+            request = request.header(header.name.as_str(), header.value.as_str());
+        }
+        let response = request.send().await.map_err(|e| self.request_error(symbol, e))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(LiquidationError::OracleError(format!(
+                "Price API answered {} for {}",
+                status, symbol
+            )));
+        }
+        let body = response.bytes().await.map_err(|e| self.request_error(symbol, e))?;
+        let body: Value = serde_json::from_slice(&body)
+            .map_err(|e| LiquidationError::OracleError(format!("Malformed price response for {}: {}", symbol, e)))?;
+
+        let price = field(&body, &self.config.price_field)
+            .and_then(number)
+            .filter(|price| price.is_finite() && *price > 0.0)
+            .ok_or_else(|| {
+                LiquidationError::OracleError(format!(
+                    "No positive price at {} in the response for {}",
+                    self.config.price_field, symbol
+                ))
+            })?;
+        let publish_time = field(&body, &self.config.timestamp_field)
+            .and_then(|value| timestamp(value, self.config.timestamp_unit))
+            .ok_or_else(|| {
+                LiquidationError::OracleError(format!(
+                    "No timestamp at {} in the response for {}",
+                    self.config.timestamp_field, symbol
+                ))
+            })?;
+        Ok((price, publish_time))
+    }
+
+    fn request_error(&self, symbol: &str, err: reqwest::Error) -> LiquidationError {
+        if err.is_timeout() {
+            LiquidationError::OracleTimeout {
+                symbol: symbol.to_string(),
+                timeout_ms: self.config.timeout_ms,
+            }
+        } else {
+            LiquidationError::OracleError(format!("Failed to fetch the price of {}: {}", symbol, err))
+        }
+    }
+}
+
+#[async_trait]
+impl OracleProvider for HttpOracle {
+    async fn get_price(&self, symbol: &str) -> Result<Amount, LiquidationError> {
+        amount::from_f64(self.fetch(symbol).await?.0)
+    }
+
+    async fn get_price_data(&self, symbol: &str) -> Result<PriceData, LiquidationError> {
+        let (price, publish_time) = self.fetch(symbol).await?;
+        Ok(PriceData::from_price(amount::from_f64(price)?, publish_time))
+    }
+
+    async fn last_update_time(&self, symbol: &str) -> Result<u64, LiquidationError> {
+        Ok(self.fetch(symbol).await?.1.max(0) as u64)
+    }
+}
+
+/// Percent-encode everything but unreserved characters, so a symbol fits in
+/// one path segment
+fn encode_component(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Value at a dotted path, indexing arrays by number
+fn field<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => value.get(key),
+    })
+}
+
+/// A number, or a string holding one
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// A Unix timestamp (in seconds) from a number in `unit` or an RFC 3339 string
+fn timestamp(value: &Value, unit: TimestampUnit) -> Option<i64> {
+    if let Value::String(text) = value
+        && let Ok(time) = chrono::DateTime::parse_from_rfc3339(text)
+    {
+        return Some(time.timestamp());
+    }
+    let number = number(value)?;
+    let secs = match unit {
+        TimestampUnit::Seconds => number,
+        TimestampUnit::Milliseconds => number / 1_000.0,
+    };
+    secs.is_finite().then_some(secs.floor() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn config(server: &MockServer) -> HttpOracleConfig {
+        HttpOracleConfig {
+            base_url: server.uri(),
+            path_template: "/v1/index/{base}-{quote}".to_string(),
+            auth_header: Some(HttpHeader {
+                name: "x-api-key".to_string(),
+                value: "secret".to_string(),
+            }),
+            timeout_ms: 200,
+            price_field: "data.price".to_string(),
+            timestamp_field: "data.ts".to_string(),
+            timestamp_unit: TimestampUnit::Milliseconds,
+            ..Default::default()
+        }
+    }
+
+    async fn serve(server: &MockServer, route: &str, response: ResponseTemplate) {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .and(header("x-api-key", "secret"))
+            .respond_with(response)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_price_read_from_configured_fields() {
+        let server = MockServer::start().await;
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let body = json!({ "data": { "price": "61234.5", "ts": now_ms - 5_000 } });
+        serve(&server, "/v1/index/BTC-USD", ResponseTemplate::new(200).set_body_json(body)).await;
+        let oracle = HttpOracle::new(config(&server)).unwrap();
+
+        assert_eq!(oracle.get_price("BTC/USD").await.unwrap(), 61234.5);
+        let data = oracle.get_price_data("BTC/USD").await.unwrap();
+        assert_eq!(data, PriceData::from_price(61234.5, now_ms / 1_000 - 5));
+        assert_eq!(oracle.last_update_time("BTC/USD").await.unwrap(), (now_ms / 1_000 - 5) as u64);
+
+        // Per-symbol paths, arrays and RFC 3339 timestamps
+        let body = json!([{ "last": 101.25, "time": chrono::Utc::now().to_rfc3339() }]);
+        serve(&server, "/markets/new-listing", ResponseTemplate::new(200).set_body_json(body)).await;
+        let oracle = HttpOracle::new(HttpOracleConfig {
+            symbol_paths: HashMap::from([("NEW/USD".to_string(), "/markets/new-listing".to_string())]),
+            price_field: "0.last".to_string(),
+            timestamp_field: "0.time".to_string(),
+            ..config(&server)
+        })
+        .unwrap();
+        assert_eq!(oracle.get_price("NEW/USD").await.unwrap(), 101.25);
+
+        // The symbol is encoded to fit one segment, and the key stays out of logs
+        let oracle = HttpOracle::new(HttpOracleConfig {
+            path_template: "/price/{symbol}".to_string(),
+            ..config(&server)
+        })
+        .unwrap();
+        assert_eq!(oracle.url("BTC/USD"), format!("{}/price/BTC%2FUSD", server.uri()));
+        assert!(!format!("{:?}", oracle).contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_responses_are_oracle_errors() {
+        let server = MockServer::start().await;
+        serve(&server, "/v1/index/BTC-USD", ResponseTemplate::new(200).set_body_string("{\"data\": {\"price\":")).await;
+        let body = json!({ "data": { "price": "n/a", "ts": chrono::Utc::now().timestamp_millis() } });
+        serve(&server, "/v1/index/ETH-USD", ResponseTemplate::new(200).set_body_json(body)).await;
+        serve(&server, "/v1/index/SOL-USD", ResponseTemplate::new(503)).await;
+        let oracle = HttpOracle::new(config(&server)).unwrap();
+
+        for (symbol, message) in [
+            ("BTC/USD", "Malformed price response"),
+            ("ETH/USD", "No positive price at data.price"),
+            ("SOL/USD", "503"),
+        ] {
+            match oracle.get_price(symbol).await {
+                Err(LiquidationError::OracleError(error)) => assert!(error.contains(message), "{}", error),
+                other => panic!("expected an oracle error for {}, got {:?}", symbol, other),
+            }
+        }
+        // A refused connection is a transport error
+        let oracle = HttpOracle::new(HttpOracleConfig {
+            base_url: "http://127.0.0.1:1".to_string(),
+            ..config(&server)
+        })
+        .unwrap();
+        assert!(matches!(oracle.get_price("BTC/USD").await, Err(LiquidationError::OracleError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_stale_timestamp_rejected() {
+        let server = MockServer::start().await;
+        let body = json!({ "data": { "price": 61000, "ts": chrono::Utc::now().timestamp_millis() - 120_000 } });
+        serve(&server, "/v1/index/BTC-USD", ResponseTemplate::new(200).set_body_json(body)).await;
+        let oracle = HttpOracle::new(config(&server)).unwrap();
+
+        match oracle.get_price("BTC/USD").await {
+            Err(LiquidationError::StalePrice { symbol, age_secs, max_age_secs }) => {
+                assert_eq!((symbol.as_str(), max_age_secs), ("BTC/USD", 30));
+                assert!((119..=125).contains(&age_secs), "{}", age_secs);
+            }
+            other => panic!("expected a stale price, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout_is_retryable() {
+        let server = MockServer::start().await;
+        let body = json!({ "data": { "price": 61000, "ts": chrono::Utc::now().timestamp_millis() } });
+        let slow = ResponseTemplate::new(200).set_body_json(body).set_delay(Duration::from_secs(2));
+        serve(&server, "/v1/index/BTC-USD", slow).await;
+        let oracle = HttpOracle::new(config(&server)).unwrap();
+
+        let error = oracle.get_price("BTC/USD").await.unwrap_err();
+        assert!(
+            matches!(&error, LiquidationError::OracleTimeout { symbol, timeout_ms: 200 } if symbol == "BTC/USD"),
+            "{:?}",
+            error
+        );
+        assert!(error.is_retryable());
+    }
+
+    #[test]
+    fn test_invalid_config_rejected() {
+        let invalid = [
+            HttpOracleConfig {
+                base_url: "not a url".to_string(),
+                ..Default::default()
+            },
+            HttpOracleConfig {
+                timeout_ms: 0,
+                ..Default::default()
+            },
+        ];
+        for config in invalid {
+            assert!(matches!(HttpOracle::new(config), Err(LiquidationError::ConfigError(_))));
+        }
+    }
+}
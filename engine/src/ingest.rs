@@ -0,0 +1,329 @@
+use crate::address::PositionAddress;
+use crate::amount::{Amount, AmountExt};
+use crate::position::Position;
+use std::collections::HashMap;
+use std::fmt;
+
+/// What the engine does with a position that breaks the ingest rules
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestPolicy {
+    /// Refuse to monitor it
+    #[default]
+    Reject,
+    /// Monitor it as suspect, never liquidating it until operators clear it
+    Flag,
+}
+
+/// A rule a position broke as it was added
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum IngestViolation {
+    /// A value isn't a finite number
+    NonFinite {
+        /// The field holding it
+        field: String,
+    },
+    /// A value that can't be negative is
+    Negative {
+        /// The field holding it
+        field: String,
+        /// The value
+        value: f64,
+    },
+    /// The position's notional at entry is more times its margin than allowed
+    ExcessiveLeverage {
+        /// Notional at entry over margin (infinite without margin)
+        leverage: f64,
+        /// The most leverage allowed
+        max_leverage: f64,
+    },
+}
+
+impl fmt::Display for IngestViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonFinite { field } => write!(f, "{} must be a finite number", field),
+            Self::Negative { field, value } => write!(f, "{} must not be negative, got {}", field, value),
+            Self::ExcessiveLeverage { leverage, max_leverage } => {
+                write!(f, "implied leverage {:.2}x at entry exceeds {}x", leverage, max_leverage)
+            }
+        }
+    }
+}
+
+/// A position refused for breaking the ingest rules
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Position {address} rejected: {}", describe(violations))]
+pub struct IngestError {
+    /// The position's address
+    pub address: PositionAddress,
+    /// Every rule it broke
+    pub violations: Vec<IngestViolation>,
+}
+
+/// The rules broken, listed in one line
+pub(crate) fn describe(violations: &[IngestViolation]) -> String {
+    violations.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+}
+
+/// Every ingest rule `position` breaks, holding it to `max_leverage` if set
+///
+/// Size, entry price and margin have to be finite and not negative. The
+/// leverage cap only applies to positions whose margin is known: those backed
+/// by collateral assets not yet valued are let through until their first
+/// check cycle values them.
+pub(crate) fn ingest_violations(position: &Position, max_leverage: Option<f64>) -> Vec<IngestViolation> {
+    let mut violations = Vec::new();
+    for (field, value) in [
+        ("size", position.size),
+        ("entry_price", position.entry_price),
+        ("margin", position.margin),
+    ] {
+        if !value.is_finite() {
+            violations.push(IngestViolation::NonFinite { field: field.to_string() });
+        } else if value < Amount::ZERO {
+            violations.push(IngestViolation::Negative {
+                field: field.to_string(),
+                value: value.as_f64(),
+            });
+        }
+    }
+    let unvalued = !position.collateral.is_empty() && position.collateral_value == Amount::ZERO;
+    if let Some(max_leverage) = max_leverage
+        && violations.is_empty()
+        && !unvalued
+    {
+        let notional = position.value(position.entry_price).as_f64();
+        let margin = position.total_margin().as_f64();
+        let leverage = if notional == 0.0 { 0.0 } else { notional / margin };
+        if leverage > max_leverage {
+            violations.push(IngestViolation::ExcessiveLeverage { leverage, max_leverage });
+        }
+    }
+    violations
+}
+
+/// A position monitored despite breaking the ingest rules
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SuspectPosition {
+    /// The position's address
+    pub address: PositionAddress,
+    /// The rules it broke
+    pub violations: Vec<IngestViolation>,
+    /// When it was flagged (Unix timestamp)
+    pub flagged_at: i64,
+}
+
+/// Suspect positions, and the positions rejected or flagged since the engine
+/// started
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct IngestStats {
+    /// Positions monitored as suspect, oldest first
+    pub suspects: Vec<SuspectPosition>,
+    /// Positions refused since the engine started
+    pub total_rejected: u64,
+    /// Positions flagged since the engine started, including those since cleared
+    pub total_flagged: u64,
+}
+
+/// Positions that broke the ingest rules, as flagged or counted
+///
+/// Suspect positions stay monitored but aren't liquidated until operators
+/// clear them.
+#[derive(Debug, Default)]
+pub(crate) struct Suspects {
+    positions: HashMap<PositionAddress, SuspectPosition>,
+    total_rejected: u64,
+    total_flagged: u64,
+}
+
+impl Suspects {
+    /// Flag a position at `now` for the rules it broke, replacing those it was
+    /// flagged for before
+    pub(crate) fn flag(&mut self, address: PositionAddress, violations: Vec<IngestViolation>, now: i64) {
+        let flagged_at = self.positions.get(&address).map_or(now, |suspect| suspect.flagged_at);
+        if !self.positions.contains_key(&address) {
+            self.total_flagged += 1;
+        }
+        self.positions.insert(
+            address,
+            SuspectPosition {
+                address,
+                violations,
+                flagged_at,
+            },
+        );
+    }
+
+    /// Count a refused position
+    pub(crate) fn record_rejection(&mut self) {
+        self.total_rejected += 1;
+    }
+
+    /// Whether a position is suspect
+    pub(crate) fn contains(&self, address: &PositionAddress) -> bool {
+        self.positions.contains_key(address)
+    }
+
+    /// Clear a position's flag, returning it if it was suspect
+    pub(crate) fn clear(&mut self, address: &PositionAddress) -> Option<SuspectPosition> {
+        self.positions.remove(address)
+    }
+
+    /// Suspect positions, oldest first, with the counters
+    pub(crate) fn stats(&self) -> IngestStats {
+        let mut suspects: Vec<SuspectPosition> = self.positions.values().cloned().collect();
+        suspects.sort_by_key(|suspect| (suspect.flagged_at, suspect.address));
+        IngestStats {
+            suspects,
+            total_rejected: self.total_rejected,
+            total_flagged: self.total_flagged,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::CollateralBalance;
+    use solana_sdk::pubkey::Pubkey;
+
+    /// A 1 BTC long from 50,000 on 5,000 of margin, 10x leveraged
+    fn create_position() -> Position {
+        Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 50000.0, 5000.0, true)
+    }
+
+    #[test]
+    fn test_sound_positions_pass() {
+        assert!(ingest_violations(&create_position(), Some(10.0)).is_empty());
+        // Empty positions carry no leverage
+        let mut empty = create_position();
+        empty.size = 0.0;
+        empty.margin = 0.0;
+        assert!(ingest_violations(&empty, Some(10.0)).is_empty());
+    }
+
+    #[test]
+    fn test_non_finite_values() {
+        let mut position = create_position();
+        position.size = f64::NAN;
+        position.entry_price = f64::INFINITY;
+        assert_eq!(
+            ingest_violations(&position, Some(100.0)),
+            [
+                IngestViolation::NonFinite { field: "size".to_string() },
+                IngestViolation::NonFinite { field: "entry_price".to_string() },
+            ]
+        );
+        position = create_position();
+        position.margin = f64::NEG_INFINITY;
+        assert_eq!(
+            ingest_violations(&position, None),
+            [IngestViolation::NonFinite { field: "margin".to_string() }]
+        );
+    }
+
+    #[test]
+    fn test_negative_values() {
+        let mut position = create_position();
+        position.size = -1.0;
+        position.margin = -5000.0;
+        // Leverage isn't judged on values already refused
+        let violations = ingest_violations(&position, Some(100.0));
+        assert_eq!(
+            violations,
+            [
+                IngestViolation::Negative { field: "size".to_string(), value: -1.0 },
+                IngestViolation::Negative { field: "margin".to_string(), value: -5000.0 },
+            ]
+        );
+        position = create_position();
+        position.entry_price = -50000.0;
+        assert_eq!(
+            ingest_violations(&position, None),
+            [IngestViolation::Negative { field: "entry_price".to_string(), value: -50000.0 }]
+        );
+    }
+
+    #[test]
+    fn test_leverage_cap() {
+        let mut position = create_position();
+        assert!(ingest_violations(&position, Some(10.0)).is_empty());
+        assert_eq!(
+            ingest_violations(&position, Some(5.0)),
+            [IngestViolation::ExcessiveLeverage { leverage: 10.0, max_leverage: 5.0 }]
+        );
+
+        // A margin typo of 0.000001 implies 50 billion times leverage
+        position.margin = 0.000001;
+        let violations = ingest_violations(&position, Some(100.0));
+        assert!(matches!(
+            violations[..],
+            [IngestViolation::ExcessiveLeverage { leverage, .. }] if leverage > 1e10
+        ));
+        assert!(ingest_violations(&position, None).is_empty());
+        position.margin = 0.0;
+        assert!(matches!(
+            ingest_violations(&position, Some(100.0))[..],
+            [IngestViolation::ExcessiveLeverage { leverage, .. }] if leverage == f64::INFINITY
+        ));
+
+        // Valued collateral counts as margin, and unvalued collateral isn't judged
+        let sol = CollateralBalance {
+            mint: Pubkey::new_unique(),
+            symbol: "SOL/USD".to_string(),
+            amount: 50.0,
+        };
+        let mut backed = position.clone().with_collateral(vec![sol]);
+        assert!(ingest_violations(&backed, Some(100.0)).is_empty());
+        backed.collateral_value = 5000.0;
+        assert!(ingest_violations(&backed, Some(10.0)).is_empty());
+    }
+
+    #[test]
+    fn test_error_lists_every_violation() {
+        let address = PositionAddress::new_unique();
+        let error = IngestError {
+            address,
+            violations: vec![
+                IngestViolation::NonFinite { field: "size".to_string() },
+                IngestViolation::Negative { field: "margin".to_string(), value: -1.0 },
+                IngestViolation::ExcessiveLeverage { leverage: 250.0, max_leverage: 100.0 },
+            ],
+        };
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Position {} rejected: size must be a finite number; margin must not be negative, got -1; \
+                 implied leverage 250.00x at entry exceeds 100x",
+                address
+            )
+        );
+    }
+
+    #[test]
+    fn test_suspects_until_cleared() {
+        let mut suspects = Suspects::default();
+        let (first, second) = (PositionAddress::new_unique(), PositionAddress::new_unique());
+        let leverage = vec![IngestViolation::ExcessiveLeverage { leverage: 500.0, max_leverage: 100.0 }];
+        suspects.flag(second, leverage.clone(), 20);
+        suspects.flag(first, leverage, 10);
+        // Flagged again, it keeps when it was first flagged
+        suspects.flag(first, vec![IngestViolation::NonFinite { field: "margin".to_string() }], 30);
+        suspects.record_rejection();
+        assert!(suspects.contains(&first));
+
+        let stats = suspects.stats();
+        let held: Vec<(PositionAddress, i64)> =
+            stats.suspects.iter().map(|suspect| (suspect.address, suspect.flagged_at)).collect();
+        assert_eq!(held, [(first, 10), (second, 20)]);
+        assert_eq!(stats.suspects[0].violations, [IngestViolation::NonFinite { field: "margin".to_string() }]);
+        assert_eq!((stats.total_rejected, stats.total_flagged), (1, 2));
+
+        assert!(suspects.clear(&first).is_some());
+        assert!(suspects.clear(&first).is_none());
+        assert!(!suspects.contains(&first));
+        assert_eq!(suspects.stats().total_flagged, 2);
+    }
+}
@@ -0,0 +1,883 @@
+use crate::health::{MarketAccount, PROGRAM_ID, PositionAccount};
+use crate::token_accounts::MarketTokenAccounts;
+use crate::types::SkipReason;
+use anchor_lang::{InstructionData, ToAccountMetas};
+use liquidation_program::{MintDecimals, OraclePrice, PositionBalances};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+/// Custom error the program fails with when the position isn't liquidatable
+pub const POSITION_HEALTHY_ERROR: u32 =
+    anchor_lang::error::ERROR_CODE_OFFSET + liquidation_program::LiquidationError::PositionHealthy as u32;
+
+/// Custom error the program fails with when the liquidator isn't a whitelisted
+/// keeper while the market is permissioned
+pub const KEEPER_NOT_WHITELISTED_ERROR: u32 =
+    anchor_lang::error::ERROR_CODE_OFFSET + liquidation_program::LiquidationError::KeeperNotWhitelisted as u32;
+
+/// Custom error the token program fails with when the liquidator can't cover
+/// the repayment
+pub const INSUFFICIENT_FUNDS_ERROR: u32 = anchor_spl::token::spl_token::error::TokenError::InsufficientFunds as u32;
+
+/// Exponent prices are scaled by when checked against the program's math
+const PRICE_EXPO: i32 = -8;
+
+/// Accounts the program's `liquidate` instruction operates on
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidateAccounts {
+    /// The position being liquidated
+    pub position: Pubkey,
+    /// Token vault holding the position's collateral
+    pub vault: Pubkey,
+    /// Token vault debt is borrowed from and repaid into
+    pub debt_vault: Pubkey,
+    /// Token account the repayment is taken from
+    pub liquidator_token_account: Pubkey,
+    /// Token account the reward is paid into, in the collateral's mint
+    pub liquidator_collateral_account: Pubkey,
+    /// Insurance fund token vault
+    pub insurance_fund_vault: Pubkey,
+    /// Mint of the collateral, the market's
+    pub collateral_mint: Pubkey,
+    /// Mint of the debt, the market's
+    pub debt_mint: Pubkey,
+    /// Price account of the collateral, which must be the market's price feed
+    pub oracle: Pubkey,
+    /// Token program owning the collateral's mint, classic or Token-2022
+    pub collateral_token_program: Pubkey,
+    /// Token program owning the debt's mint, classic or Token-2022
+    pub debt_token_program: Pubkey,
+    /// The liquidator, signing for the repayment
+    pub liquidator: Pubkey,
+    /// Id of the market, keying its address and its vault authority's
+    pub market_id: u64,
+}
+
+/// A program's market as liquidations in it are built: the market account's
+/// vaults and price feed, and the token programs owning its mints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiquidationMarket {
+    /// Program the market belongs to
+    pub program_id: Pubkey,
+    /// Id of the market within the program
+    pub market_id: u64,
+    /// Token vault holding the positions' collateral
+    pub vault: Pubkey,
+    /// Token vault debt is borrowed from and repaid into
+    pub debt_vault: Pubkey,
+    /// Insurance fund token vault
+    pub insurance_fund_vault: Pubkey,
+    /// Price account of the collateral the program checks health against
+    pub oracle: Pubkey,
+    /// Mint of the collateral
+    pub collateral_mint: Pubkey,
+    /// Mint of the debt
+    pub debt_mint: Pubkey,
+    /// Token program owning the collateral's mint, classic or Token-2022
+    pub collateral_token_program: Pubkey,
+    /// Token program owning the debt's mint, classic or Token-2022
+    pub debt_token_program: Pubkey,
+    /// Largest share of a position's debt one liquidation may repay, in basis
+    /// points
+    pub close_factor_bps: u16,
+    /// Bonus paid on top of a repayment in seized collateral, in basis points
+    pub liquidation_bonus_bps: u16,
+    /// Decimals of the collateral and debt mints
+    pub mint_decimals: MintDecimals,
+}
+
+impl LiquidationMarket {
+    /// Market of the program at `program_id` as its account holds it, with the
+    /// token programs owning its collateral and debt mints
+    pub fn new(
+        program_id: Pubkey,
+        market: &MarketAccount,
+        collateral_token_program: Pubkey,
+        debt_token_program: Pubkey,
+    ) -> Self {
+        Self {
+            program_id,
+            market_id: market.market_id,
+            vault: market.vault,
+            debt_vault: market.debt_vault,
+            insurance_fund_vault: market.insurance_fund_vault,
+            oracle: market.oracle,
+            collateral_mint: market.collateral_mint,
+            debt_mint: market.debt_mint,
+            collateral_token_program,
+            debt_token_program,
+            close_factor_bps: market.close_factor_bps,
+            liquidation_bonus_bps: market.liquidation_bonus_bps,
+            mint_decimals: market.mint_decimals(),
+        }
+    }
+
+    /// Largest repayment one liquidation of a position owing `debt` may make,
+    /// in the debt mint's base units
+    pub fn max_repay_amount(&self, debt: u64) -> u64 {
+        (debt as u128 * self.close_factor_bps as u128 / 10_000) as u64
+    }
+
+    /// Repayment the program takes of up to `target` of a position's debt, its
+    /// balances in base units, with a whole collateral token worth `price`
+    /// whole debt tokens
+    ///
+    /// The repayment is capped as the program caps it, and at what seizes all
+    /// the collateral, past which it buys nothing. A position worth too little
+    /// to be liquidated in part is closed instead. Fails with the reason the
+    /// program would reject the repayment for, by its own math and before any
+    /// transfer fee.
+    pub fn repay_amount(&self, balances: PositionBalances, price: f64, target: u64) -> Result<u64, SkipReason> {
+        let price = OraclePrice {
+            price: (price * 10f64.powi(-PRICE_EXPO)).round() as i64,
+            expo: PRICE_EXPO,
+        };
+        let PositionBalances { collateral, debt } = balances;
+        let bonus = self.liquidation_bonus_bps;
+        let seized = |repay| {
+            liquidation_program::seized_collateral(repay, collateral, price, self.mint_decimals, bonus).unwrap_or(0)
+        };
+        let max_repay = liquidation_program::max_liquidation_repay(
+            collateral,
+            debt,
+            price,
+            self.mint_decimals,
+            self.close_factor_bps,
+            bonus,
+        )
+        .unwrap_or(0);
+        // Least repayment seizing all the collateral, if any within the debt does
+        let closing = (seized(debt) >= collateral).then(|| {
+            let (mut low, mut high) = (0, debt);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if seized(mid) >= collateral {
+                    high = mid
+                } else {
+                    low = mid + 1
+                }
+            }
+            low
+        });
+
+        let repay_amount = target.min(max_repay).min(closing.unwrap_or(u64::MAX));
+        if repay_amount == 0 {
+            return Err(SkipReason::RepayRoundsToZero);
+        }
+        match liquidation_program::liquidated_balances(balances, repay_amount, seized(repay_amount), price) {
+            Ok(_) => Ok(repay_amount),
+            Err(_) => match closing {
+                Some(closing) if closing <= max_repay => Ok(closing),
+                _ => Err(SkipReason::WorsensHealth { repay_amount }),
+            },
+        }
+    }
+
+    /// `liquidator`'s associated token accounts for the market's mints
+    pub fn token_accounts(&self, liquidator: &Pubkey) -> MarketTokenAccounts {
+        MarketTokenAccounts::derive(
+            liquidator,
+            (self.collateral_mint, self.collateral_token_program),
+            (self.debt_mint, self.debt_token_program),
+        )
+    }
+
+    /// Accounts of `liquidator`'s liquidation of `position`, repaying from and
+    /// rewarded into its associated token accounts
+    pub fn liquidate_accounts(&self, position: Pubkey, liquidator: Pubkey) -> LiquidateAccounts {
+        let token_accounts = self.token_accounts(&liquidator);
+        LiquidateAccounts {
+            position,
+            vault: self.vault,
+            debt_vault: self.debt_vault,
+            liquidator_token_account: token_accounts.debt,
+            liquidator_collateral_account: token_accounts.collateral,
+            insurance_fund_vault: self.insurance_fund_vault,
+            collateral_mint: self.collateral_mint,
+            debt_mint: self.debt_mint,
+            oracle: self.oracle,
+            collateral_token_program: self.collateral_token_program,
+            debt_token_program: self.debt_token_program,
+            liquidator,
+            market_id: self.market_id,
+        }
+    }
+
+    /// The program's `liquidate` instruction by which `liquidator` repays
+    /// `repay_amount` of `position`'s debt
+    pub fn liquidate_instruction(&self, position: Pubkey, liquidator: Pubkey, repay_amount: u64) -> Instruction {
+        program_liquidate_instruction(&self.program_id, &self.liquidate_accounts(position, liquidator), repay_amount)
+    }
+}
+
+/// Address of the program's market config created with `market_id`, which
+/// records the price feed the `oracle` account must match
+pub fn market_address(market_id: u64) -> Pubkey {
+    program_market_address(&PROGRAM_ID, market_id)
+}
+
+/// Address of the market config created with `market_id` in a deployment of
+/// the program at `program_id`
+pub(crate) fn program_market_address(program_id: &Pubkey, market_id: u64) -> Pubkey {
+    Pubkey::find_program_address(&[b"market", &market_id.to_le_bytes()], program_id).0
+}
+
+/// Address of the PDA the vaults of the program's market created with
+/// `market_id` are held by, signing for the reward
+pub(crate) fn vault_authority_address(market_id: u64) -> Pubkey {
+    program_vault_authority_address(&PROGRAM_ID, market_id)
+}
+
+/// Address of the vault authority of the market created with `market_id` in
+/// a deployment of the program at `program_id`
+pub(crate) fn program_vault_authority_address(program_id: &Pubkey, market_id: u64) -> Pubkey {
+    Pubkey::find_program_address(&[b"vault-authority", &market_id.to_le_bytes()], program_id).0
+}
+
+/// Address of `owner`'s position account in the market at `market`, the one
+/// position the program derives for them there
+pub fn position_address(market: &Pubkey, owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"position", market.as_ref(), owner.as_ref()], &PROGRAM_ID).0
+}
+
+/// Whether `address` is the position account of its `owner`, created with
+/// `bump`, in the market at `market` of the program at `program_id`
+///
+/// Every market's positions belong to the same program, so this tells them
+/// apart without deriving each address.
+pub(crate) fn is_market_position(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    address: &Pubkey,
+    owner: &Pubkey,
+    bump: u8,
+) -> bool {
+    Pubkey::create_program_address(&[b"position", market.as_ref(), owner.as_ref(), &[bump]], program_id)
+        .is_ok_and(|derived| derived == *address)
+}
+
+/// Build the program's `initialize_position` instruction creating `owner`'s
+/// position account in the market created with `market_id`, paid for by
+/// `owner`
+pub fn initialize_position_instruction(market_id: u64, owner: &Pubkey) -> Instruction {
+    let market = market_address(market_id);
+    let metas = liquidation_program::accounts::InitializePosition {
+        market,
+        position: position_address(&market, owner),
+        user: *owner,
+        system_program: solana_sdk::system_program::ID,
+    };
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: metas.to_account_metas(None),
+        data: liquidation_program::instruction::InitializePosition {}.data(),
+    }
+}
+
+/// Build the program's `deposit_collateral` instruction moving `amount` of the
+/// market's collateral from `owner`'s `token_account` into their position
+///
+/// `token_program` must own the collateral's mint.
+pub fn deposit_collateral_instruction(
+    market: &MarketAccount,
+    owner: &Pubkey,
+    token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let address = market_address(market.market_id);
+    let metas = liquidation_program::accounts::DepositCollateral {
+        position: position_address(&address, owner),
+        user_token_account: *token_account,
+        vault: market.vault,
+        collateral_mint: market.collateral_mint,
+        market: address,
+        user: *owner,
+        token_program: *token_program,
+    };
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: metas.to_account_metas(None),
+        data: liquidation_program::instruction::DepositCollateral { amount }.data(),
+    }
+}
+
+/// Build the program's `borrow` instruction lending `amount` of the market's
+/// debt into `owner`'s `token_account` against their position's collateral
+///
+/// `token_program` must own the debt's mint.
+pub fn borrow_instruction(
+    market: &MarketAccount,
+    owner: &Pubkey,
+    token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let address = market_address(market.market_id);
+    let metas = liquidation_program::accounts::Borrow {
+        position: position_address(&address, owner),
+        debt_vault: market.debt_vault,
+        user_token_account: *token_account,
+        debt_mint: market.debt_mint,
+        vault_authority: vault_authority_address(market.market_id),
+        market: address,
+        oracle: market.oracle,
+        owner: *owner,
+        token_program: *token_program,
+    };
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: metas.to_account_metas(None),
+        data: liquidation_program::instruction::Borrow { amount }.data(),
+    }
+}
+
+/// Build the program's `liquidate` instruction repaying `repay_amount` of the
+/// position's debt
+pub fn liquidate_instruction(accounts: &LiquidateAccounts, repay_amount: u64) -> Instruction {
+    program_liquidate_instruction(&PROGRAM_ID, accounts, repay_amount)
+}
+
+/// Build the `liquidate` instruction of a deployment of the program at
+/// `program_id`
+pub(crate) fn program_liquidate_instruction(
+    program_id: &Pubkey,
+    accounts: &LiquidateAccounts,
+    repay_amount: u64,
+) -> Instruction {
+    let metas = liquidation_program::accounts::LiquidatePosition {
+        position: accounts.position,
+        vault: accounts.vault,
+        debt_vault: accounts.debt_vault,
+        liquidator_token_account: accounts.liquidator_token_account,
+        liquidator_collateral_account: accounts.liquidator_collateral_account,
+        insurance_fund_vault: accounts.insurance_fund_vault,
+        collateral_mint: accounts.collateral_mint,
+        debt_mint: accounts.debt_mint,
+        vault_authority: program_vault_authority_address(program_id, accounts.market_id),
+        market: program_market_address(program_id, accounts.market_id),
+        oracle: accounts.oracle,
+        collateral_token_program: accounts.collateral_token_program,
+        debt_token_program: accounts.debt_token_program,
+        liquidator: accounts.liquidator,
+    };
+    Instruction {
+        program_id: *program_id,
+        accounts: metas.to_account_metas(None),
+        data: liquidation_program::instruction::Liquidate { repay_amount }.data(),
+    }
+}
+
+/// Build the program's `flag_for_liquidation` instruction, starting the margin
+/// call of a position below the warning threshold of the market created with
+/// `market_id`
+///
+/// `oracle` must be the market's price feed and `liquidator` signs, as for
+/// [`liquidate_instruction`].
+pub(crate) fn flag_for_liquidation_instruction(
+    position: &Pubkey,
+    market_id: u64,
+    oracle: &Pubkey,
+    liquidator: &Pubkey,
+) -> Instruction {
+    let metas = liquidation_program::accounts::FlagForLiquidation {
+        position: *position,
+        market: market_address(market_id),
+        oracle: *oracle,
+        liquidator: *liquidator,
+    };
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: metas.to_account_metas(None),
+        data: liquidation_program::instruction::FlagForLiquidation {}.data(),
+    }
+}
+
+/// Associated token account of `owner` for `mint`, a mint of `token_program`
+pub fn associated_token_account(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    anchor_spl::associated_token::get_associated_token_address_with_program_id(owner, mint, token_program)
+}
+
+/// Build the associated token program's instruction creating `owner`'s token
+/// account for `mint`, a mint of `token_program`, paid for by `owner`; it
+/// succeeds if the account exists
+pub fn create_token_account_instruction(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: anchor_spl::associated_token::ID,
+        accounts: vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(associated_token_account(owner, mint, token_program), false),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+            AccountMeta::new_readonly(*token_program, false),
+        ],
+        // The `CreateIdempotent` instruction
+        data: vec![1],
+    }
+}
+
+/// Effect of a liquidation on a position account, as the program applies it
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct LiquidationOutcome {
+    /// Debt repaid by the liquidator
+    pub repay_amount: u64,
+    /// Part of the repayment a Token-2022 debt mint withholds as a transfer
+    /// fee, which repays no debt
+    pub repay_fee: u64,
+    /// Collateral seized by the liquidator, worth the repayment plus the
+    /// market's liquidation bonus
+    pub seized: u64,
+    /// Collateral left in the position
+    pub remaining_collateral: u64,
+    /// Debt left in the position
+    pub remaining_debt: u64,
+    /// Price of a unit of collateral in debt the outcome was computed at
+    pub collateral_price: f64,
+}
+
+impl LiquidationOutcome {
+    /// Outcome of repaying `repay_amount` of the account's debt with its
+    /// collateral priced at `collateral_price`, under the market's
+    /// `liquidation_bonus_bps`
+    pub fn new(account: &PositionAccount, repay_amount: u64, collateral_price: f64, liquidation_bonus_bps: u16) -> Self {
+        Self::with_repay_fee(account, repay_amount, 0, collateral_price, liquidation_bonus_bps)
+    }
+
+    /// Outcome of a repayment of which the debt mint withholds `repay_fee`
+    /// ([`transfer_fee`](crate::token_accounts::transfer_fee)): only the rest repays debt and
+    /// is rewarded
+    pub fn with_repay_fee(
+        account: &PositionAccount,
+        repay_amount: u64,
+        repay_fee: u64,
+        collateral_price: f64,
+        liquidation_bonus_bps: u16,
+    ) -> Self {
+        let repaid = repay_amount.saturating_sub(repay_fee);
+        // The program rounds the reward down before pricing the seizure, then
+        // rounds the units down
+        let reward = (repaid as u128 * liquidation_bonus_bps as u128 / 10_000) as f64;
+        let value = repaid as f64 + reward;
+        let seized = (value / collateral_price).floor().min(account.collateral as f64) as u64;
+        Self {
+            repay_amount,
+            repay_fee,
+            seized,
+            remaining_collateral: account.collateral.saturating_sub(seized),
+            remaining_debt: account.debt.saturating_sub(repaid),
+            collateral_price,
+        }
+    }
+
+    /// Value of the seized collateral beyond the repayment
+    pub fn bonus_value(&self) -> f64 {
+        self.seized as f64 * self.collateral_price - self.repay_amount as f64
+    }
+
+    /// Whether the position is left healthy, i.e. collateral value covers debt
+    pub fn is_healthy(&self) -> bool {
+        (self.remaining_collateral as f64 * self.collateral_price).floor() >= self.remaining_debt as f64
+    }
+}
+
+/// Accounts `instructions` write, each once, in the order they first appear
+///
+/// These are the accounts a transaction of them write-locks, whose recent
+/// priority fees it competes with.
+pub fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut accounts = Vec::new();
+    for meta in instructions.iter().flat_map(|instruction| &instruction.accounts) {
+        if meta.is_writable && !accounts.contains(&meta.pubkey) {
+            accounts.push(meta.pubkey);
+        }
+    }
+    accounts
+}
+
+/// Largest repayment of the account's debt a single liquidation may make under
+/// the market's `close_factor_bps`
+pub fn max_repay_amount(account: &PositionAccount, close_factor_bps: u16) -> u64 {
+    (account.debt as u128 * close_factor_bps as u128 / 10_000) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Discriminator;
+
+    fn create_account(collateral: u64, debt: u64) -> PositionAccount {
+        PositionAccount {
+            owner: Pubkey::new_unique(),
+            bump: 255,
+            collateral,
+            debt,
+            closed: false,
+            flagged_at: 0,
+            flag_price: 0,
+            flag_price_expo: 0,
+        }
+    }
+
+    fn create_market() -> MarketAccount {
+        MarketAccount {
+            authority: Pubkey::new_unique(),
+            oracle: Pubkey::new_unique(),
+            insurance_fund_vault: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            debt_mint: Pubkey::new_unique(),
+            close_factor_bps: 5_000,
+            liquidation_bonus_bps: 500,
+            liquidation_mode: liquidation_program::LiquidationMode::Permissionless,
+            keepers: Vec::new(),
+            bump: 255,
+            vault_authority_bump: 255,
+            grace_period_secs: 0,
+            warning_health_bps: liquidation_program::DEFAULT_WARNING_HEALTH_BPS,
+            instant_health_bps: liquidation_program::DEFAULT_INSTANT_HEALTH_BPS,
+            vault: Pubkey::new_unique(),
+            debt_vault: Pubkey::new_unique(),
+            vault_bump: 255,
+            debt_vault_bump: 255,
+            insurance_fund_vault_bump: 255,
+            collateral_decimals: 6,
+            debt_decimals: 6,
+            market_id: 3,
+        }
+    }
+
+    fn account_keys(instruction: &Instruction) -> Vec<(Pubkey, bool, bool)> {
+        instruction
+            .accounts
+            .iter()
+            .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable))
+            .collect()
+    }
+
+    #[test]
+    fn test_market_addresses() {
+        let (market, _) = Pubkey::find_program_address(&[b"market", &3u64.to_le_bytes()], &PROGRAM_ID);
+        assert_eq!(market_address(3), market);
+        let (authority, _) = Pubkey::find_program_address(&[b"vault-authority", &3u64.to_le_bytes()], &PROGRAM_ID);
+        assert_eq!(vault_authority_address(3), authority);
+        // Each market its own config and vault authority
+        assert_ne!(market_address(3), market_address(4));
+        assert_ne!(vault_authority_address(3), vault_authority_address(4));
+        let program_id = Pubkey::new_unique();
+        assert_ne!(program_market_address(&program_id, 3), market);
+        assert_ne!(program_vault_authority_address(&program_id, 3), authority);
+    }
+
+    #[test]
+    fn test_position_address() {
+        let market = market_address(0);
+        let owner = Pubkey::new_from_array([7; 32]);
+        let (address, bump) =
+            Pubkey::find_program_address(&[b"position", market.as_ref(), owner.as_ref()], &PROGRAM_ID);
+        assert_eq!(position_address(&market, &owner), address);
+        assert!(!address.is_on_curve());
+        // One position per owner and market
+        assert_eq!(position_address(&market, &owner), position_address(&market, &owner));
+        assert_ne!(
+            position_address(&market, &owner),
+            position_address(&market, &Pubkey::new_from_array([8; 32]))
+        );
+        assert_ne!(
+            position_address(&market, &owner),
+            position_address(&market_address(1), &owner)
+        );
+
+        assert!(is_market_position(&PROGRAM_ID, &market, &address, &owner, bump));
+        assert!(!is_market_position(
+            &PROGRAM_ID,
+            &market_address(1),
+            &address,
+            &owner,
+            bump
+        ));
+        assert!(!is_market_position(
+            &PROGRAM_ID,
+            &market,
+            &Pubkey::new_unique(),
+            &owner,
+            bump
+        ));
+    }
+
+    #[test]
+    fn test_initialize_position_instruction() {
+        let owner = Pubkey::new_unique();
+        let instruction = initialize_position_instruction(3, &owner);
+        assert_eq!(instruction.program_id, PROGRAM_ID);
+        assert_eq!(instruction.data, liquidation_program::instruction::InitializePosition::DISCRIMINATOR);
+        assert_eq!(
+            account_keys(&instruction),
+            [
+                (market_address(3), false, false),
+                (position_address(&market_address(3), &owner), false, true),
+                (owner, true, true),
+                (solana_sdk::system_program::ID, false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deposit_and_borrow_instructions() {
+        let market = create_market();
+        let owner = Pubkey::new_unique();
+        let token_account = Pubkey::new_unique();
+
+        let token_program = anchor_spl::token::ID;
+        let instruction = deposit_collateral_instruction(&market, &owner, &token_account, &token_program, 5_000);
+        assert_eq!(instruction.program_id, PROGRAM_ID);
+        assert_eq!(&instruction.data[..8], &liquidation_program::instruction::DepositCollateral::DISCRIMINATOR);
+        assert_eq!(&instruction.data[8..], &5_000u64.to_le_bytes());
+        assert_eq!(
+            account_keys(&instruction),
+            [
+                (position_address(&market_address(3), &owner), false, true),
+                (token_account, false, true),
+                (market.vault, false, true),
+                (market.collateral_mint, false, false),
+                (market_address(3), false, false),
+                (owner, true, false),
+                (anchor_spl::token::ID, false, false),
+            ]
+        );
+
+        let instruction = borrow_instruction(&market, &owner, &token_account, &anchor_spl::token_2022::ID, 1_234);
+        assert_eq!(&instruction.data[..8], &liquidation_program::instruction::Borrow::DISCRIMINATOR);
+        assert_eq!(&instruction.data[8..], &1_234u64.to_le_bytes());
+        assert_eq!(
+            account_keys(&instruction),
+            [
+                (position_address(&market_address(3), &owner), false, true),
+                (market.debt_vault, false, true),
+                (token_account, false, true),
+                (market.debt_mint, false, false),
+                (vault_authority_address(3), false, false),
+                (market_address(3), false, false),
+                (market.oracle, false, false),
+                (owner, true, false),
+                (anchor_spl::token_2022::ID, false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_liquidate_instruction() {
+        let accounts = LiquidateAccounts {
+            position: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+            debt_vault: Pubkey::new_unique(),
+            liquidator_token_account: Pubkey::new_unique(),
+            liquidator_collateral_account: Pubkey::new_unique(),
+            insurance_fund_vault: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            debt_mint: Pubkey::new_unique(),
+            oracle: Pubkey::new_unique(),
+            collateral_token_program: anchor_spl::token::ID,
+            debt_token_program: anchor_spl::token_2022::ID,
+            liquidator: Pubkey::new_unique(),
+            market_id: 2,
+        };
+        let instruction = liquidate_instruction(&accounts, 1_234);
+        assert_eq!(instruction.program_id, PROGRAM_ID);
+        assert_eq!(&instruction.data[..8], &liquidation_program::instruction::Liquidate::DISCRIMINATOR);
+        assert_eq!(&instruction.data[8..], &1_234u64.to_le_bytes());
+
+        let keys: Vec<(Pubkey, bool, bool)> = instruction
+            .accounts
+            .iter()
+            .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                (accounts.position, false, true),
+                (accounts.vault, false, true),
+                (accounts.debt_vault, false, true),
+                (accounts.liquidator_token_account, false, true),
+                (accounts.liquidator_collateral_account, false, true),
+                (accounts.insurance_fund_vault, false, true),
+                (accounts.collateral_mint, false, false),
+                (accounts.debt_mint, false, false),
+                (vault_authority_address(2), false, false),
+                (market_address(2), false, false),
+                (accounts.oracle, false, false),
+                (anchor_spl::token::ID, false, false),
+                (anchor_spl::token_2022::ID, false, false),
+                (accounts.liquidator, true, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_liquidation_market_instruction() {
+        let account = create_market();
+        let program_id = Pubkey::new_unique();
+        let market = LiquidationMarket::new(program_id, &account, anchor_spl::token::ID, anchor_spl::token_2022::ID);
+        let (position, liquidator) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        assert_eq!(market.max_repay_amount(1_001), 500);
+        let instruction = market.liquidate_instruction(position, liquidator, 1_234);
+        assert_eq!(instruction.program_id, program_id);
+        assert_eq!(&instruction.data[8..], &1_234u64.to_le_bytes());
+        let keys: Vec<Pubkey> = instruction.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(
+            keys,
+            [
+                position,
+                account.vault,
+                account.debt_vault,
+                associated_token_account(&liquidator, &account.debt_mint, &anchor_spl::token_2022::ID),
+                associated_token_account(&liquidator, &account.collateral_mint, &anchor_spl::token::ID),
+                account.insurance_fund_vault,
+                account.collateral_mint,
+                account.debt_mint,
+                program_vault_authority_address(&program_id, 3),
+                program_market_address(&program_id, 3),
+                account.oracle,
+                anchor_spl::token::ID,
+                anchor_spl::token_2022::ID,
+                liquidator,
+            ]
+        );
+        let liquidator_accounts = market.token_accounts(&liquidator);
+        assert_eq!(
+            writable_accounts(&[instruction.clone(), instruction]),
+            [
+                position,
+                account.vault,
+                account.debt_vault,
+                liquidator_accounts.debt,
+                liquidator_accounts.collateral,
+                account.insurance_fund_vault,
+            ]
+        );
+        // The program's own deployment builds the same instruction either way
+        let market = LiquidationMarket { program_id: PROGRAM_ID, ..market };
+        assert_eq!(
+            market.liquidate_instruction(position, liquidator, 1_234),
+            liquidate_instruction(&market.liquidate_accounts(position, liquidator), 1_234)
+        );
+    }
+
+    #[test]
+    fn test_liquidation_market_repay_amount() {
+        let market = LiquidationMarket::new(PROGRAM_ID, &create_market(), anchor_spl::token::ID, anchor_spl::token::ID);
+        let balances = PositionBalances {
+            collateral: 1_000_000,
+            debt: 90_000_000,
+        };
+
+        // Worth more than the debt plus the bonus, up to the close factor
+        assert_eq!(market.repay_amount(balances, 100.0, 10_000_000), Ok(10_000_000));
+        assert_eq!(market.repay_amount(balances, 100.0, 90_000_000), Ok(45_000_000));
+        assert_eq!(market.repay_amount(balances, 100.0, 0), Err(SkipReason::RepayRoundsToZero));
+
+        // Worth less, only closed, repaying no more than seizes all the collateral
+        assert_eq!(market.repay_amount(balances, 90.0, 10_000_000), Ok(85_714_286));
+        assert_eq!(market.repay_amount(balances, 90.0, 90_000_000), Ok(85_714_286));
+        assert_eq!(market.repay_amount(balances, 50.0, 90_000_000), Ok(47_619_048));
+
+        // Too few units of collateral for any repayment to seize them all
+        let balances = PositionBalances { collateral: 4, debt: 55 };
+        assert_eq!(market.repay_amount(balances, 14.3, 41), Err(SkipReason::WorsensHealth { repay_amount: 41 }));
+        assert_eq!(market.repay_amount(balances, 14.3, 55), Ok(55));
+    }
+
+    #[test]
+    fn test_flag_for_liquidation_instruction() {
+        let [position, oracle, liquidator] = [(); 3].map(|_| Pubkey::new_unique());
+        let instruction = flag_for_liquidation_instruction(&position, 3, &oracle, &liquidator);
+        assert_eq!(instruction.program_id, PROGRAM_ID);
+        assert_eq!(instruction.data, liquidation_program::instruction::FlagForLiquidation::DISCRIMINATOR);
+
+        let keys: Vec<(Pubkey, bool, bool)> = instruction
+            .accounts
+            .iter()
+            .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                (position, false, true),
+                (market_address(3), false, false),
+                (oracle, false, false),
+                (liquidator, true, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_create_token_account_instruction() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let instruction = create_token_account_instruction(&owner, &mint, &anchor_spl::token::ID);
+        assert_eq!(instruction.program_id, anchor_spl::associated_token::ID);
+        assert_eq!(instruction.data, [1]);
+        assert_eq!(instruction.accounts[1].pubkey, associated_token_account(&owner, &mint, &anchor_spl::token::ID));
+        assert!(instruction.accounts[0].is_signer && instruction.accounts[1].is_writable);
+
+        // Token-2022 accounts are derived from, and created by, that program
+        let instruction = create_token_account_instruction(&owner, &mint, &anchor_spl::token_2022::ID);
+        assert_eq!(instruction.accounts[5].pubkey, anchor_spl::token_2022::ID);
+        assert_ne!(instruction.accounts[1].pubkey, associated_token_account(&owner, &mint, &anchor_spl::token::ID));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(POSITION_HEALTHY_ERROR, 6000);
+        assert_eq!(KEEPER_NOT_WHITELISTED_ERROR, 6014);
+        assert_eq!(INSUFFICIENT_FUNDS_ERROR, 1);
+    }
+
+    #[test]
+    fn test_max_repay_amount() {
+        assert_eq!(max_repay_amount(&create_account(100, 80_000), 5_000), 40_000);
+        assert_eq!(max_repay_amount(&create_account(100, 80_001), 5_000), 40_000);
+        assert_eq!(max_repay_amount(&create_account(100, 80_000), 10_000), 80_000);
+        assert_eq!(max_repay_amount(&create_account(100, u64::MAX), 10_000), u64::MAX);
+    }
+
+    #[test]
+    fn test_liquidation_outcome() {
+        // 20,000 plus a 5% bonus buys 233 units at 90.00
+        let outcome = LiquidationOutcome::new(&create_account(800, 80_000), 20_000, 90.0, 500);
+        assert_eq!(
+            outcome,
+            LiquidationOutcome {
+                repay_amount: 20_000,
+                repay_fee: 0,
+                seized: 233,
+                remaining_collateral: 567,
+                remaining_debt: 60_000,
+                collateral_price: 90.0,
+            }
+        );
+        assert!((outcome.bonus_value() - 970.0).abs() < 1e-9);
+        assert!(!outcome.is_healthy());
+
+        // Seizure is capped at the collateral left
+        let outcome = LiquidationOutcome::new(&create_account(1_000, 80_000), 40_000, 10.0, 500);
+        assert_eq!((outcome.seized, outcome.remaining_collateral, outcome.remaining_debt), (1_000, 0, 40_000));
+        assert!(outcome.bonus_value() < 0.0);
+
+        let outcome = LiquidationOutcome::new(&create_account(1_000, 100), 100, 1.0, 0);
+        assert_eq!((outcome.seized, outcome.remaining_debt), (100, 0));
+        assert!(outcome.is_healthy());
+
+        // As on-chain the reward of 1,019 is 50, not 50.95, before pricing
+        let outcome = LiquidationOutcome::new(&create_account(10_000, 80_000), 1_019, 0.5, 500);
+        assert_eq!(outcome.seized, 2_138);
+
+        // A 1% transfer fee leaves 19,800 to repay debt and be rewarded, as
+        // the program's test of it finds
+        let outcome = LiquidationOutcome::with_repay_fee(&create_account(1_000, 80_000), 20_000, 200, 50.0, 500);
+        assert_eq!((outcome.seized, outcome.remaining_collateral, outcome.remaining_debt), (415, 585, 60_200));
+        assert!((outcome.bonus_value() - 750.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::types::InsuranceStats;
+use std::collections::VecDeque;
+
+/// Running record of bad debt absorbed by the insurance fund
+#[derive(Debug, Default)]
+pub(crate) struct InsuranceLedger {
+    /// Total bad debt absorbed since the ledger started
+    total_bad_debt: f64,
+    /// Number of liquidations that produced bad debt
+    bad_debt_events: u64,
+    /// Recent bad debt as (timestamp, amount), oldest first
+    recent: VecDeque<(i64, f64)>,
+}
+
+impl InsuranceLedger {
+    /// Create an empty ledger
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record bad debt absorbed at the given time
+    pub(crate) fn record(&mut self, timestamp: i64, bad_debt: f64) {
+        if bad_debt <= 0.0 {
+            return;
+        }
+        self.total_bad_debt += bad_debt;
+        self.bad_debt_events += 1;
+        self.recent.push_back((timestamp, bad_debt));
+    }
+
+    /// Bad debt absorbed within `window_secs` of `now`, pruning older entries
+    pub(crate) fn window_total(&mut self, now: i64, window_secs: u64) -> f64 {
+        let cutoff = now.saturating_sub(window_secs as i64);
+        while self.recent.front().is_some_and(|(timestamp, _)| *timestamp <= cutoff) {
+            self.recent.pop_front();
+        }
+        self.recent.iter().map(|(_, amount)| amount).sum()
+    }
+
+    /// Snapshot of the ledger for reporting
+    pub(crate) fn stats(&mut self, now: i64, window_secs: u64) -> InsuranceStats {
+        InsuranceStats {
+            total_bad_debt: self.total_bad_debt,
+            bad_debt_events: self.bad_debt_events,
+            window_bad_debt: self.window_total(now, window_secs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_window() {
+        let mut ledger = InsuranceLedger::new();
+        ledger.record(100, 500.0);
+        ledger.record(200, 250.0);
+        ledger.record(300, 0.0); // Not bad debt
+
+        let stats = ledger.stats(250, 3600);
+        assert_eq!(stats.total_bad_debt, 750.0);
+        assert_eq!(stats.bad_debt_events, 2);
+        assert_eq!(stats.window_bad_debt, 750.0);
+
+        // The first entry ages out of a 120 second window
+        assert_eq!(ledger.window_total(250, 120), 250.0);
+        assert_eq!(ledger.stats(250, 120).total_bad_debt, 750.0);
+    }
+}
@@ -0,0 +1,70 @@
+//! Building blocks of the `liquidation-engine` binary and `liquidation-cli`,
+//! outside the stable facade at the crate root
+//!
+//! These follow the program and the binaries' needs and may change in any
+//! release. Programs embedding the engine should only need the root exports.
+
+pub use crate::address::{FeedAddress, OwnerAddress, PositionAddress};
+pub use crate::alert::Alerter;
+pub use crate::amount::AmountExt;
+pub use crate::audit::{AuditWriter, DEFAULT_AUDIT_QUEUE_CAPACITY};
+pub use crate::chainlink::{ChainlinkOracle, ChainlinkRound, decode_feed_account};
+pub use crate::clock::{Clock, ManualClock};
+#[cfg(any(test, feature = "testing"))]
+pub use crate::compute::MockSimulator;
+#[cfg(any(test, feature = "testing"))]
+pub use crate::confirm::MockStatusPoller;
+pub use crate::depth::{ConstantLiquidity, DepthProvider};
+pub use crate::error::ConfigViolation;
+pub use crate::events::websocket_url;
+#[cfg(any(test, feature = "testing"))]
+pub use crate::fee::MockFeeSource;
+#[cfg(any(test, feature = "testing"))]
+pub use crate::funding::MockFundingSource;
+pub use crate::funding::{FixedRateFunding, FundingSource};
+pub use crate::health::{
+    MarketAccount, MintDecimals, PROGRAM_ID, PositionAccount, PositionHealth, decode_market_account,
+    decode_position_account, encode_position_account, position_account_filters, position_from_account,
+};
+pub use crate::http_oracle::{HttpOracle, HttpOracleConfig};
+pub use crate::instruction::{
+    INSUFFICIENT_FUNDS_ERROR, KEEPER_NOT_WHITELISTED_ERROR, LiquidateAccounts, LiquidationOutcome,
+    POSITION_HEALTHY_ERROR, associated_token_account, borrow_instruction, create_token_account_instruction,
+    deposit_collateral_instruction, initialize_position_instruction, liquidate_instruction, market_address,
+    max_repay_amount, position_address,
+};
+#[cfg(any(test, feature = "testing"))]
+pub use crate::mark::MockBasisSource;
+#[cfg(any(test, feature = "testing"))]
+pub use crate::oracle::MockOracleProvider;
+pub use crate::oracle::{
+    OracleConfig, PYTH_DEVNET_PROGRAM_ID, PYTH_MAINNET_PROGRAM_ID, PriceData, PythPriceFeed, TradingStatus,
+    check_staleness, decode_pyth_price_account,
+};
+pub use crate::position::MarginParams;
+#[cfg(any(test, feature = "testing"))]
+pub use crate::preflight::MockPreflightRpc;
+pub use crate::preflight::{RpcPreflight, preflight};
+pub use crate::rate_limit::RateLimiter;
+pub use crate::reload::ConfigWatcher;
+pub use crate::replay::ReplayOracle;
+pub use crate::report::{
+    DEFAULT_REPORT_QUEUE_CAPACITY, DryRunLiquidation, REPORT_CSV_HEADER, ReportWriter, read_report,
+};
+#[cfg(any(test, feature = "testing"))]
+pub use crate::rpc_pool::MockEndpoint;
+pub use crate::rpc_pool::RpcPool;
+pub use crate::simulate::{SimulatedLiquidation, SimulationReport, simulate};
+pub use crate::slot::SLOT_POLL_INTERVAL;
+pub use crate::snapshot::PositionSnapshot;
+pub use crate::state::StateFile;
+pub use crate::stats::EngineStats;
+#[cfg(any(test, feature = "testing"))]
+pub use crate::submit::{MockSubmitter, Submission};
+pub use crate::systemd::SystemdNotifier;
+pub use crate::tier::{MarginTier, MarginTierSchedule};
+pub use crate::token_accounts::{TOKEN_BALANCE_CHECK_INTERVAL, TokenAccountManager, token_program_of, transfer_fee};
+pub use crate::types::{EngineEvent, EngineMode};
+pub use crate::webhook::{
+    DEFAULT_WEBHOOK_QUEUE_CAPACITY, DEFAULT_WEBHOOK_RETRY_DELAY, WebhookNotifier, WebhookTargets,
+};
@@ -0,0 +1,450 @@
+use crate::error::LiquidationError;
+use crate::preflight::PreflightRpc;
+use crate::token_accounts::{MarketTokenAccounts, mint_decimals, token_balance};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_sdk::native_token::lamports_to_sol;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tracing::warn;
+
+/// How transactions are spread over the liquidator keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyRotation {
+    /// Each key in turn
+    #[default]
+    RoundRobin,
+    /// The key that signed least recently
+    LeastRecentlyUsed,
+}
+
+/// Liquidator keys to sign with, and how to rotate between them
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct KeyPoolConfig {
+    /// Keypair files, or directories whose `.json` files are all keypairs; the
+    /// `--keypair` file alone if empty
+    pub keypairs: Vec<String>,
+    /// How transactions are spread over the keys
+    pub rotation: KeyRotation,
+}
+
+impl KeyPoolConfig {
+    /// Path of every keypair file, directories expanded to their `.json` files
+    /// in name order
+    pub fn keypair_paths(&self) -> Result<Vec<PathBuf>, LiquidationError> {
+        let mut paths = Vec::new();
+        for entry in &self.keypairs {
+            let path = Path::new(entry);
+            if !path.is_dir() {
+                paths.push(path.to_path_buf());
+                continue;
+            }
+            let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+                .map_err(|e| {
+                    LiquidationError::ConfigError(format!("Failed to read keypair directory {}: {}", entry, e))
+                })?
+                .filter_map(|file| file.ok().map(|file| file.path()))
+                .filter(|file| file.extension().is_some_and(|extension| extension == "json"))
+                .collect();
+            files.sort();
+            paths.extend(files);
+        }
+        Ok(paths)
+    }
+}
+
+/// A liquidator key and what the engine knows of it
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct KeyStatus {
+    /// The key's address
+    #[serde_as(as = "DisplayFromStr")]
+    pub pubkey: Pubkey,
+    /// Whether operators let the key sign
+    pub enabled: bool,
+    /// SOL balance last read
+    pub sol_balance: Option<f64>,
+    /// Repayment token balance last read per market (in debt tokens)
+    pub token_balances: BTreeMap<String, f64>,
+    /// Whether a balance last read was below its floor, which leaves the key
+    /// out of rotation until it's funded
+    pub underfunded: bool,
+    /// When the key last signed a transaction (Unix timestamp)
+    pub last_used: Option<i64>,
+    /// Liquidations the key performed since the engine started
+    pub liquidations: u64,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    keys: Vec<KeyStatus>,
+    /// Order in which each key last signed, for least-recently-used rotation
+    /// within the same second
+    used_at: Vec<u64>,
+    uses: u64,
+    /// Index the round robin continues from
+    next: usize,
+}
+
+/// Keypairs paying for and signing the engine's transactions, picked in turn
+/// by the configured [`KeyRotation`]
+///
+/// Spreading transactions over several keys keeps them from contending for
+/// one account and limits what a single compromised key exposes. Keys
+/// operators disabled, or whose balances fell below their floors, are skipped
+/// until enabled or funded again.
+#[derive(Default)]
+pub(crate) struct KeyPool {
+    keypairs: Vec<Keypair>,
+    state: Mutex<PoolState>,
+}
+
+impl KeyPool {
+    /// Pool the given keypairs, all enabled
+    pub fn new(keypairs: Vec<Keypair>) -> Self {
+        let keys = keypairs
+            .iter()
+            .map(|keypair| KeyStatus {
+                pubkey: keypair.pubkey(),
+                enabled: true,
+                sol_balance: None,
+                token_balances: BTreeMap::new(),
+                underfunded: false,
+                last_used: None,
+                liquidations: 0,
+            })
+            .collect();
+        let used_at = vec![0; keypairs.len()];
+        Self {
+            keypairs,
+            state: Mutex::new(PoolState {
+                keys,
+                used_at,
+                ..PoolState::default()
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Number of keys in the pool
+    pub fn len(&self) -> usize {
+        self.keypairs.len()
+    }
+
+    /// Whether the pool holds no key
+    pub fn is_empty(&self) -> bool {
+        self.keypairs.is_empty()
+    }
+
+    /// The first key, credited with what isn't signed by a particular key
+    pub fn primary(&self) -> Option<&Keypair> {
+        self.keypairs.first()
+    }
+
+    /// Whether `pubkey` is one of the pool's keys
+    pub fn contains(&self, pubkey: &Pubkey) -> bool {
+        self.keypairs.iter().any(|keypair| keypair.pubkey() == *pubkey)
+    }
+
+    /// Pick the next key to sign with at `now`, among the enabled and funded
+    /// keys, and only `authority` if the transaction advances a durable nonce
+    pub fn select(
+        &self,
+        rotation: KeyRotation,
+        now: i64,
+        authority: Option<&Pubkey>,
+    ) -> Result<&Keypair, LiquidationError> {
+        if self.is_empty() {
+            return Err(LiquidationError::ConfigError("no payer keypair to sign liquidations".to_string()));
+        }
+        if let Some(authority) = authority
+            && !self.contains(authority)
+        {
+            let keys: Vec<String> = self.keypairs.iter().map(|keypair| keypair.pubkey().to_string()).collect();
+            return Err(LiquidationError::ConfigError(format!(
+                "nonce authority {} is not the payer {}",
+                authority,
+                keys.join(" or ")
+            )));
+        }
+
+        let mut state = self.state();
+        let eligible: Vec<usize> = (0..self.len())
+            .filter(|&index| {
+                let key = &state.keys[index];
+                key.enabled && !key.underfunded && authority.is_none_or(|authority| key.pubkey == *authority)
+            })
+            .collect();
+        if eligible.is_empty() {
+            return Err(LiquidationError::ConfigError(
+                "every payer key is disabled or below its balance floors".to_string(),
+            ));
+        }
+        let index = match rotation {
+            KeyRotation::RoundRobin => {
+                eligible.iter().copied().find(|&index| index >= state.next).unwrap_or(eligible[0])
+            }
+            KeyRotation::LeastRecentlyUsed => eligible
+                .iter()
+                .copied()
+                .min_by_key(|&index| state.used_at[index])
+                .expect("eligible keys"),
+        };
+        state.next = index + 1;
+        state.uses += 1;
+        state.used_at[index] = state.uses;
+        state.keys[index].last_used = Some(now);
+        Ok(&self.keypairs[index])
+    }
+
+    /// Let a key sign or keep it out of rotation, returning its status, or
+    /// `None` if it isn't in the pool
+    pub fn set_enabled(&self, pubkey: &Pubkey, enabled: bool) -> Option<KeyStatus> {
+        let mut state = self.state();
+        let key = state.keys.iter_mut().find(|key| key.pubkey == *pubkey)?;
+        key.enabled = enabled;
+        Some(key.clone())
+    }
+
+    /// Credit a key with a liquidation
+    pub fn record_liquidation(&self, pubkey: &Pubkey) {
+        if let Some(key) = self.state().keys.iter_mut().find(|key| key.pubkey == *pubkey) {
+            key.liquidations += 1;
+        }
+    }
+
+    /// Record a key's balances, leaving it out of rotation while its SOL is
+    /// below `min_sol_balance` or a repayment balance below
+    /// `min_repay_token_balance`, and return whether it is
+    pub fn record_balances(
+        &self,
+        pubkey: &Pubkey,
+        sol_balance: Option<f64>,
+        token_balances: BTreeMap<String, f64>,
+        min_sol_balance: f64,
+        min_repay_token_balance: f64,
+    ) -> bool {
+        let mut state = self.state();
+        let Some(key) = state.keys.iter_mut().find(|key| key.pubkey == *pubkey) else {
+            return false;
+        };
+        if sol_balance.is_some() {
+            key.sol_balance = sol_balance;
+        }
+        key.token_balances.extend(token_balances);
+        key.underfunded = key.sol_balance.is_some_and(|balance| balance < min_sol_balance)
+            || key.token_balances.values().any(|&balance| balance < min_repay_token_balance);
+        key.underfunded
+    }
+
+    /// Read every key's SOL balance and its repayment balance in each market,
+    /// returning the keys below a floor
+    ///
+    /// A missing token account holds nothing; balances that can't be read are
+    /// logged and keep their last value.
+    pub async fn refresh_balances(
+        &self,
+        rpc: &dyn PreflightRpc,
+        markets: &BTreeMap<Pubkey, MarketTokenAccounts>,
+        min_sol_balance: f64,
+        min_repay_token_balance: f64,
+    ) -> Vec<Pubkey> {
+        let mut underfunded = Vec::new();
+        for keypair in &self.keypairs {
+            let pubkey = keypair.pubkey();
+            let sol_balance = match rpc.balance(&pubkey).await {
+                Ok(lamports) => Some(lamports_to_sol(lamports)),
+                Err(e) => {
+                    warn!("Unable to read the SOL balance of liquidator {}: {}", pubkey, e);
+                    None
+                }
+            };
+            let mut token_balances = BTreeMap::new();
+            for (market, accounts) in markets {
+                // The mints are the market's whoever holds them
+                let accounts = MarketTokenAccounts::derive(
+                    &pubkey,
+                    (accounts.collateral_mint, accounts.collateral_token_program),
+                    (accounts.debt_mint, accounts.debt_token_program),
+                );
+                let balance = async {
+                    let mint = rpc
+                        .account(&accounts.debt_mint)
+                        .await?
+                        .ok_or_else(|| LiquidationError::Other(format!("Mint {} doesn't exist", accounts.debt_mint)))?;
+                    let decimals = mint_decimals(&mint)?;
+                    match rpc.account(&accounts.debt).await? {
+                        Some(account) => token_balance(&account, decimals),
+                        None => Ok(0.0),
+                    }
+                };
+                match balance.await {
+                    Ok(balance) => {
+                        token_balances.insert(market.to_string(), balance);
+                    }
+                    Err(e) => warn!(
+                        "Unable to read the repayment balance of liquidator {} in market {}: {}",
+                        pubkey, market, e
+                    ),
+                }
+            }
+            if self.record_balances(&pubkey, sol_balance, token_balances, min_sol_balance, min_repay_token_balance) {
+                warn!(
+                    "Liquidator {} is below min_sol_balance of {} or min_repay_token_balance of {}; \
+                     skipping it until funded",
+                    pubkey, min_sol_balance, min_repay_token_balance
+                );
+                underfunded.push(pubkey);
+            }
+        }
+        underfunded
+    }
+
+    /// Every key's status, in pool order
+    pub fn status(&self) -> Vec<KeyStatus> {
+        self.state().keys.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::associated_token_account;
+    use crate::preflight::MockPreflightRpc;
+    use anchor_spl::token::spl_token::{
+        solana_program::program_pack::Pack,
+        state::{Account as TokenAccount, AccountState, Mint},
+    };
+    use solana_sdk::account::Account;
+    use solana_sdk::native_token::sol_to_lamports;
+
+    fn pool(keys: usize) -> (KeyPool, Vec<Pubkey>) {
+        let keypairs: Vec<Keypair> = (0..keys).map(|_| Keypair::new()).collect();
+        let pubkeys = keypairs.iter().map(Keypair::pubkey).collect();
+        (KeyPool::new(keypairs), pubkeys)
+    }
+
+    fn picks(pool: &KeyPool, rotation: KeyRotation, count: usize) -> Vec<Pubkey> {
+        (0..count).map(|_| pool.select(rotation, 1_000, None).unwrap().pubkey()).collect()
+    }
+
+    fn packed<T: Pack>(state: T, owner: Pubkey, lamports: u64) -> Account {
+        let mut data = vec![0; T::LEN];
+        state.pack_into_slice(&mut data);
+        Account {
+            lamports,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn test_round_robin_skips_disabled_and_underfunded_keys() {
+        let (pool, keys) = pool(3);
+        assert_eq!(picks(&pool, KeyRotation::RoundRobin, 4), [keys[0], keys[1], keys[2], keys[0]]);
+
+        pool.set_enabled(&keys[1], false).unwrap();
+        assert!(pool.record_balances(&keys[2], Some(0.01), BTreeMap::new(), 0.1, 0.0));
+        assert_eq!(picks(&pool, KeyRotation::RoundRobin, 2), [keys[0], keys[0]]);
+
+        pool.set_enabled(&keys[0], false).unwrap();
+        let err = pool.select(KeyRotation::RoundRobin, 1_000, None).unwrap_err();
+        assert!(err.to_string().contains("disabled or below its balance floors"), "{}", err);
+        assert!(pool.set_enabled(&Pubkey::new_unique(), true).is_none());
+    }
+
+    #[test]
+    fn test_least_recently_used_rotation() {
+        let (pool, keys) = pool(3);
+        assert_eq!(picks(&pool, KeyRotation::LeastRecentlyUsed, 3), keys);
+        pool.set_enabled(&keys[0], false).unwrap();
+        assert_eq!(picks(&pool, KeyRotation::LeastRecentlyUsed, 2), [keys[1], keys[2]]);
+        // Re-enabled, the key that waited longest goes first
+        pool.set_enabled(&keys[0], true).unwrap();
+        assert_eq!(picks(&pool, KeyRotation::LeastRecentlyUsed, 1), [keys[0]]);
+        assert_eq!(pool.status()[0].last_used, Some(1_000));
+    }
+
+    #[test]
+    fn test_nonce_transactions_signed_by_the_authority() {
+        let (pool, keys) = pool(2);
+        for _ in 0..2 {
+            assert_eq!(pool.select(KeyRotation::RoundRobin, 0, Some(&keys[1])).unwrap().pubkey(), keys[1]);
+        }
+        let err = pool.select(KeyRotation::RoundRobin, 0, Some(&Pubkey::new_unique())).unwrap_err();
+        assert!(err.to_string().contains("nonce authority"), "{}", err);
+        let err = KeyPool::default().select(KeyRotation::RoundRobin, 0, None).unwrap_err();
+        assert!(err.to_string().contains("no payer keypair"), "{}", err);
+    }
+
+    #[test]
+    fn test_keypair_directories_expanded_in_name_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.json", "a.json", "notes.txt"] {
+            std::fs::write(dir.path().join(name), "[]").unwrap();
+        }
+        let config = KeyPoolConfig {
+            keypairs: vec!["hot.json".to_string(), dir.path().display().to_string()],
+            ..KeyPoolConfig::default()
+        };
+        assert_eq!(
+            config.keypair_paths().unwrap(),
+            [PathBuf::from("hot.json"), dir.path().join("a.json"), dir.path().join("b.json")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_balances_flags_keys_below_floors() {
+        let (pool, keys) = pool(2);
+        let rpc = MockPreflightRpc::new();
+        let market = crate::instruction::market_address(0);
+        let debt_mint = Pubkey::new_unique();
+        let token_program = anchor_spl::token::ID;
+        let accounts =
+            MarketTokenAccounts::derive(&keys[0], (Pubkey::new_unique(), token_program), (debt_mint, token_program));
+        let markets = BTreeMap::from([(market, accounts)]);
+        let mint = Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        rpc.set_account(debt_mint, packed(mint, token_program, 1_461_600));
+        // The second key holds SOL but nothing to repay with
+        for (key, amount) in [(keys[0], 5_000_000), (keys[1], 0)] {
+            rpc.set_account(
+                key,
+                Account {
+                    lamports: sol_to_lamports(1.0),
+                    ..Account::default()
+                },
+            );
+            let token_account = TokenAccount {
+                mint: debt_mint,
+                owner: key,
+                amount,
+                state: AccountState::Initialized,
+                ..TokenAccount::default()
+            };
+            rpc.set_account(
+                associated_token_account(&key, &debt_mint, &token_program),
+                packed(token_account, token_program, 2_039_280),
+            );
+        }
+
+        assert_eq!(pool.refresh_balances(&rpc, &markets, 0.5, 1.0).await, [keys[1]]);
+        let status = pool.status();
+        assert_eq!(status[0].sol_balance, Some(1.0));
+        assert_eq!(status[0].token_balances[&market.to_string()], 5.0);
+        assert!(!status[0].underfunded && status[1].underfunded);
+        assert_eq!(picks(&pool, KeyRotation::RoundRobin, 2), [keys[0], keys[0]]);
+    }
+}
@@ -1,160 +1,8106 @@
 use crate::{
-    error::LiquidationError,
-    oracle::OracleProvider,
-    position::Position,
-    types::LiquidationConfig,
+    address::{OwnerAddress, PositionAddress},
+    adl::{AdlPlan, AdlPlanner, AdlQueue},
+    amount::{self, Amount, AmountExt},
+    batch::{self, BatchEntry, TransactionBatch},
+    clock::{Clock, SystemClock},
+    compute::{self, ComputeUnitCache, MAX_COMPUTE_UNIT_LIMIT, RpcSimulator, TransactionSimulator},
+    confirm::{ConfirmationStats, ConfirmationTracker, PendingSignature, Resolution, RpcStatusPoller, StatusPoller},
+    dedup::{self, DedupStats, EventDedup},
+    depth::{DepthProvider, MarketDepth},
+    drift::DriftEstimate,
+    error::{LiquidationError, RpcErrorKind},
+    events::{self, PositionLiquidated, ProgramEvent},
+    fee::{RecentFeeSource, RpcFeeSource},
+    funding::{FundingIndex, FundingSource},
+    health::{self, PROGRAM_ID, PositionAccount},
+    ingest::{self, IngestError, IngestPolicy, IngestStats, IngestViolation, SuspectPosition, Suspects},
+    insurance::InsuranceLedger,
+    key_pool::{KeyPool, KeyStatus},
+    instruction::{self, LiquidationMarket, program_market_address},
+    margin::{MarginPools, PooledMargin},
+    margin_call::{MarginCallStage, MarginCalls},
+    mark::{BasisSource, MarkPriceCalculator, ZeroBasis},
+    market::MarketConfig,
+    memory::{MemoryStats, Retention},
+    nonce::{self, NonceAccount},
+    oracle::{OracleProvider, PriceData, PriceSnapshot},
+    position::{MarginMode, Position},
+    preflight::{self, PreflightRpc, RpcPreflight},
+    priority::{self, Candidate, Prioritizer, WeightedScore},
+    profitability::{BASE_FEE_LAMPORTS, ProfitEstimate, ProfitModel},
+    quarantine::{self, Quarantine, QuarantineStats, QuarantinedPosition},
+    race,
+    rate_limit::{RateLimitStats, RateLimiter},
+    rounding,
+    replay::ReplayReport,
+    rewards::{self, LiquidationReceipt, PnlSummary, RewardRecord, RewardTracker},
+    risk::{self, AccountRisk},
+    rpc_pool::{EndpointStatus, RpcPool},
+    sanity::PriceSanity,
+    slot::{self, SlotStatus, SlotTracker},
+    snapshot::PositionSnapshot,
+    state::{PositionState, StateFile},
+    stats::EngineStats,
+    submit::{JitoSubmitter, RpcSubmitter, SubmitterKind, TransactionSubmitter},
+    systemd::SystemdNotifier,
+    throttle::{CandidateQueue, CircuitBreaker, CycleThrottle},
+    token_accounts::MarketTokenAccounts,
+    types::{
+        AtRiskReason, CacheDivergence, ConfigChange, ConfigUpdate, EngineEvent, EngineMode, FUNDING_PROJECTION_HOURS,
+        FundingProjection, InsuranceStats, LiquidationConfig, LiquidationEvent, LiquidationResult, ModeStatus,
+        PositionStatus, PositionUpdate, SkipReason, ThrottleStats, VerificationStats, WarmUpStatus,
+    },
+    versions::{PositionVersions, PositionWrite, WriteOutcome},
+    warmup::WarmUp,
 };
-use anchor_lang::prelude::*;
-use log::{error, info};
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::pubkey::Pubkey;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use crate::audit::{AuditRecord, AuditWriter};
+use crate::report::{DryRunLiquidation, ReportWriter};
+use crate::webhook::{WebhookNotifier, WebhookPayload};
+use crate::alert::{Alert, AlertLevel, AlertTrigger, Alerter};
+#[cfg(feature = "storage")]
+use crate::backfill::{self, BackfillRange, BackfillSource, BackfillStats, RpcHistory};
+#[cfg(feature = "storage")]
+use crate::storage::{LiquidationStore, StoreWriter};
+use tracing::{Span, debug, error, info, instrument, warn};
+use futures::{FutureExt, StreamExt};
+use liquidation_program::PositionBalances;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_response::{Response as RpcResponse, RpcKeyedAccount};
+use solana_sdk::{
+    account::Account,
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, PoisonError};
+use tokio::sync::{Mutex, RwLock, broadcast};
 use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 use std::result::Result as StdResult;
 
+/// Events buffered per subscriber before a lagging subscriber starts missing them
+pub(crate) const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Positions currently in a liquidation pipeline
+#[derive(Default)]
+struct InFlight(std::sync::Mutex<HashSet<PositionAddress>>);
+
+impl InFlight {
+    /// Claim a position for one pipeline, or `None` if another already has it
+    fn acquire(&self, address: PositionAddress) -> Option<InFlightGuard<'_>> {
+        let mut positions = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        positions.insert(address).then(|| InFlightGuard { in_flight: self, address })
+    }
+    
+    fn contains(&self, address: &PositionAddress) -> bool {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).contains(address)
+    }
+}
+
+/// Releases a position claimed with [`InFlight::acquire`] when dropped, however
+/// its check ends
+struct InFlightGuard<'a> {
+    in_flight: &'a InFlight,
+    address: PositionAddress,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.in_flight
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.address);
+    }
+}
+
+/// A liquidation checked and priced, ready to submit
+struct PreparedLiquidation<'a> {
+    /// Holds the position until the liquidation is finished
+    _in_flight: InFlightGuard<'a>,
+    position: Position,
+    price_data: PriceData,
+    priority_score: Option<f64>,
+    cycle_id: Option<String>,
+    /// Position size liquidated
+    amount: Amount,
+    liquidation_fraction: f64,
+    /// Debt repaid, in base units of the debt mint
+    repay_amount: u64,
+    estimated_impact_bps: Option<f64>,
+    bad_debt: Amount,
+    model: ProfitModel,
+    /// Priority fee of the first attempt
+    first_fee: u64,
+    correlation_id: String,
+    now: i64,
+}
+
+/// How checking a position ended before submission
+enum Checked<'a> {
+    /// The check ended without a liquidation to submit, with its result if any
+    Done(Option<LiquidationResult>),
+    Ready(Box<PreparedLiquidation<'a>>),
+}
+
+/// How a prepared liquidation's submission ended
+struct Submission {
+    /// Signature and signer of the transaction, or why it failed
+    outcome: StdResult<(String, Pubkey), LiquidationError>,
+    attempts: u8,
+    priority_fee: u64,
+    compute_unit_limit: u32,
+}
+
 /// Main LiquidationEngine that monitors and liquidates undercollateralized positions
 pub struct LiquidationEngine {
-    /// RPC client for Solana
-    rpc_client: Arc<RpcClient>,
+    /// RPC endpoints for Solana, failed over between by health
+    rpc: RpcPool,
+    /// Paces RPC requests, shared with the oracle when it uses the same endpoints
+    rate_limiter: Arc<RateLimiter>,
     /// Oracle for price feeds
     oracle: Arc<dyn OracleProvider + Send + Sync>,
     /// Configuration parameters
-    config: LiquidationConfig,
+    config: std::sync::RwLock<Arc<LiquidationConfig>>,
+    /// Validated configuration to switch to at the start of the next check cycle
+    pending_config: std::sync::Mutex<Option<LiquidationConfig>>,
     /// Cache of monitored positions
-    positions: RwLock<HashMap<Pubkey, Position>>,
+    positions: RwLock<HashMap<PositionAddress, Position>>,
+    /// Cross-margin positions of each owner among the monitored ones; locked
+    /// after `positions` when both are
+    margin_pools: RwLock<MarginPools>,
+    /// Recently paid priority fees, for fee strategies that follow the market
+    fee_source: Arc<dyn RecentFeeSource>,
+    /// Simulates liquidation transactions to size their compute budget
+    simulator: Arc<dyn TransactionSimulator>,
+    /// Simulated compute unit limits per symbol
+    compute_units: ComputeUnitCache,
+    /// Sends liquidation transactions
+    submitter: Arc<dyn TransactionSubmitter>,
+    /// Submitted liquidations awaiting confirmation, which hold their
+    /// positions back from further attempts
+    confirmations: ConfirmationTracker,
+    /// Keypairs paying for and signing liquidation transactions, in rotation
+    keys: KeyPool,
+    /// Durable nonce used instead of recent blockhashes, if configured
+    nonce: Option<NonceAccount>,
+    /// Markets liquidations are built against by address, preloaded by
+    /// preflight or read when first needed
+    liquidation_markets: std::sync::RwLock<BTreeMap<Pubkey, LiquidationMarket>>,
+    /// Optional source of funding rates
+    funding_source: Option<Arc<dyn FundingSource>>,
+    /// Basis between each market and its oracle index, shifting mark prices
+    basis_source: Arc<dyn BasisSource>,
+    /// Moving average basis per symbol, from which mark prices are derived
+    mark_prices: std::sync::Mutex<MarkPriceCalculator>,
+    /// Cumulative funding index per symbol
+    funding_indices: RwLock<HashMap<String, FundingIndex>>,
+    /// Bad debt absorbed by the insurance fund
+    insurance: RwLock<InsuranceLedger>,
+    /// Rewards and network fees of successful liquidations, shared with the
+    /// tasks reading them from confirmed transactions
+    rewards: Arc<std::sync::Mutex<RewardTracker>>,
+    /// Durable record of liquidation attempts
+    #[cfg(feature = "storage")]
+    store: Option<StoreWriter>,
+    /// Report of the liquidations a dry run would have made
+    report: Option<ReportWriter>,
+    /// Audit log of every liquidation attempt and its inputs
+    audit: Option<AuditWriter>,
+    /// Notifies owners' webhooks before and after liquidation
+    webhooks: Option<WebhookNotifier>,
+    /// Alerts operators about the engine itself
+    alerts: Option<Alerter>,
+    /// Tells systemd when the engine is ready, alive and stopping
+    systemd: Option<SystemdNotifier>,
+    /// Liquidations failed in a row since the last one that didn't
+    consecutive_failures: AtomicU32,
+    /// Cooldowns and unconfirmed liquidations persisted across restarts
+    state: Option<Mutex<StateFile>>,
+    /// Keys of the liquidation events published, so each reaches subscribers,
+    /// webhooks and the store once
+    dedup: std::sync::Mutex<EventDedup>,
+    /// Position updates and liquidation events for subscribers
+    events: broadcast::Sender<EngineEvent>,
+    /// Events buffered per subscriber
+    event_capacity: usize,
+    /// Positions fully liquidated or closed, evicted once their retention passes
+    retention: std::sync::Mutex<Retention>,
+    /// Last position update pushed for each position
+    last_updates: RwLock<HashMap<PositionAddress, PositionUpdate>>,
+    /// Orders liquidation candidates, weighted by the configured weights if unset
+    prioritizer: Option<Arc<dyn Prioritizer>>,
+    /// Book statistics last computed, served until `stats.cache_secs` old
+    stats: Mutex<Option<EngineStats>>,
+    /// Auto-deleveraging queues ranked at the last check cycle's prices
+    adl: RwLock<AdlPlanner>,
+    /// Depth liquidations are sized against, the configured market liquidity if unset
+    depth_provider: Option<Arc<dyn DepthProvider>>,
+    /// When each position was last checked for liquidation, or first seen
+    /// liquidatable if it hasn't been yet
+    last_checked: RwLock<HashMap<PositionAddress, i64>>,
+    /// Positions being checked, so concurrent checks never liquidate one twice
+    in_flight: InFlight,
+    /// Liquidations admitted and throttled by the last check cycle's caps
+    throttle_stats: std::sync::Mutex<ThrottleStats>,
+    /// Candidates carried over to later cycles, and the concurrency raised to
+    /// work through them
+    candidate_queue: std::sync::Mutex<CandidateQueue>,
+    /// Pauses liquidation when too many fire within a window
+    circuit_breaker: std::sync::Mutex<CircuitBreaker>,
+    /// Whether operators let the engine check and liquidate positions
+    mode: std::sync::Mutex<ModeStatus>,
+    /// Data confirmed since the engine started, which it liquidates only once
+    /// complete
+    warm_up: std::sync::Mutex<WarmUp>,
+    /// Positions flagged under the margin call, waiting out their grace period
+    margin_calls: std::sync::Mutex<MarginCalls>,
+    /// Versions last written to positions, against which older writes are rejected
+    versions: std::sync::Mutex<PositionVersions>,
+    /// Recently accepted prices, against which outlying oracle prints are rejected
+    price_sanity: std::sync::Mutex<PriceSanity>,
+    /// The cluster's current slot, against which oracle prices are aged and
+    /// the RPC node's health is judged
+    slots: Arc<SlotTracker>,
+    /// Positions left out of check cycles after their check panicked or their
+    /// values couldn't be evaluated
+    quarantine: std::sync::Mutex<Quarantine>,
+    /// Positions monitored despite breaking the ingest rules, never liquidated
+    /// until operators clear them
+    suspects: std::sync::Mutex<Suspects>,
+    /// Liquidations checked against the chain before submission, and the
+    /// divergences found
+    verification: std::sync::Mutex<VerificationStats>,
+    /// Time cooldowns, funding and bad debt windows are judged by
+    clock: Arc<dyn Clock>,
+    /// Set once the engine replays recorded prices, which forces dry runs
+    replaying: AtomicBool,
+    /// Cancelled to stop the engine and the servers sharing it
+    shutdown: CancellationToken,
 }
 
 impl LiquidationEngine {
     /// Create a new LiquidationEngine instance
+    ///
+    /// `rpc` is a single client or URL, or an [`RpcPool`] to fail over between
+    /// endpoints. Every RPC request goes through `rate_limiter`. Nothing is
+    /// checked; [`builder`](Self::builder) validates the pieces fit together.
     pub fn new(
-        rpc_client: Arc<RpcClient>,
+        rpc: impl Into<RpcPool>,
         oracle: Arc<dyn OracleProvider + Send + Sync>,
         config: LiquidationConfig,
+        rate_limiter: Arc<RateLimiter>,
     ) -> Self {
+        let rpc = rpc.into();
+        let submitter: Arc<dyn TransactionSubmitter> = match config.submitter {
+            SubmitterKind::Rpc => Arc::new(
+                RpcSubmitter::new(rpc.clone(), rate_limiter.clone()).with_broadcast(config.broadcast_transactions),
+            ),
+            SubmitterKind::Jito => Arc::new(JitoSubmitter::new(
+                rpc.clone(),
+                rate_limiter.clone(),
+                config.jito.clone(),
+            )),
+        };
+        let nonce = config
+            .nonce
+            .clone()
+            .map(|nonce| NonceAccount::new(nonce, rpc.clone(), rate_limiter.clone()));
+        let dedup = EventDedup::new(config.event_dedup_retention_secs);
+        
         Self {
-            rpc_client,
+            fee_source: Arc::new(RpcFeeSource::new(rpc.clone(), rate_limiter.clone())),
+            simulator: Arc::new(RpcSimulator::new(rpc.clone(), rate_limiter.clone())),
+            compute_units: ComputeUnitCache::new(),
+            submitter,
+            confirmations: ConfirmationTracker::new(Arc::new(RpcStatusPoller::new(rpc.clone(), rate_limiter.clone()))),
+            keys: KeyPool::default(),
+            nonce,
+            liquidation_markets: std::sync::RwLock::new(BTreeMap::new()),
+            rpc,
+            rate_limiter,
             oracle,
-            config,
+            config: std::sync::RwLock::new(Arc::new(config)),
+            pending_config: std::sync::Mutex::new(None),
             positions: RwLock::new(HashMap::new()),
+            margin_pools: RwLock::new(MarginPools::new()),
+            funding_source: None,
+            basis_source: Arc::new(ZeroBasis),
+            mark_prices: std::sync::Mutex::new(MarkPriceCalculator::new()),
+            funding_indices: RwLock::new(HashMap::new()),
+            insurance: RwLock::new(InsuranceLedger::new()),
+            rewards: Arc::new(std::sync::Mutex::new(RewardTracker::new())),
+            #[cfg(feature = "storage")]
+            store: None,
+            report: None,
+            audit: None,
+            webhooks: None,
+            alerts: None,
+            systemd: None,
+            consecutive_failures: AtomicU32::new(0),
+            state: None,
+            dedup: std::sync::Mutex::new(dedup),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            event_capacity: EVENT_CHANNEL_CAPACITY,
+            retention: std::sync::Mutex::new(Retention::default()),
+            last_updates: RwLock::new(HashMap::new()),
+            prioritizer: None,
+            depth_provider: None,
+            stats: Mutex::new(None),
+            adl: RwLock::new(AdlPlanner::default()),
+            last_checked: RwLock::new(HashMap::new()),
+            in_flight: InFlight::default(),
+            throttle_stats: std::sync::Mutex::new(ThrottleStats::default()),
+            candidate_queue: std::sync::Mutex::new(CandidateQueue::new()),
+            circuit_breaker: std::sync::Mutex::new(CircuitBreaker::new()),
+            mode: std::sync::Mutex::new(ModeStatus {
+                mode: EngineMode::Running,
+                since: SystemClock.now_ts(),
+                source: "startup".to_string(),
+                changes: 0,
+            }),
+            warm_up: std::sync::Mutex::new(WarmUp::default()),
+            margin_calls: std::sync::Mutex::new(MarginCalls::default()),
+            versions: std::sync::Mutex::new(PositionVersions::default()),
+            price_sanity: std::sync::Mutex::new(PriceSanity::new()),
+            slots: Arc::new(SlotTracker::new()),
+            quarantine: std::sync::Mutex::new(Quarantine::default()),
+            suspects: std::sync::Mutex::new(Suspects::default()),
+            verification: std::sync::Mutex::new(VerificationStats::default()),
+            clock: Arc::new(SystemClock),
+            replaying: AtomicBool::new(false),
+            shutdown: CancellationToken::new(),
+        }
+    }
+    
+    /// Accrue funding on monitored positions using the given funding source
+    pub fn with_funding_source(mut self, funding_source: Arc<dyn FundingSource>) -> Self {
+        self.funding_source = Some(funding_source);
+        self
+    }
+    
+    /// Shift mark prices from oracle index prices by the basis `basis_source`
+    /// reports, within each symbol's configured `max_basis_bps`
+    pub fn with_basis_source(mut self, basis_source: Arc<dyn BasisSource>) -> Self {
+        self.basis_source = basis_source;
+        self
+    }
+    
+    /// Order liquidation candidates with the given prioritizer instead of the
+    /// configured weights
+    pub fn with_prioritizer(mut self, prioritizer: Arc<dyn Prioritizer>) -> Self {
+        self.prioritizer = Some(prioritizer);
+        self
+    }
+    
+    /// Size liquidations against depth from the given provider instead of the
+    /// configured market liquidity
+    pub fn with_depth_provider(mut self, depth_provider: Arc<dyn DepthProvider>) -> Self {
+        self.depth_provider = Some(depth_provider);
+        self
+    }
+    
+    /// Look up recent priority fees through the given source instead of the RPC client
+    pub fn with_fee_source(mut self, fee_source: Arc<dyn RecentFeeSource>) -> Self {
+        self.fee_source = fee_source;
+        self
+    }
+    
+    /// Size compute budgets by simulating through the given simulator instead of the RPC client
+    pub fn with_simulator(mut self, simulator: Arc<dyn TransactionSimulator>) -> Self {
+        self.simulator = simulator;
+        self
+    }
+    
+    /// Send liquidation transactions through the given submitter instead of the
+    /// configured one
+    pub fn with_submitter(mut self, submitter: Arc<dyn TransactionSubmitter>) -> Self {
+        self.submitter = submitter;
+        self
+    }
+    
+    /// Look up the status of submitted liquidations with the given poller
+    pub fn with_status_poller(mut self, poller: Arc<dyn StatusPoller>) -> Self {
+        self.confirmations = self.confirmations.with_poller(poller);
+        self
+    }
+    
+    /// Build liquidations against the given markets, by address, instead of
+    /// reading each from its program when first needed
+    pub fn with_liquidation_markets(mut self, markets: BTreeMap<Pubkey, LiquidationMarket>) -> Self {
+        self.liquidation_markets = std::sync::RwLock::new(markets);
+        self
+    }
+    
+    /// Pay for and sign liquidation transactions with the given keypair
+    ///
+    /// Required unless the engine only ever runs in dry-run mode.
+    pub fn with_payer(mut self, payer: Keypair) -> Self {
+        self.keys = KeyPool::new(vec![payer]);
+        self
+    }
+
+    /// Pay for and sign liquidation transactions with the given keypairs, each
+    /// picked in turn by the configured `key_pool.rotation`
+    pub fn with_payers(mut self, payers: Vec<Keypair>) -> Self {
+        self.keys = KeyPool::new(payers);
+        self
+    }
+    
+    /// Judge time by the given clock instead of the system clock
+    ///
+    /// A [`ManualClock`](crate::clock::ManualClock) lets [`run_replay`](Self::run_replay)
+    /// step through recorded prices. Until the mode is first set, it dates
+    /// from the clock's current time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        let status = self.mode.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if status.changes == 0 {
+            status.since = self.clock.now_ts();
+        }
+        self
+    }
+    
+    /// Current Unix timestamp by the engine's clock
+    fn now(&self) -> i64 {
+        self.clock.now_ts()
+    }
+    
+    /// Whether liquidations are simulated rather than sent, as they always are in replays
+    fn dry_run(&self) -> bool {
+        self.config().dry_run || self.replaying.load(Ordering::Relaxed)
+    }
+    
+    /// Address credited as the liquidator where no particular key signs, the
+    /// first of the pool
+    fn liquidator(&self) -> Pubkey {
+        self.keys.primary().map(|payer| payer.pubkey()).unwrap_or_default()
+    }
+    
+    /// Remember cooldowns, unconfirmed liquidations and the liquidation events
+    /// published in the given state file
+    ///
+    /// Stale entries are pruned on load and at the start of every check cycle.
+    pub fn with_state_file(mut self, mut state: StateFile) -> Self {
+        if let Err(e) = state.prune(self.now(), self.state_max_age_secs()) {
+            error!("Failed to prune state file {}: {}", state.path().display(), e);
+        }
+        let dedup = self.dedup.get_mut().unwrap_or_else(PoisonError::into_inner);
+        dedup.restore(state.seen_events());
+        dedup.prune(self.clock.now_ts());
+        // Liquidations submitted before a restart are awaited without knowing
+        // their blockhash
+        for (address, signature, submitted_at) in state.pending() {
+            match Signature::from_str(signature) {
+                Ok(signature) => self.confirmations.track(
+                    address,
+                    PendingSignature {
+                        signature,
+                        blockhash: None,
+                        submitted_at,
+                    },
+                ),
+                Err(e) => warn!("Ignoring pending liquidation {} of {}: {}", signature, address, e),
+            }
         }
+        self.state = Some(Mutex::new(state));
+        self
+    }
+    
+    /// Record every liquidation attempt through the given store writer
+    #[cfg(feature = "storage")]
+    pub fn with_store(mut self, store: StoreWriter) -> Self {
+        self.store = Some(store);
+        self
+    }
+    
+    /// Report every liquidation a dry run would have made through the given writer
+    pub fn with_report(mut self, report: ReportWriter) -> Self {
+        self.report = Some(report);
+        self
+    }
+    
+    /// Audit every liquidation attempt through the given writer
+    pub fn with_audit(mut self, audit: AuditWriter) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+    
+    /// Notify owners' webhooks through the given notifier
+    pub fn with_webhooks(mut self, webhooks: WebhookNotifier) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+    
+    /// Alert operators through the given alerter
+    pub fn with_alerts(mut self, alerts: Alerter) -> Self {
+        self.alerts = Some(alerts);
+        self
     }
 
-    /// Start the liquidation monitoring service
+    /// Start the liquidation monitoring service, running until [`shutdown`](Self::shutdown)
+    ///
+    /// Nothing is liquidated while the engine warms up: until every enabled
+    /// market's positions were loaded and every monitored symbol freshly
+    /// priced, and then for `warmup_cycles` more cycles, positions are only
+    /// evaluated, as when monitor-only. Once shut down, the audit log is
+    /// flushed before this returns.
     pub async fn start(&self) -> StdResult<(), LiquidationError> {
         info!("Starting liquidation engine");
-        let mut interval = tokio::time::interval(Duration::from_millis(self.config.check_interval_ms));
+        self.begin_warm_up();
+        let mut interval_ms = self.config().check_interval_ms;
+        let mut interval = tokio::time::interval(Duration::from_millis(interval_ms));
+        if let Some(watchdog) = self.systemd.as_ref().and_then(SystemdNotifier::watchdog_interval)
+            && Duration::from_millis(interval_ms) * 2 > watchdog
+        {
+            warn!(
+                "Check cycles every {}ms leave little of systemd's {:?} watchdog interval; expect restarts",
+                interval_ms, watchdog
+            );
+        }
+        self.notify_systemd(&format!("STATUS={}, starting", self.mode()));
+        let mut ready = false;
         
         loop {
-            interval.tick().await;
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.shutdown.cancelled() => {
+                    self.notify_systemd("STOPPING=1\nSTATUS=Shutting down");
+                    if let Some(audit) = &self.audit {
+                        audit.flush().await;
+                    }
+                    info!("Liquidation engine stopped");
+                    return Ok(());
+                }
+            }
             
-            if let Err(e) = self.check_positions().await {
+            let results = self.check_positions().await;
+            if let Err(e) = &results {
                 error!("Error checking positions: {}", e);
-                continue;
+            }
+            
+            // Only a completed cycle pings the watchdog, so systemd restarts an
+            // engine whose cycle wedged. Paused engines don't warm up, but are
+            // as ready as they're asked to be.
+            let mut state = String::new();
+            if !ready && (self.warm_up_status().is_ready() || self.mode() == EngineMode::Paused) {
+                ready = true;
+                state.push_str("READY=1\n");
+            }
+            state.push_str("WATCHDOG=1\nSTATUS=");
+            state.push_str(&self.systemd_status(results.as_deref()));
+            self.notify_systemd(&state);
+            
+            // The cycle may have applied a new interval
+            let check_interval_ms = self.config().check_interval_ms;
+            if check_interval_ms != interval_ms {
+                interval_ms = check_interval_ms;
+                let period = Duration::from_millis(interval_ms);
+                interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
             }
         }
     }
     
     /// Check all monitored positions for liquidation
-    pub async fn check_positions(&self) -> StdResult<(), LiquidationError> {
+    #[instrument(
+        name = "check_cycle",
+        skip_all,
+        fields(positions = tracing::field::Empty, cycle_id = tracing::field::Empty)
+    )]
+    pub async fn check_positions(&self) -> StdResult<Vec<LiquidationResult>, LiquidationError> {
+        self.apply_pending_config();
+        if self.mode() == EngineMode::Paused {
+            info!("Engine paused, not checking positions");
+            return Ok(Vec::new());
+        }
         info!("Checking all positions for liquidation");
         
+        let now = self.now();
+        self.accrue_funding(now).await;
+        self.prune_state(now).await;
+        self.evict_finished(now).await;
+        
         // Get a snapshot of all positions
         let positions = self.positions.read().await;
-        let positions_snapshot: Vec<Position> = positions.values().cloned().collect();
+        let mut positions_snapshot: Vec<Position> = positions.values().cloned().collect();
         drop(positions); // Release the read lock
+        Span::current().record("positions", positions_snapshot.len());
+        
+        // Quarantined positions sit cycles out, and positions that can't be
+        // evaluated join them before their values reach the cycle's math
+        let mut results = Vec::new();
+        {
+            let mut quarantine = self.quarantine.lock().unwrap_or_else(PoisonError::into_inner);
+            positions_snapshot.retain(|position| {
+                if quarantine.contains(&position.address) {
+                    return false;
+                }
+                let Some(field) = position.non_finite_field() else {
+                    return true;
+                };
+                let reason = format!("non-finite {}", field);
+                warn!("Quarantining position {}: {}", position.address, reason);
+                quarantine.quarantine(position.address, reason.clone(), now);
+                results.push(self.skip(position.address, SkipReason::Quarantined(reason)));
+                false
+            });
+        }
+        
+        // Every position is evaluated at prices taken once for the whole cycle
+        let cycle_id = Uuid::new_v4().to_string();
+        Span::current().record("cycle_id", cycle_id.as_str());
+        let (price_data, price_failures) = self.latest_price_data(&positions_snapshot).await;
+        let prices: HashMap<String, Amount> =
+            price_data.iter().map(|(symbol, data)| (symbol.clone(), data.price)).collect();
+        self.observe_warm_up(&positions_snapshot, &prices);
+        
+        // Positions the oracle couldn't price sit the cycle out, while the rest
+        // of the book is checked as usual
+        positions_snapshot.retain(|position| {
+            let Some(e) = price_failures.get(&position.symbol) else {
+                return true;
+            };
+            results.push(self.skip(position.address, SkipReason::PriceUnavailable(e.to_string())));
+            false
+        });
         
-        // Process positions sequentially to avoid borrow checker issues
-        for position in positions_snapshot {
-            if let Err(e) = self.check_position(position).await {
-                error!("Error checking position: {}", e);
+        // Work through the riskiest accounts first, with collateral assets
+        // valued at the same prices
+        self.revalue_collateral(&mut positions_snapshot, &prices).await;
+        let accounts = risk::aggregate_account_risk(&positions_snapshot, &prices);
+        risk::sort_by_account_risk(&mut positions_snapshot, &accounts);
+        *self.adl.write().await = AdlPlanner::new(&positions_snapshot, &prices);
+        // Health is judged at mark prices, collateral at the index
+        let prices = self.mark_prices(&positions_snapshot, prices).await;
+        let snapshot = PriceSnapshot {
+            prices: price_data
+                .into_iter()
+                .filter_map(|(symbol, data)| {
+                    let mark_price = *prices.get(&symbol)?;
+                    Some((symbol, data.at_mark(mark_price)))
+                })
+                .collect(),
+            cycle_id,
+            taken_at: now,
+        };
+        let mut priced: Vec<String> =
+            snapshot.prices.iter().map(|(symbol, data)| format!("{}={}", symbol, data.price)).collect();
+        priced.sort();
+        info!("Cycle {} prices taken at {}: {}", snapshot.cycle_id, now, priced.join(", "));
+        self.publish_position_updates(&positions_snapshot, &prices, now).await;
+        
+        // Only the highest priority candidates are liquidated this cycle; the
+        // rest are queued for the next one, where they're judged again at its
+        // prices rather than attempted late on stale ones. Positions held back by
+        // a cooldown or a pending liquidation are checked without taking a slot.
+        let (held_back, candidates) = self.liquidation_candidates(positions_snapshot, &prices, now).await;
+        let concurrency = self.concurrency();
+        let mut carried = Vec::new();
+        let mut checks: Vec<(Position, Option<f64>)> = held_back.into_iter().map(|position| (position, None)).collect();
+        for (rank, (position, score)) in candidates.into_iter().enumerate() {
+            if rank < concurrency {
+                checks.push((position, Some(score)));
+            } else {
+                info!("Deferring position {} with priority {:.2} to the next cycle", position.address, score);
+                carried.push(position.address);
             }
         }
         
-        Ok(())
-    }
-    
-    /// Check a single position for liquidation
-    async fn check_position(&self, position: Position) -> StdResult<(), LiquidationError> {
-        // Skip if position was recently liquidated
-        if let Some(last_liquidated) = position.last_liquidated {
-            let now = chrono::Utc::now().timestamp() as u64;
-            if now.saturating_sub(last_liquidated as u64) < self.config.min_liquidation_interval_secs {
-                return Ok(());
+        // Process positions sequentially to avoid borrow checker issues. Candidates
+        // beyond the per-cycle caps wait for the next cycle, keeping their staleness.
+        // Small liquidations are held back to share transactions at the end of the cycle.
+        let config = self.config();
+        let mut batched = Vec::new();
+        let mut throttle = CycleThrottle::new(
+            config.max_liquidations_per_symbol_per_cycle,
+            config.max_notional_liquidated_per_cycle,
+        );
+        for (position, priority_score) in checks {
+            let price = prices.get(&position.symbol).copied().unwrap_or_default();
+            if priority_score.is_some() {
+                let fraction = config.liquidation_fraction(&position.symbol, position.bad_debt(price).as_f64());
+                let notional = position.value(price).as_f64() * fraction;
+                if !throttle.admits(&position.symbol, notional) {
+                    info!("Throttling liquidation of {} until the next cycle", position.address);
+                    carried.push(position.address);
+                    results.push(self.skip(position.address, SkipReason::Throttled));
+                    continue;
+                }
+                if self.in_flight.contains(&position.address) {
+                    carried.push(position.address);
+                }
+                self.last_checked.write().await.insert(position.address, now);
+            }
+            let symbol = position.symbol.clone();
+            let pending = batched.len();
+            let checked = self.check_isolated(position, priority_score, &snapshot, &mut batched).await;
+            if let Some(prepared) = batched.get(pending) {
+                throttle.record(&symbol, (prepared.amount * price).as_f64());
             }
+            match checked {
+                Ok(Some(result)) => {
+                    if let LiquidationResult::Success { amount, .. } = &result {
+                        throttle.record(&symbol, amount * price.as_f64());
+                    }
+                    info!("{}", result);
+                    results.push(result);
+                }
+                Ok(None) => {}
+                Err(e) => error!("Error checking position: {}", e),
+            }
+        }
+        for result in self.submit_liquidations(batched).await {
+            info!("{}", result);
+            results.push(result);
         }
         
-        // Get the current price from the oracle
-        let price = self.oracle.get_price(&position.symbol).await?;
+        let (cycle_liquidations, cycle_notional, cycle_throttled) = throttle.totals();
+        let mut stats = self.throttle_stats.lock().unwrap_or_else(PoisonError::into_inner);
+        stats.cycle_liquidations = cycle_liquidations;
+        stats.cycle_notional = cycle_notional;
+        stats.cycle_throttled = cycle_throttled;
+        stats.total_throttled += cycle_throttled as u64;
+        drop(stats);
+        self.carry_over_candidates(&carried, now);
+        self.warm_up.lock().unwrap_or_else(PoisonError::into_inner).finish_cycle();
+        
+        Ok(results)
+    }
+    
+    /// Check a position as part of a cycle, turning a panic into a failed
+    /// result and quarantining the position rather than ending the cycle
+    async fn check_isolated<'a>(
+        &'a self,
+        position: Position,
+        priority_score: Option<f64>,
+        snapshot: &PriceSnapshot,
+        batched: &mut Vec<PreparedLiquidation<'a>>,
+    ) -> StdResult<Option<LiquidationResult>, LiquidationError> {
+        let (address, symbol) = (position.address, position.symbol.clone());
+        let check = self.check_scored_position(position, priority_score, Some(snapshot), Some(batched));
+        let check = AssertUnwindSafe(check);
+        let payload = match check.catch_unwind().await {
+            Ok(checked) => return checked,
+            Err(payload) => payload,
+        };
+        let reason = format!("check panicked: {}", quarantine::panic_message(payload.as_ref()));
+        error!("Quarantining position {}: {}", address, reason);
+        let mut quarantine = self.quarantine.lock().unwrap_or_else(PoisonError::into_inner);
+        quarantine.record_panic();
+        quarantine.quarantine(address, reason.clone(), self.now());
+        Ok(Some(LiquidationResult::Failure {
+            position: address,
+            error: reason,
+            sub_reason: None,
+            attempts: 0,
+            correlation_id: Uuid::new_v4().to_string(),
+            priority_score,
+            price: snapshot.get(&symbol).map_or(0.0, |price_data| price_data.price.as_f64()),
+            cycle_id: Some(snapshot.cycle_id.clone()),
+        }))
+    }
+    
+    /// Judge whether a warming-up engine's data is complete at a cycle's
+    /// prices, logging its progress, and end the warm-up once it has run
+    /// `warmup_cycles` cycles on complete data
+    fn observe_warm_up(&self, positions: &[Position], prices: &HashMap<String, Amount>) {
+        let config = self.config();
+        let mut warm_up = self.warm_up.lock().unwrap_or_else(PoisonError::into_inner);
+        if !warm_up.is_warming_up() {
+            return;
+        }
+        let markets = config.markets.iter().filter(|market| market.enabled).map(|market| market.symbol.as_str());
+        let symbols = positions.iter().map(|position| position.symbol.as_str());
+        if warm_up.observe(markets, symbols, |symbol| prices.contains_key(symbol), config.warmup_cycles) {
+            let message = format!(
+                "Warm-up complete after {} cycles on complete data; liquidating",
+                config.warmup_cycles
+            );
+            info!("{}", message);
+            self.alert(AlertTrigger::WarmUp, AlertLevel::Info, "Engine warmed up", message);
+            return;
+        }
+        let status = warm_up.status(config.warmup_cycles);
+        info!(
+            cycles_completed = status.cycles_completed,
+            "Warming up: {}/{} cycles on complete data, markets not loaded: [{}], symbols not priced: [{}]",
+            status.cycles_completed,
+            status.cycles_required,
+            status.unloaded_markets.join(", "),
+            status.unpriced_symbols.join(", ")
+        );
+    }
+    
+    /// Queue the candidates a cycle carried over, warning while the oldest has
+    /// waited longer than `max_queue_age_secs`, and with `auto_scale_concurrency`
+    /// raising the next cycle's concurrency until it no longer has
+    fn carry_over_candidates(&self, carried: &[PositionAddress], now: i64) {
+        let config = self.config();
+        let mut queue = self.candidate_queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.carry_over(carried, now);
+        if config.auto_scale_concurrency {
+            queue.scale_concurrency(
+                config.max_concurrent_liquidations,
+                config.max_auto_scaled_concurrency,
+                config.max_queue_age_secs,
+                now,
+            );
+        }
+        let (Some(oldest), Some(age)) = (queue.candidates().next().copied(), queue.oldest_age(now)) else {
+            return;
+        };
+        if age <= config.max_queue_age_secs {
+            return;
+        }
+        let depth = queue.depth();
+        drop(queue);
+        let concurrency = self.concurrency();
+        // Escalates once the backlog has waited twice as long as allowed
+        if age >= config.max_queue_age_secs.saturating_mul(2) {
+            error!(
+                queue_depth = depth,
+                oldest_queued_age_secs = age,
+                "{} liquidation candidates queued, the oldest, position {}, for {}s, over twice max_queue_age_secs of {}; liquidating {} per cycle",
+                depth, oldest.address, age, config.max_queue_age_secs, concurrency
+            );
+        } else {
+            warn!(
+                queue_depth = depth,
+                oldest_queued_age_secs = age,
+                "{} liquidation candidates queued, the oldest, position {}, for {}s, over max_queue_age_secs of {}; liquidating {} per cycle",
+                depth, oldest.address, age, config.max_queue_age_secs, concurrency
+            );
+        }
+    }
+    
+    /// Liquidations a cycle admits: `max_concurrent_liquidations`, or more while
+    /// an overdue backlog is auto-scaled
+    fn concurrency(&self) -> usize {
+        let config = self.config();
+        if !config.auto_scale_concurrency {
+            return config.max_concurrent_liquidations;
+        }
+        self.candidate_queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .concurrency(config.max_concurrent_liquidations)
+            .min(config.max_auto_scaled_concurrency.max(config.max_concurrent_liquidations))
+    }
+    
+    /// Liquidation candidates carried over to the next check cycle
+    pub fn queue_depth(&self) -> usize {
+        self.candidate_queue.lock().unwrap_or_else(PoisonError::into_inner).depth()
+    }
+    
+    /// How long the oldest candidate carried over to the next check cycle has
+    /// waited (in seconds), if any is queued
+    pub fn oldest_queued_age(&self) -> Option<u64> {
+        self.candidate_queue.lock().unwrap_or_else(PoisonError::into_inner).oldest_age(self.now())
+    }
+    
+    /// Positions liquidatable at `prices`, split into those held back by a
+    /// cooldown or an unconfirmed liquidation and the rest, scored and ordered
+    /// highest priority first
+    ///
+    /// Candidates with equal scores keep the order of `positions`.
+    async fn liquidation_candidates(
+        &self,
+        positions: Vec<Position>,
+        prices: &HashMap<String, Amount>,
+        now: i64,
+    ) -> (Vec<Position>, Vec<(Position, f64)>) {
+        let config = self.config();
+        let weighted = WeightedScore::new(config.priority_weights);
+        let prioritizer = self.prioritizer.as_deref().unwrap_or(&weighted);
         
-        // Check if the position is undercollateralized
-        if position.is_undercollateralized(price, self.config.maintenance_margin) {
-            info!("Liquidating position: {:?} at price: {}", position, price);
-            self.liquidate_position(&position, price).await?;
+        let pools = self.cross_margin_pools(prices).await;
+        let mut held_back = Vec::new();
+        let mut candidates = Vec::new();
+        let mut by_address = HashMap::new();
+        let mut last_checked = self.last_checked.write().await;
+        for position in positions {
+            let Some(&price) = prices.get(&position.symbol) else {
+                continue;
+            };
+            if !config.market_enabled(&position.symbol) {
+                continue;
+            }
+            let margin_params = config.margin_params_at(&position, price);
+            let undercollateralized = match (position.margin_mode, pools.get(&position.owner)) {
+                (MarginMode::Cross, Some(pool)) => position.is_undercollateralized_cross(price, margin_params, pool),
+                // A pool with an unpriced position can't be judged this cycle
+                (MarginMode::Cross, None) => false,
+                (MarginMode::Isolated, _) => position.is_undercollateralized(price, margin_params),
+            };
+            if !undercollateralized {
+                continue;
+            }
+            let state = self.position_state(&position.address).await;
+            let cooling_down = self.in_cooldown(&position, state.as_ref(), now) && !self.bypasses_cooldown(&position, price);
+            if cooling_down || self.confirmations.is_pending(&position.address) {
+                held_back.push(position);
+                continue;
+            }
+            
+            let bad_debt = position.bad_debt(price).as_f64();
+            let liquidation_fraction = config.liquidation_fraction(&position.symbol, bad_debt);
+            let model = ProfitModel::from_config(&config, self.priority_fee(&position, 1).await);
+            let expected_profit = match self.estimate_profit(&model, &position, price, liquidation_fraction).await {
+                Some(estimate) => estimate.net_profit,
+                None => model.expected_liquidation_reward(&position, price, liquidation_fraction),
+            };
+            let last_checked = *last_checked.entry(position.address).or_insert(now);
+            candidates.push(Candidate {
+                position: position.address,
+                expected_profit,
+                bad_debt,
+                staleness_secs: now.saturating_sub(last_checked) as f64,
+            });
+            by_address.insert(position.address, position);
         }
+        // Positions that stopped being candidates start afresh if they return
+        last_checked.retain(|address, _| by_address.contains_key(address));
+        drop(last_checked);
         
-        Ok(())
+        let scored = priority::prioritize(prioritizer, candidates)
+            .into_iter()
+            .filter_map(|(candidate, score)| {
+                info!("Liquidation candidate {} scored {:.2}: {}", candidate.position, score, candidate);
+                by_address.remove(&candidate.position).map(|position| (position, score))
+            })
+            .collect();
+        (held_back, scored)
     }
     
-    /// Execute liquidation of a position
-    async fn liquidate_position(
+    /// Pooled margin of every owner with cross positions, at `prices`
+    ///
+    /// Owners with a cross position that can't be priced are left out.
+    async fn cross_margin_pools(&self, prices: &HashMap<String, Amount>) -> HashMap<OwnerAddress, PooledMargin> {
+        let config = self.config();
+        let margin_params = |position: &Position, price: Amount| config.margin_params_at(position, price);
+        let positions = self.positions.read().await;
+        let pools = self.margin_pools.read().await;
+        pools
+            .owners()
+            .filter_map(|owner| Some((*owner, pools.pooled(owner, &positions, prices, margin_params)?)))
+            .collect()
+    }
+    
+    /// Pooled margin of a cross position's owner, with the position at `price`
+    /// and its siblings at their mark prices
+    ///
+    /// Returns `None` for isolated positions.
+    async fn pooled_margin(
         &self,
         position: &Position,
-        price: f64,
-    ) -> StdResult<(), LiquidationError> {
-        // Implement liquidation logic here
-        // This would involve:
-        // 1. Creating and sending a transaction to the Solana network
-        // 2. Updating the position's state
-        // 3. Emitting events/logs
-        
-        info!("Liquidating position: {:?} at price: {}", position, price);
+        price: Amount,
+    ) -> StdResult<Option<PooledMargin>, LiquidationError> {
+        if position.margin_mode != MarginMode::Cross {
+            return Ok(None);
+        }
+        let siblings: Vec<Position> = {
+            let positions = self.positions.read().await;
+            let pools = self.margin_pools.read().await;
+            pools
+                .siblings(&position.address)
+                .iter()
+                .filter_map(|address| positions.get(address).cloned())
+                .collect()
+        };
+        let mut prices = HashMap::from([(position.symbol.clone(), price)]);
+        for sibling in &siblings {
+            if !prices.contains_key(&sibling.symbol) {
+                let index_price = self.oracle.get_price(&sibling.symbol).await?;
+                let price = self.mark_price(&sibling.symbol, index_price).await;
+                prices.insert(sibling.symbol.clone(), price);
+            }
+        }
+        let config = self.config();
+        Ok(PooledMargin::new(siblings.iter().chain([position]), &prices, |position, price| {
+            config.margin_params_at(position, price)
+        }))
+    }
+    
+    /// Check the positions pooled with a liquidated cross position straight
+    /// away, since the pool has lost the liquidated margin
+    ///
+    /// Boxed, since checking a sibling may recheck its own siblings.
+    fn recheck_siblings<'a>(&'a self, position: &'a Position) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            let siblings = self.margin_pools.read().await.siblings(&position.address);
+            for address in siblings {
+                let Some(sibling) = self.get_position(&address).await else {
+                    continue;
+                };
+                match self.check_position(sibling).await {
+                    Ok(Some(result)) => info!("Rechecked {} pooled with {}: {}", address, position.address, result),
+                    Ok(None) => {}
+                    Err(e) => error!("Error rechecking {} pooled with {}: {}", address, position.address, e),
+                }
+            }
+        })
+    }
+    
+    /// Whether a position was liquidated too recently to be liquidated again,
+    /// including before a restart
+    fn in_cooldown(&self, position: &Position, state: Option<&PositionState>, now: i64) -> bool {
+        let last_liquidated = position.last_liquidated.max(state.and_then(|state| state.last_liquidated));
+        last_liquidated.is_some_and(|last_liquidated| {
+            (now.saturating_sub(last_liquidated) as u64) < self.config().min_liquidation_interval_secs
+        })
+    }
+    
+    /// Whether a position's cooldown can be cut short: `cooldown_bypass_delta`
+    /// is set and the engine saw the position's last liquidation
+    fn cooldown_bypassable(&self, position: &Position) -> bool {
+        self.config().cooldown_bypass_delta.is_some() && position.last_liquidated_margin_ratio.is_some()
+    }
+    
+    /// Whether a position in cooldown has worsened enough at `price` to be
+    /// liquidated again already: its margin ratio fell by more than
+    /// `cooldown_bypass_delta` since its last liquidation, or it grew since
+    fn bypasses_cooldown(&self, position: &Position, price: Amount) -> bool {
+        let (Some(delta), Some(margin_ratio)) =
+            (self.config().cooldown_bypass_delta, position.last_liquidated_margin_ratio)
+        else {
+            return false;
+        };
+        position.last_liquidated_size.is_some_and(|size| position.size > size)
+            || margin_ratio - position.margin_ratio(price) > amount::from_f64_lossy(delta)
+    }
+    
+    /// Get aggregated risk for accounts whose blended margin ratio is below `threshold`
+    ///
+    /// Accounts are ordered worst first. Partially priced accounts are included based
+    /// on the positions that could be priced.
+    pub async fn accounts_at_risk(&self, threshold: f64) -> Vec<AccountRisk> {
+        let mut positions: Vec<Position> = self.positions.read().await.values().cloned().collect();
+        let prices = self.latest_prices(&positions).await;
+        self.revalue_collateral(&mut positions, &prices).await;
         
-        // In a real implementation, we would:
-        // 1. Create a transaction to liquidate the position
-        // 2. Sign and send the transaction
-        // 3. Update the position's state
+        risk::aggregate_account_risk(&positions, &prices)
+            .into_iter()
+            .filter(|account| account.margin_ratio < threshold)
+            .collect()
+    }
+    
+    /// Fetch the latest price data for every symbol traded or held as collateral
+    /// in one batch, returning the symbols the oracle can't price and those
+    /// whose prices were rejected as outliers with their errors
+    async fn latest_price_data(
+        &self,
+        positions: &[Position],
+    ) -> (HashMap<String, PriceData>, HashMap<String, LiquidationError>) {
+        let mut symbols: Vec<&str> = positions
+            .iter()
+            .flat_map(|position| std::iter::once(position.symbol.as_str()).chain(position.collateral_symbols()))
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+        let (mut prices, mut failures) = self.oracle.get_prices_partial(&symbols).await;
+        // Prices behind the current slot are stale whatever the host's clock says
+        prices.retain(|symbol, price_data| {
+            match self.fresh_slot(symbol, price_data).and_then(|_| self.sane_price(symbol, price_data.price)) {
+                Ok(_) => true,
+                Err(e) => {
+                    failures.insert(symbol.clone(), e);
+                    false
+                }
+            }
+        });
+        for (symbol, e) in &failures {
+            warn!("Failed to fetch price for {}: {}", symbol, e);
+        }
+        (prices, failures)
+    }
+    
+    /// Fetch the latest price for every symbol traded or held as collateral, skipping
+    /// symbols the oracle can't price and prices rejected as outliers
+    async fn latest_prices(&self, positions: &[Position]) -> HashMap<String, Amount> {
+        let mut prices = HashMap::new();
+        // Collected up front: holding the iterator's closures across the awaits
+        // below would keep the future from being `Send`
+        let symbols: Vec<&str> = positions
+            .iter()
+            .flat_map(|position| std::iter::once(position.symbol.as_str()).chain(position.collateral_symbols()))
+            .collect();
+        for symbol in symbols {
+            if prices.contains_key(symbol) {
+                continue;
+            }
+            let price = self.oracle.get_price(symbol).await;
+            match price.and_then(|price| self.sane_price(symbol, price)) {
+                Ok(price) => {
+                    prices.insert(symbol.to_string(), price);
+                }
+                Err(e) => warn!("Failed to fetch price for {}: {}", symbol, e),
+            }
+        }
+        prices
+    }
+    
+    /// Record a freshly read price of a symbol, failing with `PriceOutlier` if it
+    /// jumped further from recent prices than the symbol may move at once
+    fn sane_price(&self, symbol: &str, price: Amount) -> StdResult<Amount, LiquidationError> {
+        let checked = self
+            .price_sanity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .check(&self.config().price_sanity, symbol, price.as_f64(), self.now())
+            .map(|_| price);
+        if let Err(e @ LiquidationError::PriceOutlier { .. }) = &checked {
+            self.alert(
+                AlertTrigger::OracleDivergence,
+                AlertLevel::Warning,
+                format!("Oracle price of {} rejected", symbol),
+                e.to_string(),
+            );
+        }
+        checked
+    }
+    
+    /// Mark price of a symbol at its oracle index price, folding the basis
+    /// source's latest reading into the symbol's average basis
+    ///
+    /// A basis that can't be read leaves the average as it was.
+    async fn mark_price(&self, symbol: &str, index_price: Amount) -> Amount {
+        let basis = self.basis_source.basis(symbol).await;
+        let config = self.config();
+        let mut mark_prices = self.mark_prices.lock().unwrap_or_else(PoisonError::into_inner);
+        match basis {
+            Ok(basis) => {
+                mark_prices.observe(&config.mark_price, symbol, basis, self.now());
+            }
+            Err(e) => warn!("Failed to read the basis of {}: {}", symbol, e),
+        }
+        mark_prices.mark_price(&config.mark_price, symbol, index_price)
+    }
+    
+    /// `prices` with the symbols positions trade moved to their mark prices,
+    /// leaving collateral assets at their index prices
+    async fn mark_prices(
+        &self,
+        positions: &[Position],
+        mut prices: HashMap<String, Amount>,
+    ) -> HashMap<String, Amount> {
+        let symbols: HashSet<&str> = positions.iter().map(|position| position.symbol.as_str()).collect();
+        for symbol in symbols {
+            if let Some(&index_price) = prices.get(symbol) {
+                let mark_price = self.mark_price(symbol, index_price).await;
+                prices.insert(symbol.to_string(), mark_price);
+            }
+        }
+        prices
+    }
+    
+    /// Value the collateral assets of positions at `prices` and their configured
+    /// weights, keeping the cached positions in step
+    ///
+    /// Positions with an unpriced asset keep their last collateral value.
+    async fn revalue_collateral(&self, positions: &mut [Position], prices: &HashMap<String, Amount>) {
+        let weights = &self.config().collateral_weights;
+        let mut cached = self.positions.write().await;
+        for position in positions.iter_mut().filter(|position| !position.collateral.is_empty()) {
+            position.revalue_collateral(prices, weights);
+            if let Some(cached) = cached.get_mut(&position.address) {
+                cached.collateral_value = position.collateral_value;
+            }
+        }
+    }
+    
+    /// Stop the engine, and the servers watching its shutdown token
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+    
+    /// Token cancelled once the engine is shut down
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+    
+    /// Subscribe to position updates and liquidation events
+    ///
+    /// Subscribers that fall more than `EVENT_CHANNEL_CAPACITY` events behind miss the
+    /// oldest ones; publishing never waits for subscribers.
+    pub fn subscribe(&self) -> broadcast::Receiver<EngineEvent> {
+        self.events.subscribe()
+    }
+    
+    /// Push updates for positions whose status or margin ratio changed noticeably
+    async fn publish_position_updates(&self, positions: &[Position], prices: &HashMap<String, Amount>, now: i64) {
+        let config = self.config();
+        let funding_rates = self.funding_rates().await;
+        let adl = self.adl.read().await;
+        let mut last_updates = self.last_updates.write().await;
+        let monitored: HashSet<PositionAddress> = positions.iter().map(|position| position.address).collect();
+        last_updates.retain(|address, _| monitored.contains(address));
         
-        Ok(())
+        for position in positions {
+            let Some(&price) = prices.get(&position.symbol) else {
+                continue;
+            };
+            let pending = self.confirmations.is_pending(&position.address);
+            let margin_params = config.margin_params_at(position, price);
+            let mut update = position.update(price, self.status_at(position, price, pending), margin_params, now);
+            update.adl_quantile = adl.quantile(position);
+            update.exceeds_max_leverage = config.exceeds_max_leverage(position);
+            update.liquidation_price = config.displayed_liquidation_price(position, update.liquidation_price);
+            update.estimated_time_to_liquidation_secs = self.time_to_liquidation(&update);
+            Self::project_funding(&config, position, funding_rates.get(&position.symbol).copied(), &mut update);
+            
+            let last = last_updates.get(&position.address);
+            let status_changed = last.is_none_or(|last| last.status != update.status);
+            let changed = status_changed
+                || last.is_some_and(|last| {
+                    (last.margin_ratio - update.margin_ratio).abs() >= config.position_update_min_delta
+                });
+            if changed {
+                if status_changed && update.status == PositionStatus::AtRisk {
+                    self.notify_webhook(WebhookPayload::AtRisk(update.clone()));
+                }
+                last_updates.insert(position.address, update.clone());
+                // Sending only fails when nobody is subscribed
+                let _ = self.events.send(EngineEvent::PositionUpdate(update));
+            }
+        }
     }
     
-    /// Add a position to be monitored
-    pub async fn add_position(&self, position: Position) {
+    /// Advance funding indices and record unsettled funding on every position
+    async fn accrue_funding(&self, now: i64) {
+        let Some(funding_source) = &self.funding_source else {
+            return;
+        };
+        
+        let mut symbols: Vec<String> = self
+            .positions
+            .read()
+            .await
+            .values()
+            .map(|position| position.symbol.clone())
+            .collect();
+        symbols.sort();
+        symbols.dedup();
+        
+        let mut indices = self.funding_indices.write().await;
+        for symbol in symbols {
+            match funding_source.funding_rate_per_hour(&symbol).await {
+                Ok(rate) => indices
+                    .entry(symbol)
+                    .or_insert_with(|| FundingIndex::new(now))
+                    .accrue(rate, now),
+                Err(e) => warn!("Failed to fetch funding rate for {}: {}", symbol, e),
+            }
+        }
+        
         let mut positions = self.positions.write().await;
-        positions.insert(position.address, position);
+        for position in positions.values_mut() {
+            let Some(index) = indices.get(&position.symbol) else {
+                continue;
+            };
+            
+            // Positions start accruing from the index value when first seen
+            if position.last_funding_settlement.is_none() {
+                position.cumulative_funding_at_entry = amount::from_f64_lossy(index.cumulative);
+                position.last_funding_settlement = Some(now);
+            }
+            position.accrue_funding(index.cumulative);
+        }
     }
     
-    /// Remove a position from monitoring
-    pub async fn remove_position(&self, address: &Pubkey) {
-        let mut positions = self.positions.write().await;
-        positions.remove(address);
+    /// Check a single position for liquidation
+    ///
+    /// Returns `None` when the position is healthy.
+    async fn check_position(&self, position: Position) -> StdResult<Option<LiquidationResult>, LiquidationError> {
+        self.check_scored_position(position, None, None, None).await
     }
     
-    /// Get a reference to the engine's configuration
-    pub fn config(&self) -> &LiquidationConfig {
-        &self.config
+    /// Check a position for liquidation, recording the score it was
+    /// prioritized with in the result
+    ///
+    /// Positions are evaluated at the cycle's `snapshot` if it priced their
+    /// symbol, and only liquidated if a fresh price still finds them
+    /// liquidatable; otherwise at a price fetched for the check. A liquidation
+    /// that may share a transaction is added to `batched` instead of being
+    /// submitted, and the check returns `None`.
+    #[instrument(
+        name = "check_position",
+        skip_all,
+        fields(
+            position = %position.address,
+            symbol = %position.symbol,
+            market = tracing::field::Empty,
+            priority_score = ?priority_score,
+            cycle_id = tracing::field::Empty,
+            correlation_id = tracing::field::Empty
+        )
+    )]
+    async fn check_scored_position<'a>(
+        &'a self,
+        position: Position,
+        priority_score: Option<f64>,
+        snapshot: Option<&PriceSnapshot>,
+        batched: Option<&mut Vec<PreparedLiquidation<'a>>>,
+    ) -> StdResult<Option<LiquidationResult>, LiquidationError> {
+        let prepared = match self.prepare_liquidation(position, priority_score, snapshot).await? {
+            Checked::Done(result) => return Ok(result),
+            Checked::Ready(prepared) => *prepared,
+        };
+        if let Some(batched) = batched
+            && self.batchable(&prepared).await
+        {
+            batched.push(prepared);
+            return Ok(None);
+        }
+        let submission = self.submit_prepared(&prepared).await;
+        Ok(Some(self.finish_liquidation(prepared, submission).await))
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use solana_sdk::signature::Keypair;
-    use std::str::FromStr;
     
-    #[test]
-    fn test_engine_initialization() {
-        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
-        let oracle = Arc::new(PythOracle::new(
-            "https://api.devnet.solana.com",
-            HashMap::new(),
-            None,
-        ));
+    /// Evaluate a position up to its liquidation, returning the liquidation
+    /// ready to submit unless the check ends before
+    async fn prepare_liquidation(
+        &self,
+        mut position: Position,
+        priority_score: Option<f64>,
+        snapshot: Option<&PriceSnapshot>,
+    ) -> StdResult<Checked<'_>, LiquidationError> {
+        if self.mode() == EngineMode::Paused {
+            return Ok(Checked::Done(None));
+        }
+        // Positions outside markets belong to the default program
+        let config = self.config();
+        let market = config.market(&position.symbol);
+        Span::current().record("market", market.map_or(PROGRAM_ID, |market| market.program_id).to_string());
+        if !config.market_enabled(&position.symbol) {
+            return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::MarketNotTrading))));
+        }
+        drop(config);
         
-        let config = LiquidationConfig::default();
-        let engine = LiquidationEngine::new(rpc_client, oracle, config);
+        // Held until the liquidation settles or the check bails out
+        let Some(_in_flight) = self.in_flight.acquire(position.address) else {
+            return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::InFlight))));
+        };
+        // A check that settled since the caller's copy was taken may have started a cooldown
+        if let Some(cached) = self.positions.read().await.get(&position.address) {
+            position.last_liquidated = position.last_liquidated.max(cached.last_liquidated);
+        }
+        let now = self.now();
+        let state = self.position_state(&position.address).await;
         
-        assert_eq!(engine.positions.blocking_read().len(), 0);
+        // Skip if position was recently liquidated, including before a restart,
+        // unless it may have worsened enough since to be judged at its price
+        let cooling_down = self.in_cooldown(&position, state.as_ref(), now);
+        if cooling_down && !self.cooldown_bypassable(&position) {
+            return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::Cooldown))));
+        }
+        
+        // Never resubmit while an earlier liquidation may still land
+        if let Some(pending) = self.confirmations.pending(&position.address)
+            && !self.resolve_pending(&position, &pending, now).await
+        {
+            let skipped = self.skip(position.address, SkipReason::AwaitingConfirmation(pending.signature.to_string()));
+            return Ok(Checked::Done(Some(skipped)));
+        }
+        
+        // Health is evaluated at the mark price, the index shifted by the market's basis
+        let priced = snapshot.and_then(|snapshot| Some((snapshot.get(&position.symbol)?, snapshot.cycle_id.clone())));
+        let (price_data, cycle_id) = match priced {
+            Some((price_data, cycle_id)) => {
+                Span::current().record("cycle_id", cycle_id.as_str());
+                info!(
+                    "Evaluating position {} at mark price {} from cycle {}",
+                    position.address, price_data.price, cycle_id
+                );
+                (price_data, Some(cycle_id))
+            }
+            None => (self.fresh_price_data(&position).await?, None),
+        };
+        
+        if cooling_down {
+            if !self.bypasses_cooldown(&position, price_data.price) {
+                return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::Cooldown))));
+            }
+            info!(
+                "Position {} worsened at {} since its last liquidation, cutting its cooldown short",
+                position.address, price_data.price
+            );
+        }
+        
+        // Check if the position is undercollateralized, cross positions together
+        // with their owner's other cross positions
+        let pool = self.pooled_margin(&position, price_data.price).await?;
+        if !self.should_liquidate(&position, &price_data, pool.as_ref()) {
+            return Ok(Checked::Done(None));
+        }
+        
+        // Suspect data isn't acted on until operators have looked at it
+        if self.is_suspect(&position.address) {
+            return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::Suspect))));
+        }
+        
+        // Monitoring carries on while the circuit breaker holds liquidation back
+        if let Some(tripped_at) = self.paused_since() {
+            return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::CircuitBreaker { tripped_at }))));
+        }
+        // A slot that stopped advancing means the node, or the cluster, stalled
+        if let Some(advanced_at) = self.slot_stalled_since(now) {
+            return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::SlotStalled { advanced_at }))));
+        }
+        
+        // The monitored copy may predate a deposit that landed since, so the
+        // position is judged again as the chain holds it before it's liquidated
+        let Some(fresh) = self.refetch_position(&position).await? else {
+            return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::PositionClosed))));
+        };
+        if (fresh.size, fresh.entry_price) != (position.size, position.entry_price) {
+            let cached = position.clone();
+            position.size = fresh.size;
+            position.entry_price = fresh.entry_price;
+            let pool = self.pooled_margin(&position, price_data.price).await?;
+            if !self.should_liquidate(&position, &price_data, pool.as_ref()) {
+                self.record_divergence(&cached, &position, price_data.price, now);
+                self.republish_position(&position.address).await;
+                return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::CollateralUpdated))));
+            }
+        }
+        
+        // Bankrupt positions are closed in full and their shortfall hits the insurance fund
+        let bad_debt = position.bad_debt(price_data.price);
+        let liquidation_fraction = self.config().liquidation_fraction(&position.symbol, bad_debt.as_f64());
+        if bad_debt > Amount::ZERO {
+            let message = format!(
+                "Position {} is beyond its bankruptcy price at {}: bad debt {:.2}",
+                position.address, price_data.price, bad_debt
+            );
+            warn!("{}", message);
+            let title = format!("Bad debt in {}", position.symbol);
+            self.alert(AlertTrigger::BadDebt, AlertLevel::Critical, title, message);
+            
+            if let Some(limit) = self.config().max_window_bad_debt {
+                let window_bad_debt = self
+                    .insurance
+                    .write()
+                    .await
+                    .window_total(now, self.config().bad_debt_window_secs);
+                if window_bad_debt + bad_debt.as_f64() > limit {
+                    error!(
+                        "Refusing to liquidate {}: bad debt limit of {:.2} reached, manual intervention required",
+                        position.address, limit
+                    );
+                    return Ok(Checked::Done(Some(self.skip(
+                        position.address,
+                        SkipReason::BadDebtLimit {
+                            window_bad_debt,
+                            bad_debt: bad_debt.as_f64(),
+                            limit,
+                        },
+                    ))));
+                }
+            }
+        }
+        
+        // Keep each liquidation's market impact within the slippage bound, leaving the
+        // rest of the position to later cycles
+        let max_fraction = liquidation_fraction;
+        let (liquidation_fraction, estimated_impact_bps) = self.size_within_slippage(&position, max_fraction).await;
+        if liquidation_fraction <= 0.0 {
+            let max_slippage_bps = self.config().max_slippage_bps;
+            return Ok(Checked::Done(Some(self.skip(position.address, SkipReason::NoDepth { max_slippage_bps }))));
+        }
+        // Exchanges only take whole steps of a symbol, of at least its minimum
+        // notional, so the amount rounds down rather than past the target
+        let target = position.size.as_f64() * liquidation_fraction;
+        let spec = self.config().symbol_spec(&position.symbol);
+        let Some(amount) = spec.liquidation_amount(target, price_data.price.as_f64()).map(amount::from_f64_lossy) else {
+            let symbol = position.symbol.clone();
+            let skipped = self.skip(position.address, SkipReason::BelowMinSize { amount: target, symbol });
+            return Ok(Checked::Done(Some(skipped)));
+        };
+        let liquidation_fraction = amount.as_f64() / position.size.as_f64();
+        // Bankrupt positions are closed in chunks too, realizing their bad debt pro rata
+        let bad_debt = amount::from_f64_lossy(bad_debt.as_f64() * liquidation_fraction / max_fraction);
+        
+        // Repayments the program would reject on every attempt, by its own math,
+        // aren't submitted; dry runs check them against a preloaded market only
+        let market = if self.dry_run() {
+            self.known_liquidation_market(&position.symbol)
+        } else {
+            self.liquidation_market(&position.symbol).await.ok()
+        };
+        let repay_amount = match self.repay_amount(market, &position, price_data.price.as_f64(), liquidation_fraction) {
+            Ok(repay_amount) => repay_amount,
+            Err(reason) => return Ok(Checked::Done(Some(self.skip(position.address, reason)))),
+        };
+        
+        // Skip liquidations that would cost more than they pay, at the fee the first attempt pays
+        let first_fee = self.priority_fee(&position, 1).await;
+        let model = ProfitModel::from_config(&self.config(), first_fee);
+        if let Some(estimate) = self.estimate_profit(&model, &position, price_data.price, liquidation_fraction).await {
+            if !estimate.is_profitable(self.config().min_profit_quote) {
+                let skipped = self.skip(position.address, SkipReason::Unprofitable(estimate.to_string()));
+                return Ok(Checked::Done(Some(skipped)));
+            }
+            info!("Liquidation of {} expected to be profitable: {}", position.address, estimate);
+        }
+        
+        // Prices may have moved since the cycle's snapshot, so the liquidation
+        // only goes ahead if a fresh price still finds the position liquidatable
+        if let Some(cycle_id) = &cycle_id {
+            let fresh = self.fresh_price_data(&position).await?;
+            let pool = self.pooled_margin(&position, fresh.price).await?;
+            if !self.should_liquidate(&position, &fresh, pool.as_ref()) {
+                return Ok(Checked::Done(Some(self.skip(
+                    position.address,
+                    SkipReason::PositionRecovered {
+                        cycle_id: cycle_id.clone(),
+                        from: price_data.price.as_f64(),
+                        to: fresh.price.as_f64(),
+                    },
+                ))));
+            }
+        }
+        
+        // Positions are evaluated as usual while operators hold submissions back,
+        // or the engine warms up
+        let holding = if self.is_warming_up() {
+            Some(("Warming up", SkipReason::WarmingUp))
+        } else {
+            (self.mode() == EngineMode::MonitorOnly).then_some(("Monitor-only", SkipReason::MonitorOnly))
+        };
+        if let Some((label, holding)) = holding {
+            info!(
+                "{}: would liquidate {} of position {} at {}",
+                label, amount, position.address, price_data.price
+            );
+            let reward = model.expected_liquidation_reward(&position, price_data.price, liquidation_fraction);
+            self.report_liquidation(&position, price_data.price, amount, reward, bad_debt, now);
+            return Ok(Checked::Done(Some(self.skip(position.address, holding))));
+        }
+        
+        // Under a margin call, positions are flagged and given the grace period to
+        // recover before they're liquidated, unless they fell below the instant threshold
+        if let Some(grace_ends_at) = self.margin_call_grace(&position, price_data.price, now).await? {
+            let skipped = self.skip(position.address, SkipReason::GracePeriodActive { grace_ends_at });
+            return Ok(Checked::Done(Some(skipped)));
+        }
+        
+        // Tag everything logged about this attempt so it can be traced end to end
+        let correlation_id = Uuid::new_v4().to_string();
+        Span::current().record("correlation_id", correlation_id.as_str());
+        
+        info!("Liquidating position: {:?} at price: {}", position, price_data.price);
+        if !self.dry_run() {
+            let config = self.config();
+            let mut update = position.update(
+                price_data.price,
+                PositionStatus::Liquidating,
+                config.margin_params_at(&position, price_data.price),
+                now,
+            );
+            update.exceeds_max_leverage = config.exceeds_max_leverage(&position);
+            update.liquidation_price = config.displayed_liquidation_price(&position, update.liquidation_price);
+            update.estimated_time_to_liquidation_secs = self.time_to_liquidation(&update);
+            self.notify_webhook(WebhookPayload::Liquidating(update));
+        }
+        
+        Ok(Checked::Ready(Box::new(PreparedLiquidation {
+            _in_flight,
+            position,
+            price_data,
+            priority_score,
+            cycle_id,
+            amount,
+            liquidation_fraction,
+            repay_amount,
+            estimated_impact_bps,
+            bad_debt,
+            model,
+            first_fee,
+            correlation_id,
+            now,
+        })))
+    }
+    
+    /// Whether a prepared liquidation may share a transaction with others: a
+    /// live one needing at most `batch_candidate_max_compute` units, while
+    /// instruction batching is enabled
+    async fn batchable(&self, prepared: &PreparedLiquidation<'_>) -> bool {
+        let config = self.config();
+        if !config.enable_instruction_batching || self.dry_run() {
+            return false;
+        }
+        self.compute_unit_limit(&prepared.position, prepared.repay_amount, prepared.first_fee)
+            .await
+            .is_ok_and(|limit| limit <= config.batch_candidate_max_compute)
+    }
+    
+    /// Submit a prepared liquidation in a transaction of its own, retrying
+    /// failed submissions
+    async fn submit_prepared(&self, prepared: &PreparedLiquidation<'_>) -> Submission {
+        let PreparedLiquidation {
+            position,
+            repay_amount,
+            correlation_id,
+            ..
+        } = prepared;
+        let repay_amount = *repay_amount;
+        // Retry failed submissions, re-pricing the fee so escalating strategies can outbid
+        let max_attempts = self.config().max_retries.saturating_add(1);
+        let mut attempt = 1;
+        let mut priority_fee = prepared.first_fee;
+        let mut compute_unit_limit = 0;
+        let outcome = loop {
+            let outcome = match self.compute_unit_limit(position, repay_amount, priority_fee).await {
+                Ok(limit) => {
+                    compute_unit_limit = limit;
+                    let outcome = self
+                        .liquidate_position(
+                            position,
+                            repay_amount,
+                            priority_fee,
+                            limit,
+                            correlation_id,
+                            attempt,
+                        )
+                        .await;
+                    self.record_compute_outcome(&position.symbol, outcome.is_ok());
+                    outcome
+                }
+                Err(e) => Err(e),
+            };
+            match outcome {
+                // Retrying can't fix refusals like not being on the program's whitelist,
+                // and a transaction left pending at the timeout may still land
+                Err(e) if attempt < max_attempts && e.is_retryable() && !self.confirmations.is_pending(&position.address) => {
+                    warn!(error_kind = %e.kind(), "Liquidation attempt {} of {} failed: {}", attempt, max_attempts, e);
+                }
+                outcome => break outcome,
+            }
+            
+            tokio::time::sleep(Duration::from_millis(self.config().retry_delay_ms)).await;
+            attempt += 1;
+            priority_fee = self.priority_fee(position, attempt).await;
+        };
+        Submission {
+            outcome,
+            attempts: attempt,
+            priority_fee,
+            compute_unit_limit,
+        }
+    }
+    
+    /// Record how a prepared liquidation's submission ended, publishing its
+    /// event, and return its result
+    async fn finish_liquidation(&self, prepared: PreparedLiquidation<'_>, submission: Submission) -> LiquidationResult {
+        let PreparedLiquidation {
+            _in_flight,
+            position,
+            price_data,
+            priority_score,
+            cycle_id,
+            amount,
+            liquidation_fraction,
+            estimated_impact_bps,
+            bad_debt,
+            model,
+            correlation_id,
+            now,
+            ..
+        } = prepared;
+        let Submission {
+            outcome,
+            attempts: attempt,
+            priority_fee,
+            compute_unit_limit,
+        } = submission;
+        // Credited to the key that signed, or the pool's first when none did
+        let (outcome, liquidator) = match outcome {
+            Ok((signature, liquidator)) => (Ok(signature), liquidator),
+            Err(e) => (Err(e), self.liquidator()),
+        };
+        let event = LiquidationEvent {
+            position: position.address,
+            owner: position.owner,
+            liquidator,
+            amount,
+            remaining_size: position.size - amount,
+            remaining_margin: (position.effective_margin() * amount::from_f64_lossy(1.0 - liquidation_fraction))
+                .max(Amount::ZERO),
+            liquidation_price: price_data.price,
+            timestamp: now,
+            signature: outcome.as_ref().cloned().unwrap_or_default(),
+            bad_debt,
+            symbol: position.symbol.clone(),
+            reward: amount::from_f64_lossy(model.expected_liquidation_reward(
+                &position,
+                price_data.price,
+                liquidation_fraction,
+            )),
+            dry_run: self.dry_run(),
+            error: outcome.as_ref().err().map(|e| e.to_string()),
+            priority_fee_micro_lamports: priority_fee,
+            metadata: position.metadata.clone(),
+        };
+        self.record_liquidation(&event).await;
+        if let Some(audit) = &self.audit {
+            let config = self.config();
+            audit.record(AuditRecord {
+                timestamp: now,
+                position: position.address,
+                owner: position.owner,
+                symbol: position.symbol.clone(),
+                cycle_id: cycle_id.clone(),
+                correlation_id: correlation_id.clone(),
+                config_hash: config.config_hash(),
+                oracle_price: price_data.price.as_f64(),
+                oracle_confidence: price_data.confidence,
+                oracle_ema_price: price_data.ema_price,
+                oracle_publish_time: price_data.publish_time,
+                is_long: position.is_long,
+                size: position.size.as_f64(),
+                entry_price: position.entry_price.as_f64(),
+                margin: position.effective_margin().as_f64(),
+                maintenance_margin: config.margin_params_at(&position, price_data.price).for_side(position.is_long),
+                margin_ratio: position.margin_ratio(price_data.price).as_f64(),
+                amount: amount.as_f64(),
+                bad_debt: bad_debt.as_f64(),
+                dry_run: event.dry_run,
+                attempts: attempt,
+                signature: outcome.as_ref().ok().cloned(),
+                error: event.error.clone(),
+            });
+        }
+        if event.error.is_none() {
+            self.track_reward(&event, compute_unit_limit).await;
+        }
+        if event.dry_run && event.error.is_none() {
+            self.report_liquidation(&position, price_data.price, amount, event.reward.as_f64(), bad_debt, now);
+        }
+        
+        if outcome.is_ok() && position.margin_mode == MarginMode::Cross {
+            self.recheck_siblings(&position).await;
+        }
+        // The program clears the flag of a position the liquidation brought back
+        // above the warning threshold, so it's flagged afresh if it falls again
+        if outcome.is_ok() {
+            let mut remaining = position.clone();
+            remaining.reduce(amount::from_f64_lossy(liquidation_fraction));
+            let config = self.config();
+            let health = remaining.health_factor(price_data.price, config.margin_params_at(&remaining, price_data.price));
+            if health >= config.at_risk_health_factor {
+                self.margin_calls.lock().unwrap_or_else(PoisonError::into_inner).clear(&position.address);
+            }
+        }
+        
+        self.count_failure(outcome.is_err(), &position);
+        match outcome {
+            Ok(signature) => LiquidationResult::Success {
+                position: position.address,
+                amount: amount.as_f64(),
+                estimated_impact_bps,
+                signature,
+                compute_unit_limit,
+                correlation_id,
+                priority_score,
+                price: price_data.price.as_f64(),
+                cycle_id,
+            },
+            Err(e) => {
+                error!(
+                    error_kind = %e.kind(),
+                    "Liquidation of {} failed after {} attempts: {}", position.address, attempt, e
+                );
+                // A position refused as healthy has often just been liquidated by a competitor
+                let sub_reason = match e {
+                    LiquidationError::PositionNotLiquidatable(_) => self.trace_front_run(&position, now).await,
+                    _ => None,
+                };
+                LiquidationResult::Failure {
+                    position: position.address,
+                    error: e.to_string(),
+                    sub_reason,
+                    attempts: attempt,
+                    correlation_id,
+                    priority_score,
+                    price: price_data.price.as_f64(),
+                    cycle_id,
+                }
+            }
+        }
+    }
+    
+    /// End of the grace period a liquidatable position waits out under the margin
+    /// call, flagging it on chain first if it isn't yet, or `None` once it may be
+    /// liquidated
+    ///
+    /// Positions below `instant_liquidation_health_factor` are liquidated without
+    /// a grace period, as are all positions while `margin_call_grace_secs` is 0.
+    async fn margin_call_grace(
+        &self,
+        position: &Position,
+        price: Amount,
+        now: i64,
+    ) -> StdResult<Option<i64>, LiquidationError> {
+        let (grace_secs, instant) = {
+            let config = self.config();
+            let health = position.health_factor(price, config.margin_params_at(position, price));
+            (config.margin_call_grace_secs, health < config.instant_liquidation_health_factor)
+        };
+        if grace_secs == 0 || instant {
+            return Ok(None);
+        }
+        let stage = self.margin_calls.lock().unwrap_or_else(PoisonError::into_inner).stage(&position.address, grace_secs, now);
+        match stage {
+            MarginCallStage::Expired => Ok(None),
+            MarginCallStage::Grace(grace_ends_at) => Ok(Some(grace_ends_at)),
+            MarginCallStage::Unflagged => {
+                let signature = self.flag_position(position).await?;
+                info!(
+                    "Flagged position {} for liquidation at price {}, grace period of {}s: {}",
+                    position.address, price, grace_secs, signature
+                );
+                self.margin_calls.lock().unwrap_or_else(PoisonError::into_inner).flag(position.address, now);
+                Ok(Some(now.saturating_add(i64::try_from(grace_secs).unwrap_or(i64::MAX))))
+            }
+        }
+    }
+    
+    /// Flag a position for liquidation on chain, starting its grace period, and
+    /// return the transaction signature
+    async fn flag_position(&self, position: &Position) -> StdResult<String, LiquidationError> {
+        if self.dry_run() {
+            return Ok("dry-run".to_string());
+        }
+        
+        self.ensure_submitting()?;
+        let payer = self.signer()?;
+        let oracle = self.config().oracle_feeds().get(&position.symbol).copied().ok_or_else(|| {
+            LiquidationError::ConfigError(format!("no price feed for {} to flag positions against", position.symbol))
+        })?;
+        let mut instructions: Vec<Instruction> = self.nonce.iter().map(NonceAccount::advance_instruction).collect();
+        let (_, market_id) = self.program_market(&position.symbol);
+        instructions.push(instruction::flag_for_liquidation_instruction(
+            position.address.as_ref(),
+            market_id,
+            oracle.as_ref(),
+            &payer.pubkey(),
+        ));
+        let blockhash = self.transaction_blockhash().await?;
+        let outcome = self.submitter.submit(&instructions, payer, blockhash).await;
+        if let Some(nonce) = &self.nonce
+            && outcome.as_ref().map_or_else(nonce::is_nonce_mismatch, |_| true)
+        {
+            nonce.refresh().await;
+        }
+        Ok(outcome?.to_string())
+    }
+    
+    /// Price data of a position's symbol fetched from the oracle and moved to
+    /// its mark price, failing if the price is an outlier the cycle hasn't
+    /// accepted
+    async fn fresh_price_data(&self, position: &Position) -> StdResult<PriceData, LiquidationError> {
+        let price_data = self.oracle.get_price_data(&position.symbol).await?;
+        self.fresh_slot(&position.symbol, &price_data)?;
+        self.price_sanity.lock().unwrap_or_else(PoisonError::into_inner).verify(
+            &self.config().price_sanity,
+            &position.symbol,
+            price_data.price.as_f64(),
+        )?;
+        let mark_price = self.mark_price(&position.symbol, price_data.price).await;
+        info!(
+            "Evaluating position {} at mark price {} (index {})",
+            position.address, mark_price, price_data.price
+        );
+        Ok(price_data.at_mark(mark_price))
+    }
+    
+    /// Cap the fraction of a position to liquidate at `max_fraction` so the expected
+    /// impact on its market stays within `max_slippage_bps`, returning the fraction
+    /// and its expected impact
+    ///
+    /// Liquidations aren't capped in markets of unknown depth, when the depth can't
+    /// be fetched, or by less than `min_position_size`.
+    async fn size_within_slippage(&self, position: &Position, max_fraction: f64) -> (f64, Option<f64>) {
+        let depth = match &self.depth_provider {
+            Some(provider) => provider.get_depth(&position.symbol).await,
+            None => Ok(self
+                .config()
+                .market_liquidity
+                .get(&position.symbol)
+                .map(|&liquidity| MarketDepth::Constant { liquidity })),
+        };
+        let depth = match depth {
+            Ok(Some(depth)) => depth,
+            Ok(None) => return (max_fraction, None),
+            Err(e) => {
+                warn!("Failed to get market depth of {}, not capping liquidation size: {}", position.symbol, e);
+                return (max_fraction, None);
+            }
+        };
+        // Closing a long sells into the bids, closing a short buys from the asks
+        let sell = position.is_long;
+        let size = position.size.as_f64();
+        let max_size = depth.max_size_within(self.config().max_slippage_bps as f64, sell);
+        let mut fraction = if size > 0.0 {
+            (max_size / size).min(max_fraction)
+        } else {
+            max_fraction
+        };
+        // Not worth leaving dust behind for another cycle
+        if (max_fraction - fraction) * size < self.config().min_position_size {
+            fraction = max_fraction;
+        }
+        if fraction < max_fraction {
+            info!(
+                "Liquidating {:.4} of {} in {} to stay within {} bps of slippage",
+                size * fraction, position.size, position.address, self.config().max_slippage_bps
+            );
+        }
+        (fraction, depth.impact_bps(size * fraction, sell))
+    }
+    
+    /// Report a liquidation that was only simulated to the report writer, if any
+    fn report_liquidation(
+        &self,
+        position: &Position,
+        price: Amount,
+        amount: Amount,
+        reward: f64,
+        bad_debt: Amount,
+        now: i64,
+    ) {
+        if let Some(report) = &self.report {
+            report.record(DryRunLiquidation {
+                timestamp: now,
+                position: position.address,
+                owner: position.owner,
+                symbol: position.symbol.clone(),
+                price: price.as_f64(),
+                margin_ratio: position.margin_ratio(price).as_f64(),
+                amount: amount.as_f64(),
+                reward,
+                bad_debt: bad_debt.as_f64(),
+            });
+        }
+    }
+    
+    /// Record the outcome of a liquidation attempt, unless the same liquidation
+    /// was already recorded
+    async fn record_liquidation(&self, event: &LiquidationEvent) {
+        if !self.admit_event(event).await {
+            return;
+        }
+        let _ = self.events.send(EngineEvent::Liquidation(event.clone()));
+        
+        if event.error.is_some() {
+            self.store_event(event);
+            return;
+        }
+        
+        let config = self.config();
+        let tripped = self.circuit_breaker.lock().unwrap_or_else(PoisonError::into_inner).record(
+            event.timestamp,
+            config.circuit_breaker_liquidation_count,
+            config.circuit_breaker_window_secs,
+        );
+        if tripped {
+            let message = format!(
+                "More than {} liquidations within {} seconds; pausing liquidation until resumed",
+                config.circuit_breaker_liquidation_count.unwrap_or_default(),
+                config.circuit_breaker_window_secs
+            );
+            error!("Circuit breaker tripped: {}", message);
+            self.alert(AlertTrigger::CircuitBreaker, AlertLevel::Critical, "Circuit breaker tripped", message);
+        }
+        
+        if event.bad_debt > Amount::ZERO {
+            self.insurance.write().await.record(event.timestamp, event.bad_debt.as_f64());
+        }
+        
+        // Submitted liquidations only succeed once confirmed
+        self.mark_liquidated(&event.position, event.timestamp, Some(event.liquidation_price)).await;
+        if event.remaining_size <= Amount::ZERO {
+            self.finish_position(event.position);
+        }
+        
+        self.store_event(event);
+        if !event.dry_run {
+            self.keys.record_liquidation(&event.liquidator);
+            self.notify_webhook(WebhookPayload::Liquidated(event.clone()));
+        }
+    }
+    
+    /// Whether a liquidation event wasn't published yet, remembering it in the
+    /// state file if so
+    ///
+    /// The submission path and the program log listener both report our
+    /// liquidations, and either may replay them after a restart.
+    async fn admit_event(&self, event: &LiquidationEvent) -> bool {
+        let Some(key) = dedup::event_key(event) else {
+            return true;
+        };
+        let now = self.now();
+        let retention_secs = {
+            let mut dedup = self.dedup.lock().unwrap_or_else(PoisonError::into_inner);
+            if !dedup.admit(&key, now) {
+                debug!("Dropping duplicate liquidation event {} of {}", key, event.position);
+                return false;
+            }
+            dedup.retention_secs()
+        };
+        if let Some(state) = &self.state
+            && let Err(e) = state.lock().await.record_event(&key, now, retention_secs)
+        {
+            error!("Failed to record liquidation event {}: {}", key, e);
+        }
+        true
+    }
+    
+    /// Add a successful liquidation's reward and network fees to the PnL
+    ///
+    /// Submitted liquidations are read from their confirmed transaction in the
+    /// background; dry runs record the expected reward and fees as simulated.
+    async fn track_reward(&self, event: &LiquidationEvent, compute_unit_limit: u32) {
+        let fee_price_symbol = self.config().fee_price_symbol.clone();
+        let sol_price = match self.oracle.get_price(&fee_price_symbol).await {
+            Ok(price) => price.as_f64(),
+            Err(e) => {
+                warn!("Unable to price network fees with {}, recording them as free: {}", fee_price_symbol, e);
+                0.0
+            }
+        };
+        if event.dry_run {
+            let priority_fee_lamports = event.priority_fee_micro_lamports * compute_unit_limit as u64 / 1_000_000;
+            let record = RewardRecord::new(
+                event.timestamp,
+                event.symbol.clone(),
+                event.reward.as_f64(),
+                BASE_FEE_LAMPORTS,
+                priority_fee_lamports,
+                sol_price,
+                true,
+            );
+            self.rewards.lock().unwrap_or_else(PoisonError::into_inner).record(&record);
+            return;
+        }
+        
+        let signature = match Signature::from_str(&event.signature) {
+            Ok(signature) => signature,
+            Err(e) => {
+                warn!("Unable to track the reward of liquidation {}: {}", event.signature, e);
+                return;
+            }
+        };
+        let rpc = self.rpc.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let tracker = self.rewards.clone();
+        let liquidator = event.liquidator;
+        let (timestamp, symbol, price) = (event.timestamp, event.symbol.clone(), event.liquidation_price.as_f64());
+        tokio::spawn(async move {
+            let receipt = rewards::fetch_transaction(&rpc, &rate_limiter, &signature)
+                .await
+                .and_then(|transaction| LiquidationReceipt::from_transaction(&transaction, &liquidator));
+            let receipt = match receipt {
+                Ok(receipt) => receipt,
+                Err(e) => {
+                    warn!("Unable to read the reward of liquidation {}: {}", signature, e);
+                    return;
+                }
+            };
+            let record = RewardRecord::new(
+                timestamp,
+                symbol,
+                receipt.reward(price),
+                receipt.base_fee_lamports,
+                receipt.priority_fee_lamports,
+                sol_price,
+                false,
+            )
+            .with_liquidator(liquidator);
+            info!(
+                "Liquidation {} earned {:.4} net of {:.4} in network fees",
+                signature,
+                record.net_pnl(),
+                record.network_fee
+            );
+            tracker.lock().unwrap_or_else(PoisonError::into_inner).record(&record);
+        });
+    }
+    
+    /// Trace a liquidation of `position` the program refused as healthy to a
+    /// competitor that liquidated it within `front_run_window_secs` before
+    /// `now`, recording the race as lost
+    ///
+    /// Returns the failure's sub-reason if a competitor was found.
+    async fn trace_front_run(&self, position: &Position, now: i64) -> Option<String> {
+        let config = self.config();
+        if config.front_run_window_secs == 0 || self.dry_run() {
+            return None;
+        }
+        let program_id = config.market(&position.symbol).map_or(PROGRAM_ID, |market| market.program_id);
+        let since = now.saturating_sub(config.front_run_window_secs as i64);
+        let competitor = race::find_front_runner(
+            &self.rpc,
+            &self.rate_limiter,
+            &program_id,
+            position.address.as_ref(),
+            &self.liquidator(),
+            since,
+        )
+        .await;
+        match competitor {
+            // Another of the pool's keys got there first
+            Ok(Some(competitor)) if self.keys.contains(&competitor) => None,
+            Ok(Some(competitor)) => {
+                warn!(
+                    competitor = %race::competitor_prefix(&competitor),
+                    "Position {} was liquidated first by {}", position.address, competitor
+                );
+                self.rewards
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .record_lost_race(now, &position.symbol, &competitor);
+                Some(format!("front-run by {}", competitor))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Unable to tell whether position {} was front-run: {}", position.address, e);
+                None
+            }
+        }
+    }
+    
+    /// The liquidator's rewards net of network fees, in total, per symbol and per day
+    pub fn get_pnl_summary(&self) -> PnlSummary {
+        self.rewards.lock().unwrap_or_else(PoisonError::into_inner).summary()
+    }
+    
+    /// Queue a notification for owners' webhooks, if they're configured and
+    /// subscribed to its kind
+    fn notify_webhook(&self, payload: WebhookPayload) {
+        if let Some(webhooks) = &self.webhooks
+            && self.config().webhook_events.contains(&payload.event())
+        {
+            webhooks.notify(payload);
+        }
+    }
+    
+    /// Count a liquidation attempt towards the failures in a row, alerting once
+    /// they reach `alerts.consecutive_failures`
+    fn count_failure(&self, failed: bool, position: &Position) {
+        if !failed {
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+            return;
+        }
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.config().alerts.consecutive_failures {
+            self.alert(
+                AlertTrigger::ConsecutiveFailures,
+                AlertLevel::Critical,
+                format!("{} liquidations failed in a row", failures),
+                format!("The latest was of {} in {}", position.address, position.symbol),
+            );
+        }
+    }
+    
+    /// Queue an alert for operators, if alerts are configured
+    fn alert(&self, trigger: AlertTrigger, level: AlertLevel, title: impl Into<String>, body: impl Into<String>) {
+        if let Some(alerts) = &self.alerts {
+            alerts.alert(Alert::new(trigger, level, title, body));
+        }
+    }
+    
+    /// Queue a liquidation event for the store, if one is configured
+    #[cfg_attr(not(feature = "storage"), allow(unused_variables))]
+    fn store_event(&self, event: &LiquidationEvent) {
+        #[cfg(feature = "storage")]
+        if let Some(store) = &self.store {
+            store.record(event.clone());
+        }
+    }
+    
+    /// Start a position's cooldown after a completed liquidation, remembering
+    /// its margin ratio and size at the `price` it was liquidated at, if known
+    async fn mark_liquidated(&self, address: &PositionAddress, liquidated_at: i64, price: Option<Amount>) {
+        if let Some(position) = self.positions.write().await.get_mut(address) {
+            position.last_liquidated = Some(liquidated_at);
+            position.last_liquidated_margin_ratio = price.map(|price| position.margin_ratio(price));
+            position.last_liquidated_size = price.map(|_| position.size);
+        }
+        
+        if let Some(state) = &self.state
+            && let Err(e) = state
+                .lock()
+                .await
+                .record_liquidated(address, liquidated_at, self.now())
+        {
+            error!("Failed to record liquidation of {}: {}", address, e);
+        }
+    }
+    
+    /// Get the persisted state of a position
+    async fn position_state(&self, address: &PositionAddress) -> Option<PositionState> {
+        self.state.as_ref()?.lock().await.get(address).cloned()
+    }
+    
+    /// Age after which state entries are pruned (in seconds)
+    fn state_max_age_secs(&self) -> u64 {
+        self.config().liquidation_cooldown_secs.saturating_mul(2)
+    }
+    
+    /// Prune stale entries from the state file
+    async fn prune_state(&self, now: i64) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        if let Err(e) = state.lock().await.prune(now, self.state_max_age_secs()) {
+            error!("Failed to prune state file: {}", e);
+        }
+    }
+    
+    /// Remember a position fully liquidated or closed now, so it's evicted once
+    /// `closed_retention_secs` pass
+    fn finish_position(&self, address: PositionAddress) {
+        self.retention.lock().unwrap_or_else(PoisonError::into_inner).finish(address, self.now());
+    }
+    
+    /// Evict positions finished longer than `closed_retention_secs` ago, then
+    /// the price history and average basis of symbols no monitored position
+    /// is in
+    ///
+    /// Positions still awaiting confirmation or being checked stay until
+    /// they're resolved, by when their final events are published. Cooldowns
+    /// go with the positions, and the state file forgets them as it's pruned.
+    async fn evict_finished(&self, now: i64) {
+        let expired = self
+            .retention
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .expired(now, self.config().closed_retention_secs);
+        let mut evicted = 0;
+        for address in expired {
+            if self.confirmations.is_pending(&address) || self.in_flight.contains(&address) {
+                continue;
+            }
+            self.retention.lock().unwrap_or_else(PoisonError::into_inner).evicted(&address);
+            self.remove_position(&address).await;
+            self.last_updates.write().await.remove(&address);
+            self.last_checked.write().await.remove(&address);
+            evicted += 1;
+        }
+        
+        let monitored: HashSet<String> =
+            self.positions.read().await.values().map(|position| position.symbol.clone()).collect();
+        let dropped = self
+            .price_sanity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain_symbols(|symbol| monitored.contains(symbol));
+        self.mark_prices
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain_symbols(|symbol| monitored.contains(symbol));
+        self.retention.lock().unwrap_or_else(PoisonError::into_inner).evicted_symbols(dropped);
+        if evicted > 0 || dropped > 0 {
+            info!("Evicted {} finished positions and the price buffers of {} idle symbols", evicted, dropped);
+        }
+    }
+    
+    /// Remember a submitted liquidation in the state file until it's resolved
+    async fn record_submitted(&self, address: &PositionAddress, pending: &PendingSignature) {
+        if let Some(state) = &self.state
+            && let Err(e) = state
+                .lock()
+                .await
+                .record_submitted(address, &pending.signature.to_string(), pending.submitted_at)
+        {
+            error!("Failed to record submitted liquidation of {}: {}", address, e);
+        }
+    }
+    
+    /// Forget a submitted liquidation that failed or can no longer land
+    async fn clear_pending(&self, address: &PositionAddress, now: i64) {
+        if let Some(state) = &self.state
+            && let Err(e) = state.lock().await.clear_pending(address, now)
+        {
+            error!("Failed to clear pending liquidation of {}: {}", address, e);
+        }
+    }
+    
+    /// Resolve an unconfirmed liquidation, returning whether the position may be retried
+    ///
+    /// A confirmed signature completes the liquidation and starts the cooldown. Failed
+    /// signatures, and ones that can no longer land, are forgotten so the position can
+    /// be liquidated again.
+    async fn resolve_pending(&self, position: &Position, pending: &PendingSignature, now: i64) -> bool {
+        let resolution = match self.confirmations.resolve(pending, now).await {
+            Ok(resolution) => resolution,
+            Err(e) => {
+                warn!("Unable to check liquidation signature {}: {}", pending.signature, e);
+                if pending.is_stale(now) { Resolution::Expired } else { Resolution::Pending }
+            }
+        };
+        let latency = Duration::from_secs(now.saturating_sub(pending.submitted_at).max(0) as u64);
+        match &resolution {
+            Resolution::Pending => return false,
+            Resolution::Confirmed => {
+                info!("Liquidation of {} confirmed: {}", position.address, pending.signature);
+                self.confirmations.finish(&position.address, &resolution, latency);
+                self.mark_liquidated(&position.address, pending.submitted_at, None).await;
+                return false;
+            }
+            Resolution::Failed(err) => warn!(
+                "Liquidation {} of {} failed on chain: {}",
+                pending.signature,
+                position.address,
+                LiquidationError::transaction_failed(position.address, err.clone())
+            ),
+            Resolution::Expired => warn!(
+                "Liquidation {} of {} can no longer land",
+                pending.signature, position.address
+            ),
+        }
+        self.confirmations.finish(&position.address, &resolution, latency);
+        self.clear_pending(&position.address, now).await;
+        true
+    }
+    
+    /// Outcomes and latency of the liquidations awaited for confirmation
+    pub fn confirmation_stats(&self) -> ConfirmationStats {
+        self.confirmations.stats()
+    }
+    
+    /// Requests delayed or throttled by the RPC rate limiter
+    pub fn rate_limit_stats(&self) -> RateLimitStats {
+        self.rate_limiter.stats()
+    }
+    
+    /// Liquidations admitted and throttled by the last check cycle, the backlog
+    /// it left, and whether the circuit breaker has paused liquidation
+    pub fn throttle_stats(&self) -> ThrottleStats {
+        ThrottleStats {
+            queue_depth: self.queue_depth(),
+            oldest_queued_age_secs: self.oldest_queued_age(),
+            concurrency: self.concurrency(),
+            paused_since: self.paused_since(),
+            ..self.throttle_stats.lock().unwrap_or_else(PoisonError::into_inner).clone()
+        }
+    }
+    
+    /// Positions quarantined out of check cycles, and how many checks panicked
+    pub fn quarantine_stats(&self) -> QuarantineStats {
+        self.quarantine.lock().unwrap_or_else(PoisonError::into_inner).stats()
+    }
+    
+    /// Let a quarantined position back into check cycles, returning it if it
+    /// was quarantined
+    pub fn release_quarantined(&self, address: &PositionAddress) -> Option<QuarantinedPosition> {
+        let released = self.quarantine.lock().unwrap_or_else(PoisonError::into_inner).release(address);
+        if released.is_some() {
+            info!("Position {} released from quarantine", address);
+        }
+        released
+    }
+    
+    /// Let every quarantined position back into check cycles, returning how
+    /// many were quarantined
+    pub fn clear_quarantine(&self) -> usize {
+        let released = self.quarantine.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        info!("Released {} positions from quarantine", released);
+        released
+    }
+    
+    /// How many liquidations were checked against the chain before submission,
+    /// and how many it called off
+    pub fn verification_stats(&self) -> VerificationStats {
+        self.verification.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+    
+    /// Liquidation events dropped as duplicates since the engine started, and
+    /// the keys remembered to catch them
+    pub fn dedup_stats(&self) -> DedupStats {
+        self.dedup.lock().unwrap_or_else(PoisonError::into_inner).stats()
+    }
+    
+    /// What the engine holds in memory, and what it evicted or dropped to keep
+    /// that bounded
+    pub async fn memory_stats(&self) -> MemoryStats {
+        let state_entries = match &self.state {
+            Some(state) => state.lock().await.len(),
+            None => 0,
+        };
+        #[cfg(feature = "storage")]
+        let store_dropped = self.store.as_ref().map_or(0, StoreWriter::dropped);
+        #[cfg(not(feature = "storage"))]
+        let store_dropped = 0;
+        MemoryStats {
+            positions: self.positions.read().await.len(),
+            position_updates: self.last_updates.read().await.len(),
+            last_checked: self.last_checked.read().await.len(),
+            price_histories: self.price_sanity.lock().unwrap_or_else(PoisonError::into_inner).symbols(),
+            mark_price_averages: self.mark_prices.lock().unwrap_or_else(PoisonError::into_inner).symbols(),
+            funding_indices: self.funding_indices.read().await.len(),
+            state_entries,
+            dedup_keys: self.dedup_stats().tracked_events,
+            event_capacity: self.event_capacity,
+            event_subscribers: self.events.receiver_count(),
+            queued_events: self.events.len(),
+            store_dropped,
+            report_dropped: self.report.as_ref().map_or(0, ReportWriter::dropped),
+            audit_dropped: self.audit.as_ref().map_or(0, AuditWriter::dropped),
+            webhooks_dropped: self.webhooks.as_ref().map_or(0, |webhooks| webhooks.stats().dropped),
+            alerts_dropped: self.alerts.as_ref().map_or(0, |alerts| alerts.stats().dropped),
+            ..self.retention.lock().unwrap_or_else(PoisonError::into_inner).stats()
+        }
+    }
+    
+    /// Every liquidator key's balances, usage and whether it's signing
+    pub fn key_status(&self) -> Vec<KeyStatus> {
+        self.keys.status()
+    }
+    
+    /// Let a liquidator key sign liquidations again, or stop it from doing so,
+    /// returning its status if it's in the pool
+    pub fn set_key_enabled(&self, pubkey: &Pubkey, enabled: bool) -> Option<KeyStatus> {
+        let status = self.keys.set_enabled(pubkey, enabled)?;
+        info!("Liquidator key {} {}", pubkey, if enabled { "enabled" } else { "disabled" });
+        Some(status)
+    }
+    
+    /// Read every liquidator key's SOL balance and repayment balance in each
+    /// of `markets`, skipping the keys below `min_sol_balance` or
+    /// `min_repay_token_balance` until a later refresh finds them funded
+    ///
+    /// Operators are alerted of the keys found below a floor, which are returned.
+    pub async fn refresh_key_balances(
+        &self,
+        rpc: &dyn PreflightRpc,
+        markets: &BTreeMap<Pubkey, MarketTokenAccounts>,
+    ) -> Vec<Pubkey> {
+        let config = self.config();
+        let (min_sol_balance, min_token_balance) = (config.min_sol_balance, config.min_repay_token_balance);
+        let low = self.keys.refresh_balances(rpc, markets, min_sol_balance, min_token_balance).await;
+        if !low.is_empty() {
+            let status = self.keys.status();
+            let lines: Vec<String> = status
+                .iter()
+                .filter(|key| low.contains(&key.pubkey))
+                .map(|key| {
+                    let tokens: Vec<String> = key
+                        .token_balances
+                        .iter()
+                        .map(|(market, balance)| format!("{} in market {}", balance, market))
+                        .collect();
+                    format!(
+                        "{} holds {} SOL and {} repayment tokens",
+                        key.pubkey,
+                        key.sol_balance.map_or_else(|| "unknown".to_string(), |balance| balance.to_string()),
+                        tokens.join(", ")
+                    )
+                })
+                .collect();
+            self.alert(
+                AlertTrigger::LowBalance,
+                AlertLevel::Critical,
+                "Liquidator balance low",
+                format!(
+                    "Below min_sol_balance of {} or min_repay_token_balance of {}, so not signing; fund them:\n{}",
+                    min_sol_balance,
+                    min_token_balance,
+                    lines.join("\n")
+                ),
+            );
+        }
+        low
+    }
+    
+    /// Positions monitored as suspect, and how many positions were refused or
+    /// flagged for breaking the ingest rules
+    pub fn ingest_stats(&self) -> IngestStats {
+        self.suspects.lock().unwrap_or_else(PoisonError::into_inner).stats()
+    }
+    
+    /// Whether a monitored position is suspect
+    pub fn is_suspect(&self, address: &PositionAddress) -> bool {
+        self.suspects.lock().unwrap_or_else(PoisonError::into_inner).contains(address)
+    }
+    
+    /// Clear a suspect position's flag so it may be liquidated, returning it if
+    /// it was suspect
+    pub fn clear_suspect(&self, address: &PositionAddress) -> Option<SuspectPosition> {
+        let cleared = self.suspects.lock().unwrap_or_else(PoisonError::into_inner).clear(address);
+        if cleared.is_some() {
+            info!("Position {} cleared of suspicion", address);
+        }
+        cleared
+    }
+    
+    /// Skip `position` for `reason`, counting the skip under its label
+    fn skip(&self, position: PositionAddress, reason: SkipReason) -> LiquidationResult {
+        let mut stats = self.throttle_stats.lock().unwrap_or_else(PoisonError::into_inner);
+        *stats.total_skipped.entry(reason.label().to_string()).or_default() += 1;
+        LiquidationResult::Skipped { position, reason }
+    }
+    
+    /// When the circuit breaker tripped, while liquidation is paused
+    pub fn paused_since(&self) -> Option<i64> {
+        self.circuit_breaker.lock().unwrap_or_else(PoisonError::into_inner).tripped_at()
+    }
+    
+    /// Resume liquidation after the circuit breaker tripped, returning whether it
+    /// was paused
+    pub fn resume(&self) -> bool {
+        let mut breaker = self.circuit_breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        let paused = breaker.tripped_at().is_some();
+        breaker.reset();
+        if paused {
+            info!("Liquidation resumed after the circuit breaker tripped");
+            self.alert(
+                AlertTrigger::Paused,
+                AlertLevel::Info,
+                "Liquidation resumed",
+                "Liquidation resumed after the circuit breaker tripped",
+            );
+        }
+        paused
+    }
+    
+    /// What the engine does with the positions it monitors
+    pub fn mode(&self) -> EngineMode {
+        self.mode.lock().unwrap_or_else(PoisonError::into_inner).mode
+    }
+    
+    /// The engine's mode, when and by whom it was last set, and how often it changed
+    pub fn mode_status(&self) -> ModeStatus {
+        self.mode.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+    
+    /// Switch the engine to `mode` on behalf of `source`, the operator or
+    /// mechanism asking for it, returning the mode it was in
+    ///
+    /// Takes effect for the next position checked; a liquidation already being
+    /// submitted isn't recalled.
+    pub fn set_mode(&self, mode: EngineMode, source: &str) -> EngineMode {
+        let mut status = self.mode.lock().unwrap_or_else(PoisonError::into_inner);
+        let previous = status.mode;
+        if previous != mode {
+            warn!(mode = %mode, previous = %previous, source = source, "Engine mode changed from {} to {} by {}", previous, mode, source);
+            if mode == EngineMode::Paused || previous == EngineMode::Paused {
+                let (level, title) = match mode {
+                    EngineMode::Paused => (AlertLevel::Warning, "Engine paused"),
+                    _ => (AlertLevel::Info, "Engine unpaused"),
+                };
+                let message = format!("Engine mode changed from {} to {} by {}", previous, mode, source);
+                self.alert(AlertTrigger::Paused, level, title, message);
+            }
+            *status = ModeStatus {
+                mode,
+                since: self.now(),
+                source: source.to_string(),
+                changes: status.changes + 1,
+            };
+        }
+        previous
+    }
+    
+    /// Send `state` to systemd, if the engine runs under it
+    fn notify_systemd(&self, state: &str) {
+        if let Some(systemd) = &self.systemd
+            && let Err(e) = systemd.notify(state)
+        {
+            warn!("Failed to notify systemd: {}", e);
+        }
+    }
+    
+    /// One-line status for systemd: the engine's mode and warm-up, and how
+    /// the last check cycle went
+    fn systemd_status(&self, results: StdResult<&[LiquidationResult], &LiquidationError>) -> String {
+        let mode = self.mode();
+        if mode == EngineMode::Paused {
+            return mode.to_string();
+        }
+        let results = match results {
+            Ok(results) => results,
+            Err(e) => return format!("{}, check cycle failed: {}", mode, e),
+        };
+        let warm_up = self.warm_up_status();
+        if !warm_up.is_ready() {
+            return format!(
+                "{}, warming up: {}/{} cycles on complete data",
+                mode, warm_up.cycles_completed, warm_up.cycles_required
+            );
+        }
+        let count = |matches: fn(&LiquidationResult) -> bool| results.iter().filter(|result| matches(result)).count();
+        format!(
+            "{}, last cycle: {} liquidated, {} failed, {} skipped",
+            mode,
+            count(|result| matches!(result, LiquidationResult::Success { .. })),
+            count(|result| matches!(result, LiquidationResult::Failure { .. })),
+            count(|result| matches!(result, LiquidationResult::Skipped { .. })),
+        )
+    }
+    
+    /// Hold liquidations back until the engine has warmed up, as it does once started
+    pub(crate) fn begin_warm_up(&self) {
+        self.warm_up.lock().unwrap_or_else(PoisonError::into_inner).begin();
+        let message = format!("Warming up for {} cycles on complete data", self.config().warmup_cycles);
+        info!("{}", message);
+        self.alert(AlertTrigger::WarmUp, AlertLevel::Info, "Engine warming up", message);
+    }
+    
+    /// Whether the engine started and is still warming up, holding back liquidations
+    fn is_warming_up(&self) -> bool {
+        self.warm_up.lock().unwrap_or_else(PoisonError::into_inner).is_warming_up()
+    }
+    
+    /// Progress of the engine's warm-up: whether it's ready to liquidate, and
+    /// otherwise the data and cycles it still waits for
+    pub fn warm_up_status(&self) -> WarmUpStatus {
+        let required = self.config().warmup_cycles;
+        self.warm_up.lock().unwrap_or_else(PoisonError::into_inner).status(required)
+    }
+    
+    /// Fail unless the engine's mode lets it send transactions
+    fn ensure_submitting(&self) -> StdResult<(), LiquidationError> {
+        match self.mode() {
+            EngineMode::Running => Ok(()),
+            mode => Err(LiquidationError::SubmissionsDisabled(mode)),
+        }
+    }
+    
+    /// Health of each RPC endpoint, in configured order
+    pub fn rpc_status(&self) -> Vec<EndpointStatus> {
+        self.rpc.status()
+    }
+    
+    /// Tracker of the cluster's current slot, for listeners outside the
+    /// engine that see slots to feed
+    pub fn slot_tracker(&self) -> Arc<SlotTracker> {
+        self.slots.clone()
+    }
+    
+    /// The cluster's slot as last seen, and whether it stalled
+    pub fn slot_status(&self) -> SlotStatus {
+        self.slots.status(self.now(), self.config().max_slot_silence_secs)
+    }
+    
+    /// Ask the RPC node for its current slot, returning it
+    pub async fn refresh_slot(&self) -> StdResult<u64, LiquidationError> {
+        let slot = self
+            .rpc
+            .call(&self.rate_limiter, |rpc_client| rpc_client.get_slot().map_err(LiquidationError::from))
+            .await?;
+        self.slots.observe(slot, self.now());
+        Ok(slot)
+    }
+    
+    /// When the slot last advanced, if it stalled for longer than
+    /// `max_slot_silence_secs` before `now`
+    fn slot_stalled_since(&self, now: i64) -> Option<i64> {
+        let max_silence_secs = self.config().max_slot_silence_secs?;
+        let advanced_at = self.slots.stalled_since(now, max_silence_secs)?;
+        warn!("Slot hasn't advanced since {}, holding liquidations back", advanced_at);
+        Some(advanced_at)
+    }
+    
+    /// Fail with `StalePriceSlot` if a price was published more than
+    /// `max_price_age_slots` before the current slot
+    ///
+    /// Prices without a slot, and any price before a slot is seen, pass.
+    fn fresh_slot(&self, symbol: &str, price_data: &PriceData) -> StdResult<(), LiquidationError> {
+        let (Some(max_age_slots), Some(publish_slot), Some(current_slot)) =
+            (self.config().max_price_age_slots, price_data.publish_slot, self.slots.current_slot())
+        else {
+            return Ok(());
+        };
+        slot::check_slot_staleness(symbol, publish_slot, current_slot, max_age_slots).map(drop)
+    }
+    
+    /// Get the insurance fund drawdown caused by bad-debt liquidations
+    pub async fn get_insurance_stats(&self) -> InsuranceStats {
+        self.insurance
+            .write()
+            .await
+            .stats(self.now(), self.config().bad_debt_window_secs)
+    }
+    
+    /// Distribution of risk across the monitored book: margin ratios, open
+    /// notional, statuses, the positions closest to liquidation and the bad debt
+    /// uniform price shocks would realize
+    ///
+    /// Computed from a snapshot of the positions on the blocking pool, away from
+    /// the check cycle, and cached for `stats.cache_secs`; callers arriving while
+    /// it's computed wait for the same result. The liquidation backlog and
+    /// memory use are always current.
+    pub async fn get_stats(&self) -> StdResult<EngineStats, LiquidationError> {
+        let memory = self.memory_stats().await;
+        let mut cached = self.stats.lock().await;
+        let now = self.now();
+        let config = self.config();
+        let with_queue = |stats: &EngineStats| EngineStats {
+            queue_depth: self.queue_depth(),
+            oldest_queued_age_secs: self.oldest_queued_age(),
+            memory: memory.clone(),
+            ..stats.clone()
+        };
+        if let Some(stats) = cached.as_ref()
+            && now.saturating_sub(stats.generated_at) < config.stats.cache_secs as i64
+        {
+            return Ok(with_queue(stats));
+        }
+        
+        let mut positions: Vec<Position> = self.positions.read().await.values().cloned().collect();
+        let prices = self.latest_prices(&positions).await;
+        self.revalue_collateral(&mut positions, &prices).await;
+        let prices = self.mark_prices(&positions, prices).await;
+        let funding_rates = self.funding_rates().await;
+        let mut updates = Vec::new();
+        for position in &positions {
+            let Some(&price) = prices.get(&position.symbol) else {
+                continue;
+            };
+            let pending = self.confirmations.is_pending(&position.address);
+            let status = self.status_at(position, price, pending);
+            let mut update = position.update(price, status, config.margin_params_at(position, price), now);
+            update.exceeds_max_leverage = config.exceeds_max_leverage(position);
+            update.liquidation_price = config.displayed_liquidation_price(position, update.liquidation_price);
+            update.estimated_time_to_liquidation_secs = self.time_to_liquidation(&update);
+            Self::project_funding(&config, position, funding_rates.get(&position.symbol).copied(), &mut update);
+            updates.push(update);
+        }
+        let insurance_fund = config.insurance_fund_balance - self.get_insurance_stats().await.total_bad_debt;
+        
+        let stats = tokio::task::spawn_blocking(move || {
+            EngineStats::compute(&positions, updates, &prices, &config, insurance_fund, now)
+        })
+        .await
+        .map_err(|e| LiquidationError::Other(format!("Computing stats failed: {}", e)))?;
+        let stats = with_queue(&stats);
+        *cached = Some(stats.clone());
+        Ok(stats)
+    }
+    
+    /// Estimate the economics of liquidating a position
+    ///
+    /// Returns `None` when network fees can't be priced, in which case the
+    /// liquidation proceeds rather than leaving the position open.
+    async fn estimate_profit(
+        &self,
+        model: &ProfitModel,
+        position: &Position,
+        price: Amount,
+        liquidation_fraction: f64,
+    ) -> Option<ProfitEstimate> {
+        let sol_price = match self.oracle.get_price(&self.config().fee_price_symbol).await {
+            Ok(price) => price.as_f64(),
+            Err(e) => {
+                warn!("Unable to price network fees with {}: {}", self.config().fee_price_symbol, e);
+                return None;
+            }
+        };
+        
+        Some(model.estimate(position, price, liquidation_fraction, sol_price))
+    }
+    
+    /// Decide whether a position should be liquidated at the given price data
+    ///
+    /// Cross positions are judged by `pool`, the pooled margin of their owner's
+    /// cross positions at the same price. Symbols whose confidence policy widens
+    /// prices are judged at the edge of each price's confidence interval most
+    /// favourable to the position.
+    fn should_liquidate(&self, position: &Position, price_data: &PriceData, pool: Option<&PooledMargin>) -> bool {
+        let config = self.config();
+        let margin_params = |price: Amount| config.margin_params_at(position, price);
+        let undercollateralized = |price: Amount, confidence: f64| {
+            let price = config
+                .oracle_confidence
+                .trigger_price(&position.symbol, position.is_long, price, confidence);
+            match pool {
+                Some(pool) => position.is_undercollateralized_cross(
+                    price,
+                    margin_params(price),
+                    &pool.repriced(position, price_data.price, price, margin_params),
+                ),
+                None => position.is_undercollateralized(price, margin_params(price)),
+            }
+        };
+        if !undercollateralized(price_data.price, price_data.confidence) {
+            if price_data.confidence > 0.0 && undercollateralized(price_data.price, 0.0) {
+                info!(
+                    "Skipping position {}: liquidatable at {} but not within its confidence of {}",
+                    position.address, price_data.price, price_data.confidence
+                );
+            }
+            return false;
+        }
+        
+        // Guard against single-slot wicks by requiring the EMA to agree
+        let ema_price = amount::from_f64_lossy(price_data.ema_price);
+        if config.require_twap_confirmation && !undercollateralized(ema_price, price_data.ema_confidence) {
+            info!(
+                "Skipping position {}: liquidatable at spot {} but not at EMA {}",
+                position.address, price_data.price, price_data.ema_price
+            );
+            return false;
+        }
+        
+        true
+    }
+    
+    /// Priority fee for a liquidation attempt on a position (1 for the first submission)
+    async fn priority_fee(&self, position: &Position, attempt: u8) -> u64 {
+        let config = self.config();
+        let accounts = self.writable_accounts(position).await;
+        config
+            .priority_fee_strategy
+            .fee(self.fee_source.as_ref(), &accounts, attempt, config.max_priority_fee_micro_lamports)
+            .await
+    }
+    
+    /// Accounts a liquidation of a position write-locks, whose recent fees it
+    /// competes with
+    ///
+    /// Dry runs don't read markets from the chain for this, so without a
+    /// preloaded market only the position is known.
+    async fn writable_accounts(&self, position: &Position) -> Vec<Pubkey> {
+        let market = if self.dry_run() {
+            self.known_liquidation_market(&position.symbol)
+        } else {
+            self.liquidation_market(&position.symbol).await.ok()
+        };
+        let Some(market) = market else {
+            return vec![position.address.pubkey()];
+        };
+        let liquidate = market.liquidate_instruction(position.address.pubkey(), self.liquidator(), 0);
+        instruction::writable_accounts(&[liquidate])
+    }
+    
+    /// Compute unit limit for liquidating a position, repaying `repay_amount`
+    ///
+    /// The transaction is simulated with the program's liquidate instruction for
+    /// the position. Liquidations of the same symbol share an instruction shape,
+    /// so one simulation per symbol is cached until it keeps failing. Dry runs
+    /// aren't simulated and use the configured estimate.
+    async fn compute_unit_limit(
+        &self,
+        position: &Position,
+        repay_amount: u64,
+        priority_fee: u64,
+    ) -> StdResult<u32, LiquidationError> {
+        let config = self.config();
+        if self.dry_run() {
+            return Ok(config.estimated_compute_units);
+        }
+        if let Some(limit) = self.compute_units.get(&position.symbol) {
+            return Ok(limit);
+        }
+        
+        // Simulate with the maximum limit so the budget doesn't constrain the estimate
+        let mut instructions = self.transaction_prefix(MAX_COMPUTE_UNIT_LIMIT, priority_fee);
+        instructions.push(self.liquidate_instruction(position, self.liquidator(), repay_amount).await?);
+        let units_consumed = self.simulator.simulate_units(&instructions, &self.liquidator()).await?;
+        let limit = compute::with_headroom(units_consumed, config.compute_unit_headroom_percent);
+        info!(
+            "Simulated liquidation of {} consumed {} CU, requesting {} CU for {}",
+            position.address, units_consumed, limit, position.symbol
+        );
+        self.compute_units.insert(&position.symbol, limit);
+        Ok(limit)
+    }
+    
+    /// Track whether a liquidation using the cached compute estimate succeeded
+    fn record_compute_outcome(&self, symbol: &str, succeeded: bool) {
+        if succeeded {
+            self.compute_units.record_success(symbol);
+        } else if self
+            .compute_units
+            .record_failure(symbol, self.config().compute_estimate_max_failures)
+        {
+            warn!("Discarding compute estimate for {} after repeated failures", symbol);
+        }
+    }
+    
+    /// Instructions a liquidation transaction starts with, ahead of its
+    /// liquidations
+    ///
+    /// With a durable nonce, advancing it has to be the first instruction.
+    fn transaction_prefix(&self, compute_unit_limit: u32, priority_fee: u64) -> Vec<Instruction> {
+        let mut instructions: Vec<Instruction> = self.nonce.iter().map(NonceAccount::advance_instruction).collect();
+        instructions.extend(compute::budget_instructions(compute_unit_limit, priority_fee));
+        instructions
+    }
+    
+    /// Program and id of the market a symbol's positions belong to, the
+    /// default program's first market for positions outside markets
+    fn program_market(&self, symbol: &str) -> (Pubkey, u64) {
+        self.config()
+            .market(symbol)
+            .map_or((PROGRAM_ID, 0), |market| (market.program_id, market.market_id))
+    }
+    
+    /// Market a symbol's positions belong to, if preloaded or read before
+    fn known_liquidation_market(&self, symbol: &str) -> Option<LiquidationMarket> {
+        let (program_id, market_id) = self.program_market(symbol);
+        self.liquidation_markets
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&program_market_address(&program_id, market_id))
+            .copied()
+    }
+    
+    /// Market a symbol's positions belong to, read from its program unless
+    /// preloaded or read before
+    async fn liquidation_market(&self, symbol: &str) -> StdResult<LiquidationMarket, LiquidationError> {
+        if let Some(market) = self.known_liquidation_market(symbol) {
+            return Ok(market);
+        }
+        let (program_id, market_id) = self.program_market(symbol);
+        let rpc = RpcPreflight::new(self.rpc.clone(), self.rate_limiter.clone());
+        let market = preflight::load_market(&rpc, &program_id, market_id).await?;
+        self.liquidation_markets
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(program_market_address(&program_id, market_id), market);
+        Ok(market)
+    }
+    
+    /// Repayment by which `liquidation_fraction` of a position is liquidated at
+    /// `price`, in base units of the debt mint, or why the program would reject it
+    ///
+    /// The share of the debt is repaid as the program takes it from the
+    /// position's `market` (see [`LiquidationMarket::repay_amount`]), or as is
+    /// without one.
+    fn repay_amount(
+        &self,
+        market: Option<LiquidationMarket>,
+        position: &Position,
+        price: f64,
+        liquidation_fraction: f64,
+    ) -> StdResult<u64, SkipReason> {
+        let decimals = self.config().market(&position.symbol).map(|market| market.mint_decimals).unwrap_or_default();
+        // Positions read from the program borrowed their debt against the
+        // collateral at its entry price
+        let debt = (position.size * position.entry_price - position.effective_margin()).max(Amount::ZERO).as_f64();
+        let target = rounding::to_base_units(debt * liquidation_fraction, decimals.debt);
+        let Some(market) = market else {
+            return Ok(target);
+        };
+        let balances = PositionBalances {
+            collateral: rounding::to_base_units(position.size.as_f64(), decimals.collateral),
+            debt: rounding::to_base_units(debt, decimals.debt),
+        };
+        market.repay_amount(balances, price, target)
+    }
+    
+    /// The program's instruction by which `liquidator` liquidates a position,
+    /// repaying `repay_amount` of its debt
+    async fn liquidate_instruction(
+        &self,
+        position: &Position,
+        liquidator: Pubkey,
+        repay_amount: u64,
+    ) -> StdResult<Instruction, LiquidationError> {
+        let market = self.liquidation_market(&position.symbol).await?;
+        Ok(market.liquidate_instruction(position.address.pubkey(), liquidator, repay_amount))
+    }
+    
+    /// Keypair paying for and signing the next transaction, picked from the
+    /// pool by the configured rotation
+    ///
+    /// Fails without an enabled and funded key, or one that can advance the
+    /// configured durable nonce.
+    fn signer(&self) -> StdResult<&Keypair, LiquidationError> {
+        let authority = self.nonce.as_ref().map(NonceAccount::authority);
+        self.keys.select(self.config().key_pool.rotation, self.now(), authority.as_ref())
+    }
+    
+    /// Blockhash to sign a liquidation transaction against
+    ///
+    /// The durable nonce's current value if one is configured, otherwise a fresh
+    /// blockhash so a retry never reuses one that may have expired.
+    async fn transaction_blockhash(&self) -> StdResult<Hash, LiquidationError> {
+        if let Some(nonce) = &self.nonce {
+            return nonce.value().await;
+        }
+        self.rpc
+            .call(&self.rate_limiter, |rpc_client| {
+                rpc_client.get_latest_blockhash().map_err(LiquidationError::from)
+            })
+            .await
+    }
+    
+    /// Execute liquidation of a position, returning the transaction signature
+    /// and the key that signed it
+    #[instrument(
+        skip_all,
+        fields(
+            correlation_id = %correlation_id,
+            attempt = attempt,
+            priority_fee = priority_fee,
+            compute_unit_limit = compute_unit_limit,
+            signature = tracing::field::Empty,
+            liquidator = tracing::field::Empty
+        )
+    )]
+    async fn liquidate_position(
+        &self,
+        position: &Position,
+        repay_amount: u64,
+        priority_fee: u64,
+        compute_unit_limit: u32,
+        correlation_id: &str,
+        attempt: u8,
+    ) -> StdResult<(String, Pubkey), LiquidationError> {
+        info!(
+            "Liquidating position {}, repaying {}, with priority fee {} and {} CU limit",
+            position.address, repay_amount, priority_fee, compute_unit_limit
+        );
+        
+        if self.dry_run() {
+            Span::current().record("signature", "dry-run");
+            return Ok(("dry-run".to_string(), self.liquidator()));
+        }
+        
+        self.ensure_submitting()?;
+        let payer = self.signer()?;
+        Span::current().record("liquidator", payer.pubkey().to_string().as_str());
+        let mut instructions = self.transaction_prefix(compute_unit_limit, priority_fee);
+        instructions.push(self.liquidate_instruction(position, payer.pubkey(), repay_amount).await?);
+        let blockhash = self.transaction_blockhash().await?;
+        let outcome = self.submitter.submit(&instructions, payer, blockhash).await;
+        // A landed transaction advanced the nonce, and a mismatch means it moved on
+        // without us; either way re-read it. Other failures leave it untouched, so
+        // retries keep using the same value.
+        if let Some(nonce) = &self.nonce
+            && outcome.as_ref().map_or_else(nonce::is_nonce_mismatch, |_| true)
+        {
+            nonce.refresh().await;
+        }
+        let signature = outcome?;
+        Span::current().record("signature", signature.to_string().as_str());
+        
+        // Tracked until resolved, so neither a timeout nor a restart leads to a
+        // second liquidation that could land alongside this one
+        let pending = PendingSignature {
+            signature,
+            // Transactions signed against a durable nonce don't expire
+            blockhash: self.nonce.is_none().then_some(blockhash),
+            submitted_at: self.now(),
+        };
+        self.record_submitted(&position.address, &pending).await;
+        let timeout = Duration::from_secs(self.config().confirmation_timeout_secs);
+        match self.confirmations.await_confirmation(position.address, pending, timeout).await {
+            Ok(_) => Ok((signature.to_string(), payer.pubkey())),
+            Err(LiquidationError::ConfirmationTimeout) => {
+                warn!(
+                    "Liquidation {} of {} not confirmed within {:?}, leaving it pending",
+                    signature, position.address, timeout
+                );
+                Err(LiquidationError::ConfirmationTimeout)
+            }
+            Err(e) => {
+                self.clear_pending(&position.address, self.now()).await;
+                Err(e)
+            }
+        }
+    }
+    
+    /// Submit a cycle's prepared liquidations, packed into shared transactions
+    /// per market, and return their results in the order they resolved
+    ///
+    /// Every transaction is paid for and signed by the same key, the liquidator
+    /// of each instruction. When a liquidation fails the transaction it shares,
+    /// it fails with its own error and the others are resubmitted one by one; a
+    /// shared transaction that failed otherwise, or expired, is resubmitted one
+    /// by one in full.
+    async fn submit_liquidations(&self, prepared: Vec<PreparedLiquidation<'_>>) -> Vec<LiquidationResult> {
+        if prepared.is_empty() {
+            return Vec::new();
+        }
+        let config = self.config();
+        let payer = match self.signer() {
+            Ok(payer) => payer,
+            Err(e) => {
+                let mut results = Vec::new();
+                for prepared in prepared {
+                    let submission = Submission {
+                        outcome: Err(batch_error(&e)),
+                        attempts: 0,
+                        priority_fee: prepared.first_fee,
+                        compute_unit_limit: 0,
+                    };
+                    results.push(self.finish_liquidation(prepared, submission).await);
+                }
+                return results;
+            }
+        };
+        // Liquidations awaiting their outcome, with the attempts made and the last
+        // priority fee paid
+        let mut pending: HashMap<Pubkey, (PreparedLiquidation<'_>, u8, u64)> = HashMap::new();
+        let mut entries = Vec::new();
+        let mut results = Vec::new();
+        for prepared in prepared {
+            let position = &prepared.position;
+            let repay_amount = prepared.repay_amount;
+            let entry = match self.compute_unit_limit(position, repay_amount, prepared.first_fee).await {
+                Ok(limit) => self
+                    .liquidate_instruction(position, payer.pubkey(), repay_amount)
+                    .await
+                    .map(|instruction| BatchEntry::from_instruction(position.address.into(), instruction, limit)),
+                Err(e) => Err(e),
+            };
+            match entry {
+                Ok(entry) => {
+                    let first_fee = prepared.first_fee;
+                    pending.insert(entry.position, (prepared, 0, first_fee));
+                    entries.push(entry);
+                }
+                Err(e) => {
+                    let submission = Submission {
+                        outcome: Err(e),
+                        attempts: 0,
+                        priority_fee: prepared.first_fee,
+                        compute_unit_limit: 0,
+                    };
+                    results.push(self.finish_liquidation(prepared, submission).await);
+                }
+            }
+        }
+        let compute_units: HashMap<Pubkey, u32> =
+            entries.iter().map(|entry| (entry.position, entry.compute_units)).collect();
+        // Liquidations share transactions within their market, sized against the
+        // largest prefix they may be submitted with
+        let mut markets: BTreeMap<Pubkey, Vec<BatchEntry>> = BTreeMap::new();
+        for entry in entries {
+            markets.entry(entry.instruction.program_id).or_default().push(entry);
+        }
+        let prefix = self.transaction_prefix(MAX_COMPUTE_UNIT_LIMIT, config.max_priority_fee_micro_lamports);
+        let mut queue: VecDeque<TransactionBatch> = markets
+            .into_values()
+            .flat_map(|entries| batch::pack(entries, &prefix, &payer.pubkey(), config.max_instructions_per_tx))
+            .collect();
+        
+        let mut outcomes = Vec::new();
+        while let Some(batch) = queue.pop_front() {
+            let positions = batch.positions();
+            let shared = positions.len() > 1;
+            let accounts = instruction::writable_accounts(&batch.instructions(&[]));
+            let priority_fee = config
+                .priority_fee_strategy
+                .fee(self.fee_source.as_ref(), &accounts, 0, config.max_priority_fee_micro_lamports)
+                .await;
+            for position in &positions {
+                if let Some((_, attempts, fee)) = pending.get_mut(position) {
+                    *attempts += 1;
+                    *fee = priority_fee;
+                }
+            }
+            let prefix = self.transaction_prefix(batch.compute_units(), priority_fee);
+            match self.send_batch(&batch, &prefix, payer).await {
+                Ok((signature, Resolution::Confirmed)) => {
+                    outcomes.extend(positions.into_iter().map(|position| (position, Ok(signature.to_string()))));
+                }
+                Ok((signature, Resolution::Failed(err))) if shared => {
+                    let (failed, rest) = batch.split_failure(&err, prefix.len());
+                    warn!(
+                        "Batched liquidation {} of {} positions failed ({:?}), resubmitting the rest one by one",
+                        signature,
+                        positions.len(),
+                        err
+                    );
+                    if let Some((entry, err)) = failed {
+                        let err = LiquidationError::transaction_failed(entry.position.into(), err);
+                        outcomes.push((entry.position, Err(err)));
+                    }
+                    queue.extend(rest.into_iter().map(|entry| TransactionBatch { entries: vec![entry] }));
+                }
+                Ok((_, Resolution::Failed(err))) => {
+                    outcomes.push((positions[0], Err(LiquidationError::transaction_failed(positions[0].into(), err))));
+                }
+                Ok((signature, _)) if shared => {
+                    warn!("Batched liquidation {} expired, resubmitting its positions one by one", signature);
+                    queue.extend(batch.entries.into_iter().map(|entry| TransactionBatch { entries: vec![entry] }));
+                }
+                Ok((signature, _)) => {
+                    outcomes.push((positions[0], Err(LiquidationError::BlockhashExpired(signature.to_string()))));
+                }
+                Err(e) => outcomes.extend(positions.into_iter().map(|position| (position, Err(batch_error(&e))))),
+            }
+        }
+        
+        for (position, outcome) in outcomes {
+            let Some((prepared, attempts, priority_fee)) = pending.remove(&position) else {
+                continue;
+            };
+            let submission = Submission {
+                outcome: outcome.map(|signature| (signature, payer.pubkey())),
+                attempts,
+                priority_fee,
+                compute_unit_limit: compute_units.get(&position).copied().unwrap_or_default(),
+            };
+            results.push(self.finish_liquidation(prepared, submission).await);
+        }
+        results
+    }
+    
+    /// Submit a batch of liquidations in one transaction, starting with
+    /// `prefix` and paid for by `payer`, and await its resolution
+    ///
+    /// Every position of the batch is held while the transaction is pending,
+    /// and released if it fails or expires.
+    async fn send_batch(
+        &self,
+        batch: &TransactionBatch,
+        prefix: &[Instruction],
+        payer: &Keypair,
+    ) -> StdResult<(Signature, Resolution), LiquidationError> {
+        self.ensure_submitting()?;
+        let instructions = batch.instructions(prefix);
+        let blockhash = self.transaction_blockhash().await?;
+        let outcome = self.submitter.submit(&instructions, payer, blockhash).await;
+        if let Some(nonce) = &self.nonce
+            && outcome.as_ref().map_or_else(nonce::is_nonce_mismatch, |_| true)
+        {
+            nonce.refresh().await;
+        }
+        let signature = outcome?;
+        
+        let pending = PendingSignature {
+            signature,
+            blockhash: self.nonce.is_none().then_some(blockhash),
+            submitted_at: self.now(),
+        };
+        let positions: Vec<PositionAddress> = batch.positions().into_iter().map(PositionAddress::from).collect();
+        for position in &positions {
+            self.record_submitted(position, &pending).await;
+        }
+        // The first position is tracked while awaited, the others alongside it
+        for position in &positions[1..] {
+            self.confirmations.track(*position, pending);
+        }
+        let timeout = Duration::from_secs(self.config().confirmation_timeout_secs);
+        let (resolution, latency) = match self.confirmations.await_resolution(positions[0], pending, timeout).await {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                warn!(
+                    "Liquidation {} of {:?} not confirmed within {:?}, leaving it pending",
+                    signature, positions, timeout
+                );
+                return Err(e);
+            }
+        };
+        for position in &positions[1..] {
+            self.confirmations.finish(position, &resolution, latency);
+        }
+        if resolution != Resolution::Confirmed {
+            for position in &positions {
+                self.clear_pending(position, self.now()).await;
+            }
+        }
+        Ok((signature, resolution))
+    }
+    
+    /// Follow the logs of the program at `program_id` over the pubsub service at
+    /// `ws_url`, applying the events of every confirmed transaction to the
+    /// monitored positions
+    ///
+    /// Only returns once the subscription fails or the node closes it.
+    pub async fn follow_program_events(&self, ws_url: &str, program_id: &Pubkey) -> StdResult<(), LiquidationError> {
+        let client = PubsubClient::new(ws_url)
+            .await
+            .map_err(|e| LiquidationError::rpc(RpcErrorKind::Transport, format!("failed to connect to {}: {}", ws_url, e)))?;
+        let (mut logs, unsubscribe) = client
+            .logs_subscribe(events::program_logs_filter(program_id), events::program_logs_config())
+            .await
+            .map_err(|e| LiquidationError::rpc(RpcErrorKind::Response, format!("failed to subscribe to program logs: {}", e)))?;
+        info!("Following events of program {} from {}", program_id, ws_url);
+
+        while let Some(response) = logs.next().await {
+            self.slots.observe(response.context.slot, self.now());
+            // Failed transactions changed nothing, whatever they logged
+            if response.value.err.is_some() {
+                continue;
+            }
+            for event in events::parse_program_events(&response.value.logs, program_id) {
+                self.apply_logged_event(&response.value.signature, &event).await;
+            }
+        }
+        unsubscribe().await;
+        Err(LiquidationError::rpc(RpcErrorKind::Transport, "program log subscription closed"))
+    }
+
+    /// Record the liquidations the program at `program_id` made in `range` to
+    /// `store`, by us or any other keeper, with `symbol` as their positions'
+    ///
+    /// The program's transactions are read through the engine's RPC endpoints
+    /// within its request budget, and an interrupted backfill of the same range
+    /// resumes where it stopped (see [`backfill`](crate::backfill)).
+    #[cfg(feature = "storage")]
+    pub async fn backfill_liquidations(
+        &self,
+        store: &LiquidationStore,
+        program_id: &Pubkey,
+        symbol: &str,
+        range: &BackfillRange,
+    ) -> StdResult<BackfillStats, LiquidationError> {
+        let source = BackfillSource {
+            program_id: *program_id,
+            symbol: symbol.to_string(),
+            liquidation_fee_bps: self.config().liquidation_fee_bps,
+        };
+        let history = RpcHistory::new(self.rpc.clone(), self.rate_limiter.clone());
+        let stats = backfill::backfill(&history, store, &source, range).await?;
+        info!(
+            "Backfilled {} liquidations of program {} from {} transactions, {} already recorded",
+            stats.recorded, program_id, stats.transactions, stats.duplicates
+        );
+        Ok(stats)
+    }
+
+    /// Monitor every open position account of a market, as fetched from its
+    /// program with `getProgramAccounts`, returning how many are monitored
+    ///
+    /// Accounts that can't be decoded or belong to the program's other markets
+    /// are skipped, and the rest are versioned by the slot they were read at.
+    pub async fn load_market_positions(&self, market: &MarketConfig) -> StdResult<usize, LiquidationError> {
+        let program_id = market.program_id;
+        let (slot, accounts) = self
+            .rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                let mut config = health::position_accounts_config();
+                config.account_config.commitment = Some(rpc_client.commitment());
+                config.with_context = Some(true);
+                let response: RpcResponse<Vec<RpcKeyedAccount>> = rpc_client
+                    .send(RpcRequest::GetProgramAccounts, serde_json::json!([program_id.to_string(), config]))
+                    .map_err(LiquidationError::from)?;
+                Ok((response.context.slot, response.value))
+            })
+            .await?;
+        let mut monitored = 0;
+        for keyed in accounts {
+            let Ok(address) = keyed.pubkey.parse::<PositionAddress>() else {
+                warn!("Skipping position account with invalid address {}", keyed.pubkey);
+                continue;
+            };
+            let Some(account) = keyed.account.decode::<Account>() else {
+                warn!("Skipping undecodable position account {}", address);
+                continue;
+            };
+            match health::decode_position_account(&account.data) {
+                Ok(account) if !market.holds_position(address.as_ref(), &account) => {
+                    debug!("Skipping position account {} of another market", address);
+                }
+                Ok(account) => monitored += usize::from(self.apply_position_account(address, &account, market, slot).await),
+                Err(e) => warn!("Skipping position account {}: {}", address, e),
+            }
+        }
+        info!("Monitoring {} position accounts of the {} market", monitored, market.symbol);
+        self.warm_up.lock().unwrap_or_else(PoisonError::into_inner).record_load(&market.symbol);
+        Ok(monitored)
+    }
+    
+    /// Follow a market's position accounts over the pubsub service at `ws_url`,
+    /// loading them afresh once subscribed so changes made while unsubscribed
+    /// aren't missed
+    ///
+    /// Only returns once the subscription fails or the node closes it.
+    pub async fn follow_position_accounts(&self, ws_url: &str, market: &MarketConfig) -> StdResult<(), LiquidationError> {
+        let client = PubsubClient::new(ws_url)
+            .await
+            .map_err(|e| LiquidationError::rpc(RpcErrorKind::Transport, format!("failed to connect to {}: {}", ws_url, e)))?;
+        let (mut accounts, unsubscribe) = client
+            .program_subscribe(&market.program_id, Some(health::position_accounts_config()))
+            .await
+            .map_err(|e| LiquidationError::rpc(RpcErrorKind::Response, format!("failed to subscribe to position accounts: {}", e)))?;
+        self.load_market_positions(market).await?;
+        
+        while let Some(response) = accounts.next().await {
+            let Ok(address) = response.value.pubkey.parse::<PositionAddress>() else {
+                continue;
+            };
+            let Some(account) = response.value.account.decode::<Account>() else {
+                warn!("Skipping undecodable update of position account {}", address);
+                continue;
+            };
+            match health::decode_position_account(&account.data) {
+                Ok(account) if !market.holds_position(address.as_ref(), &account) => {}
+                // Deposits and withdrawals move the position's status right away
+                Ok(account) => {
+                    if self.apply_position_account(address, &account, market, response.context.slot).await {
+                        self.republish_position(&address).await;
+                    }
+                }
+                Err(e) => warn!("Skipping position account {}: {}", address, e),
+            }
+        }
+        unsubscribe().await;
+        Err(LiquidationError::rpc(RpcErrorKind::Transport, "position account subscription closed"))
+    }
+    
+    /// Monitor a market's position account as decoded at `slot`, returning
+    /// whether it's monitored
+    ///
+    /// Positions already monitored keep their liquidation and funding history,
+    /// and closed or empty accounts stop being monitored. Accounts read before
+    /// the position's version was written are ignored, and those breaking the
+    /// ingest rules are refused or flagged as added positions are, though not
+    /// held to the leverage cap.
+    async fn apply_position_account(
+        &self,
+        address: PositionAddress,
+        account: &PositionAccount,
+        market: &MarketConfig,
+        slot: u64,
+    ) -> bool {
+        self.slots.observe(slot, self.now());
+        if let Some(version) = self.position_version(&address)
+            && slot < version
+        {
+            debug!("Ignoring position account {} read at slot {}, before version {}", address, slot, version);
+            return self.get_position(&address).await.is_some();
+        }
+        let position = health::position_from_account(address.pubkey(), account, &market.symbol, market.mint_decimals);
+        let Some(position) = position else {
+            self.remove_position(&address).await;
+            return false;
+        };
+        let position = match self.get_position(&address).await {
+            Some(known) => Position {
+                size: position.size,
+                entry_price: position.entry_price,
+                ..known
+            },
+            None => position,
+        };
+        match self.write_ingested(position, &PositionWrite::at_version(slot), false).await {
+            Ok(_) => true,
+            // Written to since the version was checked, or refused by the ingest rules
+            Err(e) => {
+                debug!("Ignoring position account {} read at slot {}: {}", address, slot, e);
+                self.get_position(&address).await.is_some()
+            }
+        }
+    }
+    
+    /// A market position as its account now holds it on chain, read at
+    /// `verify_commitment` just before liquidating it and monitored from then on
+    ///
+    /// `None` once the account is closed or left without collateral. Positions
+    /// outside markets have no account to read, dry runs submit nothing, and
+    /// operators may turn `verify_before_submit` off, so those come back as they
+    /// were.
+    async fn refetch_position(&self, position: &Position) -> StdResult<Option<Position>, LiquidationError> {
+        let config = self.config();
+        let market = match config.market(&position.symbol) {
+            Some(market) if config.verify_before_submit && !self.dry_run() => market.clone(),
+            _ => return Ok(Some(position.clone())),
+        };
+        let commitment = CommitmentConfig {
+            commitment: config.verify_commitment,
+        };
+        drop(config);
+        let address = position.address;
+        let (slot, account) = self
+            .rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client
+                    .get_account_with_commitment(address.as_ref(), commitment)
+                    .map(|response| (response.context.slot, response.value))
+                    .map_err(LiquidationError::from)
+            })
+            .await?;
+        self.verification.lock().unwrap_or_else(PoisonError::into_inner).verified += 1;
+        let Some(account) = account else {
+            self.remove_position(&address).await;
+            return Ok(None);
+        };
+        let account = health::decode_position_account(&account.data)?;
+        if !self.apply_position_account(address, &account, &market, slot).await {
+            return Ok(None);
+        }
+        Ok(self.get_position(&address).await)
+    }
+    
+    /// Count a liquidation called off because the position as read from chain,
+    /// `fresh`, wasn't liquidatable at `price` though its monitored copy was
+    fn record_divergence(&self, cached: &Position, fresh: &Position, price: Amount, now: i64) {
+        let margin_params = self.config().margin_params_at(fresh, price);
+        let divergence = CacheDivergence {
+            position: fresh.address,
+            price: price.as_f64(),
+            cached_health_factor: cached.health_factor(price, margin_params),
+            chain_health_factor: fresh.health_factor(price, margin_params),
+            size_delta: (fresh.size - cached.size).as_f64(),
+            entry_price_delta: (fresh.entry_price - cached.entry_price).as_f64(),
+            detected_at: now,
+        };
+        warn!(
+            cache_divergence = divergence.health_factor_delta(),
+            "Position {} isn't liquidatable as the chain holds it: health factor {:.4} on chain, {:.4} cached, \
+             size {:+}, entry price {:+}; no longer liquidating it",
+            fresh.address,
+            divergence.chain_health_factor,
+            divergence.cached_health_factor,
+            divergence.size_delta,
+            divergence.entry_price_delta
+        );
+        let mut verification = self.verification.lock().unwrap_or_else(PoisonError::into_inner);
+        verification.divergences += 1;
+        verification.last_divergence = Some(divergence);
+    }
+    
+    /// Push an update for a monitored position whose account changed, if its
+    /// status did, rather than waiting for the next check cycle
+    async fn republish_position(&self, address: &PositionAddress) {
+        let update = match self.position_update(address).await {
+            Ok(update) => update,
+            Err(e) => {
+                warn!("Unable to update the status of {}: {}", address, e);
+                return;
+            }
+        };
+        let mut last_updates = self.last_updates.write().await;
+        if last_updates.get(address).is_some_and(|last| last.status == update.status) {
+            return;
+        }
+        if update.status == PositionStatus::AtRisk {
+            self.notify_webhook(WebhookPayload::AtRisk(update.clone()));
+        }
+        last_updates.insert(*address, update.clone());
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(EngineEvent::PositionUpdate(update));
+    }
+    
+    /// Bring a monitored position up to date with an event the program emitted
+    ///
+    /// Liquidations by other keepers start the position's cooldown as our own
+    /// do, and positions left without collateral or closed stop being
+    /// monitored.
+    pub async fn apply_program_event(&self, event: &ProgramEvent) {
+        let address = event.position();
+        {
+            let mut positions = self.positions.write().await;
+            let Some(position) = positions.get(&address) else {
+                return;
+            };
+            let mut pools = self.margin_pools.write().await;
+            match event.apply(position) {
+                Some(position) => {
+                    pools.insert(&position);
+                    positions.insert(address, position);
+                }
+                None => {
+                    pools.remove(&address);
+                    positions.remove(&address);
+                }
+            }
+        }
+
+        if let ProgramEvent::Deposited(_) = event {
+            self.republish_position(&address).await;
+        }
+        if let ProgramEvent::Liquidated(liquidation) = event
+            && !self.keys.contains(&liquidation.liquidator)
+        {
+            info!(
+                "Position {} liquidated by {}: {} repaid, {} collateral seized",
+                address, liquidation.liquidator, liquidation.repay_amount, liquidation.collateral_seized
+            );
+            self.mark_liquidated(&address, liquidation.timestamp, None).await;
+        }
+    }
+    
+    /// Apply an event the program logged in the transaction `signature`
+    ///
+    /// Our own liquidations are also published as liquidation events, unless
+    /// the submission path already published the same signature.
+    pub async fn apply_logged_event(&self, signature: &str, event: &ProgramEvent) {
+        let liquidation = match event {
+            ProgramEvent::Liquidated(liquidation) if self.keys.contains(&liquidation.liquidator) => {
+                self.logged_liquidation(signature, liquidation).await
+            }
+            _ => None,
+        };
+        self.apply_program_event(event).await;
+        if let Some(liquidation) = liquidation {
+            self.record_liquidation(&liquidation).await;
+        }
+    }
+
+    /// A liquidation event for our liquidation of a monitored position, as
+    /// the program logged it
+    async fn logged_liquidation(&self, signature: &str, logged: &PositionLiquidated) -> Option<LiquidationEvent> {
+        let position = self.positions.read().await.get(&PositionAddress::from(logged.position))?.clone();
+        let seized = amount::from_units(logged.collateral_seized, 0);
+        let repaid = amount::from_units(logged.repay_amount, 0);
+        let remaining_size = (position.size - seized).max(Amount::ZERO);
+        let liquidation_fee_bps = self.config().liquidation_fee_bps;
+        Some(LiquidationEvent {
+            position: position.address,
+            owner: position.owner,
+            liquidator: logged.liquidator,
+            amount: seized.min(position.size),
+            remaining_size,
+            remaining_margin: Amount::ZERO,
+            liquidation_price: repaid.checked_div(seized).filter(|_| seized > Amount::ZERO).unwrap_or(Amount::ZERO),
+            timestamp: logged.timestamp,
+            signature: signature.to_string(),
+            // Debt left on a position with no collateral is never repaid
+            bad_debt: if remaining_size > Amount::ZERO {
+                Amount::ZERO
+            } else {
+                amount::from_units(logged.remaining_debt, 0)
+            },
+            symbol: position.symbol.clone(),
+            reward: repaid * Amount::from(liquidation_fee_bps) / Amount::from(10_000),
+            dry_run: false,
+            error: None,
+            priority_fee_micro_lamports: 0,
+            metadata: position.metadata.clone(),
+        })
+    }
+
+    /// Add a position to be monitored
+    ///
+    /// A position already monitored is replaced, keeping the metadata entries
+    /// the new one doesn't set. Positions breaking the ingest rules (see
+    /// [`ingest_violations`](crate::ingest::ingest_violations)) are refused with every
+    /// rule they broke, or under [`IngestPolicy::Flag`] monitored as suspect.
+    /// Metadata isn't size-checked here; callers taking positions from outside
+    /// check [`Position::metadata_problem`].
+    pub async fn add_position(&self, position: Position) -> StdResult<(), IngestError> {
+        let flagged = self.screen_ingest(&position, true)?;
+        let mut positions = self.positions.write().await;
+        self.insert_position(&mut positions, position, flagged).await;
+        Ok(())
+    }
+    
+    /// Add or replace a monitored position as [`add_position`](Self::add_position)
+    /// does, unless the write is older than the version last written to it
+    ///
+    /// Older writes fail with [`LiquidationError::StaleUpdate`], leaving the
+    /// position as it was, and retries of a write already applied are ignored.
+    pub async fn write_position(&self, position: Position, write: &PositionWrite) -> StdResult<WriteOutcome, LiquidationError> {
+        self.write_ingested(position, write, true).await
+    }
+    
+    /// Write a position as [`write_position`](Self::write_position) does,
+    /// holding it to the ingest leverage cap only if `leverage_capped`
+    async fn write_ingested(
+        &self,
+        position: Position,
+        write: &PositionWrite,
+        leverage_capped: bool,
+    ) -> StdResult<WriteOutcome, LiquidationError> {
+        let flagged = self.screen_ingest(&position, leverage_capped)?;
+        let mut positions = self.positions.write().await;
+        let outcome = self.versions.lock().unwrap_or_else(PoisonError::into_inner).admit(position.address, write)?;
+        if outcome == WriteOutcome::Applied {
+            self.insert_position(&mut positions, position, flagged).await;
+        }
+        Ok(outcome)
+    }
+    
+    /// Hold a position about to be monitored to the ingest rules, returning
+    /// the rules it broke to flag it for under [`IngestPolicy::Flag`]
+    ///
+    /// Positions modelled from program accounts have no margin of their own,
+    /// so they're only held to the leverage cap if `leverage_capped`.
+    fn screen_ingest(
+        &self,
+        position: &Position,
+        leverage_capped: bool,
+    ) -> StdResult<Option<Vec<IngestViolation>>, IngestError> {
+        let config = self.config();
+        let max_leverage = config.max_ingest_leverage.filter(|_| leverage_capped);
+        let violations = ingest::ingest_violations(position, max_leverage);
+        if violations.is_empty() {
+            return Ok(None);
+        }
+        match config.ingest_policy {
+            IngestPolicy::Flag => {
+                warn!(
+                    "Monitoring position {} as suspect: {}",
+                    position.address,
+                    ingest::describe(&violations)
+                );
+                Ok(Some(violations))
+            }
+            IngestPolicy::Reject => {
+                self.suspects.lock().unwrap_or_else(PoisonError::into_inner).record_rejection();
+                let error = IngestError {
+                    address: position.address,
+                    violations,
+                };
+                warn!("{}", error);
+                Err(error)
+            }
+        }
+    }
+    
+    /// Version last written to a monitored position, if it was ever written
+    /// with one
+    pub fn position_version(&self, address: &PositionAddress) -> Option<u64> {
+        self.versions.lock().unwrap_or_else(PoisonError::into_inner).version(address)
+    }
+    
+    async fn insert_position(
+        &self,
+        positions: &mut HashMap<PositionAddress, Position>,
+        mut position: Position,
+        flagged: Option<Vec<IngestViolation>>,
+    ) {
+        if let Some(known) = positions.get(&position.address) {
+            position.merge_metadata(&known.metadata);
+        }
+        if let Some(violations) = flagged {
+            self.suspects.lock().unwrap_or_else(PoisonError::into_inner).flag(position.address, violations, self.now());
+        }
+        {
+            let mut retention = self.retention.lock().unwrap_or_else(PoisonError::into_inner);
+            if position.size > Amount::ZERO {
+                retention.forget(&position.address);
+            } else {
+                retention.finish(position.address, self.now());
+            }
+        }
+        self.margin_pools.write().await.insert(&position);
+        positions.insert(position.address, position);
+    }
+    
+    /// Remove a position from monitoring, returning it if it was monitored
+    pub async fn remove_position(&self, address: &PositionAddress) -> Option<Position> {
+        let mut positions = self.positions.write().await;
+        self.margin_pools.write().await.remove(address);
+        self.margin_calls.lock().unwrap_or_else(PoisonError::into_inner).clear(address);
+        self.versions.lock().unwrap_or_else(PoisonError::into_inner).forget(address);
+        self.quarantine.lock().unwrap_or_else(PoisonError::into_inner).release(address);
+        self.suspects.lock().unwrap_or_else(PoisonError::into_inner).clear(address);
+        self.retention.lock().unwrap_or_else(PoisonError::into_inner).forget(address);
+        positions.remove(address)
+    }
+    
+    /// Get a monitored position
+    pub async fn get_position(&self, address: &PositionAddress) -> Option<Position> {
+        self.positions.read().await.get(address).cloned()
+    }
+    
+    /// Remove a position from monitoring by its bare key
+    #[deprecated(since = "0.1.0", note = "use `remove_position` with a `PositionAddress`")]
+    pub async fn remove_position_by_pubkey(&self, address: &Pubkey) -> Option<Position> {
+        self.remove_position(&PositionAddress::from(*address)).await
+    }
+    
+    /// Get a monitored position by its bare key
+    #[deprecated(since = "0.1.0", note = "use `get_position` with a `PositionAddress`")]
+    pub async fn get_position_by_pubkey(&self, address: &Pubkey) -> Option<Position> {
+        self.get_position(&PositionAddress::from(*address)).await
+    }
+    
+    /// Audit records of every liquidation attempt on a position, oldest first,
+    /// including positions no longer monitored
+    pub async fn audit_records(&self, address: &PositionAddress) -> StdResult<Vec<AuditRecord>, LiquidationError> {
+        match &self.audit {
+            Some(audit) => audit.records_for(address).await,
+            None => Err(LiquidationError::ConfigError("audit log is disabled; set audit_log_path".to_string())),
+        }
+    }
+    
+    /// Get all monitored positions
+    pub async fn get_positions(&self) -> Vec<Position> {
+        self.positions.read().await.values().cloned().collect()
+    }
+    
+    /// Monitored positions whose metadata maps `key` to `value`, e.g. the one
+    /// with an exchange backend's id
+    pub async fn list_positions_by_metadata(&self, key: &str, value: &str) -> Vec<Position> {
+        self.positions
+            .read()
+            .await
+            .values()
+            .filter(|position| position.metadata.get(key).is_some_and(|found| found == value))
+            .cloned()
+            .collect()
+    }
+    
+    /// Snapshot of the monitored positions
+    pub async fn snapshot_positions(&self) -> PositionSnapshot {
+        PositionSnapshot::new(self.get_positions().await, self.now())
+    }
+    
+    /// Monitor the positions of a snapshot, returning how many it held
+    ///
+    /// The snapshot replaces the monitored positions, or with `merge` is added to
+    /// them, its positions replacing monitored ones with the same address. The
+    /// positions it holds start over without a version.
+    pub async fn restore_positions(&self, snapshot: PositionSnapshot, merge: bool) -> StdResult<usize, LiquidationError> {
+        snapshot.validate()?;
+        let count = snapshot.positions.len();
+        let mut positions = self.positions.write().await;
+        let mut pools = self.margin_pools.write().await;
+        let mut versions = self.versions.lock().unwrap_or_else(PoisonError::into_inner);
+        if !merge {
+            positions.clear();
+            pools.clear();
+            versions.clear();
+        }
+        for position in snapshot.positions {
+            versions.forget(&position.address);
+            pools.insert(&position);
+            positions.insert(position.address, position);
+        }
+        Ok(count)
+    }
+    
+    /// Write the monitored positions to a snapshot file, returning how many were written
+    pub async fn export_positions(&self, path: impl AsRef<Path>) -> StdResult<usize, LiquidationError> {
+        let snapshot = self.snapshot_positions().await;
+        snapshot.save(path)?;
+        Ok(snapshot.positions.len())
+    }
+    
+    /// Monitor the positions of a snapshot file, as [`restore_positions`](Self::restore_positions) does
+    pub async fn import_positions(&self, path: impl AsRef<Path>, merge: bool) -> StdResult<usize, LiquidationError> {
+        self.restore_positions(PositionSnapshot::load(path)?, merge).await
+    }
+    
+    /// Current status of a monitored position
+    ///
+    /// Positions that can't be priced are reported as active.
+    pub async fn position_status(&self, position: &Position) -> PositionStatus {
+        let pending = self.confirmations.is_pending(&position.address);
+        match self.oracle.get_price(&position.symbol).await {
+            Ok(index_price) => self.status_at(position, self.mark_price(&position.symbol, index_price).await, pending),
+            Err(_) if pending => PositionStatus::Liquidating,
+            Err(_) => PositionStatus::Active,
+        }
+    }
+    
+    /// Seconds until the recent drift of a position's symbol would carry its mark
+    /// price to its liquidation price, zero once past it and `None` while it
+    /// drifts away
+    ///
+    /// The drift is estimated from the prices the sanity check accepted.
+    fn time_to_liquidation(&self, update: &PositionUpdate) -> Option<f64> {
+        if update.distance_to_liquidation_bps? <= 0.0 {
+            return Some(0.0);
+        }
+        let history = self.price_sanity.lock().unwrap_or_else(PoisonError::into_inner).history(&update.symbol);
+        let liquidation_price = update.liquidation_price?.as_f64();
+        DriftEstimate::from_prices(&history)?.time_to_reach(update.mark_price.as_f64(), liquidation_price)
+    }
+    
+    /// Hourly funding rate each symbol last accrued at, none without a funding source
+    async fn funding_rates(&self) -> HashMap<String, f64> {
+        let indices = self.funding_indices.read().await;
+        indices.iter().map(|(symbol, index)| (symbol.clone(), index.rate_per_hour)).collect()
+    }
+    
+    /// Project `update`'s liquidation price over [`FUNDING_PROJECTION_HOURS`] of
+    /// funding at `funding_rate_per_hour`, its symbol's current rate if known
+    ///
+    /// An otherwise healthy position whose liquidation price the last projection
+    /// carries past the mark price is flagged at risk for its funding, so its
+    /// owner is warned while the price alone doesn't threaten it.
+    fn project_funding(
+        config: &LiquidationConfig,
+        position: &Position,
+        funding_rate_per_hour: Option<f64>,
+        update: &mut PositionUpdate,
+    ) {
+        let Some(funding_rate_per_hour) = funding_rate_per_hour else {
+            return;
+        };
+        let margin_params = config.margin_params_at(position, update.mark_price);
+        update.funding_projections = FUNDING_PROJECTION_HOURS
+            .iter()
+            .map(|&hours| {
+                let projected =
+                    position.projected_liquidation_price(f64::from(hours), funding_rate_per_hour, margin_params);
+                FundingProjection {
+                    hours,
+                    liquidation_price: config.displayed_liquidation_price(position, projected),
+                }
+            })
+            .collect();
+        
+        let reaches_mark = update
+            .funding_projections
+            .last()
+            .and_then(|projection| projection.liquidation_price)
+            .is_some_and(|price| {
+                if position.is_long { price >= update.mark_price } else { price <= update.mark_price }
+            });
+        if update.status == PositionStatus::Active && reaches_mark {
+            update.status = PositionStatus::AtRisk;
+            update.at_risk_reason = Some(AtRiskReason::Funding);
+        }
+    }
+    
+    /// Risk metrics of one monitored position at the latest prices, including its
+    /// health factor
+    pub async fn position_update(&self, address: &PositionAddress) -> StdResult<PositionUpdate, LiquidationError> {
+        let position = self
+            .get_position(address)
+            .await
+            .ok_or(LiquidationError::PositionNotFound(*address))?;
+        let mut positions = [position];
+        let prices = self.latest_prices(&positions).await;
+        self.revalue_collateral(&mut positions, &prices).await;
+        let [position] = positions;
+        
+        let index_price = self.oracle.get_price(&position.symbol).await?;
+        let price = self.mark_price(&position.symbol, index_price).await;
+        let pending = self.confirmations.is_pending(address);
+        let status = self.status_at(&position, price, pending);
+        let config = self.config();
+        let mut update = position.update(price, status, config.margin_params_at(&position, price), self.now());
+        update.adl_quantile = self.adl.read().await.quantile(&position);
+        update.exceeds_max_leverage = config.exceeds_max_leverage(&position);
+        update.liquidation_price = config.displayed_liquidation_price(&position, update.liquidation_price);
+        update.estimated_time_to_liquidation_secs = self.time_to_liquidation(&update);
+        let funding_rate = self.funding_rates().await.get(&position.symbol).copied();
+        Self::project_funding(&config, &position, funding_rate, &mut update);
+        Ok(update)
+    }
+    
+    /// Auto-deleveraging queue of a symbol as ranked at the last check cycle,
+    /// if any of its positions were priced
+    pub async fn get_adl_queue(&self, symbol: &str) -> Option<AdlQueue> {
+        self.adl.read().await.queue(symbol).cloned()
+    }
+    
+    /// Plan how `bad_debt` of a bankrupt position in `symbol` would be absorbed
+    /// by auto-deleveraging the last check cycle's queue, once the insurance fund
+    /// left after the bad debt already absorbed runs out
+    ///
+    /// Nothing is deleveraged; the plan is only computed.
+    pub async fn plan_adl(&self, symbol: &str, bankrupt_is_long: bool, bad_debt: f64) -> Option<AdlPlan> {
+        let insurance_fund = self.config().insurance_fund_balance - self.get_insurance_stats().await.total_bad_debt;
+        self.adl.read().await.plan(symbol, bankrupt_is_long, bad_debt, insurance_fund)
+    }
+    
+    /// Status of a position at the given price: at risk below the configured
+    /// health factor
+    fn status_at(&self, position: &Position, price: Amount, pending_liquidation: bool) -> PositionStatus {
+        let config = self.config();
+        if pending_liquidation {
+            PositionStatus::Liquidating
+        } else if position.health_factor(price, config.margin_params_at(position, price)) < config.at_risk_health_factor {
+            PositionStatus::AtRisk
+        } else {
+            PositionStatus::Active
+        }
+    }
+    
+    /// Check one monitored position immediately, outside the regular cycle
+    pub async fn check_position_now(
+        &self,
+        address: &PositionAddress,
+    ) -> StdResult<Option<LiquidationResult>, LiquidationError> {
+        let position = self
+            .get_position(address)
+            .await
+            .ok_or(LiquidationError::PositionNotFound(*address))?;
+        let mut positions = [position];
+        let prices = self.latest_prices(&positions).await;
+        self.revalue_collateral(&mut positions, &prices).await;
+        let [position] = positions;
+        self.check_position(position).await
+    }
+    
+    /// Replay recorded prices from `from_ts` to `to_ts`, running a check cycle every `step_secs`
+    ///
+    /// Needs a clock that can be set (see [`with_clock`](Self::with_clock)), which each step
+    /// moves forward, so cooldowns and bad debt windows pass in virtual time without
+    /// waiting. From the first step on the engine only ever dry runs, and replayed
+    /// liquidations shrink the monitored positions as the program would.
+    pub async fn run_replay(&self, from_ts: i64, to_ts: i64, step_secs: u64) -> StdResult<ReplayReport, LiquidationError> {
+        let step = i64::try_from(step_secs)
+            .ok()
+            .filter(|step| *step > 0)
+            .ok_or_else(|| LiquidationError::ConfigError(format!("invalid replay step of {} seconds", step_secs)))?;
+        if to_ts < from_ts {
+            return Err(LiquidationError::ConfigError(format!(
+                "replay ends at {} before it starts at {}",
+                to_ts, from_ts
+            )));
+        }
+        self.clock.set_ts(from_ts)?;
+        self.replaying.store(true, Ordering::Relaxed);
+        
+        // Prices of closed positions keep being followed, so drawdowns aren't cut short
+        let watched = self.get_positions().await;
+        let mut report = ReplayReport::new(from_ts, to_ts, step_secs);
+        let mut next = Some(from_ts);
+        while let Some(now) = next.filter(|now| *now <= to_ts) {
+            self.clock.set_ts(now)?;
+            let prices = self.latest_prices(&watched).await;
+            report.record_step(now, &prices);
+            for result in self.check_positions().await? {
+                let notional = match &result {
+                    LiquidationResult::Success { position, amount, .. } => {
+                        self.settle_replayed(position, amount::from_f64_lossy(*amount), &prices).await
+                    }
+                    _ => 0.0,
+                };
+                report.record_result(now, result, notional);
+            }
+            next = now.checked_add(step);
+        }
+        
+        info!(
+            "Replayed {} steps: {} liquidations of {:.2} notional, {} failed, {} skipped",
+            report.steps, report.liquidations, report.notional, report.failures, report.skipped
+        );
+        Ok(report)
+    }
+    
+    /// Apply a replayed liquidation of `amount` to a monitored position, closing it
+    /// if nothing remains, and return the notional liquidated at `prices`
+    async fn settle_replayed(
+        &self,
+        address: &PositionAddress,
+        amount: Amount,
+        prices: &HashMap<String, Amount>,
+    ) -> f64 {
+        let mut positions = self.positions.write().await;
+        let Some(position) = positions.get_mut(address) else {
+            return 0.0;
+        };
+        let notional = (amount * prices.get(&position.symbol).copied().unwrap_or_default()).as_f64();
+        if amount < position.size {
+            position.reduce(amount / position.size);
+        } else {
+            positions.remove(address);
+            self.margin_pools.write().await.remove(address);
+        }
+        notional
+    }
+    
+    /// Get the engine's current configuration
+    pub fn config(&self) -> Arc<LiquidationConfig> {
+        self.config.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+    
+    /// Validate a configuration update and stage it for the next check cycle
+    ///
+    /// Returns the configuration that will take effect. Updates staged before the
+    /// next cycle are combined.
+    pub fn update_config(&self, update: &ConfigUpdate) -> StdResult<LiquidationConfig, LiquidationError> {
+        self.stage_config(|config| config.with_update(update))
+    }
+    
+    /// Stage a configuration reloaded from file for the next check cycle,
+    /// returning the changes that will take effect
+    ///
+    /// Fields only read at startup keep their current values, with a warning
+    /// that changing them requires a restart. Invalid configurations are
+    /// rejected, leaving the current one in effect.
+    pub fn reload_config(&self, reloaded: &LiquidationConfig) -> StdResult<Vec<ConfigChange>, LiquidationError> {
+        let mut changes = Vec::new();
+        self.stage_config(|current| {
+            reloaded.validate()?;
+            let (config, left_out) = current.hot_reload(reloaded);
+            config.validate()?;
+            for change in left_out {
+                warn!("Ignoring reloaded {}: changing it requires a restart", change);
+            }
+            changes = current.diff(&config);
+            Ok(config)
+        })?;
+        for change in &changes {
+            info!("Reloaded configuration: {}", change);
+        }
+        Ok(changes)
+    }
+    
+    /// Markets of the configuration that will be in effect at the next check
+    /// cycle
+    pub fn markets(&self) -> Vec<MarketConfig> {
+        let pending = self.pending_config.lock().unwrap_or_else(PoisonError::into_inner);
+        match pending.as_ref() {
+            Some(config) => config.markets.clone(),
+            None => self.config().markets.clone(),
+        }
+    }
+    
+    /// Add a market, or replace the one in its symbol, from the next check cycle
+    ///
+    /// Returns the configuration that will take effect.
+    pub fn upsert_market(&self, market: MarketConfig) -> StdResult<LiquidationConfig, LiquidationError> {
+        self.stage_config(|config| config.with_market(market))
+    }
+    
+    /// Stop treating a symbol as a market from the next check cycle, returning
+    /// the removed market if it had one
+    pub fn remove_market(&self, symbol: &str) -> StdResult<Option<MarketConfig>, LiquidationError> {
+        let mut removed = None;
+        self.stage_config(|config| {
+            let (config, market) = config.without_market(symbol);
+            removed = market;
+            Ok(config)
+        })?;
+        Ok(removed)
+    }
+    
+    /// Stage the configuration `change` derives from the one that will be in
+    /// effect at the next check cycle
+    fn stage_config(
+        &self,
+        change: impl FnOnce(&LiquidationConfig) -> StdResult<LiquidationConfig, LiquidationError>,
+    ) -> StdResult<LiquidationConfig, LiquidationError> {
+        let mut pending = self.pending_config.lock().unwrap_or_else(PoisonError::into_inner);
+        let base = match pending.as_ref() {
+            Some(config) => config.clone(),
+            None => (*self.config()).clone(),
+        };
+        let config = change(&base)?;
+        *pending = Some(config.clone());
+        Ok(config)
+    }
+    
+    /// Switch to a staged configuration, if any
+    fn apply_pending_config(&self) {
+        let pending = self.pending_config.lock().unwrap_or_else(PoisonError::into_inner).take();
+        if let Some(config) = pending {
+            info!("Applying configuration update: {:?}", config);
+            *self.config.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(config);
+        }
+    }
+}
+
+/// Assembles a [`LiquidationEngine`], checking that its pieces fit together
+///
+/// The RPC client and oracle are required; everything else has a default. Each
+/// builder builds one engine, since the keypairs and store move into it.
+#[derive(Default)]
+pub struct LiquidationEngineBuilder {
+    rpc: Option<RpcPool>,
+    oracle: Option<Arc<dyn OracleProvider + Send + Sync>>,
+    config: Option<LiquidationConfig>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    keypairs: Vec<Keypair>,
+    clock: Option<Arc<dyn Clock>>,
+    event_capacity: Option<usize>,
+    #[cfg(feature = "storage")]
+    store: Option<StoreWriter>,
+    report: Option<ReportWriter>,
+    audit: Option<AuditWriter>,
+    webhooks: Option<WebhookNotifier>,
+    alerts: Option<Alerter>,
+    systemd: Option<SystemdNotifier>,
+    liquidation_markets: BTreeMap<Pubkey, LiquidationMarket>,
+    built: bool,
+}
+
+impl LiquidationEngine {
+    /// Start assembling an engine
+    pub fn builder() -> LiquidationEngineBuilder {
+        LiquidationEngineBuilder::default()
+    }
+}
+
+impl LiquidationEngineBuilder {
+    /// RPC endpoints for Solana: a single client or URL, or an [`RpcPool`] (required)
+    pub fn rpc_client(&mut self, rpc: impl Into<RpcPool>) -> &mut Self {
+        self.rpc = Some(rpc.into());
+        self
+    }
+    
+    /// Oracle for price feeds (required)
+    pub fn oracle(&mut self, oracle: Arc<dyn OracleProvider + Send + Sync>) -> &mut Self {
+        self.oracle = Some(oracle);
+        self
+    }
+    
+    /// Configuration parameters (default: [`LiquidationConfig::default`])
+    pub fn config(&mut self, config: LiquidationConfig) -> &mut Self {
+        self.config = Some(config);
+        self
+    }
+    
+    /// Paces RPC requests (default: a limiter built from the configuration's `rate_limit`)
+    pub fn rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>) -> &mut Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+    
+    /// Keypair paying for and signing liquidation transactions (required unless dry running)
+    ///
+    /// Each call adds a key to the pool the configured `key_pool.rotation` picks from.
+    pub fn keypair(&mut self, keypair: Keypair) -> &mut Self {
+        self.keypairs.push(keypair);
+        self
+    }
+    
+    /// Keypairs paying for and signing liquidation transactions, added to the pool
+    pub fn keypairs(&mut self, keypairs: impl IntoIterator<Item = Keypair>) -> &mut Self {
+        self.keypairs.extend(keypairs);
+        self
+    }
+    
+    /// Time cooldowns, funding and bad debt windows are judged by (default: [`SystemClock`])
+    pub fn clock(&mut self, clock: Arc<dyn Clock>) -> &mut Self {
+        self.clock = Some(clock);
+        self
+    }
+    
+    /// Events buffered per subscriber (default: [`EVENT_CHANNEL_CAPACITY`])
+    pub fn event_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.event_capacity = Some(capacity);
+        self
+    }
+    
+    /// Record every liquidation attempt through the given store writer
+    #[cfg(feature = "storage")]
+    pub fn store(&mut self, store: StoreWriter) -> &mut Self {
+        self.store = Some(store);
+        self
+    }
+    
+    /// Report every liquidation a dry run would have made through the given writer
+    pub fn report(&mut self, report: ReportWriter) -> &mut Self {
+        self.report = Some(report);
+        self
+    }
+    
+    /// Audit every liquidation attempt through the given writer
+    pub fn audit(&mut self, audit: AuditWriter) -> &mut Self {
+        self.audit = Some(audit);
+        self
+    }
+    
+    /// Notify owners' webhooks through the given notifier
+    pub fn webhooks(&mut self, webhooks: WebhookNotifier) -> &mut Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+    
+    /// Alert operators through the given alerter
+    pub fn alerts(&mut self, alerts: Alerter) -> &mut Self {
+        self.alerts = Some(alerts);
+        self
+    }
+    
+    /// Notify systemd of readiness, liveness and shutdown through the given
+    /// notifier (see [`SystemdNotifier::from_env`])
+    pub fn systemd(&mut self, systemd: SystemdNotifier) -> &mut Self {
+        self.systemd = Some(systemd);
+        self
+    }
+    
+    /// Markets liquidations are built against, by address, as
+    /// [`PreflightReport::markets`](crate::preflight::PreflightReport::markets) loaded them
+    /// (default: each read from its program when first needed)
+    pub fn liquidation_markets(&mut self, markets: BTreeMap<Pubkey, LiquidationMarket>) -> &mut Self {
+        self.liquidation_markets = markets;
+        self
+    }
+    
+    /// Build the engine, or report the first piece that's missing or doesn't fit
+    pub fn build(&mut self) -> StdResult<LiquidationEngine, LiquidationError> {
+        let invalid = |problem: &str| LiquidationError::ConfigError(format!("LiquidationEngineBuilder {}", problem));
+        if self.built {
+            return Err(invalid("was already built; use a new builder for another engine"));
+        }
+        let rpc = self.rpc.clone().ok_or_else(|| invalid("is missing rpc_client"))?;
+        let oracle = self.oracle.clone().ok_or_else(|| invalid("is missing oracle"))?;
+        let config = self.config.clone().unwrap_or_default();
+        config.validate()?;
+        if !config.dry_run && self.keypairs.is_empty() {
+            return Err(invalid("is missing keypair, which is required unless dry_run is set"));
+        }
+        let event_capacity = self.event_capacity.unwrap_or(EVENT_CHANNEL_CAPACITY);
+        if event_capacity == 0 {
+            return Err(invalid("event_capacity must be positive"));
+        }
+        
+        self.built = true;
+        let rate_limiter = self
+            .rate_limiter
+            .take()
+            .unwrap_or_else(|| Arc::new(RateLimiter::new(config.rate_limit.clone())));
+        let mut engine = LiquidationEngine::new(rpc, oracle, config, rate_limiter);
+        engine.keys = KeyPool::new(std::mem::take(&mut self.keypairs));
+        engine.events = broadcast::channel(event_capacity).0;
+        engine.event_capacity = event_capacity;
+        if let Some(clock) = self.clock.take() {
+            engine = engine.with_clock(clock);
+        }
+        #[cfg(feature = "storage")]
+        {
+            engine.store = self.store.take();
+        }
+        engine.report = self.report.take();
+        engine.audit = self.audit.take();
+        engine.webhooks = self.webhooks.take();
+        engine.alerts = self.alerts.take();
+        engine.systemd = self.systemd.take();
+        engine.liquidation_markets = std::sync::RwLock::new(std::mem::take(&mut self.liquidation_markets));
+        Ok(engine)
+    }
+}
+
+/// Error reported for each liquidation of a batch that failed with `e` before
+/// any of them could be told apart
+fn batch_error(e: &LiquidationError) -> LiquidationError {
+    match e {
+        LiquidationError::ConfirmationTimeout => LiquidationError::ConfirmationTimeout,
+        LiquidationError::SubmissionsDisabled(mode) => LiquidationError::SubmissionsDisabled(*mode),
+        e => LiquidationError::LiquidationFailed(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::alert::{DEFAULT_ALERT_QUEUE_CAPACITY, Notifier};
+    use crate::compute::MockSimulator;
+    use crate::confirm::{MockStatusPoller, PENDING_SIGNATURE_EXPIRY_SECS, SignatureStatus};
+    use crate::fee::{MockFeeSource, PriorityFeeStrategy};
+    use crate::funding::FixedRateFunding;
+    use crate::mark::MockBasisSource;
+    use crate::health::{MintDecimals, POSITION_ACCOUNT_LEN};
+    use crate::instruction::POSITION_HEALTHY_ERROR;
+    use crate::nonce::NonceConfig;
+    use crate::oracle::{ConfidencePolicy, MockOracle, MockOracleProvider, PythOracle};
+    use crate::position::{CollateralBalance, MarginParams};
+    use crate::priority::PriorityWeights;
+    use crate::replay::{REPLAY_CSV_HEADER, ReplayOracle};
+    use crate::rounding::SymbolSpec;
+    use crate::sanity::PriceSanityConfig;
+    use crate::stats::SideNotional;
+    use crate::types::EnginePhase;
+    use crate::submit::MockSubmitter;
+    use serde_json::json;
+    use solana_account_decoder::{UiAccount, UiAccountEncoding};
+    use solana_client::client_error::{ClientErrorKind, Result as ClientResult};
+    use solana_client::rpc_client::{RpcClient, RpcClientConfig};
+    use solana_client::rpc_request::RpcRequest;
+    use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+    use solana_sdk::nonce::state::{Data, DurableNonce, State, Versions};
+    use solana_sdk::system_instruction;
+    use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+    use std::collections::{BTreeMap, VecDeque};
+    
+    fn create_engine(config: LiquidationConfig) -> LiquidationEngine {
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        LiquidationEngine::new(rpc_client, Arc::new(MockOracle::new()), config, Arc::new(RateLimiter::default()))
+    }
+    
+    fn create_test_position() -> Position {
+        // 10x long, liquidatable below ~56,842 at 5% maintenance margin
+        Position::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            "BTC/USD",
+            1.0,
+            60000.0,
+            6000.0,
+            true,
+        )
+    }
+    
+    #[test]
+    fn test_engine_initialization() {
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let rate_limiter = Arc::new(RateLimiter::default());
+        let oracle = Arc::new(PythOracle::new(
+            "https://api.devnet.solana.com",
+            HashMap::new(),
+            None,
+            rate_limiter.clone(),
+        ));
+        
+        let config = LiquidationConfig::default();
+        let engine = LiquidationEngine::new(rpc_client, oracle, config, rate_limiter);
+        
+        assert_eq!(engine.positions.blocking_read().len(), 0);
+    }
+    
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn test_pubkey_shims_match_typed_accessors() {
+        let engine = create_engine(LiquidationConfig::default());
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        
+        let address = position.address.pubkey();
+        let monitored = engine.get_position(&position.address).await;
+        assert!(monitored.is_some());
+        assert_eq!(engine.get_position_by_pubkey(&address).await, monitored);
+        assert!(engine.get_position_by_pubkey(&position.owner.pubkey()).await.is_none());
+        assert_eq!(engine.remove_position_by_pubkey(&address).await, monitored);
+        assert!(engine.get_position(&position.address).await.is_none());
+        assert!(engine.remove_position_by_pubkey(&address).await.is_none());
+    }
+    
+    #[test]
+    fn test_twap_confirmation_blocks_wick() {
+        let position = create_test_position();
+        let mut price_data = PriceData::from_price(55000.0, 0);
+        price_data.ema_price = 59000.0;
+        
+        // Spot alone is enough without confirmation
+        let engine = create_engine(LiquidationConfig::default());
+        assert!(engine.should_liquidate(&position, &price_data, None));
+        
+        // With confirmation, the healthy EMA blocks the wick
+        let engine = create_engine(LiquidationConfig {
+            require_twap_confirmation: true,
+            ..Default::default()
+        });
+        assert!(!engine.should_liquidate(&position, &price_data, None));
+    }
+    
+    #[test]
+    fn test_twap_confirmation_allows_underwater() {
+        let position = create_test_position();
+        let mut price_data = PriceData::from_price(55000.0, 0);
+        price_data.ema_price = 55500.0;
+        
+        let engine = create_engine(LiquidationConfig {
+            require_twap_confirmation: true,
+            ..Default::default()
+        });
+        assert!(engine.should_liquidate(&position, &price_data, None));
+        
+        // A healthy spot price is never liquidated, whatever the EMA says
+        price_data.price = 59000.0;
+        assert!(!engine.should_liquidate(&position, &price_data, None));
+    }
+    
+    #[tokio::test]
+    async fn test_funding_accrual_makes_position_liquidatable() {
+        let engine = create_engine(LiquidationConfig::default())
+            .with_funding_source(Arc::new(FixedRateFunding::new(0.002)));
+        let position = create_test_position();
+        let address = position.address;
+        engine.add_position(position).await.unwrap();
+        
+        let price_data = PriceData::from_price(57500.0, 0);
+        let start = 1_700_000_000;
+        engine.accrue_funding(start).await;
+        let position = engine.positions.read().await[&address].clone();
+        assert_eq!(position.last_funding_settlement, Some(start));
+        assert!(!engine.should_liquidate(&position, &price_data, None));
+        
+        // 8 hours of 0.2%/h funding against the long
+        engine.accrue_funding(start + 8 * 3600).await;
+        let position = engine.positions.read().await[&address].clone();
+        assert!((position.unsettled_funding - 960.0).abs() < 1e-6);
+        assert!(engine.should_liquidate(&position, &price_data, None));
+    }
+    
+    async fn create_funded_engine(funding_rate_per_hour: f64) -> LiquidationEngine {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 60000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let config = LiquidationConfig::default();
+        LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_funding_source(Arc::new(FixedRateFunding::new(funding_rate_per_hour)))
+    }
+    
+    #[tokio::test]
+    async fn test_funding_projection_flags_healthy_position_at_risk() {
+        // 0.3%/h against the long costs 4,320 of its 6,000 margin over a day
+        let engine = create_funded_engine(0.003).await;
+        let mut events = engine.subscribe();
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        
+        engine.check_positions().await.unwrap();
+        let update = match events.try_recv().unwrap() {
+            EngineEvent::PositionUpdate(update) => update,
+            other => panic!("unexpected event: {:?}", other),
+        };
+        // A health factor of 2 at the entry price, but a day's funding carries the
+        // liquidation price to (60,000 - 1,680) / 0.95 ≈ 61,389
+        assert!(update.health_factor.unwrap() > 1.9);
+        assert_eq!(update.status, PositionStatus::AtRisk);
+        assert_eq!(update.at_risk_reason, Some(AtRiskReason::Funding));
+        let hours: Vec<u32> = update.funding_projections.iter().map(|projection| projection.hours).collect();
+        assert_eq!(hours, FUNDING_PROJECTION_HOURS);
+        // (60,000 - 5,820) / 0.95 and (60,000 - 4,560) / 0.95 fall short of the mark price
+        let projected: Vec<f64> = update.funding_projections.iter().filter_map(|p| p.liquidation_price).collect();
+        assert_eq!(projected.len(), 3);
+        assert!((projected[0] - 54180.0 / 0.95).abs() < 1.0);
+        assert!((projected[1] - 55440.0 / 0.95).abs() < 1.0);
+        assert!((projected[2] - 58320.0 / 0.95).abs() < 1.0);
+        
+        // The same position receiving funding is healthy, and projects nothing
+        let engine = create_funded_engine(-0.003).await;
+        engine.add_position(position.clone()).await.unwrap();
+        engine.check_positions().await.unwrap();
+        let update = engine.position_update(&position.address).await.unwrap();
+        assert_eq!(update.status, PositionStatus::Active);
+        assert_eq!(update.at_risk_reason, None);
+        assert!(update.funding_projections.iter().all(|projection| projection.liquidation_price.is_none()));
+    }
+    
+    async fn create_cross_margin_engine() -> LiquidationEngine {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        oracle.set_price("ETH/USD", 3500.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let config = LiquidationConfig {
+            whitelisted_symbols: vec!["BTC/USD".to_string(), "ETH/USD".to_string()],
+            ..Default::default()
+        };
+        LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+    }
+    
+    #[tokio::test]
+    async fn test_cross_margin_winner_backs_loser() {
+        let engine = create_cross_margin_engine().await;
+        let owner = Pubkey::new_unique();
+        // Down 5000 at 55,000 and underwater on its own, but the ETH long is up 5000
+        let loser = Position::new(Pubkey::new_unique(), owner, "BTC/USD", 1.0, 60000.0, 3000.0, true)
+            .with_margin_mode(MarginMode::Cross);
+        let winner = Position::new(Pubkey::new_unique(), owner, "ETH/USD", 10.0, 3000.0, 3000.0, true)
+            .with_margin_mode(MarginMode::Cross);
+        engine.add_position(loser.clone()).await.unwrap();
+        engine.add_position(winner.clone()).await.unwrap();
+        
+        assert!(engine.check_positions().await.unwrap().is_empty());
+        assert!(engine.check_position_now(&loser.address).await.unwrap().is_none());
+        
+        // Isolated, the winner's profit stops backing the loser
+        engine.add_position(winner.clone().with_margin_mode(MarginMode::Isolated)).await.unwrap();
+        let results = engine.check_positions().await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(&results[0], LiquidationResult::Success { position, .. } if *position == loser.address));
+        
+        // Nor does a removed position
+        let engine = create_cross_margin_engine().await;
+        engine.add_position(loser.clone()).await.unwrap();
+        engine.add_position(winner.clone()).await.unwrap();
+        engine.remove_position(&winner.address).await;
+        let result = engine.check_position_now(&loser.address).await.unwrap();
+        assert!(matches!(result, Some(LiquidationResult::Success { .. })));
+    }
+    
+    #[tokio::test]
+    async fn test_cross_liquidation_rechecks_siblings() {
+        let engine = create_cross_margin_engine().await;
+        let owner = Pubkey::new_unique();
+        let positions: Vec<Position> = (0..2)
+            .map(|_| {
+                Position::new(Pubkey::new_unique(), owner, "BTC/USD", 1.0, 60000.0, 3000.0, true)
+                    .with_margin_mode(MarginMode::Cross)
+            })
+            .collect();
+        for position in &positions {
+            engine.add_position(position.clone()).await.unwrap();
+        }
+        let mut events = engine.subscribe();
+        
+        // Liquidating one position of the underwater pool checks the other straight away
+        let result = engine.check_position_now(&positions[0].address).await.unwrap();
+        assert!(matches!(result, Some(LiquidationResult::Success { .. })));
+        let mut liquidated = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let EngineEvent::Liquidation(event) = event {
+                liquidated.push(event.position);
+            }
+        }
+        assert_eq!(liquidated, [positions[0].address, positions[1].address]);
+        assert!(engine.get_position(&positions[1].address).await.unwrap().last_liquidated.is_some());
+    }
+    
+    #[tokio::test]
+    async fn test_cooldown_follows_clock() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        let clock = ManualClock::new(1_700_000_000);
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(
+            rpc_client,
+            Arc::new(oracle),
+            LiquidationConfig::default(),
+            Arc::new(RateLimiter::default()),
+        )
+        .with_clock(Arc::new(clock.clone()));
+        let position = create_test_position();
+        let address = position.address;
+        engine.add_position(position).await.unwrap();
+        
+        let check = || async { engine.check_position_now(&address).await.unwrap() };
+        assert!(matches!(check().await, Some(LiquidationResult::Success { .. })));
+        assert_eq!(engine.get_position(&address).await.unwrap().last_liquidated, Some(1_700_000_000));
+        
+        // Held back until min_liquidation_interval_secs have passed on the clock
+        clock.advance(299);
+        assert!(matches!(check().await, Some(LiquidationResult::Skipped { reason: SkipReason::Cooldown, .. })));
+        clock.advance(1);
+        assert!(matches!(check().await, Some(LiquidationResult::Success { .. })));
+        assert_eq!(engine.get_position(&address).await.unwrap().last_liquidated, Some(1_700_000_300));
+    }
+    
+    #[tokio::test]
+    async fn test_grown_position_bypasses_cooldown() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        let clock = ManualClock::new(1_700_000_000);
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let config = LiquidationConfig {
+            cooldown_bypass_delta: Some(0.01),
+            ..LiquidationConfig::default()
+        };
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_clock(Arc::new(clock.clone()));
+        let position = create_test_position();
+        let address = position.address;
+        engine.add_position(position).await.unwrap();
+        
+        let check = || async { engine.check_position_now(&address).await.unwrap() };
+        assert!(matches!(check().await, Some(LiquidationResult::Success { .. })));
+        let liquidated = engine.get_position(&address).await.unwrap();
+        assert_eq!(liquidated.last_liquidated_size, Some(1.0));
+        assert!((liquidated.last_liquidated_margin_ratio.unwrap() - 1000.0 / 55000.0).abs() < 1e-12);
+        
+        // Unchanged, the position waits out its cooldown
+        clock.advance(60);
+        assert!(matches!(check().await, Some(LiquidationResult::Skipped { reason: SkipReason::Cooldown, .. })));
+        
+        // Adding to it at the same leverage leaves its margin ratio as it was
+        engine
+            .add_position(Position {
+                size: 2.0,
+                margin: 12000.0,
+                ..liquidated
+            })
+            .await.unwrap();
+        assert!(matches!(check().await, Some(LiquidationResult::Success { .. })));
+        assert_eq!(engine.get_position(&address).await.unwrap().last_liquidated, Some(1_700_000_060));
+    }
+    
+    #[tokio::test]
+    async fn test_memory_returns_to_baseline_after_positions_liquidated() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        let clock = ManualClock::new(1_700_000_000);
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let config = LiquidationConfig {
+            enable_partial_liquidations: false,
+            ..LiquidationConfig::default()
+        };
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_clock(Arc::new(clock.clone()));
+        
+        // 10k positions opened and fully liquidated over a few hours
+        for _ in 0..10 {
+            let positions: Vec<Position> = (0..1_000).map(|_| create_test_position()).collect();
+            for position in &positions {
+                engine.add_position(position.clone()).await.unwrap();
+            }
+            for position in &positions {
+                let result = engine.check_position_now(&position.address).await.unwrap();
+                assert!(matches!(result, Some(LiquidationResult::Success { .. })));
+            }
+            engine.check_positions().await.unwrap();
+            clock.advance(600);
+        }
+        let memory = engine.memory_stats().await;
+        assert_eq!((memory.positions, memory.finished_positions), (10_000, 10_000));
+        assert!(memory.position_updates > 0);
+        
+        // Once the last of them is out of its retention, nothing is left of them
+        clock.advance(3_600);
+        engine.check_positions().await.unwrap();
+        let memory = engine.memory_stats().await;
+        assert_eq!(memory.positions, 0);
+        assert_eq!(memory.finished_positions, 0);
+        assert_eq!(memory.evicted_positions, 10_000);
+        assert_eq!((memory.position_updates, memory.last_checked), (0, 0));
+        assert_eq!((memory.price_histories, memory.mark_price_averages), (0, 0));
+    }
+    
+    /// Prices every symbol at one price, published at a fixed time and slot
+    #[derive(Debug)]
+    struct SlottedOracle {
+        price: f64,
+        publish_time: i64,
+        publish_slot: u64,
+    }
+    
+    #[async_trait::async_trait]
+    impl OracleProvider for SlottedOracle {
+        async fn get_price(&self, _symbol: &str) -> StdResult<f64, LiquidationError> {
+            Ok(self.price)
+        }
+        
+        async fn get_price_data(&self, _symbol: &str) -> StdResult<PriceData, LiquidationError> {
+            let mut data = PriceData::from_price(self.price, self.publish_time);
+            data.publish_slot = Some(self.publish_slot);
+            Ok(data)
+        }
+    }
+    
+    fn create_slotted_engine(clock: &ManualClock) -> LiquidationEngine {
+        let oracle = SlottedOracle {
+            price: 55000.0,
+            publish_time: 1_700_000_000,
+            publish_slot: 1_000,
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let config = LiquidationConfig::default();
+        LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_clock(Arc::new(clock.clone()))
+    }
+    
+    #[tokio::test]
+    async fn test_prices_aged_by_slot_not_clock() {
+        // The host's clock ran an hour ahead of the oracle, but slots keep coming
+        let clock = ManualClock::new(1_700_003_600);
+        let engine = create_slotted_engine(&clock);
+        engine.add_position(create_test_position()).await.unwrap();
+        engine.slot_tracker().observe(1_100, clock.now_ts());
+        let results = engine.check_positions().await.unwrap();
+        assert!(matches!(results[..], [LiquidationResult::Success { .. }]), "{:?}", results);
+        
+        // On time by the clock, but 200 slots behind the cluster
+        let clock = ManualClock::new(1_700_000_000);
+        let engine = create_slotted_engine(&clock);
+        engine.add_position(create_test_position()).await.unwrap();
+        engine.slot_tracker().observe(1_200, clock.now_ts());
+        match &engine.check_positions().await.unwrap()[..] {
+            [LiquidationResult::Skipped { reason: SkipReason::PriceUnavailable(reason), .. }] => {
+                assert_eq!(reason, "Stale price for BTC/USD: published 200 slots ago, max 150")
+            }
+            other => panic!("expected the stale price to skip the position, got {:?}", other),
+        }
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        let result = engine.check_position_now(&position.address).await;
+        assert!(matches!(result, Err(LiquidationError::StalePriceSlot { age_slots: 200, .. })), "{:?}", result);
+        
+        // Without a slot to judge by, only the oracle's own age check applies
+        let engine = create_slotted_engine(&clock);
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        let result = engine.check_position_now(&position.address).await.unwrap();
+        assert!(matches!(result, Some(LiquidationResult::Success { .. })));
+    }
+    
+    #[tokio::test]
+    async fn test_stalled_slot_holds_liquidations_back() {
+        let clock = ManualClock::new(1_700_000_000);
+        let engine = create_slotted_engine(&clock);
+        let position = create_test_position();
+        let address = position.address;
+        engine.add_position(position).await.unwrap();
+        let slots = engine.slot_tracker();
+        slots.observe(1_010, clock.now_ts());
+        
+        // The node keeps answering, with a slot that no longer advances
+        clock.advance(31);
+        slots.observe(1_010, clock.now_ts());
+        assert!(engine.slot_status().stalled);
+        match engine.check_position_now(&address).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason: SkipReason::SlotStalled { advanced_at }, .. }) => {
+                assert_eq!(advanced_at, 1_700_000_000)
+            }
+            other => panic!("expected the stalled slot to hold the liquidation back, got {:?}", other),
+        }
+        
+        slots.observe(1_011, clock.now_ts());
+        assert!(!engine.slot_status().stalled);
+        assert!(matches!(engine.check_position_now(&address).await.unwrap(), Some(LiquidationResult::Success { .. })));
+    }
+    
+    /// A builder with the required pieces
+    fn create_builder() -> LiquidationEngineBuilder {
+        let mut builder = LiquidationEngine::builder();
+        builder.rpc_client("https://api.devnet.solana.com").oracle(Arc::new(MockOracle::new()));
+        builder
+    }
+    
+    fn build_error(builder: &mut LiquidationEngineBuilder) -> String {
+        match builder.build() {
+            Err(LiquidationError::ConfigError(message)) => message,
+            Err(e) => panic!("expected a configuration error, got {:?}", e),
+            Ok(_) => panic!("expected the build to fail"),
+        }
+    }
+    
+    #[test]
+    fn test_builder_requires_rpc_and_oracle() {
+        let mut builder = LiquidationEngine::builder();
+        assert_eq!(build_error(&mut builder), "LiquidationEngineBuilder is missing rpc_client");
+        builder.rpc_client("https://api.devnet.solana.com");
+        assert_eq!(build_error(&mut builder), "LiquidationEngineBuilder is missing oracle");
+        builder.oracle(Arc::new(MockOracle::new()));
+        assert!(builder.build().is_ok());
+    }
+    
+    #[test]
+    fn test_builder_requires_keypair_unless_dry_run() {
+        let mut builder = create_builder();
+        builder.config(LiquidationConfig {
+            dry_run: false,
+            ..Default::default()
+        });
+        assert_eq!(
+            build_error(&mut builder),
+            "LiquidationEngineBuilder is missing keypair, which is required unless dry_run is set"
+        );
+        
+        let payer = Keypair::new();
+        let liquidator = payer.pubkey();
+        let engine = builder.keypair(payer).build().unwrap();
+        assert_eq!(engine.liquidator(), liquidator);
+    }
+    
+    #[test]
+    fn test_builder_validates_config() {
+        let mut builder = create_builder();
+        builder.config(LiquidationConfig {
+            check_interval_ms: 0,
+            ..Default::default()
+        });
+        assert_eq!(build_error(&mut builder), "check_interval_ms must be positive");
+        
+        let mut builder = create_builder();
+        builder.event_capacity(0);
+        assert_eq!(build_error(&mut builder), "LiquidationEngineBuilder event_capacity must be positive");
+    }
+    
+    #[tokio::test]
+    async fn test_builder_builds_once() {
+        let clock = ManualClock::new(1_700_000_000);
+        let mut builder = create_builder();
+        builder.clock(Arc::new(clock.clone())).event_capacity(2);
+        let engine = builder.build().unwrap();
+        assert_eq!(engine.now(), 1_700_000_000);
+        assert_eq!(engine.config().check_interval_ms, LiquidationConfig::default().check_interval_ms);
+        
+        // Subscribers fall behind after the configured number of events
+        let mut events = engine.subscribe();
+        let update = create_test_position().update(60000.0, PositionStatus::Active, MarginParams::shared(0.05), engine.now());
+        for _ in 0..3 {
+            engine.events.send(EngineEvent::PositionUpdate(update.clone())).unwrap();
+        }
+        assert!(matches!(events.recv().await, Err(broadcast::error::RecvError::Lagged(1))));
+        
+        assert_eq!(
+            build_error(&mut builder),
+            "LiquidationEngineBuilder was already built; use a new builder for another engine"
+        );
+    }
+    
+    /// Next notification sent to `listener`, standing in for systemd
+    #[cfg(unix)]
+    async fn next_notification(listener: &tokio::net::UnixDatagram) -> String {
+        let mut buf = [0; 256];
+        let len = tokio::time::timeout(Duration::from_secs(5), listener.recv(&mut buf)).await.unwrap().unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_systemd_notified_from_startup_to_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let listener = tokio::net::UnixDatagram::bind(&path).unwrap();
+        let config = LiquidationConfig {
+            check_interval_ms: 200,
+            warmup_cycles: 1,
+            ..Default::default()
+        };
+        let mut builder = create_builder();
+        builder.config(config).systemd(SystemdNotifier::connect(path.to_str().unwrap()).unwrap());
+        let engine = Arc::new(builder.build().unwrap());
+        let running = tokio::spawn({
+            let engine = engine.clone();
+            async move { engine.start().await }
+        });
+        
+        assert_eq!(next_notification(&listener).await, "STATUS=running, starting");
+        assert_eq!(
+            next_notification(&listener).await,
+            "WATCHDOG=1\nSTATUS=running, warming up: 1/1 cycles on complete data"
+        );
+        // Ready once the second cycle ends the warm-up, and only then
+        assert_eq!(
+            next_notification(&listener).await,
+            "READY=1\nWATCHDOG=1\nSTATUS=running, last cycle: 0 liquidated, 0 failed, 0 skipped"
+        );
+        
+        engine.shutdown();
+        assert_eq!(next_notification(&listener).await, "STOPPING=1\nSTATUS=Shutting down");
+        running.await.unwrap().unwrap();
+    }
+    
+    async fn check_with_sol_price(config: LiquidationConfig, position: Position) -> Option<LiquidationResult> {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()));
+        engine.check_position(position).await.unwrap()
+    }
+    
+    #[tokio::test]
+    async fn test_profitable_liquidation_proceeds() {
+        // 5x long at 4% margin ratio: liquidatable but not bankrupt
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        let config = LiquidationConfig {
+            min_profit_quote: 10.0,
+            ..Default::default()
+        };
+        let result = check_with_sol_price(config, position).await;
+        assert!(matches!(result, Some(LiquidationResult::Success { amount, .. }) if amount == 0.5));
+    }
+    
+    #[tokio::test]
+    async fn test_partial_amount_rounded_to_step() {
+        // 1.23 BTC at 4% margin ratio, half of which is 0.615
+        let mut position = create_test_position();
+        position.size = 1.23;
+        position.margin = 14760.0;
+        let spec = SymbolSpec {
+            step_size: Some(0.1),
+            ..Default::default()
+        };
+        let config = |spec: SymbolSpec| LiquidationConfig {
+            symbol_specs: HashMap::from([("BTC/USD".to_string(), spec)]),
+            ..Default::default()
+        };
+        let result = check_with_sol_price(config(spec), position.clone()).await;
+        assert!(matches!(result, Some(LiquidationResult::Success { amount, .. }) if amount == 0.6));
+
+        // 0.6 BTC at 50,000 is too small where the exchange takes at least 40,000
+        let spec = SymbolSpec {
+            min_notional: Some(40_000.0),
+            ..spec
+        };
+        let result = check_with_sol_price(config(spec), position).await;
+        assert!(
+            matches!(&result, Some(LiquidationResult::Skipped { reason: SkipReason::BelowMinSize { .. }, .. })),
+            "{:?}",
+            result
+        );
+    }
+    
+    #[tokio::test]
+    async fn test_unprofitable_liquidation_skipped() {
+        // $18 dust position at 5x, liquidatable at $50,000
+        let mut position = create_test_position();
+        position.size = 0.0003;
+        position.margin = 3.6;
+        let config = LiquidationConfig {
+            min_profit_quote: 1.0,
+            ..Default::default()
+        };
+        
+        match check_with_sol_price(config, position).await {
+            Some(LiquidationResult::Skipped {
+                reason: SkipReason::Unprofitable(estimate),
+                ..
+            }) => assert!(estimate.contains("reward 0.7500"), "{}", estimate),
+            other => panic!("expected unprofitable skip, got {:?}", other),
+        }
+    }
+    
+    fn create_gapping_position() -> Position {
+        // 100x long at 52,632 gapping 5% against itself to 50,000
+        let mut position = create_test_position();
+        position.entry_price = 52632.0;
+        position.margin = 526.32;
+        position
+    }
+    
+    #[tokio::test]
+    async fn test_bad_debt_recorded_for_bankrupt_position() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        
+        // Loss of 2632 against 526.32 of margin leaves 2105.68 of bad debt
+        let position = create_gapping_position();
+        assert!(position.bankruptcy_price().unwrap() > 50000.0);
+        
+        // Bankrupt positions are closed in full, partial liquidation notwithstanding
+        let result = engine.check_position(position).await.unwrap();
+        assert!(matches!(result, Some(LiquidationResult::Success { amount, .. }) if amount == 1.0));
+        
+        let stats = engine.get_insurance_stats().await;
+        assert_eq!(stats.bad_debt_events, 1);
+        assert!((stats.total_bad_debt - 2105.68).abs() < 1e-6);
+        assert!((stats.window_bad_debt - 2105.68).abs() < 1e-6);
+    }
+    
+    #[tokio::test]
+    async fn test_bad_debt_limit_refuses_liquidation() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let config = LiquidationConfig {
+            max_window_bad_debt: Some(1000.0),
+            ..Default::default()
+        };
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()));
+        
+        match engine.check_position(create_gapping_position()).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => {
+                assert!(matches!(reason, SkipReason::BadDebtLimit { .. }), "{}", reason)
+            }
+            other => panic!("expected bad debt skip, got {:?}", other),
+        }
+        assert_eq!(engine.get_insurance_stats().await, InsuranceStats::default());
+    }
+    
+    fn create_owned_position(owner: OwnerAddress, symbol: &str, margin: f64, is_long: bool) -> Position {
+        Position::new(Pubkey::new_unique(), owner, symbol, 1.0, 60000.0, margin, is_long)
+    }
+    
+    #[tokio::test]
+    async fn test_accounts_at_risk_nets_and_orders() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 57000.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        
+        let (worst, risky, hedged, unpriced) = (
+            OwnerAddress::new_unique(),
+            OwnerAddress::new_unique(),
+            OwnerAddress::new_unique(),
+            OwnerAddress::new_unique(),
+        );
+        engine.add_position(create_owned_position(risky, "BTC/USD", 6000.0, true)).await.unwrap();
+        engine.add_position(create_owned_position(worst, "BTC/USD", 4000.0, true)).await.unwrap();
+        // Thin margin on both legs, but the exposure cancels out
+        engine.add_position(create_owned_position(hedged, "BTC/USD", 3500.0, true)).await.unwrap();
+        engine.add_position(create_owned_position(hedged, "BTC/USD", 100.0, false)).await.unwrap();
+        engine.add_position(create_owned_position(unpriced, "BTC/USD", 5000.0, true)).await.unwrap();
+        engine.add_position(create_owned_position(unpriced, "ETH/USD", 100.0, true)).await.unwrap();
+        
+        let accounts = engine.accounts_at_risk(0.05).await;
+        let owners: Vec<OwnerAddress> = accounts.iter().map(|account| account.owner).collect();
+        assert_eq!(owners, vec![worst, unpriced]);
+        
+        // The unpriced ETH leg is kept on the account but left out of the totals
+        let partial = &accounts[1];
+        assert!(partial.is_partially_priced());
+        assert_eq!(partial.positions.len(), 2);
+        assert_eq!(partial.total_notional, 57000.0);
+        
+        // Raising the threshold brings in the risky account, but never the hedged one
+        let accounts = engine.accounts_at_risk(1.0).await;
+        let owners: Vec<OwnerAddress> = accounts.iter().map(|account| account.owner).collect();
+        assert_eq!(owners, vec![worst, unpriced, risky]);
+    }
+    
+    #[tokio::test]
+    async fn test_collateral_price_drop_reorders_and_liquidates() {
+        let oracle = Arc::new(MockOracle::new());
+        oracle.set_price("BTC/USD", 57500.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        oracle.set_price("USDC/USD", 1.0).await;
+        let config = LiquidationConfig {
+            collateral_weights: HashMap::from([("SOL/USD".to_string(), 0.9)]),
+            // SOL halves in one step below
+            price_sanity: PriceSanityConfig {
+                symbol_max_price_jump_bps: HashMap::from([("SOL/USD".to_string(), 5_000)]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, oracle.clone(), config, Arc::new(RateLimiter::default()));
+        let collateral = |symbol: &str, amount| CollateralBalance {
+            mint: Pubkey::new_unique(),
+            symbol: symbol.to_string(),
+            amount,
+        };
+        
+        // 5,500 USDC against a 2,500 loss, and 1,000 USDC plus 60 SOL worth
+        // 5,400 after the haircut
+        let (usdc_owner, sol_owner) = (OwnerAddress::new_unique(), OwnerAddress::new_unique());
+        let usdc_only = create_owned_position(usdc_owner, "BTC/USD", 0.0, true)
+            .with_collateral(vec![collateral("USDC/USD", 5500.0)]);
+        let mixed = create_owned_position(sol_owner, "BTC/USD", 0.0, true)
+            .with_collateral(vec![collateral("USDC/USD", 1000.0), collateral("SOL/USD", 60.0)]);
+        let mixed_address = mixed.address;
+        engine.add_position(usdc_only).await.unwrap();
+        engine.add_position(mixed).await.unwrap();
+        
+        let owners = |accounts: Vec<AccountRisk>| accounts.iter().map(|account| account.owner).collect::<Vec<_>>();
+        assert_eq!(owners(engine.accounts_at_risk(1.0).await), [usdc_owner, sol_owner]);
+        assert!(engine.check_position_now(&mixed_address).await.unwrap().is_none());
+        
+        // Only the traded symbol's price is unchanged, yet the SOL-backed
+        // account becomes the riskiest and liquidatable: 1,200 / 57,500 ≈ 2%
+        oracle.set_price("SOL/USD", 50.0).await;
+        assert_eq!(owners(engine.accounts_at_risk(1.0).await), [sol_owner, usdc_owner]);
+        assert!((engine.get_position(&mixed_address).await.unwrap().collateral_value - 3700.0).abs() < 1e-9);
+        assert!(matches!(
+            engine.check_position_now(&mixed_address).await.unwrap(),
+            Some(LiquidationResult::Success { .. })
+        ));
+    }
+    
+    #[tokio::test]
+    async fn test_check_positions_processes_worst_account_first() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 57000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        
+        let mut expected = Vec::new();
+        for margin in [4500.0, 3200.0, 4000.0, 5000.0, 3600.0] {
+            let position = create_owned_position(OwnerAddress::new_unique(), "BTC/USD", margin, true);
+            expected.push((margin, position.address));
+            engine.add_position(position).await.unwrap();
+        }
+        expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+        
+        let results = engine.check_positions().await.unwrap();
+        let processed: Vec<PositionAddress> = results
+            .iter()
+            .map(|result| match result {
+                LiquidationResult::Success { position, .. } => *position,
+                other => panic!("unexpected result: {}", other),
+            })
+            .collect();
+        let expected: Vec<PositionAddress> = expected.into_iter().map(|(_, address)| address).collect();
+        assert_eq!(processed, expected);
+    }
+    
+    /// A cascade of 50 liquidatable positions of varied size, half of them
+    /// bankrupt, in an engine processing 10 at a time
+    async fn create_cascade(config: LiquidationConfig) -> (LiquidationEngine, Vec<Position>) {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let config = LiquidationConfig {
+            max_concurrent_liquidations: 10,
+            ..config
+        };
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()));
+        
+        let mut positions = Vec::new();
+        for i in 0..50 {
+            let size = 0.1 + (i * 37 % 50) as f64 * 0.05;
+            // 10x longs from 60,000 are bankrupt at 50,000; at 5.5x they're
+            // left with a 2% margin ratio
+            let leverage = if i % 2 == 0 { 10.0 } else { 60.0 / 11.0 };
+            let position = Position::new(
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                "BTC/USD",
+                size,
+                60000.0,
+                size * 60000.0 / leverage,
+                true,
+            );
+            engine.add_position(position.clone()).await.unwrap();
+            positions.push(position);
+        }
+        (engine, positions)
+    }
+    
+    /// Positions and scores of the liquidations in `results`
+    fn scored_results(results: &[LiquidationResult]) -> Vec<(PositionAddress, f64)> {
+        results
+            .iter()
+            .map(|result| match result {
+                LiquidationResult::Success { position, priority_score, .. } => (*position, priority_score.unwrap()),
+                other => panic!("unexpected result: {}", other),
+            })
+            .collect()
+    }
+    
+    #[tokio::test]
+    async fn test_cascade_processed_by_priority() {
+        let config = LiquidationConfig {
+            priority_weights: PriorityWeights {
+                staleness: 0.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (engine, positions) = create_cascade(config.clone()).await;
+        
+        // Score every candidate as the weighted sum of its net reward and bad debt
+        let weighted = WeightedScore::new(config.priority_weights);
+        let model = ProfitModel::from_config(&config, 1_000);
+        let mut expected: Vec<(PositionAddress, f64)> = positions
+            .iter()
+            .map(|position| {
+                let bad_debt = position.bad_debt(50000.0);
+                let estimate = model.estimate(position, 50000.0, config.liquidation_fraction(&position.symbol, bad_debt), 100.0);
+                let candidate = Candidate {
+                    position: position.address,
+                    expected_profit: estimate.net_profit,
+                    bad_debt,
+                    staleness_secs: 0.0,
+                };
+                (position.address, weighted.score(&candidate))
+            })
+            .collect();
+        expected.sort_by(|a, b| b.1.total_cmp(&a.1));
+        
+        // The ten best go first, the next ten the cycle after
+        let first = scored_results(&engine.check_positions().await.unwrap());
+        assert_eq!(first.len(), 10);
+        for ((position, score), (expected_position, expected_score)) in first.iter().zip(&expected[..10]) {
+            assert_eq!(position, expected_position);
+            assert!((score - expected_score).abs() < 1e-9);
+        }
+        
+        for position in &first {
+            engine.remove_position(&position.0).await;
+        }
+        let second = scored_results(&engine.check_positions().await.unwrap());
+        let second: Vec<PositionAddress> = second.iter().map(|(position, _)| *position).collect();
+        let expected: Vec<PositionAddress> = expected[10..20].iter().map(|(position, _)| *position).collect();
+        assert_eq!(second, expected);
+    }
+    
+    #[tokio::test]
+    async fn test_cycle_evaluates_positions_at_one_snapshot() {
+        // BTC/USD drifts down 10 with every read
+        let btc = Arc::new(std::sync::Mutex::new(50000.0));
+        let mut oracle = MockOracleProvider::new();
+        let drifting = |btc: Arc<std::sync::Mutex<f64>>| {
+            move |symbol: &str| match symbol {
+                "BTC/USD" => {
+                    let mut price = btc.lock().unwrap();
+                    *price -= 10.0;
+                    PriceData::from_price(*price + 10.0, 1_700_000_000)
+                }
+                _ => PriceData::from_price(100.0, 1_700_000_000),
+            }
+        };
+        let read = drifting(btc.clone());
+        oracle.expect_get_price_data().returning(move |symbol| Ok(read(symbol)));
+        let read = drifting(btc.clone());
+        oracle.expect_get_prices_partial().returning(move |symbols| {
+            let prices = symbols.iter().map(|&symbol| (symbol.to_string(), read(symbol))).collect();
+            (prices, HashMap::new())
+        });
+        oracle
+            .expect_get_price()
+            .returning(|symbol| if symbol == "BTC/USD" { Ok(50000.0) } else { Ok(100.0) });
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        for _ in 0..3 {
+            engine.add_position(create_test_position()).await.unwrap();
+        }
+        
+        let results = engine.check_positions().await.unwrap();
+        let evaluated: Vec<(f64, Option<String>)> = results
+            .iter()
+            .map(|result| match result {
+                LiquidationResult::Success { price, cycle_id, .. } => (*price, cycle_id.clone()),
+                other => panic!("expected a liquidation, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(evaluated.len(), 3);
+        assert!(evaluated.iter().all(|result| *result == (50000.0, evaluated[0].1.clone())));
+        assert!(evaluated[0].1.is_some());
+        // Each liquidation was revalidated at a fresh price before it went ahead
+        assert_eq!(*btc.lock().unwrap(), 50000.0 - 40.0);
+        
+        // Checks outside a cycle fetch their own price
+        match engine.check_position(create_test_position()).await.unwrap() {
+            Some(LiquidationResult::Success { price, cycle_id: None, .. }) => assert_eq!(price, 49960.0),
+            other => panic!("expected a liquidation, got {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_recovered_price_spares_snapshot_candidate() {
+        let btc = Arc::new(std::sync::Mutex::new(50000.0));
+        let mut oracle = MockOracleProvider::new();
+        let recovering = btc.clone();
+        oracle.expect_get_price_data().returning(move |symbol| match symbol {
+            "BTC/USD" => Ok(PriceData::from_price(*recovering.lock().unwrap(), 1_700_000_000)),
+            _ => Ok(PriceData::from_price(100.0, 1_700_000_000)),
+        });
+        oracle.expect_get_price().returning(|_| Ok(100.0));
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        let position = create_test_position();
+        let snapshot = PriceSnapshot {
+            cycle_id: "c1".to_string(),
+            taken_at: 1_700_000_000,
+            prices: HashMap::from([("BTC/USD".to_string(), PriceData::from_price(50000.0, 1_700_000_000))]),
+        };
+        
+        // Back above the liquidation price by the time it would be submitted
+        *btc.lock().unwrap() = 58000.0;
+        match engine.check_scored_position(position, Some(1.0), Some(&snapshot), None).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => {
+                assert_eq!(reason.to_string(), "price moved since cycle c1: 50000 to 58000");
+                assert_eq!(
+                    reason,
+                    SkipReason::PositionRecovered {
+                        cycle_id: "c1".to_string(),
+                        from: 50000.0,
+                        to: 58000.0,
+                    }
+                );
+            }
+            other => panic!("expected the liquidation to be skipped, got {:?}", other),
+        }
+    }
+    
+    /// Scores candidates by how long they've waited alone
+    struct OldestFirst;
+    
+    impl Prioritizer for OldestFirst {
+        fn score(&self, candidate: &Candidate) -> f64 {
+            candidate.staleness_secs
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_custom_prioritizer_and_staleness() {
+        let (engine, positions) = create_cascade(LiquidationConfig::default()).await;
+        let engine = engine.with_prioritizer(Arc::new(OldestFirst));
+        
+        // Positions waiting longest come first, whatever they'd pay
+        let now = chrono::Utc::now().timestamp();
+        let waiting: Vec<PositionAddress> = positions[40..].iter().map(|position| position.address).collect();
+        for (age, address) in waiting.iter().enumerate() {
+            engine.last_checked.write().await.insert(*address, now - 1000 + age as i64);
+        }
+        let results = scored_results(&engine.check_positions().await.unwrap());
+        let processed: Vec<PositionAddress> = results.iter().map(|(position, _)| *position).collect();
+        assert_eq!(processed, waiting);
+        assert!(results[0].1 >= 1000.0);
+        
+        // Checked positions start waiting again from now
+        let last_checked = engine.last_checked.read().await;
+        assert!(waiting.iter().all(|address| last_checked[address] >= now));
+    }
+    
+    #[cfg(feature = "storage")]
+    #[tokio::test]
+    async fn test_liquidations_recorded_to_store() {
+        use crate::storage::{LiquidationStore, StoreWriter};
+        
+        let store = Arc::new(LiquidationStore::open_in_memory().unwrap());
+        let (writer, handle) = StoreWriter::spawn(store.clone(), 16);
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()))
+            .with_store(writer);
+        
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        let address = position.address;
+        engine.check_position(position).await.unwrap();
+        drop(engine);
+        handle.await.unwrap();
+        
+        let events = store.events_for_position(&address).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].dry_run);
+        assert_eq!(events[0].symbol, "BTC/USD");
+        assert_eq!(events[0].error, None);
+    }
+    
+    #[tokio::test]
+    async fn test_audit_flushed_before_shutdown_completes() {
+        use crate::audit::{AuditWriter, DEFAULT_AUDIT_QUEUE_CAPACITY, read_audit};
+        use crate::report::report_path_for_day;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let (writer, _) = AuditWriter::spawn(&path, DEFAULT_AUDIT_QUEUE_CAPACITY);
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        let clock = ManualClock::new(1_700_006_400); // 2023-11-15T00:00:00Z
+        let mut builder = LiquidationEngine::builder();
+        builder
+            .rpc_client("https://api.devnet.solana.com")
+            .oracle(Arc::new(oracle))
+            .clock(Arc::new(clock))
+            .audit(writer);
+        let engine = Arc::new(builder.build().unwrap());
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        let mut events = engine.subscribe();
+        
+        let running = tokio::spawn({
+            let engine = engine.clone();
+            async move { engine.start().await }
+        });
+        while !matches!(events.recv().await.unwrap(), EngineEvent::Liquidation(_)) {}
+        engine.shutdown();
+        running.await.unwrap().unwrap();
+        
+        // Once the engine has stopped, the attempt is on disk
+        let records = read_audit(report_path_for_day(&path, "2023-11-15")).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].position, records[0].owner), (position.address, position.owner));
+        assert_eq!(records[0].oracle_price, 55000.0);
+        assert_eq!(records[0].config_hash, engine.config().config_hash());
+    }
+    
+    #[tokio::test]
+    async fn test_dry_run_cycles_reported() {
+        use crate::report::{DEFAULT_REPORT_QUEUE_CAPACITY, ReportWriter, read_report, report_path_for_day};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dry_run.csv");
+        let (writer, _) = ReportWriter::spawn(&path, DEFAULT_REPORT_QUEUE_CAPACITY).unwrap();
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let clock = ManualClock::new(1_700_006_400); // 2023-11-15T00:00:00Z
+        let mut builder = LiquidationEngine::builder();
+        builder
+            .rpc_client("https://api.devnet.solana.com")
+            .oracle(Arc::new(oracle))
+            .clock(Arc::new(clock.clone()))
+            .report(writer.clone());
+        let engine = builder.build().unwrap();
+        let positions = [create_test_position(), create_test_position()];
+        for position in &positions {
+            engine.add_position(position.clone()).await.unwrap();
+        }
+        
+        // One cycle a day apart, each liquidating both positions
+        engine.check_positions().await.unwrap();
+        clock.advance(86_400);
+        engine.check_positions().await.unwrap();
+        writer.flush().await;
+        
+        for (day, timestamp) in [("2023-11-15", 1_700_006_400), ("2023-11-16", 1_700_092_800)] {
+            let mut rows = read_report(report_path_for_day(&path, day)).unwrap();
+            rows.sort_by_key(|row| positions.iter().position(|position| position.address == row.position));
+            assert_eq!(rows.len(), 2, "{}", day);
+            for (row, position) in rows.iter().zip(&positions) {
+                assert_eq!((row.timestamp, row.owner, row.symbol.as_str()), (timestamp, position.owner, "BTC/USD"));
+                assert_eq!(row.price, 55000.0);
+                assert!((row.margin_ratio - position.margin_ratio(55000.0)).abs() < 1e-12);
+                assert!((row.amount - 0.5).abs() < 1e-12);
+                assert!(row.reward > 0.0);
+                assert_eq!(row.bad_debt, 0.0);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_pnl_simulated() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine =
+            LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        let mut events = engine.subscribe();
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+
+        engine.check_positions().await.unwrap();
+        let event = loop {
+            if let EngineEvent::Liquidation(event) = events.recv().await.unwrap() {
+                break event;
+            }
+        };
+        let summary = engine.get_pnl_summary();
+        assert_eq!((summary.total.liquidations, summary.total.simulated), (1, 1));
+        assert_eq!(summary.total.reward, event.reward);
+        assert_eq!(summary.total.base_fee_lamports, BASE_FEE_LAMPORTS);
+        assert!(summary.total.network_fee > 0.0);
+        assert!((summary.total.net_pnl - (event.reward - summary.total.network_fee)).abs() < 1e-9);
+        assert_eq!(summary.by_symbol["BTC/USD"], summary.total);
+    }
+
+    async fn create_engine_with_state(rpc_url: &str, state_path: &std::path::Path) -> LiquidationEngine {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new(rpc_url));
+        LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()))
+            .with_state_file(StateFile::load(state_path).unwrap())
+    }
+    
+    #[tokio::test]
+    async fn test_cooldown_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("state.json");
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        let engine = create_engine_with_state("https://api.devnet.solana.com", &state_path).await;
+        engine.add_position(position.clone()).await.unwrap();
+        let result = engine.check_position(position.clone()).await.unwrap();
+        assert!(matches!(result, Some(LiquidationResult::Success { .. })));
+        drop(engine);
+        
+        // The restarted engine only knows the position as it was first loaded
+        let engine = create_engine_with_state("https://api.devnet.solana.com", &state_path).await;
+        engine.add_position(position.clone()).await.unwrap();
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => assert_eq!(reason, SkipReason::Cooldown),
+            other => panic!("expected cooldown, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_positions_exported_and_imported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positions.json");
+        let engine = create_engine(LiquidationConfig::default());
+        let exported: Vec<Position> = (0..3).map(|_| create_test_position()).collect();
+        for position in &exported {
+            engine.add_position(position.clone()).await.unwrap();
+        }
+        assert_eq!(engine.export_positions(&path).await.unwrap(), 3);
+
+        // Merging keeps positions the snapshot doesn't have and takes its version of the rest
+        let restarted = create_engine(LiquidationConfig::default());
+        let other = create_test_position();
+        let mut stale = exported[0].clone();
+        stale.margin = 3000.0;
+        restarted.add_position(other.clone()).await.unwrap();
+        restarted.add_position(stale).await.unwrap();
+        assert_eq!(restarted.import_positions(&path, true).await.unwrap(), 3);
+        assert_eq!(restarted.get_positions().await.len(), 4);
+        assert_eq!(restarted.get_position(&exported[0].address).await, Some(exported[0].clone()));
+
+        // Replacing drops them
+        assert_eq!(restarted.import_positions(&path, false).await.unwrap(), 3);
+        assert!(restarted.get_position(&other.address).await.is_none());
+        assert_eq!(restarted.snapshot_positions().await.positions, engine.snapshot_positions().await.positions);
+
+        // A bad snapshot leaves the cache alone
+        std::fs::write(&path, "{\"version\": 7}").unwrap();
+        assert!(matches!(restarted.import_positions(&path, false).await, Err(LiquidationError::ConfigError(_))));
+        assert_eq!(restarted.get_positions().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_replay_is_deterministic() {
+        // Live settings: the replay has to force dry runs, with no payer to sign with
+        let config = LiquidationConfig {
+            dry_run: false,
+            min_liquidation_interval_secs: 600,
+            ..Default::default()
+        };
+        let long = create_test_position();
+        let bankrupt_short = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 0.1, 50000.0, 500.0, false);
+        // 20x long, liquidatable below 100
+        let sol_long = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "SOL/USD", 100.0, 100.0, 500.0, true);
+        let healthy = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 60000.0, 30000.0, true);
+        let book = PositionSnapshot::new(vec![long.clone(), bankrupt_short.clone(), sol_long.clone(), healthy.clone()], 0);
+
+        let replay = || async {
+            let oracle = Arc::new(
+                ReplayOracle::from_csv(include_str!("../fixtures/replay/prices.csv"), Arc::new(ManualClock::new(0)))
+                    .unwrap(),
+            );
+            let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+            let engine = LiquidationEngine::new(rpc_client, oracle.clone(), config.clone(), Arc::new(RateLimiter::default()))
+                .with_clock(oracle.clock().clone());
+            engine.restore_positions(book.clone(), false).await.unwrap();
+            let report = engine.run_replay(1_700_000_000, 1_700_003_600, 300).await.unwrap();
+            (report, engine.get_positions().await)
+        };
+        let (report, remaining) = replay().await;
+
+        assert_eq!((report.steps, report.liquidations, report.skipped, report.failures), (13, 5, 2, 0));
+        let outcomes: Vec<(i64, PositionAddress, Option<f64>)> = report
+            .results
+            .iter()
+            .map(|replayed| match &replayed.result {
+                LiquidationResult::Success { position, amount, signature, .. } => {
+                    assert_eq!(signature, "dry-run");
+                    (replayed.timestamp, *position, Some(*amount))
+                }
+                LiquidationResult::Skipped { position, reason } => {
+                    assert_eq!(*reason, SkipReason::Cooldown);
+                    (replayed.timestamp, *position, None)
+                }
+                other => panic!("unexpected result {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                // Bankrupt from the start, so closed in full
+                (1_700_000_000, bankrupt_short.address, Some(0.1)),
+                // Half at 99, held back by the cooldown at 97, then bankrupt at 94
+                (1_700_000_300, sol_long.address, Some(50.0)),
+                (1_700_000_600, sol_long.address, None),
+                (1_700_000_900, sol_long.address, Some(50.0)),
+                (1_700_001_200, long.address, Some(0.5)),
+                (1_700_001_500, long.address, None),
+                (1_700_001_800, long.address, Some(0.5)),
+            ]
+        );
+        assert!((report.notional - (6000.0 + 4950.0 + 4700.0 + 28000.0 + 26500.0)).abs() < 1e-6);
+        assert_eq!(remaining, vec![healthy]);
+
+        // SOL's fall is the worst, timed to its bottom after the positions on it closed
+        let drawdown = report.worst_drawdown.clone().unwrap();
+        assert_eq!(drawdown.symbol, "SOL/USD");
+        assert_eq!((drawdown.peak_timestamp, drawdown.peak_price), (1_700_000_000, 100.0));
+        assert_eq!((drawdown.trough_timestamp, drawdown.trough_price), (1_700_002_100, 80.0));
+        assert!((drawdown.fall - 0.2).abs() < 1e-12);
+
+        // Everything but the random correlation and cycle ids repeats exactly
+        let stripped = |report: &ReplayReport| {
+            let mut json = serde_json::to_value(report).unwrap();
+            for result in json["results"].as_array_mut().unwrap() {
+                result.as_object_mut().unwrap().remove("correlation_id");
+                result.as_object_mut().unwrap().remove("cycle_id");
+            }
+            json
+        };
+        assert_eq!(stripped(&replay().await.0), stripped(&report));
+    }
+
+    #[tokio::test]
+    async fn test_liquidations_split_within_slippage() {
+        let config = LiquidationConfig {
+            enable_partial_liquidations: false,
+            min_liquidation_interval_secs: 0,
+            max_slippage_bps: 50,
+            market_liquidity: HashMap::from([("BTC/USD".to_string(), 10_000.0)]),
+            ..Default::default()
+        };
+        // Both at 4% margin under a flat price
+        let whale = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 500.0, 60000.0, 1_200_000.0, true);
+        let minnow = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 0.5, 60000.0, 1200.0, true);
+        let prices: String = (0..12)
+            .map(|step| format!("{},BTC/USD,60000,30\n", 1_700_000_000 + step * 300))
+            .collect();
+        let oracle = Arc::new(
+            ReplayOracle::from_csv(&format!("{}\n{}", REPLAY_CSV_HEADER, prices), Arc::new(ManualClock::new(0))).unwrap(),
+        );
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, oracle.clone(), config, Arc::new(RateLimiter::default()))
+            .with_clock(oracle.clock().clone());
+        engine
+            .restore_positions(PositionSnapshot::new(vec![whale.clone(), minnow.clone()], 0), false)
+            .await
+            .unwrap();
+        let report = engine.run_replay(1_700_000_000, 1_700_003_300, 300).await.unwrap();
+
+        let chunks = |address: PositionAddress| -> Vec<(f64, f64)> {
+            report
+                .results
+                .iter()
+                .filter_map(|replayed| match &replayed.result {
+                    LiquidationResult::Success { position, amount, estimated_impact_bps, .. } if *position == address => {
+                        Some((*amount, estimated_impact_bps.unwrap()))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        // 10,000 BTC of liquidity takes 50 BTC per chunk at 50 bps
+        let whale_chunks = chunks(whale.address);
+        assert_eq!(whale_chunks.len(), 10);
+        for (amount, impact) in whale_chunks {
+            assert!((amount - 50.0).abs() < 1e-6, "{}", amount);
+            assert!((impact - 50.0).abs() < 1e-6, "{}", impact);
+        }
+        let minnow_chunks = chunks(minnow.address);
+        assert_eq!(minnow_chunks.len(), 1);
+        assert!((minnow_chunks[0].0 - 0.5).abs() < 1e-9);
+        assert!((minnow_chunks[0].1 - 0.5).abs() < 1e-9);
+        assert!(engine.get_positions().await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_replay_needs_settable_clock() {
+        let engine = create_engine(LiquidationConfig::default());
+        assert!(matches!(engine.run_replay(0, 600, 60).await, Err(LiquidationError::ConfigError(_))));
+        let engine = engine.with_clock(Arc::new(ManualClock::new(0)));
+        assert!(engine.run_replay(0, 600, 0).await.is_err());
+        assert!(engine.run_replay(600, 0, 60).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_program_events_update_positions() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = create_engine_with_state("https://api.devnet.solana.com", &dir.path().join("state.json")).await;
+        // 1,000 units of collateral against 80,000 of debt, modelled with no
+        // margin as loaded from its account, outside the ingest leverage cap
+        let position = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "SOL/USD", 1_000.0, 80.0, 0.0, true);
+        engine.restore_positions(PositionSnapshot::new(vec![position.clone()], 0), true).await.unwrap();
+
+        engine
+            .apply_program_event(&ProgramEvent::Deposited(events::PositionDeposited {
+                position: position.address.into(),
+                owner: position.owner.into(),
+                amount: 600,
+                new_collateral: 1_600,
+            }))
+            .await;
+        let deposited = engine.get_position(&position.address).await.unwrap();
+        assert_eq!((deposited.size, deposited.entry_price), (1_600.0, 50.0));
+
+        // Another keeper's liquidation starts the cooldown
+        let now = chrono::Utc::now().timestamp();
+        let mut liquidation = events::PositionLiquidated {
+            position: position.address.into(),
+            liquidator: Pubkey::new_unique(),
+            repay_amount: 40_000,
+            collateral_seized: 600,
+            remaining_debt: 40_000,
+            timestamp: now,
+        };
+        engine.apply_program_event(&ProgramEvent::Liquidated(liquidation.clone())).await;
+        let liquidated = engine.get_position(&position.address).await.unwrap();
+        assert_eq!((liquidated.size, liquidated.entry_price, liquidated.last_liquidated), (1_000.0, 40.0, Some(now)));
+        assert_eq!(engine.position_state(&position.address).await.unwrap().last_liquidated, Some(now));
+        match engine.check_position(liquidated).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => assert_eq!(reason, SkipReason::Cooldown),
+            other => panic!("expected cooldown, got {:?}", other),
+        }
+
+        // Events about positions that aren't monitored are ignored, and ones
+        // taking all the collateral end the monitoring
+        liquidation.position = Pubkey::new_unique();
+        engine.apply_program_event(&ProgramEvent::Liquidated(liquidation.clone())).await;
+        assert_eq!(engine.get_positions().await.len(), 1);
+        liquidation.position = position.address.into();
+        liquidation.collateral_seized = 1_000;
+        engine.apply_program_event(&ProgramEvent::Liquidated(liquidation)).await;
+        assert!(engine.get_position(&position.address).await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_pending_signature_blocks_retry_until_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("state.json");
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        let signature = Signature::new_unique().to_string();
+        let now = chrono::Utc::now().timestamp();
+        
+        // Submitted just before a crash, with the RPC node unreachable after restart
+        let mut state = StateFile::load(&state_path).unwrap();
+        state.record_submitted(&position.address, &signature, now).unwrap();
+        drop(state);
+        let engine = create_engine_with_state("http://127.0.0.1:1", &state_path).await;
+        match engine.check_position(position.clone()).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => {
+                assert_eq!(reason, SkipReason::AwaitingConfirmation(signature.to_string()))
+            }
+            other => panic!("expected to await confirmation, got {:?}", other),
+        }
+        drop(engine);
+        
+        // Once the transaction can no longer land, the position is retried
+        let mut state = StateFile::load(&state_path).unwrap();
+        state
+            .record_submitted(&position.address, &signature, now - PENDING_SIGNATURE_EXPIRY_SECS - 1)
+            .unwrap();
+        drop(state);
+        let engine = create_engine_with_state("http://127.0.0.1:1", &state_path).await;
+        let result = engine.check_position(position.clone()).await.unwrap();
+        assert!(matches!(result, Some(LiquidationResult::Success { .. })));
+        
+        let state = engine.position_state(&position.address).await.unwrap();
+        assert_eq!(state.pending_signature, None);
+        assert!(state.last_liquidated.is_some());
+    }
+    
+    #[tokio::test]
+    async fn test_config_update_applies_on_next_cycle() {
+        let engine = create_engine(LiquidationConfig::default());
+        let update = ConfigUpdate {
+            maintenance_margin: Some(0.08),
+            ..Default::default()
+        };
+        
+        assert_eq!(engine.update_config(&update).unwrap().maintenance_margin, 0.08);
+        assert_eq!(engine.config().maintenance_margin, 0.05);
+        
+        engine.check_positions().await.unwrap();
+        assert_eq!(engine.config().maintenance_margin, 0.08);
+        
+        // Invalid updates are rejected without staging anything
+        let invalid = ConfigUpdate {
+            maintenance_margin: Some(-1.0),
+            ..Default::default()
+        };
+        assert!(engine.update_config(&invalid).is_err());
+        engine.check_positions().await.unwrap();
+        assert_eq!(engine.config().maintenance_margin, 0.08);
+    }
+    
+    #[tokio::test]
+    async fn test_markets_apply_their_own_margin() {
+        let oracle = Arc::new(MockOracle::new());
+        oracle.set_price("BTC/USD", 57000.0).await;
+        oracle.set_price("ETH/USD", 2850.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let config = LiquidationConfig {
+            markets: vec![
+                MarketConfig::new(Pubkey::new_unique(), "BTC/USD", 0.05),
+                MarketConfig::new(Pubkey::new_unique(), "ETH/USD", 0.1),
+            ],
+            ..LiquidationConfig::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, oracle.clone(), config, Arc::new(RateLimiter::default()));
+        
+        // Both are 10x longs down 5%, with 3,000 of equity on 57,000 of value
+        let btc = create_test_position();
+        let eth = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "ETH/USD", 20.0, 3000.0, 6000.0, true);
+        engine.add_position(btc.clone()).await.unwrap();
+        engine.add_position(eth.clone()).await.unwrap();
+        assert_eq!(engine.position_update(&btc.address).await.unwrap().maintenance_margin, 5.0);
+        assert_eq!(engine.position_update(&eth.address).await.unwrap().maintenance_margin, 10.0);
+        
+        let results = engine.check_positions().await.unwrap();
+        assert!(matches!(results[..], [LiquidationResult::Success { position, .. }] if position == eth.address));
+        
+        // Disabling a market leaves its positions alone from the next cycle
+        let mut disabled = engine.config().market("BTC/USD").unwrap().clone();
+        disabled.enabled = false;
+        disabled.maintenance_margin = 0.1;
+        engine.upsert_market(disabled).unwrap();
+        let results = engine.check_positions().await.unwrap();
+        assert!(!results.iter().any(|result| matches!(result, LiquidationResult::Success { position, .. } if *position == btc.address)));
+        let result = engine.check_position_now(&btc.address).await.unwrap();
+        assert!(matches!(result, Some(LiquidationResult::Skipped { reason: SkipReason::MarketNotTrading, .. })));
+    }
+    
+    #[tokio::test]
+    async fn test_short_margin_liquidates_mirrored_short() {
+        let oracle = Arc::new(MockOracle::new());
+        oracle.set_price("BTC/USD", 54000.0).await;
+        oracle.set_price("ETH/USD", 3300.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let sided = |symbol| MarketConfig {
+            maintenance_margin_short: Some(0.1),
+            ..MarketConfig::new(Pubkey::new_unique(), symbol, 0.05)
+        };
+        let config = LiquidationConfig {
+            markets: vec![sided("BTC/USD"), sided("ETH/USD")],
+            ..LiquidationConfig::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, oracle, config, Arc::new(RateLimiter::default()));
+        
+        // 5x positions 10% under water: the long keeps 11.1% of its value,
+        // the short 9.1%, which only falls short of the short requirement
+        let long = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 60000.0, 12000.0, true);
+        let short = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "ETH/USD", 20.0, 3000.0, 12000.0, false);
+        engine.add_position(long.clone()).await.unwrap();
+        engine.add_position(short.clone()).await.unwrap();
+        let long_update = engine.position_update(&long.address).await.unwrap();
+        let short_update = engine.position_update(&short.address).await.unwrap();
+        assert_eq!((long_update.maintenance_margin, short_update.maintenance_margin), (5.0, 10.0));
+        assert!((short_update.liquidation_price.unwrap() - 72000.0 / 20.0 / 1.1).abs() < 1e-9);
+        
+        let results = engine.check_positions().await.unwrap();
+        assert!(matches!(results[..], [LiquidationResult::Success { position, .. }] if position == short.address));
+        assert_eq!(engine.config().margin_params("ETH/USD"), MarginParams { long: 0.05, short: 0.1 });
+    }
+    
+    #[tokio::test]
+    async fn test_widened_confidence_spares_boundary_position() {
+        // 10x long liquidatable below ~56,842, priced at 56,800 within 100
+        let oracle = Arc::new(MockOracle::new());
+        oracle.set_price("BTC/USD", 56800.0).await;
+        oracle.set_confidence("BTC/USD", 100.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let engine_with = |config: LiquidationConfig| {
+            let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+            LiquidationEngine::new(rpc_client, oracle.clone(), config, Arc::new(RateLimiter::default()))
+        };
+        
+        // Rejection leaves wide intervals to the oracle and liquidates at the point price
+        let engine = engine_with(LiquidationConfig::default());
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        let results = engine.check_positions().await.unwrap();
+        assert!(matches!(results[..], [LiquidationResult::Success { position: address, .. }] if address == position.address));
+        
+        // Widened, the long is judged at 56,900, where it still clears maintenance
+        let mut config = LiquidationConfig::default();
+        config
+            .oracle_confidence
+            .symbol_policies
+            .insert("BTC/USD".to_string(), ConfidencePolicy::Widen);
+        let engine = engine_with(config.clone());
+        engine.add_position(position.clone()).await.unwrap();
+        assert!(engine.check_positions().await.unwrap().is_empty());
+        
+        // Unless the interval is narrowed below the margin of safety
+        config.oracle_confidence.widen_multiplier = 0.25;
+        let engine = engine_with(config);
+        engine.add_position(position.clone()).await.unwrap();
+        assert_eq!(engine.check_positions().await.unwrap().len(), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_basis_shifts_boundary_position_to_liquidation() {
+        let oracle = Arc::new(MockOracle::new());
+        oracle.set_price("BTC/USD", 56900.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let basis = MockBasisSource::new();
+        basis.set_basis("BTC/USD", -0.002).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, oracle, LiquidationConfig::default(), Arc::new(RateLimiter::default()))
+            .with_basis_source(Arc::new(basis));
+        
+        // 2,900 of equity clears the 2,845 margin at the index, but not the
+        // 2,839 margin on 2,786 of equity 20 bps lower
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        assert!(engine.check_positions().await.unwrap().is_empty());
+        assert_eq!(engine.position_update(&position.address).await.unwrap().mark_price, 56900.0);
+        
+        let mut config = LiquidationConfig::default();
+        config.mark_price.max_basis_bps.insert("BTC/USD".to_string(), 50);
+        engine.reload_config(&config).unwrap();
+        let results = engine.check_positions().await.unwrap();
+        assert!(matches!(results[..], [LiquidationResult::Success { position: address, .. }] if address == position.address));
+        assert!((engine.position_update(&position.address).await.unwrap().mark_price - 56786.2).abs() < 1e-6);
+    }
+    
+    #[tokio::test]
+    async fn test_stats_of_fixture_book() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("ETH/USD", 3000.0).await;
+        let clock = ManualClock::new(1_700_000_000);
+        let config = LiquidationConfig {
+            insurance_fund_balance: 5000.0,
+            ..LiquidationConfig::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle.clone()), config, Arc::new(RateLimiter::default()))
+            .with_clock(Arc::new(clock.clone()));
+        let snapshot = PositionSnapshot::from_json(include_bytes!("../fixtures/stats/book.json")).unwrap();
+        engine.restore_positions(snapshot, false).await.unwrap();
+
+        // Margins of 4%, 4%, 6%, 6%, 9.6%, 16% and 25%; DOGE can't be priced
+        let stats = engine.get_stats().await.unwrap();
+        assert_eq!((stats.positions, stats.unpriced), (8, 1));
+        let counts: Vec<usize> = stats.margin_ratio_histogram.iter().map(|bucket| bucket.count).collect();
+        assert_eq!(counts, [0, 2, 2, 1, 0, 1, 1, 0, 0, 0]);
+        assert_eq!(stats.open_notional["BTC/USD"], SideNotional { long: 200_000.0, short: 25_000.0 });
+        assert_eq!(stats.open_notional["ETH/USD"], SideNotional { long: 30_000.0, short: 45_000.0 });
+        assert_eq!(stats.statuses[&PositionStatus::AtRisk], 2);
+        assert_eq!(stats.statuses[&PositionStatus::Active], 5);
+
+        // The two 4% margins lead and the 25% one trails
+        let closest: Vec<PositionAddress> = stats.closest_to_liquidation.iter().map(|update| update.address).collect();
+        assert_eq!(closest.len(), 7);
+        let leading = HashSet::from([closest[0], closest[1]]);
+        let fixture = |byte| PositionAddress(Pubkey::new_from_array([byte; 32]));
+        assert_eq!(leading, HashSet::from([fixture(1), fixture(7)]));
+        assert_eq!(closest[6], fixture(4));
+
+        // Longs go under as prices fall and shorts as they rise
+        let expected = [
+            (-0.2, 3, 19_200.0, 14_200.0),
+            (-0.1, 3, 6_200.0, 1_200.0),
+            (-0.05, 3, 500.0, 0.0),
+            (0.05, 2, 150.0, 0.0),
+            (0.1, 2, 1_000.0, 0.0),
+            (0.2, 3, 6_200.0, 1_200.0),
+        ];
+        assert_eq!(stats.price_shocks.len(), expected.len());
+        for (impact, (shock, liquidations, bad_debt, shortfall)) in stats.price_shocks.iter().zip(expected) {
+            assert_eq!((impact.shock, impact.liquidations), (shock, liquidations));
+            assert!((impact.bad_debt - bad_debt).abs() < 1e-6, "{} at {}", impact.bad_debt, shock);
+            assert!((impact.insurance_shortfall - shortfall).abs() < 1e-6);
+        }
+
+        // Served from the cache until it expires
+        oracle.set_price("BTC/USD", 45000.0).await;
+        assert_eq!(engine.get_stats().await.unwrap(), stats);
+        clock.advance(10);
+        let stats = engine.get_stats().await.unwrap();
+        assert_eq!(stats.open_notional["BTC/USD"], SideNotional { long: 180_000.0, short: 22_500.0 });
+    }
+
+    #[tokio::test]
+    async fn test_time_to_liquidation_follows_drift() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 60000.0).await;
+        let clock = ManualClock::new(1_700_000_000);
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(
+            rpc_client,
+            Arc::new(oracle.clone()),
+            LiquidationConfig::default(),
+            Arc::new(RateLimiter::default()),
+        )
+        .with_clock(Arc::new(clock.clone()));
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        
+        // A single price doesn't drift anywhere yet
+        let update = engine.position_update(&position.address).await.unwrap();
+        let liquidation_price = update.liquidation_price.unwrap();
+        let distance = update.distance_to_liquidation_bps.unwrap();
+        assert!((distance - (60000.0 - liquidation_price) * 10_000.0 / 60000.0).abs() < 1e-9);
+        assert_eq!(update.estimated_time_to_liquidation_secs, None);
+        
+        // Falling 1% a minute
+        clock.advance(60);
+        oracle.set_price("BTC/USD", 59400.0).await;
+        let update = engine.position_update(&position.address).await.unwrap();
+        let expected = (liquidation_price / 59400.0).ln() / (0.99f64.ln() / 60.0);
+        assert!((update.estimated_time_to_liquidation_secs.unwrap() - expected).abs() < 1e-6);
+        
+        // Rising over the two minutes, away from the liquidation price
+        clock.advance(60);
+        oracle.set_price("BTC/USD", 61000.0).await;
+        let update = engine.position_update(&position.address).await.unwrap();
+        assert_eq!(update.estimated_time_to_liquidation_secs, None);
+        
+        // and once past it there's no time left
+        clock.advance(60);
+        oracle.set_price("BTC/USD", 55000.0).await;
+        let update = engine.position_update(&position.address).await.unwrap();
+        assert!(update.distance_to_liquidation_bps.unwrap() < 0.0);
+        assert_eq!(update.estimated_time_to_liquidation_secs, Some(0.0));
+        
+        // Both are listed with the positions closest to liquidation
+        let stats = engine.get_stats().await.unwrap();
+        assert_eq!(stats.closest_to_liquidation[0].estimated_time_to_liquidation_secs, Some(0.0));
+        assert!(stats.closest_to_liquidation[0].distance_to_liquidation_bps.unwrap() < 0.0);
+    }
+    
+    #[tokio::test]
+    async fn test_position_updates_respect_min_delta() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 59000.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle.clone()), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        let mut events = engine.subscribe();
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        
+        let next_update = |events: &mut broadcast::Receiver<EngineEvent>| match events.try_recv() {
+            Ok(EngineEvent::PositionUpdate(update)) => Some(update),
+            Ok(other) => panic!("unexpected event: {:?}", other),
+            Err(_) => None,
+        };
+        
+        engine.check_positions().await.unwrap();
+        let update = next_update(&mut events).unwrap();
+        assert_eq!(update.address, position.address);
+        assert_eq!(update.status, PositionStatus::Active);
+        assert_eq!(update.mark_price, 59000.0);
+        
+        // A small move stays under the 0.1 percentage point delta
+        oracle.set_price("BTC/USD", 58990.0).await;
+        engine.check_positions().await.unwrap();
+        assert!(next_update(&mut events).is_none());
+        
+        oracle.set_price("BTC/USD", 58500.0).await;
+        engine.check_positions().await.unwrap();
+        let update = next_update(&mut events).unwrap();
+        assert_eq!(update.mark_price, 58500.0);
+        assert!(next_update(&mut events).is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_adl_queue_ranked_each_cycle() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 62000.0).await;
+        let config = LiquidationConfig {
+            insurance_fund_balance: 1000.0,
+            ..Default::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()));
+        let mut events = engine.subscribe();
+        // 2,000 of profit on 6,000 of margin at 7.75x scores 2.58
+        let long = create_test_position();
+        // 1,000 on 1,000 at 31x scores 31
+        let levered = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 61000.0, 1000.0, true);
+        let losing_short = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 0.1, 61000.0, 3000.0, false);
+        for position in [&long, &levered, &losing_short] {
+            engine.add_position(position.clone()).await.unwrap();
+        }
+        assert!(engine.get_adl_queue("BTC/USD").await.is_none());
+
+        engine.check_positions().await.unwrap();
+        let queue = engine.get_adl_queue("BTC/USD").await.unwrap();
+        let ranked: Vec<PositionAddress> = queue.longs.iter().map(|entry| entry.position).collect();
+        assert_eq!(ranked, [levered.address, long.address]);
+        assert!(queue.shorts.is_empty());
+
+        let mut quantiles = HashMap::new();
+        while let Ok(EngineEvent::PositionUpdate(update)) = events.try_recv() {
+            quantiles.insert(update.address, update.adl_quantile);
+        }
+        assert_eq!(quantiles[&levered.address], Some(1.0));
+        assert_eq!(quantiles[&long.address], Some(0.5));
+        assert_eq!(quantiles[&losing_short.address], None);
+        assert_eq!(engine.position_update(&long.address).await.unwrap().adl_quantile, Some(0.5));
+
+        // 1,000 of a bankrupt short's 2,500 is insured; the levered long gives up all
+        // its profit and the other a quarter of its own
+        let plan = engine.plan_adl("BTC/USD", false, 2500.0).await.unwrap();
+        assert_eq!(plan.insurance_covered, 1000.0);
+        let reductions: Vec<(PositionAddress, f64)> =
+            plan.reductions.iter().map(|cut| (cut.position, cut.size)).collect();
+        assert_eq!(reductions, [(levered.address, 1.0), (long.address, 0.25)]);
+        assert_eq!(plan.uncovered, 0.0);
+    }
+    
+    #[tokio::test]
+    async fn test_cycle_caps_throttle_cascade() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        oracle.set_price("SOL/USD", 97.0).await;
+        let config = LiquidationConfig {
+            whitelisted_symbols: vec![],
+            max_liquidations_per_symbol_per_cycle: Some(2),
+            max_notional_liquidated_per_cycle: Some(60000.0),
+            ..Default::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()));
+        // Halves of 27,500 notional for each BTC position and 4,850 for each SOL one
+        for _ in 0..5 {
+            engine.add_position(create_test_position()).await.unwrap();
+        }
+        for _ in 0..3 {
+            let sol = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "SOL/USD", 100.0, 100.0, 500.0, true);
+            engine.add_position(sol).await.unwrap();
+        }
+
+        let tally = |results: &[LiquidationResult]| {
+            let mut tally = (0, 0, 0);
+            for result in results {
+                match result {
+                    LiquidationResult::Success { .. } => tally.0 += 1,
+                    LiquidationResult::Skipped { reason: SkipReason::Throttled, .. } => tally.1 += 1,
+                    LiquidationResult::Skipped { reason: SkipReason::Cooldown, .. } => tally.2 += 1,
+                    other => panic!("unexpected result {:?}", other),
+                }
+            }
+            tally
+        };
+        // Two BTC liquidations fill that symbol's cap and one SOL liquidation most of
+        // the notional cap
+        assert_eq!(tally(&engine.check_positions().await.unwrap()), (3, 5, 0));
+        let stats = engine.throttle_stats();
+        assert_eq!(
+            stats.cycle_liquidations,
+            BTreeMap::from([("BTC/USD".to_string(), 2), ("SOL/USD".to_string(), 1)])
+        );
+        assert!((stats.cycle_notional - 59850.0).abs() < 1e-6);
+        assert_eq!((stats.cycle_throttled, stats.total_throttled), (5, 5));
+
+        // The caps start afresh for the throttled positions next cycle
+        assert_eq!(tally(&engine.check_positions().await.unwrap()), (3, 2, 3));
+        let stats = engine.throttle_stats();
+        assert_eq!((stats.cycle_throttled, stats.total_throttled), (2, 7));
+        assert_eq!(stats.paused_since, None);
+    }
+    
+    #[tokio::test]
+    async fn test_backlog_queued_and_auto_scaled() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        let clock = ManualClock::new(1_700_000_000);
+        let config = LiquidationConfig {
+            max_concurrent_liquidations: 1,
+            max_queue_age_secs: 10,
+            auto_scale_concurrency: true,
+            max_auto_scaled_concurrency: 2,
+            ..Default::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle.clone()), config, Arc::new(RateLimiter::default()))
+            .with_clock(Arc::new(clock.clone()));
+        for _ in 0..5 {
+            engine.add_position(create_test_position()).await.unwrap();
+        }
+        let successes = |results: &[LiquidationResult]| {
+            results.iter().filter(|result| matches!(result, LiquidationResult::Success { .. })).count()
+        };
+
+        // One liquidation a cycle leaves the rest queued
+        assert_eq!(successes(&engine.check_positions().await.unwrap()), 1);
+        assert_eq!((engine.queue_depth(), engine.oldest_queued_age()), (4, Some(0)));
+
+        // Once the backlog is overdue the next cycle liquidates two, at most
+        clock.advance(20);
+        assert_eq!(engine.oldest_queued_age(), Some(20));
+        assert_eq!(successes(&engine.check_positions().await.unwrap()), 1);
+        let stats = engine.throttle_stats();
+        assert_eq!((stats.queue_depth, stats.oldest_queued_age_secs, stats.concurrency), (3, Some(20), 2));
+        assert_eq!(successes(&engine.check_positions().await.unwrap()), 2);
+        assert_eq!(engine.throttle_stats().concurrency, 2);
+        let stats = engine.get_stats().await.unwrap();
+        assert_eq!((stats.queue_depth, stats.oldest_queued_age_secs), (1, Some(20)));
+
+        // The queued candidate is judged again at fresh prices, and leaves the
+        // queue once it's no longer liquidatable
+        oracle.set_price("BTC/USD", 60000.0).await;
+        assert_eq!(successes(&engine.check_positions().await.unwrap()), 0);
+        let stats = engine.throttle_stats();
+        assert_eq!((stats.queue_depth, stats.oldest_queued_age_secs, stats.concurrency), (0, None, 1));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_pauses_until_resumed() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 60000.0).await;
+        let config = LiquidationConfig {
+            circuit_breaker_liquidation_count: Some(2),
+            circuit_breaker_window_secs: 60,
+            ..Default::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle.clone()), config, Arc::new(RateLimiter::default()));
+        for _ in 0..4 {
+            engine.add_position(create_test_position()).await.unwrap();
+        }
+        let mut events = engine.subscribe();
+        assert!(engine.check_positions().await.unwrap().is_empty());
+
+        // The crash liquidates three before the breaker holds the fourth back
+        oracle.set_price("BTC/USD", 55000.0).await;
+        let results = engine.check_positions().await.unwrap();
+        let successes = results.iter().filter(|result| matches!(result, LiquidationResult::Success { .. })).count();
+        assert_eq!(successes, 3);
+        let paused: Vec<&LiquidationResult> = results
+            .iter()
+            .filter(|result| matches!(result, LiquidationResult::Skipped { reason: SkipReason::CircuitBreaker { .. }, .. }))
+            .collect();
+        assert_eq!(paused.len(), 1);
+        let paused_since = engine.paused_since().unwrap();
+        assert_eq!(engine.throttle_stats().paused_since, Some(paused_since));
+
+        // Positions stay monitored while paused
+        while events.try_recv().is_ok() {}
+        oracle.set_price("BTC/USD", 54000.0).await;
+        let results = engine.check_positions().await.unwrap();
+        assert!(!results.iter().any(|result| matches!(result, LiquidationResult::Success { .. })));
+        assert!(matches!(events.try_recv(), Ok(EngineEvent::PositionUpdate(_))));
+
+        assert!(engine.resume());
+        assert!(!engine.resume());
+        let results = engine.check_positions().await.unwrap();
+        let successes = results.iter().filter(|result| matches!(result, LiquidationResult::Success { .. })).count();
+        assert_eq!(successes, 1);
+        assert_eq!(engine.paused_since(), None);
+    }
+    
+    #[tokio::test]
+    async fn test_liquidation_events_published() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        let mut events = engine.subscribe();
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        engine.add_position(position.clone()).await.unwrap();
+        
+        engine.check_positions().await.unwrap();
+        match events.try_recv().unwrap() {
+            EngineEvent::PositionUpdate(update) => assert_eq!(update.status, PositionStatus::AtRisk),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match events.try_recv().unwrap() {
+            EngineEvent::Liquidation(event) => {
+                assert_eq!(event.position, position.address);
+                assert_eq!(event.owner, position.owner);
+                assert!(event.dry_run);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_external_id_echoed_in_events() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        let mut events = engine.subscribe();
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        engine.add_position(position.clone().with_metadata("external_id", "pos-8812")).await.unwrap();
+        
+        // Adding the position again merges its metadata into what's known
+        engine.add_position(position.clone().with_metadata("desk", "retail")).await.unwrap();
+        let found = engine.list_positions_by_metadata("external_id", "pos-8812").await;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].metadata["desk"], "retail");
+        assert!(engine.list_positions_by_metadata("external_id", "pos-1").await.is_empty());
+        
+        engine.check_positions().await.unwrap();
+        match events.try_recv().unwrap() {
+            EngineEvent::PositionUpdate(update) => assert_eq!(update.metadata["external_id"], "pos-8812"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match events.try_recv().unwrap() {
+            EngineEvent::Liquidation(event) => {
+                assert_eq!(event.position, position.address);
+                assert_eq!(event.metadata["external_id"], "pos-8812");
+                assert_eq!(event.metadata["desk"], "retail");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_priority_fee_recorded_in_event() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let fee_source = MockFeeSource::new();
+        fee_source.set_fees(vec![1_000, 2_000, 3_000, 400_000]).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let config = LiquidationConfig {
+            priority_fee_strategy: PriorityFeeStrategy::Percentile {
+                percentile: 75,
+                multiplier: 2.0,
+            },
+            ..Default::default()
+        };
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_fee_source(Arc::new(fee_source.clone()));
+        let mut events = engine.subscribe();
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        engine.check_position(position.clone()).await.unwrap();
+        match events.try_recv().unwrap() {
+            EngineEvent::Liquidation(event) => assert_eq!(event.priority_fee_micro_lamports, 6_000),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        
+        // A spike in recent fees is capped
+        fee_source.set_fees(vec![400_000]).await;
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        engine.check_position(position).await.unwrap();
+        match events.try_recv().unwrap() {
+            EngineEvent::Liquidation(event) => {
+                assert_eq!(event.priority_fee_micro_lamports, engine.config().max_priority_fee_micro_lamports)
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_repayment_checked_against_program() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 1.55).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let engine = create_submitting_engine(oracle, &submitter, Some(Keypair::new()));
+        
+        // Half of a debt of 1.5 base units rounds down to nothing, which the program rejects
+        let position = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 1.5, 0.0, true);
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason: SkipReason::RepayRoundsToZero, .. }) => {}
+            other => panic!("expected a skip, got {:?}", other),
+        }
+        assert!(submitter.submitted().is_empty());
+        
+        // A bankrupt position is closed, repaying no more than seizes all its collateral
+        let position = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1000.0, 1.6, 0.0, true);
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Success { .. }) => {}
+            other => panic!("expected a liquidation, got {:?}", other),
+        }
+        let submitted = submitter.submitted();
+        let liquidate = submitted[0].instructions.last().unwrap();
+        assert_eq!(&liquidate.data[8..], &1_477u64.to_le_bytes());
+    }
+    
+    #[tokio::test]
+    async fn test_priority_fee_priced_on_liquidation_accounts() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let fee_source = MockFeeSource::new();
+        let payer = Keypair::new();
+        let liquidator = payer.pubkey();
+        let engine =
+            create_submitting_engine(oracle, &submitter, Some(payer)).with_fee_source(Arc::new(fee_source.clone()));
+        let config = LiquidationConfig {
+            priority_fee_strategy: PriorityFeeStrategy::Percentile {
+                percentile: 75,
+                multiplier: 2.0,
+            },
+            ..(*engine.config()).clone()
+        };
+        *engine.config.write().unwrap() = Arc::new(config);
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        let address = position.address.pubkey();
+        
+        // A node without recent fees doesn't hold the liquidation back
+        fee_source.set_error(Some("Method not found")).await;
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Success { .. }) => {}
+            other => panic!("expected a liquidation, got {:?}", other),
+        }
+        let max_fee = engine.config().max_priority_fee_micro_lamports;
+        let submitted = submitter.submitted();
+        assert_eq!(submitted[0].instructions[1], compute::budget_instructions(120_000, max_fee)[1]);
+        
+        // Fees are looked up for the vaults and token accounts the liquidation locks
+        let market = engine.liquidation_market("BTC/USD").await.unwrap();
+        let token_accounts = market.token_accounts(&liquidator);
+        let accounts = vec![
+            address,
+            market.vault,
+            market.debt_vault,
+            token_accounts.debt,
+            token_accounts.collateral,
+            market.insurance_fund_vault,
+        ];
+        assert_eq!(fee_source.queries().await[0], accounts);
+    }
+    
+    #[tokio::test]
+    async fn test_compute_limit_simulated_once_per_symbol() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let simulator = MockSimulator::new(100_000);
+        let rpc_client = Arc::new(RpcClient::new_mock("succeeds".to_string()));
+        let config = LiquidationConfig {
+            dry_run: false,
+            compute_unit_headroom_percent: 15,
+            ..Default::default()
+        };
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(simulator.clone()))
+            .with_submitter(Arc::new(MockSubmitter::new()))
+            .with_liquidation_markets(create_liquidation_markets())
+            .with_payer(Keypair::new());
+        let liquidatable = || {
+            let mut position = create_test_position();
+            position.margin = 12000.0;
+            position
+        };
+        
+        let first = liquidatable();
+        for position in [first.clone(), liquidatable()] {
+            match engine.check_position(position).await.unwrap() {
+                Some(LiquidationResult::Success { compute_unit_limit, .. }) => assert_eq!(compute_unit_limit, 115_000),
+                other => panic!("expected a liquidation, got {:?}", other),
+            }
+        }
+        assert_eq!(simulator.simulations(), 1);
+        // Simulated as sent: the budget, then the first position's liquidation
+        let market = engine.liquidation_market("BTC/USD").await.unwrap();
+        let mut expected = compute::budget_instructions(MAX_COMPUTE_UNIT_LIMIT, 1_000);
+        expected.push(market.liquidate_instruction(first.address.pubkey(), engine.liquidator(), 19_000));
+        assert_eq!(simulator.simulated(), vec![expected]);
+        
+        // Repeated failures with the cached limit force a fresh simulation
+        simulator.set_units(200_000);
+        for _ in 0..engine.config().compute_estimate_max_failures {
+            engine.record_compute_outcome("BTC/USD", false);
+        }
+        match engine.check_position(liquidatable()).await.unwrap() {
+            Some(LiquidationResult::Success { compute_unit_limit, .. }) => assert_eq!(compute_unit_limit, 230_000),
+            other => panic!("expected a liquidation, got {:?}", other),
+        }
+        assert_eq!(simulator.simulations(), 2);
+        
+        // A failed simulation fails the attempt rather than guessing a limit
+        simulator.set_error("account not found");
+        engine.record_compute_outcome("BTC/USD", false);
+        engine.record_compute_outcome("BTC/USD", false);
+        engine.record_compute_outcome("BTC/USD", false);
+        let config = LiquidationConfig {
+            max_retries: 0,
+            ..(*engine.config()).clone()
+        };
+        *engine.config.write().unwrap() = Arc::new(config);
+        assert!(matches!(
+            engine.check_position(liquidatable()).await.unwrap(),
+            Some(LiquidationResult::Failure { attempts: 1, .. })
+        ));
+    }
+    
+    #[tokio::test]
+    async fn test_dry_run_uses_estimated_compute_units() {
+        let simulator = MockSimulator::new(100_000);
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(simulator.clone()));
+        
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Success { compute_unit_limit, .. }) => {
+                assert_eq!(compute_unit_limit, engine.config().estimated_compute_units)
+            }
+            other => panic!("expected a liquidation, got {:?}", other),
+        }
+        assert_eq!(simulator.simulations(), 0);
+    }
+    
+    /// A market of the program at `program_id` as preflight loads it
+    fn create_liquidation_market(program_id: Pubkey) -> LiquidationMarket {
+        LiquidationMarket {
+            program_id,
+            market_id: 0,
+            vault: Pubkey::new_unique(),
+            debt_vault: Pubkey::new_unique(),
+            insurance_fund_vault: Pubkey::new_unique(),
+            oracle: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            debt_mint: Pubkey::new_unique(),
+            collateral_token_program: anchor_spl::token::ID,
+            debt_token_program: anchor_spl::token::ID,
+            close_factor_bps: 5_000,
+            liquidation_bonus_bps: 500,
+            mint_decimals: liquidation_program::MintDecimals { collateral: 0, debt: 0 },
+        }
+    }
+    
+    /// The first market of the default program, which positions outside markets belong to
+    fn create_liquidation_markets() -> BTreeMap<Pubkey, LiquidationMarket> {
+        BTreeMap::from([(program_market_address(&PROGRAM_ID, 0), create_liquidation_market(PROGRAM_ID))])
+    }
+    
+    fn create_submitting_engine(oracle: MockOracle, submitter: &MockSubmitter, payer: Option<Keypair>) -> LiquidationEngine {
+        let rpc_client = Arc::new(RpcClient::new_mock("succeeds".to_string()));
+        let config = LiquidationConfig {
+            dry_run: false,
+            retry_delay_ms: 0,
+            ..Default::default()
+        };
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(MockSimulator::new(100_000)))
+            .with_submitter(Arc::new(submitter.clone()))
+            .with_liquidation_markets(create_liquidation_markets());
+        match payer {
+            Some(payer) => engine.with_payer(payer),
+            None => engine,
+        }
+    }
+    
+    #[cfg(feature = "storage")]
+    #[tokio::test]
+    async fn test_liquidation_published_once_through_both_paths() {
+        use crate::storage::{LiquidationStore, StoreWriter};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("state.json");
+        let store = Arc::new(LiquidationStore::open_in_memory().unwrap());
+        let (writer, handle) = StoreWriter::spawn(store.clone(), 16);
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let payer = Keypair::new();
+        let liquidator = payer.pubkey();
+        let engine = create_submitting_engine(oracle, &submitter, Some(payer))
+            .with_state_file(StateFile::load(&state_path).unwrap())
+            .with_store(writer);
+        let mut events = engine.subscribe();
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        engine.add_position(position.clone()).await.unwrap();
+        
+        let signature = match engine.check_position(position.clone()).await.unwrap() {
+            Some(LiquidationResult::Success { signature, .. }) => signature,
+            other => panic!("expected a liquidation, got {:?}", other),
+        };
+        // The program's logs then report the same transaction
+        let logged = ProgramEvent::Liquidated(events::PositionLiquidated {
+            position: position.address.into(),
+            liquidator,
+            repay_amount: 25_000,
+            collateral_seized: 1,
+            remaining_debt: 25_000,
+            timestamp: chrono::Utc::now().timestamp(),
+        });
+        engine.apply_logged_event(&signature, &logged).await;
+        
+        let mut published = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let EngineEvent::Liquidation(event) = event {
+                published.push(event);
+            }
+        }
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].signature, signature);
+        assert_eq!(engine.dedup_stats().deduped_events, 1);
+        drop(engine);
+        handle.await.unwrap();
+        assert_eq!(store.events_for_position(&position.address).unwrap().len(), 1);
+        
+        // The signature is still known after a restart
+        let submitter = MockSubmitter::new();
+        let engine = create_submitting_engine(MockOracle::new(), &submitter, None)
+            .with_state_file(StateFile::load(&state_path).unwrap());
+        assert_eq!(engine.dedup_stats().tracked_events, 1);
+        let mut events = engine.subscribe();
+        engine.record_liquidation(&published[0]).await;
+        assert!(events.try_recv().is_err());
+        assert_eq!(engine.dedup_stats().deduped_events, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_liquidation_submitted_with_compute_budget() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let payer = Keypair::new();
+        let payer_address = payer.pubkey();
+        let engine = create_submitting_engine(oracle, &submitter, Some(payer));
+        let mut events = engine.subscribe();
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        let address = position.address.pubkey();
+        
+        let signature = match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Success { signature, .. }) => signature,
+            other => panic!("expected a liquidation, got {:?}", other),
+        };
+        let submitted = submitter.submitted();
+        assert_eq!(submitted.len(), 1);
+        // Half the position repays half its 38,000 of debt, after the budget
+        let market = engine.liquidation_market("BTC/USD").await.unwrap();
+        let mut expected = compute::budget_instructions(120_000, 1_000);
+        expected.push(market.liquidate_instruction(address, payer_address, 19_000));
+        assert_eq!(submitted[0].instructions, expected);
+        match events.try_recv().unwrap() {
+            EngineEvent::Liquidation(event) => {
+                assert_eq!(event.signature, signature);
+                assert_eq!(event.liquidator, payer_address);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_liquidations_rotate_over_funded_keys() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let keypairs = vec![Keypair::new(), Keypair::new(), Keypair::new()];
+        let keys: Vec<Pubkey> = keypairs.iter().map(Keypair::pubkey).collect();
+        let engine = create_submitting_engine(oracle, &submitter, None).with_payers(keypairs);
+        // The second key fell below min_sol_balance
+        let config = engine.config();
+        assert!(engine.keys.record_balances(
+            &keys[1],
+            Some(0.01),
+            BTreeMap::new(),
+            config.min_sol_balance,
+            config.min_repay_token_balance
+        ));
+        let mut events = engine.subscribe();
+        
+        for _ in 0..3 {
+            let mut position = create_test_position();
+            position.margin = 12000.0;
+            match engine.check_position(position).await.unwrap() {
+                Some(LiquidationResult::Success { .. }) => {}
+                other => panic!("expected a liquidation, got {:?}", other),
+            }
+        }
+        let mut liquidators = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let EngineEvent::Liquidation(event) = event {
+                liquidators.push(event.liquidator);
+            }
+        }
+        // Round robin over the funded keys, each event naming the key that signed
+        assert_eq!(liquidators, [keys[0], keys[2], keys[0]]);
+        assert_eq!(submitter.submitted().len(), 3);
+        let status = engine.key_status();
+        assert_eq!(status.iter().map(|key| key.liquidations).collect::<Vec<_>>(), [2, 0, 1]);
+        assert!(status[1].underfunded && status[1].last_used.is_none());
+        
+        // Disabling the rest leaves no key to sign with
+        engine.set_key_enabled(&keys[0], false).unwrap();
+        engine.set_key_enabled(&keys[2], false).unwrap();
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Failure { error, .. }) => {
+                assert!(error.contains("below its balance floors"), "{}", error)
+            }
+            other => panic!("expected a failure, got {:?}", other),
+        }
+        assert_eq!(submitter.submitted().len(), 3);
+    }
+    
+    #[tokio::test]
+    async fn test_other_pool_keys_liquidations_are_ours() {
+        let keypairs = vec![Keypair::new(), Keypair::new()];
+        let second = keypairs[1].pubkey();
+        let engine = create_submitting_engine(MockOracle::new(), &MockSubmitter::new(), None).with_payers(keypairs);
+        let mut events = engine.subscribe();
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        
+        // Logged by a key of the pool other than the first, so published as ours
+        let logged = ProgramEvent::Liquidated(events::PositionLiquidated {
+            position: position.address.into(),
+            liquidator: second,
+            repay_amount: 25_000,
+            collateral_seized: 1,
+            remaining_debt: 25_000,
+            timestamp: chrono::Utc::now().timestamp(),
+        });
+        engine.apply_logged_event(&Signature::new_unique().to_string(), &logged).await;
+        let mut published = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let EngineEvent::Liquidation(event) = event {
+                published.push(event);
+            }
+        }
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].liquidator, second);
+        assert_eq!(engine.key_status()[1].liquidations, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_modes_hold_back_submissions() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let engine = create_submitting_engine(oracle, &submitter, Some(Keypair::new()));
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        engine.add_position(position.clone()).await.unwrap();
+        let mut events = engine.subscribe();
+        
+        // Monitor-only evaluates and publishes the liquidatable position, but skips it
+        assert_eq!(engine.set_mode(EngineMode::MonitorOnly, "test"), EngineMode::Running);
+        let results = engine.check_positions().await.unwrap();
+        match results.as_slice() {
+            [LiquidationResult::Skipped { position: address, reason }] => {
+                assert_eq!(*address, position.address);
+                assert_eq!(*reason, SkipReason::MonitorOnly);
+            }
+            other => panic!("expected a monitor-only skip, got {:?}", other),
+        }
+        match events.try_recv().unwrap() {
+            EngineEvent::PositionUpdate(update) => assert_eq!(update.status, PositionStatus::AtRisk),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(events.try_recv().is_err());
+        
+        // Paused checks nothing at all
+        engine.set_mode(EngineMode::Paused, "test");
+        assert!(engine.check_positions().await.unwrap().is_empty());
+        assert!(engine.check_position(position.clone()).await.unwrap().is_none());
+        assert!(events.try_recv().is_err());
+        assert!(submitter.submitted().is_empty());
+        let status = engine.mode_status();
+        assert_eq!((status.mode, status.source.as_str(), status.changes), (EngineMode::Paused, "test", 2));
+        
+        // Nothing is submitted in a batch either
+        let market = create_liquidation_market(PROGRAM_ID);
+        let entry = BatchEntry::from_instruction(
+            position.address.into(),
+            market.liquidate_instruction(position.address.into(), engine.liquidator(), 19_000),
+            100_000,
+        );
+        let batch = TransactionBatch { entries: vec![entry] };
+        let err = engine.send_batch(&batch, &[], engine.signer().unwrap()).await.unwrap_err();
+        assert!(matches!(err, LiquidationError::SubmissionsDisabled(EngineMode::Paused)));
+        
+        // Back to running, the position is liquidated
+        engine.set_mode(EngineMode::Running, "test");
+        let results = engine.check_positions().await.unwrap();
+        assert!(matches!(results.as_slice(), [LiquidationResult::Success { .. }]));
+        assert_eq!(submitter.submitted().len(), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_mode_dated_by_engine_clock() {
+        let clock = ManualClock::new(1_700_000_000);
+        let engine = create_engine(LiquidationConfig::default()).with_clock(Arc::new(clock.clone()));
+        assert_eq!(engine.mode_status().since, 1_700_000_000);
+        
+        clock.advance(60);
+        engine.set_mode(EngineMode::Paused, "test");
+        assert_eq!(engine.mode_status().since, 1_700_000_060);
+        
+        // Changed modes keep their time when the clock is swapped after
+        let engine = engine.with_clock(Arc::new(ManualClock::new(0)));
+        assert_eq!(engine.mode_status().since, 1_700_000_060);
+    }
+    
+    #[tokio::test]
+    async fn test_warm_up_waits_for_every_price() {
+        let oracle = Arc::new(MockOracle::new());
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let config = LiquidationConfig {
+            min_liquidation_interval_secs: 0,
+            ..Default::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, oracle.clone(), config, Arc::new(RateLimiter::default()));
+        let btc = create_test_position();
+        engine.add_position(btc.clone()).await.unwrap();
+        let eth = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "ETH/USD", 10.0, 3000.0, 10000.0, true);
+        engine.add_position(eth).await.unwrap();
+        let held_back = |results: &[LiquidationResult]| {
+            results.iter().any(|result| {
+                matches!(result, LiquidationResult::Skipped { position, reason } if *position == btc.address && *reason == SkipReason::WarmingUp)
+            })
+        };
+        
+        // Without an ETH price the liquidatable BTC position is only evaluated
+        engine.begin_warm_up();
+        for _ in 0..5 {
+            assert!(held_back(&engine.check_positions().await.unwrap()));
+        }
+        let status = engine.warm_up_status();
+        assert_eq!(status.phase, EnginePhase::WarmingUp);
+        assert_eq!(status.unpriced_symbols, ["ETH/USD"]);
+        assert_eq!(status.cycles_completed, 0);
+        
+        // Once it appears, the engine runs two more cycles before liquidating
+        oracle.set_price("ETH/USD", 3000.0).await;
+        for _ in 0..2 {
+            assert!(held_back(&engine.check_positions().await.unwrap()));
+        }
+        assert_eq!(engine.warm_up_status().cycles_completed, 2);
+        let results = engine.check_positions().await.unwrap();
+        assert!(
+            results.iter().any(|result| matches!(result, LiquidationResult::Success { position, .. } if *position == btc.address)),
+            "{:?}",
+            results
+        );
+        assert!(engine.warm_up_status().is_ready());
+    }
+    
+    #[tokio::test]
+    async fn test_unpriced_symbols_skip_only_their_positions() {
+        let oracle = Arc::new(MockOracle::new());
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine =
+            LiquidationEngine::new(rpc_client, oracle.clone(), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        let btc = create_test_position();
+        engine.add_position(btc.clone()).await.unwrap();
+        // A market delisted from the oracle, as underwater as it gets
+        let delisted = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "DELISTED/USD", 10.0, 3000.0, 3000.0, true);
+        engine.add_position(delisted.clone()).await.unwrap();
+        
+        let results = engine.check_positions().await.unwrap();
+        assert_eq!(results.len(), 2, "{:?}", results);
+        assert!(results.iter().any(|result| matches!(
+            result,
+            LiquidationResult::Success { position, .. } if *position == btc.address
+        )));
+        assert!(
+            results.iter().any(|result| matches!(
+                result,
+                LiquidationResult::Skipped { position, reason: SkipReason::PriceUnavailable(reason) }
+                    if *position == delisted.address && reason == "Oracle error: No price for DELISTED/USD"
+            )),
+            "{:?}",
+            results
+        );
+        assert_eq!(engine.throttle_stats().total_skipped["price_unavailable"], 1);
+        
+        // Priced again, the position is checked like any other
+        oracle.set_price("DELISTED/USD", 2800.0).await;
+        let results = engine.check_positions().await.unwrap();
+        assert!(
+            results.iter().any(|result| matches!(
+                result,
+                LiquidationResult::Success { position, .. } if *position == delisted.address
+            )),
+            "{:?}",
+            results
+        );
+        assert_eq!(engine.throttle_stats().total_skipped["price_unavailable"], 1);
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_concurrent_checks_liquidate_once() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let engine = Arc::new(create_submitting_engine(oracle, &submitter, Some(Keypair::new())));
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        engine.add_position(position.clone()).await.unwrap();
+        
+        let checks: Vec<_> = (0..100)
+            .map(|_| {
+                let engine = engine.clone();
+                let position = position.clone();
+                tokio::spawn(async move { engine.check_position(position).await.unwrap() })
+            })
+            .collect();
+        let mut liquidations = 0;
+        for check in checks {
+            match check.await.unwrap() {
+                Some(LiquidationResult::Success { .. }) => liquidations += 1,
+                Some(LiquidationResult::Skipped { .. }) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        assert_eq!(liquidations, 1);
+        assert_eq!(submitter.submitted().len(), 1);
+        assert!(!engine.in_flight.contains(&position.address));
+        
+        // Checks that start once it settled see its cooldown, however old their copy
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => assert_eq!(reason, SkipReason::Cooldown),
+            other => panic!("expected cooldown, got {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_in_flight_released_on_oracle_error() {
+        let engine = create_engine(LiquidationConfig::default());
+        let position = create_test_position();
+        
+        // Nothing prices BTC/USD, so the check fails part way
+        assert!(engine.check_position(position.clone()).await.is_err());
+        assert!(!engine.in_flight.contains(&position.address));
+        
+        // A position already being checked is skipped
+        let _guard = engine.in_flight.acquire(position.address).unwrap();
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => assert_eq!(reason, SkipReason::InFlight),
+            other => panic!("expected a skip, got {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_outlier_price_skipped_until_sustained() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 59000.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(
+            rpc_client,
+            Arc::new(oracle.clone()),
+            LiquidationConfig::default(),
+            Arc::new(RateLimiter::default()),
+        );
+        let position = create_test_position();
+        engine.add_position(position.clone()).await.unwrap();
+        assert!(engine.check_positions().await.unwrap().is_empty());
+        
+        // A 40% crash is only believed on the third read
+        oracle.set_price("BTC/USD", 35400.0).await;
+        assert!(engine.check_positions().await.unwrap().is_empty());
+        assert!(matches!(
+            engine.check_position(position).await,
+            Err(LiquidationError::PriceOutlier { last_accepted, .. }) if last_accepted == 59000.0
+        ));
+        assert!(engine.check_positions().await.unwrap().is_empty());
+        let results = engine.check_positions().await.unwrap();
+        assert!(matches!(results.as_slice(), [LiquidationResult::Success { .. }]), "{:?}", results);
+    }
+    
+    #[tokio::test]
+    async fn test_failed_submissions_are_retried() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        submitter.set_error(Some("blockhash not found"));
+        let engine = create_submitting_engine(oracle, &submitter, Some(Keypair::new()));
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Failure { attempts, error, .. }) => {
+                assert_eq!(attempts, 4);
+                assert!(error.contains("blockhash not found"), "{}", error);
+            }
+            other => panic!("expected a failure, got {:?}", other),
+        }
+        assert_eq!(submitter.submitted().len(), 4);
+    }
+    
+    #[tokio::test]
+    async fn test_submission_requires_payer() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let engine = create_submitting_engine(oracle, &submitter, None);
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Failure { error, .. }) => assert!(error.contains("no payer keypair"), "{}", error),
+            other => panic!("expected a failure, got {:?}", other),
+        }
+        assert!(submitter.submitted().is_empty());
+    }
+    
+    /// Notifier remembering the alerts it was handed, in order
+    #[derive(Debug, Default)]
+    struct RecordingNotifier(std::sync::Mutex<Vec<(AlertLevel, String)>>);
+    
+    #[async_trait::async_trait]
+    impl Notifier for RecordingNotifier {
+        async fn notify(&self, level: AlertLevel, title: &str, _body: &str) -> StdResult<(), LiquidationError> {
+            self.0.lock().unwrap().push((level, title.to_string()));
+            Ok(())
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_failures_in_a_row_and_pauses_alerted() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let notifier = Arc::new(RecordingNotifier::default());
+        let triggers = [AlertTrigger::ConsecutiveFailures, AlertTrigger::Paused];
+        let (alerts, _) = Alerter::spawn(notifier.clone(), triggers, Duration::ZERO, DEFAULT_ALERT_QUEUE_CAPACITY);
+        let engine = create_submitting_engine(oracle, &submitter, None).with_alerts(alerts.clone());
+        
+        // Without a payer every liquidation fails, and the third in a row is alerted
+        for _ in 0..3 {
+            let mut position = create_test_position();
+            position.margin = 12000.0;
+            let result = engine.check_position(position).await.unwrap();
+            assert!(matches!(result, Some(LiquidationResult::Failure { .. })), "{:?}", result);
+        }
+        engine.set_mode(EngineMode::Paused, "ops");
+        engine.set_mode(EngineMode::MonitorOnly, "ops");
+        engine.set_mode(EngineMode::Running, "ops");
+        
+        for _ in 0..100 {
+            if alerts.stats().sent >= 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            *notifier.0.lock().unwrap(),
+            [
+                (AlertLevel::Critical, "3 liquidations failed in a row".to_string()),
+                (AlertLevel::Warning, "Engine paused".to_string()),
+                (AlertLevel::Info, "Engine unpaused".to_string()),
+            ]
+        );
+    }
+    
+    /// RPC node answering each method from a queue of responses and recording
+    /// every request it receives
+    #[derive(Clone, Default)]
+    struct ScriptedRpc {
+        responses: Arc<std::sync::Mutex<HashMap<String, VecDeque<serde_json::Value>>>>,
+        requests: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+    
+    impl ScriptedRpc {
+        fn push(&self, method: &str, result: serde_json::Value) {
+            self.responses
+                .lock()
+                .unwrap()
+                .entry(method.to_string())
+                .or_default()
+                .push_back(result);
+        }
+        
+        fn push_latest_blockhash(&self, blockhash: &Hash) {
+            self.push(
+                "getLatestBlockhash",
+                json!({
+                    "context": { "slot": 1 },
+                    "value": { "blockhash": blockhash.to_string(), "lastValidBlockHeight": 100 }
+                }),
+            );
+        }
+        
+        fn push_nonce_account(&self, address: &Pubkey, authority: &Pubkey, blockhash: &Hash) {
+            let state = State::Initialized(Data::new(*authority, DurableNonce::from_blockhash(blockhash), 5000));
+            let account = Account {
+                lamports: 1_447_680,
+                data: bincode::serialize(&Versions::new(state)).unwrap(),
+                owner: solana_sdk::system_program::id(),
+                executable: false,
+                rent_epoch: 0,
+            };
+            let account = UiAccount::encode(address, &account, UiAccountEncoding::Base64, None, None);
+            self.push("getAccountInfo", json!({ "context": { "slot": 1 }, "value": account }));
+        }
+        
+        fn requests(&self, method: &str) -> usize {
+            self.requests.lock().unwrap().iter().filter(|request| *request == method).count()
+        }
+        
+        fn client(&self) -> Arc<RpcClient> {
+            Arc::new(RpcClient::new_sender(self.clone(), RpcClientConfig::default()))
+        }
+    }
+    
+    #[async_trait::async_trait]
+    impl RpcSender for ScriptedRpc {
+        async fn send(&self, request: RpcRequest, _params: serde_json::Value) -> ClientResult<serde_json::Value> {
+            let method = request.to_string();
+            self.requests.lock().unwrap().push(method.clone());
+            // The client checks the node version before some calls
+            if method == "getVersion" {
+                return Ok(json!({ "solana-core": "1.18.26" }));
+            }
+            self.responses
+                .lock()
+                .unwrap()
+                .get_mut(&method)
+                .and_then(VecDeque::pop_front)
+                .ok_or_else(|| ClientErrorKind::Custom(format!("no response queued for {}", method)).into())
+        }
+        
+        fn get_transport_stats(&self) -> RpcTransportStats {
+            RpcTransportStats::default()
+        }
+        
+        fn url(&self) -> String {
+            "scripted".to_string()
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_market_positions_loaded_from_accounts() {
+        let rpc = ScriptedRpc::default();
+        let engine = LiquidationEngine::new(
+            rpc.client(),
+            Arc::new(MockOracle::new()),
+            LiquidationConfig::default(),
+            Arc::new(RateLimiter::default()),
+        );
+        let market = MarketConfig {
+            mint_decimals: MintDecimals { collateral: 9, debt: 6 },
+            ..MarketConfig::new(Pubkey::new_unique(), "SOL/USD", 0.05)
+        };
+        let keyed = |address: &PositionAddress, data: &[u8]| {
+            let account = Account {
+                lamports: 1_287_600,
+                data: data.to_vec(),
+                owner: market.program_id,
+                executable: false,
+                rent_epoch: 0,
+            };
+            json!({
+                "pubkey": address.to_string(),
+                "account": UiAccount::encode(address.as_ref(), &account, UiAccountEncoding::Base64, None, None),
+            })
+        };
+        let mut account = health::decode_position_account(include_bytes!("../fixtures/accounts/position.bin")).unwrap();
+        let (open, bump) = Pubkey::find_program_address(
+            &[b"position", market.address().as_ref(), account.owner.as_ref()],
+            &market.program_id,
+        );
+        account.bump = bump;
+        let (open, foreign) = (PositionAddress::from(open), PositionAddress::new_unique());
+        let data = &health::encode_position_account(&account)[..];
+        let accounts = |slot: u64, accounts: Vec<serde_json::Value>| json!({ "context": { "slot": slot }, "value": accounts });
+        rpc.push(
+            "getProgramAccounts",
+            accounts(10, vec![keyed(&open, data), keyed(&foreign, &[0; POSITION_ACCOUNT_LEN]), keyed(&foreign, data)]),
+        );
+        
+        // The account with another discriminator is skipped, as is the one
+        // that isn't the market's position
+        assert_eq!(engine.load_market_positions(&market).await.unwrap(), 1);
+        assert!(engine.get_position(&foreign).await.is_none());
+        let position = engine.get_position(&open).await.unwrap();
+        assert_eq!((position.symbol.as_str(), position.size), ("SOL/USD", 567.0));
+        assert!((position.size * position.entry_price - 60_000.0).abs() < 1e-6);
+        
+        // Reloading keeps what the engine knows of the position, metadata
+        // included, and drops it once the account is closed
+        engine.add_position(position.with_metadata("external_id", "pos-8812")).await.unwrap();
+        engine.positions.write().await.get_mut(&open).unwrap().last_liquidated = Some(1_700_000_000);
+        rpc.push("getProgramAccounts", accounts(11, vec![keyed(&open, data)]));
+        engine.load_market_positions(&market).await.unwrap();
+        let reloaded = engine.get_position(&open).await.unwrap();
+        assert_eq!(reloaded.last_liquidated, Some(1_700_000_000));
+        assert_eq!(reloaded.metadata["external_id"], "pos-8812");
+        let mut closed = account.clone();
+        closed.closed = true;
+        rpc.push("getProgramAccounts", accounts(12, vec![keyed(&open, &health::encode_position_account(&closed))]));
+        assert_eq!(engine.load_market_positions(&market).await.unwrap(), 0);
+        assert!(engine.get_position(&open).await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_position_writes_reconcile_by_version() {
+        let rpc = ScriptedRpc::default();
+        let engine = LiquidationEngine::new(
+            rpc.client(),
+            Arc::new(MockOracle::new()),
+            LiquidationConfig::default(),
+            Arc::new(RateLimiter::default()),
+        );
+        let market = MarketConfig {
+            mint_decimals: MintDecimals { collateral: 9, debt: 6 },
+            ..MarketConfig::new(Pubkey::new_unique(), "SOL/USD", 0.05)
+        };
+        let address = PositionAddress::new_unique();
+        let account = health::decode_position_account(include_bytes!("../fixtures/accounts/position.bin")).unwrap();
+        let mut deposited = account.clone();
+        deposited.collateral = 700_000_000_000;
+        
+        // The chain holds 567 SOL at slot 100
+        assert!(engine.apply_position_account(address, &account, &market, 100).await);
+        assert_eq!(engine.position_version(&address), Some(100));
+        let chain = engine.get_position(&address).await.unwrap();
+        
+        // An upstream write from before then, retried late, loses to it
+        let old = Position { size: 500.0, ..chain.clone() };
+        let write = PositionWrite {
+            version: Some(90),
+            idempotency_key: Some("req-1".to_string()),
+        };
+        match engine.write_position(old.clone(), &write).await {
+            Err(LiquidationError::StaleUpdate { current_version, attempted_version }) => {
+                assert_eq!((current_version, attempted_version), (100, 90))
+            }
+            other => panic!("expected a stale update, got {:?}", other),
+        }
+        assert_eq!(engine.get_position(&address).await.unwrap().size, 567.0);
+        
+        // A newer write wins over an older chain read, and its retries are ignored
+        let newer = Position { size: 650.0, ..chain.clone() };
+        let write = PositionWrite {
+            version: Some(120),
+            idempotency_key: Some("req-2".to_string()),
+        };
+        assert_eq!(engine.write_position(newer.clone(), &write).await.unwrap(), WriteOutcome::Applied);
+        assert!(engine.apply_position_account(address, &deposited, &market, 110).await);
+        assert_eq!(engine.get_position(&address).await.unwrap().size, 650.0);
+        assert_eq!(engine.write_position(old, &write).await.unwrap(), WriteOutcome::Duplicate);
+        assert_eq!(engine.get_position(&address).await.unwrap().size, 650.0);
+        
+        // until the chain moves past it
+        assert!(engine.apply_position_account(address, &deposited, &market, 130).await);
+        assert_eq!(engine.get_position(&address).await.unwrap().size, 700.0);
+        assert_eq!(engine.position_version(&address), Some(130));
+        
+        // Writes without a version always apply, and removal forgets the version
+        engine.add_position(newer).await.unwrap();
+        assert_eq!(engine.get_position(&address).await.unwrap().size, 650.0);
+        engine.remove_position(&address).await;
+        assert_eq!(engine.position_version(&address), None);
+    }
+    
+    #[tokio::test]
+    async fn test_deposit_before_submission_skips_liquidation() {
+        let oracle = MockOracle::new();
+        oracle.set_price("SOL/USD", 110.0).await;
+        let market = MarketConfig {
+            mint_decimals: MintDecimals { collateral: 9, debt: 6 },
+            ..MarketConfig::new(Pubkey::new_unique(), "SOL/USD", 0.05)
+        };
+        let rpc = ScriptedRpc::default();
+        let config = LiquidationConfig {
+            dry_run: false,
+            markets: vec![market.clone()],
+            ..Default::default()
+        };
+        let submitter = MockSubmitter::new();
+        let engine = LiquidationEngine::new(rpc.client(), Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(MockSimulator::new(100_000)))
+            .with_submitter(Arc::new(submitter.clone()))
+            .with_status_poller(Arc::new(MockStatusPoller::new()))
+            .with_payer(Keypair::new());
+        let mut events = engine.subscribe();
+        let encoded = |account: &PositionAccount| {
+            let account = Account {
+                lamports: 1_287_600,
+                data: health::encode_position_account(account),
+                owner: market.program_id,
+                executable: false,
+                rent_epoch: 0,
+            };
+            json!({ "context": { "slot": 1 }, "value": UiAccount::encode(&Pubkey::default(), &account, UiAccountEncoding::Base64, None, None) })
+        };
+        
+        // 567 SOL against 60,000 of debt is under the 5% maintenance margin at 110
+        let address = PositionAddress::new_unique();
+        let account = health::decode_position_account(include_bytes!("../fixtures/accounts/position.bin")).unwrap();
+        assert!(engine.apply_position_account(address, &account, &market, 1).await);
+        engine.republish_position(&address).await;
+        match events.try_recv() {
+            Ok(EngineEvent::PositionUpdate(update)) => assert_eq!(update.status, PositionStatus::AtRisk),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        
+        // The owner tops up to 700 SOL after the position was checked, before
+        // the liquidation is submitted
+        let mut deposited = account.clone();
+        deposited.collateral = 700_000_000_000;
+        rpc.push("getAccountInfo", encoded(&deposited));
+        let stale = engine.get_position(&address).await.unwrap();
+        match engine.check_position(stale).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => assert_eq!(reason, SkipReason::CollateralUpdated),
+            other => panic!("expected the liquidation to be skipped, got {:?}", other),
+        }
+        assert!(submitter.submitted().is_empty());
+        assert_eq!(engine.get_position(&address).await.unwrap().size, 700.0);
+        match events.try_recv() {
+            Ok(EngineEvent::PositionUpdate(update)) => assert_eq!(update.status, PositionStatus::Active),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        
+        // Account changes seen by the subscription move the status at once: a
+        // withdrawal back under water, then a deposit
+        assert!(engine.apply_position_account(address, &account, &market, 2).await);
+        engine.republish_position(&address).await;
+        assert!(matches!(
+            events.try_recv(),
+            Ok(EngineEvent::PositionUpdate(update)) if update.status == PositionStatus::AtRisk
+        ));
+        assert!(engine.apply_position_account(address, &deposited, &market, 3).await);
+        engine.republish_position(&address).await;
+        assert!(matches!(
+            events.try_recv(),
+            Ok(EngineEvent::PositionUpdate(update)) if update.status == PositionStatus::Active
+        ));
+        // Updates that leave the status as it was aren't pushed
+        engine.republish_position(&address).await;
+        assert!(events.try_recv().is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_divergent_cache_corrected_before_submission() {
+        let oracle = MockOracle::new();
+        oracle.set_price("SOL/USD", 55.0).await;
+        let market = MarketConfig {
+            mint_decimals: MintDecimals { collateral: 9, debt: 6 },
+            ..MarketConfig::new(Pubkey::new_unique(), "SOL/USD", 0.05)
+        };
+        let rpc = ScriptedRpc::default();
+        let config = LiquidationConfig {
+            dry_run: false,
+            markets: vec![market.clone()],
+            ..Default::default()
+        };
+        let submitter = MockSubmitter::new();
+        let engine = LiquidationEngine::new(rpc.client(), Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(MockSimulator::new(100_000)))
+            .with_submitter(Arc::new(submitter.clone()))
+            .with_status_poller(Arc::new(MockStatusPoller::new()))
+            .with_payer(Keypair::new());
+        
+        // The cache holds 567 SOL against 60,000 of debt, worth half the debt at 55
+        let address = PositionAddress::new_unique();
+        let account = health::decode_position_account(include_bytes!("../fixtures/accounts/position.bin")).unwrap();
+        assert!(engine.apply_position_account(address, &account, &market, 1).await);
+        
+        // The chain holds 2,000 SOL, well clear of the maintenance margin
+        let mut healthy = account.clone();
+        healthy.collateral = 2_000_000_000_000;
+        let encoded = Account {
+            lamports: 1_287_600,
+            data: health::encode_position_account(&healthy),
+            owner: market.program_id,
+            executable: false,
+            rent_epoch: 0,
+        };
+        rpc.push(
+            "getAccountInfo",
+            json!({ "context": { "slot": 2 }, "value": UiAccount::encode(&Pubkey::default(), &encoded, UiAccountEncoding::Base64, None, None) }),
+        );
+        let cached = engine.get_position(&address).await.unwrap();
+        match engine.check_position(cached).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => assert_eq!(reason, SkipReason::CollateralUpdated),
+            other => panic!("expected the liquidation to be skipped, got {:?}", other),
+        }
+        assert!(submitter.submitted().is_empty());
+        assert_eq!(rpc.requests("getAccountInfo"), 1);
+        
+        // The cache takes the chain's copy, and the divergence is counted
+        assert_eq!(engine.get_position(&address).await.unwrap().size, 2000.0);
+        let stats = engine.verification_stats();
+        assert_eq!((stats.verified, stats.divergences), (1, 1));
+        let divergence = stats.last_divergence.unwrap();
+        assert_eq!(divergence.position, address);
+        assert_eq!(divergence.price, 55.0);
+        assert_eq!(divergence.size_delta, 1433.0);
+        assert!(divergence.chain_health_factor > divergence.cached_health_factor);
+        assert!(divergence.health_factor_delta() > 0.0);
+        assert_eq!(engine.throttle_stats().total_skipped.get("collateral_updated"), Some(&1));
+    }
+    
+    #[tokio::test]
+    async fn test_verification_off_reads_nothing_from_chain() {
+        let oracle = MockOracle::new();
+        oracle.set_price("SOL/USD", 55.0).await;
+        let market = MarketConfig {
+            mint_decimals: MintDecimals { collateral: 9, debt: 6 },
+            ..MarketConfig::new(Pubkey::new_unique(), "SOL/USD", 0.05)
+        };
+        let rpc = ScriptedRpc::default();
+        let config = LiquidationConfig {
+            dry_run: false,
+            verify_before_submit: false,
+            markets: vec![market.clone()],
+            ..Default::default()
+        };
+        let engine = LiquidationEngine::new(rpc.client(), Arc::new(oracle), config, Arc::new(RateLimiter::default()));
+        let address = PositionAddress::new_unique();
+        let account = health::decode_position_account(include_bytes!("../fixtures/accounts/position.bin")).unwrap();
+        assert!(engine.apply_position_account(address, &account, &market, 1).await);
+        
+        let position = engine.get_position(&address).await.unwrap();
+        assert_eq!(engine.refetch_position(&position).await.unwrap(), Some(position));
+        assert_eq!(rpc.requests("getAccountInfo"), 0);
+        assert_eq!(engine.verification_stats(), VerificationStats::default());
+    }
+    
+    #[tokio::test]
+    async fn test_nonce_advanced_first_and_refreshed_after_use() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let payer = Keypair::new();
+        let nonce_config = NonceConfig {
+            account: Pubkey::new_unique(),
+            authority: payer.pubkey(),
+        };
+        let values = [Hash::new_unique(), Hash::new_unique(), Hash::new_unique()];
+        let rpc = ScriptedRpc::default();
+        for value in &values {
+            rpc.push_nonce_account(&nonce_config.account, &payer.pubkey(), value);
+        }
+        let durable = |i: usize| *DurableNonce::from_blockhash(&values[i]).as_hash();
+        
+        let submitter = MockSubmitter::new();
+        // The nonce moved on under the first attempt; the second fails for an
+        // unrelated reason and the third lands
+        submitter.fail_next("Transaction simulation failed: Blockhash not found");
+        submitter.fail_next("connection reset by peer");
+        let config = LiquidationConfig {
+            dry_run: false,
+            retry_delay_ms: 0,
+            nonce: Some(nonce_config.clone()),
+            ..Default::default()
+        };
+        let engine = LiquidationEngine::new(rpc.client(), Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(MockSimulator::new(100_000)))
+            .with_submitter(Arc::new(submitter.clone()))
+            .with_status_poller(Arc::new(MockStatusPoller::new()))
+            .with_liquidation_markets(create_liquidation_markets())
+            .with_payer(payer);
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        assert!(matches!(
+            engine.check_position(position).await.unwrap(),
+            Some(LiquidationResult::Success { .. })
+        ));
+        let submitted = submitter.submitted();
+        let blockhashes: Vec<Hash> = submitted.iter().map(|submission| submission.blockhash).collect();
+        assert_eq!(blockhashes, vec![durable(0), durable(1), durable(1)]);
+        let advance = system_instruction::advance_nonce_account(&nonce_config.account, &nonce_config.authority);
+        for submission in &submitted {
+            assert_eq!(submission.instructions[0], advance);
+            assert_eq!(submission.instructions[1..3], compute::budget_instructions(120_000, 1_000));
+            assert_eq!(submission.instructions[3].program_id, PROGRAM_ID);
+        }
+        
+        // Read once up front, after the mismatch and after the landed transaction
+        assert_eq!(rpc.requests("getAccountInfo"), 3);
+        assert_eq!(rpc.requests("getLatestBlockhash"), 0);
+        assert_eq!(engine.nonce.as_ref().unwrap().value().await.unwrap(), durable(2));
+    }
+    
+    #[tokio::test]
+    async fn test_nonce_authority_must_be_payer() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let submitter = MockSubmitter::new();
+        let config = LiquidationConfig {
+            dry_run: false,
+            max_retries: 0,
+            nonce: Some(NonceConfig {
+                account: Pubkey::new_unique(),
+                authority: Pubkey::new_unique(),
+            }),
+            ..Default::default()
+        };
+        let engine = LiquidationEngine::new(ScriptedRpc::default().client(), Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(MockSimulator::new(100_000)))
+            .with_submitter(Arc::new(submitter.clone()))
+            .with_payer(Keypair::new());
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Failure { error, .. }) => assert!(error.contains("nonce authority"), "{}", error),
+            other => panic!("expected a failure, got {:?}", other),
+        }
+        assert!(submitter.submitted().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_liquidation_refused_without_program_market() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        // The program's market was never initialized
+        let rpc = ScriptedRpc::default();
+        rpc.push("getAccountInfo", json!({ "context": { "slot": 1 }, "value": null }));
+        let submitter = MockSubmitter::new();
+        let config = LiquidationConfig {
+            dry_run: false,
+            retry_delay_ms: 0,
+            ..Default::default()
+        };
+        let engine = LiquidationEngine::new(rpc.client(), Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(MockSimulator::new(100_000)))
+            .with_submitter(Arc::new(submitter.clone()))
+            .with_payer(Keypair::new());
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        // Nothing that can't liquidate is sent, nor retried
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Failure { attempts, error, .. }) => {
+                assert_eq!(attempts, 1);
+                assert!(error.contains("initialized"), "{}", error);
+            }
+            other => panic!("expected a failure, got {:?}", other),
+        }
+        assert!(submitter.submitted().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_fresh_blockhash_per_attempt_without_nonce() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let blockhashes = [Hash::new_unique(), Hash::new_unique(), Hash::new_unique(), Hash::new_unique()];
+        let rpc = ScriptedRpc::default();
+        for blockhash in &blockhashes {
+            rpc.push_latest_blockhash(blockhash);
+        }
+        let submitter = MockSubmitter::new();
+        submitter.set_error(Some("blockhash not found"));
+        let config = LiquidationConfig {
+            dry_run: false,
+            retry_delay_ms: 0,
+            ..Default::default()
+        };
+        let engine = LiquidationEngine::new(rpc.client(), Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(MockSimulator::new(100_000)))
+            .with_submitter(Arc::new(submitter.clone()))
+            .with_liquidation_markets(create_liquidation_markets())
+            .with_payer(Keypair::new());
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        assert!(matches!(
+            engine.check_position(position).await.unwrap(),
+            Some(LiquidationResult::Failure { attempts: 4, .. })
+        ));
+        let submitted: Vec<Hash> = submitter.submitted().iter().map(|submission| submission.blockhash).collect();
+        assert_eq!(submitted, blockhashes);
+        assert_eq!(rpc.requests("getLatestBlockhash"), 4);
+        assert!(submitter.submitted().iter().all(|submission| submission.instructions.len() == 3));
+    }
+    
+    /// Engine submitting liquidations signed against `blockhashes` in turn, with
+    /// their status reported by `poller`
+    async fn create_confirming_engine(
+        blockhashes: &[Hash],
+        submitter: &MockSubmitter,
+        poller: &MockStatusPoller,
+    ) -> LiquidationEngine {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc = ScriptedRpc::default();
+        for blockhash in blockhashes {
+            rpc.push_latest_blockhash(blockhash);
+        }
+        let config = LiquidationConfig {
+            dry_run: false,
+            retry_delay_ms: 0,
+            confirmation_timeout_secs: 5,
+            ..Default::default()
+        };
+        LiquidationEngine::new(rpc.client(), Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_simulator(Arc::new(MockSimulator::new(100_000)))
+            .with_submitter(Arc::new(submitter.clone()))
+            .with_status_poller(Arc::new(poller.clone()))
+            .with_liquidation_markets(create_liquidation_markets())
+            .with_payer(Keypair::new())
+    }
+    
+    /// Add `count` liquidatable positions of the single-vault test market
+    async fn add_market_positions(engine: &LiquidationEngine, count: usize) -> Vec<Pubkey> {
+        let mut positions = Vec::new();
+        for _ in 0..count {
+            let mut position = create_test_position();
+            position.address = PositionAddress::new_unique();
+            position.margin = 12000.0;
+            positions.push(position.address.into());
+            engine.add_position(position).await.unwrap();
+        }
+        positions
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_batch_falls_back_to_individual_liquidations() {
+        let submitter = MockSubmitter::new();
+        let poller = MockStatusPoller::new();
+        let blockhashes: Vec<Hash> = (0..6).map(|_| Hash::new_unique()).collect();
+        let engine = create_confirming_engine(&blockhashes, &submitter, &poller).await;
+        let positions = add_market_positions(&engine, 3).await;
+        
+        // Without batching every liquidation is a transaction of its own
+        let results = engine.check_positions().await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|result| matches!(result, LiquidationResult::Success { .. })));
+        assert_eq!(submitter.submitted().len(), 3);
+        for position in &positions {
+            engine.remove_position_by_pubkey(position).await.unwrap();
+        }
+        
+        let config = LiquidationConfig {
+            enable_instruction_batching: true,
+            ..(*engine.config()).clone()
+        };
+        *engine.config.write().unwrap() = Arc::new(config);
+        let positions = add_market_positions(&engine, 3).await;
+        // The second liquidation fails the batch, after the two budget instructions
+        poller.push_status(SignatureStatus::Failed(TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(6000),
+        )));
+        let results = engine.check_positions().await.unwrap();
+        
+        // The three liquidations share the market's vaults and one transaction
+        let submitted = submitter.submitted();
+        assert_eq!(
+            submitted[3..].iter().map(|submission| submission.instructions.len()).collect::<Vec<_>>(),
+            vec![5, 3, 3]
+        );
+        let batched: Vec<Pubkey> =
+            submitted[3].instructions[2..].iter().map(|instruction| instruction.accounts[0].pubkey).collect();
+        assert_eq!(batched.iter().copied().collect::<HashSet<_>>(), positions.iter().copied().collect());
+        let outcomes: Vec<(Pubkey, bool, u8)> = results
+            .iter()
+            .map(|result| match result {
+                LiquidationResult::Success { position, .. } => ((*position).into(), true, 0),
+                LiquidationResult::Failure { position, attempts, .. } => ((*position).into(), false, *attempts),
+                other => panic!("unexpected result: {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![(batched[1], false, 1), (batched[0], true, 0), (batched[2], true, 0)]
+        );
+        assert!(positions.iter().all(|&position| !engine.confirmations.is_pending(&position.into())));
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn test_unconfirmed_liquidation_held_until_it_lands() {
+        let submitter = MockSubmitter::new();
+        let poller = MockStatusPoller::new();
+        poller.set_status(SignatureStatus::Pending);
+        let engine = create_confirming_engine(&[Hash::new_unique()], &submitter, &poller).await;
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        engine.add_position(position.clone()).await.unwrap();
+        
+        // Not resubmitted once the timeout passes, as it may still land
+        match engine.check_position(position.clone()).await.unwrap() {
+            Some(LiquidationResult::Failure { attempts, error, .. }) => {
+                assert_eq!(attempts, 1);
+                assert_eq!(error, LiquidationError::ConfirmationTimeout.to_string());
+            }
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        assert_eq!(engine.position_status(&position).await, PositionStatus::Liquidating);
+        let signature = engine.confirmations.pending(&position.address).unwrap().signature;
+        match engine.check_position(position.clone()).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => {
+                assert_eq!(reason, SkipReason::AwaitingConfirmation(signature.to_string()))
+            }
+            other => panic!("expected to await confirmation, got {:?}", other),
+        }
+        let results = engine.check_positions().await.unwrap();
+        assert!(matches!(results.as_slice(), [LiquidationResult::Skipped { .. }]), "{:?}", results);
+        assert_eq!(submitter.submitted().len(), 1);
+        
+        // Landing late completes the liquidation
+        poller.set_status(SignatureStatus::Confirmed);
+        engine.check_position(position.clone()).await.unwrap();
+        assert_ne!(engine.position_status(&position).await, PositionStatus::Liquidating);
+        match engine.check_position(position).await.unwrap() {
+            Some(LiquidationResult::Skipped { reason, .. }) => assert_eq!(reason, SkipReason::Cooldown),
+            other => panic!("expected cooldown, got {:?}", other),
+        }
+        let stats = engine.confirmation_stats();
+        assert_eq!((stats.pending, stats.timed_out, stats.confirmed), (0, 1, 1));
+        assert_eq!(submitter.submitted().len(), 1);
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn test_program_refusal_not_retried() {
+        let submitter = MockSubmitter::new();
+        let poller = MockStatusPoller::new();
+        let healthy = TransactionError::InstructionError(0, InstructionError::Custom(POSITION_HEALTHY_ERROR));
+        poller.set_status(SignatureStatus::Failed(healthy));
+        let engine = create_confirming_engine(&[Hash::new_unique()], &submitter, &poller).await;
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        match engine.check_position(position.clone()).await.unwrap() {
+            Some(LiquidationResult::Failure { attempts, error, sub_reason, .. }) => {
+                assert_eq!(attempts, 1);
+                assert_eq!(error, LiquidationError::PositionNotLiquidatable(position.address).to_string());
+                // Without an RPC node to ask, the refusal isn't traced to a competitor
+                assert_eq!(sub_reason, None);
+            }
+            other => panic!("expected a failure, got {:?}", other),
+        }
+        assert!(!engine.confirmations.is_pending(&position.address));
+        assert_eq!(engine.confirmation_stats().failed, 1);
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn test_expired_blockhash_resubmitted() {
+        let submitter = MockSubmitter::new();
+        let poller = MockStatusPoller::new();
+        let blockhashes = [Hash::new_unique(), Hash::new_unique()];
+        // The first transaction never lands and its blockhash runs out
+        poller.push_status(SignatureStatus::Pending);
+        poller.expire_blockhash(blockhashes[0]);
+        let engine = create_confirming_engine(&blockhashes, &submitter, &poller).await;
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        
+        assert!(matches!(
+            engine.check_position(position).await.unwrap(),
+            Some(LiquidationResult::Success { .. })
+        ));
+        let submitted: Vec<Hash> = submitter.submitted().iter().map(|submission| submission.blockhash).collect();
+        assert_eq!(submitted, blockhashes);
+        let stats = engine.confirmation_stats();
+        assert_eq!((stats.expired, stats.confirmed), (1, 1));
+    }
+    
+    /// Records the name and fields of every span, including fields recorded later
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        spans: Arc<std::sync::Mutex<Vec<RecordedSpan>>>,
+    }
+    
+    type RecordedSpan = (String, HashMap<String, String>);
+    
+    struct SpanIndex(usize);
+    
+    struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+    
+    impl tracing::field::Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+        
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+    
+    impl<S> tracing_subscriber::Layer<S> for SpanRecorder
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            id: &tracing::span::Id,
+            ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut fields = HashMap::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((attrs.metadata().name().to_string(), fields));
+            ctx.span(id).unwrap().extensions_mut().insert(SpanIndex(spans.len() - 1));
+        }
+        
+        fn on_record(
+            &self,
+            id: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let span = ctx.span(id).unwrap();
+            let index = span.extensions().get::<SpanIndex>().unwrap().0;
+            values.record(&mut FieldVisitor(&mut self.spans.lock().unwrap()[index].1));
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_liquidation_spans_carry_correlation_id() {
+        use tracing_subscriber::layer::SubscriberExt;
+        
+        let recorder = SpanRecorder::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+        
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), LiquidationConfig::default(), Arc::new(RateLimiter::default()));
+        let mut position = create_test_position();
+        position.margin = 12000.0;
+        engine.add_position(position.clone()).await.unwrap();
+        
+        let results = engine.check_positions().await.unwrap();
+        let Some(LiquidationResult::Success { correlation_id, .. }) = results.first() else {
+            panic!("expected a liquidation, got {:?}", results);
+        };
+        
+        let spans = recorder.spans.lock().unwrap();
+        let span = |name: &str| &spans.iter().find(|(span, _)| span == name).unwrap().1;
+        assert_eq!(span("check_cycle")["positions"], "1");
+        
+        let check = span("check_position");
+        assert_eq!(check["position"], position.address.to_string());
+        assert_eq!(check["symbol"], "BTC/USD");
+        assert_eq!(&check["correlation_id"], correlation_id);
+        assert!(check["priority_score"].starts_with("Some("));
+        
+        let liquidate = span("liquidate_position");
+        assert_eq!(&liquidate["correlation_id"], correlation_id);
+        assert_eq!(liquidate["attempt"], "1");
+        assert_eq!(liquidate["signature"], "dry-run");
+    }
+    
+    #[tokio::test]
+    async fn test_healthy_position_has_no_result() {
+        let mut position = create_test_position();
+        position.margin = 20000.0;
+        assert!(check_with_sol_price(LiquidationConfig::default(), position).await.is_none());
+    }
+    
+    /// Depth feed that panics when asked for one symbol's depth
+    #[derive(Debug)]
+    struct PanickingDepth(&'static str);
+    
+    #[async_trait::async_trait]
+    impl DepthProvider for PanickingDepth {
+        async fn get_depth(&self, symbol: &str) -> StdResult<Option<MarketDepth>, LiquidationError> {
+            if symbol == self.0 {
+                panic!("depth feed has no book for {}", symbol);
+            }
+            Ok(None)
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_bad_positions_quarantined_without_ending_cycle() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 55000.0).await;
+        oracle.set_price("ETH/USD", 2800.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let clock = ManualClock::new(1_700_000_000);
+        // Positions that can't be evaluated are only monitored as suspects
+        let config = LiquidationConfig {
+            max_concurrent_liquidations: 10,
+            ingest_policy: IngestPolicy::Flag,
+            ..Default::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()))
+            .with_depth_provider(Arc::new(PanickingDepth("ETH/USD")))
+            .with_clock(Arc::new(clock.clone()));
+        
+        let liquidatable = create_test_position();
+        let mut healthy = create_test_position();
+        healthy.margin = 30000.0;
+        let mut nan = create_test_position();
+        nan.entry_price = f64::NAN;
+        // 10x long, liquidatable at 2,800, whose check panics sizing the liquidation
+        let panicking = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "ETH/USD", 1.0, 3000.0, 300.0, true);
+        for position in [&liquidatable, &healthy, &nan, &panicking] {
+            engine.add_position(position.clone()).await.unwrap();
+        }
+        
+        let checked = |result: &LiquidationResult| match result {
+            LiquidationResult::Success { position, .. }
+            | LiquidationResult::Failure { position, .. }
+            | LiquidationResult::Skipped { position, .. } => *position,
+        };
+        
+        // The cycle completes, checking the others around the bad two
+        let results = engine.check_positions().await.unwrap();
+        assert_eq!(results.len(), 3, "{:?}", results);
+        assert!(results.iter().any(|result| matches!(
+            result,
+            LiquidationResult::Skipped { position, reason: SkipReason::Quarantined(reason) }
+                if *position == nan.address && reason == "non-finite entry_price"
+        )));
+        assert!(results.iter().any(|result| matches!(
+            result,
+            LiquidationResult::Success { position, .. } if *position == liquidatable.address
+        )));
+        let Some(LiquidationResult::Failure { error, attempts: 0, price, .. }) =
+            results.iter().find(|result| checked(result) == panicking.address)
+        else {
+            panic!("expected a failure, got {:?}", results);
+        };
+        assert_eq!(error, "check panicked: depth feed has no book for ETH/USD");
+        assert_eq!(*price, 2800.0);
+        assert!(engine.position_update(&healthy.address).await.is_ok());
+        
+        let stats = engine.quarantine_stats();
+        let quarantined: HashSet<PositionAddress> = stats.positions.iter().map(|position| position.address).collect();
+        assert_eq!(quarantined, HashSet::from([nan.address, panicking.address]));
+        assert_eq!((stats.total_panics, stats.total_quarantined), (1, 2));
+        assert_eq!(engine.throttle_stats().total_skipped["quarantined"], 1);
+        
+        // Quarantined positions sit out later cycles, still monitored, until released
+        clock.advance(3600);
+        let results = engine.check_positions().await.unwrap();
+        assert!(results.iter().all(|result| !quarantined.contains(&checked(result))), "{:?}", results);
+        assert_eq!(engine.get_positions().await.len(), 4);
+        assert!(engine.release_quarantined(&panicking.address).is_some());
+        assert!(engine.release_quarantined(&panicking.address).is_none());
+        let results = engine.check_positions().await.unwrap();
+        assert!(results.iter().any(|result| matches!(
+            result,
+            LiquidationResult::Failure { position, .. } if *position == panicking.address
+        )));
+        assert_eq!(engine.quarantine_stats().total_panics, 2);
+        assert_eq!(engine.clear_quarantine(), 2);
+    }
+    
+    #[tokio::test]
+    async fn test_positions_breaking_ingest_rules_refused() {
+        let engine = create_engine(LiquidationConfig::default());
+        let mut broken = create_test_position();
+        broken.size = -1.0;
+        broken.entry_price = f64::NAN;
+        let error = engine.add_position(broken.clone()).await.unwrap_err();
+        assert_eq!(error.address, broken.address);
+        assert_eq!(
+            error.violations,
+            [
+                IngestViolation::Negative { field: "size".to_string(), value: -1.0 },
+                IngestViolation::NonFinite { field: "entry_price".to_string() },
+            ]
+        );
+        
+        // A margin typo of 0.000001 implies 60 billion times leverage
+        let mut typo = create_test_position();
+        typo.margin = 0.000001;
+        assert!(engine.add_position(typo.clone()).await.is_err());
+        // Refused writes don't take their version
+        let written = engine.write_position(typo.clone(), &PositionWrite::at_version(5)).await;
+        assert!(matches!(written, Err(LiquidationError::Ingest(_))), "{:?}", written);
+        assert!(engine.position_version(&typo.address).is_none());
+        assert!(engine.get_positions().await.is_empty());
+        let stats = engine.ingest_stats();
+        assert_eq!((stats.total_rejected, stats.total_flagged), (3, 0));
+        
+        // Without a cap only the values are judged
+        let engine = create_engine(LiquidationConfig {
+            max_ingest_leverage: None,
+            ..Default::default()
+        });
+        engine.add_position(typo.clone()).await.unwrap();
+        assert!(!engine.is_suspect(&typo.address));
+        assert!(engine.add_position(broken).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_suspect_positions_not_liquidated_until_cleared() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        // 5x longs at a 4% margin ratio, liquidatable, beyond a 3x cap
+        let config = LiquidationConfig {
+            max_ingest_leverage: Some(3.0),
+            ingest_policy: IngestPolicy::Flag,
+            ..Default::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
+        let engine = LiquidationEngine::new(rpc_client, Arc::new(oracle), config, Arc::new(RateLimiter::default()));
+        let mut suspect = create_test_position();
+        suspect.margin = 12000.0;
+        engine.add_position(suspect.clone()).await.unwrap();
+        let mut versioned = create_test_position();
+        versioned.margin = 12000.0;
+        let written = engine.write_position(versioned.clone(), &PositionWrite::at_version(5)).await.unwrap();
+        assert_eq!(written, WriteOutcome::Applied);
+        
+        let stats = engine.ingest_stats();
+        let flagged: HashSet<PositionAddress> = stats.suspects.iter().map(|suspect| suspect.address).collect();
+        assert_eq!(flagged, HashSet::from([suspect.address, versioned.address]));
+        assert!(matches!(
+            stats.suspects[0].violations[..],
+            [IngestViolation::ExcessiveLeverage { leverage, max_leverage }] if leverage == 5.0 && max_leverage == 3.0
+        ));
+        assert_eq!((stats.total_rejected, stats.total_flagged), (0, 2));
+        
+        // Still monitored and evaluated, but never liquidated
+        assert_eq!(engine.position_status(&suspect).await, PositionStatus::AtRisk);
+        let results = engine.check_positions().await.unwrap();
+        assert_eq!(results.len(), 2, "{:?}", results);
+        assert!(results.iter().all(|result| matches!(
+            result,
+            LiquidationResult::Skipped { reason: SkipReason::Suspect, .. }
+        )));
+        assert_eq!(engine.throttle_stats().total_skipped["suspect"], 2);
+        
+        // Once cleared it's liquidated as any other
+        assert_eq!(engine.clear_suspect(&suspect.address).unwrap().address, suspect.address);
+        assert!(engine.clear_suspect(&suspect.address).is_none());
+        let result = engine.check_position_now(&suspect.address).await.unwrap();
+        assert!(matches!(result, Some(LiquidationResult::Success { amount, .. }) if amount == 0.5), "{:?}", result);
+        
+        // Stopping monitoring a suspect forgets the flag
+        engine.remove_position(&versioned.address).await;
+        assert!(!engine.is_suspect(&versioned.address));
+        assert!(engine.ingest_stats().suspects.is_empty());
     }
 }
@@ -1,35 +1,57 @@
-use clap::Parser;
-use env_logger::Env;
-use log::{error, info};
-use solana_client::rpc_client::RpcClient;
-use std::collections::HashMap;
-use std::sync::Arc;
-
-mod error;
-mod liquidation;
-mod oracle;
-mod position;
-mod types;
-
-use crate::{
-    error::LiquidationError,
-    liquidation::LiquidationEngine,
-    oracle::{OracleConfig, PythOracle},
-    types::LiquidationConfig,
+use clap::{Parser, ValueEnum};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError};
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+#[cfg(feature = "admin")]
+use liquidation_engine::admin;
+#[cfg(feature = "grpc")]
+use liquidation_engine::grpc;
+#[cfg(feature = "storage")]
+use liquidation_engine::storage;
+use liquidation_engine::internals::{
+    Alerter, AuditWriter, ConfigWatcher, DEFAULT_AUDIT_QUEUE_CAPACITY, DEFAULT_REPORT_QUEUE_CAPACITY,
+    DEFAULT_WEBHOOK_QUEUE_CAPACITY, DEFAULT_WEBHOOK_RETRY_DELAY, EngineMode, ManualClock, RateLimiter, ReplayOracle,
+    ReportWriter, RpcPool, RpcPreflight, SLOT_POLL_INTERVAL, StateFile, SystemdNotifier, TOKEN_BALANCE_CHECK_INTERVAL,
+    WebhookNotifier, WebhookTargets, preflight, websocket_url,
 };
+use liquidation_engine::{LiquidationConfig, LiquidationEngine, PythOracle};
 
 // Re-export error type for use in main
-pub use error::LiquidationError as Error;
+pub use liquidation_engine::LiquidationError as Error;
+
+/// Log output format
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    /// One JSON object per event, including the fields of enclosing spans
+    Json,
+    /// Human-readable multi-line output
+    Text,
+}
 
 /// Command line arguments
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Solana RPC URL
     #[arg(long, default_value = "https://api.devnet.solana.com")]
     rpc_url: String,
 
-    /// Path to payer keypair file (default: ./local_keypair.json)
+    /// Additional RPC URL to fail over to when the primary is unhealthy (repeatable)
+    #[arg(long = "fallback-rpc-url")]
+    fallback_rpc_urls: Vec<String>,
+
+    /// Send liquidations to every healthy RPC endpoint, not just the healthiest one
+    #[arg(long, default_value_t = false)]
+    broadcast_transactions: bool,
+
+    /// WebSocket URL to follow program events on (default: derived from --rpc-url)
+    #[arg(long)]
+    ws_url: Option<String>,
+
+    /// Path to payer keypair file, unless `key_pool.keypairs` lists them (default: ./local_keypair.json)
     #[arg(long, default_value = "./local_keypair.json")]
     keypair: String,
 
@@ -41,9 +63,72 @@ struct Args {
     #[arg(long, default_value = "info")]
     log_level: String,
 
-    /// Check interval in milliseconds
-    #[arg(long, default_value_t = 1000)]
-    check_interval_ms: u64,
+    /// Log output format
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// TOML configuration file; flags given here override its values
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Check interval in milliseconds (default: 1000)
+    #[arg(long)]
+    check_interval_ms: Option<u64>,
+
+    /// SQLite database recording liquidation attempts (requires the `storage` feature)
+    #[arg(long)]
+    database_path: Option<String>,
+
+    /// JSON file remembering cooldowns and unconfirmed liquidations across restarts
+    #[arg(long)]
+    state_path: Option<String>,
+
+    /// File every liquidation a dry run would have made is appended to, rotated
+    /// daily (.csv, .json or .jsonl)
+    #[arg(long)]
+    dry_run_report_path: Option<String>,
+
+    /// JSON lines file every liquidation attempt and its inputs are audited to,
+    /// rotated daily
+    #[arg(long)]
+    audit_log_path: Option<String>,
+
+    /// Position snapshot to start monitoring from, as written by the admin API's
+    /// `GET /snapshot` or the CLI's `snapshot save`
+    #[arg(long)]
+    positions_snapshot: Option<PathBuf>,
+
+    /// Replay recorded prices from this CSV file (timestamp,symbol,price,confidence)
+    /// against the --positions-snapshot positions, print a report and exit
+    #[arg(long)]
+    replay_prices: Option<PathBuf>,
+
+    /// Virtual seconds between the check cycles of a replay
+    #[arg(long, default_value_t = 60)]
+    replay_step_secs: u64,
+
+    /// Address to serve the admin API on, e.g. 127.0.0.1:8080 (requires the `admin` feature)
+    #[arg(long)]
+    admin_addr: Option<std::net::SocketAddr>,
+
+    /// Address to serve the gRPC service on, e.g. 127.0.0.1:50051 (requires the `grpc` feature)
+    #[arg(long)]
+    grpc_addr: Option<std::net::SocketAddr>,
+
+    /// Start without checking the RPC endpoint, programs, oracle feeds and
+    /// liquidator accounts first
+    #[arg(long, default_value_t = false)]
+    skip_preflight: bool,
+
+    /// Create the liquidator's missing token accounts during the preflight checks
+    #[arg(long, default_value_t = false)]
+    create_token_accounts: bool,
+
+    /// Mode to start in; monitor-only checks positions but sends no
+    /// transactions. SIGUSR1 switches a running engine to monitor-only and
+    /// SIGUSR2 back to running.
+    #[arg(long, value_enum, default_value_t = EngineMode::Running)]
+    mode: EngineMode,
 }
 
 #[tokio::main]
@@ -51,51 +136,480 @@ async fn main() -> Result<(), Error> {
     // Parse command line arguments
     let args = Args::parse();
 
-    // Initialize logger
-    env_logger::Builder::from_env(Env::default().default_filter_or(&args.log_level)).init();
+    // Initialize logging; RUST_LOG takes precedence over --log-level, and records
+    // from crates using the log facade are forwarded to the subscriber
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&args.log_level));
+    match args.log_format {
+        LogFormat::Json => tracing_subscriber::fmt()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_env_filter(filter)
+            .init(),
+        LogFormat::Text => tracing_subscriber::fmt().pretty().with_env_filter(filter).init(),
+    }
 
     info!("Starting liquidation engine with config: {:?}", args);
 
-    // Initialize RPC client
-    let rpc_client = Arc::new(RpcClient::new(&args.rpc_url));
+    let config = load_config(&args)?;
+
+    // Initialize RPC endpoints; the engine and oracle share them, so they share
+    // their health tracking and request budget too
+    let mut rpc_urls = vec![args.rpc_url.clone()];
+    rpc_urls.extend(args.fallback_rpc_urls.iter().cloned());
+    let rpc = RpcPool::from_urls(&rpc_urls, config.rpc_pool.clone())?;
+    let rate_limiter = Arc::new(RateLimiter::new(config.rate_limit.clone()));
+
+    // Replays are priced from the recording, only ever dry run and leave the
+    // state file and database alone
+    if let Some(path) = &args.replay_prices {
+        let oracle = Arc::new(ReplayOracle::load_csv(path, Arc::new(ManualClock::new(0)))?);
+        let engine = LiquidationEngine::builder()
+            .rpc_client(rpc)
+            .oracle(oracle.clone())
+            .config(LiquidationConfig { dry_run: true, ..config })
+            .rate_limiter(rate_limiter)
+            .clock(oracle.clock().clone())
+            .build()?;
+        return replay(&engine, &oracle, &args).await;
+    }
+
+    let alerts = Alerter::from_config(&config.alerts).map(|(alerts, _)| alerts);
+    if alerts.is_some() {
+        info!("Alerting {:?} of {:?}", config.alerts.notifier, config.alerts.triggers);
+    }
 
-    // Initialize oracle with default config
+    // Liquidator keys come from key_pool.keypairs, or --keypair alone
+    let keypair_paths = if config.key_pool.keypairs.is_empty() {
+        vec![PathBuf::from(&args.keypair)]
+    } else {
+        config.key_pool.keypair_paths()?
+    };
+    
+    // Catch misconfiguration before the first cycle rather than in every one
+    let mut balance_check = None;
+    let mut liquidation_markets = BTreeMap::new();
+    if args.skip_preflight {
+        warn!("Skipping preflight checks");
+    } else {
+        let preflight_rpc = RpcPreflight::new(rpc.clone(), rate_limiter.clone());
+        let report = preflight(&preflight_rpc, &config, &keypair_paths, args.create_token_accounts).await;
+        for check in &report.checks {
+            match &check.result {
+                Ok(detail) => info!("Preflight {}: {}", check.name, detail),
+                Err(message) => error!("Preflight {} failed: {}", check.name, message),
+            }
+        }
+        let token_accounts = report.token_accounts.clone();
+        liquidation_markets = report.markets.clone();
+        report.into_result()?;
+        if !config.dry_run && !token_accounts.is_empty() {
+            balance_check = Some((preflight_rpc, token_accounts));
+        }
+    }
+    
     let oracle = Arc::new(PythOracle::new(
-        &args.rpc_url,
-        HashMap::new(), // You might want to load price accounts from config
-        Some(OracleConfig {
-            max_price_age_secs: 60, // 1 minute
-            min_confidence_interval: 0.05, // 5%
-            max_confidence_interval: 0.1, // 10% (as a decimal, not seconds)
-            use_mainnet: false,
-        }),
+        rpc.clone(),
+        config.price_accounts.clone(),
+        Some(config.oracle_config()),
+        rate_limiter.clone(),
     ));
-
-    // Create liquidation engine with default config and override specific fields
-    let mut config = LiquidationConfig::default();
-    config.check_interval_ms = args.check_interval_ms;
     
-    let engine = LiquidationEngine::new(
-        rpc_client,
-        oracle,
-        config,
-    );
+    let mut builder = LiquidationEngine::builder();
+    builder
+        .rpc_client(rpc)
+        .oracle(oracle.clone())
+        .rate_limiter(rate_limiter)
+        .liquidation_markets(liquidation_markets);
+    
+    // The payers are only needed once liquidations are actually submitted
+    for path in &keypair_paths {
+        match solana_sdk::signature::read_keypair_file(path) {
+            Ok(payer) => {
+                builder.keypair(payer);
+            }
+            Err(e) if config.dry_run => warn!("Not loading payer keypair {}: {}", path.display(), e),
+            Err(e) => {
+                return Err(Error::ConfigError(format!("Failed to read keypair {}: {}", path.display(), e)));
+            }
+        }
+    }
+    if keypair_paths.len() > 1 {
+        info!("Rotating {} liquidator keys {:?}", keypair_paths.len(), config.key_pool.rotation);
+    }
+    
+    #[cfg(feature = "storage")]
+    if let Some(path) = &config.database_path {
+        let store = Arc::new(storage::LiquidationStore::open(path)?);
+        let (writer, _) = storage::StoreWriter::spawn(store, storage::DEFAULT_QUEUE_CAPACITY);
+        info!("Recording liquidations to {}", path);
+        builder.store(writer);
+    }
+    #[cfg(not(feature = "storage"))]
+    if config.database_path.is_some() {
+        warn!("Ignoring --database-path: built without the storage feature");
+    }
+    
+    let webhook_targets = WebhookTargets::from_config(&config);
+    if !webhook_targets.is_empty() {
+        let (notifier, _) = WebhookNotifier::spawn(
+            webhook_targets,
+            DEFAULT_WEBHOOK_QUEUE_CAPACITY,
+            DEFAULT_WEBHOOK_RETRY_DELAY,
+        );
+        info!("Notifying owners' webhooks of {:?}", config.webhook_events);
+        builder.webhooks(notifier);
+    }
+    if let Some(alerts) = alerts {
+        builder.alerts(alerts);
+    }
+    // Under a Type=notify unit, tell systemd once warmed up and after every cycle
+    if let Some(systemd) = SystemdNotifier::from_env() {
+        info!("Notifying systemd, watchdog interval {:?}", systemd.watchdog_interval());
+        builder.systemd(systemd);
+    }
+    
+    let report = match &config.dry_run_report_path {
+        Some(path) if config.dry_run => {
+            let (writer, _) = ReportWriter::spawn(path, DEFAULT_REPORT_QUEUE_CAPACITY)?;
+            info!("Reporting dry run liquidations to {}", path);
+            builder.report(writer.clone());
+            Some(writer)
+        }
+        Some(_) => {
+            warn!("Ignoring dry_run_report_path: dry run is disabled");
+            None
+        }
+        None => None,
+    };
+    
+    if let Some(path) = &config.audit_log_path {
+        let (writer, _) = AuditWriter::spawn(path, DEFAULT_AUDIT_QUEUE_CAPACITY);
+        info!("Auditing liquidation attempts to {}", path);
+        builder.audit(writer);
+    }
+    
+    let engine = builder.config(config).build()?;
+    
+    let engine = match &engine.config().state_path {
+        Some(path) => {
+            let state = StateFile::load(path)?;
+            info!("Loaded state for {} positions from {}", state.len(), path);
+            engine.with_state_file(state)
+        }
+        None => engine,
+    };
+    
+    if let Some(path) = &args.positions_snapshot {
+        let count = engine.import_positions(path, false).await?;
+        info!("Loaded {} positions from snapshot {}", count, path.display());
+    }
+    
+    let engine = Arc::new(engine);
+    
+    // Keep every liquidator key's balances current, skipping and alerting on
+    // the keys that run short of SOL or tokens to repay with
+    if let Some((preflight_rpc, token_accounts)) = balance_check {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            loop {
+                engine.refresh_key_balances(&preflight_rpc, &token_accounts).await;
+                tokio::time::sleep(TOKEN_BALANCE_CHECK_INTERVAL).await;
+            }
+        });
+    }
+    // Track the cluster's slot, which prices are aged against and whose
+    // stalling holds liquidations back
+    {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = engine.refresh_slot().await {
+                    warn!("Failed to fetch the current slot: {}", e);
+                }
+                tokio::time::sleep(SLOT_POLL_INTERVAL).await;
+            }
+        });
+    }
+    if args.mode != EngineMode::Running {
+        engine.set_mode(args.mode, "command line");
+    }
+    #[cfg(unix)]
+    forward_mode_signals(engine.clone());
+    #[cfg(feature = "admin")]
+    if let Some(addr) = args.admin_addr {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            if let Err(e) = admin::serve(engine, addr).await {
+                error!("Admin API error: {}", e);
+            }
+        });
+    }
+    #[cfg(not(feature = "admin"))]
+    if args.admin_addr.is_some() {
+        warn!("Ignoring --admin-addr: built without the admin feature");
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = args.grpc_addr {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            if let Err(e) = grpc::serve(engine, addr).await {
+                error!("gRPC server error: {}", e);
+            }
+        });
+    }
+    #[cfg(not(feature = "grpc"))]
+    if args.grpc_addr.is_some() {
+        warn!("Ignoring --grpc-addr: built without the grpc feature");
+    }
+    
+    // Keep cached positions in step with deposits and other keepers'
+    // liquidations in every market's program, and with the position accounts of
+    // each enabled market, resubscribing whenever a subscription drops. Markets
+    // added at runtime are picked up along with their price feeds, and removed
+    // ones are dropped at their next resubscribe.
+    let ws_url = args.ws_url.clone().unwrap_or_else(|| websocket_url(&args.rpc_url));
+    {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let followed = Arc::new(std::sync::Mutex::new(HashSet::new()));
+            let loaded = Arc::new(std::sync::Mutex::new(HashSet::new()));
+            loop {
+                let config = engine.config();
+                for (symbol, feed) in config.oracle_feeds() {
+                    oracle.add_price_account(&symbol, feed).await;
+                }
+                for program_id in config.program_ids() {
+                    if !followed.lock().unwrap_or_else(PoisonError::into_inner).insert(program_id) {
+                        continue;
+                    }
+                    let engine = engine.clone();
+                    let ws_url = ws_url.clone();
+                    let followed = followed.clone();
+                    tokio::spawn(async move {
+                        while engine.config().program_ids().contains(&program_id) {
+                            if let Err(e) = engine.follow_program_events(&ws_url, &program_id).await {
+                                warn!("Program {} event subscription ended: {}", program_id, e);
+                            }
+                            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                        }
+                        followed.lock().unwrap_or_else(PoisonError::into_inner).remove(&program_id);
+                    });
+                }
+                for market in config.markets.iter().filter(|market| market.enabled) {
+                    let symbol = market.symbol.clone();
+                    if !loaded.lock().unwrap_or_else(PoisonError::into_inner).insert(symbol.clone()) {
+                        continue;
+                    }
+                    let engine = engine.clone();
+                    let ws_url = ws_url.clone();
+                    let loaded = loaded.clone();
+                    tokio::spawn(async move {
+                        while let Some(market) = engine.config().market(&symbol).filter(|market| market.enabled).cloned() {
+                            if let Err(e) = engine.follow_position_accounts(&ws_url, &market).await {
+                                warn!("{} position account subscription ended: {}", symbol, e);
+                            }
+                            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                        }
+                        loaded.lock().unwrap_or_else(PoisonError::into_inner).remove(&symbol);
+                    });
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            }
+        });
+    }
+    
+    // Stage edits to the configuration file, or a reread on SIGHUP, for the
+    // next check cycle, with flags still overriding the file
+    if let Some(path) = args.config.clone() {
+        let args = args.clone();
+        let watcher = ConfigWatcher::new(path, move || load_config(&args));
+        tokio::spawn(watcher.run(engine.clone()));
+    }
     
     info!("Liquidation engine started with config: {:?}", engine.config());
 
+    // Stop on Ctrl-C or SIGTERM, taking the servers sharing the engine down with it
+    {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            if shutdown_requested().await {
+                info!("Shutting down");
+                engine.shutdown();
+            }
+        });
+    }
+
     // Start the engine
     engine.start().await.map_err(|e| {
         error!("Engine error: {}", e);
         e
     })?;
+    if let Some(report) = &report {
+        report.flush().await;
+    }
 
     Ok(())
 }
 
+/// Wait for Ctrl-C or SIGTERM, which systemd stops services with, returning
+/// whether either arrived
+#[cfg(unix)]
+async fn shutdown_requested() -> bool {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!("Not shutting down on SIGTERM: {}", e);
+            return tokio::signal::ctrl_c().await.is_ok();
+        }
+    };
+    tokio::select! {
+        interrupted = tokio::signal::ctrl_c() => interrupted.is_ok(),
+        terminated = terminate.recv() => terminated.is_some(),
+    }
+}
+
+/// Wait for Ctrl-C, returning whether it arrived
+#[cfg(not(unix))]
+async fn shutdown_requested() -> bool {
+    tokio::signal::ctrl_c().await.is_ok()
+}
+
+/// Switch the engine to monitor-only on SIGUSR1 and back to running on SIGUSR2,
+/// so maintenance windows can be entered without the admin API
+#[cfg(unix)]
+fn forward_mode_signals(engine: Arc<LiquidationEngine>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let signals = [
+        (SignalKind::user_defined1(), "SIGUSR1", EngineMode::MonitorOnly),
+        (SignalKind::user_defined2(), "SIGUSR2", EngineMode::Running),
+    ];
+    for (kind, name, mode) in signals {
+        match signal(kind) {
+            Ok(mut received) => {
+                let engine = engine.clone();
+                tokio::spawn(async move {
+                    while received.recv().await.is_some() {
+                        engine.set_mode(mode, name);
+                    }
+                });
+            }
+            Err(e) => warn!("Not switching modes on {}: {}", name, e),
+        }
+    }
+}
+
+/// Replay recorded prices against the --positions-snapshot positions and print the report
+async fn replay(engine: &LiquidationEngine, oracle: &ReplayOracle, args: &Args) -> Result<(), Error> {
+    let Some(path) = &args.positions_snapshot else {
+        return Err(Error::ConfigError("--replay-prices needs --positions-snapshot".to_string()));
+    };
+    let count = engine.import_positions(path, false).await?;
+    let (from_ts, to_ts) = oracle
+        .time_range()
+        .ok_or_else(|| Error::ConfigError("no recorded prices to replay".to_string()))?;
+    info!("Replaying {} positions from {} to {}", count, from_ts, to_ts);
+    let report = engine.run_replay(from_ts, to_ts, args.replay_step_secs).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// The configuration file's settings, or the defaults, overridden by flags
+fn load_config(args: &Args) -> Result<LiquidationConfig, Error> {
+    let mut config = match &args.config {
+        Some(path) => {
+            let toml = std::fs::read_to_string(path)
+                .map_err(|e| Error::ConfigError(format!("Failed to read {}: {}", path.display(), e)))?;
+            LiquidationConfig::from_toml(&toml)
+                .map_err(|e| Error::ConfigError(format!("{}: {}", path.display(), e)))?
+        }
+        None => LiquidationConfig::default(),
+    };
+    if let Some(check_interval_ms) = args.check_interval_ms {
+        config.check_interval_ms = check_interval_ms;
+    }
+    if args.database_path.is_some() {
+        config.database_path = args.database_path.clone();
+    }
+    if args.state_path.is_some() {
+        config.state_path = args.state_path.clone();
+    }
+    if args.dry_run_report_path.is_some() {
+        config.dry_run_report_path = args.dry_run_report_path.clone();
+    }
+    if args.audit_log_path.is_some() {
+        config.audit_log_path = args.audit_log_path.clone();
+    }
+    config.broadcast_transactions |= args.broadcast_transactions;
+    config.validate()?;
+    Ok(config)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::signature::Keypair;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_log_format_flag() {
+        let args = Args::parse_from(["liquidation-engine", "--log-format", "json"]);
+        assert_eq!(args.log_format, LogFormat::Json);
+        assert_eq!(Args::parse_from(["liquidation-engine"]).log_format, LogFormat::Text);
+        assert!(Args::try_parse_from(["liquidation-engine", "--log-format", "xml"]).is_err());
+    }
+
+    #[test]
+    fn test_fallback_rpc_url_flag() {
+        let args = Args::parse_from([
+            "liquidation-engine",
+            "--fallback-rpc-url",
+            "https://a.example",
+            "--fallback-rpc-url",
+            "https://b.example",
+        ]);
+        assert_eq!(args.fallback_rpc_urls, ["https://a.example", "https://b.example"]);
+        assert!(Args::parse_from(["liquidation-engine"]).fallback_rpc_urls.is_empty());
+    }
+
+    #[test]
+    fn test_preflight_flags() {
+        let args = Args::parse_from(["liquidation-engine"]);
+        assert!(!args.skip_preflight && !args.create_token_accounts);
+        let args = Args::parse_from(["liquidation-engine", "--skip-preflight", "--create-token-accounts"]);
+        assert!(args.skip_preflight && args.create_token_accounts);
+    }
+
+    #[test]
+    fn test_mode_flag() {
+        assert_eq!(Args::parse_from(["liquidation-engine"]).mode, EngineMode::Running);
+        let args = Args::parse_from(["liquidation-engine", "--mode", "monitor-only"]);
+        assert_eq!(args.mode, EngineMode::MonitorOnly);
+        assert!(Args::try_parse_from(["liquidation-engine", "--mode", "stopped"]).is_err());
+    }
+
+    #[test]
+    fn test_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "maintenance_margin = 0.1\ncheck_interval_ms = 500\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = load_config(&Args::parse_from(["liquidation-engine", "--config", path])).unwrap();
+        assert_eq!((config.maintenance_margin, config.check_interval_ms), (0.1, 500));
+        // Flags override the file, which leaves the rest at their defaults
+        let args = Args::parse_from(["liquidation-engine", "--config", path, "--check-interval-ms", "250"]);
+        let config = load_config(&args).unwrap();
+        assert_eq!((config.maintenance_margin, config.check_interval_ms), (0.1, 250));
+        assert_eq!(config.max_retries, LiquidationConfig::default().max_retries);
+
+        std::fs::write(path, "maintenance_margin = 1.5\n").unwrap();
+        assert!(load_config(&Args::parse_from(["liquidation-engine", "--config", path])).is_err());
+        let config = load_config(&Args::parse_from(["liquidation-engine"])).unwrap();
+        assert_eq!(config.check_interval_ms, 1000);
+    }
 
     #[test]
     fn test_config_default() {
@@ -106,13 +620,26 @@ mod tests {
 
     #[test]
     fn test_engine_initialization() {
-        let rpc_client = Arc::new(RpcClient::new("https://api.devnet.solana.com"));
-        let oracle = Arc::new(PythOracle::new(
-            "https://api.devnet.solana.com",
-            HashMap::new(),
-            None,
-        ));
+        let rpc = RpcPool::from("https://api.devnet.solana.com");
+        let rate_limiter = Arc::new(RateLimiter::default());
+        let oracle = Arc::new(PythOracle::new(rpc.clone(), HashMap::new(), None, rate_limiter.clone()));
         let config = LiquidationConfig::default();
-        let _engine = LiquidationEngine::new(rpc_client, oracle, config);
+        let _engine = LiquidationEngine::new(rpc, oracle, config, rate_limiter);
+    }
+
+    #[test]
+    fn test_engine_built_from_flags() {
+        let args = Args::parse_from(["liquidation-engine", "--check-interval-ms", "250"]);
+        let config = load_config(&args).unwrap();
+        let rpc = RpcPool::from(args.rpc_url.as_str());
+        let oracle = Arc::new(PythOracle::new(rpc.clone(), HashMap::new(), None, Arc::new(RateLimiter::default())));
+        let engine = LiquidationEngine::builder()
+            .rpc_client(rpc)
+            .oracle(oracle)
+            .config(config)
+            .build()
+            .unwrap();
+        assert_eq!(engine.config().check_interval_ms, 250);
+        assert!(engine.config().dry_run);
     }
 }
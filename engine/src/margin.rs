@@ -0,0 +1,220 @@
+use crate::address::{OwnerAddress, PositionAddress};
+use crate::amount::{self, Amount, AmountExt, RATIO_SCALE, Rounding};
+use crate::position::{LIQUIDATION_HEALTH_FACTOR, MarginMode, MarginParams, Position};
+use std::collections::{BTreeSet, HashMap};
+
+/// Margin and PnL pooled across an owner's cross-margin positions
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct PooledMargin {
+    /// Margin of the pooled positions, net of unsettled funding (in quote currency)
+    pub margin: Amount,
+    /// Unrealized PnL across the pooled positions (in quote currency)
+    pub unrealized_pnl: Amount,
+    /// Margin the pooled positions require under the maintenance margin (in quote currency)
+    pub maintenance_requirement: Amount,
+}
+
+impl PooledMargin {
+    /// Pool `positions` at `prices`, or `None` if one of them can't be priced
+    ///
+    /// `margin_params` gives the maintenance margin ratios of a position at a price.
+    pub fn new<'a>(
+        positions: impl IntoIterator<Item = &'a Position>,
+        prices: &HashMap<String, Amount>,
+        margin_params: impl Fn(&Position, Amount) -> MarginParams,
+    ) -> Option<Self> {
+        let mut pool = Self {
+            margin: Amount::ZERO,
+            unrealized_pnl: Amount::ZERO,
+            maintenance_requirement: Amount::ZERO,
+        };
+        for position in positions {
+            let price = *prices.get(&position.symbol)?;
+            pool.margin += position.effective_margin();
+            pool.unrealized_pnl += position.unrealized_pnl(price);
+            pool.maintenance_requirement += position.value(price) * position.maintenance_margin(margin_params(position, price));
+        }
+        Some(pool)
+    }
+
+    /// Margin available to the pooled positions: pooled margin plus their PnL
+    pub fn available_margin(&self) -> Amount {
+        self.margin + self.unrealized_pnl
+    }
+
+    /// Available margin over the maintenance requirement, rounded down; below
+    /// [`LIQUIDATION_HEALTH_FACTOR`] every pooled position can be liquidated
+    ///
+    /// Empty pools are infinitely healthy.
+    pub fn health_factor(&self) -> f64 {
+        if self.maintenance_requirement == Amount::ZERO {
+            return f64::INFINITY;
+        }
+        self.available_margin()
+            .checked_div(self.maintenance_requirement)
+            .map_or(f64::INFINITY, |health_factor| health_factor.round_to(RATIO_SCALE, Rounding::Down).as_f64())
+    }
+
+    /// The pool with one of its positions moved from `from_price` to `to_price`
+    ///
+    /// `margin_params` gives the maintenance margin ratios of the position at a price.
+    pub fn repriced(
+        &self,
+        position: &Position,
+        from_price: Amount,
+        to_price: Amount,
+        margin_params: impl Fn(Amount) -> MarginParams,
+    ) -> Self {
+        let requirement =
+            |price: Amount| position.value(price) * position.maintenance_margin(margin_params(price));
+        Self {
+            margin: self.margin,
+            unrealized_pnl: self.unrealized_pnl - position.unrealized_pnl(from_price) + position.unrealized_pnl(to_price),
+            maintenance_requirement: self.maintenance_requirement - requirement(from_price) + requirement(to_price),
+        }
+    }
+
+    /// Whether the pooled positions can be liquidated, comparing without
+    /// dividing as [`Position::is_undercollateralized`] does
+    pub fn is_undercollateralized(&self) -> bool {
+        self.maintenance_requirement != Amount::ZERO
+            && self.available_margin()
+                < self.maintenance_requirement * amount::from_f64_lossy(LIQUIDATION_HEALTH_FACTOR)
+    }
+}
+
+/// Cross-margin positions of each owner, kept in step with the monitored
+/// positions as they're added, updated and removed
+#[derive(Debug, Default)]
+pub(crate) struct MarginPools {
+    /// Addresses of each owner's cross positions
+    members: HashMap<OwnerAddress, BTreeSet<PositionAddress>>,
+    /// Owner of each pooled position
+    owners: HashMap<PositionAddress, OwnerAddress>,
+}
+
+impl MarginPools {
+    /// Create empty pools
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a position, or bring a pooled one up to date with a change of owner
+    /// or margin mode
+    pub fn insert(&mut self, position: &Position) {
+        self.remove(&position.address);
+        if position.margin_mode == MarginMode::Cross {
+            self.members.entry(position.owner).or_default().insert(position.address);
+            self.owners.insert(position.address, position.owner);
+        }
+    }
+
+    /// Remove a position from its owner's pool
+    pub fn remove(&mut self, address: &PositionAddress) {
+        let Some(owner) = self.owners.remove(address) else {
+            return;
+        };
+        if let Some(members) = self.members.get_mut(&owner) {
+            members.remove(address);
+            if members.is_empty() {
+                self.members.remove(&owner);
+            }
+        }
+    }
+
+    /// Remove every position
+    pub fn clear(&mut self) {
+        self.members.clear();
+        self.owners.clear();
+    }
+
+    /// Owners with cross positions
+    pub fn owners(&self) -> impl Iterator<Item = &OwnerAddress> {
+        self.members.keys()
+    }
+
+    /// Addresses of an owner's cross positions, in address order
+    pub fn members(&self, owner: &OwnerAddress) -> impl Iterator<Item = &PositionAddress> {
+        self.members.get(owner).into_iter().flatten()
+    }
+
+    /// Addresses of the other positions pooled with a position
+    pub fn siblings(&self, address: &PositionAddress) -> Vec<PositionAddress> {
+        let Some(owner) = self.owners.get(address) else {
+            return Vec::new();
+        };
+        self.members(owner).filter(|member| *member != address).copied().collect()
+    }
+
+    /// Pool an owner's cross positions, as found in `positions`, at `prices`
+    ///
+    /// Returns `None` if the owner has no cross positions or one of them
+    /// can't be priced.
+    pub fn pooled(
+        &self,
+        owner: &OwnerAddress,
+        positions: &HashMap<PositionAddress, Position>,
+        prices: &HashMap<String, Amount>,
+        margin_params: impl Fn(&Position, Amount) -> MarginParams,
+    ) -> Option<PooledMargin> {
+        let members = self.members.get(owner)?;
+        PooledMargin::new(
+            members.iter().filter_map(|address| positions.get(address)),
+            prices,
+            margin_params,
+        )
+    }
+}
+
+impl<'a> FromIterator<&'a Position> for MarginPools {
+    fn from_iter<I: IntoIterator<Item = &'a Position>>(positions: I) -> Self {
+        let mut pools = Self::new();
+        for position in positions {
+            pools.insert(position);
+        }
+        pools
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARGIN: MarginParams = MarginParams::shared(0.05);
+
+    fn create_position(owner: OwnerAddress, symbol: &str, entry_price: f64, margin: f64, mode: MarginMode) -> Position {
+        Position::new(PositionAddress::new_unique(), owner, symbol, 1.0, entry_price, margin, true)
+            .with_margin_mode(mode)
+    }
+
+    #[test]
+    fn test_pools_follow_positions() {
+        let owner = OwnerAddress::new_unique();
+        let mut btc = create_position(owner, "BTC/USD", 60_000.0, 6_000.0, MarginMode::Cross);
+        let eth = create_position(owner, "ETH/USD", 3_000.0, 300.0, MarginMode::Cross);
+        let isolated = create_position(owner, "SOL/USD", 100.0, 10.0, MarginMode::Isolated);
+        let mut pools = MarginPools::from_iter([&btc, &eth, &isolated]);
+        assert_eq!(pools.members(&owner).count(), 2);
+        assert_eq!(pools.siblings(&btc.address), [eth.address]);
+        assert!(pools.siblings(&isolated.address).is_empty());
+
+        let positions: HashMap<PositionAddress, Position> =
+            [&btc, &eth, &isolated].map(|position| (position.address, position.clone())).into();
+        let prices = HashMap::from([("BTC/USD".to_string(), 57_000.0), ("ETH/USD".to_string(), 3_300.0)]);
+        let pool = pools.pooled(&owner, &positions, &prices, |_, _| MARGIN).unwrap();
+        assert_eq!(pool.margin, 6_300.0);
+        assert_eq!(pool.unrealized_pnl, -3_000.0 + 300.0);
+        assert!((pool.maintenance_requirement - (57_000.0 + 3_300.0) * 0.05).abs() < 1e-9);
+        let repriced = pool.repriced(&btc, 57_000.0, 60_000.0, |_| MARGIN);
+        assert_eq!(repriced.unrealized_pnl, 300.0);
+        assert!(pools.pooled(&owner, &positions, &HashMap::new(), |_, _| MARGIN).is_none());
+
+        // Switching to isolated leaves the pool, removing the last member empties it
+        btc.margin_mode = MarginMode::Isolated;
+        pools.insert(&btc);
+        assert!(pools.siblings(&eth.address).is_empty());
+        pools.remove(&eth.address);
+        assert_eq!(pools.owners().count(), 0);
+        assert!(pools.pooled(&owner, &positions, &prices, |_, _| MARGIN).is_none());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::address::PositionAddress;
+use std::collections::HashMap;
+
+/// Positions the engine flagged for liquidation under the program's margin
+/// call, by when it flagged them
+///
+/// A flagged position may be liquidated once its grace period is over. Like
+/// the program's flag, the engine's is kept until a liquidation leaves the
+/// position above the warning threshold or the position goes away.
+#[derive(Debug, Default)]
+pub(crate) struct MarginCalls {
+    flagged_at: HashMap<PositionAddress, i64>,
+}
+
+/// Where a liquidatable position stands in its margin call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MarginCallStage {
+    /// Not flagged yet, which comes before anything else
+    Unflagged,
+    /// Flagged, with a grace period ending at the given time
+    Grace(i64),
+    /// Flagged, and its grace period is over
+    Expired,
+}
+
+impl MarginCalls {
+    /// Stage of a position's margin call at `now`, under a grace period of
+    /// `grace_secs`
+    pub(crate) fn stage(&self, address: &PositionAddress, grace_secs: u64, now: i64) -> MarginCallStage {
+        let Some(&flagged_at) = self.flagged_at.get(address) else {
+            return MarginCallStage::Unflagged;
+        };
+        let grace_ends_at = flagged_at.saturating_add(i64::try_from(grace_secs).unwrap_or(i64::MAX));
+        if now < grace_ends_at {
+            MarginCallStage::Grace(grace_ends_at)
+        } else {
+            MarginCallStage::Expired
+        }
+    }
+
+    /// Record a position flagged at `now`
+    pub(crate) fn flag(&mut self, address: PositionAddress, now: i64) {
+        self.flagged_at.insert(address, now);
+    }
+
+    /// Forget a position's flag, returning whether it was flagged
+    pub(crate) fn clear(&mut self, address: &PositionAddress) -> bool {
+        self.flagged_at.remove(address).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_margin_call_stages() {
+        let mut margin_calls = MarginCalls::default();
+        let address = PositionAddress::new_unique();
+        assert_eq!(margin_calls.stage(&address, 120, 1_000), MarginCallStage::Unflagged);
+
+        margin_calls.flag(address, 1_000);
+        assert_eq!(margin_calls.stage(&address, 120, 1_000), MarginCallStage::Grace(1_120));
+        assert_eq!(margin_calls.stage(&address, 120, 1_119), MarginCallStage::Grace(1_120));
+        assert_eq!(margin_calls.stage(&address, 120, 1_120), MarginCallStage::Expired);
+        // Grace periods too long to represent never end
+        assert_eq!(margin_calls.stage(&address, u64::MAX, i64::MAX - 1), MarginCallStage::Grace(i64::MAX));
+
+        assert!(margin_calls.clear(&address));
+        assert!(!margin_calls.clear(&address));
+        assert_eq!(margin_calls.stage(&address, 120, 1_120), MarginCallStage::Unflagged);
+    }
+}
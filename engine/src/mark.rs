@@ -0,0 +1,228 @@
+use crate::amount::{self, Amount};
+use crate::error::{ConfigViolation, LiquidationError};
+use async_trait::async_trait;
+use std::collections::HashMap;
+#[cfg(any(test, feature = "testing"))]
+use std::sync::Arc;
+#[cfg(any(test, feature = "testing"))]
+use tokio::sync::RwLock;
+
+/// Trait for sources of the basis between a market's traded price and its
+/// oracle index price
+#[async_trait]
+pub trait BasisSource: Send + Sync + std::fmt::Debug {
+    /// Get the current basis of a symbol as a share of the index price
+    ///
+    /// Positive when the market trades above the index (e.g., 0.001 for 10 bps).
+    async fn basis(&self, symbol: &str) -> Result<f64, LiquidationError>;
+}
+
+/// Basis source reporting no basis, so the mark price is the index price
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ZeroBasis;
+
+#[async_trait]
+impl BasisSource for ZeroBasis {
+    async fn basis(&self, _symbol: &str) -> Result<f64, LiquidationError> {
+        Ok(0.0)
+    }
+}
+
+/// Mock basis source for testing
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Clone, Default)]
+pub struct MockBasisSource {
+    bases: Arc<RwLock<HashMap<String, f64>>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockBasisSource {
+    /// Create a new mock basis source
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the basis of a symbol
+    pub async fn set_basis(&self, symbol: &str, basis: f64) {
+        let mut bases = self.bases.write().await;
+        bases.insert(symbol.to_string(), basis);
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[async_trait]
+impl BasisSource for MockBasisSource {
+    async fn basis(&self, symbol: &str) -> Result<f64, LiquidationError> {
+        self.bases
+            .read()
+            .await
+            .get(symbol)
+            .copied()
+            .ok_or_else(|| LiquidationError::Other(format!("No basis for {}", symbol)))
+    }
+}
+
+/// Settings for deriving mark prices from oracle index prices
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct MarkPriceConfig {
+    /// Largest basis a symbol's mark price may deviate from its index price by
+    /// (in basis points); symbols without one are marked at the index price
+    pub max_basis_bps: HashMap<String, u32>,
+    /// Time for a basis sample's weight in the average to halve (in seconds)
+    pub basis_half_life_secs: u64,
+}
+
+impl Default for MarkPriceConfig {
+    fn default() -> Self {
+        Self {
+            max_basis_bps: HashMap::new(),
+            basis_half_life_secs: 60,
+        }
+    }
+}
+
+impl MarkPriceConfig {
+    /// Largest basis of a symbol's mark price, as a share of the index price
+    pub fn max_basis(&self, symbol: &str) -> f64 {
+        self.max_basis_bps.get(symbol).copied().unwrap_or(0) as f64 / 10_000.0
+    }
+
+    /// Every problem with the settings
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if self.basis_half_life_secs == 0 {
+            violations.push(ConfigViolation::new("basis_half_life_secs", "must be positive"));
+        }
+        let mut limits: Vec<(&String, &u32)> = self.max_basis_bps.iter().collect();
+        limits.sort();
+        for (symbol, bps) in limits {
+            if *bps >= 10_000 {
+                violations.push(ConfigViolation::new(
+                    format!("max_basis_bps.{}", symbol),
+                    format!("must be below 10000, got {}", bps),
+                ));
+            }
+        }
+        violations
+    }
+}
+
+/// Mark price of an index price shifted by `basis`, clamped to `max_basis`
+/// either way
+pub(crate) fn mark_price(index_price: f64, basis: f64, max_basis: f64) -> f64 {
+    index_price * (1.0 + basis.clamp(-max_basis, max_basis))
+}
+
+/// Exponential moving average of one symbol's basis
+#[derive(Debug, Clone, Copy)]
+struct BasisEma {
+    value: f64,
+    updated_at: i64,
+}
+
+/// Derives mark prices from index prices and a moving average of each
+/// symbol's basis
+///
+/// The first sample of a symbol starts its average; later samples are weighted
+/// by the time since the previous one, so sampling more often doesn't speed the
+/// average up.
+#[derive(Debug, Default)]
+pub(crate) struct MarkPriceCalculator {
+    emas: HashMap<String, BasisEma>,
+}
+
+impl MarkPriceCalculator {
+    /// Create a calculator without any basis history
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold a basis sample of `symbol` taken at `now` into its average,
+    /// returning the updated average
+    pub fn observe(&mut self, config: &MarkPriceConfig, symbol: &str, basis: f64, now: i64) -> f64 {
+        let Some(ema) = self.emas.get_mut(symbol) else {
+            self.emas.insert(symbol.to_string(), BasisEma { value: basis, updated_at: now });
+            return basis;
+        };
+        let elapsed = now.saturating_sub(ema.updated_at).max(0) as f64;
+        let weight = 1.0 - 0.5f64.powf(elapsed / config.basis_half_life_secs.max(1) as f64);
+        ema.value += (basis - ema.value) * weight;
+        ema.updated_at = ema.updated_at.max(now);
+        ema.value
+    }
+
+    /// Current average basis of a symbol, if it was ever sampled
+    pub fn basis_ema(&self, symbol: &str) -> Option<f64> {
+        self.emas.get(symbol).map(|ema| ema.value)
+    }
+
+    /// Mark price of `symbol` at `index_price`, using its average basis clamped
+    /// to the symbol's `max_basis_bps`
+    ///
+    /// Without a basis the mark price is exactly the index price.
+    pub fn mark_price(&self, config: &MarkPriceConfig, symbol: &str, index_price: Amount) -> Amount {
+        let factor = mark_price(1.0, self.basis_ema(symbol).unwrap_or(0.0), config.max_basis(symbol));
+        index_price * amount::from_f64_lossy(factor)
+    }
+
+    /// Drop the average of every symbol `keep` rejects, returning how many
+    /// were dropped
+    pub fn retain_symbols(&mut self, mut keep: impl FnMut(&str) -> bool) -> usize {
+        let before = self.emas.len();
+        self.emas.retain(|symbol, _| keep(symbol));
+        before - self.emas.len()
+    }
+
+    /// Symbols with an average basis
+    pub fn symbols(&self) -> usize {
+        self.emas.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basis_clamped_to_max() {
+        assert_eq!(mark_price(100.0, 2.0, 0.5), 150.0);
+        assert_eq!(mark_price(100.0, -2.0, 0.5), 50.0);
+        assert_eq!(mark_price(100.0, 0.25, 0.5), 125.0);
+        assert_eq!(mark_price(100.0, 0.25, 0.0), 100.0);
+
+        let mut config = MarkPriceConfig::default();
+        config.max_basis_bps.insert("BTC/USD".to_string(), 50);
+        let mut calculator = MarkPriceCalculator::new();
+        calculator.observe(&config, "BTC/USD", 0.02, 0);
+        calculator.observe(&config, "ETH/USD", 0.02, 0);
+        assert!((calculator.mark_price(&config, "BTC/USD", 60_000.0) - 60_300.0).abs() < 1e-6);
+        // Symbols without a limit are marked at the index
+        assert_eq!(calculator.mark_price(&config, "ETH/USD", 3_000.0), 3_000.0);
+    }
+
+    #[test]
+    fn test_basis_ema_decays_by_half_life() {
+        let config = MarkPriceConfig::default();
+        let mut calculator = MarkPriceCalculator::new();
+        assert_eq!(calculator.observe(&config, "BTC/USD", 0.01, 0), 0.01);
+
+        // One half-life moves the average halfway to the new sample
+        assert!((calculator.observe(&config, "BTC/USD", 0.0, 60) - 0.005).abs() < 1e-12);
+        // Resampling at the same time changes nothing
+        assert!((calculator.observe(&config, "BTC/USD", 0.0, 60) - 0.005).abs() < 1e-12);
+        // Two more halve it twice
+        assert!((calculator.observe(&config, "BTC/USD", 0.0, 180) - 0.00125).abs() < 1e-12);
+        assert_eq!(calculator.basis_ema("ETH/USD"), None);
+    }
+
+    #[test]
+    fn test_mark_price_violations() {
+        let mut config = MarkPriceConfig::default();
+        assert!(config.violations().is_empty());
+        config.basis_half_life_secs = 0;
+        config.max_basis_bps.insert("BTC/USD".to_string(), 10_000);
+        let fields: Vec<String> = config.violations().into_iter().map(|violation| violation.field).collect();
+        assert_eq!(fields, ["basis_half_life_secs", "max_basis_bps.BTC/USD"]);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::address::FeedAddress;
+use crate::error::ConfigViolation;
+use crate::health::{MintDecimals, PositionAccount};
+use crate::instruction::{is_market_position, program_market_address};
+use crate::position::MarginParams;
+use crate::rounding::to_base_units;
+use serde_with::{DisplayFromStr, serde_as};
+use solana_sdk::pubkey::Pubkey;
+
+/// Share of a position liquidated in a single transaction in markets that
+/// don't set their own
+pub(crate) const DEFAULT_CLOSE_FACTOR: f64 = 0.5;
+
+fn default_close_factor() -> f64 {
+    DEFAULT_CLOSE_FACTOR
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A market the engine monitors, whose parameters take precedence over the
+/// global configuration for positions in its symbol
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MarketConfig {
+    /// Program holding the market's positions
+    #[serde_as(as = "DisplayFromStr")]
+    pub program_id: Pubkey,
+    /// Id the market was created with in its program, which its config, vault
+    /// authority and position accounts are derived from
+    #[serde(default)]
+    pub market_id: u64,
+    /// Trading pair symbol of the market's positions
+    pub symbol: String,
+    /// Pyth price account of the symbol, taking precedence over `price_accounts`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oracle_feed: Option<FeedAddress>,
+    /// Decimals of the market's collateral and debt mints, scaling its position
+    /// accounts' amounts to whole tokens
+    #[serde(default)]
+    pub mint_decimals: MintDecimals,
+    /// Maintenance margin ratio of the market's positions (e.g., 0.05 for 5%)
+    pub maintenance_margin: f64,
+    /// Maintenance margin ratio of long positions, if it differs from
+    /// `maintenance_margin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintenance_margin_long: Option<f64>,
+    /// Maintenance margin ratio of short positions, if it differs from
+    /// `maintenance_margin`; usually higher, as shorts can lose without bound
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintenance_margin_short: Option<f64>,
+    /// Share of a position liquidated in a single transaction (0-1) when
+    /// partial liquidations are enabled; bankrupt positions are closed in full
+    #[serde(default = "default_close_factor")]
+    pub close_factor: f64,
+    /// Whether the market's positions are checked for liquidation
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+impl MarketConfig {
+    /// An enabled market without its own price feed, the program's first,
+    /// counting amounts in base units and liquidating [`DEFAULT_CLOSE_FACTOR`]
+    /// of a position at a time
+    pub fn new(program_id: Pubkey, symbol: impl Into<String>, maintenance_margin: f64) -> Self {
+        Self {
+            program_id,
+            market_id: 0,
+            symbol: symbol.into(),
+            oracle_feed: None,
+            mint_decimals: MintDecimals::default(),
+            maintenance_margin,
+            maintenance_margin_long: None,
+            maintenance_margin_short: None,
+            close_factor: DEFAULT_CLOSE_FACTOR,
+            enabled: true,
+        }
+    }
+
+    /// Address of the market's config account in its program
+    pub fn address(&self) -> Pubkey {
+        program_market_address(&self.program_id, self.market_id)
+    }
+
+    /// Whether the program account at `address`, decoded as `account`, is one
+    /// of the market's positions rather than another market's of its program
+    pub(crate) fn holds_position(&self, address: &Pubkey, account: &PositionAccount) -> bool {
+        is_market_position(&self.program_id, &self.address(), address, &account.owner, account.bump)
+    }
+
+    /// Maintenance margin ratios of the market's positions on each side,
+    /// falling back to `maintenance_margin` for sides without their own
+    pub fn margin_params(&self) -> MarginParams {
+        MarginParams {
+            long: self.maintenance_margin_long.unwrap_or(self.maintenance_margin),
+            short: self.maintenance_margin_short.unwrap_or(self.maintenance_margin),
+        }
+    }
+
+    /// Repay amount of a liquidation repaying `debt` (in debt tokens), in base
+    /// units of the debt mint; rounded down, so it never repays more than `debt`
+    pub fn repay_amount(&self, debt: f64) -> u64 {
+        to_base_units(debt, self.mint_decimals.debt)
+    }
+
+    /// Every inconsistency in the market's parameters
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if self.symbol.is_empty() {
+            violations.push(ConfigViolation::new("symbol", "must not be empty"));
+        }
+        let margins = [
+            ("maintenance_margin", Some(self.maintenance_margin)),
+            ("maintenance_margin_long", self.maintenance_margin_long),
+            ("maintenance_margin_short", self.maintenance_margin_short),
+        ];
+        for (field, margin) in margins {
+            if let Some(margin) = margin
+                && !(margin > 0.0 && margin < 1.0)
+            {
+                violations.push(ConfigViolation::new(field, format!("must be between 0 and 1, got {}", margin)));
+            }
+        }
+        if !(self.close_factor > 0.0 && self.close_factor <= 1.0) {
+            violations.push(ConfigViolation::new(
+                "close_factor",
+                format!("must be greater than 0 and at most 1, got {}", self.close_factor),
+            ));
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_market_defaults_and_violations() {
+        let program_id = Pubkey::new_unique();
+        let market: MarketConfig = toml::from_str(&format!(
+            "program_id = \"{}\"\nsymbol = \"BTC/USD\"\nmaintenance_margin = 0.03",
+            program_id
+        ))
+        .unwrap();
+        assert_eq!(market, MarketConfig::new(program_id, "BTC/USD", 0.03));
+        assert!(market.violations().is_empty());
+
+        assert_eq!(market.margin_params(), MarginParams::shared(0.03));
+
+        let market = MarketConfig {
+            close_factor: 1.5,
+            maintenance_margin: 0.0,
+            maintenance_margin_short: Some(1.0),
+            ..market
+        };
+        let fields: Vec<String> = market.violations().into_iter().map(|violation| violation.field).collect();
+        assert_eq!(fields, ["maintenance_margin", "maintenance_margin_short", "close_factor"]);
+    }
+
+    #[test]
+    fn test_side_margins_fall_back_to_shared() {
+        let program_id = Pubkey::new_unique();
+        let market: MarketConfig = toml::from_str(&format!(
+            "program_id = \"{}\"\nsymbol = \"SOL/USD\"\nmaintenance_margin = 0.05\nmaintenance_margin_short = 0.08",
+            program_id
+        ))
+        .unwrap();
+        assert!(market.violations().is_empty());
+        assert_eq!(market.margin_params(), MarginParams { long: 0.05, short: 0.08 });
+
+        // Sides without their own margin aren't written out
+        let serialized = toml::to_string(&market).unwrap();
+        assert!(serialized.contains("maintenance_margin_short = 0.08"));
+        assert!(!serialized.contains("maintenance_margin_long"));
+    }
+
+    #[test]
+    fn test_positions_held_by_market_id() {
+        let program_id = Pubkey::new_unique();
+        let market: MarketConfig = toml::from_str(&format!(
+            "program_id = \"{}\"\nmarket_id = 2\nsymbol = \"SOL/USD\"\nmaintenance_margin = 0.05",
+            program_id
+        ))
+        .unwrap();
+        assert_eq!(market.address(), program_market_address(&program_id, 2));
+        let first = MarketConfig::new(program_id, "BTC/USD", 0.05);
+        assert_eq!(first.address(), program_market_address(&program_id, 0));
+
+        let mut account = PositionAccount {
+            owner: Pubkey::new_unique(),
+            bump: 0,
+            collateral: 1,
+            debt: 1,
+            closed: false,
+            flagged_at: 0,
+            flag_price: 0,
+            flag_price_expo: 0,
+        };
+        let (address, bump) = Pubkey::find_program_address(
+            &[b"position", market.address().as_ref(), account.owner.as_ref()],
+            &program_id,
+        );
+        account.bump = bump;
+        assert!(market.holds_position(&address, &account));
+        // The same owner's position in the program's other market isn't
+        assert!(!first.holds_position(&address, &account));
+    }
+
+    #[test]
+    fn test_repay_amount_in_debt_base_units() {
+        let market = MarketConfig {
+            mint_decimals: MintDecimals { collateral: 9, debt: 6 },
+            ..MarketConfig::new(Pubkey::new_unique(), "BTC/USD", 0.05)
+        };
+        assert_eq!(market.repay_amount(1234.5678919), 1_234_567_891);
+        assert_eq!(market.repay_amount(0.1 + 0.2), 300_000);
+        assert_eq!(market.repay_amount(0.0000009), 0);
+        // Markets counting in base units repay whole units
+        assert_eq!(MarketConfig::new(Pubkey::new_unique(), "BTC/USD", 0.05).repay_amount(2.9), 2);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::address::PositionAddress;
+use std::collections::HashMap;
+
+/// What the engine holds in memory, and what it evicted or dropped to keep
+/// that bounded over long runs
+///
+/// Positions stay cached for `closed_retention_secs` after they're fully
+/// liquidated or closed, and a symbol's price history and average basis only
+/// while positions in it are monitored. Internal queues are bounded: events to a
+/// full queue are dropped and counted rather than waited for, and subscribers
+/// lagging more than `event_capacity` events behind miss the oldest.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MemoryStats {
+    /// Positions cached, including finished ones awaiting eviction
+    pub positions: usize,
+    /// Positions fully liquidated or closed, evicted once their retention passes
+    pub finished_positions: usize,
+    /// Finished positions evicted since the engine started
+    pub evicted_positions: u64,
+    /// Symbols whose price history and average basis were dropped since the
+    /// engine started, after their last position was removed
+    pub evicted_symbols: u64,
+    /// Positions with a last pushed update
+    pub position_updates: usize,
+    /// Positions with a last check time
+    pub last_checked: usize,
+    /// Symbols with a price history for the sanity check
+    pub price_histories: usize,
+    /// Symbols with an average basis for mark prices
+    pub mark_price_averages: usize,
+    /// Symbols with a funding index
+    pub funding_indices: usize,
+    /// Entries in the state file
+    pub state_entries: usize,
+    /// Liquidation event keys remembered for deduplication
+    pub dedup_keys: usize,
+    /// Events buffered per subscriber before a lagging one misses the oldest
+    pub event_capacity: usize,
+    /// Subscribers to position updates and liquidation events
+    pub event_subscribers: usize,
+    /// Events buffered for the slowest subscriber
+    pub queued_events: usize,
+    /// Liquidation attempts not written to the store because its queue was full
+    pub store_dropped: u64,
+    /// Dry-run liquidations not written to the report because its queue was full
+    pub report_dropped: u64,
+    /// Audit records not written because the audit log's queue was full
+    pub audit_dropped: u64,
+    /// Webhook notifications dropped because their queue was full
+    pub webhooks_dropped: u64,
+    /// Alerts dropped because their queue was full
+    pub alerts_dropped: u64,
+}
+
+/// Positions fully liquidated or closed, and when, so they're evicted once
+/// their retention passes
+#[derive(Debug, Default)]
+pub(crate) struct Retention {
+    finished: HashMap<PositionAddress, i64>,
+    evicted_positions: u64,
+    evicted_symbols: u64,
+}
+
+impl Retention {
+    /// Remember a position finished at `now`, unless it already was
+    pub(crate) fn finish(&mut self, address: PositionAddress, now: i64) {
+        self.finished.entry(address).or_insert(now);
+    }
+
+    /// Forget a position that opened again or is no longer monitored
+    pub(crate) fn forget(&mut self, address: &PositionAddress) {
+        self.finished.remove(address);
+    }
+
+    /// Positions finished more than `retention_secs` before `now`
+    pub(crate) fn expired(&self, now: i64, retention_secs: u64) -> Vec<PositionAddress> {
+        let cutoff = now.saturating_sub(retention_secs as i64);
+        self.finished
+            .iter()
+            .filter(|(_, finished_at)| **finished_at <= cutoff)
+            .map(|(address, _)| *address)
+            .collect()
+    }
+
+    /// Forget an evicted position, counting it
+    pub(crate) fn evicted(&mut self, address: &PositionAddress) {
+        if self.finished.remove(address).is_some() {
+            self.evicted_positions += 1;
+        }
+    }
+
+    /// Count symbols whose buffers were dropped
+    pub(crate) fn evicted_symbols(&mut self, symbols: usize) {
+        self.evicted_symbols += symbols as u64;
+    }
+
+    /// Finished positions awaiting eviction, and those evicted so far
+    pub(crate) fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            finished_positions: self.finished.len(),
+            evicted_positions: self.evicted_positions,
+            evicted_symbols: self.evicted_symbols,
+            ..MemoryStats::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finished_positions_expire_after_retention() {
+        let mut retention = Retention::default();
+        let (early, late) = (PositionAddress::new_unique(), PositionAddress::new_unique());
+        retention.finish(early, 1_000);
+        retention.finish(late, 2_000);
+        // Finishing again doesn't restart the clock
+        retention.finish(early, 1_500);
+        assert!(retention.expired(4_599, 3_600).is_empty());
+        assert_eq!(retention.expired(4_600, 3_600), [early]);
+
+        retention.evicted(&early);
+        retention.evicted(&early);
+        let stats = retention.stats();
+        assert_eq!((stats.finished_positions, stats.evicted_positions), (1, 1));
+    }
+
+    #[test]
+    fn test_reopened_position_not_evicted() {
+        let mut retention = Retention::default();
+        let address = PositionAddress::new_unique();
+        retention.finish(address, 1_000);
+        retention.forget(&address);
+        assert!(retention.expired(10_000, 3_600).is_empty());
+        assert_eq!(retention.stats().finished_positions, 0);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::error::{LiquidationError, RpcErrorKind};
+use crate::rate_limit::RateLimiter;
+use crate::rpc_pool::RpcPool;
+use serde_with::{DisplayFromStr, serde_as};
+use solana_client::nonce_utils;
+use solana_sdk::{account::Account, hash::Hash, instruction::Instruction, pubkey::Pubkey, system_instruction};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Durable nonce account used in place of recent blockhashes
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct NonceConfig {
+    /// The nonce account
+    #[serde_as(as = "DisplayFromStr")]
+    pub account: Pubkey,
+    /// Authority allowed to advance the nonce; must be the engine's payer
+    #[serde_as(as = "DisplayFromStr")]
+    pub authority: Pubkey,
+}
+
+/// Nonce value stored in a nonce account
+pub(crate) fn nonce_value(account: &Account) -> Result<Hash, LiquidationError> {
+    nonce_utils::data_from_account(account)
+        .map(|data| data.blockhash())
+        .map_err(|e| LiquidationError::rpc(RpcErrorKind::Decode, format!("invalid nonce account: {}", e)))
+}
+
+/// Whether an error means the transaction's nonce no longer matches the account,
+/// so the nonce should be re-read before retrying
+pub(crate) fn is_nonce_mismatch(error: &LiquidationError) -> bool {
+    let message = error.to_string().to_lowercase();
+    message.contains("blockhash not found") || message.contains("nonce")
+}
+
+/// Durable nonce account with the last nonce value read from it
+#[derive(Debug)]
+pub(crate) struct NonceAccount {
+    config: NonceConfig,
+    rpc: RpcPool,
+    rate_limiter: Arc<RateLimiter>,
+    value: Mutex<Option<Hash>>,
+}
+
+impl NonceAccount {
+    /// Track the configured nonce account, reading it through the given RPC endpoints
+    pub fn new(config: NonceConfig, rpc: RpcPool, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            config,
+            rpc,
+            rate_limiter,
+            value: Mutex::new(None),
+        }
+    }
+
+    /// Authority allowed to advance the nonce
+    pub fn authority(&self) -> Pubkey {
+        self.config.authority
+    }
+
+    /// Instruction advancing the nonce, which must come first in the transaction
+    pub fn advance_instruction(&self) -> Instruction {
+        system_instruction::advance_nonce_account(&self.config.account, &self.config.authority)
+    }
+
+    /// Stored nonce value, read from the account the first time
+    pub async fn value(&self) -> Result<Hash, LiquidationError> {
+        let mut value = self.value.lock().await;
+        if let Some(hash) = *value {
+            return Ok(hash);
+        }
+        let hash = self.fetch().await?;
+        *value = Some(hash);
+        Ok(hash)
+    }
+
+    /// Re-read the nonce value after it's been used
+    ///
+    /// On failure the stored value is dropped so the next use reads it afresh.
+    pub async fn refresh(&self) {
+        let mut value = self.value.lock().await;
+        *value = match self.fetch().await {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                warn!("Failed to refresh nonce account {}: {}", self.config.account, e);
+                None
+            }
+        };
+    }
+
+    async fn fetch(&self) -> Result<Hash, LiquidationError> {
+        let address = self.config.account;
+        let account = self
+            .rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client.get_account(&address).map_err(LiquidationError::from)
+            })
+            .await
+            .map_err(|e| match e {
+                LiquidationError::RpcError { endpoint, kind, message } => LiquidationError::RpcError {
+                    endpoint,
+                    kind,
+                    message: format!("nonce account {}: {}", address, message),
+                },
+                e => e,
+            })?;
+        nonce_value(&account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::rpc_client::RpcClient;
+    use solana_sdk::nonce::state::{Data, DurableNonce, State, Versions};
+    use solana_sdk::system_program;
+
+    fn create_nonce_account(authority: &Pubkey, blockhash: &Hash) -> Account {
+        let state = State::Initialized(Data::new(*authority, DurableNonce::from_blockhash(blockhash), 5000));
+        Account {
+            lamports: 1_447_680,
+            data: bincode::serialize(&Versions::new(state)).unwrap(),
+            owner: system_program::id(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn test_nonce_value() {
+        let blockhash = Hash::new_unique();
+        let account = create_nonce_account(&Pubkey::new_unique(), &blockhash);
+        assert_eq!(
+            nonce_value(&account).unwrap(),
+            *DurableNonce::from_blockhash(&blockhash).as_hash()
+        );
+
+        let uninitialized = Account {
+            data: bincode::serialize(&Versions::new(State::Uninitialized)).unwrap(),
+            ..account.clone()
+        };
+        assert!(nonce_value(&uninitialized).is_err());
+        let not_system_owned = Account {
+            owner: Pubkey::new_unique(),
+            ..account
+        };
+        assert!(nonce_value(&not_system_owned).is_err());
+    }
+
+    #[test]
+    fn test_advance_instruction() {
+        let config = NonceConfig {
+            account: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+        };
+        let rpc = Arc::new(RpcClient::new_mock("succeeds".to_string())).into();
+        let nonce = NonceAccount::new(config.clone(), rpc, Arc::new(RateLimiter::default()));
+
+        let instruction = nonce.advance_instruction();
+        assert_eq!(instruction.program_id, system_program::id());
+        assert_eq!(instruction.accounts[0].pubkey, config.account);
+        assert!(instruction.accounts.iter().any(|meta| meta.pubkey == config.authority && meta.is_signer));
+    }
+
+    #[test]
+    fn test_is_nonce_mismatch() {
+        assert!(is_nonce_mismatch(&LiquidationError::rpc(
+            RpcErrorKind::Response,
+            "Transaction simulation failed: Blockhash not found"
+        )));
+        assert!(is_nonce_mismatch(&LiquidationError::LiquidationFailed(
+            "invalid nonce account".to_string()
+        )));
+        assert!(!is_nonce_mismatch(&LiquidationError::ConfirmationTimeout));
+        assert!(!is_nonce_mismatch(&LiquidationError::rpc(RpcErrorKind::Transport, "connection refused")));
+    }
+}
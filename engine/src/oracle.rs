@@ -1,29 +1,323 @@
-use crate::error::LiquidationError;
+use crate::address::FeedAddress;
+use crate::amount::{self, Amount, AmountExt};
+use crate::error::{ConfigViolation, LiquidationError};
+use crate::rate_limit::RateLimiter;
+use crate::rpc_pool::RpcPool;
 use async_trait::async_trait;
+use pyth_sdk_solana::state::PriceStatus;
 use solana_sdk::pubkey::Pubkey;
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-// Newtype wrapper to implement Debug for RpcClient
-#[derive(Clone)]
-struct DebuggableRpcClient(Arc<solana_client::rpc_client::RpcClient>);
+/// Pyth oracle program owning the mainnet price accounts
+pub const PYTH_MAINNET_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
 
-impl fmt::Debug for DebuggableRpcClient {
+/// Most accounts the RPC node returns for one `getMultipleAccounts` request
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Pyth oracle program owning the devnet price accounts
+pub const PYTH_DEVNET_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s");
+
+/// Price data reported by an oracle for a single symbol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceData {
+    /// The price selected by the oracle's configured price source
+    pub price: Amount,
+    /// The confidence interval of the aggregate price
+    pub confidence: f64,
+    /// The exponentially-weighted moving average price
+    pub ema_price: f64,
+    /// The confidence interval of the EMA price
+    pub ema_confidence: f64,
+    /// Unix timestamp of the last price update
+    pub publish_time: i64,
+    /// Slot the price was published in, for providers that report one
+    pub publish_slot: Option<u64>,
+}
+
+impl PriceData {
+    /// Create price data for a provider without an EMA (the EMA mirrors the price)
+    pub fn from_price(price: Amount, publish_time: i64) -> Self {
+        Self {
+            price,
+            confidence: 0.0,
+            ema_price: price.as_f64(),
+            ema_confidence: 0.0,
+            publish_time,
+            publish_slot: None,
+        }
+    }
+
+    /// The same data moved to `mark_price`, with the EMA and confidence
+    /// intervals scaled by the same factor
+    pub fn at_mark(&self, mark_price: Amount) -> Self {
+        let factor = if self.price == Amount::ZERO { 1.0 } else { mark_price.as_f64() / self.price.as_f64() };
+        Self {
+            price: mark_price,
+            confidence: self.confidence * factor,
+            ema_price: self.ema_price * factor,
+            ema_confidence: self.ema_confidence * factor,
+            publish_time: self.publish_time,
+            publish_slot: self.publish_slot,
+        }
+    }
+}
+
+/// Reject a price published at `publish_time` if it's more than `max_age_secs`
+/// old at `now`, returning its age otherwise
+///
+/// Clocks running behind the oracle don't make its prices stale.
+pub fn check_staleness(symbol: &str, publish_time: i64, now: i64, max_age_secs: u64) -> Result<u64, LiquidationError> {
+    let age_secs = now.saturating_sub(publish_time).max(0) as u64;
+    if age_secs > max_age_secs {
+        return Err(LiquidationError::StalePrice {
+            symbol: symbol.to_string(),
+            age_secs,
+            max_age_secs,
+        });
+    }
+    Ok(age_secs)
+}
+
+/// Trading status of a Pyth price
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradingStatus {
+    Unknown,
+    Trading,
+    Halted,
+    Auction,
+    Ignored,
+}
+
+impl From<PriceStatus> for TradingStatus {
+    fn from(status: PriceStatus) -> Self {
+        match status {
+            PriceStatus::Unknown => Self::Unknown,
+            PriceStatus::Trading => Self::Trading,
+            PriceStatus::Halted => Self::Halted,
+            PriceStatus::Auction => Self::Auction,
+            PriceStatus::Ignored => Self::Ignored,
+        }
+    }
+}
+
+impl fmt::Display for TradingStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("RpcClient").finish()
+        let status = match self {
+            Self::Unknown => "unknown",
+            Self::Trading => "trading",
+            Self::Halted => "halted",
+            Self::Auction => "auction",
+            Self::Ignored => "ignored",
+        };
+        f.write_str(status)
+    }
+}
+
+/// The fields of a Pyth price account the engine reads, as stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PythPriceFeed {
+    /// The aggregate price, scaled by `10^-expo`
+    pub price: i64,
+    /// The confidence interval of the aggregate price, scaled alike
+    pub confidence: u64,
+    /// Exponent of the prices and confidence intervals
+    pub expo: i32,
+    /// The EMA price, scaled alike
+    pub ema_price: i64,
+    /// The confidence interval of the EMA price, scaled alike
+    pub ema_confidence: u64,
+    /// Trading status of the aggregate price
+    pub status: TradingStatus,
+    /// Slot the aggregate price was published in
+    pub publish_slot: u64,
+    /// Unix timestamp of the last price update
+    pub publish_time: i64,
+    /// Publishers quoting the price
+    pub num_publishers: u32,
+}
+
+impl PythPriceFeed {
+    /// The feed as price data, reporting the price `source` selects
+    ///
+    /// The price is selected on the raw mantissas so fixed point converts it
+    /// exactly; fails if the exponent or price is out of its range.
+    pub fn price_data(&self, source: PriceSource) -> Result<PriceData, LiquidationError> {
+        let scale = 10f64.powi(self.expo);
+        Ok(PriceData {
+            price: amount::from_mantissa(source.select(self.price, self.ema_price), self.expo)?,
+            confidence: self.confidence as f64 * scale,
+            ema_price: self.ema_price as f64 * scale,
+            ema_confidence: self.ema_confidence as f64 * scale,
+            publish_time: self.publish_time,
+            publish_slot: Some(self.publish_slot),
+        })
+    }
+
+    /// Confidence interval of the aggregate price as a ratio of it
+    pub fn confidence_ratio(&self) -> f64 {
+        self.confidence as f64 / self.price as f64
+    }
+}
+
+/// Decode a Pyth price account, checking its magic number, version and type
+pub fn decode_pyth_price_account(data: &[u8]) -> Result<PythPriceFeed, LiquidationError> {
+    let account = pyth_sdk_solana::state::load_price_account(data)
+        .map_err(|e| LiquidationError::OracleError(e.to_string()))?;
+    Ok(PythPriceFeed {
+        price: account.agg.price,
+        confidence: account.agg.conf,
+        expo: account.expo,
+        ema_price: account.ema_price.val,
+        ema_confidence: account.ema_conf.val as u64,
+        status: account.agg.status.into(),
+        publish_slot: account.agg.pub_slot,
+        publish_time: account.timestamp,
+        num_publishers: account.num_qt,
+    })
+}
+
+/// Prices a check cycle evaluates its positions at, taken once at its start so
+/// positions of the same symbol are judged alike
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PriceSnapshot {
+    /// Identifier of the cycle, attached to its logs and results
+    pub cycle_id: String,
+    /// Unix timestamp the prices were taken at
+    pub taken_at: i64,
+    /// Price data of each symbol, moved to its mark price
+    pub prices: HashMap<String, PriceData>,
+}
+
+impl PriceSnapshot {
+    /// Price data of a symbol, if it could be priced when the snapshot was taken
+    pub fn get(&self, symbol: &str) -> Option<PriceData> {
+        self.prices.get(symbol).copied()
+    }
+}
+
+/// Which price a Pyth price account should report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PriceSource {
+    /// The instantaneous aggregate price
+    #[default]
+    Aggregate,
+    /// The EMA price
+    Ema,
+    /// The lower of the aggregate and EMA prices
+    MinOfBoth,
+    /// The higher of the aggregate and EMA prices
+    MaxOfBoth,
+}
+
+impl PriceSource {
+    /// Select the reported price from the aggregate and EMA prices
+    pub fn select<T: PartialOrd + Copy>(&self, aggregate: T, ema: T) -> T {
+        match self {
+            Self::Aggregate => aggregate,
+            Self::Ema => ema,
+            Self::MinOfBoth if ema < aggregate => ema,
+            Self::MaxOfBoth if ema > aggregate => ema,
+            Self::MinOfBoth | Self::MaxOfBoth => aggregate,
+        }
+    }
+}
+
+/// What becomes of oracle prices whose confidence interval is wider than the
+/// oracle accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidencePolicy {
+    /// Reject the price, skipping the positions it would have priced
+    #[default]
+    Reject,
+    /// Accept the price, judging positions at the edge of its confidence
+    /// interval most favourable to the trader
+    Widen,
+}
+
+/// Settings for oracle prices with wide confidence intervals
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ConfidenceConfig {
+    /// Policy of symbols without their own
+    pub policy: ConfidencePolicy,
+    /// Confidence intervals a widened price moves in the trader's favour by
+    pub widen_multiplier: f64,
+    /// `policy` of symbols that need another, by symbol
+    pub symbol_policies: HashMap<String, ConfidencePolicy>,
+}
+
+impl Default for ConfidenceConfig {
+    fn default() -> Self {
+        Self {
+            policy: ConfidencePolicy::Reject,
+            widen_multiplier: 1.0,
+            symbol_policies: HashMap::new(),
+        }
+    }
+}
+
+impl ConfidenceConfig {
+    /// Policy for prices of a symbol
+    pub fn policy(&self, symbol: &str) -> ConfidencePolicy {
+        self.symbol_policies.get(symbol).copied().unwrap_or(self.policy)
+    }
+
+    /// Price a position on the given side of `symbol` is judged at, for a
+    /// `price` known within `confidence`
+    ///
+    /// Widened, longs are judged `widen_multiplier` confidence intervals above
+    /// the price and shorts as far below it, so only positions underwater at
+    /// every price the oracle considers plausible are liquidated.
+    pub fn trigger_price(&self, symbol: &str, is_long: bool, price: Amount, confidence: f64) -> Amount {
+        let widening = || amount::from_f64_lossy(self.widen_multiplier * confidence);
+        match self.policy(symbol) {
+            ConfidencePolicy::Reject => price,
+            ConfidencePolicy::Widen if is_long => price + widening(),
+            ConfidencePolicy::Widen => price - widening(),
+        }
+    }
+
+    /// Every problem with the settings
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if !self.widen_multiplier.is_finite() || self.widen_multiplier < 0.0 {
+            violations.push(ConfigViolation::new(
+                "widen_multiplier",
+                format!("must be non-negative, got {}", self.widen_multiplier),
+            ));
+        }
+        violations
     }
 }
 
 /// Trait for price oracle providers
+///
+/// Under the `testing` feature, and in the crate's own tests, mockall generates
+/// `MockOracleProvider` from it. Its default methods are mocked too; see
+/// [`MockOracleProvider::expect_prices`] for expectations answering every
+/// price lookup from a fixed table.
+#[cfg_attr(any(test, feature = "testing"), mockall::automock)]
 #[async_trait]
 pub trait OracleProvider: Send + Sync + std::fmt::Debug {
     /// Get the current price for a symbol
-    async fn get_price(&self, symbol: &str) -> Result<f64, LiquidationError>;
+    async fn get_price(&self, symbol: &str) -> Result<Amount, LiquidationError>;
+    
+    /// Get the current price data (selected price and EMA) for a symbol
+    async fn get_price_data(&self, symbol: &str) -> Result<PriceData, LiquidationError> {
+        // Default implementation has no EMA, so it mirrors the spot price
+        let price = self.get_price(symbol).await?;
+        Ok(PriceData::from_price(price, chrono::Utc::now().timestamp()))
+    }
     
-    /// Get multiple prices at once (for batch processing)
-    async fn get_prices(&self, symbols: &[&str]) -> Result<HashMap<String, f64>, LiquidationError> {
+    /// Get multiple prices at once (for batch processing), failing if any
+    /// symbol can't be priced
+    // The symbols' lifetime is named for mockall, which can't elide nested ones
+    async fn get_prices<'a>(&self, symbols: &[&'a str]) -> Result<HashMap<String, Amount>, LiquidationError> {
         let mut prices = HashMap::new();
         for &symbol in symbols {
             let price = self.get_price(symbol).await?;
@@ -32,6 +326,28 @@ pub trait OracleProvider: Send + Sync + std::fmt::Debug {
         Ok(prices)
     }
     
+    /// Get the current price data of multiple symbols at once, returning the
+    /// symbols that couldn't be priced with their errors rather than failing
+    /// the whole batch
+    async fn get_prices_partial<'a>(
+        &self,
+        symbols: &[&'a str],
+    ) -> (HashMap<String, PriceData>, HashMap<String, LiquidationError>) {
+        let mut prices = HashMap::new();
+        let mut failures = HashMap::new();
+        for &symbol in symbols {
+            match self.get_price_data(symbol).await {
+                Ok(price_data) => {
+                    prices.insert(symbol.to_string(), price_data);
+                }
+                Err(e) => {
+                    failures.insert(symbol.to_string(), e);
+                }
+            }
+        }
+        (prices, failures)
+    }
+    
     /// Get the last update time for a price feed
     async fn last_update_time(&self, _symbol: &str) -> Result<u64, LiquidationError> {
         // Default implementation returns current timestamp
@@ -39,13 +355,67 @@ pub trait OracleProvider: Send + Sync + std::fmt::Debug {
     }
 }
 
+#[cfg(any(test, feature = "testing"))]
+impl MockOracleProvider {
+    /// Answer every price lookup, including those of the trait's default
+    /// methods, from `prices` published at `publish_time`
+    ///
+    /// Symbols without a price fail with an oracle error, as they would with a
+    /// provider that doesn't know them.
+    pub fn expect_prices(&mut self, prices: HashMap<String, Amount>, publish_time: i64) -> &mut Self {
+        let prices = Arc::new(prices);
+        let lookup = |prices: &Arc<HashMap<String, Amount>>| {
+            let prices = Arc::clone(prices);
+            move |symbol: &str| {
+                prices
+                    .get(symbol)
+                    .copied()
+                    .ok_or_else(|| LiquidationError::OracleError(format!("No price for {}", symbol)))
+            }
+        };
+        self.expect_get_price().returning(lookup(&prices));
+        let price = lookup(&prices);
+        self.expect_get_price_data()
+            .returning(move |symbol| price(symbol).map(|price| PriceData::from_price(price, publish_time)));
+        let price = lookup(&prices);
+        self.expect_get_prices().returning(move |symbols| {
+            symbols
+                .iter()
+                .map(|&symbol| Ok((symbol.to_string(), price(symbol)?)))
+                .collect()
+        });
+        let price = lookup(&prices);
+        self.expect_get_prices_partial().returning(move |symbols| {
+            let mut found = HashMap::new();
+            let mut failures = HashMap::new();
+            for &symbol in symbols {
+                match price(symbol) {
+                    Ok(price) => {
+                        found.insert(symbol.to_string(), PriceData::from_price(price, publish_time));
+                    }
+                    Err(e) => {
+                        failures.insert(symbol.to_string(), e);
+                    }
+                }
+            }
+            (found, failures)
+        });
+        let price = lookup(&prices);
+        self.expect_last_update_time()
+            .returning(move |symbol| price(symbol).map(|_| publish_time as u64));
+        self
+    }
+}
+
 /// Pyth Network Oracle implementation
 #[derive(Debug, Clone)]
 pub struct PythOracle {
-    /// RPC client for Solana
-    rpc_client: DebuggableRpcClient,
+    /// RPC endpoints for Solana
+    rpc: RpcPool,
+    /// Paces requests to the RPC endpoint
+    rate_limiter: Arc<RateLimiter>,
     /// Cache of price accounts
-    price_accounts: Arc<RwLock<HashMap<String, Pubkey>>>,
+    price_accounts: Arc<RwLock<HashMap<String, FeedAddress>>>,
     /// Price feed configuration
     config: OracleConfig,
 }
@@ -61,6 +431,10 @@ pub struct OracleConfig {
     pub max_confidence_interval: f64,
     /// Whether to use the Pyth mainnet program
     pub use_mainnet: bool,
+    /// Which price to report from the price account
+    pub price_source: PriceSource,
+    /// Which prices beyond `max_confidence_interval` are still reported
+    pub confidence: ConfidenceConfig,
 }
 
 impl Default for OracleConfig {
@@ -70,47 +444,82 @@ impl Default for OracleConfig {
             min_confidence_interval: 0.001, // 0.1%
             max_confidence_interval: 0.01,  // 1%
             use_mainnet: false,
+            price_source: PriceSource::Aggregate,
+            confidence: ConfidenceConfig::default(),
+        }
+    }
+}
+
+impl OracleConfig {
+    /// Pyth program expected to own the configured price accounts
+    pub fn program_id(&self) -> Pubkey {
+        if self.use_mainnet {
+            PYTH_MAINNET_PROGRAM_ID
+        } else {
+            PYTH_DEVNET_PROGRAM_ID
+        }
+    }
+
+    /// Check a symbol's confidence interval, as a ratio of its price, against
+    /// the accepted bounds
+    ///
+    /// Intervals wider than `max_confidence_interval` are only rejected under
+    /// [`ConfidencePolicy::Reject`]; widened prices are left for the engine to
+    /// judge at the edge of their interval.
+    pub fn check_confidence(&self, symbol: &str, confidence_ratio: f64) -> Result<(), LiquidationError> {
+        if confidence_ratio < self.min_confidence_interval {
+            return Err(LiquidationError::LowConfidencePrice(symbol.to_string()));
+        }
+        if confidence_ratio > self.max_confidence_interval
+            && self.confidence.policy(symbol) == ConfidencePolicy::Reject
+        {
+            return Err(LiquidationError::HighConfidenceInterval(symbol.to_string()));
         }
+        Ok(())
+    }
+
+    /// Check a decoded price feed at `now` as the oracle does before reporting
+    /// it: first its age, then its confidence interval
+    pub fn check_feed(&self, symbol: &str, feed: &PythPriceFeed, now: i64) -> Result<(), LiquidationError> {
+        check_staleness(symbol, feed.publish_time, now, self.max_price_age_secs)?;
+        self.check_confidence(symbol, feed.confidence_ratio())
     }
 }
 
 impl PythOracle {
     /// Create a new PythOracle instance
+    ///
+    /// `rpc` is a URL or an [`RpcPool`]; pass the engine's pool and rate limiter
+    /// when both use the same RPC endpoints.
     pub fn new(
-        rpc_url: &str,
-        price_accounts: HashMap<String, Pubkey>,
+        rpc: impl Into<RpcPool>,
+        price_accounts: HashMap<String, FeedAddress>,
         config: Option<OracleConfig>,
+        rate_limiter: Arc<RateLimiter>,
     ) -> Self {
-        let rpc_client = DebuggableRpcClient(Arc::new(
-            solana_client::rpc_client::RpcClient::new(rpc_url.to_string()),
-        ));
         Self {
-            rpc_client,
+            rpc: rpc.into(),
+            rate_limiter,
             price_accounts: Arc::new(RwLock::new(price_accounts)),
             config: config.unwrap_or_default(),
         }
     }
     
     /// Add or update a price account
-    pub async fn add_price_account(&self, symbol: &str, pubkey: Pubkey) {
+    pub async fn add_price_account(&self, symbol: &str, feed: FeedAddress) {
         let mut accounts = self.price_accounts.write().await;
-        accounts.insert(symbol.to_string(), pubkey);
+        accounts.insert(symbol.to_string(), feed);
     }
     
     /// Get the price account for a symbol
-    pub async fn get_price_account(&self, symbol: &str) -> Option<Pubkey> {
+    pub async fn get_price_account(&self, symbol: &str) -> Option<FeedAddress> {
         let accounts = self.price_accounts.read().await;
         accounts.get(symbol).copied()
     }
     
-    fn get_rpc_client(&self) -> Arc<solana_client::rpc_client::RpcClient> {
-        self.rpc_client.0.clone()
-    }
-}
-
-#[async_trait]
-impl OracleProvider for PythOracle {
-    async fn get_price(&self, symbol: &str) -> Result<f64, LiquidationError> {
+    /// Fetch and decode the price account for a symbol, rejecting stale or
+    /// low-quality prices
+    async fn load_price_account(&self, symbol: &str) -> Result<PythPriceFeed, LiquidationError> {
         // Get the price account for the symbol
         let price_account = self
             .get_price_account(symbol)
@@ -119,64 +528,155 @@ impl OracleProvider for PythOracle {
             
         // Fetch the price account data
         let account_data = self
-            .get_rpc_client()
-            .get_account_data(&price_account)
-            .map_err(|e| LiquidationError::RpcError(e.to_string()))?;
+            .rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client
+                    .get_account_data(price_account.as_ref())
+                    .map_err(LiquidationError::from)
+            })
+            .await?;
             
-        // Parse the price data using Pyth's SDK
-        let price_account = pyth_sdk_solana::state::load_price_account(&account_data)
-            .map_err(|e| LiquidationError::OracleError(e.to_string()))?;
-            
-        // Check if the price is stale
-        let last_update_time = price_account.timestamp;
-        let current_time = chrono::Utc::now().timestamp() as u64;
-        
-        if current_time.saturating_sub(last_update_time as u64) > self.config.max_price_age_secs {
-            return Err(LiquidationError::StalePrice(symbol.to_string()));
+        self.checked_feed(symbol, &account_data)
+    }
+    
+    /// Decode a symbol's price account, rejecting stale or low-quality prices
+    fn checked_feed(&self, symbol: &str, account_data: &[u8]) -> Result<PythPriceFeed, LiquidationError> {
+        let feed = decode_pyth_price_account(account_data)?;
+        self.config.check_feed(symbol, &feed, chrono::Utc::now().timestamp())?;
+        Ok(feed)
+    }
+}
+
+#[async_trait]
+impl OracleProvider for PythOracle {
+    async fn get_price(&self, symbol: &str) -> Result<Amount, LiquidationError> {
+        Ok(self.get_price_data(symbol).await?.price)
+    }
+
+    async fn get_price_data(&self, symbol: &str) -> Result<PriceData, LiquidationError> {
+        let feed = self.load_price_account(symbol).await?;
+        feed.price_data(self.config.price_source)
+    }
+    
+    async fn get_prices<'a>(&self, symbols: &[&'a str]) -> Result<HashMap<String, Amount>, LiquidationError> {
+        let (prices, mut failures) = self.get_prices_partial(symbols).await;
+        // The first failing symbol fails the batch, as one by one lookups would
+        if let Some(error) = symbols.iter().find_map(|symbol| failures.remove(*symbol)) {
+            return Err(error);
         }
-        
-        // Get the current price and confidence interval
-        let price = price_account.agg.price as f64 * 10f64.powi(price_account.expo as i32);
-        let confidence = price_account.agg.conf as f64 * 10f64.powi(price_account.expo as i32);
-        
-        // Check confidence interval
-        let confidence_ratio = confidence / price;
-        if confidence_ratio < self.config.min_confidence_interval {
-            return Err(LiquidationError::LowConfidencePrice(symbol.to_string()));
+        Ok(prices.into_iter().map(|(symbol, price_data)| (symbol, price_data.price)).collect())
+    }
+    
+    /// Price accounts are fetched together, as many as a request takes at once
+    async fn get_prices_partial<'a>(
+        &self,
+        symbols: &[&'a str],
+    ) -> (HashMap<String, PriceData>, HashMap<String, LiquidationError>) {
+        let mut prices = HashMap::new();
+        let mut failures = HashMap::new();
+        let mut requested = Vec::new();
+        let accounts = self.price_accounts.read().await;
+        for &symbol in symbols {
+            // Symbols asked for twice are fetched once
+            if failures.contains_key(symbol) || requested.iter().any(|(requested, _)| *requested == symbol) {
+                continue;
+            }
+            match accounts.get(symbol) {
+                Some(feed) => requested.push((symbol, *feed)),
+                None => {
+                    failures.insert(
+                        symbol.to_string(),
+                        LiquidationError::OracleError(format!("No price account for {}", symbol)),
+                    );
+                }
+            }
         }
+        drop(accounts);
         
-        if confidence_ratio > self.config.max_confidence_interval {
-            return Err(LiquidationError::HighConfidenceInterval(symbol.to_string()));
+        for chunk in requested.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let pubkeys: Vec<Pubkey> = chunk.iter().map(|(_, feed)| feed.pubkey()).collect();
+            let fetched = self
+                .rpc
+                .call(&self.rate_limiter, move |rpc_client| {
+                    rpc_client
+                        .get_multiple_accounts(&pubkeys)
+                        .map_err(LiquidationError::from)
+                })
+                .await;
+            let accounts = match fetched {
+                Ok(accounts) => accounts,
+                Err(e) => {
+                    for (symbol, _) in chunk {
+                        failures.insert(
+                            symbol.to_string(),
+                            LiquidationError::OracleError(format!("Failed to fetch the price account of {}: {}", symbol, e)),
+                        );
+                    }
+                    continue;
+                }
+            };
+            for ((symbol, pubkey), account) in chunk.iter().zip(accounts) {
+                let price_data = account
+                    .ok_or_else(|| LiquidationError::OracleError(format!("Price account {} of {} doesn't exist", pubkey, symbol)))
+                    .and_then(|account| self.checked_feed(symbol, &account.data))
+                    .and_then(|feed| feed.price_data(self.config.price_source));
+                match price_data {
+                    Ok(price_data) => {
+                        prices.insert(symbol.to_string(), price_data);
+                    }
+                    Err(e) => {
+                        failures.insert(symbol.to_string(), e);
+                    }
+                }
+            }
         }
-        
-        Ok(price)
+        (prices, failures)
     }
 }
 
 /// Mock oracle for testing
+#[cfg(any(test, feature = "testing"))]
 #[derive(Debug, Clone, Default)]
 pub struct MockOracle {
-    prices: Arc<RwLock<HashMap<String, f64>>>,
+    prices: Arc<RwLock<HashMap<String, Amount>>>,
+    ema_prices: Arc<RwLock<HashMap<String, f64>>>,
+    confidences: Arc<RwLock<HashMap<String, f64>>>,
 }
 
+#[cfg(any(test, feature = "testing"))]
 impl MockOracle {
     /// Create a new mock oracle
     pub fn new() -> Self {
         Self {
             prices: Arc::new(RwLock::new(HashMap::new())),
+            ema_prices: Arc::new(RwLock::new(HashMap::new())),
+            confidences: Arc::new(RwLock::new(HashMap::new())),
         }
     }
     
     /// Set a price for a symbol
-    pub async fn set_price(&self, symbol: &str, price: f64) {
+    pub async fn set_price(&self, symbol: &str, price: Amount) {
         let mut prices = self.prices.write().await;
         prices.insert(symbol.to_string(), price);
     }
+    
+    /// Set an EMA price for a symbol (defaults to the spot price when unset)
+    pub async fn set_ema_price(&self, symbol: &str, price: f64) {
+        let mut prices = self.ema_prices.write().await;
+        prices.insert(symbol.to_string(), price);
+    }
+    
+    /// Set the confidence interval of a symbol's price (defaults to zero when unset)
+    pub async fn set_confidence(&self, symbol: &str, confidence: f64) {
+        let mut confidences = self.confidences.write().await;
+        confidences.insert(symbol.to_string(), confidence);
+    }
 }
 
+#[cfg(any(test, feature = "testing"))]
 #[async_trait]
 impl OracleProvider for MockOracle {
-    async fn get_price(&self, symbol: &str) -> Result<f64, LiquidationError> {
+    async fn get_price(&self, symbol: &str) -> Result<Amount, LiquidationError> {
         self.prices
             .read()
             .await
@@ -184,12 +684,23 @@ impl OracleProvider for MockOracle {
             .copied()
             .ok_or_else(|| LiquidationError::OracleError(format!("No price for {}", symbol)))
     }
+    
+    async fn get_price_data(&self, symbol: &str) -> Result<PriceData, LiquidationError> {
+        let price = self.get_price(symbol).await?;
+        let mut data = PriceData::from_price(price, chrono::Utc::now().timestamp());
+        if let Some(ema_price) = self.ema_prices.read().await.get(symbol) {
+            data.ema_price = *ema_price;
+        }
+        if let Some(confidence) = self.confidences.read().await.get(symbol) {
+            data.confidence = *confidence;
+        }
+        Ok(data)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::signature::Keypair;
     
     #[tokio::test]
     async fn test_mock_oracle() {
@@ -203,6 +714,179 @@ mod tests {
         assert!(oracle.get_price("NON_EXISTENT").await.is_err());
     }
     
+    #[tokio::test]
+    async fn test_mock_oracle_ema_price() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        
+        // EMA mirrors the spot price until set
+        let data = oracle.get_price_data("BTC/USD").await.unwrap();
+        assert_eq!(data.ema_price, 50000.0);
+        
+        oracle.set_ema_price("BTC/USD", 52000.0).await;
+        let data = oracle.get_price_data("BTC/USD").await.unwrap();
+        assert_eq!(data.price, 50000.0);
+        assert_eq!(data.ema_price, 52000.0);
+    }
+    
+    #[tokio::test]
+    async fn test_mock_provider_prices() {
+        let mut oracle = MockOracleProvider::new();
+        oracle.expect_prices(HashMap::from([("BTC/USD".to_string(), 60000.0)]), 1_700_000_000);
+        assert_eq!(oracle.get_price("BTC/USD").await.unwrap(), 60000.0);
+        
+        // Lookups through the default methods answer from the same table
+        assert_eq!(oracle.get_price_data("BTC/USD").await.unwrap().publish_time, 1_700_000_000);
+        assert_eq!(oracle.last_update_time("BTC/USD").await.unwrap(), 1_700_000_000);
+        assert!(oracle.get_prices(&["BTC/USD", "ETH/USD"]).await.is_err());
+        let (prices, failures) = oracle.get_prices_partial(&["BTC/USD", "ETH/USD"]).await;
+        assert_eq!(prices["BTC/USD"], PriceData::from_price(60000.0, 1_700_000_000));
+        assert!(matches!(failures.get("ETH/USD"), Some(LiquidationError::OracleError(_))));
+    }
+    
+    #[tokio::test]
+    async fn test_partial_prices_split_successes_and_failures() {
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("ETH/USD", 3000.0).await;
+        
+        // One unknown symbol fails the strict batch, but not the partial one
+        assert!(oracle.get_prices(&["BTC/USD", "DELISTED/USD", "ETH/USD"]).await.is_err());
+        let (prices, failures) = oracle.get_prices_partial(&["BTC/USD", "DELISTED/USD", "ETH/USD", "BTC/USD"]).await;
+        let mut priced: Vec<(&str, f64)> = prices.iter().map(|(symbol, data)| (symbol.as_str(), data.price)).collect();
+        priced.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(priced, [("BTC/USD", 50000.0), ("ETH/USD", 3000.0)]);
+        assert_eq!(failures.len(), 1);
+        assert!(failures.contains_key("DELISTED/USD"));
+        
+        let (prices, failures) = oracle.get_prices_partial(&[]).await;
+        assert!(prices.is_empty() && failures.is_empty());
+    }
+    
+    #[test]
+    fn test_price_source_select() {
+        assert_eq!(PriceSource::Aggregate.select(100.0, 90.0), 100.0);
+        assert_eq!(PriceSource::Ema.select(100.0, 90.0), 90.0);
+        assert_eq!(PriceSource::MinOfBoth.select(100.0, 90.0), 90.0);
+        assert_eq!(PriceSource::MaxOfBoth.select(100.0, 90.0), 100.0);
+    }
+    
+    #[test]
+    fn test_confidence_policy_rejects_or_passes_wide_intervals() {
+        let mut config = OracleConfig::default();
+        config.check_confidence("BTC/USD", 0.005).unwrap();
+        assert!(matches!(
+            config.check_confidence("BTC/USD", 0.0001),
+            Err(LiquidationError::LowConfidencePrice(_))
+        ));
+        assert!(matches!(
+            config.check_confidence("BTC/USD", 0.02),
+            Err(LiquidationError::HighConfidenceInterval(_))
+        ));
+        
+        // Widened symbols report wide intervals, but never overly narrow ones
+        config.confidence.symbol_policies.insert("BTC/USD".to_string(), ConfidencePolicy::Widen);
+        config.check_confidence("BTC/USD", 0.02).unwrap();
+        assert!(config.check_confidence("BTC/USD", 0.0001).is_err());
+        assert!(config.check_confidence("ETH/USD", 0.02).is_err());
+    }
+    
+    #[test]
+    fn test_widened_trigger_price_favours_the_trader() {
+        let mut config = ConfidenceConfig {
+            widen_multiplier: 2.0,
+            ..ConfidenceConfig::default()
+        };
+        assert_eq!(config.trigger_price("BTC/USD", true, 50000.0, 100.0), 50000.0);
+        
+        config.policy = ConfidencePolicy::Widen;
+        assert_eq!(config.trigger_price("BTC/USD", true, 50000.0, 100.0), 50200.0);
+        assert_eq!(config.trigger_price("BTC/USD", false, 50000.0, 100.0), 49800.0);
+        
+        config.symbol_policies.insert("BTC/USD".to_string(), ConfidencePolicy::Reject);
+        assert_eq!(config.trigger_price("BTC/USD", false, 50000.0, 100.0), 50000.0);
+        
+        assert!(config.violations().is_empty());
+        config.widen_multiplier = -1.0;
+        assert_eq!(config.violations()[0].field, "widen_multiplier");
+    }
+    
+    /// SOL/USD price account at 8 decimals: 145.23456789 within 0.29046913,
+    /// published at `PUBLISHED_AT`
+    const PYTH_FEED: &[u8] = include_bytes!("../fixtures/accounts/pyth_price.bin");
+
+    const PUBLISHED_AT: i64 = 1_760_000_000;
+
+    // Copied into a heap buffer as the RPC client's are, which Pyth's loader
+    // needs aligned
+    fn decode_fixture() -> PythPriceFeed {
+        decode_pyth_price_account(&PYTH_FEED.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_decode_pyth_fixture() {
+        let feed = decode_fixture();
+        assert_eq!(
+            feed,
+            PythPriceFeed {
+                price: 14_523_456_789,
+                confidence: 29_046_913,
+                expo: -8,
+                ema_price: 14_500_000_000,
+                ema_confidence: 30_000_000,
+                status: TradingStatus::Trading,
+                publish_slot: 370_000_001,
+                publish_time: PUBLISHED_AT,
+                num_publishers: 2,
+            }
+        );
+        let data = feed.price_data(PriceSource::Aggregate).unwrap();
+        assert!((data.price - 145.23456789).abs() < 1e-9);
+        assert!((data.confidence - 0.29046913).abs() < 1e-9);
+        assert_eq!((data.ema_price, data.publish_time), (145.0, PUBLISHED_AT));
+        assert_eq!(data.publish_slot, Some(370_000_001));
+        assert_eq!(feed.price_data(PriceSource::MinOfBoth).unwrap().price, 145.0);
+
+        let mut data = PYTH_FEED.to_vec();
+        data[0] ^= 1;
+        assert!(matches!(decode_pyth_price_account(&data), Err(LiquidationError::OracleError(_))));
+    }
+
+    #[test]
+    fn test_feed_checked_for_age_then_confidence() {
+        let feed = decode_fixture();
+        let config = OracleConfig::default();
+        config.check_feed("SOL/USD", &feed, PUBLISHED_AT + 30).unwrap();
+        match config.check_feed("SOL/USD", &feed, PUBLISHED_AT + 31) {
+            Err(LiquidationError::StalePrice { age_secs, max_age_secs, .. }) => assert_eq!((age_secs, max_age_secs), (31, 30)),
+            other => panic!("expected a stale price, got {:?}", other),
+        }
+        assert_eq!(check_staleness("SOL/USD", PUBLISHED_AT, PUBLISHED_AT - 5, 0).unwrap(), 0);
+
+        // A 0.2% interval is too wide for a 0.1% cap and too narrow for a 0.5% floor
+        let strict = OracleConfig {
+            max_confidence_interval: 0.001,
+            ..OracleConfig::default()
+        };
+        assert!(matches!(
+            strict.check_feed("SOL/USD", &feed, PUBLISHED_AT),
+            Err(LiquidationError::HighConfidenceInterval(_))
+        ));
+        let narrow = OracleConfig {
+            min_confidence_interval: 0.005,
+            ..OracleConfig::default()
+        };
+        assert!(matches!(
+            narrow.check_feed("SOL/USD", &feed, PUBLISHED_AT),
+            Err(LiquidationError::LowConfidencePrice(_))
+        ));
+        // Stale prices fail on their age whatever their interval
+        assert!(matches!(
+            strict.check_feed("SOL/USD", &feed, PUBLISHED_AT + 60),
+            Err(LiquidationError::StalePrice { .. })
+        ));
+    }
+
     // Note: PythOracle tests would require a running Solana validator
     // with Pyth price accounts, which is beyond the scope of unit tests
 }
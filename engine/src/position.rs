@@ -1,57 +1,261 @@
+use crate::address::{OwnerAddress, PositionAddress};
+use crate::amount::{self, Amount, AmountExt, PRICE_SCALE, RATIO_SCALE, Rounding};
+use crate::margin::PooledMargin;
+use crate::types::{AtRiskReason, PositionStatus, PositionUpdate};
+use serde_with::{DisplayFromStr, serde_as};
 use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
 use std::fmt;
 
+/// Health factor below which a position can be liquidated
+pub(crate) const LIQUIDATION_HEALTH_FACTOR: f64 = 1.0;
+
+/// Maintenance margin ratios positions are held to, by side
+///
+/// Shorts can lose without bound as the price rises, so markets may hold them
+/// to a higher requirement than longs.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MarginParams {
+    /// Maintenance margin ratio of long positions (e.g., 0.05 for 5%)
+    pub long: f64,
+    /// Maintenance margin ratio of short positions
+    pub short: f64,
+}
+
+impl MarginParams {
+    /// The same maintenance margin ratio for both sides
+    pub const fn shared(maintenance_margin: f64) -> Self {
+        Self {
+            long: maintenance_margin,
+            short: maintenance_margin,
+        }
+    }
+
+    /// Maintenance margin ratio of positions on one side
+    pub fn for_side(&self, is_long: bool) -> f64 {
+        if is_long { self.long } else { self.short }
+    }
+}
+
+/// An amount of one collateral asset held by a position
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CollateralBalance {
+    /// Mint of the collateral token
+    #[serde_as(as = "DisplayFromStr")]
+    pub mint: Pubkey,
+    /// Oracle symbol the collateral is priced by (e.g., "SOL/USD")
+    pub symbol: String,
+    /// Amount held (in units of the asset)
+    pub amount: Amount,
+}
+
+/// How a position's margin backs it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarginMode {
+    /// Only the position's own margin backs it
+    #[default]
+    Isolated,
+    /// The margin of all the owner's cross positions, and their PnL, backs it
+    Cross,
+}
+
+/// Most metadata entries a position may carry
+pub(crate) const MAX_METADATA_KEYS: usize = 16;
+
+/// Most bytes a position's metadata keys and values may take together
+pub(crate) const MAX_METADATA_BYTES: usize = 1024;
+
 /// Represents a trading position in the perpetual futures market
-#[derive(Clone, Debug, PartialEq)]
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Position {
     /// The address of the position account on-chain
-    pub address: Pubkey,
+    pub address: PositionAddress,
     /// The owner of the position
-    pub owner: Pubkey,
+    pub owner: OwnerAddress,
     /// The trading pair symbol (e.g., "BTC/USD")
     pub symbol: String,
     /// The size of the position (in base currency)
-    pub size: f64,
+    pub size: Amount,
     /// The entry price of the position
-    pub entry_price: f64,
-    /// The margin allocated to the position (in quote currency)
-    pub margin: f64,
+    pub entry_price: Amount,
+    /// The margin allocated to the position (in quote currency), on top of
+    /// any collateral assets
+    pub margin: Amount,
     /// Whether the position is long (true) or short (false)
     pub is_long: bool,
     /// Timestamp of the last liquidation (if any)
+    #[serde(default)]
     pub last_liquidated: Option<i64>,
+    /// Margin ratio the position was last liquidated at, if the engine saw it
+    #[serde(default)]
+    pub last_liquidated_margin_ratio: Option<Amount>,
+    /// Size of the position when it was last liquidated, if the engine saw it
+    #[serde(default)]
+    pub last_liquidated_size: Option<Amount>,
+    /// Cumulative funding index (per unit of notional) already reflected in margin
+    #[serde(default)]
+    pub cumulative_funding_at_entry: Amount,
+    /// Timestamp funding tracking started or was last settled (if any)
+    #[serde(default)]
+    pub last_funding_settlement: Option<i64>,
+    /// Funding owed by the position but not yet settled into margin (in quote currency)
+    #[serde(default)]
+    pub unsettled_funding: Amount,
+    /// Collateral assets backing the position besides its quote margin
+    #[serde(default)]
+    pub collateral: Vec<CollateralBalance>,
+    /// Weighted value of `collateral` at the prices it was last revalued at
+    /// (in quote currency)
+    #[serde(default)]
+    pub collateral_value: Amount,
+    /// Whether the position shares margin with the owner's other cross positions
+    #[serde(default)]
+    pub margin_mode: MarginMode,
+    /// Notes and references the engine carries along without interpreting,
+    /// e.g. the exchange backend's id of the position, echoed in every update
+    /// and event about it
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
 }
 
 impl Position {
     /// Create a new position
     pub fn new(
-        address: Pubkey,
-        owner: Pubkey,
+        address: impl Into<PositionAddress>,
+        owner: impl Into<OwnerAddress>,
         symbol: &str,
-        size: f64,
-        entry_price: f64,
-        margin: f64,
+        size: Amount,
+        entry_price: Amount,
+        margin: Amount,
         is_long: bool,
     ) -> Self {
         Self {
-            address,
-            owner,
+            address: address.into(),
+            owner: owner.into(),
             symbol: symbol.to_string(),
             size,
             entry_price,
             margin,
             is_long,
             last_liquidated: None,
+            last_liquidated_margin_ratio: None,
+            last_liquidated_size: None,
+            cumulative_funding_at_entry: Amount::ZERO,
+            last_funding_settlement: None,
+            unsettled_funding: Amount::ZERO,
+            collateral: Vec::new(),
+            collateral_value: Amount::ZERO,
+            margin_mode: MarginMode::Isolated,
+            metadata: HashMap::new(),
         }
     }
 
+    /// Back the position with its own margin only, or the owner's pooled margin
+    pub fn with_margin_mode(mut self, margin_mode: MarginMode) -> Self {
+        self.margin_mode = margin_mode;
+        self
+    }
+
+    /// Back the position with collateral assets, valued at the next
+    /// [`Position::revalue_collateral`]
+    pub fn with_collateral(mut self, collateral: Vec<CollateralBalance>) -> Self {
+        self.collateral = collateral;
+        self
+    }
+
+    /// Quote margin plus the weighted value of the collateral assets
+    pub fn total_margin(&self) -> Amount {
+        self.margin + self.collateral_value
+    }
+
+    /// Margin, including collateral assets, net of unsettled funding
+    pub fn effective_margin(&self) -> Amount {
+        self.total_margin() - self.unsettled_funding
+    }
+
+    /// Symbols the collateral assets are priced by
+    pub fn collateral_symbols(&self) -> impl Iterator<Item = &str> {
+        self.collateral.iter().map(|balance| balance.symbol.as_str())
+    }
+
+    /// Value the collateral assets at `prices`, each weighted by its haircut in
+    /// `weights` (1 for symbols without one)
+    ///
+    /// Returns whether every asset could be priced; otherwise the previous
+    /// value is kept rather than counting the unpriced assets as worthless.
+    pub fn revalue_collateral(&mut self, prices: &HashMap<String, Amount>, weights: &HashMap<String, f64>) -> bool {
+        let mut value = Amount::ZERO;
+        for balance in &self.collateral {
+            let Some(&price) = prices.get(&balance.symbol) else {
+                return false;
+            };
+            let weight = weights.get(&balance.symbol).map_or(Amount::ONE, |&weight| amount::from_f64_lossy(weight));
+            value += balance.amount * price * weight;
+        }
+        self.collateral_value = value;
+        true
+    }
+
+    /// Close `fraction` of the position, taking the same share of its margin,
+    /// collateral assets and unsettled funding
+    pub fn reduce(&mut self, fraction: Amount) {
+        let remaining = Amount::ONE - fraction.clamp(Amount::ZERO, Amount::ONE);
+        self.size *= remaining;
+        self.margin *= remaining;
+        self.unsettled_funding *= remaining;
+        self.collateral_value *= remaining;
+        for balance in &mut self.collateral {
+            balance.amount *= remaining;
+        }
+    }
+
+    /// Calculate the funding owed for a cumulative funding delta (per unit of notional)
+    ///
+    /// Positive funding is paid by longs and received by shorts; the result is positive
+    /// when the position pays.
+    pub fn funding_payment(&self, funding_delta: f64) -> Amount {
+        self.payment_for(amount::from_f64_lossy(funding_delta))
+    }
+
+    /// Funding owed for a cumulative funding delta already in fixed point
+    fn payment_for(&self, funding_delta: Amount) -> Amount {
+        let payment = self.size * self.entry_price * funding_delta;
+        if self.is_long { payment } else { -payment }
+    }
+
+    /// Settle funding at the given hourly rate over a number of hours into margin
+    pub fn apply_funding(&mut self, funding_rate_per_hour: f64, hours: f64) {
+        let funding_delta = amount::from_f64_lossy(funding_rate_per_hour * hours);
+        let payment = self.payment_for(funding_delta);
+        self.margin -= payment;
+        self.cumulative_funding_at_entry += funding_delta;
+        
+        // Settled funding is no longer outstanding, but never overshoot past zero
+        if self.unsettled_funding * payment > Amount::ZERO {
+            self.unsettled_funding = if payment > Amount::ZERO {
+                (self.unsettled_funding - payment).max(Amount::ZERO)
+            } else {
+                (self.unsettled_funding - payment).min(Amount::ZERO)
+            };
+        }
+    }
+
+    /// Record funding accrued up to the market's current cumulative funding index
+    pub fn accrue_funding(&mut self, cumulative_funding: f64) {
+        self.unsettled_funding =
+            self.payment_for(amount::from_f64_lossy(cumulative_funding) - self.cumulative_funding_at_entry);
+    }
+
     /// Calculate the current value of the position
-    pub fn value(&self, current_price: f64) -> f64 {
+    pub fn value(&self, current_price: Amount) -> Amount {
         self.size * current_price
     }
 
     /// Calculate the unrealized PnL of the position
-    pub fn unrealized_pnl(&self, current_price: f64) -> f64 {
+    pub fn unrealized_pnl(&self, current_price: Amount) -> Amount {
         let price_diff = if self.is_long {
             current_price - self.entry_price
         } else {
@@ -61,69 +265,287 @@ impl Position {
         self.size * price_diff
     }
 
-    /// Calculate the margin ratio (collateral / position value)
-    pub fn margin_ratio(&self, current_price: f64) -> f64 {
+    /// Margin plus unrealized PnL at the given price
+    fn equity(&self, current_price: Amount) -> Amount {
+        self.effective_margin() + self.unrealized_pnl(current_price)
+    }
+
+    /// Margin `params` require of the position at the given price
+    fn required_margin(&self, current_price: Amount, params: MarginParams) -> Amount {
+        self.value(current_price) * self.maintenance_margin(params)
+    }
+    
+    /// Calculate the margin ratio (collateral / position value), rounded down
+    ///
+    /// A ratio that can't be evaluated, e.g. of a NaN entry price, is
+    /// [unbounded](AmountExt::UNBOUNDED) so the position never looks liquidatable.
+    pub fn margin_ratio(&self, current_price: Amount) -> Amount {
         let position_value = self.value(current_price);
-        if position_value == 0.0 {
-            return 0.0;
+        if position_value == Amount::ZERO {
+            return Amount::ZERO;
         }
         
-        (self.margin + self.unrealized_pnl(current_price)) / position_value
+        let margin_ratio = self
+            .equity(current_price)
+            .checked_div(position_value)
+            .map_or(Amount::UNBOUNDED, |ratio| ratio.round_to(RATIO_SCALE, Rounding::Down));
+        if margin_ratio.is_nan() { Amount::UNBOUNDED } else { margin_ratio }
     }
 
-    /// Calculate the leverage of the position
-    pub fn leverage(&self, current_price: f64) -> f64 {
+    /// Maintenance margin ratio `params` hold the position to, by its side
+    pub fn maintenance_margin(&self, params: MarginParams) -> Amount {
+        amount::from_f64_lossy(params.for_side(self.is_long))
+    }
+
+    /// Calculate the health factor: equity over the margin `params` require of
+    /// the position at the given price, so below 1.0 it can be liquidated
+    ///
+    /// Rounded down, and an f64 for ranking and display; the liquidation decision
+    /// itself is [`is_undercollateralized`](Self::is_undercollateralized). Zero-size
+    /// positions are infinitely healthy, as are those whose health can't be
+    /// evaluated, e.g. of a NaN entry price.
+    pub fn health_factor(&self, current_price: Amount, params: MarginParams) -> f64 {
+        if self.size == Amount::ZERO {
+            return f64::INFINITY;
+        }
+        
+        let equity = self.equity(current_price);
+        let health_factor = match equity.checked_div(self.required_margin(current_price, params)) {
+            Some(health_factor) => health_factor.round_to(RATIO_SCALE, Rounding::Down).as_f64(),
+            // Fixed point can't divide by a zero requirement
+            None if equity < Amount::ZERO => f64::NEG_INFINITY,
+            None => f64::INFINITY,
+        };
+        if health_factor.is_nan() { f64::INFINITY } else { health_factor }
+    }
+
+    /// Calculate the leverage of the position, rounded up, 0 if it can't be evaluated
+    pub fn leverage(&self, current_price: Amount) -> Amount {
         let position_value = self.value(current_price);
-        if position_value == 0.0 {
-            return 0.0;
+        if position_value == Amount::ZERO {
+            return Amount::ZERO;
         }
         
-        position_value / (self.margin + self.unrealized_pnl(current_price).max(0.0))
+        let leverage = position_value
+            .checked_div(self.equity(current_price))
+            .map_or(Amount::ZERO, |leverage| leverage.round_to(RATIO_SCALE, Rounding::Up));
+        if leverage.is_nan() { Amount::ZERO } else { leverage }
     }
 
-    /// Check if the position is liquidatable at the given price
-    pub fn is_liquidatable(&self, current_price: f64) -> bool {
-        let margin_ratio = self.margin_ratio(current_price);
-        let maintenance_margin = self.calculate_maintenance_margin();
+    /// Calculate the bankruptcy price, where margin plus PnL reaches zero
+    ///
+    /// Longs are bankrupt below the returned price and shorts above it, rounded up
+    /// for longs and down for shorts. Returns `None` for zero-size positions and
+    /// longs whose margin covers the full notional.
+    pub fn bankruptcy_price(&self) -> Option<Amount> {
+        if self.size == Amount::ZERO {
+            return None;
+        }
         
-        margin_ratio < maintenance_margin
+        let margin_per_unit = self.effective_margin().checked_div(self.size)?.round_to(PRICE_SCALE, Rounding::Down);
+        if self.is_long {
+            let price = self.entry_price - margin_per_unit;
+            (price > Amount::ZERO).then_some(price)
+        } else {
+            Some((self.entry_price + margin_per_unit).max(Amount::ZERO))
+        }
     }
     
-    /// Check if the position is undercollateralized at the given price
-    pub fn is_undercollateralized(&self, current_price: f64, maintenance_margin: f64) -> bool {
-        let margin_ratio = self.margin_ratio(current_price);
-        margin_ratio < maintenance_margin
+    /// Calculate the shortfall beyond the position's margin at the given price
+    pub fn bad_debt(&self, current_price: Amount) -> Amount {
+        (-self.equity(current_price)).max(Amount::ZERO)
     }
 
-    /// Calculate the maintenance margin requirement based on leverage
-    fn calculate_maintenance_margin(&self) -> f64 {
-        // This is a simplified version - in production, this would consider
-        // position size, market volatility, and other risk parameters
-        const BASE_MAINTENANCE_MARGIN: f64 = 0.005; // 0.5%
-        
-        // Increase maintenance margin for larger positions
-        let size_factor = (self.size / 1_000_000.0).min(1.0); // Cap at 1.0
-        let size_impact = 0.001 * size_factor; // Up to 0.1% additional margin
+    /// Check if the position's health factor is below [`LIQUIDATION_HEALTH_FACTOR`]
+    /// at the given price
+    ///
+    /// Equity is compared against the required margin without dividing, so in
+    /// fixed point the decision is exact. Zero-size positions and those that
+    /// can't be evaluated are never undercollateralized.
+    pub fn is_undercollateralized(&self, current_price: Amount, params: MarginParams) -> bool {
+        if self.size == Amount::ZERO {
+            return false;
+        }
         
-        BASE_MAINTENANCE_MARGIN + size_impact
+        self.equity(current_price)
+            < self.required_margin(current_price, params) * amount::from_f64_lossy(LIQUIDATION_HEALTH_FACTOR)
     }
 
-    /// Calculate the liquidation price of the position
-    pub fn liquidation_price(&self) -> f64 {
-        if self.size == 0.0 {
-            return 0.0;
+    /// Check if the position is liquidatable at the given price
+    #[deprecated(since = "0.1.0", note = "use `is_undercollateralized`")]
+    pub fn is_liquidatable(&self, current_price: Amount, params: MarginParams) -> bool {
+        self.is_undercollateralized(current_price, params)
+    }
+
+    /// Cross-margin aware [`is_undercollateralized`](Self::is_undercollateralized):
+    /// a cross position is undercollateralized when the pool of its owner's cross
+    /// positions, itself included, is
+    ///
+    /// Isolated positions ignore the pool.
+    pub fn is_undercollateralized_cross(
+        &self,
+        current_price: Amount,
+        params: MarginParams,
+        pool: &PooledMargin,
+    ) -> bool {
+        match self.margin_mode {
+            MarginMode::Isolated => self.is_undercollateralized(current_price, params),
+            MarginMode::Cross => pool.is_undercollateralized(),
+        }
+    }
+
+    /// Calculate the liquidation price of the position: where its margin ratio
+    /// falls to the maintenance margin `params` hold its side to
+    ///
+    /// Longs are liquidatable below the returned price and shorts above it; the
+    /// price is rounded up for longs and down for shorts, never past the exact
+    /// boundary. Returns `None` for zero-size positions, those with non-finite
+    /// values and longs that are liquidatable at no positive price; shorts without
+    /// enough margin to survive at any price return zero, i.e. they are
+    /// immediately liquidatable.
+    pub fn liquidation_price(&self, params: MarginParams) -> Option<Amount> {
+        self.liquidation_price_with_margin(self.effective_margin(), params)
+    }
+
+    /// Project the liquidation price `hours_ahead` hours out, once funding at
+    /// `funding_rate_per_hour` over them is paid out of margin
+    ///
+    /// Funding the position pays moves its liquidation price towards the
+    /// market. Returns `None` when it receives funding instead, which only
+    /// carries the liquidation price away, and where
+    /// [`liquidation_price`](Self::liquidation_price) would.
+    pub fn projected_liquidation_price(
+        &self,
+        hours_ahead: f64,
+        funding_rate_per_hour: f64,
+        params: MarginParams,
+    ) -> Option<Amount> {
+        let payment = self.funding_payment(funding_rate_per_hour * hours_ahead);
+        if payment < Amount::ZERO {
+            return None;
+        }
+        self.liquidation_price_with_margin(self.effective_margin() - payment, params)
+    }
+
+    /// Liquidation price of the position were its effective margin `margin`
+    fn liquidation_price_with_margin(&self, margin: Amount, params: MarginParams) -> Option<Amount> {
+        if self.size == Amount::ZERO || self.non_finite_field().is_some() || !margin.is_finite() {
+            return None;
         }
 
-        let maintenance_margin = self.calculate_maintenance_margin();
+        let maintenance_margin = self.maintenance_margin(params);
         
+        // Solve margin_ratio(p) = maintenance_margin for p; the PnL is linear in p, so
+        // margin_ratio(p) = (margin + size * (p - entry) * side) / (size * p)
         if self.is_long {
-            // For long: liquidation_price = entry_price * (1 - 1/leverage + maintenance_margin)
-            let leverage = self.leverage(self.entry_price);
-            self.entry_price * (1.0 - 1.0 / leverage + maintenance_margin)
+            // p = (size * entry - margin) / (size * (1 - mm))
+            let price = (self.size * self.entry_price - margin)
+                .checked_div(self.size * (Amount::ONE - maintenance_margin))?
+                .round_to(PRICE_SCALE, Rounding::Up);
+            (price > Amount::ZERO).then_some(price)
         } else {
-            // For short: liquidation_price = entry_price * (1 + 1/leverage - maintenance_margin)
-            let leverage = self.leverage(self.entry_price);
-            self.entry_price * (1.0 + 1.0 / leverage - maintenance_margin)
+            // p = (size * entry + margin) / (size * (1 + mm))
+            let price = (self.size * self.entry_price + margin)
+                .checked_div(self.size * (Amount::ONE + maintenance_margin))?
+                .round_to(PRICE_SCALE, Rounding::Down);
+            Some(price.max(Amount::ZERO))
+        }
+    }
+    
+    /// How far `current_price` is from the liquidation price `params` give the
+    /// position (in basis points of `current_price`): positive while the price
+    /// has still to fall (longs) or rise (shorts) to it, negative once past it
+    ///
+    /// Returns `None` without a liquidation price or a positive current price.
+    pub fn distance_to_liquidation_bps(&self, current_price: Amount, params: MarginParams) -> Option<f64> {
+        let liquidation_price = self.liquidation_price(params)?;
+        if current_price <= Amount::ZERO {
+            return None;
+        }
+        let distance = ((current_price - liquidation_price) * Amount::from(10_000)).checked_div(current_price)?;
+        let distance = distance.as_f64();
+        Some(if self.is_long { distance } else { -distance })
+    }
+
+    /// Name of the first of the position's values its margin math depends on
+    /// that is NaN or infinite, if any
+    ///
+    /// Such positions can't be evaluated, so checks flag rather than judge them.
+    pub fn non_finite_field(&self) -> Option<&'static str> {
+        [
+            ("size", self.size),
+            ("entry_price", self.entry_price),
+            ("margin", self.margin),
+            ("collateral_value", self.collateral_value),
+            ("unsettled_funding", self.unsettled_funding),
+        ]
+        .into_iter()
+        .find(|(_, value)| !value.is_finite())
+        .map(|(field, _)| field)
+    }
+
+    /// Attach a metadata entry, replacing any under the same key
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Keep the entries of `previous` metadata the position doesn't set itself
+    pub fn merge_metadata(&mut self, previous: &HashMap<String, String>) {
+        for (key, value) in previous {
+            self.metadata.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
+
+    /// Why the position's metadata is too large to accept, if it is: over
+    /// [`MAX_METADATA_KEYS`] entries or [`MAX_METADATA_BYTES`] bytes of keys
+    /// and values
+    pub fn metadata_problem(&self) -> Option<String> {
+        if self.metadata.len() > MAX_METADATA_KEYS {
+            return Some(format!(
+                "metadata has {} keys, over the limit of {}",
+                self.metadata.len(),
+                MAX_METADATA_KEYS
+            ));
+        }
+        let bytes: usize = self.metadata.iter().map(|(key, value)| key.len() + value.len()).sum();
+        (bytes > MAX_METADATA_BYTES)
+            .then(|| format!("metadata takes {} bytes, over the limit of {}", bytes, MAX_METADATA_BYTES))
+    }
+
+    /// Snapshot the position's risk metrics at the given mark price
+    pub fn update(
+        &self,
+        mark_price: Amount,
+        status: PositionStatus,
+        params: MarginParams,
+        timestamp: i64,
+    ) -> PositionUpdate {
+        let health_factor = self.health_factor(mark_price, params);
+        PositionUpdate {
+            address: self.address,
+            owner: self.owner,
+            symbol: self.symbol.clone(),
+            size: self.size,
+            entry_price: self.entry_price,
+            margin: self.effective_margin(),
+            is_long: self.is_long,
+            status,
+            at_risk_reason: (status == PositionStatus::AtRisk).then_some(AtRiskReason::HealthFactor),
+            leverage: self.leverage(mark_price),
+            liquidation_price: self.liquidation_price(params),
+            distance_to_liquidation_bps: self.distance_to_liquidation_bps(mark_price, params),
+            estimated_time_to_liquidation_secs: None,
+            funding_projections: Vec::new(),
+            mark_price,
+            unrealized_pnl: self.unrealized_pnl(mark_price),
+            margin_ratio: self.margin_ratio(mark_price).as_f64() * 100.0,
+            maintenance_margin: self.maintenance_margin(params).as_f64() * 100.0,
+            health_factor: health_factor.is_finite().then_some(health_factor),
+            adl_quantile: None,
+            exceeds_max_leverage: false,
+            metadata: self.metadata.clone(),
+            timestamp,
         }
     }
 }
@@ -141,7 +563,19 @@ impl fmt::Display for Position {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::signature::Keypair;
+    use solana_sdk::signature::{Keypair, Signer};
+    
+    /// 0.5% on either side, leaving 10x positions room to move
+    const MARGIN: MarginParams = MarginParams::shared(0.005);
+    
+    /// 1/16, which keeps the requirements and liquidation prices exact as f64
+    /// and in fixed point alike
+    const SIXTEENTH: MarginParams = MarginParams::shared(0.0625);
+    
+    /// An amount written as an f64 literal
+    fn amt(value: f64) -> Amount {
+        amount::from_f64(value).unwrap()
+    }
     
     fn create_test_position() -> Position {
         let owner = Keypair::new().pubkey();
@@ -149,18 +583,18 @@ mod tests {
             Keypair::new().pubkey(),
             owner,
             "BTC/USD",
-            1.0,     // 1 BTC
-            60000.0, // $60,000 entry
-            6000.0,  // $6,000 margin (10x leverage)
-            true,    // Long position
+            amt(1.0),     // 1 BTC
+            amt(60000.0), // $60,000 entry
+            amt(6000.0),  // $6,000 margin (10x leverage)
+            true,         // Long position
         )
     }
     
     #[test]
     fn test_position_value() {
         let position = create_test_position();
-        assert_eq!(position.value(60000.0), 60000.0);
-        assert_eq!(position.value(65000.0), 65000.0);
+        assert_eq!(position.value(amt(60000.0)), amt(60000.0));
+        assert_eq!(position.value(amt(65000.0)), amt(65000.0));
     }
     
     #[test]
@@ -168,27 +602,24 @@ mod tests {
         let position = create_test_position();
         
         // Price increase (long position should be profitable)
-        assert_eq!(position.unrealized_pnl(65000.0), 5000.0);
+        assert_eq!(position.unrealized_pnl(amt(65000.0)), amt(5000.0));
         
         // Price decrease (long position should have loss)
-        assert_eq!(position.unrealized_pnl(55000.0), -5000.0);
+        assert_eq!(position.unrealized_pnl(amt(55000.0)), amt(-5000.0));
     }
     
     #[test]
     fn test_margin_ratio() {
         let position = create_test_position();
         
-        // At entry price
-        let margin_ratio = position.margin_ratio(60000.0);
-        assert!((margin_ratio - 0.1).abs() < 0.0001); // 10% margin ratio (6000/60000)
+        // At entry price: 6,000 / 60,000
+        assert_eq!(position.margin_ratio(amt(60000.0)), amt(0.1));
         
-        // Price increase
-        let margin_ratio = position.margin_ratio(65000.0);
-        assert!(margin_ratio > 0.1);
+        // Price increase: 10,000 / 64,000
+        assert_eq!(position.margin_ratio(amt(64000.0)), amt(0.15625));
         
-        // Price decrease
-        let margin_ratio = position.margin_ratio(55000.0);
-        assert!(margin_ratio < 0.1);
+        // Price decrease: 3,600 / 57,600
+        assert_eq!(position.margin_ratio(amt(57600.0)), amt(0.0625));
     }
     
     #[test]
@@ -196,40 +627,519 @@ mod tests {
         let position = create_test_position();
         
         // At entry price, leverage should be 10x
-        let leverage = position.leverage(60000.0);
-        assert!((leverage - 10.0).abs() < 0.01);
+        assert_eq!(position.leverage(amt(60000.0)), amt(10.0));
         
-        // With profit, leverage decreases
-        let leverage = position.leverage(65000.0);
-        assert!(leverage < 10.0);
+        // With profit, leverage decreases: 64,000 / 10,000
+        assert_eq!(position.leverage(amt(64000.0)), amt(6.4));
         
-        // With loss, leverage increases
-        let leverage = position.leverage(55000.0);
-        assert!(leverage > 10.0);
+        // With loss, leverage increases: 57,600 / 3,600
+        assert_eq!(position.leverage(amt(57600.0)), amt(16.0));
     }
     
     #[test]
     fn test_liquidation_price() {
+        // 10x long: (60,000 - 6,000) / (1 - 1/16)
         let position = create_test_position();
-        let liq_price = position.liquidation_price();
+        assert_eq!(position.liquidation_price(SIXTEENTH), Some(amt(57600.0)));
         
-        // For a 10x long position with ~0.5% maintenance, liquidation should be around 5-6% below entry
-        assert!(liq_price < position.entry_price * 0.95);
-        assert!(liq_price > position.entry_price * 0.90);
+        // Short with 8,000 of margin: (60,000 + 8,000) / (1 + 1/16)
+        let mut short = create_test_position();
+        (short.is_long, short.margin) = (false, amt(8000.0));
+        assert_eq!(short.liquidation_price(SIXTEENTH), Some(amt(64000.0)));
     }
     
     #[test]
-    fn test_is_liquidatable() {
+    fn test_is_undercollateralized() {
         let position = create_test_position();
         
         // At entry price, should not be liquidatable
-        assert!(!position.is_liquidatable(60000.0));
+        assert!(!position.is_undercollateralized(amt(60000.0), SIXTEENTH));
         
-        // At liquidation price, should be liquidatable
-        let liq_price = position.liquidation_price();
-        assert!(position.is_liquidatable(liq_price));
+        // At the liquidation price 3,600 of equity meets the 3,600 required
+        assert!(!position.is_undercollateralized(amt(57600.0), SIXTEENTH));
+        assert!(!position.is_undercollateralized(amt(57601.0), SIXTEENTH));
+        // A dollar lower 3,599 falls short of 3,599.9375
+        assert!(position.is_undercollateralized(amt(57599.0), SIXTEENTH));
         
         // Below liquidation price, should be liquidatable
-        assert!(position.is_liquidatable(liq_price * 0.9));
+        assert!(position.is_undercollateralized(amt(51840.0), SIXTEENTH));
+    }
+    
+    #[test]
+    #[allow(deprecated)]
+    fn test_is_liquidatable_matches_is_undercollateralized() {
+        let position = create_test_position();
+        for price in [60000.0, 57600.0, 57599.0, 51840.0] {
+            assert_eq!(
+                position.is_liquidatable(amt(price), SIXTEENTH),
+                position.is_undercollateralized(amt(price), SIXTEENTH)
+            );
+        }
+    }
+    
+    #[test]
+    fn test_health_factor() {
+        // 10x long: 6,000 of equity against 3,750 required at entry
+        let mut position = create_test_position();
+        assert_eq!(position.health_factor(amt(60000.0), SIXTEENTH), 1.6);
+        // 2,250 against 3,515.625 at 56,250
+        assert_eq!(position.health_factor(amt(56250.0), SIXTEENTH), 0.64);
+        assert!(position.is_undercollateralized(amt(56250.0), SIXTEENTH));
+        // Owed funding eats into equity
+        position.unsettled_funding = amt(1500.0);
+        assert_eq!(position.health_factor(amt(60000.0), SIXTEENTH), 1.2);
+        
+        // 2 BTC short bankrupt at 52,500: 2,600 of equity against 6,400 required at 51,200
+        let mut short = create_test_position();
+        short.size = amt(2.0);
+        short.entry_price = amt(50000.0);
+        short.margin = amt(5000.0);
+        short.is_long = false;
+        assert_eq!(short.bankruptcy_price(), Some(amt(52500.0)));
+        assert_eq!(short.health_factor(amt(51200.0), SIXTEENTH), 0.40625);
+        assert_eq!(short.health_factor(amt(52500.0), SIXTEENTH), 0.0);
+        // 2,520 short of the 6,720 required at 53,760
+        assert_eq!(short.health_factor(amt(53760.0), SIXTEENTH), -0.375);
+        assert!(short.health_factor(amt(40000.0), SIXTEENTH) > 1.0);
+        
+        // Nothing at risk without a position
+        position.size = Amount::ZERO;
+        assert_eq!(position.health_factor(amt(60000.0), SIXTEENTH), f64::INFINITY);
+        assert!(!position.is_undercollateralized(amt(60000.0), SIXTEENTH));
+        assert_eq!(position.update(amt(60000.0), PositionStatus::Active, SIXTEENTH, 0).health_factor, None);
+    }
+    
+    #[test]
+    fn test_health_factor_matches_liquidation_price() {
+        let position = create_test_position();
+        let liq_price = position.liquidation_price(SIXTEENTH).unwrap();
+        assert_eq!(position.health_factor(liq_price, SIXTEENTH), 1.0);
+        assert!(!position.is_undercollateralized(liq_price + amt(0.5), SIXTEENTH));
+        assert!(position.is_undercollateralized(liq_price - amt(0.5), SIXTEENTH));
+    }
+    
+    #[test]
+    fn test_side_margins_set_liquidation_prices() {
+        // A 4x long and short of the same size and entry
+        let long = Position::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            "BTC/USD",
+            amt(1.0),
+            amt(68000.0),
+            amt(17000.0),
+            true,
+        );
+        let short = Position { is_long: false, ..long.clone() };
+        
+        // Under a shared requirement: 51,000 / (1 - 1/16) and 85,000 / (1 + 1/16)
+        assert_eq!(long.liquidation_price(SIXTEENTH), Some(amt(54400.0)));
+        assert_eq!(short.liquidation_price(SIXTEENTH), Some(amt(80000.0)));
+        
+        // A higher short requirement only brings the short's liquidation closer:
+        // 85,000 / (1 + 1/4)
+        let sided = MarginParams { long: 0.0625, short: 0.25 };
+        assert_eq!(long.liquidation_price(sided), long.liquidation_price(SIXTEENTH));
+        assert_eq!(short.liquidation_price(sided), Some(amt(68000.0)));
+        // 11,000 of equity at 74,000 against 4,625 or 18,500 required
+        assert!(!short.is_undercollateralized(amt(74000.0), SIXTEENTH));
+        assert!(short.is_undercollateralized(amt(74000.0), sided));
+        
+        let update = short.update(amt(74000.0), PositionStatus::AtRisk, sided, 0);
+        assert_eq!(update.maintenance_margin, 25.0);
+        assert_eq!(update.liquidation_price, short.liquidation_price(sided));
+        assert!(update.health_factor.unwrap() < 1.0);
+        assert_eq!(long.update(amt(74000.0), PositionStatus::Active, sided, 0).maintenance_margin, 6.25);
+    }
+    
+    #[test]
+    fn test_distance_to_liquidation_bps() {
+        // Liquidated at 80.00 with half the value required as margin
+        let params = MarginParams::shared(0.5);
+        let mut long = create_test_position();
+        (long.size, long.entry_price, long.margin) = (amt(1.0), amt(100.0), amt(60.0));
+        assert_eq!(long.liquidation_price(params), Some(amt(80.0)));
+        assert_eq!(long.distance_to_liquidation_bps(amt(100.0), params), Some(2_000.0));
+        assert_eq!(long.distance_to_liquidation_bps(amt(80.0), params), Some(0.0));
+        assert_eq!(long.distance_to_liquidation_bps(amt(64.0), params), Some(-2_500.0));
+        assert_eq!(long.distance_to_liquidation_bps(Amount::ZERO, params), None);
+        
+        // Liquidated at 96.00 with a quarter required
+        let params = MarginParams::shared(0.25);
+        let mut short = long.clone();
+        (short.is_long, short.margin) = (false, amt(20.0));
+        assert_eq!(short.liquidation_price(params), Some(amt(96.0)));
+        assert_eq!(short.distance_to_liquidation_bps(amt(80.0), params), Some(2_000.0));
+        assert_eq!(short.distance_to_liquidation_bps(amt(120.0), params), Some(-2_000.0));
+        
+        short.size = Amount::ZERO;
+        assert_eq!(short.distance_to_liquidation_bps(amt(80.0), params), None);
+    }
+    
+    #[test]
+    fn test_liquidation_price_degenerate_cases() {
+        // Zero size has no liquidation price
+        let mut position = create_test_position();
+        position.size = Amount::ZERO;
+        assert_eq!(position.liquidation_price(MARGIN), None);
+        
+        // A fully collateralized long can't be liquidated at any positive price
+        let mut position = create_test_position();
+        position.margin = amt(60000.0);
+        assert_eq!(position.liquidation_price(MARGIN), None);
+        assert!(!position.is_undercollateralized(amt(1.0), MARGIN));
+        
+        // A short whose losses already exceed notional is liquidatable everywhere
+        let mut position = create_test_position();
+        position.is_long = false;
+        position.margin = amt(-61000.0);
+        assert_eq!(position.liquidation_price(MARGIN), Some(Amount::ZERO));
+        assert!(position.is_undercollateralized(amt(1.0), MARGIN));
+        assert!(position.is_undercollateralized(amt(60000.0), MARGIN));
+    }
+    
+    // Fixed point has no NaN or infinite values to guard against
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn test_non_finite_values_never_liquidatable() {
+        let mut position = create_test_position();
+        assert_eq!(position.non_finite_field(), None);
+        position.entry_price = f64::NAN;
+        assert_eq!(position.non_finite_field(), Some("entry_price"));
+        assert_eq!(position.margin_ratio(50000.0), f64::INFINITY);
+        assert_eq!(position.health_factor(50000.0, MARGIN), f64::INFINITY);
+        assert_eq!(position.leverage(50000.0), 0.0);
+        assert_eq!(position.liquidation_price(MARGIN), None);
+        assert_eq!(position.distance_to_liquidation_bps(50000.0, MARGIN), None);
+        assert_eq!(position.bad_debt(50000.0), 0.0);
+        assert!(!position.is_undercollateralized(50000.0, MARGIN));
+        
+        let mut position = create_test_position();
+        position.margin = f64::INFINITY;
+        position.unsettled_funding = f64::INFINITY;
+        assert_eq!(position.non_finite_field(), Some("margin"));
+        assert!(!position.is_undercollateralized(50000.0, MARGIN));
+    }
+    
+    #[test]
+    fn test_liquidation_price_short_low_leverage() {
+        // 2.4x short: (60,000 + 25,000) / (1 + 1/16), well above entry
+        let mut position = create_test_position();
+        position.is_long = false;
+        position.margin = amt(25000.0);
+        
+        assert_eq!(position.liquidation_price(SIXTEENTH), Some(amt(80000.0)));
+        // 5,001 of equity against 4,999.9375 required, then 4,999 against 5,000.0625
+        assert!(!position.is_undercollateralized(amt(79999.0), SIXTEENTH));
+        assert!(position.is_undercollateralized(amt(80001.0), SIXTEENTH));
+    }
+    
+    #[test]
+    fn test_liquidation_price_matches_is_undercollateralized() {
+        // Deterministic xorshift so failures are reproducible
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        // Cents, as the values of a market would be
+        let cents = |value: f64| amt((value * 100.0).round() / 100.0);
+        
+        for _ in 0..1000 {
+            let size = 0.01 + next() * 100.0;
+            let entry_price = 1.0 + next() * 100_000.0;
+            let leverage = 1.5 + next() * 98.5;
+            let is_long = next() < 0.5;
+            let position = Position::new(
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                "BTC/USD",
+                cents(size),
+                cents(entry_price),
+                cents(size * entry_price / leverage),
+                is_long,
+            );
+            
+            let liq_price = position.liquidation_price(MARGIN).unwrap();
+            let below = liq_price * amt(1.0 - 1e-6);
+            let above = liq_price * amt(1.0 + 1e-6);
+            if is_long {
+                assert!(liq_price < position.entry_price, "{}", position);
+                assert!(position.is_undercollateralized(below, MARGIN), "{}", position);
+                assert!(!position.is_undercollateralized(above, MARGIN), "{}", position);
+            } else {
+                assert!(liq_price > position.entry_price, "{}", position);
+                assert!(!position.is_undercollateralized(below, MARGIN), "{}", position);
+                assert!(position.is_undercollateralized(above, MARGIN), "{}", position);
+            }
+        }
+    }
+    
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn test_divisions_round_against_the_trader() {
+        use std::str::FromStr;
+        
+        let dec = |value: &str| Amount::from_str(value).unwrap();
+        let position = create_test_position();
+        let mut short = create_test_position();
+        short.is_long = false;
+        
+        // 11,000 / 65,000 = 0.169230769230769..., rounded down
+        assert_eq!(position.margin_ratio(dec("65000")), dec("0.169230769230"));
+        // 1,000 / 55,000 = 0.018181818181818..., rounded down
+        assert_eq!(position.margin_ratio(dec("55000")), dec("0.018181818181"));
+        // 65,000 / 11,000 = 5.909090909090..., rounded up
+        assert_eq!(position.leverage(dec("65000")), dec("5.909090909091"));
+        
+        // 54,000 / (1 - 0.005) = 54271.356783919..., rounded up
+        let liq_price = position.liquidation_price(MARGIN).unwrap();
+        assert_eq!(liq_price, dec("54271.35678392"));
+        // 66,000 / (1 + 0.005) = 65671.641791044..., rounded down
+        let short_liq_price = short.liquidation_price(MARGIN).unwrap();
+        assert_eq!(short_liq_price, dec("65671.64179104"));
+        
+        // The exact boundary lies within one price step past the rounded trigger
+        assert!(!position.is_undercollateralized(liq_price, MARGIN));
+        assert!(position.is_undercollateralized(liq_price - dec("0.00000001"), MARGIN));
+        assert!(!short.is_undercollateralized(short_liq_price, MARGIN));
+        assert!(short.is_undercollateralized(short_liq_price + dec("0.00000001"), MARGIN));
+        
+        // 6,000 / 7 = 857.142857142857..., leaving the long bankrupt at 59142.85714286
+        let mut seventh = create_test_position();
+        seventh.size = dec("7");
+        assert_eq!(seventh.bankruptcy_price(), Some(dec("59142.85714286")));
+        seventh.is_long = false;
+        assert_eq!(seventh.bankruptcy_price(), Some(dec("60857.14285714")));
+    }
+    
+    #[test]
+    fn test_bankruptcy_price() {
+        let position = create_test_position();
+        assert_eq!(position.bankruptcy_price(), Some(amt(54000.0)));
+        assert_eq!(position.bad_debt(amt(54000.0)), Amount::ZERO);
+        assert_eq!(position.bad_debt(amt(53000.0)), amt(1000.0));
+        
+        let mut short = create_test_position();
+        short.is_long = false;
+        assert_eq!(short.bankruptcy_price(), Some(amt(66000.0)));
+        assert_eq!(short.bad_debt(amt(67500.0)), amt(1500.0));
+        
+        let mut empty = create_test_position();
+        empty.size = Amount::ZERO;
+        assert_eq!(empty.bankruptcy_price(), None);
+    }
+    
+    #[test]
+    fn test_position_update_metrics() {
+        let position = create_test_position();
+        let params = MarginParams::shared(0.05);
+        let update = position.update(amt(57600.0), PositionStatus::Active, params, 1_700_000_000);
+        
+        assert_eq!(update.address, position.address);
+        assert_eq!(update.mark_price, amt(57600.0));
+        assert_eq!(update.unrealized_pnl, amt(-2400.0));
+        // 3,600 / 57,600
+        assert_eq!(update.margin_ratio, 6.25);
+        assert_eq!(update.leverage, amt(16.0));
+        assert_eq!(update.maintenance_margin, 5.0);
+        assert_eq!(update.liquidation_price, position.liquidation_price(params));
+        assert_eq!(update.timestamp, 1_700_000_000);
+    }
+    
+    #[test]
+    fn test_collateral_assets_weighted_into_margin() {
+        let mint = Pubkey::new_unique();
+        let usdc = |amount| CollateralBalance { mint, symbol: "USDC/USD".to_string(), amount: amt(amount) };
+        let sol = |amount| CollateralBalance {
+            mint: Pubkey::new_unique(),
+            symbol: "SOL/USD".to_string(),
+            amount: amt(amount),
+        };
+        let weights = HashMap::from([("SOL/USD".to_string(), 0.9)]);
+        let mut prices = HashMap::from([("USDC/USD".to_string(), amt(1.0)), ("SOL/USD".to_string(), amt(100.0))]);
+        
+        // Backed by 6,000 USDC the long is as safe as with 6,000 of margin,
+        // whatever SOL does
+        let mut usdc_only = create_test_position().with_collateral(vec![usdc(6000.0)]);
+        usdc_only.margin = Amount::ZERO;
+        assert!(usdc_only.revalue_collateral(&prices, &weights));
+        assert_eq!(usdc_only.total_margin(), amt(6000.0));
+        assert!(!usdc_only.is_undercollateralized(amt(57500.0), MarginParams::shared(0.05)));
+        
+        // 3,000 USDC and 40 SOL count as 3,000 + 40 * 100 * 0.9 = 6,600
+        let mut mixed = create_test_position().with_collateral(vec![usdc(3000.0), sol(40.0)]);
+        mixed.margin = Amount::ZERO;
+        assert!(mixed.revalue_collateral(&prices, &weights));
+        assert_eq!(mixed.total_margin(), amt(6600.0));
+        assert!(!mixed.is_undercollateralized(amt(57500.0), MarginParams::shared(0.05)));
+        let liquidation_price = mixed.liquidation_price(MARGIN).unwrap();
+        
+        // Halving SOL leaves 4,800 against the 2,500 loss: 2,300 / 57,500 = 4%
+        prices.insert("SOL/USD".to_string(), amt(50.0));
+        assert!(mixed.revalue_collateral(&prices, &weights));
+        assert!(usdc_only.revalue_collateral(&prices, &weights));
+        assert!(mixed.is_undercollateralized(amt(57500.0), MarginParams::shared(0.05)));
+        assert!(!usdc_only.is_undercollateralized(amt(57500.0), MarginParams::shared(0.05)));
+        assert!(mixed.liquidation_price(MARGIN).unwrap() > liquidation_price);
+        
+        // Without a SOL price the last value stands
+        prices.remove("SOL/USD");
+        assert!(!mixed.revalue_collateral(&prices, &weights));
+        assert_eq!(mixed.total_margin(), amt(4800.0));
+    }
+    
+    #[test]
+    fn test_reduce_keeps_margin_ratio() {
+        let mut position = Position::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            "BTC/USD",
+            amt(2.0),
+            amt(60000.0),
+            amt(6000.0),
+            true,
+        )
+        .with_collateral(vec![CollateralBalance {
+            mint: Pubkey::new_unique(),
+            symbol: "SOL/USD".to_string(),
+            amount: amt(40.0),
+        }]);
+        position.collateral_value = amt(4000.0);
+        position.unsettled_funding = amt(500.0);
+        let ratio = position.margin_ratio(amt(55000.0));
+
+        position.reduce(amt(0.25));
+        assert_eq!((position.size, position.margin, position.collateral[0].amount), (amt(1.5), amt(4500.0), amt(30.0)));
+        assert_eq!((position.collateral_value, position.unsettled_funding), (amt(3000.0), amt(375.0)));
+        assert_eq!(position.margin_ratio(amt(55000.0)), ratio);
+
+        position.reduce(amt(1.5));
+        assert_eq!(position.size, Amount::ZERO);
+    }
+
+    #[test]
+    fn test_cross_margin_pools_winners_with_losers() {
+        use crate::margin::MarginPools;
+
+        let owner = OwnerAddress::new_unique();
+        // Down 5000 at 55,000, underwater on its own
+        let loser = Position::new(Pubkey::new_unique(), owner, "BTC/USD", amt(1.0), amt(60000.0), amt(3000.0), true)
+            .with_margin_mode(MarginMode::Cross);
+        // Up 5000 at 3,500
+        let winner = Position::new(Pubkey::new_unique(), owner, "ETH/USD", amt(10.0), amt(3000.0), amt(4875.0), true)
+            .with_margin_mode(MarginMode::Cross);
+        let prices = HashMap::from([("BTC/USD".to_string(), amt(55000.0)), ("ETH/USD".to_string(), amt(3500.0))]);
+        assert!(loser.is_undercollateralized(amt(55000.0), SIXTEENTH));
+
+        let mut positions: HashMap<PositionAddress, Position> =
+            [&loser, &winner].map(|position| (position.address, position.clone())).into();
+        let mut pools = MarginPools::from_iter(positions.values());
+        // 7,875 of margin and no net PnL against the 5,625 required
+        let pool = pools.pooled(&owner, &positions, &prices, |_, _| SIXTEENTH).unwrap();
+        assert_eq!(pool.health_factor(), 1.4);
+        assert!(!loser.is_undercollateralized_cross(amt(55000.0), SIXTEENTH, &pool));
+
+        // Isolated, the winner's profit no longer backs the loser
+        let winner = winner.with_margin_mode(MarginMode::Isolated);
+        pools.insert(&winner);
+        positions.insert(winner.address, winner.clone());
+        let pool = pools.pooled(&owner, &positions, &prices, |_, _| SIXTEENTH).unwrap();
+        assert!(loser.is_undercollateralized_cross(amt(55000.0), SIXTEENTH, &pool));
+        assert!(!winner.is_undercollateralized_cross(amt(3500.0), SIXTEENTH, &pool));
+    }
+
+    #[test]
+    fn test_funding_payment_direction() {
+        let long = create_test_position();
+        let mut short = create_test_position();
+        short.is_long = false;
+        
+        // Positive funding: longs pay, shorts receive
+        assert_eq!(long.funding_payment(0.001), amt(60.0));
+        assert_eq!(short.funding_payment(0.001), amt(-60.0));
+    }
+    
+    #[test]
+    fn test_apply_funding_makes_position_liquidatable() {
+        let mut position = create_test_position();
+        
+        // Healthy at 5% maintenance ignoring funding: (6000 - 2500) / 57500 ≈ 6.1%
+        assert!(!position.is_undercollateralized(amt(57500.0), MarginParams::shared(0.05)));
+        
+        // 8 hours at 0.2%/h against the long costs 960
+        position.apply_funding(0.002, 8.0);
+        assert_eq!(position.margin, amt(5040.0));
+        assert_eq!(position.cumulative_funding_at_entry, amt(0.016));
+        assert!(position.is_undercollateralized(amt(57500.0), MarginParams::shared(0.05)));
+    }
+    
+    #[test]
+    fn test_unsettled_funding_counts_toward_margin_ratio() {
+        let mut position = create_test_position();
+        assert!(!position.is_undercollateralized(amt(57500.0), MarginParams::shared(0.05)));
+        
+        // Index moved 8 hours at 0.2%/h without settlement
+        position.accrue_funding(0.016);
+        assert_eq!(position.unsettled_funding, amt(960.0));
+        assert_eq!(position.margin, amt(6000.0));
+        assert!(position.is_undercollateralized(amt(57500.0), MarginParams::shared(0.05)));
+        
+        // Settling the same period leaves nothing unsettled and the same health
+        position.apply_funding(0.002, 8.0);
+        assert_eq!(position.unsettled_funding, Amount::ZERO);
+        assert!(position.is_undercollateralized(amt(57500.0), MarginParams::shared(0.05)));
+    }
+
+    #[test]
+    fn test_projected_liquidation_price() {
+        // Without a maintenance margin the liquidation price is where the
+        // margin left after funding is lost: 60,000 - 6,000 + 60,000 * 0.1%/h * hours
+        let params = MarginParams::shared(0.0);
+        let long = create_test_position();
+        assert_eq!(long.projected_liquidation_price(0.0, 0.001, params), Some(amt(54000.0)));
+        assert_eq!(long.projected_liquidation_price(1.0, 0.001, params), Some(amt(54060.0)));
+        assert_eq!(long.projected_liquidation_price(8.0, 0.001, params), Some(amt(54480.0)));
+        assert_eq!(long.projected_liquidation_price(24.0, 0.001, params), Some(amt(55440.0)));
+        // At 1/16: (60,000 - (6,000 - 1,440)) / (1 - 1/16)
+        assert_eq!(long.projected_liquidation_price(24.0, 0.001, SIXTEENTH), Some(amt(59136.0)));
+        // Funding paid to the long carries its liquidation price away
+        assert_eq!(long.projected_liquidation_price(24.0, -0.001, params), None);
+
+        // Shorts pay negative funding, which pulls their liquidation price down
+        let mut short = create_test_position();
+        short.is_long = false;
+        assert_eq!(short.projected_liquidation_price(8.0, -0.001, params), Some(amt(65520.0)));
+        assert_eq!(short.projected_liquidation_price(8.0, 0.001, params), None);
+    }
+
+    #[test]
+    fn test_metadata_round_trips_and_merges() {
+        let mut position = create_test_position().with_metadata("external_id", "pos-8812");
+        let json = serde_json::to_string(&position).unwrap();
+        assert_eq!(serde_json::from_str::<Position>(&json).unwrap(), position);
+        // Amounts are written as JSON numbers whichever type they are
+        assert!(json.contains(r#""entry_price":60000.0"#) || json.contains(r#""entry_price":60000"#));
+        // Positions without metadata are written and read as before
+        let bare = Position { metadata: HashMap::new(), ..position.clone() };
+        assert!(!serde_json::to_string(&bare).unwrap().contains("metadata"));
+
+        // Entries set anew win, and the rest are kept
+        let previous = HashMap::from([
+            ("external_id".to_string(), "pos-1".to_string()),
+            ("desk".to_string(), "retail".to_string()),
+        ]);
+        position.merge_metadata(&previous);
+        assert_eq!(position.metadata["external_id"], "pos-8812");
+        assert_eq!(position.metadata["desk"], "retail");
+        assert!(position.metadata_problem().is_none());
+
+        let large = position.clone().with_metadata("note", "x".repeat(MAX_METADATA_BYTES));
+        assert!(large.metadata_problem().unwrap().contains("bytes, over the limit of 1024"));
+        let mut crowded = position;
+        crowded.metadata = (0..=MAX_METADATA_KEYS).map(|i| (i.to_string(), String::new())).collect();
+        assert_eq!(crowded.metadata_problem().unwrap(), "metadata has 17 keys, over the limit of 16");
     }
 }
@@ -0,0 +1,884 @@
+use crate::address::FeedAddress;
+use crate::error::LiquidationError;
+use crate::health::decode_market_account;
+use crate::instruction::{LiquidationMarket, create_token_account_instruction, program_market_address};
+use crate::oracle::{PYTH_DEVNET_PROGRAM_ID, PYTH_MAINNET_PROGRAM_ID};
+use crate::rate_limit::RateLimiter;
+use crate::rpc_pool::RpcPool;
+use crate::token_accounts::{MarketTokenAccounts, token_program_of};
+use crate::types::LiquidationConfig;
+use async_trait::async_trait;
+use solana_sdk::{
+    account::Account,
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+};
+use std::collections::BTreeMap;
+#[cfg(any(test, feature = "testing"))]
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(any(test, feature = "testing"))]
+use std::sync::{Mutex, PoisonError};
+
+/// RPC requests the preflight checks make
+#[async_trait]
+pub trait PreflightRpc: Send + Sync + fmt::Debug {
+    /// Version of the node's software
+    async fn version(&self) -> Result<String, LiquidationError>;
+
+    /// Succeeds if the node reports itself healthy
+    async fn health(&self) -> Result<(), LiquidationError>;
+
+    /// An account, `None` if it doesn't exist
+    async fn account(&self, address: &Pubkey) -> Result<Option<Account>, LiquidationError>;
+
+    /// Balance of an account (in lamports)
+    async fn balance(&self, address: &Pubkey) -> Result<u64, LiquidationError>;
+
+    /// Send `instructions` in a transaction paid for by `payer`, waiting for it
+    /// to confirm
+    async fn send(&self, payer: &Keypair, instructions: &[Instruction]) -> Result<Signature, LiquidationError>;
+}
+
+/// Preflight RPC requests to the healthiest of a pool of RPC endpoints
+#[derive(Debug, Clone)]
+pub struct RpcPreflight {
+    rpc: RpcPool,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl RpcPreflight {
+    /// Create preflight requests to the given RPC endpoints
+    pub fn new(rpc: RpcPool, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self { rpc, rate_limiter }
+    }
+}
+
+#[async_trait]
+impl PreflightRpc for RpcPreflight {
+    async fn version(&self) -> Result<String, LiquidationError> {
+        self.rpc
+            .call(&self.rate_limiter, |rpc_client| {
+                rpc_client
+                    .get_version()
+                    .map(|version| version.solana_core)
+                    .map_err(LiquidationError::from)
+            })
+            .await
+    }
+
+    async fn health(&self) -> Result<(), LiquidationError> {
+        self.rpc
+            .call(&self.rate_limiter, |rpc_client| {
+                rpc_client.get_health().map_err(LiquidationError::from)
+            })
+            .await
+    }
+
+    async fn account(&self, address: &Pubkey) -> Result<Option<Account>, LiquidationError> {
+        let address = *address;
+        self.rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client
+                    .get_account_with_commitment(&address, CommitmentConfig::confirmed())
+                    .map(|response| response.value)
+                    .map_err(LiquidationError::from)
+            })
+            .await
+    }
+
+    async fn balance(&self, address: &Pubkey) -> Result<u64, LiquidationError> {
+        let address = *address;
+        self.rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client.get_balance(&address).map_err(LiquidationError::from)
+            })
+            .await
+    }
+
+    async fn send(&self, payer: &Keypair, instructions: &[Instruction]) -> Result<Signature, LiquidationError> {
+        let blockhash = self
+            .rpc
+            .call(&self.rate_limiter, |rpc_client| {
+                rpc_client.get_latest_blockhash().map_err(LiquidationError::from)
+            })
+            .await?;
+        let transaction =
+            Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &[payer], blockhash);
+        self.rpc
+            .call(&self.rate_limiter, move |rpc_client| {
+                rpc_client
+                    .send_and_confirm_transaction(&transaction)
+                    .map_err(LiquidationError::from)
+            })
+            .await
+    }
+}
+
+/// Mock preflight RPC for testing, a healthy node holding only the accounts
+/// it's given
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Clone, Default)]
+pub struct MockPreflightRpc {
+    down: Arc<Mutex<bool>>,
+    unhealthy: Arc<Mutex<bool>>,
+    accounts: Arc<Mutex<HashMap<Pubkey, Account>>>,
+    sent: Arc<Mutex<Vec<Vec<Instruction>>>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockPreflightRpc {
+    /// Create a mock node without accounts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the node down, failing every request, or bring it back up
+    pub fn set_down(&self, down: bool) {
+        *self.down.lock().unwrap_or_else(PoisonError::into_inner) = down;
+    }
+
+    /// Have the node report itself unhealthy, or healthy again
+    pub fn set_unhealthy(&self, unhealthy: bool) {
+        *self.unhealthy.lock().unwrap_or_else(PoisonError::into_inner) = unhealthy;
+    }
+
+    /// Create or replace an account
+    pub fn set_account(&self, address: Pubkey, account: Account) {
+        self.accounts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(address, account);
+    }
+
+    /// Remove an account, if it exists
+    pub fn remove_account(&self, address: &Pubkey) {
+        self.accounts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(address);
+    }
+
+    /// Instructions of every transaction sent so far, in order
+    pub fn sent(&self) -> Vec<Vec<Instruction>> {
+        self.sent.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    fn check_up(&self) -> Result<(), LiquidationError> {
+        if *self.down.lock().unwrap_or_else(PoisonError::into_inner) {
+            return Err(LiquidationError::RpcUnavailable("mock node is down".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[async_trait]
+impl PreflightRpc for MockPreflightRpc {
+    async fn version(&self) -> Result<String, LiquidationError> {
+        self.check_up()?;
+        Ok("1.18.26".to_string())
+    }
+
+    async fn health(&self) -> Result<(), LiquidationError> {
+        self.check_up()?;
+        if *self.unhealthy.lock().unwrap_or_else(PoisonError::into_inner) {
+            return Err(LiquidationError::RpcUnavailable("node is behind".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn account(&self, address: &Pubkey) -> Result<Option<Account>, LiquidationError> {
+        self.check_up()?;
+        Ok(self.accounts.lock().unwrap_or_else(PoisonError::into_inner).get(address).cloned())
+    }
+
+    async fn balance(&self, address: &Pubkey) -> Result<u64, LiquidationError> {
+        Ok(self.account(address).await?.map_or(0, |account| account.lamports))
+    }
+
+    async fn send(&self, _payer: &Keypair, instructions: &[Instruction]) -> Result<Signature, LiquidationError> {
+        self.check_up()?;
+        self.sent
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(instructions.to_vec());
+        Ok(Signature::new_unique())
+    }
+}
+
+/// Outcome of one preflight check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightCheck {
+    /// What was checked
+    pub name: String,
+    /// What was found, or what's wrong and how to fix it
+    pub result: Result<String, String>,
+}
+
+/// Outcome of every preflight check, in the order they ran
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+    /// The first liquidator key's token accounts in every market that could be
+    /// loaded, by market address
+    pub token_accounts: BTreeMap<Pubkey, MarketTokenAccounts>,
+    /// Every market that could be loaded, by address, to build liquidations
+    /// against
+    pub markets: BTreeMap<Pubkey, LiquidationMarket>,
+}
+
+impl PreflightReport {
+    fn pass(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.checks.push(PreflightCheck {
+            name: name.into(),
+            result: Ok(detail.into()),
+        });
+    }
+
+    fn fail(&mut self, name: impl Into<String>, message: impl Into<String>) {
+        self.checks.push(PreflightCheck {
+            name: name.into(),
+            result: Err(message.into()),
+        });
+    }
+
+    /// Checks that failed, in the order they ran
+    pub fn failures(&self) -> impl Iterator<Item = &PreflightCheck> {
+        self.checks.iter().filter(|check| check.result.is_err())
+    }
+
+    /// Whether every check passed
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Fail with a summary of the failed checks, if any
+    pub fn into_result(self) -> Result<(), LiquidationError> {
+        let failures: Vec<String> = self
+            .failures()
+            .map(|check| format!("{}: {}", check.name, check.result.as_ref().unwrap_err()))
+            .collect();
+        if failures.is_empty() {
+            return Ok(());
+        }
+        Err(LiquidationError::ConfigError(format!(
+            "{} of {} preflight checks failed (pass --skip-preflight to start anyway): {}",
+            failures.len(),
+            self.checks.len(),
+            failures.join("; ")
+        )))
+    }
+}
+
+/// Check that the engine can do its job before it starts: the RPC endpoint
+/// responds, every monitored program is deployed, every oracle feed holds a
+/// Pyth price, and each keypair at `keypair_paths` can pay for liquidations
+/// and has token accounts to repay and be rewarded in for every program's
+/// market
+///
+/// Every check runs, whichever fail; with several keys, the checks of each
+/// name its keypair file. Missing token accounts are created when
+/// `create_token_accounts` is set, each paid for by its key, and the first
+/// key's token accounts in every market that could be loaded are returned with
+/// the report. In dry run nothing is submitted, so the keypairs are optional
+/// and their balances and token accounts aren't checked.
+pub async fn preflight(
+    rpc: &dyn PreflightRpc,
+    config: &LiquidationConfig,
+    keypair_paths: &[PathBuf],
+    create_token_accounts: bool,
+) -> PreflightReport {
+    let mut report = PreflightReport::default();
+
+    match (rpc.version().await, rpc.health().await) {
+        (Ok(version), Ok(())) => report.pass("rpc", format!("solana-core {}", version)),
+        (Err(e), _) => report.fail(
+            "rpc",
+            format!("endpoint doesn't respond to getVersion ({}); check --rpc-url", e),
+        ),
+        (Ok(_), Err(e)) => report.fail(
+            "rpc",
+            format!("endpoint reports itself unhealthy ({}); wait for it to catch up or use another", e),
+        ),
+    }
+
+    for program_id in config.program_ids() {
+        let name = format!("program {}", program_id);
+        match rpc.account(&program_id).await {
+            Ok(Some(account)) if account.executable => report.pass(name, "deployed"),
+            Ok(Some(_)) => report.fail(
+                name,
+                "account exists but isn't executable; check the program_id of its markets",
+            ),
+            Ok(None) => report.fail(
+                name,
+                "not deployed on this cluster; check --rpc-url points at the cluster it's deployed to",
+            ),
+            Err(e) => report.fail(name, format!("couldn't be fetched: {}", e)),
+        }
+    }
+
+    let pyth_program_id = if config.use_mainnet {
+        PYTH_MAINNET_PROGRAM_ID
+    } else {
+        PYTH_DEVNET_PROGRAM_ID
+    };
+    let feeds: BTreeMap<String, FeedAddress> = config.oracle_feeds().into_iter().collect();
+    for (symbol, feed) in feeds {
+        let name = format!("oracle feed {}", symbol);
+        match rpc.account(feed.as_ref()).await {
+            Ok(Some(account)) if account.owner != pyth_program_id => report.fail(
+                name,
+                format!(
+                    "{} is owned by {}, not the Pyth program {}; check the feed and use_mainnet",
+                    feed, account.owner, pyth_program_id
+                ),
+            ),
+            Ok(Some(account)) => match pyth_sdk_solana::state::load_price_account(&account.data) {
+                Ok(_) => report.pass(name, feed.to_string()),
+                Err(e) => report.fail(name, format!("{} isn't a Pyth price account ({}); check the feed", feed, e)),
+            },
+            Ok(None) => report.fail(
+                name,
+                format!("{} doesn't exist on this cluster; check the feed and use_mainnet", feed),
+            ),
+            Err(e) => report.fail(name, format!("{} couldn't be fetched: {}", feed, e)),
+        }
+    }
+
+    // Checks of one key among several name its keypair file
+    let qualify = |name: &str, path: &Path| match keypair_paths {
+        [_] => name.to_string(),
+        _ => format!("{} ({})", name, path.display()),
+    };
+    if keypair_paths.is_empty() {
+        if !config.dry_run {
+            report.fail("keypair", "no keypair configured; set key_pool.keypairs or --keypair");
+        }
+        return report;
+    }
+    let mut payers = Vec::new();
+    for path in keypair_paths {
+        match solana_sdk::signature::read_keypair_file(path) {
+            Ok(payer) => {
+                report.pass(qualify("keypair", path), payer.pubkey().to_string());
+                payers.push((path, payer));
+            }
+            Err(e) if config.dry_run => {
+                report.pass(qualify("keypair", path), format!("not needed in dry run ({})", e));
+            }
+            Err(e) => report.fail(
+                qualify("keypair", path),
+                format!(
+                    "failed to read {} ({}); check --keypair and key_pool.keypairs",
+                    path.display(),
+                    e
+                ),
+            ),
+        }
+    }
+    if config.dry_run || payers.len() < keypair_paths.len() {
+        return report;
+    }
+
+    for (path, payer) in &payers {
+        let liquidator = payer.pubkey();
+        let name = qualify("balance", path);
+        match rpc.balance(&liquidator).await {
+            Ok(balance) if balance >= sol_to_lamports(config.min_sol_balance) => {
+                report.pass(name, format!("{} SOL", lamports_to_sol(balance)));
+            }
+            Ok(balance) => report.fail(
+                name,
+                format!(
+                    "liquidator {} holds {} SOL, below min_sol_balance of {}; fund it",
+                    liquidator,
+                    lamports_to_sol(balance),
+                    config.min_sol_balance
+                ),
+            ),
+            Err(e) => report.fail(name, format!("couldn't be fetched: {}", e)),
+        }
+    }
+
+    let mut missing: Vec<Vec<_>> = payers.iter().map(|_| Vec::new()).collect();
+    for (program_id, market_id) in config.program_markets() {
+        let address = program_market_address(&program_id, market_id);
+        let label = format!("market {} of program {}", market_id, program_id);
+        let name = label.clone();
+        let market = match rpc.account(&address).await {
+            Ok(Some(account)) => match decode_market_account(&account.data) {
+                Ok(market) => market,
+                Err(e) => {
+                    report.fail(name, format!("{} couldn't be decoded: {}", address, e));
+                    continue;
+                }
+            },
+            Ok(None) => {
+                report.fail(name, format!("{} doesn't exist; has the market been initialized?", address));
+                continue;
+            }
+            Err(e) => {
+                report.fail(name, format!("{} couldn't be fetched: {}", address, e));
+                continue;
+            }
+        };
+        // Token accounts are derived under their mint's token program
+        let mut mints = Vec::new();
+        for (role, mint) in [("collateral", market.collateral_mint), ("debt", market.debt_mint)] {
+            let name = format!("{} mint of {}", role, label);
+            match rpc.account(&mint).await {
+                Ok(Some(account)) => match token_program_of(&account) {
+                    Ok(token_program) => mints.push((mint, token_program)),
+                    Err(e) => report.fail(name, format!("{}: {}", mint, e)),
+                },
+                Ok(None) => report.fail(name, format!("{} doesn't exist", mint)),
+                Err(e) => report.fail(name, format!("{} couldn't be fetched: {}", mint, e)),
+            }
+        }
+        let [collateral, debt] = mints[..] else {
+            continue;
+        };
+        report
+            .markets
+            .insert(address, LiquidationMarket::new(program_id, &market, collateral.1, debt.1));
+        for ((path, payer), missing) in payers.iter().zip(&mut missing) {
+            let accounts = MarketTokenAccounts::derive(&payer.pubkey(), collateral, debt);
+            report.token_accounts.entry(address).or_insert(accounts);
+            for (role, mint, token_program, token_account) in accounts.roles() {
+                let name = qualify(&format!("{} token account of {}", role, label), path);
+                match rpc.account(&token_account).await {
+                    Ok(Some(_)) => report.pass(name, token_account.to_string()),
+                    Ok(None) if create_token_accounts => missing.push((name, token_account, mint, token_program)),
+                    Ok(None) => report.fail(
+                        name,
+                        format!(
+                            "{} for mint {} doesn't exist; create it or pass --create-token-accounts",
+                            token_account, mint
+                        ),
+                    ),
+                    Err(e) => report.fail(name, format!("{} couldn't be fetched: {}", token_account, e)),
+                }
+            }
+        }
+    }
+    // Each key pays for its own token accounts
+    for ((_, payer), missing) in payers.iter().zip(missing) {
+        if missing.is_empty() {
+            continue;
+        }
+        let instructions: Vec<Instruction> = missing
+            .iter()
+            .map(|(_, _, mint, token_program)| create_token_account_instruction(&payer.pubkey(), mint, token_program))
+            .collect();
+        let sent = rpc.send(payer, &instructions).await;
+        for (name, token_account, _, _) in missing {
+            match &sent {
+                Ok(signature) => report.pass(name, format!("{} created in {}", token_account, signature)),
+                Err(e) => report.fail(name, format!("{} couldn't be created: {}", token_account, e)),
+            }
+        }
+    }
+
+    report
+}
+
+/// Read the market created with `market_id` in the program at `program_id`
+/// and the token programs owning its mints, failing with a configuration error
+/// if either is missing or isn't what the program holds
+pub(crate) async fn load_market(
+    rpc: &dyn PreflightRpc,
+    program_id: &Pubkey,
+    market_id: u64,
+) -> Result<LiquidationMarket, LiquidationError> {
+    let address = program_market_address(program_id, market_id);
+    let account = rpc.account(&address).await?.ok_or_else(|| {
+        LiquidationError::ConfigError(format!(
+            "market {} ({}) of program {} doesn't exist; has the market been initialized?",
+            market_id, address, program_id
+        ))
+    })?;
+    let market = decode_market_account(&account.data)?;
+    let mut token_programs = Vec::new();
+    for mint in [market.collateral_mint, market.debt_mint] {
+        let account = rpc.account(&mint).await?.ok_or_else(|| {
+            LiquidationError::ConfigError(format!("mint {} of program {} doesn't exist", mint, program_id))
+        })?;
+        token_programs.push(token_program_of(&account)?);
+    }
+    Ok(LiquidationMarket::new(*program_id, &market, token_programs[0], token_programs[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{MarketAccount, PROGRAM_ID};
+    use crate::instruction::associated_token_account;
+    use crate::market::MarketConfig;
+    use anchor_lang::AccountSerialize;
+    use solana_sdk::signature::write_keypair_file;
+
+    /// A node where everything the default configuration with a BTC/USD feed
+    /// and `program_id`'s market needs is in place, for a funded liquidator
+    struct Cluster {
+        rpc: MockPreflightRpc,
+        config: LiquidationConfig,
+        feed: Pubkey,
+        liquidator: Keypair,
+        /// Collateral then debt mint, with their token programs
+        mints: [Pubkey; 2],
+        token_programs: [Pubkey; 2],
+        _dir: tempfile::TempDir,
+        keypair_path: std::path::PathBuf,
+    }
+
+    fn price_account_data() -> Vec<u8> {
+        let mut data = vec![0; std::mem::size_of::<pyth_sdk_solana::state::PriceAccount>()];
+        data[0..4].copy_from_slice(&pyth_sdk_solana::state::MAGIC.to_le_bytes());
+        data[4..8].copy_from_slice(&pyth_sdk_solana::state::VERSION_2.to_le_bytes());
+        data[8..12].copy_from_slice(&(pyth_sdk_solana::state::AccountType::Price as u32).to_le_bytes());
+        data
+    }
+
+    fn account(owner: Pubkey, data: Vec<u8>, lamports: u64) -> Account {
+        Account {
+            lamports,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn cluster() -> Cluster {
+        let rpc = MockPreflightRpc::new();
+        let feed = Pubkey::new_unique();
+        let mut config = LiquidationConfig {
+            dry_run: false,
+            ..LiquidationConfig::default()
+        };
+        config.price_accounts.insert("BTC/USD".to_string(), feed.into());
+
+        rpc.set_account(
+            PROGRAM_ID,
+            Account {
+                executable: true,
+                ..account(Pubkey::new_unique(), Vec::new(), 1)
+            },
+        );
+        rpc.set_account(feed, account(PYTH_DEVNET_PROGRAM_ID, price_account_data(), 1));
+
+        let liquidator = Keypair::new();
+        rpc.set_account(liquidator.pubkey(), account(Pubkey::default(), Vec::new(), sol_to_lamports(1.0)));
+        let mints = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let market = MarketAccount {
+            authority: Pubkey::new_unique(),
+            oracle: feed,
+            insurance_fund_vault: Pubkey::new_unique(),
+            collateral_mint: mints[0],
+            debt_mint: mints[1],
+            close_factor_bps: 5_000,
+            liquidation_bonus_bps: 500,
+            liquidation_mode: liquidation_program::LiquidationMode::Permissionless,
+            keepers: Vec::new(),
+            bump: 255,
+            vault_authority_bump: 255,
+            grace_period_secs: 0,
+            warning_health_bps: liquidation_program::DEFAULT_WARNING_HEALTH_BPS,
+            instant_health_bps: liquidation_program::DEFAULT_INSTANT_HEALTH_BPS,
+            vault: Pubkey::new_unique(),
+            debt_vault: Pubkey::new_unique(),
+            vault_bump: 255,
+            debt_vault_bump: 255,
+            insurance_fund_vault_bump: 255,
+            collateral_decimals: 6,
+            debt_decimals: 6,
+            market_id: 0,
+        };
+        let mut data = Vec::new();
+        market.try_serialize(&mut data).unwrap();
+        rpc.set_account(program_market_address(&PROGRAM_ID, 0), account(PROGRAM_ID, data, 1));
+        // The debt is a Token-2022 mint
+        let token_programs = [anchor_spl::token::ID, anchor_spl::token_2022::ID];
+        for (mint, token_program) in mints.into_iter().zip(token_programs) {
+            rpc.set_account(mint, account(token_program, Vec::new(), 1));
+            rpc.set_account(
+                associated_token_account(&liquidator.pubkey(), &mint, &token_program),
+                account(token_program, Vec::new(), 1),
+            );
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let keypair_path = dir.path().join("keypair.json");
+        write_keypair_file(&liquidator, &keypair_path).unwrap();
+        Cluster {
+            rpc,
+            config,
+            feed,
+            liquidator,
+            mints,
+            token_programs,
+            _dir: dir,
+            keypair_path,
+        }
+    }
+
+    impl Cluster {
+        async fn preflight(&self, create_token_accounts: bool) -> PreflightReport {
+            preflight(
+                &self.rpc,
+                &self.config,
+                std::slice::from_ref(&self.keypair_path),
+                create_token_accounts,
+            )
+            .await
+        }
+    }
+
+    fn failed(report: &PreflightReport) -> Vec<&str> {
+        report.failures().map(|check| check.name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_ready_cluster_passes() {
+        let cluster = cluster();
+        let report = cluster.preflight(false).await;
+        assert!(report.passed(), "{:?}", report);
+        let names: Vec<&str> = report.checks.iter().map(|check| check.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "rpc".to_string(),
+                format!("program {}", PROGRAM_ID),
+                "oracle feed BTC/USD".to_string(),
+                "keypair".to_string(),
+                "balance".to_string(),
+                format!("collateral token account of market 0 of program {}", PROGRAM_ID),
+                format!("debt token account of market 0 of program {}", PROGRAM_ID),
+            ]
+        );
+        // The market's token accounts are kept for building liquidations
+        let address = program_market_address(&PROGRAM_ID, 0);
+        let accounts = report.token_accounts[&address];
+        assert_eq!(
+            accounts.debt,
+            associated_token_account(&cluster.liquidator.pubkey(), &cluster.mints[1], &cluster.token_programs[1])
+        );
+        assert_eq!(accounts.debt_token_program, anchor_spl::token_2022::ID);
+        let market = report.markets[&address];
+        assert_eq!(market.oracle, cluster.feed);
+        assert_eq!(market.token_accounts(&cluster.liquidator.pubkey()), accounts);
+        assert_eq!(load_market(&cluster.rpc, &PROGRAM_ID, 0).await.unwrap(), market);
+        report.into_result().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_load_market_requires_market_and_mints() {
+        let cluster = cluster();
+        let other = Pubkey::new_unique();
+        let err = load_market(&cluster.rpc, &other, 0).await.unwrap_err();
+        assert!(matches!(&err, LiquidationError::ConfigError(message) if message.contains("initialized")), "{}", err);
+        // Nor is another market of the same program
+        let err = load_market(&cluster.rpc, &PROGRAM_ID, 1).await.unwrap_err();
+        assert!(matches!(&err, LiquidationError::ConfigError(message) if message.contains("initialized")), "{}", err);
+
+        cluster.rpc.remove_account(&cluster.mints[1]);
+        let err = load_market(&cluster.rpc, &PROGRAM_ID, 0).await.unwrap_err();
+        assert!(err.to_string().contains(&cluster.mints[1].to_string()), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_rpc_fails_every_remote_check() {
+        let cluster = cluster();
+        cluster.rpc.set_down(true);
+        let report = cluster.preflight(false).await;
+        assert_eq!(failed(&report).len(), 5);
+        assert!(report.checks[0].result.as_ref().unwrap_err().contains("--rpc-url"));
+
+        cluster.rpc.set_down(false);
+        cluster.rpc.set_unhealthy(true);
+        let report = cluster.preflight(false).await;
+        assert_eq!(failed(&report), ["rpc"]);
+        assert!(report.checks[0].result.as_ref().unwrap_err().contains("unhealthy"));
+    }
+
+    #[tokio::test]
+    async fn test_program_must_be_deployed_and_executable() {
+        let cluster = cluster();
+        let name = format!("program {}", PROGRAM_ID);
+        cluster
+            .rpc
+            .set_account(PROGRAM_ID, account(Pubkey::new_unique(), Vec::new(), 1));
+        let report = cluster.preflight(false).await;
+        assert_eq!(failed(&report), [name.as_str()]);
+        assert!(report.checks[1].result.as_ref().unwrap_err().contains("isn't executable"));
+
+        // Markets add their programs to the checks
+        let mut cluster = cluster;
+        let other = Pubkey::new_unique();
+        cluster
+            .config
+            .markets
+            .push(MarketConfig::new(other, "ETH/USD", 0.05));
+        let report = cluster.preflight(false).await;
+        let other_program = format!("program {}", other);
+        assert!(failed(&report).contains(&other_program.as_str()));
+        assert!(failed(&report).contains(&format!("market 0 of program {}", other).as_str()));
+
+        // As do markets of the same program, each checked by its id
+        cluster.config.markets.push(MarketConfig {
+            market_id: 2,
+            ..MarketConfig::new(PROGRAM_ID, "SOL/USD", 0.05)
+        });
+        let report = cluster.preflight(false).await;
+        assert!(failed(&report).contains(&format!("market 2 of program {}", PROGRAM_ID).as_str()));
+        assert!(!report.markets.contains_key(&program_market_address(&PROGRAM_ID, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_oracle_feed_must_hold_a_pyth_price() {
+        let cluster = cluster();
+        cluster
+            .rpc
+            .set_account(cluster.feed, account(PYTH_DEVNET_PROGRAM_ID, vec![0; 16], 1));
+        let report = cluster.preflight(false).await;
+        assert_eq!(failed(&report), ["oracle feed BTC/USD"]);
+        assert!(report.checks[2].result.as_ref().unwrap_err().contains("isn't a Pyth price account"));
+
+        // A mainnet feed on a devnet configuration
+        cluster
+            .rpc
+            .set_account(cluster.feed, account(PYTH_MAINNET_PROGRAM_ID, price_account_data(), 1));
+        let report = cluster.preflight(false).await;
+        assert!(report.checks[2].result.as_ref().unwrap_err().contains("use_mainnet"));
+
+        let mut cluster = cluster;
+        cluster.config.use_mainnet = true;
+        assert!(cluster.preflight(false).await.passed());
+        cluster.config.price_accounts.insert("ETH/USD".to_string(), FeedAddress::new_unique());
+        let report = cluster.preflight(false).await;
+        assert_eq!(failed(&report), ["oracle feed ETH/USD"]);
+    }
+
+    #[tokio::test]
+    async fn test_keypair_required_unless_dry_run() {
+        let mut cluster = cluster();
+        cluster.keypair_path = cluster.keypair_path.with_file_name("missing.json");
+        let report = cluster.preflight(false).await;
+        assert_eq!(failed(&report), ["keypair"]);
+        assert!(report.checks.last().unwrap().result.as_ref().unwrap_err().contains("--keypair"));
+
+        cluster.config.dry_run = true;
+        let report = cluster.preflight(false).await;
+        assert!(report.passed());
+        assert_eq!(report.checks.last().unwrap().name, "keypair");
+    }
+
+    #[tokio::test]
+    async fn test_liquidator_needs_min_sol_balance() {
+        let mut cluster = cluster();
+        cluster.config.min_sol_balance = 1.5;
+        let report = cluster.preflight(false).await;
+        assert_eq!(failed(&report), ["balance"]);
+        assert!(report.checks[4].result.as_ref().unwrap_err().contains("below min_sol_balance of 1.5"));
+    }
+
+    #[tokio::test]
+    async fn test_mints_must_belong_to_a_token_program() {
+        let cluster = cluster();
+        cluster
+            .rpc
+            .set_account(cluster.mints[0], account(Pubkey::new_unique(), Vec::new(), 1));
+        let report = cluster.preflight(false).await;
+        let mint_check = format!("collateral mint of market 0 of program {}", PROGRAM_ID);
+        assert_eq!(failed(&report), [mint_check.as_str()]);
+        assert!(report.checks[5].result.as_ref().unwrap_err().contains("is not a token program"));
+        // Without both mints no token account can be derived
+        assert!(report.token_accounts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_missing_token_accounts_reported_or_created() {
+        let cluster = cluster();
+        let debt_account =
+            associated_token_account(&cluster.liquidator.pubkey(), &cluster.mints[1], &cluster.token_programs[1]);
+        cluster.rpc.accounts.lock().unwrap().remove(&debt_account);
+        // Every failure is reported together
+        cluster.rpc.set_unhealthy(true);
+        let report = cluster.preflight(false).await;
+        let debt_check = format!("debt token account of market 0 of program {}", PROGRAM_ID);
+        assert_eq!(failed(&report), ["rpc", debt_check.as_str()]);
+        assert!(report.checks[6].result.as_ref().unwrap_err().contains("--create-token-accounts"));
+        let error = report.into_result().unwrap_err().to_string();
+        assert!(error.contains("2 of 7 preflight checks failed"), "{}", error);
+        assert!(cluster.rpc.sent().is_empty());
+
+        cluster.rpc.set_unhealthy(false);
+        let report = cluster.preflight(true).await;
+        assert!(report.passed(), "{:?}", report);
+        let sent = cluster.rpc.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0],
+            [create_token_account_instruction(
+                &cluster.liquidator.pubkey(),
+                &cluster.mints[1],
+                &cluster.token_programs[1]
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_every_key_checked_by_its_keypair_file() {
+        let cluster = cluster();
+        // A second key with too little SOL and neither token account
+        let second = Keypair::new();
+        let second_path = cluster._dir.path().join("second.json");
+        write_keypair_file(&second, &second_path).unwrap();
+        cluster
+            .rpc
+            .set_account(second.pubkey(), account(Pubkey::default(), Vec::new(), sol_to_lamports(0.01)));
+        let paths = [cluster.keypair_path.clone(), second_path.clone()];
+
+        let report = preflight(&cluster.rpc, &cluster.config, &paths, true).await;
+        let qualified = |name: &str, path: &std::path::PathBuf| format!("{} ({})", name, path.display());
+        let names: Vec<&str> = report.checks.iter().map(|check| check.name.as_str()).collect();
+        assert_eq!(
+            names[3..7],
+            [
+                qualified("keypair", &paths[0]),
+                qualified("keypair", &paths[1]),
+                qualified("balance", &paths[0]),
+                qualified("balance", &paths[1]),
+            ]
+        );
+        assert_eq!(failed(&report), [qualified("balance", &paths[1])]);
+        assert_eq!(report.checks.len(), 11);
+        // The first key's accounts are kept, and only the second's were created, by it
+        assert_eq!(
+            report.token_accounts[&program_market_address(&PROGRAM_ID, 0)].debt,
+            associated_token_account(&cluster.liquidator.pubkey(), &cluster.mints[1], &cluster.token_programs[1])
+        );
+        let sent = cluster.rpc.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0],
+            [
+                create_token_account_instruction(&second.pubkey(), &cluster.mints[0], &cluster.token_programs[0]),
+                create_token_account_instruction(&second.pubkey(), &cluster.mints[1], &cluster.token_programs[1]),
+            ]
+        );
+
+        // Every key has to be readable
+        let paths = [cluster.keypair_path.clone(), cluster.keypair_path.with_file_name("missing.json")];
+        let report = preflight(&cluster.rpc, &cluster.config, &paths, false).await;
+        assert_eq!(failed(&report), [qualified("keypair", &paths[1])]);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::address::PositionAddress;
+use crate::error::{ConfigViolation, LiquidationError, first_violation};
+use std::fmt;
+
+/// What a liquidatable position is ranked on when more become liquidatable at
+/// once than can be processed in one cycle
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// The liquidatable position
+    pub position: PositionAddress,
+    /// Expected liquidator reward net of network fees and slippage (in quote currency)
+    pub expected_profit: f64,
+    /// Shortfall beyond the position's margin at the current price, which grows
+    /// the longer the position is left open (in quote currency)
+    pub bad_debt: f64,
+    /// Time since the position was last checked for liquidation (in seconds)
+    pub staleness_secs: f64,
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected profit {:.4}, bad debt {:.4}, last checked {:.0}s ago",
+            self.expected_profit, self.bad_debt, self.staleness_secs
+        )
+    }
+}
+
+/// Scores liquidation candidates; higher scores are processed first
+pub trait Prioritizer: Send + Sync {
+    /// Score of a candidate
+    fn score(&self, candidate: &Candidate) -> f64;
+}
+
+/// Weights of [`WeightedScore`], per unit of each factor
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PriorityWeights {
+    /// Weight of the expected profit (per unit of quote currency)
+    pub expected_profit: f64,
+    /// Weight of the bad debt (per unit of quote currency)
+    pub bad_debt: f64,
+    /// Weight of the time since the last check (per second)
+    pub staleness: f64,
+}
+
+impl Default for PriorityWeights {
+    fn default() -> Self {
+        Self {
+            expected_profit: 1.0,
+            bad_debt: 1.0,
+            staleness: 0.1,
+        }
+    }
+}
+
+impl PriorityWeights {
+    /// Check that the weights are usable
+    pub fn validate(&self) -> Result<(), LiquidationError> {
+        first_violation(self.violations())
+    }
+
+    /// Every problem with the weights
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        [
+            ("expected_profit", self.expected_profit),
+            ("bad_debt", self.bad_debt),
+            ("staleness", self.staleness),
+        ]
+        .into_iter()
+        .filter(|(_, weight)| !(weight.is_finite() && *weight >= 0.0))
+        .map(|(field, weight)| ConfigViolation::new(field, format!("must be a non-negative number, got {}", weight)))
+        .collect()
+    }
+}
+
+/// Default prioritizer: a weighted sum of a candidate's factors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct WeightedScore {
+    weights: PriorityWeights,
+}
+
+impl WeightedScore {
+    pub fn new(weights: PriorityWeights) -> Self {
+        Self { weights }
+    }
+}
+
+impl Prioritizer for WeightedScore {
+    fn score(&self, candidate: &Candidate) -> f64 {
+        self.weights.expected_profit * candidate.expected_profit
+            + self.weights.bad_debt * candidate.bad_debt
+            + self.weights.staleness * candidate.staleness_secs
+    }
+}
+
+/// Score candidates and order them highest score first
+///
+/// Candidates with equal scores keep their given order.
+pub(crate) fn prioritize(prioritizer: &dyn Prioritizer, candidates: Vec<Candidate>) -> Vec<(Candidate, f64)> {
+    let mut scored: Vec<(Candidate, f64)> = candidates
+        .into_iter()
+        .map(|candidate| {
+            let score = prioritizer.score(&candidate);
+            (candidate, score)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(expected_profit: f64, bad_debt: f64, staleness_secs: f64) -> Candidate {
+        Candidate {
+            position: PositionAddress::new_unique(),
+            expected_profit,
+            bad_debt,
+            staleness_secs,
+        }
+    }
+
+    #[test]
+    fn test_weighted_score() {
+        let weighted = WeightedScore::new(PriorityWeights::default());
+        assert_eq!(weighted.score(&candidate(50.0, 20.0, 30.0)), 73.0);
+
+        // Only profit counts when the other weights are zero
+        let profit_only = WeightedScore::new(PriorityWeights {
+            bad_debt: 0.0,
+            staleness: 0.0,
+            ..Default::default()
+        });
+        assert_eq!(profit_only.score(&candidate(50.0, 20.0, 30.0)), 50.0);
+    }
+
+    #[test]
+    fn test_prioritize_orders_by_score() {
+        let weighted = WeightedScore::new(PriorityWeights::default());
+        let candidates = vec![
+            candidate(10.0, 0.0, 0.0),
+            candidate(5.0, 100.0, 0.0),
+            candidate(10.0, 0.0, 0.0),
+            candidate(-2.0, 0.0, 200.0),
+        ];
+        let order: Vec<PositionAddress> = [1, 3, 0, 2].iter().map(|&i| candidates[i].position).collect();
+
+        let scored = prioritize(&weighted, candidates);
+        assert_eq!(scored.iter().map(|(candidate, _)| candidate.position).collect::<Vec<_>>(), order);
+        assert_eq!(scored.iter().map(|(_, score)| *score).collect::<Vec<_>>(), [105.0, 18.0, 10.0, 10.0]);
+    }
+
+    #[test]
+    fn test_weight_violations() {
+        assert!(PriorityWeights::default().violations().is_empty());
+        let weights = PriorityWeights {
+            bad_debt: -1.0,
+            staleness: f64::NAN,
+            ..Default::default()
+        };
+        let violations: Vec<String> = weights.violations().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            violations,
+            ["bad_debt must be a non-negative number, got -1", "staleness must be a non-negative number, got NaN"]
+        );
+        assert!(weights.validate().is_err());
+    }
+}
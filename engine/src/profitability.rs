@@ -0,0 +1,193 @@
+use crate::{
+    amount::{Amount, AmountExt},
+    position::Position,
+    types::LiquidationConfig,
+};
+use std::fmt;
+
+/// Base fee charged per transaction signature (in lamports)
+pub const BASE_FEE_LAMPORTS: u64 = 5_000;
+
+/// Lamports per SOL
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+
+/// Expected economics of a single liquidation (all amounts in quote currency)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ProfitEstimate {
+    /// Fraction of the position being liquidated (0-1)
+    pub liquidation_fraction: f64,
+    /// Expected liquidator reward
+    pub reward: f64,
+    /// Expected network fees (base fee plus priority fee)
+    pub network_fee: f64,
+    /// Expected slippage when unwinding the liquidated amount
+    pub slippage: f64,
+    /// Reward net of fees and slippage
+    pub net_profit: f64,
+}
+
+impl ProfitEstimate {
+    /// Whether the liquidation clears the minimum profit threshold
+    pub fn is_profitable(&self, min_profit_quote: f64) -> bool {
+        self.net_profit >= min_profit_quote
+    }
+}
+
+impl fmt::Display for ProfitEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reward {:.4}, network fee {:.4}, slippage {:.4}, net {:.4} at {:.0}% of position",
+            self.reward,
+            self.network_fee,
+            self.slippage,
+            self.net_profit,
+            self.liquidation_fraction * 100.0
+        )
+    }
+}
+
+/// Liquidator profitability model derived from the engine configuration
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ProfitModel {
+    /// Liquidator reward as a share of the repaid value (in basis points)
+    pub liquidation_fee_bps: u16,
+    /// Priority fee in microlamports per compute unit
+    pub priority_fee_micro_lamports: u64,
+    /// Compute units requested by a liquidation transaction
+    pub estimated_compute_units: u32,
+    /// Expected slippage (in basis points)
+    pub max_slippage_bps: u16,
+}
+
+impl ProfitModel {
+    /// Build the model from the engine configuration and the priority fee a
+    /// liquidation would pay
+    pub fn from_config(config: &LiquidationConfig, priority_fee_micro_lamports: u64) -> Self {
+        Self {
+            liquidation_fee_bps: config.liquidation_fee_bps,
+            priority_fee_micro_lamports,
+            estimated_compute_units: config.estimated_compute_units,
+            max_slippage_bps: config.max_slippage_bps,
+        }
+    }
+
+    /// Expected reward for liquidating a fraction of a position
+    ///
+    /// Matches the on-chain program, which pays the liquidator a share of the repaid
+    /// amount (10% with the default 1000 bps).
+    pub fn expected_liquidation_reward(&self, position: &Position, price: Amount, liquidation_fraction: f64) -> f64 {
+        position.value(price).as_f64() * liquidation_fraction * self.liquidation_fee_bps as f64 / 10_000.0
+    }
+
+    /// Expected network fee in quote currency given the SOL price
+    pub fn estimated_network_fee(&self, sol_price: f64) -> f64 {
+        let priority_fee_lamports =
+            self.priority_fee_micro_lamports as f64 * self.estimated_compute_units as f64 / 1_000_000.0;
+        (BASE_FEE_LAMPORTS as f64 + priority_fee_lamports) / LAMPORTS_PER_SOL * sol_price
+    }
+
+    /// Expected slippage on the liquidated notional
+    pub fn estimated_slippage(&self, position: &Position, price: Amount, liquidation_fraction: f64) -> f64 {
+        position.value(price).as_f64() * liquidation_fraction * self.max_slippage_bps as f64 / 10_000.0
+    }
+
+    /// Estimate the full economics of a liquidation
+    pub fn estimate(
+        &self,
+        position: &Position,
+        price: Amount,
+        liquidation_fraction: f64,
+        sol_price: f64,
+    ) -> ProfitEstimate {
+        let reward = self.expected_liquidation_reward(position, price, liquidation_fraction);
+        let network_fee = self.estimated_network_fee(sol_price);
+        let slippage = self.estimated_slippage(position, price, liquidation_fraction);
+
+        ProfitEstimate {
+            liquidation_fraction,
+            reward,
+            network_fee,
+            slippage,
+            net_profit: reward - network_fee - slippage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn create_position(size: f64, entry_price: f64) -> Position {
+        Position::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            "BTC/USD",
+            size,
+            entry_price,
+            size * entry_price / 10.0,
+            true,
+        )
+    }
+
+    fn create_model(priority_fee_micro_lamports: u64) -> ProfitModel {
+        ProfitModel {
+            liquidation_fee_bps: 1000,
+            priority_fee_micro_lamports,
+            estimated_compute_units: 200_000,
+            max_slippage_bps: 50,
+        }
+    }
+
+    #[test]
+    fn test_reward_matches_program_formula() {
+        let model = create_model(0);
+        let position = create_position(1.0, 50000.0);
+
+        // 10% of the repaid half
+        assert!((model.expected_liquidation_reward(&position, 50000.0, 0.5) - 2500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_network_fee() {
+        // 5000 + 1_000_000 * 200_000 / 1e6 = 205_000 lamports at $100/SOL
+        let model = create_model(1_000_000);
+        assert!((model.estimated_network_fee(100.0) - 0.0205).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_profitable_large_position() {
+        let model = create_model(1_000);
+        let position = create_position(10.0, 50000.0);
+
+        let estimate = model.estimate(&position, 50000.0, 0.5, 100.0);
+        assert!(estimate.net_profit > 20_000.0);
+        assert!(estimate.is_profitable(1.0));
+    }
+
+    #[test]
+    fn test_unprofitable_dust_position() {
+        // $15 position paying $0.75 at 50%, with a heavy priority fee
+        let model = create_model(10_000_000);
+        let position = create_position(0.0003, 50000.0);
+
+        let estimate = model.estimate(&position, 50000.0, 0.5, 100.0);
+        assert!(estimate.reward < 1.0);
+        assert!(estimate.network_fee > 0.2);
+        assert!(!estimate.is_profitable(1.0));
+    }
+
+    #[test]
+    fn test_breakeven_boundary() {
+        let model = create_model(0);
+        let position = create_position(1.0, 1000.0);
+
+        // reward 100, slippage 5, network fee 5000 lamports at $100/SOL
+        let estimate = model.estimate(&position, 1000.0, 1.0, 100.0);
+        let breakeven = 100.0 - 5.0 - 0.0005;
+        assert!((estimate.net_profit - breakeven).abs() < 1e-9);
+        assert!(estimate.is_profitable(estimate.net_profit));
+        assert!(!estimate.is_profitable(estimate.net_profit + 1e-9));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::address::PositionAddress;
+use std::any::Any;
+use std::collections::HashMap;
+
+/// A position held out of check cycles
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct QuarantinedPosition {
+    /// The position's address
+    pub address: PositionAddress,
+    /// Why it was quarantined, e.g. the message its check panicked with
+    pub reason: String,
+    /// When it was quarantined (Unix timestamp)
+    pub quarantined_at: i64,
+}
+
+/// Positions quarantined, and the checks that panicked since the engine started
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct QuarantineStats {
+    /// Positions held out of check cycles, oldest first
+    pub positions: Vec<QuarantinedPosition>,
+    /// Checks that panicked since the engine started
+    pub total_panics: u64,
+    /// Positions quarantined since the engine started, including those since
+    /// released
+    pub total_quarantined: u64,
+}
+
+/// Positions whose checks panicked or whose values can't be evaluated
+///
+/// Quarantined positions stay monitored but are left out of check cycles
+/// until operators release them.
+#[derive(Debug, Default)]
+pub(crate) struct Quarantine {
+    positions: HashMap<PositionAddress, QuarantinedPosition>,
+    total_panics: u64,
+    total_quarantined: u64,
+}
+
+impl Quarantine {
+    /// Quarantine a position at `now`, returning whether it wasn't already
+    pub(crate) fn quarantine(&mut self, address: PositionAddress, reason: String, now: i64) -> bool {
+        if self.positions.contains_key(&address) {
+            return false;
+        }
+        self.positions.insert(
+            address,
+            QuarantinedPosition {
+                address,
+                reason,
+                quarantined_at: now,
+            },
+        );
+        self.total_quarantined += 1;
+        true
+    }
+
+    /// Count a check that panicked
+    pub(crate) fn record_panic(&mut self) {
+        self.total_panics += 1;
+    }
+
+    /// Whether a position is quarantined
+    pub(crate) fn contains(&self, address: &PositionAddress) -> bool {
+        self.positions.contains_key(address)
+    }
+
+    /// Release a position back into check cycles, returning it if it was
+    /// quarantined
+    pub(crate) fn release(&mut self, address: &PositionAddress) -> Option<QuarantinedPosition> {
+        self.positions.remove(address)
+    }
+
+    /// Release every position, returning how many were quarantined
+    pub(crate) fn clear(&mut self) -> usize {
+        let released = self.positions.len();
+        self.positions.clear();
+        released
+    }
+
+    /// Quarantined positions, oldest first, with the counters
+    pub(crate) fn stats(&self) -> QuarantineStats {
+        let mut positions: Vec<QuarantinedPosition> = self.positions.values().cloned().collect();
+        positions.sort_by_key(|position| (position.quarantined_at, position.address));
+        QuarantineStats {
+            positions,
+            total_panics: self.total_panics,
+            total_quarantined: self.total_quarantined,
+        }
+    }
+}
+
+/// Message a panic was raised with, as far as it's a string
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quarantine_until_released() {
+        let mut quarantine = Quarantine::default();
+        let (first, second) = (PositionAddress::new_unique(), PositionAddress::new_unique());
+        assert!(quarantine.quarantine(second, "panicked".to_string(), 20));
+        assert!(quarantine.quarantine(first, "non-finite entry_price".to_string(), 10));
+        assert!(!quarantine.quarantine(first, "again".to_string(), 30));
+        quarantine.record_panic();
+        assert!(quarantine.contains(&first));
+
+        let stats = quarantine.stats();
+        let held: Vec<(PositionAddress, &str)> =
+            stats.positions.iter().map(|position| (position.address, position.reason.as_str())).collect();
+        assert_eq!(held, [(first, "non-finite entry_price"), (second, "panicked")]);
+        assert_eq!((stats.total_panics, stats.total_quarantined), (1, 2));
+
+        assert_eq!(quarantine.release(&first).unwrap().quarantined_at, 10);
+        assert!(quarantine.release(&first).is_none());
+        assert_eq!(quarantine.clear(), 1);
+        assert!(!quarantine.contains(&second));
+        assert_eq!(quarantine.stats().total_quarantined, 2);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload = std::panic::catch_unwind(|| panic!("bad {}", "position")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "bad position");
+        let payload = std::panic::catch_unwind(|| std::panic::panic_any(7)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "unknown panic");
+    }
+}
@@ -0,0 +1,187 @@
+use crate::error::LiquidationError;
+use crate::rate_limit::RateLimiter;
+use crate::rewards;
+use crate::rpc_pool::RpcPool;
+use anchor_lang::Discriminator;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_sdk::{bs58, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiInstruction, UiMessage, UiParsedInstruction,
+};
+use std::str::FromStr;
+
+/// Most recent transactions of a position searched for a competitor's liquidation
+pub(crate) const FRONT_RUN_SIGNATURE_LIMIT: usize = 20;
+
+/// Characters of a competitor's pubkey lost races are counted by
+pub(crate) const COMPETITOR_PREFIX_LEN: usize = 8;
+
+/// Signatures of the transactions among `signatures` that succeeded at or
+/// after `since`, in the order listed
+///
+/// Transactions without a block time can't be placed in the window, so they're
+/// left out.
+pub(crate) fn recent_successes(
+    signatures: &[RpcConfirmedTransactionStatusWithSignature],
+    since: i64,
+) -> Vec<Signature> {
+    signatures
+        .iter()
+        .filter(|status| status.err.is_none() && status.block_time.is_some_and(|time| time >= since))
+        .filter_map(|status| Signature::from_str(&status.signature).ok())
+        .collect()
+}
+
+/// The liquidator of `position` in a `jsonParsed` transaction, if the
+/// transaction succeeded and liquidated it with the program at `program_id`
+/// on behalf of someone other than `liquidator`
+///
+/// The competitor is the liquidation's signer, which pays the repayment.
+pub(crate) fn competing_liquidator(
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    program_id: &Pubkey,
+    position: &Pubkey,
+    liquidator: &Pubkey,
+) -> Option<Pubkey> {
+    liquidate_instructions(transaction, program_id)
+        .into_iter()
+        .filter(|(liquidated, _)| liquidated == position)
+        .filter_map(|(_, signer)| signer)
+        .find(|signer| signer != liquidator)
+}
+
+/// Positions the top-level `liquidate` instructions of the program at
+/// `program_id` liquidated in a successful `jsonParsed` transaction, in order,
+/// each with the instruction's signer
+pub(crate) fn liquidate_instructions(
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    program_id: &Pubkey,
+) -> Vec<(Pubkey, Option<Pubkey>)> {
+    let Some(meta) = transaction.transaction.meta.as_ref() else {
+        return Vec::new();
+    };
+    if meta.err.is_some() {
+        return Vec::new();
+    }
+    let EncodedTransaction::Json(transaction) = &transaction.transaction.transaction else {
+        return Vec::new();
+    };
+    let UiMessage::Parsed(message) = &transaction.message else {
+        return Vec::new();
+    };
+    let program_id = program_id.to_string();
+    let signers: Vec<&str> = message
+        .account_keys
+        .iter()
+        .filter(|account| account.signer)
+        .map(|account| account.pubkey.as_str())
+        .collect();
+    message
+        .instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(instruction)) => Some(instruction),
+            _ => None,
+        })
+        .filter(|instruction| {
+            instruction.program_id == program_id
+                && bs58::decode(&instruction.data)
+                    .into_vec()
+                    .is_ok_and(|data| data.starts_with(&liquidation_program::instruction::Liquidate::DISCRIMINATOR))
+        })
+        .filter_map(|instruction| {
+            let position = Pubkey::from_str(instruction.accounts.first()?).ok()?;
+            let signer = instruction
+                .accounts
+                .iter()
+                .find(|account| signers.contains(&account.as_str()))
+                .and_then(|account| Pubkey::from_str(account).ok());
+            Some((position, signer))
+        })
+        .collect()
+}
+
+/// Prefix of a competitor's pubkey its lost races are counted under
+pub(crate) fn competitor_prefix(competitor: &Pubkey) -> String {
+    competitor.to_string().chars().take(COMPETITOR_PREFIX_LEN).collect()
+}
+
+/// Find who liquidated `position` first: the signer of the latest liquidation
+/// of it by the program at `program_id`, landed at or after `since` by someone
+/// other than `liquidator`
+pub(crate) async fn find_front_runner(
+    rpc: &RpcPool,
+    rate_limiter: &RateLimiter,
+    program_id: &Pubkey,
+    position: &Pubkey,
+    liquidator: &Pubkey,
+    since: i64,
+) -> Result<Option<Pubkey>, LiquidationError> {
+    let address = *position;
+    let signatures = rpc
+        .call(rate_limiter, move |rpc_client| {
+            rpc_client
+                .get_signatures_for_address_with_config(
+                    &address,
+                    GetConfirmedSignaturesForAddress2Config {
+                        limit: Some(FRONT_RUN_SIGNATURE_LIMIT),
+                        ..Default::default()
+                    },
+                )
+                .map_err(LiquidationError::from)
+        })
+        .await?;
+    for signature in recent_successes(&signatures, since) {
+        let transaction = rewards::fetch_transaction(rpc, rate_limiter, &signature).await?;
+        if let Some(competitor) = competing_liquidator(&transaction, program_id, position, liquidator) {
+            return Ok(Some(competitor));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::PROGRAM_ID;
+
+    const SIGNATURES: &str = include_str!("../fixtures/race/signatures.json");
+    const TRANSACTION: &str = include_str!("../fixtures/race/competitor_tx.json");
+    const POSITION: &str = "A8gURbKkT4ueX6hpjNKKqzvt7VHuTuzfAwnhA4WccZS6";
+    const COMPETITOR: &str = "BaEXKhLPmWZh4myCBpwM2Ex7WbGpAN1AE4Q6YiB9s6bN";
+    /// Block time of our own failed liquidation, the newest transaction listed
+    const FAILED_AT: i64 = 1_759_999_962;
+
+    #[test]
+    fn test_recent_successes() {
+        let signatures: Vec<RpcConfirmedTransactionStatusWithSignature> = serde_json::from_str(SIGNATURES).unwrap();
+        // Our own failed attempt is skipped, and the deposit an hour earlier is
+        // outside the window
+        let recent = recent_successes(&signatures, FAILED_AT - 300);
+        assert_eq!(recent, vec![Signature::from_str(&signatures[1].signature).unwrap()]);
+        assert_eq!(recent_successes(&signatures, FAILED_AT - 7_200).len(), 2);
+        assert!(recent_successes(&signatures, FAILED_AT).is_empty());
+    }
+
+    #[test]
+    fn test_competing_liquidator() {
+        let transaction: EncodedConfirmedTransactionWithStatusMeta = serde_json::from_str(TRANSACTION).unwrap();
+        let position = Pubkey::from_str(POSITION).unwrap();
+        let competitor = Pubkey::from_str(COMPETITOR).unwrap();
+        let ours = Pubkey::new_unique();
+        assert_eq!(competing_liquidator(&transaction, &PROGRAM_ID, &position, &ours), Some(competitor));
+        assert_eq!(competitor_prefix(&competitor), "BaEXKhLP");
+
+        // Our own liquidations, other positions' and other programs' aren't races lost
+        assert_eq!(competing_liquidator(&transaction, &PROGRAM_ID, &position, &competitor), None);
+        assert_eq!(competing_liquidator(&transaction, &PROGRAM_ID, &Pubkey::new_unique(), &ours), None);
+        assert_eq!(competing_liquidator(&transaction, &Pubkey::new_unique(), &position, &ours), None);
+
+        // Nor are liquidations that failed
+        let mut failed: EncodedConfirmedTransactionWithStatusMeta = serde_json::from_str(TRANSACTION).unwrap();
+        failed.transaction.meta.as_mut().unwrap().err =
+            Some(solana_sdk::transaction::TransactionError::AccountInUse);
+        assert_eq!(competing_liquidator(&failed, &PROGRAM_ID, &position, &ours), None);
+    }
+}
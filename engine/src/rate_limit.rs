@@ -0,0 +1,377 @@
+use crate::error::{ConfigViolation, LiquidationError, first_violation};
+use rand::Rng;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tracing::warn;
+
+/// Settings for pacing RPC requests and backing off when the node throttles us
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Requests sent per second on average
+    pub requests_per_second: f64,
+    /// Requests that may be sent back to back before pacing kicks in
+    pub burst: u32,
+    /// Retries of a throttled request before giving up with `RateLimited`
+    pub max_backoff_retries: u32,
+    /// Delay before the first retry of a throttled request (in milliseconds),
+    /// doubled on every further retry
+    pub initial_backoff_ms: u64,
+    /// Longest delay between retries of a throttled request (in milliseconds)
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 10.0,
+            burst: 20,
+            max_backoff_retries: 5,
+            initial_backoff_ms: 250,
+            max_backoff_ms: 8_000,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Check that the limits are usable
+    pub fn validate(&self) -> Result<(), LiquidationError> {
+        first_violation(self.violations())
+    }
+
+    /// Every problem with the settings
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if !self.requests_per_second.is_finite() || self.requests_per_second <= 0.0 {
+            violations.push(ConfigViolation::new(
+                "requests_per_second",
+                format!("must be a positive number, got {}", self.requests_per_second),
+            ));
+        }
+        if self.burst == 0 {
+            violations.push(ConfigViolation::new("burst", "must be at least 1"));
+        }
+        if self.initial_backoff_ms > self.max_backoff_ms {
+            violations.push(ConfigViolation::new(
+                "initial_backoff_ms",
+                format!(
+                    "({}) must not exceed max_backoff_ms ({})",
+                    self.initial_backoff_ms, self.max_backoff_ms
+                ),
+            ));
+        }
+        violations
+    }
+}
+
+/// Whether the RPC node turned a request away for being over its limits
+/// (HTTP 429 or 503)
+pub(crate) fn is_throttled(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Reqwest(e) => matches!(e.status().map(|status| status.as_u16()), Some(429 | 503)),
+        _ => false,
+    }
+}
+
+/// Counts of requests held back by a `RateLimiter`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct RateLimitStats {
+    /// Requests that waited for the token bucket before being sent
+    pub delayed: u64,
+    /// Responses where the RPC node throttled us
+    pub throttled: u64,
+    /// Requests given up on after exhausting the backoff budget
+    pub exhausted: u64,
+}
+
+/// Tokens available for sending requests
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token bucket pacing requests to one RPC endpoint, with exponential backoff
+/// when the endpoint throttles us anyway
+///
+/// Share one limiter (behind an `Arc`) between everything talking to the same
+/// endpoint so their requests count against the same budget.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    bucket: Mutex<Bucket>,
+    delayed: AtomicU64,
+    throttled: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+impl RateLimiter {
+    /// Create a limiter with a full bucket
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                tokens: config.burst as f64,
+                refilled_at: Instant::now(),
+            }),
+            config,
+            delayed: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            exhausted: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait until a request may be sent
+    ///
+    /// Waiters are served in the order they arrived.
+    pub async fn acquire(&self) {
+        let mut bucket = self.bucket.lock().await;
+        self.refill(&mut bucket);
+        if bucket.tokens < 1.0 {
+            self.delayed.fetch_add(1, Ordering::Relaxed);
+            let wait = (1.0 - bucket.tokens) / self.config.requests_per_second;
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+            self.refill(&mut bucket);
+        }
+        bucket.tokens = (bucket.tokens - 1.0).max(0.0);
+    }
+
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.config.requests_per_second).min(self.config.burst as f64);
+        bucket.refilled_at = now;
+    }
+
+    /// Delay before retry number `retry` (0 for the first) of a throttled request
+    ///
+    /// Between half and all of the exponential delay, so clients throttled
+    /// together don't retry in lockstep.
+    fn backoff_delay(&self, retry: u32) -> Duration {
+        let exponential = self
+            .config
+            .initial_backoff_ms
+            .saturating_mul(1u64.checked_shl(retry).unwrap_or(u64::MAX))
+            .min(self.config.max_backoff_ms);
+        let jittered = rand::thread_rng().gen_range(exponential / 2..=exponential);
+        Duration::from_millis(jittered)
+    }
+
+    /// Send a request once the bucket allows it, retrying with backoff while it
+    /// fails with `RateLimited`
+    ///
+    /// Gives up with `RateLimited` once the backoff budget is spent.
+    pub async fn call<T, F, Fut>(&self, mut request: F) -> Result<T, LiquidationError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, LiquidationError>>,
+    {
+        let mut retry = 0;
+        loop {
+            self.acquire().await;
+            match request().await {
+                Err(LiquidationError::RateLimited(msg)) => {
+                    self.throttled.fetch_add(1, Ordering::Relaxed);
+                    if retry >= self.config.max_backoff_retries {
+                        self.exhausted.fetch_add(1, Ordering::Relaxed);
+                        return Err(LiquidationError::RateLimited(format!("{} after {} retries", msg, retry)));
+                    }
+                    let delay = self.backoff_delay(retry);
+                    warn!("RPC request throttled ({}), retrying in {:?}", msg, delay);
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Run a blocking RPC client call off the runtime through `call`
+    ///
+    /// Client errors converted with `LiquidationError::from` are classified as
+    /// throttled or not by `is_throttled`.
+    pub async fn call_blocking<T, F>(&self, rpc_client: &Arc<RpcClient>, request: F) -> Result<T, LiquidationError>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> Result<T, LiquidationError> + Send + Sync + 'static,
+    {
+        let request = Arc::new(request);
+        self.call(|| {
+            let rpc_client = rpc_client.clone();
+            let request = request.clone();
+            async move {
+                tokio::task::spawn_blocking(move || request(&rpc_client))
+                    .await
+                    .map_err(|e| LiquidationError::Other(e.to_string()))?
+            }
+        })
+        .await
+    }
+
+    /// Counts of delayed and throttled requests so far
+    pub fn stats(&self) -> RateLimitStats {
+        RateLimitStats {
+            delayed: self.delayed.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(RateLimitConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    /// Error the RPC client returns for an HTTP error status
+    fn http_error(status: u16) -> ClientError {
+        let response = http::Response::builder().status(status).body("").unwrap();
+        reqwest::Response::from(response).error_for_status().unwrap_err().into()
+    }
+
+    fn create_limiter(requests_per_second: f64, burst: u32) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            requests_per_second,
+            burst,
+            max_backoff_retries: 3,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 300,
+        })
+    }
+
+    #[test]
+    fn test_is_throttled() {
+        assert!(is_throttled(&http_error(429)));
+        assert!(is_throttled(&http_error(503)));
+        assert!(!is_throttled(&http_error(500)));
+        assert!(!is_throttled(&ClientErrorKind::Custom("429".to_string()).into()));
+        assert!(matches!(
+            LiquidationError::from(http_error(429)),
+            LiquidationError::RateLimited(_)
+        ));
+        assert!(matches!(
+            LiquidationError::from(http_error(500)),
+            LiquidationError::RpcUnavailable(_)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_paced_after_burst() {
+        let limiter = create_limiter(10.0, 3);
+        let start = Instant::now();
+
+        let mut sent_at = Vec::new();
+        for _ in 0..6 {
+            limiter.acquire().await;
+            sent_at.push(start.elapsed().as_millis());
+        }
+        // The burst goes out at once, then one request every 100ms
+        assert_eq!(sent_at, vec![0, 0, 0, 100, 200, 300]);
+        assert_eq!(limiter.stats().delayed, 3);
+
+        // An idle second refills the bucket
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let idle_until = start.elapsed();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed(), idle_until);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_request_backs_off_and_recovers() {
+        let limiter = create_limiter(1_000.0, 10);
+        let attempts = AtomicU32::new(0);
+        let start = Instant::now();
+
+        let result = limiter
+            .call(|| async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(http_error(429).into()),
+                    1 => Err(http_error(503).into()),
+                    _ => Ok(42),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        // Jittered between half and all of 100ms + 200ms
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(150) && elapsed <= Duration::from_millis(300), "{:?}", elapsed);
+        assert_eq!(
+            limiter.stats(),
+            RateLimitStats {
+                delayed: 0,
+                throttled: 2,
+                exhausted: 0
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limited_after_backoff_budget() {
+        let limiter = create_limiter(1_000.0, 10);
+        let attempts = AtomicU32::new(0);
+        let start = Instant::now();
+
+        let result: Result<(), _> = limiter
+            .call(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(http_error(429).into())
+            })
+            .await;
+        assert!(matches!(result, Err(LiquidationError::RateLimited(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+        // Delays of 100ms, 200ms and 300ms (capped from 400ms), each jittered
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(300) && elapsed <= Duration::from_millis(600), "{:?}", elapsed);
+        assert_eq!(limiter.stats().throttled, 4);
+        assert_eq!(limiter.stats().exhausted, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_other_errors_not_retried() {
+        let limiter = create_limiter(1_000.0, 10);
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<(), _> = limiter
+            .call(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(http_error(500).into())
+            })
+            .await;
+        assert!(matches!(result, Err(LiquidationError::RpcUnavailable(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(limiter.stats(), RateLimitStats::default());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(RateLimitConfig::default().validate().is_ok());
+        let config = RateLimitConfig {
+            requests_per_second: 0.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        let config = RateLimitConfig {
+            burst: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        let config = RateLimitConfig {
+            initial_backoff_ms: 10_000,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! Hot reload of the configuration file
+//!
+//! The file is polled for changes and reread on SIGHUP. Each new version is
+//! validated and staged for the next check cycle through
+//! [`LiquidationEngine::reload_config`], so a broken edit leaves the running
+//! configuration in place.
+
+use crate::{error::LiquidationError, liquidation::LiquidationEngine, types::LiquidationConfig};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{error, info};
+
+/// How often the configuration file is checked for changes
+pub(crate) const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A configuration file reloaded into a running engine when it changes
+pub struct ConfigWatcher<F> {
+    path: PathBuf,
+    load: F,
+    poll_interval: Duration,
+    /// Contents last seen, `None` while the file can't be read
+    contents: Option<String>,
+}
+
+impl<F> ConfigWatcher<F>
+where
+    F: Fn() -> Result<LiquidationConfig, LiquidationError>,
+{
+    /// Watch the file at `path`, building each new configuration with `load`,
+    /// which may apply overrides on top of the file
+    ///
+    /// The file as it is now counts as already loaded.
+    pub fn new(path: impl Into<PathBuf>, load: F) -> Self {
+        let path = path.into();
+        let contents = std::fs::read_to_string(&path).ok();
+        Self {
+            path,
+            load,
+            poll_interval: CONFIG_POLL_INTERVAL,
+            contents,
+        }
+    }
+
+    /// Check the file for changes this often (default: [`CONFIG_POLL_INTERVAL`])
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Whether the file's contents changed since they were last seen
+    fn changed(&mut self) -> bool {
+        let contents = std::fs::read_to_string(&self.path).ok();
+        if contents == self.contents {
+            return false;
+        }
+        self.contents = contents;
+        true
+    }
+
+    /// Load the configuration and stage it in `engine`, keeping the current
+    /// one if it's invalid
+    pub fn reload(&self, engine: &LiquidationEngine) {
+        match (self.load)().and_then(|config| engine.reload_config(&config)) {
+            Ok(changes) => info!(
+                "Reloaded {}: {} changes staged for the next cycle",
+                self.path.display(),
+                changes.len()
+            ),
+            Err(e) => error!(
+                "Rejected configuration from {}, keeping the current one: {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Reload whenever the file changes or the process receives SIGHUP, until
+    /// the engine shuts down
+    pub async fn run(mut self, engine: Arc<LiquidationEngine>) {
+        let hangup = Arc::new(Notify::new());
+        #[cfg(unix)]
+        forward_hangups(hangup.clone());
+        let shutdown = engine.shutdown_token();
+        let mut interval = tokio::time::interval(self.poll_interval);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = hangup.notified() => {
+                    self.changed();
+                    self.reload(&engine);
+                }
+                _ = interval.tick() => {
+                    if self.changed() {
+                        self.reload(&engine);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Wake `hangup` whenever the process receives SIGHUP
+#[cfg(unix)]
+fn forward_hangups(hangup: Arc<Notify>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::hangup()) {
+        Ok(mut hangups) => {
+            tokio::spawn(async move {
+                while hangups.recv().await.is_some() {
+                    hangup.notify_one();
+                }
+            });
+        }
+        Err(e) => tracing::warn!("Not reloading the configuration on SIGHUP: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::MockOracle;
+    use crate::position::Position;
+    use crate::rate_limit::RateLimiter;
+    use crate::types::LiquidationResult;
+    use solana_client::rpc_client::RpcClient;
+    use solana_sdk::pubkey::Pubkey;
+
+    #[tokio::test]
+    async fn test_rewritten_file_applies_next_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "maintenance_margin = 0.05\n").unwrap();
+        let load = {
+            let path = path.clone();
+            move || LiquidationConfig::from_toml(&std::fs::read_to_string(&path)?)
+        };
+
+        let oracle = MockOracle::new();
+        oracle.set_price("BTC/USD", 50000.0).await;
+        oracle.set_price("SOL/USD", 100.0).await;
+        let engine = Arc::new(LiquidationEngine::new(
+            Arc::new(RpcClient::new("https://api.devnet.solana.com")),
+            Arc::new(oracle),
+            load().unwrap(),
+            Arc::new(RateLimiter::default()),
+        ));
+        // 3,000 of equity on 50,000 of value clears a 5% margin but not 10%
+        let position = Position::new(Pubkey::new_unique(), Pubkey::new_unique(), "BTC/USD", 1.0, 52000.0, 5000.0, true);
+        engine.add_position(position.clone()).await.unwrap();
+        let watcher = ConfigWatcher::new(&path, load).with_poll_interval(Duration::from_millis(10));
+        tokio::spawn(watcher.run(engine.clone()));
+        assert!(engine.check_positions().await.unwrap().is_empty());
+
+        std::fs::write(&path, "maintenance_margin = 0.1\n").unwrap();
+        let results = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let results = engine.check_positions().await.unwrap();
+                if !results.is_empty() {
+                    return results;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(engine.config().maintenance_margin, 0.1);
+        assert!(matches!(results[..], [LiquidationResult::Success { position: address, .. }] if address == position.address));
+
+        // An invalid edit leaves the reloaded configuration in place
+        std::fs::write(&path, "maintenance_margin = 2.0\n").unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        engine.check_positions().await.unwrap();
+        assert_eq!(engine.config().maintenance_margin, 0.1);
+        engine.shutdown();
+    }
+}
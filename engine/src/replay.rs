@@ -0,0 +1,332 @@
+use crate::{
+    amount::{Amount, AmountExt},
+    clock::Clock,
+    error::LiquidationError,
+    oracle::{OracleProvider, PriceData},
+    types::LiquidationResult,
+};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Header of a replay price file
+pub(crate) const REPLAY_CSV_HEADER: &str = "timestamp,symbol,price,confidence";
+
+/// A recorded price of a symbol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PricePoint {
+    /// Unix timestamp the price was published at
+    pub timestamp: i64,
+    /// The price
+    pub price: Amount,
+    /// The confidence interval of the price
+    pub confidence: f64,
+}
+
+/// Oracle replaying recorded prices by a clock
+///
+/// Each symbol reports the last price recorded at or before the clock's time.
+#[derive(Debug, Clone)]
+pub struct ReplayOracle {
+    clock: Arc<dyn Clock>,
+    series: Arc<HashMap<String, Vec<PricePoint>>>,
+}
+
+impl ReplayOracle {
+    /// An oracle replaying each symbol's recorded prices, in any order, by `clock`
+    pub fn new(mut series: HashMap<String, Vec<PricePoint>>, clock: Arc<dyn Clock>) -> Self {
+        for points in series.values_mut() {
+            points.sort_by_key(|point| point.timestamp);
+        }
+        Self {
+            clock,
+            series: Arc::new(series),
+        }
+    }
+
+    /// Parse recorded prices from CSV with a [`REPLAY_CSV_HEADER`] header
+    pub fn from_csv(csv: &str, clock: Arc<dyn Clock>) -> Result<Self, LiquidationError> {
+        let series = parse_csv(csv).map_err(|e| LiquidationError::ConfigError(format!("Invalid replay prices: {}", e)))?;
+        Ok(Self::new(series, clock))
+    }
+
+    /// Load recorded prices from the CSV file at `path`
+    pub fn load_csv(path: impl AsRef<Path>, clock: Arc<dyn Clock>) -> Result<Self, LiquidationError> {
+        let path = path.as_ref();
+        let invalid = |e: String| LiquidationError::ConfigError(format!("Invalid replay prices {}: {}", path.display(), e));
+        let csv = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        Ok(Self::new(parse_csv(&csv).map_err(invalid)?, clock))
+    }
+
+    /// The clock prices are replayed by
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Earliest and latest recorded timestamps, or `None` without prices
+    pub fn time_range(&self) -> Option<(i64, i64)> {
+        let timestamps = self.series.values().flat_map(|points| [points.first(), points.last()]).flatten();
+        let (first, last) = timestamps.fold((i64::MAX, i64::MIN), |(first, last), point| {
+            (first.min(point.timestamp), last.max(point.timestamp))
+        });
+        (first <= last).then_some((first, last))
+    }
+
+    /// The price of a symbol at the clock's time
+    fn point(&self, symbol: &str) -> Result<PricePoint, LiquidationError> {
+        let now = self.clock.now_ts();
+        let points = self
+            .series
+            .get(symbol)
+            .ok_or_else(|| LiquidationError::OracleError(format!("No recorded prices for {}", symbol)))?;
+        let recorded = points.partition_point(|point| point.timestamp <= now);
+        recorded
+            .checked_sub(1)
+            .map(|index| points[index])
+            .ok_or_else(|| LiquidationError::OracleError(format!("No price for {} recorded by {}", symbol, now)))
+    }
+}
+
+#[async_trait]
+impl OracleProvider for ReplayOracle {
+    async fn get_price(&self, symbol: &str) -> Result<Amount, LiquidationError> {
+        Ok(self.point(symbol)?.price)
+    }
+
+    // Recordings have no EMA, so it mirrors the price
+    async fn get_price_data(&self, symbol: &str) -> Result<PriceData, LiquidationError> {
+        let point = self.point(symbol)?;
+        Ok(PriceData {
+            price: point.price,
+            confidence: point.confidence,
+            ema_price: point.price.as_f64(),
+            ema_confidence: point.confidence,
+            publish_time: point.timestamp,
+            publish_slot: None,
+        })
+    }
+
+    async fn last_update_time(&self, symbol: &str) -> Result<u64, LiquidationError> {
+        Ok(self.point(symbol)?.timestamp.max(0) as u64)
+    }
+}
+
+fn parse_csv(csv: &str) -> Result<HashMap<String, Vec<PricePoint>>, String> {
+    let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    match lines.next() {
+        Some((_, header)) if header.trim() == REPLAY_CSV_HEADER => {}
+        _ => return Err(format!("expected a {} header", REPLAY_CSV_HEADER)),
+    }
+
+    let mut series: HashMap<String, Vec<PricePoint>> = HashMap::new();
+    for (index, line) in lines {
+        let (symbol, point) = parse_row(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        series.entry(symbol).or_default().push(point);
+    }
+    Ok(series)
+}
+
+fn parse_row(line: &str) -> Result<(String, PricePoint), String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [timestamp, symbol, price, confidence] = fields[..] else {
+        return Err(format!("expected 4 fields, got {}", fields.len()));
+    };
+    let timestamp: i64 = timestamp.parse().map_err(|_| format!("invalid timestamp {:?}", timestamp))?;
+    if symbol.is_empty() {
+        return Err("missing symbol".to_string());
+    }
+    let price: Amount = price.parse().map_err(|_| format!("invalid price {:?}", price))?;
+    if !(price.is_finite() && price > Amount::ZERO) {
+        return Err(format!("price must be positive, got {}", price));
+    }
+    let confidence: f64 = confidence.parse().map_err(|_| format!("invalid confidence {:?}", confidence))?;
+    if !(confidence.is_finite() && confidence >= 0.0) {
+        return Err(format!("confidence must be non-negative, got {}", confidence));
+    }
+    Ok((symbol.to_string(), PricePoint { timestamp, price, confidence }))
+}
+
+/// A result of a replay, with the time of the step it came from
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReplayedResult {
+    /// Timestamp of the step
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub result: LiquidationResult,
+}
+
+/// Largest fall of a symbol's price from its running peak during a replay
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Drawdown {
+    pub symbol: String,
+    pub peak_timestamp: i64,
+    pub peak_price: f64,
+    pub trough_timestamp: i64,
+    pub trough_price: f64,
+    /// Fall from peak to trough, as a fraction of the peak
+    pub fall: f64,
+}
+
+/// What a replay liquidated, and when
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReplayReport {
+    /// Timestamp of the first step
+    pub from_ts: i64,
+    /// Timestamp no step went past
+    pub to_ts: i64,
+    /// Virtual time between steps (in seconds)
+    pub step_secs: u64,
+    /// Steps replayed
+    pub steps: usize,
+    /// Successful liquidations
+    pub liquidations: usize,
+    /// Liquidations that failed
+    pub failures: usize,
+    /// Liquidatable positions that were skipped
+    pub skipped: usize,
+    /// Notional liquidated, at the prices of the steps liquidating it (in quote currency)
+    pub notional: f64,
+    /// Largest price fall of the replay, if any price fell
+    pub worst_drawdown: Option<Drawdown>,
+    /// Every result, in the order of the steps
+    pub results: Vec<ReplayedResult>,
+    /// Highest price of each symbol so far, with when it was seen
+    #[serde(skip)]
+    peaks: BTreeMap<String, (i64, f64)>,
+}
+
+impl ReplayReport {
+    /// An empty report of a replay from `from_ts` to `to_ts` in steps of `step_secs`
+    pub fn new(from_ts: i64, to_ts: i64, step_secs: u64) -> Self {
+        Self {
+            from_ts,
+            to_ts,
+            step_secs,
+            steps: 0,
+            liquidations: 0,
+            failures: 0,
+            skipped: 0,
+            notional: 0.0,
+            worst_drawdown: None,
+            results: Vec::new(),
+            peaks: BTreeMap::new(),
+        }
+    }
+
+    /// Record the prices seen at a step
+    pub fn record_step(&mut self, timestamp: i64, prices: &HashMap<String, Amount>) {
+        self.steps += 1;
+        // In symbol order, so ties resolve the same way every run
+        let prices: BTreeMap<&String, f64> = prices.iter().map(|(symbol, price)| (symbol, price.as_f64())).collect();
+        for (symbol, price) in prices {
+            let (peak_timestamp, peak_price) = *self
+                .peaks
+                .entry(symbol.clone())
+                .and_modify(|peak| {
+                    if price > peak.1 {
+                        *peak = (timestamp, price);
+                    }
+                })
+                .or_insert((timestamp, price));
+            let fall = 1.0 - price / peak_price;
+            if fall > self.worst_drawdown.as_ref().map_or(0.0, |worst| worst.fall) {
+                self.worst_drawdown = Some(Drawdown {
+                    symbol: symbol.clone(),
+                    peak_timestamp,
+                    peak_price,
+                    trough_timestamp: timestamp,
+                    trough_price: price,
+                    fall,
+                });
+            }
+        }
+    }
+
+    /// Record a result of the step at `timestamp` and the notional it liquidated
+    pub fn record_result(&mut self, timestamp: i64, result: LiquidationResult, notional: f64) {
+        match &result {
+            LiquidationResult::Success { .. } => {
+                self.liquidations += 1;
+                self.notional += notional;
+            }
+            LiquidationResult::Failure { .. } => self.failures += 1,
+            LiquidationResult::Skipped { .. } => self.skipped += 1,
+        }
+        self.results.push(ReplayedResult { timestamp, result });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    const PRICES: &str = include_str!("../fixtures/replay/prices.csv");
+
+    #[tokio::test]
+    async fn test_prices_follow_the_clock() {
+        let clock = ManualClock::new(0);
+        let oracle = ReplayOracle::from_csv(PRICES, Arc::new(clock.clone())).unwrap();
+        assert_eq!(oracle.time_range(), Some((1_700_000_000, 1_700_003_600)));
+
+        // Nothing is recorded before the first price
+        assert!(oracle.get_price("BTC/USD").await.is_err());
+
+        clock.set(1_700_000_000);
+        assert_eq!(oracle.get_price("BTC/USD").await.unwrap(), 60000.0);
+        // Between recordings the last one holds
+        clock.set(1_700_000_299);
+        let data = oracle.get_price_data("BTC/USD").await.unwrap();
+        assert_eq!((data.price, data.confidence, data.publish_time), (60000.0, 30.0, 1_700_000_000));
+        clock.set(1_700_000_300);
+        assert_eq!(oracle.get_price("SOL/USD").await.unwrap(), 99.0);
+        assert!(oracle.get_price("ETH/USD").await.is_err());
+    }
+
+    #[test]
+    fn test_invalid_csv_rejected() {
+        let error = |csv: &str| match ReplayOracle::from_csv(csv, Arc::new(ManualClock::new(0))) {
+            Err(LiquidationError::ConfigError(message)) => message,
+            other => panic!("expected a configuration error, got {:?}", other),
+        };
+        assert_eq!(
+            error("time,symbol,price\n"),
+            "Invalid replay prices: expected a timestamp,symbol,price,confidence header"
+        );
+        let header = format!("{}\n", REPLAY_CSV_HEADER);
+        assert_eq!(
+            error(&format!("{}1700000000,BTC/USD,60000,30\n\n1700000300,BTC/USD,abc,30\n", header)),
+            "Invalid replay prices: line 4: invalid price \"abc\""
+        );
+        assert_eq!(
+            error(&format!("{}1700000000,BTC/USD,-1,30\n", header)),
+            "Invalid replay prices: line 2: price must be positive, got -1"
+        );
+        assert_eq!(
+            error(&format!("{}1700000000,BTC/USD,60000\n", header)),
+            "Invalid replay prices: line 2: expected 4 fields, got 3"
+        );
+    }
+
+    #[test]
+    fn test_worst_drawdown() {
+        let mut report = ReplayReport::new(0, 20, 10);
+        for (timestamp, btc, sol) in [(0, 100.0, 10.0), (10, 120.0, 9.0), (20, 90.0, 9.5)] {
+            let prices = HashMap::from([("BTC/USD".to_string(), btc), ("SOL/USD".to_string(), sol)]);
+            report.record_step(timestamp, &prices);
+        }
+        assert_eq!(report.steps, 3);
+        assert_eq!(
+            report.worst_drawdown,
+            Some(Drawdown {
+                symbol: "BTC/USD".to_string(),
+                peak_timestamp: 10,
+                peak_price: 120.0,
+                trough_timestamp: 20,
+                trough_price: 90.0,
+                fall: 0.25,
+            })
+        );
+    }
+}
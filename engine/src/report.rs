@@ -0,0 +1,332 @@
+//! Report of the liquidations a dry run would have made
+//!
+//! Rows are appended to one file per UTC day, named after the configured path
+//! with the day inserted before the extension (`dry_run.csv` is written as
+//! `dry_run-2024-03-01.csv`). The extension picks the format: `.csv` for CSV
+//! with a [`REPORT_CSV_HEADER`] header, `.json` or `.jsonl` for JSON lines. As
+//! with the store, the engine only queues rows; a dedicated task writes them.
+
+use crate::address::{OwnerAddress, PositionAddress};
+use crate::error::LiquidationError;
+use solana_sdk::pubkey::Pubkey;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Default capacity of the report writer's queue, beyond which rows are
+/// dropped and counted rather than waited for
+pub const DEFAULT_REPORT_QUEUE_CAPACITY: usize = 1024;
+
+/// Header of a CSV report
+pub const REPORT_CSV_HEADER: &str = "timestamp,position,owner,symbol,price,margin_ratio,amount,reward,bad_debt";
+
+/// A liquidation a dry run would have made
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DryRunLiquidation {
+    /// When the liquidation would have been made
+    pub timestamp: i64,
+    /// The position that would have been liquidated
+    pub position: PositionAddress,
+    /// The position's owner
+    pub owner: OwnerAddress,
+    /// The trading pair symbol
+    pub symbol: String,
+    /// Price the position would have been liquidated at
+    pub price: f64,
+    /// Margin ratio at that price (collateral / position value)
+    pub margin_ratio: f64,
+    /// Amount that would have been liquidated (in base currency)
+    pub amount: f64,
+    /// Expected liquidator reward (in quote currency)
+    pub reward: f64,
+    /// Expected bad debt (in quote currency)
+    pub bad_debt: f64,
+}
+
+impl DryRunLiquidation {
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.timestamp,
+            self.position,
+            self.owner,
+            self.symbol,
+            self.price,
+            self.margin_ratio,
+            self.amount,
+            self.reward,
+            self.bad_debt
+        )
+    }
+
+    fn from_csv(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [timestamp, position, owner, symbol, price, margin_ratio, amount, reward, bad_debt] = fields[..] else {
+            return Err(format!("expected 9 fields, got {}", fields.len()));
+        };
+        let pubkey = |value: &str| Pubkey::from_str(value).map_err(|_| format!("invalid pubkey {:?}", value));
+        let number = |value: &str| value.parse::<f64>().map_err(|_| format!("invalid number {:?}", value));
+        Ok(Self {
+            timestamp: timestamp.parse().map_err(|_| format!("invalid timestamp {:?}", timestamp))?,
+            position: pubkey(position)?.into(),
+            owner: pubkey(owner)?.into(),
+            symbol: symbol.to_string(),
+            price: number(price)?,
+            margin_ratio: number(margin_ratio)?,
+            amount: number(amount)?,
+            reward: number(reward)?,
+            bad_debt: number(bad_debt)?,
+        })
+    }
+}
+
+/// Format of a report file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReportFormat {
+    /// Comma separated values with a header
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+impl ReportFormat {
+    /// The format chosen by a path's extension
+    pub fn from_path(path: &Path) -> Result<Self, LiquidationError> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => Ok(Self::Csv),
+            Some("json" | "jsonl") => Ok(Self::JsonLines),
+            _ => Err(LiquidationError::ConfigError(format!(
+                "dry run report {} must end in .csv, .json or .jsonl",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// File the rows of a UTC day are written to: `path` with the day inserted
+/// before its extension
+pub(crate) fn report_path_for_day(path: &Path, day: &str) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let mut name = format!("{}-{}", stem, day);
+    if let Some(extension) = path.extension() {
+        name = format!("{}.{}", name, extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/// UTC day of a timestamp, formatted as `YYYY-MM-DD`
+pub(crate) fn day_of(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+/// Read the rows of a report file, in the format its extension names
+pub fn read_report(path: impl AsRef<Path>) -> Result<Vec<DryRunLiquidation>, LiquidationError> {
+    let path = path.as_ref();
+    let format = ReportFormat::from_path(path)?;
+    let invalid = |e: String| LiquidationError::Other(format!("Invalid dry run report {}: {}", path.display(), e));
+    let contents = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+    let mut lines = contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    if format == ReportFormat::Csv {
+        match lines.next() {
+            Some((_, header)) if header.trim() == REPORT_CSV_HEADER => {}
+            None => return Ok(Vec::new()),
+            _ => return Err(invalid(format!("expected a {} header", REPORT_CSV_HEADER))),
+        }
+    }
+    lines
+        .map(|(index, line)| {
+            let row = match format {
+                ReportFormat::Csv => DryRunLiquidation::from_csv(line),
+                ReportFormat::JsonLines => serde_json::from_str(line).map_err(|e| e.to_string()),
+            };
+            row.map_err(|e| invalid(format!("line {}: {}", index + 1, e)))
+        })
+        .collect()
+}
+
+/// Appends rows to the file of their day, switching files as days change
+struct ReportFile {
+    path: PathBuf,
+    format: ReportFormat,
+    /// Day being written and its file
+    current: Option<(String, BufWriter<File>)>,
+}
+
+impl ReportFile {
+    fn write(&mut self, row: &DryRunLiquidation) -> std::io::Result<()> {
+        let day = day_of(row.timestamp);
+        if self.current.as_ref().is_none_or(|(current, _)| *current != day) {
+            self.flush()?;
+            let path = report_path_for_day(&self.path, &day);
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let mut writer = BufWriter::new(file);
+            if self.format == ReportFormat::Csv && std::fs::metadata(&path)?.len() == 0 {
+                writeln!(writer, "{}", REPORT_CSV_HEADER)?;
+            }
+            self.current = Some((day, writer));
+        }
+        let Some((_, writer)) = &mut self.current else {
+            unreachable!("a file is open for the row's day");
+        };
+        match self.format {
+            ReportFormat::Csv => writeln!(writer, "{}", row.to_csv()),
+            ReportFormat::JsonLines => writeln!(writer, "{}", serde_json::to_string(row)?),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.current {
+            Some((_, writer)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+enum ReportCommand {
+    Row(Box<DryRunLiquidation>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle for queueing rows to the dry run report's writer task
+#[derive(Debug, Clone)]
+pub struct ReportWriter {
+    tx: mpsc::Sender<ReportCommand>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl ReportWriter {
+    /// Start a writer task appending rows to the daily files of `path`, with a
+    /// queue of `capacity` rows
+    ///
+    /// Rows are flushed whenever the queue runs empty. The task finishes, flushing
+    /// what's left, once every `ReportWriter` handle has been dropped.
+    pub fn spawn(path: impl Into<PathBuf>, capacity: usize) -> Result<(Self, JoinHandle<()>), LiquidationError> {
+        let path = path.into();
+        let format = ReportFormat::from_path(&path)?;
+        let (tx, mut rx) = mpsc::channel::<ReportCommand>(capacity);
+        let handle = tokio::task::spawn_blocking(move || {
+            let mut file = ReportFile { path, format, current: None };
+            while let Some(command) = rx.blocking_recv() {
+                match command {
+                    ReportCommand::Row(row) => {
+                        if let Err(e) = file.write(&row) {
+                            error!("Failed to report dry run liquidation of {}: {}", row.position, e);
+                        }
+                    }
+                    ReportCommand::Flush(done) => {
+                        if let Err(e) = file.flush() {
+                            error!("Failed to flush dry run report: {}", e);
+                        }
+                        let _ = done.send(());
+                    }
+                }
+                if rx.is_empty()
+                    && let Err(e) = file.flush()
+                {
+                    error!("Failed to flush dry run report: {}", e);
+                }
+            }
+            if let Err(e) = file.flush() {
+                error!("Failed to flush dry run report: {}", e);
+            }
+        });
+        let dropped = Arc::new(AtomicU64::new(0));
+        Ok((Self { tx, dropped }, handle))
+    }
+
+    /// Queue a row without waiting, dropping and counting it if the queue is full
+    pub fn record(&self, row: DryRunLiquidation) {
+        if let Err(e) = self.tx.try_send(ReportCommand::Row(Box::new(row))) {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(dropped, "Dropping dry run report row, queue unavailable: {}", e);
+        }
+    }
+
+    /// Rows dropped from a full or closed queue so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Wait until every row queued so far has been written out
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.tx.send(ReportCommand::Flush(done)).await.is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_row(timestamp: i64) -> DryRunLiquidation {
+        DryRunLiquidation {
+            timestamp,
+            position: PositionAddress::new_unique(),
+            owner: OwnerAddress::new_unique(),
+            symbol: "BTC/USD".to_string(),
+            price: 50_000.0,
+            margin_ratio: 2.5,
+            amount: 0.5,
+            reward: 2_500.0,
+            bad_debt: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_format_and_daily_paths() {
+        assert_eq!(ReportFormat::from_path(Path::new("report.csv")).unwrap(), ReportFormat::Csv);
+        assert_eq!(ReportFormat::from_path(Path::new("report.jsonl")).unwrap(), ReportFormat::JsonLines);
+        assert!(ReportFormat::from_path(Path::new("report.txt")).is_err());
+        assert_eq!(
+            report_path_for_day(Path::new("reports/dry_run.csv"), "2023-11-15"),
+            Path::new("reports/dry_run-2023-11-15.csv")
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_json_lines_rotate_daily() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dry_run.jsonl");
+        let (writer, handle) = ReportWriter::spawn(&path, DEFAULT_REPORT_QUEUE_CAPACITY).unwrap();
+        let day = 1_700_006_400; // 2023-11-15T00:00:00Z
+        let rows = [create_row(day + 10), create_row(day + 20), create_row(day + 86_400)];
+        for row in &rows {
+            writer.record(row.clone());
+        }
+        writer.flush().await;
+        assert_eq!(read_report(report_path_for_day(&path, "2023-11-15")).unwrap(), rows[..2]);
+
+        // Dropping the last handle flushes what's left
+        writer.record(create_row(day + 86_500));
+        drop(writer);
+        handle.await.unwrap();
+        let next_day = read_report(report_path_for_day(&path, "2023-11-16")).unwrap();
+        assert_eq!(next_day.len(), 2);
+        assert_eq!(next_day[0], rows[2]);
+    }
+
+    #[test]
+    fn test_read_rejects_malformed_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dry_run.csv");
+        std::fs::write(&path, format!("{}\n{}\n", REPORT_CSV_HEADER, create_row(1).to_csv())).unwrap();
+        assert_eq!(read_report(&path).unwrap().len(), 1);
+
+        std::fs::write(&path, format!("{}\n1,not-a-pubkey\n", REPORT_CSV_HEADER)).unwrap();
+        let error = read_report(&path).unwrap_err().to_string();
+        assert!(error.contains("line 2: expected 9 fields"), "{}", error);
+        std::fs::write(&path, "position,amount\n").unwrap();
+        assert!(read_report(&path).is_err());
+    }
+}
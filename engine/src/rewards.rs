@@ -0,0 +1,441 @@
+use crate::error::LiquidationError;
+use crate::profitability::BASE_FEE_LAMPORTS;
+use crate::rate_limit::RateLimiter;
+use crate::rpc_pool::RpcPool;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::native_token::lamports_to_sol;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiTransactionEncoding, UiTransactionTokenBalance,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+use tracing::debug;
+
+/// Times a confirmed liquidation's transaction is fetched before giving up,
+/// as RPC nodes index transactions shortly after confirming them
+pub(crate) const TRANSACTION_FETCH_ATTEMPTS: u32 = 5;
+
+/// Delay between fetches of a transaction the RPC node hasn't indexed yet
+pub(crate) const TRANSACTION_FETCH_DELAY: Duration = Duration::from_secs(1);
+
+/// What a confirmed liquidation transaction moved in and out of the
+/// liquidator's accounts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct LiquidationReceipt {
+    /// Collateral the liquidator's token accounts received (in base currency)
+    pub collateral_received: f64,
+    /// Debt the liquidator's token accounts repaid (in quote currency)
+    pub debt_repaid: f64,
+    /// Token-2022 transfer fees withheld from the transaction's transfers of
+    /// the collateral's mint (in base currency), which the liquidator bears on
+    /// the seizure
+    pub collateral_fees: f64,
+    /// Token-2022 transfer fees withheld from the transaction's transfers of
+    /// the debt's mint (in quote currency): the repayment's, and the insurance
+    /// fund's cover of any bad debt
+    pub debt_fees: f64,
+    /// Base fee paid for the transaction's signatures (in lamports)
+    pub base_fee_lamports: u64,
+    /// Priority fee paid on top of the base fee (in lamports)
+    pub priority_fee_lamports: u64,
+}
+
+impl LiquidationReceipt {
+    /// Read a liquidation's receipt from its `jsonParsed` transaction
+    ///
+    /// The program takes the repayment from the liquidator's debt token account
+    /// and pays the seized collateral into its collateral token account, so
+    /// token balances owned by the liquidator that fell are the repayment and
+    /// those that rose the collateral. A Token-2022 transfer fee is withheld
+    /// from what the receiving account is credited, so those are what was
+    /// received net of fees, and the fees are what a mint's balances fell by
+    /// in total.
+    pub fn from_transaction(
+        transaction: &EncodedConfirmedTransactionWithStatusMeta,
+        liquidator: &Pubkey,
+    ) -> Result<Self, LiquidationError> {
+        let meta = transaction
+            .transaction
+            .meta
+            .as_ref()
+            .ok_or_else(|| LiquidationError::Other("transaction has no status meta".to_string()))?;
+        if let Some(err) = &meta.err {
+            return Err(LiquidationError::Other(format!("transaction failed: {}", err)));
+        }
+        let signatures = match &transaction.transaction.transaction {
+            EncodedTransaction::Json(transaction) => transaction.signatures.len() as u64,
+            _ => 1,
+        };
+        let base_fee_lamports = BASE_FEE_LAMPORTS * signatures.max(1);
+
+        let owner = liquidator.to_string();
+        // Whether each balance is the liquidator's, its mint and its amount, by
+        // account
+        let balances = |balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>| -> HashMap<u8, (bool, String, f64)> {
+            let balances: &[UiTransactionTokenBalance] = match balances {
+                OptionSerializer::Some(balances) => balances,
+                _ => &[],
+            };
+            balances
+                .iter()
+                .map(|balance| {
+                    let ours = matches!(&balance.owner, OptionSerializer::Some(balance_owner) if *balance_owner == owner);
+                    (balance.account_index, (ours, balance.mint.clone(), token_amount(balance)))
+                })
+                .collect()
+        };
+        let pre = balances(&meta.pre_token_balances);
+        let post = balances(&meta.post_token_balances);
+
+        // Accounts the transaction creates have no balance before it, and ones it
+        // closes none after
+        let mut collateral_received = 0.0;
+        let mut debt_repaid = 0.0;
+        let (mut collateral_mints, mut debt_mints) = (HashSet::new(), HashSet::new());
+        let mut mint_changes: HashMap<&str, f64> = HashMap::new();
+        for index in pre.keys().chain(post.keys().filter(|index| !pre.contains_key(index))) {
+            let (ours, mint, _) = pre.get(index).or_else(|| post.get(index)).expect("balance of a listed account");
+            let amount = |balances: &HashMap<u8, (bool, String, f64)>| balances.get(index).map(|balance| balance.2);
+            let change = amount(&post).unwrap_or_default() - amount(&pre).unwrap_or_default();
+            *mint_changes.entry(mint).or_default() += change;
+            if !ours {
+                continue;
+            }
+            if change > 0.0 {
+                collateral_received += change;
+                collateral_mints.insert(mint.as_str());
+            } else {
+                debt_repaid -= change;
+                debt_mints.insert(mint.as_str());
+            }
+        }
+        let fees = |mints: &HashSet<&str>| -> f64 {
+            let change: f64 = mints.iter().map(|mint| mint_changes[mint]).sum();
+            (-change).max(0.0)
+        };
+
+        Ok(Self {
+            collateral_received,
+            debt_repaid,
+            collateral_fees: fees(&collateral_mints),
+            debt_fees: fees(&debt_mints),
+            base_fee_lamports,
+            priority_fee_lamports: meta.fee.saturating_sub(base_fee_lamports),
+        })
+    }
+
+    /// Reward earned with collateral priced at `price`: the collateral's value
+    /// over the repaid debt (in quote currency)
+    pub fn reward(&self, price: f64) -> f64 {
+        self.collateral_received * price - self.debt_repaid
+    }
+}
+
+/// Exact amount of a token balance, from its raw amount rather than the
+/// rounded `uiAmount`
+fn token_amount(balance: &UiTransactionTokenBalance) -> f64 {
+    let amount = &balance.ui_token_amount;
+    match amount.amount.parse::<u64>() {
+        Ok(raw) => raw as f64 / 10f64.powi(amount.decimals as i32),
+        Err(_) => amount.ui_amount.unwrap_or_default(),
+    }
+}
+
+/// Fetch a confirmed transaction with `jsonParsed` encoding, retrying briefly
+/// while the RPC node hasn't indexed it yet
+pub(crate) async fn fetch_transaction(
+    rpc: &RpcPool,
+    rate_limiter: &RateLimiter,
+    signature: &Signature,
+) -> Result<EncodedConfirmedTransactionWithStatusMeta, LiquidationError> {
+    let mut attempt = 1;
+    loop {
+        let signature = *signature;
+        let result = rpc
+            .call(rate_limiter, move |rpc_client| {
+                rpc_client
+                    .get_transaction_with_config(
+                        &signature,
+                        RpcTransactionConfig {
+                            encoding: Some(UiTransactionEncoding::JsonParsed),
+                            commitment: Some(CommitmentConfig::confirmed()),
+                            max_supported_transaction_version: Some(0),
+                        },
+                    )
+                    .map_err(LiquidationError::from)
+            })
+            .await;
+        match result {
+            Err(e) if attempt < TRANSACTION_FETCH_ATTEMPTS => {
+                debug!("Transaction {} not available yet (attempt {}): {}", signature, attempt, e);
+            }
+            result => return result,
+        }
+        tokio::time::sleep(TRANSACTION_FETCH_DELAY).await;
+        attempt += 1;
+    }
+}
+
+/// Reward and costs of one successful liquidation
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RewardRecord {
+    /// When the liquidation happened
+    pub timestamp: i64,
+    /// The trading pair symbol of the liquidated position
+    pub symbol: String,
+    /// Reward received (in quote currency)
+    pub reward: f64,
+    /// Base fee paid (in lamports)
+    pub base_fee_lamports: u64,
+    /// Priority fee paid (in lamports)
+    pub priority_fee_lamports: u64,
+    /// Base and priority fees (in quote currency)
+    pub network_fee: f64,
+    /// Whether the figures are a dry run's estimates rather than read from the
+    /// confirmed transaction
+    pub simulated: bool,
+    /// Key that signed the liquidation, unknown for dry runs
+    pub liquidator: Option<Pubkey>,
+}
+
+impl RewardRecord {
+    /// Record a liquidation's reward and fees, pricing the fees at `sol_price`
+    pub fn new(
+        timestamp: i64,
+        symbol: impl Into<String>,
+        reward: f64,
+        base_fee_lamports: u64,
+        priority_fee_lamports: u64,
+        sol_price: f64,
+        simulated: bool,
+    ) -> Self {
+        Self {
+            timestamp,
+            symbol: symbol.into(),
+            reward,
+            base_fee_lamports,
+            priority_fee_lamports,
+            network_fee: lamports_to_sol(base_fee_lamports + priority_fee_lamports) * sol_price,
+            simulated,
+            liquidator: None,
+        }
+    }
+
+    /// Attribute the liquidation to the key that signed it
+    pub fn with_liquidator(mut self, liquidator: Pubkey) -> Self {
+        self.liquidator = Some(liquidator);
+        self
+    }
+
+    /// Reward net of network fees (in quote currency)
+    pub fn net_pnl(&self) -> f64 {
+        self.reward - self.network_fee
+    }
+}
+
+/// Rewards and costs summed over a set of liquidations
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PnlTotals {
+    /// Number of successful liquidations
+    pub liquidations: u64,
+    /// How many of them were simulated
+    pub simulated: u64,
+    /// Rewards received (in quote currency)
+    pub reward: f64,
+    /// Base fees paid (in lamports)
+    pub base_fee_lamports: u64,
+    /// Priority fees paid (in lamports)
+    pub priority_fee_lamports: u64,
+    /// Base and priority fees paid (in quote currency)
+    pub network_fee: f64,
+    /// Rewards net of network fees (in quote currency)
+    pub net_pnl: f64,
+    /// Liquidations refused because a competitor liquidated the position first
+    #[serde(default)]
+    pub lost_races: u64,
+}
+
+impl PnlTotals {
+    fn add(&mut self, record: &RewardRecord) {
+        self.liquidations += 1;
+        self.simulated += record.simulated as u64;
+        self.reward += record.reward;
+        self.base_fee_lamports += record.base_fee_lamports;
+        self.priority_fee_lamports += record.priority_fee_lamports;
+        self.network_fee += record.network_fee;
+        self.net_pnl += record.net_pnl();
+    }
+}
+
+/// The liquidator's PnL, in total, per symbol, per UTC day and per key
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PnlSummary {
+    /// Every liquidation recorded
+    pub total: PnlTotals,
+    /// Liquidations per symbol
+    pub by_symbol: BTreeMap<String, PnlTotals>,
+    /// Liquidations per day, keyed `YYYY-MM-DD`
+    pub by_day: BTreeMap<String, PnlTotals>,
+    /// Races lost per competitor, keyed by the first
+    /// [`COMPETITOR_PREFIX_LEN`](crate::race::COMPETITOR_PREFIX_LEN) characters
+    /// of its pubkey
+    #[serde(default)]
+    pub lost_races: BTreeMap<String, u64>,
+    /// Submitted liquidations per key that signed them
+    #[serde(default)]
+    pub by_liquidator: BTreeMap<String, PnlTotals>,
+}
+
+/// Running record of the liquidator's rewards and network fees
+#[derive(Debug, Default)]
+pub(crate) struct RewardTracker {
+    summary: PnlSummary,
+}
+
+impl RewardTracker {
+    /// Create an empty tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a successful liquidation to the totals
+    pub fn record(&mut self, record: &RewardRecord) {
+        self.summary.total.add(record);
+        self.summary.by_symbol.entry(record.symbol.clone()).or_default().add(record);
+        self.summary
+            .by_day
+            .entry(crate::report::day_of(record.timestamp))
+            .or_default()
+            .add(record);
+        if let Some(liquidator) = record.liquidator {
+            self.summary.by_liquidator.entry(liquidator.to_string()).or_default().add(record);
+        }
+    }
+
+    /// Add a liquidation of a position in `symbol` that `competitor` beat us to
+    pub fn record_lost_race(&mut self, timestamp: i64, symbol: &str, competitor: &Pubkey) {
+        self.summary.total.lost_races += 1;
+        self.summary.by_symbol.entry(symbol.to_string()).or_default().lost_races += 1;
+        self.summary.by_day.entry(crate::report::day_of(timestamp)).or_default().lost_races += 1;
+        *self
+            .summary
+            .lost_races
+            .entry(crate::race::competitor_prefix(competitor))
+            .or_default() += 1;
+    }
+
+    /// Totals recorded so far
+    pub fn summary(&self) -> PnlSummary {
+        self.summary.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const TRANSACTION: &str = include_str!("../fixtures/rewards/liquidation_tx.json");
+    /// The same liquidation with Token-2022 mints withholding a 1% transfer fee
+    const TOKEN_2022_TRANSACTION: &str = include_str!("../fixtures/rewards/liquidation_tx_token_2022.json");
+    const LIQUIDATOR: &str = "66nvbsJ2HVXa2xwFZRa6sKD39gU4c7DkMRVSNzPSWrUg";
+
+    #[test]
+    fn test_receipt_from_parsed_transaction() {
+        let transaction: EncodedConfirmedTransactionWithStatusMeta = serde_json::from_str(TRANSACTION).unwrap();
+        let liquidator = Pubkey::from_str(LIQUIDATOR).unwrap();
+        let receipt = LiquidationReceipt::from_transaction(&transaction, &liquidator).unwrap();
+
+        // Repaid 22,500 USDC for 0.55 BTC, ignoring the vaults' side of the transfers
+        assert!((receipt.collateral_received - 0.55).abs() < 1e-12);
+        assert!((receipt.debt_repaid - 22_500.0).abs() < 1e-9);
+        assert!((receipt.reward(45_000.0) - 2_250.0).abs() < 1e-6);
+        assert!(receipt.collateral_fees.abs() < 1e-12 && receipt.debt_fees.abs() < 1e-9);
+        // One signature, and 10,000 microlamports per CU over 200,000 CU
+        assert_eq!(receipt.base_fee_lamports, 5_000);
+        assert_eq!(receipt.priority_fee_lamports, 2_000);
+
+        // 7000 lamports at $150/SOL
+        let record = RewardRecord::new(1_727_000_000, "BTC/USD", receipt.reward(45_000.0), 5_000, 2_000, 150.0, false);
+        assert!((record.network_fee - 0.00105).abs() < 1e-12);
+        assert!((record.net_pnl() - (2_250.0 - 0.00105)).abs() < 1e-6);
+
+        // Another keeper's transaction moved none of our tokens
+        let receipt = LiquidationReceipt::from_transaction(&transaction, &Pubkey::new_unique()).unwrap();
+        assert_eq!(receipt.reward(45_000.0), 0.0);
+    }
+
+    #[test]
+    fn test_receipt_net_of_transfer_fees() {
+        let transaction: EncodedConfirmedTransactionWithStatusMeta = serde_json::from_str(TOKEN_2022_TRANSACTION).unwrap();
+        let liquidator = Pubkey::from_str(LIQUIDATOR).unwrap();
+        let receipt = LiquidationReceipt::from_transaction(&transaction, &liquidator).unwrap();
+
+        // The liquidator paid all 22,500 but received 0.5445 of the 0.55 BTC
+        // seized, and the vault 22,275 of the repayment
+        assert!((receipt.collateral_received - 0.5445).abs() < 1e-12);
+        assert!((receipt.debt_repaid - 22_500.0).abs() < 1e-9);
+        assert!((receipt.collateral_fees - 0.0055).abs() < 1e-12);
+        assert!((receipt.debt_fees - 225.0).abs() < 1e-9);
+        // The fee on the seizure comes out of the reward
+        assert!((receipt.reward(45_000.0) - 2_002.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_totals_per_symbol_and_day() {
+        let mut tracker = RewardTracker::new();
+        tracker.record(&RewardRecord::new(0, "BTC/USD", 100.0, 5_000, 0, 100.0, false));
+        tracker.record(&RewardRecord::new(3_600, "ETH/USD", 50.0, 5_000, 5_000, 100.0, true));
+        tracker.record(&RewardRecord::new(86_400, "BTC/USD", 10.0, 5_000, 0, 100.0, false));
+
+        let summary = tracker.summary();
+        assert_eq!(summary.total.liquidations, 3);
+        assert_eq!(summary.total.simulated, 1);
+        assert_eq!(summary.total.priority_fee_lamports, 5_000);
+        assert!((summary.total.net_pnl - (160.0 - 0.002)).abs() < 1e-9);
+        assert_eq!(summary.by_symbol["BTC/USD"].liquidations, 2);
+        assert!((summary.by_symbol["ETH/USD"].network_fee - 0.001).abs() < 1e-12);
+        assert_eq!(summary.by_day["1970-01-01"].liquidations, 2);
+        assert_eq!(summary.by_day["1970-01-02"].reward, 10.0);
+    }
+
+    #[test]
+    fn test_totals_per_liquidator() {
+        let mut tracker = RewardTracker::new();
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        tracker.record(&RewardRecord::new(0, "BTC/USD", 100.0, 5_000, 0, 100.0, false).with_liquidator(first));
+        tracker.record(&RewardRecord::new(60, "BTC/USD", 20.0, 5_000, 0, 100.0, false).with_liquidator(second));
+        tracker.record(&RewardRecord::new(120, "ETH/USD", 30.0, 5_000, 0, 100.0, false).with_liquidator(first));
+        tracker.record(&RewardRecord::new(180, "ETH/USD", 5.0, 5_000, 0, 100.0, true));
+
+        let summary = tracker.summary();
+        assert_eq!(summary.total.liquidations, 4);
+        assert_eq!(summary.by_liquidator.len(), 2);
+        assert_eq!(summary.by_liquidator[&first.to_string()].liquidations, 2);
+        assert_eq!(summary.by_liquidator[&first.to_string()].reward, 130.0);
+        assert_eq!(summary.by_liquidator[&second.to_string()].reward, 20.0);
+    }
+
+    #[test]
+    fn test_lost_races_per_competitor() {
+        let mut tracker = RewardTracker::new();
+        let (first, second) = (Pubkey::new_from_array([1; 32]), Pubkey::new_from_array([2; 32]));
+        tracker.record(&RewardRecord::new(0, "BTC/USD", 100.0, 5_000, 0, 100.0, false));
+        tracker.record_lost_race(60, "BTC/USD", &first);
+        tracker.record_lost_race(120, "ETH/USD", &first);
+        tracker.record_lost_race(86_400, "BTC/USD", &second);
+
+        let summary = tracker.summary();
+        assert_eq!((summary.total.liquidations, summary.total.lost_races), (1, 3));
+        assert_eq!(summary.by_symbol["BTC/USD"].lost_races, 2);
+        assert_eq!(summary.by_day["1970-01-01"].lost_races, 2);
+        // Lost races count no liquidation of their own
+        assert_eq!(summary.by_symbol["ETH/USD"].liquidations, 0);
+        assert_eq!(summary.lost_races[&first.to_string()[..8]], 2);
+        assert_eq!(summary.lost_races[&second.to_string()[..8]], 1);
+    }
+}
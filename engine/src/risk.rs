@@ -0,0 +1,197 @@
+use crate::address::{OwnerAddress, PositionAddress};
+use crate::amount::{Amount, AmountExt};
+use crate::position::Position;
+use std::collections::{BTreeMap, HashMap};
+
+/// Risk aggregated across all positions held by one owner
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct AccountRisk {
+    /// The owner's address
+    pub owner: OwnerAddress,
+    /// Notional of the owner's net exposure, summed across symbols (in quote currency)
+    pub total_notional: f64,
+    /// Margin across priced positions, net of unsettled funding (in quote currency)
+    pub total_margin: f64,
+    /// Unrealized PnL across priced positions (in quote currency)
+    pub unrealized_pnl: f64,
+    /// Blended margin ratio: (margin + PnL) / net notional
+    pub margin_ratio: f64,
+    /// Addresses of the owner's positions
+    pub positions: Vec<PositionAddress>,
+    /// Symbols without a price, whose positions are excluded from the totals
+    pub unpriced_symbols: Vec<String>,
+}
+
+impl AccountRisk {
+    /// Whether some of the owner's positions couldn't be priced
+    pub fn is_partially_priced(&self) -> bool {
+        !self.unpriced_symbols.is_empty()
+    }
+}
+
+/// Aggregate positions by owner using the given prices
+///
+/// Long and short exposure in the same symbol is netted before computing notional, so a
+/// fully hedged account has zero notional and an infinite margin ratio. Accounts are
+/// returned worst (lowest margin ratio) first.
+pub(crate) fn aggregate_account_risk(positions: &[Position], prices: &HashMap<String, Amount>) -> Vec<AccountRisk> {
+    let mut by_owner: BTreeMap<OwnerAddress, Vec<&Position>> = BTreeMap::new();
+    for position in positions {
+        by_owner.entry(position.owner).or_default().push(position);
+    }
+
+    let mut accounts: Vec<AccountRisk> = by_owner
+        .into_iter()
+        .map(|(owner, positions)| {
+            let mut net_sizes: BTreeMap<&str, f64> = BTreeMap::new();
+            let mut total_margin = 0.0;
+            let mut unrealized_pnl = 0.0;
+            let mut unpriced_symbols = Vec::new();
+
+            for position in &positions {
+                let Some(&price) = prices.get(&position.symbol) else {
+                    if !unpriced_symbols.contains(&position.symbol) {
+                        unpriced_symbols.push(position.symbol.clone());
+                    }
+                    continue;
+                };
+
+                let signed_size = if position.is_long { position.size } else { -position.size };
+                *net_sizes.entry(position.symbol.as_str()).or_default() += signed_size.as_f64();
+                total_margin += position.effective_margin().as_f64();
+                unrealized_pnl += position.unrealized_pnl(price).as_f64();
+            }
+
+            let total_notional: f64 = net_sizes
+                .iter()
+                .map(|(symbol, net_size)| net_size.abs() * prices[*symbol].as_f64())
+                .sum();
+            let margin_ratio = if total_notional == 0.0 {
+                f64::INFINITY
+            } else {
+                (total_margin + unrealized_pnl) / total_notional
+            };
+
+            AccountRisk {
+                owner,
+                total_notional,
+                total_margin,
+                unrealized_pnl,
+                margin_ratio,
+                positions: positions.iter().map(|position| position.address).collect(),
+                unpriced_symbols,
+            }
+        })
+        .collect();
+
+    accounts.sort_by(|a, b| a.margin_ratio.total_cmp(&b.margin_ratio));
+    accounts
+}
+
+/// Order positions so that those belonging to the riskiest accounts come first
+///
+/// The relative order of positions within an account is preserved.
+pub(crate) fn sort_by_account_risk(positions: &mut [Position], accounts: &[AccountRisk]) {
+    let rank: HashMap<OwnerAddress, usize> = accounts
+        .iter()
+        .enumerate()
+        .map(|(rank, account)| (account.owner, rank))
+        .collect();
+    positions.sort_by_key(|position| rank.get(&position.owner).copied().unwrap_or(usize::MAX));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_position(
+        owner: OwnerAddress,
+        symbol: &str,
+        size: f64,
+        entry_price: f64,
+        margin: f64,
+        is_long: bool,
+    ) -> Position {
+        Position::new(PositionAddress::new_unique(), owner, symbol, size, entry_price, margin, is_long)
+    }
+
+    fn prices() -> HashMap<String, f64> {
+        HashMap::from([("BTC/USD".to_string(), 50000.0), ("ETH/USD".to_string(), 3000.0)])
+    }
+
+    #[test]
+    fn test_blended_margin_ratio() {
+        let owner = OwnerAddress::new_unique();
+        let positions = vec![
+            create_position(owner, "BTC/USD", 1.0, 50000.0, 5000.0, true),
+            create_position(owner, "ETH/USD", 10.0, 3300.0, 3000.0, true),
+        ];
+
+        let accounts = aggregate_account_risk(&positions, &prices());
+        assert_eq!(accounts.len(), 1);
+        let account = &accounts[0];
+        assert_eq!(account.total_notional, 80000.0);
+        assert_eq!(account.total_margin, 8000.0);
+        assert_eq!(account.unrealized_pnl, -3000.0);
+        assert_eq!(account.margin_ratio, 5000.0 / 80000.0);
+        assert!(!account.is_partially_priced());
+    }
+
+    #[test]
+    fn test_long_and_short_are_netted() {
+        let owner = OwnerAddress::new_unique();
+        let positions = vec![
+            create_position(owner, "BTC/USD", 2.0, 50000.0, 10000.0, true),
+            create_position(owner, "BTC/USD", 1.5, 50000.0, 7500.0, false),
+        ];
+
+        let accounts = aggregate_account_risk(&positions, &prices());
+        assert_eq!(accounts[0].total_notional, 25000.0);
+        assert_eq!(accounts[0].margin_ratio, 17500.0 / 25000.0);
+
+        // A fully hedged account carries no notional
+        let hedged = vec![
+            create_position(owner, "BTC/USD", 1.0, 50000.0, 5000.0, true),
+            create_position(owner, "BTC/USD", 1.0, 50000.0, 5000.0, false),
+        ];
+        let accounts = aggregate_account_risk(&hedged, &prices());
+        assert_eq!(accounts[0].total_notional, 0.0);
+        assert_eq!(accounts[0].margin_ratio, f64::INFINITY);
+    }
+
+    #[test]
+    fn test_missing_prices_mark_account_partially_priced() {
+        let owner = OwnerAddress::new_unique();
+        let positions = vec![
+            create_position(owner, "BTC/USD", 1.0, 50000.0, 5000.0, true),
+            create_position(owner, "SOL/USD", 100.0, 100.0, 1000.0, true),
+        ];
+
+        let accounts = aggregate_account_risk(&positions, &prices());
+        assert_eq!(accounts.len(), 1);
+        assert!(accounts[0].is_partially_priced());
+        assert_eq!(accounts[0].unpriced_symbols, vec!["SOL/USD".to_string()]);
+        assert_eq!(accounts[0].positions.len(), 2);
+        assert_eq!(accounts[0].total_notional, 50000.0);
+    }
+
+    #[test]
+    fn test_sort_by_account_risk() {
+        let safe = OwnerAddress::new_unique();
+        let risky = OwnerAddress::new_unique();
+        let mut positions = vec![
+            create_position(safe, "BTC/USD", 1.0, 50000.0, 25000.0, true),
+            create_position(risky, "BTC/USD", 1.0, 52000.0, 4000.0, true),
+            create_position(safe, "ETH/USD", 1.0, 3000.0, 1500.0, true),
+            create_position(risky, "ETH/USD", 1.0, 3000.0, 300.0, true),
+        ];
+        let first_safe = positions[0].address;
+
+        let accounts = aggregate_account_risk(&positions, &prices());
+        assert_eq!(accounts[0].owner, risky);
+        sort_by_account_risk(&mut positions, &accounts);
+
+        assert!(positions[..2].iter().all(|position| position.owner == risky));
+        assert_eq!(positions[2].address, first_safe);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::error::ConfigViolation;
+
+/// Share of a grid step within which a value already counts as on the grid,
+/// absorbing float error such as `0.3 / 0.1 == 2.9999999999999996`; values
+/// many steps from zero are allowed a few floats' width of their step count
+/// on top
+const GRID_TOLERANCE: f64 = 1e-9;
+
+/// Which way a value off its grid is moved onto it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RoundingDirection {
+    /// To the grid point at or below the value
+    Down,
+    /// To the grid point at or above the value
+    Up,
+}
+
+impl RoundingDirection {
+    /// Direction a liquidation price of a position on the given side is
+    /// rounded: against the trader, towards where the position is liquidated
+    /// sooner
+    ///
+    /// A long is liquidated as the price falls, so its price rounds up; a short
+    /// as it rises, so its price rounds down. The rounded price is never
+    /// reached later than the exact one.
+    pub fn against_trader(is_long: bool) -> Self {
+        if is_long { Self::Up } else { Self::Down }
+    }
+}
+
+/// Round `price` to a multiple of `tick` in `direction`
+///
+/// Prices already on the grid, give or take float error, stay on that grid
+/// point. Non-positive ticks and non-finite prices leave the price as it is.
+pub(crate) fn round_price_to_tick(price: f64, tick: f64, direction: RoundingDirection) -> f64 {
+    round_to_grid(price, tick, direction)
+}
+
+/// Round `size` down to a multiple of `step`, so the rounded size never
+/// exceeds it
+///
+/// Non-positive steps and non-finite sizes leave the size as it is.
+pub(crate) fn round_size_to_step(size: f64, step: f64) -> f64 {
+    round_to_grid(size, step, RoundingDirection::Down)
+}
+
+/// `amount` of a token as the whole number of base units a mint with
+/// `decimals` counts it in, rounded down so it never exceeds the amount
+///
+/// Negative and non-finite amounts are 0; amounts beyond `u64::MAX` base units
+/// saturate.
+pub(crate) fn to_base_units(amount: f64, decimals: u8) -> u64 {
+    let units = round_to_grid(amount * 10f64.powi(i32::from(decimals)), 1.0, RoundingDirection::Down);
+    if units.is_finite() && units > 0.0 { units as u64 } else { 0 }
+}
+
+fn round_to_grid(value: f64, step: f64, direction: RoundingDirection) -> f64 {
+    if !(step > 0.0 && step.is_finite() && value.is_finite()) {
+        return value;
+    }
+    let steps = value / step;
+    let nearest = steps.round();
+    if (steps - nearest).abs() <= GRID_TOLERANCE + 4.0 * f64::EPSILON * nearest.abs() {
+        // Already on the grid; the grid point may still lie a float's width past
+        // the value, which is then kept instead
+        let point = grid_point(nearest, step);
+        return match direction {
+            RoundingDirection::Down => point.min(value),
+            RoundingDirection::Up => point.max(value),
+        };
+    }
+    match direction {
+        RoundingDirection::Down => grid_point(steps.floor(), step),
+        RoundingDirection::Up => grid_point(steps.ceil(), step),
+    }
+}
+
+/// The grid point `steps` steps of `step` from zero, dividing by the step's
+/// inverse where that's a whole number so e.g. 3 steps of 0.1 are exactly 0.3
+fn grid_point(steps: f64, step: f64) -> f64 {
+    let inverse = (1.0 / step).round();
+    if inverse >= 1.0 && (inverse * step - 1.0).abs() <= GRID_TOLERANCE {
+        steps / inverse
+    } else {
+        steps * step
+    }
+}
+
+/// The grid an exchange quotes a symbol on
+///
+/// Any of the sizes may be unset, leaving that quantity unrounded or unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SymbolSpec {
+    /// Price increment (in quote currency)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tick_size: Option<f64>,
+    /// Size increment (in base units)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_size: Option<f64>,
+    /// Smallest notional the exchange accepts (in quote currency)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_notional: Option<f64>,
+}
+
+impl SymbolSpec {
+    /// Liquidation price of a position on the given side, rounded to the tick
+    /// against the trader (see [`RoundingDirection::against_trader`])
+    pub fn liquidation_price(&self, price: f64, is_long: bool) -> f64 {
+        match self.tick_size {
+            Some(tick) => round_price_to_tick(price, tick, RoundingDirection::against_trader(is_long)),
+            None => price,
+        }
+    }
+
+    /// Amount of a liquidation of up to `size` at `price`: `size` rounded down
+    /// to the step, or `None` if nothing that size reaches the minimum notional
+    pub fn liquidation_amount(&self, size: f64, price: f64) -> Option<f64> {
+        let amount = self.step_size.map_or(size, |step| round_size_to_step(size, step));
+        let min_notional = self.min_notional.unwrap_or_default();
+        (amount > 0.0 && amount * price >= min_notional).then_some(amount)
+    }
+
+    /// Every size that isn't positive
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let sizes = [
+            ("tick_size", self.tick_size),
+            ("step_size", self.step_size),
+            ("min_notional", self.min_notional),
+        ];
+        sizes
+            .into_iter()
+            .filter_map(|(field, size)| Some((field, size?)))
+            .filter(|(_, size)| !(size.is_finite() && *size > 0.0))
+            .map(|(field, size)| ConfigViolation::new(field, format!("must be positive, got {}", size)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    const TICKS: [f64; 8] = [0.5, 0.01, 0.25, 0.1, 0.001, 5.0, 1e-8, 0.3];
+
+    /// Whether `value` is the closest float to a grid point, give or take the
+    /// error of multiplying it out
+    fn on_grid(value: f64, step: f64) -> bool {
+        let nearest = (value / step).round() * step;
+        (value - nearest).abs() <= 4.0 * f64::EPSILON * value.abs().max(step)
+    }
+
+    #[test]
+    fn test_trigger_prices_round_against_trader() {
+        // A long is liquidated sooner at the higher price, a short at the lower
+        assert_eq!(round_price_to_tick(56893.234817, 0.5, RoundingDirection::against_trader(true)), 56893.5);
+        assert_eq!(round_price_to_tick(56893.234817, 0.5, RoundingDirection::against_trader(false)), 56893.0);
+        assert_eq!(round_price_to_tick(56893.234817, 0.01, RoundingDirection::Up), 56893.24);
+        assert_eq!(round_price_to_tick(56893.234817, 0.01, RoundingDirection::Down), 56893.23);
+        // Prices on the grid stay put, whatever the float error dividing them
+        assert_eq!(round_price_to_tick(0.3, 0.1, RoundingDirection::Up), 0.3);
+        assert_eq!(round_price_to_tick(0.3, 0.1, RoundingDirection::Down), 0.3);
+        assert_eq!(round_price_to_tick(100.0, 0.0, RoundingDirection::Up), 100.0);
+
+        let spec = SymbolSpec {
+            tick_size: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(spec.liquidation_price(54271.36, true), 54271.5);
+        assert_eq!(spec.liquidation_price(65671.64, false), 65671.5);
+    }
+
+    #[test]
+    fn test_amounts_round_down_to_step() {
+        assert_eq!(round_size_to_step(0.615, 0.1), 0.6);
+        assert_eq!(round_size_to_step(0.3, 0.1), 0.3);
+        assert_eq!(round_size_to_step(0.0999, 0.1), 0.0);
+        assert_eq!(round_size_to_step(1234.5, 5.0), 1230.0);
+
+        assert_eq!(to_base_units(1.2345678919, 6), 1_234_567);
+        assert_eq!(to_base_units(0.3, 1), 3);
+        assert_eq!(to_base_units(-1.0, 6), 0);
+        assert_eq!(to_base_units(f64::NAN, 6), 0);
+
+        // Liquidations that round to nothing or below the minimum notional are off
+        let spec = SymbolSpec {
+            step_size: Some(0.1),
+            min_notional: Some(1000.0),
+            ..Default::default()
+        };
+        assert_eq!(spec.liquidation_amount(0.615, 50000.0), Some(0.6));
+        assert_eq!(spec.liquidation_amount(0.0999, 50000.0), None);
+        assert_eq!(spec.liquidation_amount(0.15, 5000.0), None);
+        assert_eq!(SymbolSpec::default().liquidation_amount(0.615, 50000.0), Some(0.615));
+    }
+
+    #[test]
+    fn test_rounded_values_on_grid() {
+        let mut rng = StdRng::seed_from_u64(604);
+        for _ in 0..20_000 {
+            let tick = TICKS[rng.gen_range(0..TICKS.len())];
+            // Half the values are grid points, give or take the float error of
+            // multiplying them out
+            let on_tick = rng.gen_bool(0.5);
+            let value = if on_tick {
+                rng.gen_range(0..10_000_000) as f64 * tick
+            } else {
+                rng.gen_range(0.0..100_000.0)
+            };
+            let down = round_price_to_tick(value, tick, RoundingDirection::Down);
+            let up = round_price_to_tick(value, tick, RoundingDirection::Up);
+            assert!(on_grid(down, tick) && on_grid(up, tick), "{} on {}: {}, {}", value, tick, down, up);
+            assert!(down <= value && value <= up, "{} on {}: {}, {}", value, tick, down, up);
+            assert!(up - down <= tick + 4.0 * f64::EPSILON * up, "{} on {}: {}, {}", value, tick, down, up);
+            if on_tick {
+                assert!(up - down <= 4.0 * f64::EPSILON * up, "{} on {}: {}, {}", value, tick, down, up);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rounded_amounts_never_exceed_target() {
+        let mut rng = StdRng::seed_from_u64(604);
+        for _ in 0..20_000 {
+            let step = TICKS[rng.gen_range(0..TICKS.len())];
+            // Targets on the grid, a step's multiple times a fraction, included
+            let target = rng.gen_range(0..1_000) as f64 * step * rng.gen_range(0.0..1.0);
+            let amount = round_size_to_step(target, step);
+            assert!(on_grid(amount, step) && amount <= target, "{} on {}: {}", target, step, amount);
+            assert!(target - amount < step + 4.0 * f64::EPSILON * target, "{} on {}: {}", target, step, amount);
+
+            let decimals = rng.gen_range(0..=9);
+            let units = to_base_units(target, decimals);
+            assert!(units as f64 <= target * 10f64.powi(i32::from(decimals)) * (1.0 + f64::EPSILON));
+        }
+    }
+
+    #[test]
+    fn test_spec_violations() {
+        assert!(SymbolSpec::default().violations().is_empty());
+        let spec = SymbolSpec {
+            tick_size: Some(0.0),
+            step_size: Some(0.001),
+            min_notional: Some(f64::NAN),
+        };
+        let violations: Vec<String> = spec.violations().iter().map(ToString::to_string).collect();
+        assert_eq!(violations, ["tick_size must be positive, got 0", "min_notional must be positive, got NaN"]);
+    }
+}
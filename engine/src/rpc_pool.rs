@@ -0,0 +1,577 @@
+use crate::error::{ConfigViolation, LiquidationError, first_violation};
+use crate::rate_limit::RateLimiter;
+#[cfg(any(test, feature = "testing"))]
+use async_trait::async_trait;
+#[cfg(any(test, feature = "testing"))]
+use solana_client::client_error::Result as ClientResult;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+#[cfg(any(test, feature = "testing"))]
+use solana_client::rpc_client::RpcClientConfig;
+#[cfg(any(test, feature = "testing"))]
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+#[cfg(any(test, feature = "testing"))]
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+#[cfg(any(test, feature = "testing"))]
+use solana_rpc_client::mock_sender::MockSender;
+use std::fmt;
+#[cfg(any(test, feature = "testing"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Health tracking settings for an `RpcPool`
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RpcPoolConfig {
+    /// Consecutive failures after which an endpoint is quarantined
+    pub quarantine_after_failures: u32,
+    /// How long a quarantined endpoint is left alone before it's probed again
+    /// (in seconds)
+    pub quarantine_secs: u64,
+    /// Weight of the latest request in an endpoint's latency average (0-1)
+    pub latency_smoothing: f64,
+}
+
+impl Default for RpcPoolConfig {
+    fn default() -> Self {
+        Self {
+            quarantine_after_failures: 3,
+            quarantine_secs: 30,
+            latency_smoothing: 0.2,
+        }
+    }
+}
+
+impl RpcPoolConfig {
+    /// Check that the health tracking settings are usable
+    pub fn validate(&self) -> Result<(), LiquidationError> {
+        first_violation(self.violations())
+    }
+
+    /// Every problem with the settings
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if self.quarantine_after_failures == 0 {
+            violations.push(ConfigViolation::new("quarantine_after_failures", "must be at least 1"));
+        }
+        if !(self.latency_smoothing > 0.0 && self.latency_smoothing <= 1.0) {
+            violations.push(ConfigViolation::new(
+                "latency_smoothing",
+                format!("must be in (0, 1], got {}", self.latency_smoothing),
+            ));
+        }
+        violations
+    }
+}
+
+/// Whether a client error means the endpoint itself is unusable, as opposed to
+/// the node answering that the request failed
+pub(crate) fn is_endpoint_failure(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { data, .. }) => {
+            matches!(data, RpcResponseErrorData::NodeUnhealthy { .. })
+        }
+        // The client checks the node's version before some requests and only keeps
+        // the message of a failure, so an unreachable node surfaces as this
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(message)) => {
+            message.starts_with("cluster version query failed")
+        }
+        _ => false,
+    }
+}
+
+/// Health of one endpoint as last observed
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct EndpointStatus {
+    /// The endpoint's URL
+    pub url: String,
+    /// Failed requests since the last success
+    pub consecutive_failures: u32,
+    /// Moving average of request latency (in milliseconds), once measured
+    pub latency_ms: Option<f64>,
+    /// Whether the endpoint is being skipped until it's probed again
+    pub quarantined: bool,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    consecutive_failures: u32,
+    latency_ms: Option<f64>,
+    quarantined_until: Option<Instant>,
+}
+
+struct Endpoint {
+    client: Arc<RpcClient>,
+    health: Mutex<Health>,
+}
+
+impl Endpoint {
+    fn health(&self) -> MutexGuard<'_, Health> {
+        self.health.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// RPC endpoints used interchangeably, each request going to the healthiest
+///
+/// Endpoints that keep failing are quarantined, then probed with `getHealth`
+/// once the quarantine runs out and restored if they answer. Clones share the
+/// same endpoints and health.
+#[derive(Clone)]
+pub struct RpcPool {
+    endpoints: Arc<Vec<Endpoint>>,
+    config: RpcPoolConfig,
+}
+
+impl RpcPool {
+    /// Create a pool of the given clients, preferred in order while equally healthy
+    pub fn new(clients: Vec<Arc<RpcClient>>, config: RpcPoolConfig) -> Result<Self, LiquidationError> {
+        if clients.is_empty() {
+            return Err(LiquidationError::ConfigError("at least one RPC endpoint is required".to_string()));
+        }
+        let endpoints = clients
+            .into_iter()
+            .map(|client| Endpoint {
+                client,
+                health: Mutex::new(Health::default()),
+            })
+            .collect();
+        Ok(Self {
+            endpoints: Arc::new(endpoints),
+            config,
+        })
+    }
+
+    /// Create a pool connecting to the given URLs
+    pub fn from_urls<S: AsRef<str>>(urls: &[S], config: RpcPoolConfig) -> Result<Self, LiquidationError> {
+        let clients = urls
+            .iter()
+            .map(|url| Arc::new(RpcClient::new(url.as_ref().to_string())))
+            .collect();
+        Self::new(clients, config)
+    }
+
+    /// URLs of every endpoint
+    pub fn urls(&self) -> Vec<String> {
+        self.endpoints.iter().map(|endpoint| endpoint.client.url()).collect()
+    }
+
+    /// Health of every endpoint, in configured order
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .map(|endpoint| {
+                let health = endpoint.health();
+                EndpointStatus {
+                    url: endpoint.client.url(),
+                    consecutive_failures: health.consecutive_failures,
+                    latency_ms: health.latency_ms,
+                    quarantined: health.quarantined_until.is_some_and(|until| until > now),
+                }
+            })
+            .collect()
+    }
+
+    /// Endpoints out of quarantine, healthiest first
+    fn available(&self) -> Vec<&Endpoint> {
+        let now = Instant::now();
+        let mut available: Vec<(&Endpoint, u32, f64)> = self
+            .endpoints
+            .iter()
+            .filter_map(|endpoint| {
+                let health = endpoint.health();
+                if health.quarantined_until.is_some() {
+                    return None;
+                }
+                Some((endpoint, health.consecutive_failures, health.latency_ms.unwrap_or(0.0)))
+            })
+            .collect();
+        // Stable, so equally healthy endpoints keep their configured order
+        available.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+        let mut available: Vec<&Endpoint> = available.into_iter().map(|(endpoint, ..)| endpoint).collect();
+
+        if available.is_empty() {
+            // Everything is quarantined; try the endpoints due back soonest rather
+            // than failing outright
+            let mut quarantined: Vec<&Endpoint> = self.endpoints.iter().collect();
+            quarantined.sort_by_key(|endpoint| endpoint.health().quarantined_until.unwrap_or(now));
+            available = quarantined;
+        }
+        available
+    }
+
+    fn record_success(&self, endpoint: &Endpoint, latency: Duration) {
+        let mut health = endpoint.health();
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let smoothing = self.config.latency_smoothing;
+        health.latency_ms = Some(match health.latency_ms {
+            Some(average) => smoothing * latency_ms + (1.0 - smoothing) * average,
+            None => latency_ms,
+        });
+        health.consecutive_failures = 0;
+        health.quarantined_until = None;
+    }
+
+    fn record_failure(&self, endpoint: &Endpoint) {
+        let mut health = endpoint.health();
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= self.config.quarantine_after_failures {
+            warn!(
+                "Quarantining RPC endpoint {} for {}s after {} consecutive failures",
+                endpoint.client.url(),
+                self.config.quarantine_secs,
+                health.consecutive_failures
+            );
+            health.quarantined_until = Some(Instant::now() + Duration::from_secs(self.config.quarantine_secs));
+        }
+    }
+
+    /// Probe endpoints whose quarantine has run out, restoring those that answer
+    async fn probe_quarantined(&self, rate_limiter: &RateLimiter) {
+        let now = Instant::now();
+        for endpoint in self.endpoints.iter() {
+            let due = endpoint.health().quarantined_until.is_some_and(|until| until <= now);
+            if !due {
+                continue;
+            }
+            let started = Instant::now();
+            let probe = rate_limiter
+                .call_blocking(&endpoint.client, |rpc_client| {
+                    rpc_client.get_health().map_err(LiquidationError::from)
+                })
+                .await;
+            match probe {
+                Ok(()) => {
+                    info!("RPC endpoint {} is healthy again", endpoint.client.url());
+                    self.record_success(endpoint, started.elapsed());
+                }
+                Err(e) => {
+                    warn!("RPC endpoint {} still unhealthy: {}", endpoint.client.url(), e);
+                    self.record_failure(endpoint);
+                }
+            }
+        }
+    }
+
+    /// Run a blocking RPC client call on the healthiest endpoint, failing over to
+    /// the next when the endpoint is unavailable
+    ///
+    /// Errors the node answered with are returned without failing over.
+    pub async fn call<T, F>(&self, rate_limiter: &RateLimiter, request: F) -> Result<T, LiquidationError>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> Result<T, LiquidationError> + Send + Sync + 'static,
+    {
+        self.probe_quarantined(rate_limiter).await;
+
+        let request = Arc::new(request);
+        let mut last_error = None;
+        for endpoint in self.available() {
+            let started = Instant::now();
+            let request = request.clone();
+            match rate_limiter
+                .call_blocking(&endpoint.client, move |rpc_client| request(rpc_client))
+                .await
+            {
+                Err(e @ (LiquidationError::RpcUnavailable(_) | LiquidationError::RateLimited(_))) => {
+                    warn!("RPC endpoint {} failed, trying the next: {}", endpoint.client.url(), e);
+                    self.record_failure(endpoint);
+                    last_error = Some(e);
+                }
+                result => {
+                    self.record_success(endpoint, started.elapsed());
+                    return result.map_err(|e| e.at_endpoint(&endpoint.client.url()));
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| LiquidationError::RpcUnavailable("no RPC endpoints".to_string())))
+    }
+
+    /// Run a blocking RPC client call on every endpoint out of quarantine at once
+    pub async fn broadcast<T, F>(&self, rate_limiter: &RateLimiter, request: F) -> Vec<Result<T, LiquidationError>>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> Result<T, LiquidationError> + Send + Sync + 'static,
+    {
+        let request = Arc::new(request);
+        let calls = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.health().quarantined_until.is_none())
+            .map(|endpoint| {
+                let request = request.clone();
+                async move {
+                    let started = Instant::now();
+                    let result = rate_limiter
+                        .call_blocking(&endpoint.client, move |rpc_client| request(rpc_client))
+                        .await;
+                    match &result {
+                        Err(LiquidationError::RpcUnavailable(_) | LiquidationError::RateLimited(_)) => {
+                            self.record_failure(endpoint)
+                        }
+                        _ => self.record_success(endpoint, started.elapsed()),
+                    }
+                    result
+                }
+            });
+        futures::future::join_all(calls).await
+    }
+}
+
+impl fmt::Debug for RpcPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcPool").field("urls", &self.urls()).finish()
+    }
+}
+
+impl From<Arc<RpcClient>> for RpcPool {
+    fn from(client: Arc<RpcClient>) -> Self {
+        Self::new(vec![client], RpcPoolConfig::default()).unwrap()
+    }
+}
+
+impl From<&str> for RpcPool {
+    fn from(url: &str) -> Self {
+        Arc::new(RpcClient::new(url.to_string())).into()
+    }
+}
+
+impl From<String> for RpcPool {
+    fn from(url: String) -> Self {
+        url.as_str().into()
+    }
+}
+
+/// Mock RPC endpoint for testing that can be taken down
+///
+/// Answers like `RpcClient::new_mock(behavior)` while up and fails every request
+/// with a connection error while down.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Clone)]
+pub struct MockEndpoint {
+    url: String,
+    behavior: Arc<MockSender>,
+    down: Arc<AtomicBool>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockEndpoint {
+    /// Create an endpoint reporting `url` and answering per `behavior`, e.g.
+    /// "succeeds" or "malicious"
+    pub fn new(url: &str, behavior: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            behavior: Arc::new(MockSender::new(behavior)),
+            down: Arc::new(AtomicBool::new(false)),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Client sending to this endpoint
+    pub fn client(&self) -> Arc<RpcClient> {
+        Arc::new(RpcClient::new_sender(self.clone(), RpcClientConfig::default()))
+    }
+
+    /// Take the endpoint down or bring it back up
+    pub fn set_down(&self, down: bool) {
+        self.down.store(down, Ordering::SeqCst);
+    }
+
+    /// Number of requests for `method` received so far, including failed ones
+    pub fn requests(&self, method: &str) -> usize {
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|request| *request == method)
+            .count()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[async_trait]
+impl RpcSender for MockEndpoint {
+    async fn send(&self, request: RpcRequest, params: serde_json::Value) -> ClientResult<serde_json::Value> {
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(request.to_string());
+        if self.down.load(Ordering::SeqCst) {
+            let error = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "connection refused");
+            return Err(error.into());
+        }
+        // The mock sender has no answer for health checks
+        if request == RpcRequest::GetHealth {
+            return Ok(serde_json::json!("ok"));
+        }
+        self.behavior.send(request, params).await
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_pool(endpoints: &[&MockEndpoint]) -> RpcPool {
+        let config = RpcPoolConfig {
+            quarantine_after_failures: 2,
+            quarantine_secs: 30,
+            ..Default::default()
+        };
+        RpcPool::new(endpoints.iter().map(|endpoint| endpoint.client()).collect(), config).unwrap()
+    }
+
+    async fn latest_blockhash(pool: &RpcPool, rate_limiter: &RateLimiter) -> Result<(), LiquidationError> {
+        pool.call(rate_limiter, |rpc_client| {
+            rpc_client.get_latest_blockhash().map(|_| ()).map_err(LiquidationError::from)
+        })
+        .await
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(RpcPoolConfig::default().validate().is_ok());
+        let config = RpcPoolConfig {
+            quarantine_after_failures: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        let config = RpcPoolConfig {
+            latency_smoothing: 0.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_endpoint_failures_classified() {
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "connection refused");
+        assert!(is_endpoint_failure(&refused.into()));
+        let unhealthy = ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: -32005,
+            message: "Node is behind".to_string(),
+            data: RpcResponseErrorData::NodeUnhealthy { num_slots_behind: Some(200) },
+        });
+        assert!(is_endpoint_failure(&unhealthy.into()));
+        let answered = ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: -32002,
+            message: "Transaction simulation failed".to_string(),
+            data: RpcResponseErrorData::Empty,
+        });
+        assert!(!is_endpoint_failure(&answered.into()));
+        let version_unknown = ClientErrorKind::RpcError(RpcError::RpcRequestError(
+            "cluster version query failed: connection refused".to_string(),
+        ));
+        assert!(is_endpoint_failure(&version_unknown.into()));
+        assert!(RpcPool::new(Vec::new(), RpcPoolConfig::default()).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fails_over_to_healthy_endpoint() {
+        let (primary, backup) = (MockEndpoint::new("primary", "succeeds"), MockEndpoint::new("backup", "succeeds"));
+        let pool = create_pool(&[&primary, &backup]);
+        let rate_limiter = RateLimiter::default();
+
+        latest_blockhash(&pool, &rate_limiter).await.unwrap();
+        assert_eq!((primary.requests("getLatestBlockhash"), backup.requests("getLatestBlockhash")), (1, 0));
+
+        primary.set_down(true);
+        latest_blockhash(&pool, &rate_limiter).await.unwrap();
+        assert_eq!((primary.requests("getLatestBlockhash"), backup.requests("getLatestBlockhash")), (2, 1));
+        let status = pool.status();
+        assert_eq!(status[0].consecutive_failures, 1);
+        assert!(!status[0].quarantined);
+        assert_eq!(status[1].consecutive_failures, 0);
+
+        // The backup is now the healthier endpoint, so it's tried first
+        latest_blockhash(&pool, &rate_limiter).await.unwrap();
+        assert_eq!((primary.requests("getLatestBlockhash"), backup.requests("getLatestBlockhash")), (2, 2));
+
+        // Errors the node answered with don't fail over, and name the endpoint
+        let result = pool
+            .call(&rate_limiter, |rpc_client| {
+                rpc_client.get_account(&solana_sdk::pubkey::Pubkey::new_unique()).map_err(LiquidationError::from)
+            })
+            .await;
+        assert!(matches!(result, Err(LiquidationError::RpcError { endpoint: Some(url), .. }) if url == "backup"));
+        assert_eq!(primary.requests("getAccountInfo"), 0);
+
+        backup.set_down(true);
+        assert!(matches!(
+            latest_blockhash(&pool, &rate_limiter).await,
+            Err(LiquidationError::RpcUnavailable(_))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_quarantine_expires_after_probe() {
+        let (primary, backup) = (MockEndpoint::new("primary", "succeeds"), MockEndpoint::new("backup", "succeeds"));
+        let pool = create_pool(&[&primary, &backup]);
+        let rate_limiter = RateLimiter::default();
+
+        latest_blockhash(&pool, &rate_limiter).await.unwrap();
+        primary.set_down(true);
+        backup.set_down(true);
+        assert!(latest_blockhash(&pool, &rate_limiter).await.is_err());
+        backup.set_down(false);
+        latest_blockhash(&pool, &rate_limiter).await.unwrap();
+        assert!(pool.status()[0].quarantined);
+        assert!(!pool.status()[1].quarantined);
+        // Quarantined endpoints aren't tried at all
+        latest_blockhash(&pool, &rate_limiter).await.unwrap();
+        assert_eq!(primary.requests("getLatestBlockhash"), 3);
+        assert_eq!(primary.requests("getHealth"), 0);
+
+        // A failed probe extends the quarantine
+        tokio::time::advance(Duration::from_secs(31)).await;
+        latest_blockhash(&pool, &rate_limiter).await.unwrap();
+        assert_eq!(primary.requests("getHealth"), 1);
+        assert!(pool.status()[0].quarantined);
+
+        // Once the probe succeeds the endpoint is back in rotation with a clean slate
+        primary.set_down(false);
+        tokio::time::advance(Duration::from_secs(31)).await;
+        latest_blockhash(&pool, &rate_limiter).await.unwrap();
+        assert_eq!(primary.requests("getHealth"), 2);
+        let status = pool.status();
+        assert!(!status[0].quarantined);
+        assert_eq!(status[0].consecutive_failures, 0);
+        assert_eq!(pool.available()[0].client.url(), "primary".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_skips_quarantined_endpoints() {
+        let endpoints = [
+            MockEndpoint::new("a", "succeeds"),
+            MockEndpoint::new("b", "succeeds"),
+            MockEndpoint::new("c", "succeeds"),
+        ];
+        let pool = create_pool(&endpoints.iter().collect::<Vec<_>>());
+        let rate_limiter = RateLimiter::default();
+        let get_slot = |rpc_client: &RpcClient| rpc_client.get_slot().map_err(LiquidationError::from);
+
+        assert_eq!(pool.broadcast(&rate_limiter, get_slot).await.len(), 3);
+        endpoints[2].set_down(true);
+        for _ in 0..2 {
+            let results = pool.broadcast(&rate_limiter, get_slot).await;
+            assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 2);
+        }
+        assert!(pool.status()[2].quarantined);
+
+        assert_eq!(pool.broadcast(&rate_limiter, get_slot).await.len(), 2);
+        assert_eq!(endpoints[2].requests("getSlot"), 3);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::error::{ConfigViolation, LiquidationError};
+use std::collections::{HashMap, VecDeque};
+
+/// Settings for rejecting oracle prices that jump away from recent history
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PriceSanityConfig {
+    /// Whether outlying prices are rejected at all
+    pub enabled: bool,
+    /// Largest move from the last accepted price accepted at once (in basis points)
+    pub max_price_jump_bps: u32,
+    /// Consecutive reads a larger move has to persist for before it's accepted
+    pub min_confirmation_ticks: u32,
+    /// Accepted prices remembered per symbol, which the drift towards
+    /// liquidation is also estimated from
+    pub history_len: usize,
+    /// `max_price_jump_bps` of symbols that legitimately gap further, by symbol
+    pub symbol_max_price_jump_bps: HashMap<String, u32>,
+}
+
+impl Default for PriceSanityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_price_jump_bps: 2_000, // 20%
+            min_confirmation_ticks: 3,
+            history_len: 16,
+            symbol_max_price_jump_bps: HashMap::new(),
+        }
+    }
+}
+
+impl PriceSanityConfig {
+    /// Largest move accepted at once for a symbol (in basis points)
+    pub fn max_jump_bps(&self, symbol: &str) -> u32 {
+        self.symbol_max_price_jump_bps
+            .get(symbol)
+            .copied()
+            .unwrap_or(self.max_price_jump_bps)
+    }
+
+    /// Every problem with the settings
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if self.max_price_jump_bps == 0 {
+            violations.push(ConfigViolation::new("max_price_jump_bps", "must be positive"));
+        }
+        if self.min_confirmation_ticks == 0 {
+            violations.push(ConfigViolation::new("min_confirmation_ticks", "must be at least 1"));
+        }
+        if self.history_len == 0 {
+            violations.push(ConfigViolation::new("history_len", "must be at least 1"));
+        }
+        let mut overrides: Vec<(&String, &u32)> = self.symbol_max_price_jump_bps.iter().collect();
+        overrides.sort();
+        for (symbol, bps) in overrides {
+            if *bps == 0 {
+                violations.push(ConfigViolation::new(
+                    format!("symbol_max_price_jump_bps.{}", symbol),
+                    "must be positive",
+                ));
+            }
+        }
+        violations
+    }
+}
+
+/// Recent accepted prices of one symbol, and a move waiting to be confirmed
+#[derive(Debug, Default)]
+struct PriceHistory {
+    /// Accepted prices with when they were read (Unix timestamps), oldest first
+    accepted: VecDeque<(i64, f64)>,
+    /// First read of an unconfirmed move and how many consecutive reads agreed with it
+    unconfirmed: Option<(f64, u32)>,
+}
+
+impl PriceHistory {
+    fn last_accepted(&self) -> Option<f64> {
+        self.accepted.back().map(|&(_, price)| price)
+    }
+
+    fn accept(&mut self, price: f64, timestamp: i64, history_len: usize) {
+        self.unconfirmed = None;
+        self.accepted.push_back((timestamp, price));
+        while self.accepted.len() > history_len.max(1) {
+            self.accepted.pop_front();
+        }
+    }
+}
+
+/// Move between two prices (in basis points of the first)
+fn deviation_bps(from: f64, to: f64) -> f64 {
+    if from == 0.0 {
+        return if to == 0.0 { 0.0 } else { f64::INFINITY };
+    }
+    ((to - from) / from).abs() * 10_000.0
+}
+
+/// Rejects oracle prices that jump further from the last accepted one than a
+/// symbol is allowed to move, until the move persists
+///
+/// The first price of a symbol is always accepted.
+#[derive(Debug, Default)]
+pub(crate) struct PriceSanity {
+    histories: HashMap<String, PriceHistory>,
+}
+
+impl PriceSanity {
+    /// Create a sanity check without any history
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a new price of `symbol` at `timestamp`, accepting it or failing with
+    /// [`PriceOutlier`](LiquidationError::PriceOutlier)
+    ///
+    /// A rejected move is accepted on the `min_confirmation_ticks`th consecutive
+    /// read within `max_price_jump_bps` of where it first appeared. Every price
+    /// is accepted while the check is disabled.
+    pub fn check(
+        &mut self,
+        config: &PriceSanityConfig,
+        symbol: &str,
+        price: f64,
+        timestamp: i64,
+    ) -> Result<f64, LiquidationError> {
+        let max_jump_bps = config.max_jump_bps(symbol) as f64;
+        let history = self.histories.entry(symbol.to_string()).or_default();
+        let Some(last_accepted) = history.last_accepted().filter(|_| config.enabled) else {
+            history.accept(price, timestamp, config.history_len);
+            return Ok(price);
+        };
+        let deviation = deviation_bps(last_accepted, price);
+        if deviation <= max_jump_bps {
+            history.accept(price, timestamp, config.history_len);
+            return Ok(price);
+        }
+
+        let (first, ticks) = match history.unconfirmed {
+            Some((first, ticks)) if deviation_bps(first, price) <= max_jump_bps => (first, ticks + 1),
+            _ => (price, 1),
+        };
+        if ticks >= config.min_confirmation_ticks {
+            history.accept(price, timestamp, config.history_len);
+            return Ok(price);
+        }
+        history.unconfirmed = Some((first, ticks));
+        Err(LiquidationError::PriceOutlier {
+            symbol: symbol.to_string(),
+            price,
+            last_accepted,
+            deviation_bps: deviation,
+        })
+    }
+
+    /// Check a price against the last accepted one without recording it
+    pub fn verify(&self, config: &PriceSanityConfig, symbol: &str, price: f64) -> Result<f64, LiquidationError> {
+        let Some(last_accepted) = self.last_accepted(symbol).filter(|_| config.enabled) else {
+            return Ok(price);
+        };
+        let deviation = deviation_bps(last_accepted, price);
+        if deviation > config.max_jump_bps(symbol) as f64 {
+            return Err(LiquidationError::PriceOutlier {
+                symbol: symbol.to_string(),
+                price,
+                last_accepted,
+                deviation_bps: deviation,
+            });
+        }
+        Ok(price)
+    }
+
+    /// Last price accepted for a symbol
+    pub fn last_accepted(&self, symbol: &str) -> Option<f64> {
+        self.histories.get(symbol)?.last_accepted()
+    }
+
+    /// Prices accepted for a symbol with when they were read (Unix
+    /// timestamps), oldest first
+    pub fn history(&self, symbol: &str) -> Vec<(i64, f64)> {
+        self.histories
+            .get(symbol)
+            .map_or_else(Vec::new, |history| history.accepted.iter().copied().collect())
+    }
+
+    /// Drop the history of every symbol `keep` rejects, returning how many
+    /// were dropped
+    ///
+    /// A dropped symbol starts over like it was never read, so its next price
+    /// is accepted as is.
+    pub fn retain_symbols(&mut self, mut keep: impl FnMut(&str) -> bool) -> usize {
+        let before = self.histories.len();
+        self.histories.retain(|symbol, _| keep(symbol));
+        before - self.histories.len()
+    }
+
+    /// Symbols with a history
+    pub fn symbols(&self) -> usize {
+        self.histories.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spike_rejected_sustained_move_accepted() {
+        let config = PriceSanityConfig::default();
+        let mut sanity = PriceSanity::new();
+        // Cold start takes whatever comes first
+        assert_eq!(sanity.check(&config, "BTC/USD", 50_000.0, 0).unwrap(), 50_000.0);
+        assert!(sanity.check(&config, "BTC/USD", 51_000.0, 0).is_ok());
+
+        // A 40% print that doesn't repeat is dropped
+        let error = sanity.check(&config, "BTC/USD", 71_400.0, 0).unwrap_err();
+        assert!(matches!(error, LiquidationError::PriceOutlier { last_accepted, .. } if last_accepted == 51_000.0));
+        assert!(sanity.check(&config, "BTC/USD", 51_100.0, 0).is_ok());
+        assert_eq!(sanity.last_accepted("BTC/USD"), Some(51_100.0));
+
+        // The same move held for three reads is real
+        assert!(sanity.check(&config, "BTC/USD", 71_500.0, 0).is_err());
+        assert!(sanity.check(&config, "BTC/USD", 71_600.0, 0).is_err());
+        assert_eq!(sanity.check(&config, "BTC/USD", 71_550.0, 0).unwrap(), 71_550.0);
+        assert!(sanity.verify(&config, "BTC/USD", 71_000.0).is_ok());
+        assert!(sanity.verify(&config, "BTC/USD", 51_000.0).is_err());
+    }
+
+    #[test]
+    fn test_erratic_outliers_never_confirm() {
+        let config = PriceSanityConfig::default();
+        let mut sanity = PriceSanity::new();
+        sanity.check(&config, "BTC/USD", 50_000.0, 0).unwrap();
+        for price in [70_000.0, 30_000.0, 70_000.0, 30_000.0] {
+            assert!(sanity.check(&config, "BTC/USD", price, 0).is_err());
+        }
+        assert_eq!(sanity.last_accepted("BTC/USD"), Some(50_000.0));
+    }
+
+    #[test]
+    fn test_symbol_overrides_and_disabling() {
+        let mut config = PriceSanityConfig::default();
+        config.symbol_max_price_jump_bps.insert("MEME/USD".to_string(), 9_000);
+        let mut sanity = PriceSanity::new();
+        sanity.check(&config, "MEME/USD", 1.0, 0).unwrap();
+        assert!(sanity.check(&config, "MEME/USD", 1.8, 0).is_ok());
+        sanity.check(&config, "BTC/USD", 50_000.0, 60).unwrap();
+        assert!(sanity.check(&config, "BTC/USD", 90_000.0, 120).is_err());
+
+        // Prices are still remembered while the check is disabled
+        config.enabled = false;
+        assert!(sanity.check(&config, "BTC/USD", 90_000.0, 180).is_ok());
+        assert!(sanity.verify(&config, "BTC/USD", 90_000.0).is_ok());
+        assert_eq!(sanity.history("BTC/USD"), [(60, 50_000.0), (180, 90_000.0)]);
+    }
+
+    #[test]
+    fn test_dropped_symbol_starts_over() {
+        let config = PriceSanityConfig::default();
+        let mut sanity = PriceSanity::new();
+        sanity.check(&config, "BTC/USD", 50_000.0, 0).unwrap();
+        sanity.check(&config, "ETH/USD", 3_000.0, 0).unwrap();
+        assert_eq!(sanity.retain_symbols(|symbol| symbol == "ETH/USD"), 1);
+        assert_eq!(sanity.symbols(), 1);
+
+        // A price that would have been an outlier is the new first price
+        assert!(sanity.check(&config, "BTC/USD", 90_000.0, 60).is_ok());
+        assert_eq!(sanity.history("BTC/USD"), [(60, 90_000.0)]);
+    }
+}
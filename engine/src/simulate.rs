@@ -0,0 +1,109 @@
+use crate::address::{OwnerAddress, PositionAddress};
+use crate::amount::{Amount, AmountExt};
+use crate::error::LiquidationError;
+use crate::position::Position;
+use crate::profitability::ProfitModel;
+use crate::types::LiquidationConfig;
+use std::collections::HashMap;
+
+/// A position that becomes liquidatable at the simulated prices
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SimulatedLiquidation {
+    pub address: PositionAddress,
+    pub owner: OwnerAddress,
+    pub symbol: String,
+    /// Simulated price of the symbol
+    pub price: f64,
+    /// Margin ratio at the simulated price
+    pub margin_ratio: f64,
+    /// Price at which the position becomes liquidatable, if any
+    pub liquidation_price: Option<f64>,
+    /// Price at which margin plus PnL reaches zero, if any
+    pub bankruptcy_price: Option<f64>,
+    /// Notional the engine would liquidate
+    pub notional: f64,
+    /// Reward expected by the liquidator
+    pub reward: f64,
+    /// Shortfall beyond the position's margin
+    pub bad_debt: f64,
+}
+
+/// Effect of the simulated bad debt on the insurance fund
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InsuranceImpact {
+    /// Balance before absorbing bad debt
+    pub balance: f64,
+    /// Balance left after absorbing bad debt
+    pub remaining: f64,
+    /// Bad debt the fund can't cover
+    pub shortfall: f64,
+}
+
+/// Outcome of a simulation over a book of positions
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SimulationReport {
+    /// Prices the book was simulated at
+    pub prices: HashMap<String, f64>,
+    /// Number of positions simulated
+    pub positions: usize,
+    /// Positions that become liquidatable
+    pub liquidations: Vec<SimulatedLiquidation>,
+    pub total_notional: f64,
+    pub total_rewards: f64,
+    pub total_bad_debt: f64,
+    pub insurance: InsuranceImpact,
+}
+
+/// Simulate liquidations of `positions` at `prices`
+///
+/// Positions are judged against the maintenance margin of their market, side and
+/// margin tier at the simulated price, and liquidated as the engine would:
+/// bankrupt positions in full, others by the configured fraction. Bad debt is charged against the insurance fund. Fails
+/// for positions in a symbol without a price.
+pub fn simulate(
+    positions: &[Position],
+    prices: &HashMap<String, Amount>,
+    config: &LiquidationConfig,
+    insurance_fund_balance: f64,
+) -> Result<SimulationReport, LiquidationError> {
+    let model = ProfitModel::from_config(config, 0);
+    let mut liquidations = Vec::new();
+    for position in positions {
+        let price = *prices
+            .get(&position.symbol)
+            .ok_or_else(|| LiquidationError::OracleError(format!("No price for {}", position.symbol)))?;
+        let margin_params = config.margin_params_at(position, price);
+        if !position.is_undercollateralized(price, margin_params) {
+            continue;
+        }
+        let bad_debt = position.bad_debt(price).as_f64();
+        let fraction = config.liquidation_fraction(&position.symbol, bad_debt);
+        liquidations.push(SimulatedLiquidation {
+            address: position.address,
+            owner: position.owner,
+            symbol: position.symbol.clone(),
+            price: price.as_f64(),
+            margin_ratio: position.margin_ratio(price).as_f64(),
+            liquidation_price: position.liquidation_price(margin_params).map(Amount::as_f64),
+            bankruptcy_price: position.bankruptcy_price().map(Amount::as_f64),
+            notional: position.value(price).as_f64() * fraction,
+            reward: model.expected_liquidation_reward(position, price, fraction),
+            bad_debt,
+        });
+    }
+
+    let total_bad_debt: f64 = liquidations.iter().map(|liquidation| liquidation.bad_debt).sum();
+    Ok(SimulationReport {
+        prices: prices.iter().map(|(symbol, price)| (symbol.clone(), price.as_f64())).collect(),
+        positions: positions.len(),
+        total_notional: liquidations.iter().map(|liquidation| liquidation.notional).sum(),
+        total_rewards: liquidations.iter().map(|liquidation| liquidation.reward).sum(),
+        total_bad_debt,
+        insurance: InsuranceImpact {
+            balance: insurance_fund_balance,
+            remaining: (insurance_fund_balance - total_bad_debt).max(0.0),
+            shortfall: (total_bad_debt - insurance_fund_balance).max(0.0),
+        },
+        liquidations,
+    })
+}
@@ -0,0 +1,143 @@
+use crate::error::LiquidationError;
+use std::sync::{Mutex, PoisonError};
+use tokio::time::Duration;
+
+/// How often the engine binary asks the RPC node for its current slot
+pub const SLOT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reject a price published in `publish_slot` if it's more than `max_age_slots`
+/// behind `current_slot`, returning its age in slots otherwise
+///
+/// Unlike [`check_staleness`](crate::oracle::check_staleness) this doesn't trust the
+/// host's clock, and catches prices that stopped updating because the cluster
+/// did. Prices published after the slot last seen are fresh.
+pub(crate) fn check_slot_staleness(
+    symbol: &str,
+    publish_slot: u64,
+    current_slot: u64,
+    max_age_slots: u64,
+) -> Result<u64, LiquidationError> {
+    let age_slots = current_slot.saturating_sub(publish_slot);
+    if age_slots > max_age_slots {
+        return Err(LiquidationError::StalePriceSlot {
+            symbol: symbol.to_string(),
+            age_slots,
+            max_age_slots,
+        });
+    }
+    Ok(age_slots)
+}
+
+/// The cluster's slot as last seen, and whether it's still advancing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SlotStatus {
+    /// Highest slot seen, if any was
+    pub slot: Option<u64>,
+    /// When the slot last advanced (Unix timestamp)
+    pub advanced_at: Option<i64>,
+    /// Whether the slot hasn't advanced for longer than allowed, which holds
+    /// liquidations back
+    pub stalled: bool,
+}
+
+/// Latest slot read from the RPC node or its subscriptions, shared by
+/// everything that sees one
+///
+/// Slots only move forward: an older slot reported by a lagging endpoint or a
+/// late notification is ignored. The tracker notes when the slot last
+/// advanced, so a node or cluster that stopped producing slots is noticed even
+/// while it keeps answering.
+#[derive(Debug, Default)]
+pub struct SlotTracker(Mutex<SlotStatus>);
+
+impl SlotTracker {
+    /// A tracker that hasn't seen a slot yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `slot` as seen at `now`, returning whether it advanced the
+    /// tracked slot
+    pub fn observe(&self, slot: u64, now: i64) -> bool {
+        let mut status = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if status.slot.is_some_and(|seen| seen >= slot) {
+            return false;
+        }
+        status.slot = Some(slot);
+        status.advanced_at = Some(now);
+        true
+    }
+
+    /// Highest slot seen, if any was
+    pub fn current_slot(&self) -> Option<u64> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).slot
+    }
+
+    /// When the slot last advanced, if it stalled for longer than
+    /// `max_silence_secs` before `now`
+    ///
+    /// A tracker that never saw a slot hasn't stalled.
+    pub fn stalled_since(&self, now: i64, max_silence_secs: u64) -> Option<i64> {
+        let advanced_at = self.0.lock().unwrap_or_else(PoisonError::into_inner).advanced_at?;
+        (now.saturating_sub(advanced_at) > max_silence_secs as i64).then_some(advanced_at)
+    }
+
+    /// The slot as last seen at `now`, stalled after `max_silence_secs`
+    /// without advancing
+    pub fn status(&self, now: i64, max_silence_secs: Option<u64>) -> SlotStatus {
+        let stalled = max_silence_secs.is_some_and(|max_silence_secs| {
+            self.stalled_since(now, max_silence_secs).is_some()
+        });
+        SlotStatus {
+            stalled,
+            ..*self.0.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slot_staleness() {
+        assert_eq!(check_slot_staleness("SOL/USD", 1_000, 1_150, 150).unwrap(), 150);
+        match check_slot_staleness("SOL/USD", 1_000, 1_151, 150) {
+            Err(LiquidationError::StalePriceSlot { age_slots, max_age_slots, .. }) => {
+                assert_eq!((age_slots, max_age_slots), (151, 150))
+            }
+            other => panic!("expected a stale price, got {:?}", other),
+        }
+        // A price from a slot the tracker hasn't caught up to yet is fresh
+        assert_eq!(check_slot_staleness("SOL/USD", 1_200, 1_150, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_slots_only_move_forward() {
+        let tracker = SlotTracker::new();
+        assert_eq!(tracker.current_slot(), None);
+        assert!(tracker.observe(1_000, 100));
+        assert!(!tracker.observe(990, 105));
+        assert!(!tracker.observe(1_000, 105));
+        assert_eq!(tracker.current_slot(), Some(1_000));
+        assert_eq!(tracker.status(105, None).advanced_at, Some(100));
+    }
+
+    #[test]
+    fn test_stalls_after_silence() {
+        let tracker = SlotTracker::new();
+        // Nothing seen yet is no reason to stop
+        assert_eq!(tracker.stalled_since(1_000, 30), None);
+
+        tracker.observe(1_000, 100);
+        assert_eq!(tracker.stalled_since(130, 30), None);
+        // The node still answers, but with the same slot
+        tracker.observe(1_000, 131);
+        assert_eq!(tracker.stalled_since(131, 30), Some(100));
+        assert!(tracker.status(131, Some(30)).stalled);
+        assert!(!tracker.status(131, None).stalled);
+
+        tracker.observe(1_001, 132);
+        assert_eq!(tracker.stalled_since(132, 30), None);
+    }
+}
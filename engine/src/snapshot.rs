@@ -0,0 +1,230 @@
+use crate::{amount::AmountExt, error::LiquidationError, position::Position, state::write_atomically};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// Version of the snapshot format this build reads and writes
+pub(crate) const SNAPSHOT_VERSION: u32 = 1;
+
+/// Monitored positions captured at one point in time, for debugging, replay
+/// and seeding a freshly started engine
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PositionSnapshot {
+    /// Format version of the snapshot
+    pub version: u32,
+    /// When the snapshot was taken (Unix timestamp)
+    pub created_at: i64,
+    /// The positions, ordered by address
+    pub positions: Vec<Position>,
+}
+
+/// The part of a snapshot checked before the rest is parsed, so files from
+/// another version are reported as such rather than by whichever field differs
+#[derive(serde::Deserialize)]
+struct Header {
+    version: u32,
+}
+
+impl PositionSnapshot {
+    /// Snapshot `positions` taken at `created_at`
+    pub fn new(mut positions: Vec<Position>, created_at: i64) -> Self {
+        positions.sort_by_key(|position| position.address);
+        Self {
+            version: SNAPSHOT_VERSION,
+            created_at,
+            positions,
+        }
+    }
+
+    /// Parse and validate a snapshot from JSON
+    pub fn from_json(json: &[u8]) -> Result<Self, LiquidationError> {
+        Self::parse(json).map_err(|e| LiquidationError::ConfigError(format!("Invalid position snapshot: {}", e)))
+    }
+
+    /// Load and validate the snapshot file at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LiquidationError> {
+        let path = path.as_ref();
+        let invalid = |e: String| LiquidationError::ConfigError(format!("Invalid position snapshot {}: {}", path.display(), e));
+        let json = fs::read(path).map_err(|e| invalid(e.to_string()))?;
+        Self::parse(&json).map_err(invalid)
+    }
+
+    /// Write the snapshot to `path`, replacing any file there atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), LiquidationError> {
+        write_atomically(path.as_ref(), &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Check that the snapshot is of this version and its positions are usable
+    ///
+    /// Pubkeys are checked as the snapshot is parsed.
+    pub fn validate(&self) -> Result<(), LiquidationError> {
+        self.problem()
+            .map_or(Ok(()), |e| Err(LiquidationError::ConfigError(format!("Invalid position snapshot: {}", e))))
+    }
+
+    fn parse(json: &[u8]) -> Result<Self, String> {
+        let header: Header =
+            serde_json::from_slice(json).map_err(|e| format!("not a versioned position snapshot ({})", e))?;
+        check_version(header.version)?;
+        let snapshot: Self = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+        snapshot.problem().map_or(Ok(snapshot), Err)
+    }
+
+    /// The first thing wrong with the snapshot, if any
+    fn problem(&self) -> Option<String> {
+        if let Err(e) = check_version(self.version) {
+            return Some(e);
+        }
+        let mut addresses = HashSet::new();
+        for position in &self.positions {
+            if !addresses.insert(position.address) {
+                return Some(format!("position {} appears more than once", position.address));
+            }
+            let values = [
+                ("size", position.size),
+                ("entry_price", position.entry_price),
+                ("margin", position.margin),
+                ("cumulative_funding_at_entry", position.cumulative_funding_at_entry),
+                ("unsettled_funding", position.unsettled_funding),
+                ("collateral_value", position.collateral_value),
+            ]
+            .into_iter()
+            .chain(position.collateral.iter().map(|balance| ("collateral amount", balance.amount)));
+            for (field, value) in values {
+                if !value.is_finite() {
+                    return Some(format!("position {}: {} must be a finite number, got {}", position.address, field, value));
+                }
+            }
+            if let Some(problem) = position.metadata_problem() {
+                return Some(format!("position {}: {}", position.address, problem));
+            }
+        }
+        None
+    }
+}
+
+fn check_version(version: u32) -> Result<(), String> {
+    if version == SNAPSHOT_VERSION {
+        Ok(())
+    } else {
+        Err(format!("version {} isn't supported, expected version {}", version, SNAPSHOT_VERSION))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::CollateralBalance;
+    use solana_sdk::pubkey::Pubkey;
+
+    /// A book of 1000 positions including edge values: no margin, losses beyond
+    /// the margin, shorts, owed and earned funding, and collateral assets
+    fn create_book() -> Vec<Position> {
+        (0..1000)
+            .map(|i| {
+                let margin = if i % 10 == 0 { 0.0 } else { i as f64 * 1.5 };
+                let mut position = Position::new(
+                    Pubkey::new_unique(),
+                    Pubkey::new_unique(),
+                    if i % 2 == 0 { "BTC/USD" } else { "SOL/USD" },
+                    0.001 + i as f64 / 7.0,
+                    60000.0 - i as f64,
+                    margin,
+                    i % 3 != 0,
+                );
+                position.last_liquidated = (i % 4 == 0).then_some(1_700_000_000 + i);
+                position.unsettled_funding = (i as f64 - 500.0) / 3.0;
+                if i % 5 == 0 {
+                    position = position.with_collateral(vec![CollateralBalance {
+                        mint: Pubkey::new_unique(),
+                        symbol: "SOL/USD".to_string(),
+                        amount: i as f64 / 11.0,
+                    }]);
+                    position.collateral_value = i as f64 / 11.0 * 80.0;
+                }
+                position
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positions.json");
+        let book = create_book();
+        // Zero margin and a loss well beyond it are kept as they are
+        assert!(book.iter().any(|position| position.margin == 0.0));
+        assert!(book.iter().any(|position| position.unrealized_pnl(30000.0) < -position.margin));
+
+        let snapshot = PositionSnapshot::new(book, 1_700_000_123);
+        snapshot.save(&path).unwrap();
+        let loaded = PositionSnapshot::load(&path).unwrap();
+        assert_eq!(loaded, snapshot);
+        assert_eq!(loaded.positions.len(), 1000);
+        assert!(loaded.positions.windows(2).all(|pair| pair[0].address < pair[1].address));
+        assert!(!dir.path().join("positions.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_corrupt_snapshots_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positions.json");
+        let error = |contents: &str| {
+            fs::write(&path, contents).unwrap();
+            match PositionSnapshot::load(&path) {
+                Err(LiquidationError::ConfigError(message)) => message,
+                other => panic!("expected a configuration error, got {:?}", other),
+            }
+        };
+        let prefix = format!("Invalid position snapshot {}: ", path.display());
+
+        let snapshot = serde_json::to_string(&PositionSnapshot::new(create_book(), 0)).unwrap();
+        let truncated = error(&snapshot[..snapshot.len() / 2]);
+        assert!(truncated.starts_with(&format!("{}not a versioned position snapshot", prefix)), "{}", truncated);
+
+        // A plain list of positions, as the admin API lists them, has no header
+        let list = serde_json::to_string(&create_book()).unwrap();
+        assert!(error(&list).contains("not a versioned position snapshot"));
+
+        let newer = snapshot.replacen("\"version\":1", "\"version\":2", 1);
+        assert_eq!(error(&newer), format!("{}version 2 isn't supported, expected version 1", prefix));
+
+        // Pubkeys must parse
+        let address = create_book()[0].address.to_string();
+        let position = format!(
+            r#"{{"address":"{}","owner":"not-a-pubkey","symbol":"BTC/USD","size":1.0,"entry_price":1.0,"margin":1.0,"is_long":true}}"#,
+            address
+        );
+        let bad_owner = error(&format!(r#"{{"version":1,"created_at":0,"positions":[{}]}}"#, position));
+        assert!(bad_owner.starts_with(&prefix), "{}", bad_owner);
+        let duplicated = position.replace("not-a-pubkey", &address);
+        let duplicated = format!(r#"{{"version":1,"created_at":0,"positions":[{},{}]}}"#, duplicated, duplicated);
+        assert_eq!(error(&duplicated), format!("{}position {} appears more than once", prefix, address));
+
+        fs::remove_file(&path).unwrap();
+        assert!(PositionSnapshot::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_non_finite_values_rejected() {
+        let mut snapshot = PositionSnapshot::new(create_book(), 0);
+        assert!(snapshot.validate().is_ok());
+
+        let address = snapshot.positions[3].address;
+        snapshot.positions[3].size = f64::NAN;
+        match snapshot.validate() {
+            Err(LiquidationError::ConfigError(message)) => assert_eq!(
+                message,
+                format!("Invalid position snapshot: position {}: size must be a finite number, got NaN", address)
+            ),
+            other => panic!("expected a configuration error, got {:?}", other),
+        }
+        snapshot.positions[3].size = 1.0;
+        snapshot.positions[3].margin = f64::INFINITY;
+        assert!(snapshot.validate().is_err());
+
+        snapshot.positions[3].margin = 0.0;
+        snapshot.version = 0;
+        assert!(snapshot.validate().is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use crate::{address::PositionAddress, error::LiquidationError, types::PositionStatus};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Liquidation state remembered for a position across restarts
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PositionState {
+    /// Timestamp of the last completed liquidation
+    pub last_liquidated: Option<i64>,
+    /// Last known status of the position
+    pub status: PositionStatus,
+    /// Signature of a submitted liquidation that hasn't been confirmed yet
+    pub pending_signature: Option<String>,
+    /// Timestamp of the last change to this entry
+    pub updated_at: i64,
+}
+
+/// Contents of a state file, as written since liquidation events were deduplicated
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct Contents {
+    positions: BTreeMap<String, PositionState>,
+    /// Keys of the liquidation events published, with when each was seen
+    #[serde(default)]
+    seen_events: BTreeMap<String, i64>,
+}
+
+/// A state file in either format
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum StoredContents {
+    Current(Contents),
+    /// Positions alone, as written before
+    Legacy(BTreeMap<String, PositionState>),
+}
+
+/// Engine state persisted to a JSON file
+///
+/// Every change is flushed immediately by writing a temporary file and renaming it
+/// over the previous one, so a crash leaves either the old or the new state on disk.
+#[derive(Debug)]
+pub struct StateFile {
+    path: PathBuf,
+    entries: BTreeMap<String, PositionState>,
+    seen_events: BTreeMap<String, i64>,
+}
+
+impl StateFile {
+    /// Load the state file at `path`, starting empty if it doesn't exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LiquidationError> {
+        let path = path.as_ref().to_path_buf();
+        let contents = match fs::read(&path) {
+            Ok(contents) => match serde_json::from_slice(&contents)? {
+                StoredContents::Current(contents) => contents,
+                StoredContents::Legacy(positions) => Contents {
+                    positions,
+                    ..Contents::default()
+                },
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Contents::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            entries: contents.positions,
+            seen_events: contents.seen_events,
+        })
+    }
+
+    /// Path of the state file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of positions with remembered state
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no position has remembered state
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the remembered state of a position
+    pub fn get(&self, address: &PositionAddress) -> Option<&PositionState> {
+        self.entries.get(&address.to_string())
+    }
+
+    /// Every position with a submitted liquidation that hasn't been confirmed yet,
+    /// with its signature and when it was submitted
+    pub fn pending(&self) -> Vec<(PositionAddress, &str, i64)> {
+        self.entries
+            .iter()
+            .filter_map(|(address, state)| {
+                let signature = state.pending_signature.as_deref()?;
+                Some((address.parse().ok()?, signature, state.updated_at))
+            })
+            .collect()
+    }
+
+    /// Record a liquidation transaction that was submitted but not yet confirmed
+    pub fn record_submitted(
+        &mut self,
+        address: &PositionAddress,
+        signature: &str,
+        now: i64,
+    ) -> Result<(), LiquidationError> {
+        let last_liquidated = self.get(address).and_then(|state| state.last_liquidated);
+        self.entries.insert(
+            address.to_string(),
+            PositionState {
+                last_liquidated,
+                status: PositionStatus::Liquidating,
+                pending_signature: Some(signature.to_string()),
+                updated_at: now,
+            },
+        );
+        self.flush()
+    }
+
+    /// Record a completed liquidation, starting the position's cooldown at `liquidated_at`
+    pub fn record_liquidated(
+        &mut self,
+        address: &PositionAddress,
+        liquidated_at: i64,
+        now: i64,
+    ) -> Result<(), LiquidationError> {
+        self.entries.insert(
+            address.to_string(),
+            PositionState {
+                last_liquidated: Some(liquidated_at),
+                status: PositionStatus::Liquidated,
+                pending_signature: None,
+                updated_at: now,
+            },
+        );
+        self.flush()
+    }
+
+    /// Forget a pending signature that failed or was dropped
+    pub fn clear_pending(&mut self, address: &PositionAddress, now: i64) -> Result<(), LiquidationError> {
+        let Some(state) = self.entries.get_mut(&address.to_string()) else {
+            return Ok(());
+        };
+        state.pending_signature = None;
+        state.status = PositionStatus::AtRisk;
+        state.updated_at = now;
+        self.flush()
+    }
+
+    /// Keys of the liquidation events published, with when each was seen
+    pub fn seen_events(&self) -> impl Iterator<Item = (&str, i64)> {
+        self.seen_events.iter().map(|(key, seen_at)| (key.as_str(), *seen_at))
+    }
+
+    /// Remember a published liquidation event's key (see [`DedupStats`](crate::dedup::DedupStats)),
+    /// forgetting those seen more than `retention_secs` before `now`
+    pub fn record_event(&mut self, key: &str, now: i64, retention_secs: u64) -> Result<(), LiquidationError> {
+        let cutoff = now.saturating_sub(retention_secs as i64);
+        self.seen_events.retain(|_, seen_at| *seen_at > cutoff);
+        self.seen_events.insert(key.to_string(), now);
+        self.flush()
+    }
+
+    /// Drop entries untouched for longer than `max_age_secs`, returning how many were removed
+    ///
+    /// Entries with a pending signature are kept until the signature is resolved.
+    pub fn prune(&mut self, now: i64, max_age_secs: u64) -> Result<usize, LiquidationError> {
+        let cutoff = now.saturating_sub(max_age_secs as i64);
+        let before = self.entries.len();
+        self.entries
+            .retain(|_, state| state.pending_signature.is_some() || state.updated_at > cutoff);
+
+        let removed = before - self.entries.len();
+        if removed > 0 {
+            self.flush()?;
+        }
+        Ok(removed)
+    }
+
+    /// Write the state to disk atomically
+    fn flush(&self) -> Result<(), LiquidationError> {
+        let contents = serde_json::json!({
+            "positions": &self.entries,
+            "seen_events": &self.seen_events,
+        });
+        write_atomically(&self.path, &serde_json::to_vec_pretty(&contents)?)
+    }
+}
+
+/// Write `contents` to `path` through a temporary file renamed over it, so a
+/// crash leaves either the old or the new contents on disk
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), LiquidationError> {
+    let mut tmp_path = path.to_path_buf().into_os_string();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_file_starts_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = StateFile::load(dir.path().join("state.json")).unwrap();
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn test_state_survives_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let (liquidated, submitted) = (PositionAddress::new_unique(), PositionAddress::new_unique());
+
+        let mut state = StateFile::load(&path).unwrap();
+        state.record_liquidated(&liquidated, 1_000, 1_000).unwrap();
+        state.record_submitted(&submitted, "5sig", 1_010).unwrap();
+        drop(state);
+
+        let state = StateFile::load(&path).unwrap();
+        assert_eq!(state.len(), 2);
+        assert_eq!(state.get(&liquidated).unwrap().last_liquidated, Some(1_000));
+        assert_eq!(state.get(&liquidated).unwrap().status, PositionStatus::Liquidated);
+        let pending = state.get(&submitted).unwrap();
+        assert_eq!(pending.pending_signature.as_deref(), Some("5sig"));
+        assert_eq!(pending.status, PositionStatus::Liquidating);
+
+        // Nothing is left behind from the atomic rename
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_clear_pending() {
+        let dir = tempfile::tempdir().unwrap();
+        let address = PositionAddress::new_unique();
+        let mut state = StateFile::load(dir.path().join("state.json")).unwrap();
+        state.record_submitted(&address, "5sig", 100).unwrap();
+
+        state.clear_pending(&address, 200).unwrap();
+        let entry = state.get(&address).unwrap();
+        assert_eq!(entry.pending_signature, None);
+        assert_eq!(entry.last_liquidated, None);
+        assert_eq!(entry.updated_at, 200);
+    }
+
+    #[test]
+    fn test_prune_keeps_recent_and_pending() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let (old, recent, pending) =
+            (PositionAddress::new_unique(), PositionAddress::new_unique(), PositionAddress::new_unique());
+
+        let mut state = StateFile::load(&path).unwrap();
+        state.record_liquidated(&old, 100, 100).unwrap();
+        state.record_liquidated(&recent, 900, 900).unwrap();
+        state.record_submitted(&pending, "5sig", 100).unwrap();
+
+        assert_eq!(state.prune(1_000, 600).unwrap(), 1);
+        assert!(state.get(&old).is_none());
+        assert!(state.get(&recent).is_some());
+        assert!(state.get(&pending).is_some());
+        assert_eq!(StateFile::load(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_seen_events_survive_reload_within_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let mut state = StateFile::load(&path).unwrap();
+        state.record_event("5sig", 100, 600).unwrap();
+        state.record_event("6sig", 800, 600).unwrap();
+        drop(state);
+
+        let mut state = StateFile::load(&path).unwrap();
+        assert_eq!(state.seen_events().collect::<Vec<_>>(), vec![("5sig", 100), ("6sig", 800)]);
+        state.record_event("7sig", 1_000, 600).unwrap();
+        assert_eq!(state.seen_events().collect::<Vec<_>>(), vec![("6sig", 800), ("7sig", 1_000)]);
+    }
+
+    #[test]
+    fn test_loads_files_without_seen_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let address = PositionAddress::new_unique();
+        let legacy = serde_json::json!({
+            address.to_string(): {
+                "last_liquidated": 1_000,
+                "status": "liquidated",
+                "pending_signature": null,
+                "updated_at": 1_000,
+            }
+        });
+        fs::write(&path, legacy.to_string()).unwrap();
+
+        let state = StateFile::load(&path).unwrap();
+        assert_eq!(state.get(&address).unwrap().last_liquidated, Some(1_000));
+        assert_eq!(state.seen_events().count(), 0);
+    }
+}
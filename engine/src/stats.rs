@@ -0,0 +1,230 @@
+use crate::amount::{self, Amount, AmountExt};
+use crate::error::ConfigViolation;
+use crate::memory::MemoryStats;
+use crate::position::Position;
+use crate::simulate::simulate;
+use crate::types::{LiquidationConfig, PositionStatus, PositionUpdate};
+use std::collections::{BTreeMap, HashMap};
+
+/// Uniform price moves the book's potential bad debt is reported at
+pub(crate) const PRICE_SHOCKS: [f64; 6] = [-0.2, -0.1, -0.05, 0.05, 0.1, 0.2];
+
+/// Number of positions closest to liquidation reported
+pub(crate) const CLOSEST_POSITIONS: usize = 10;
+
+/// Settings for the book statistics served to dashboards
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Edges between the margin ratio histogram's buckets (in percent), in
+    /// ascending order; the first and last buckets are open-ended
+    pub margin_ratio_buckets: Vec<f64>,
+    /// How long computed statistics are served before they're recomputed (in seconds)
+    pub cache_secs: u64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            margin_ratio_buckets: vec![2.5, 5.0, 7.5, 10.0, 15.0, 20.0, 30.0, 50.0, 100.0],
+            cache_secs: 10,
+        }
+    }
+}
+
+impl StatsConfig {
+    /// Every problem with the settings
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if self.margin_ratio_buckets.iter().any(|edge| !edge.is_finite()) {
+            violations.push(ConfigViolation::new("margin_ratio_buckets", "must be finite"));
+        } else if self.margin_ratio_buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+            violations.push(ConfigViolation::new("margin_ratio_buckets", "must be strictly ascending"));
+        }
+        violations
+    }
+}
+
+/// Positions whose margin ratio falls between two bucket edges
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HistogramBucket {
+    /// Inclusive lower edge (in percent), `None` for the first bucket
+    pub lower: Option<f64>,
+    /// Exclusive upper edge (in percent), `None` for the last bucket
+    pub upper: Option<f64>,
+    pub count: usize,
+}
+
+/// Count `values` into the buckets between `edges`
+pub(crate) fn histogram(edges: &[f64], values: impl IntoIterator<Item = f64>) -> Vec<HistogramBucket> {
+    let mut buckets: Vec<HistogramBucket> = (0..=edges.len())
+        .map(|index| HistogramBucket {
+            lower: index.checked_sub(1).map(|lower| edges[lower]),
+            upper: edges.get(index).copied(),
+            count: 0,
+        })
+        .collect();
+    for value in values {
+        buckets[edges.partition_point(|edge| *edge <= value)].count += 1;
+    }
+    buckets
+}
+
+/// Open notional of one symbol on each side (in quote currency)
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SideNotional {
+    pub long: f64,
+    pub short: f64,
+}
+
+/// Liquidations and bad debt the book would see if every price moved by `shock`
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ShockImpact {
+    /// Relative price move (e.g., -0.1 for 10% down)
+    pub shock: f64,
+    /// Positions that would become liquidatable
+    pub liquidations: usize,
+    /// Bad debt those positions would realize
+    pub bad_debt: f64,
+    /// Bad debt the insurance fund couldn't cover
+    pub insurance_shortfall: f64,
+}
+
+/// Snapshot of the distribution of risk across the monitored book
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EngineStats {
+    /// When the statistics were computed (Unix timestamp)
+    pub generated_at: i64,
+    /// Positions monitored
+    pub positions: usize,
+    /// Positions left out of the statistics as their symbol couldn't be priced
+    pub unpriced: usize,
+    /// Positions with exposure by margin ratio
+    pub margin_ratio_histogram: Vec<HistogramBucket>,
+    /// Open notional at the current prices, by symbol
+    pub open_notional: BTreeMap<String, SideNotional>,
+    /// Positions by status
+    pub statuses: BTreeMap<PositionStatus, usize>,
+    /// Positions with the lowest health factors, lowest first
+    pub closest_to_liquidation: Vec<PositionUpdate>,
+    /// Potential bad debt at each of [`PRICE_SHOCKS`]
+    pub price_shocks: Vec<ShockImpact>,
+    /// Liquidation candidates carried over to the next check cycle, as of the
+    /// request rather than `generated_at`
+    pub queue_depth: usize,
+    /// How long the oldest carried over candidate has waited (in seconds), as
+    /// of the request
+    pub oldest_queued_age_secs: Option<u64>,
+    /// What the engine holds in memory, as of the request
+    #[serde(default)]
+    pub memory: MemoryStats,
+}
+
+impl EngineStats {
+    /// Statistics of `positions` at `prices`, given their `updates` at those
+    /// prices, with bad debt charged against `insurance_fund`
+    ///
+    /// Positions in symbols without a price are counted as unpriced; collateral
+    /// assets keep their current value under the price shocks.
+    pub fn compute(
+        positions: &[Position],
+        mut updates: Vec<PositionUpdate>,
+        prices: &HashMap<String, Amount>,
+        config: &LiquidationConfig,
+        insurance_fund: f64,
+        now: i64,
+    ) -> Self {
+        let priced: Vec<Position> = positions
+            .iter()
+            .filter(|position| prices.contains_key(&position.symbol))
+            .cloned()
+            .collect();
+
+        let mut open_notional: BTreeMap<String, SideNotional> = BTreeMap::new();
+        for position in &priced {
+            let notional = open_notional.entry(position.symbol.clone()).or_default();
+            let value = position.value(prices[&position.symbol]).as_f64();
+            if position.is_long {
+                notional.long += value;
+            } else {
+                notional.short += value;
+            }
+        }
+
+        let mut statuses = BTreeMap::from([
+            (PositionStatus::Active, 0),
+            (PositionStatus::AtRisk, 0),
+            (PositionStatus::Liquidating, 0),
+        ]);
+        for update in &updates {
+            *statuses.entry(update.status).or_default() += 1;
+        }
+
+        updates.retain(|update| update.health_factor.is_some());
+        let margin_ratio_histogram = histogram(
+            &config.stats.margin_ratio_buckets,
+            updates.iter().map(|update| update.margin_ratio),
+        );
+        updates.sort_by(|a, b| a.health_factor.unwrap_or_default().total_cmp(&b.health_factor.unwrap_or_default()));
+        updates.truncate(CLOSEST_POSITIONS);
+
+        let price_shocks = PRICE_SHOCKS
+            .iter()
+            .filter_map(|&shock| {
+                let shocked = prices
+                    .iter()
+                    .map(|(symbol, price)| (symbol.clone(), *price * amount::from_f64_lossy(1.0 + shock)))
+                    .collect();
+                let report = simulate(&priced, &shocked, config, insurance_fund).ok()?;
+                Some(ShockImpact {
+                    shock,
+                    liquidations: report.liquidations.len(),
+                    bad_debt: report.total_bad_debt,
+                    insurance_shortfall: report.insurance.shortfall,
+                })
+            })
+            .collect();
+
+        Self {
+            generated_at: now,
+            positions: positions.len(),
+            unpriced: positions.len() - priced.len(),
+            margin_ratio_histogram,
+            open_notional,
+            statuses,
+            closest_to_liquidation: updates,
+            price_shocks,
+            queue_depth: 0,
+            oldest_queued_age_secs: None,
+            memory: MemoryStats::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_edges() {
+        let buckets = histogram(&[5.0, 10.0], [-1.0, 4.9, 5.0, 7.0, 10.0, 250.0]);
+        let counts: Vec<usize> = buckets.iter().map(|bucket| bucket.count).collect();
+        assert_eq!(counts, [2, 2, 2]);
+        assert_eq!((buckets[0].lower, buckets[0].upper), (None, Some(5.0)));
+        assert_eq!((buckets[1].lower, buckets[1].upper), (Some(5.0), Some(10.0)));
+        assert_eq!((buckets[2].lower, buckets[2].upper), (Some(10.0), None));
+
+        // Without edges everything falls in one bucket
+        assert_eq!(histogram(&[], [1.0, 2.0])[0].count, 2);
+    }
+
+    #[test]
+    fn test_stats_config_violations() {
+        assert!(StatsConfig::default().violations().is_empty());
+        let config = StatsConfig {
+            margin_ratio_buckets: vec![5.0, 5.0],
+            ..StatsConfig::default()
+        };
+        assert_eq!(config.violations()[0].field, "margin_ratio_buckets");
+    }
+}
@@ -0,0 +1,537 @@
+//! Durable record of liquidation attempts, enabled with the `storage` feature
+//!
+//! Events are kept in a SQLite database. The engine never writes on its hot path:
+//! events are handed to a [`StoreWriter`], which queues them on a bounded channel
+//! drained by a dedicated writer task.
+
+use crate::{
+    address::PositionAddress,
+    amount::{self, Amount, AmountExt},
+    error::LiquidationError,
+    types::LiquidationEvent,
+};
+use tracing::{error, warn};
+use rusqlite::{Connection, Row, params};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Default capacity of the writer queue, beyond which events are dropped and
+/// counted rather than waited for
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// Schema migrations, applied in order and tracked with `PRAGMA user_version`
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE liquidation_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        position TEXT NOT NULL,
+        liquidator TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        amount REAL NOT NULL,
+        remaining_size REAL NOT NULL,
+        remaining_margin REAL NOT NULL,
+        liquidation_price REAL NOT NULL,
+        reward REAL NOT NULL,
+        bad_debt REAL NOT NULL,
+        timestamp INTEGER NOT NULL,
+        signature TEXT NOT NULL,
+        dry_run INTEGER NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX idx_liquidation_events_position ON liquidation_events (position);
+    CREATE INDEX idx_liquidation_events_timestamp ON liquidation_events (timestamp);",
+    // Events recorded before owners were tracked get the default pubkey
+    "ALTER TABLE liquidation_events ADD COLUMN owner TEXT NOT NULL DEFAULT '11111111111111111111111111111111';",
+    "ALTER TABLE liquidation_events ADD COLUMN priority_fee_micro_lamports INTEGER NOT NULL DEFAULT 0;",
+    // Position metadata as a JSON object
+    "ALTER TABLE liquidation_events ADD COLUMN metadata TEXT NOT NULL DEFAULT '{}';",
+    // Backfilled liquidations are deduplicated by signature, and an unfinished
+    // backfill resumes from its cursor
+    "CREATE INDEX idx_liquidation_events_signature ON liquidation_events (signature);
+    CREATE TABLE backfill_cursors (
+        key TEXT PRIMARY KEY,
+        signature TEXT NOT NULL,
+        slot INTEGER NOT NULL
+    );",
+];
+
+const SELECT_EVENTS: &str = "SELECT position, liquidator, symbol, amount, remaining_size, remaining_margin,
+    liquidation_price, reward, bad_debt, timestamp, signature, dry_run, error, owner, priority_fee_micro_lamports,
+    metadata FROM liquidation_events";
+
+/// Aggregated liquidation activity for one symbol or one day
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct LiquidationStats {
+    /// The symbol, or the UTC day formatted as `YYYY-MM-DD`
+    pub key: String,
+    /// Number of liquidations
+    pub count: u64,
+    /// Liquidated notional (in quote currency)
+    pub total_volume: f64,
+    /// Liquidator rewards (in quote currency)
+    pub total_rewards: f64,
+}
+
+/// Last transaction an unfinished backfill processed, the oldest so far as
+/// signatures are listed newest first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackfillCursor {
+    /// Signature of the transaction
+    pub signature: String,
+    /// Slot the transaction landed in
+    pub slot: u64,
+}
+
+/// SQLite-backed store of liquidation events
+pub struct LiquidationStore {
+    conn: Mutex<Connection>,
+}
+
+impl LiquidationStore {
+    /// Open the store at `path`, creating and migrating the schema as needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LiquidationError> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::from_connection(conn)
+    }
+
+    /// Open a store held in memory
+    pub fn open_in_memory() -> Result<Self, LiquidationError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self, LiquidationError> {
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        Self::migrate(&mut conn)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// Apply any migrations newer than the database's schema version
+    fn migrate(conn: &mut Connection) -> Result<(), LiquidationError> {
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > MIGRATIONS.len() {
+            return Err(LiquidationError::StorageError(format!(
+                "Database schema version {} is newer than supported version {}",
+                version,
+                MIGRATIONS.len()
+            )));
+        }
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    fn connection(&self) -> Result<std::sync::MutexGuard<'_, Connection>, LiquidationError> {
+        self.conn
+            .lock()
+            .map_err(|_| LiquidationError::StorageError("Store connection poisoned".to_string()))
+    }
+
+    /// Record a liquidation event
+    pub fn insert_event(&self, event: &LiquidationEvent) -> Result<(), LiquidationError> {
+        self.insert(event, "").map(|_| ())
+    }
+
+    /// Record a liquidation event unless one of the same position in the same
+    /// transaction already was, returning whether it was recorded
+    ///
+    /// A transaction may liquidate several positions, so its signature alone
+    /// doesn't identify the event.
+    pub fn insert_event_once(&self, event: &LiquidationEvent) -> Result<bool, LiquidationError> {
+        self.insert(
+            event,
+            "WHERE NOT EXISTS (SELECT 1 FROM liquidation_events WHERE signature = ?11 AND position = ?1)",
+        )
+    }
+
+    fn insert(&self, event: &LiquidationEvent, condition: &str) -> Result<bool, LiquidationError> {
+        let metadata = serde_json::to_string(&event.metadata)
+            .map_err(|e| LiquidationError::StorageError(format!("Unable to encode metadata: {}", e)))?;
+        let inserted = self.connection()?.execute(
+            &format!(
+                "INSERT INTO liquidation_events (position, liquidator, symbol, amount, remaining_size,
+                    remaining_margin, liquidation_price, reward, bad_debt, timestamp, signature, dry_run, error, owner,
+                    priority_fee_micro_lamports, metadata)
+                 SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16 {}",
+                condition
+            ),
+            params![
+                event.position.to_string(),
+                event.liquidator.to_string(),
+                event.symbol,
+                event.amount.as_f64(),
+                event.remaining_size.as_f64(),
+                event.remaining_margin.as_f64(),
+                event.liquidation_price.as_f64(),
+                event.reward.as_f64(),
+                event.bad_debt.as_f64(),
+                event.timestamp,
+                event.signature,
+                event.dry_run,
+                event.error,
+                event.owner.to_string(),
+                event.priority_fee_micro_lamports,
+                metadata,
+            ],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Cursor of the unfinished backfill identified by `key`, if there's one
+    pub fn backfill_cursor(&self, key: &str) -> Result<Option<BackfillCursor>, LiquidationError> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare("SELECT signature, slot FROM backfill_cursors WHERE key = ?1")?;
+        let mut rows = stmt.query_map(params![key], |row| {
+            Ok(BackfillCursor {
+                signature: row.get(0)?,
+                slot: row.get(1)?,
+            })
+        })?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Move the cursor of the backfill identified by `key` to `cursor`
+    pub fn set_backfill_cursor(&self, key: &str, cursor: &BackfillCursor) -> Result<(), LiquidationError> {
+        self.connection()?.execute(
+            "INSERT INTO backfill_cursors (key, signature, slot) VALUES (?1, ?2, ?3)
+             ON CONFLICT (key) DO UPDATE SET signature = excluded.signature, slot = excluded.slot",
+            params![key, cursor.signature, cursor.slot],
+        )?;
+        Ok(())
+    }
+
+    /// Forget the cursor of a finished backfill, so running it again starts over
+    pub fn clear_backfill_cursor(&self, key: &str) -> Result<(), LiquidationError> {
+        self.connection()?
+            .execute("DELETE FROM backfill_cursors WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+
+    /// All events for a position, oldest first
+    pub fn events_for_position(&self, position: &PositionAddress) -> Result<Vec<LiquidationEvent>, LiquidationError> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&format!("{} WHERE position = ?1 ORDER BY timestamp, id", SELECT_EVENTS))?;
+        let events = stmt
+            .query_map(params![position.to_string()], event_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(events)
+    }
+
+    /// All events with `from_ts <= timestamp < to_ts`, oldest first
+    pub fn events_between(&self, from_ts: i64, to_ts: i64) -> Result<Vec<LiquidationEvent>, LiquidationError> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE timestamp >= ?1 AND timestamp < ?2 ORDER BY timestamp, id",
+            SELECT_EVENTS
+        ))?;
+        let events = stmt
+            .query_map(params![from_ts, to_ts], event_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(events)
+    }
+
+    /// Liquidation activity per symbol
+    ///
+    /// Only liquidations that were actually submitted and succeeded are counted.
+    pub fn stats_by_symbol(&self) -> Result<Vec<LiquidationStats>, LiquidationError> {
+        self.stats("symbol")
+    }
+
+    /// Liquidation activity per UTC day
+    ///
+    /// Only liquidations that were actually submitted and succeeded are counted.
+    pub fn stats_by_day(&self) -> Result<Vec<LiquidationStats>, LiquidationError> {
+        self.stats("date(timestamp, 'unixepoch')")
+    }
+
+    fn stats(&self, group_by: &str) -> Result<Vec<LiquidationStats>, LiquidationError> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {group_by}, COUNT(*), SUM(amount * liquidation_price), SUM(reward)
+             FROM liquidation_events WHERE error IS NULL AND dry_run = 0
+             GROUP BY 1 ORDER BY 1"
+        ))?;
+        let stats = stmt
+            .query_map([], |row| {
+                Ok(LiquidationStats {
+                    key: row.get(0)?,
+                    count: row.get(1)?,
+                    total_volume: row.get(2)?,
+                    total_rewards: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(stats)
+    }
+}
+
+fn parse_pubkey(row: &Row<'_>, index: usize) -> rusqlite::Result<Pubkey> {
+    let value: String = row.get(index)?;
+    Pubkey::from_str(&value)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+fn parse_metadata(row: &Row<'_>, index: usize) -> rusqlite::Result<HashMap<String, String>> {
+    let value: String = row.get(index)?;
+    serde_json::from_str(&value)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+fn parse_amount(row: &Row<'_>, index: usize) -> rusqlite::Result<Amount> {
+    let value: f64 = row.get(index)?;
+    amount::from_f64(value)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Real, Box::new(e)))
+}
+
+fn event_from_row(row: &Row<'_>) -> rusqlite::Result<LiquidationEvent> {
+    Ok(LiquidationEvent {
+        position: parse_pubkey(row, 0)?.into(),
+        owner: parse_pubkey(row, 13)?.into(),
+        liquidator: parse_pubkey(row, 1)?,
+        symbol: row.get(2)?,
+        amount: parse_amount(row, 3)?,
+        remaining_size: parse_amount(row, 4)?,
+        remaining_margin: parse_amount(row, 5)?,
+        liquidation_price: parse_amount(row, 6)?,
+        reward: parse_amount(row, 7)?,
+        bad_debt: parse_amount(row, 8)?,
+        timestamp: row.get(9)?,
+        signature: row.get(10)?,
+        dry_run: row.get(11)?,
+        error: row.get(12)?,
+        priority_fee_micro_lamports: row.get(14)?,
+        metadata: parse_metadata(row, 15)?,
+    })
+}
+
+/// Handle for queueing events to the store's writer task
+#[derive(Debug, Clone)]
+pub struct StoreWriter {
+    tx: mpsc::Sender<LiquidationEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl StoreWriter {
+    /// Start a writer task draining a queue of `capacity` events into `store`
+    ///
+    /// The task finishes once every `StoreWriter` handle has been dropped and the
+    /// queue is empty.
+    pub fn spawn(store: Arc<LiquidationStore>, capacity: usize) -> (Self, JoinHandle<()>) {
+        let (tx, mut rx) = mpsc::channel::<LiquidationEvent>(capacity);
+        let handle = tokio::task::spawn_blocking(move || {
+            while let Some(event) = rx.blocking_recv() {
+                if let Err(e) = store.insert_event(&event) {
+                    error!("Failed to store liquidation event for {}: {}", event.position, e);
+                }
+            }
+        });
+        let dropped = Arc::new(AtomicU64::new(0));
+        (Self { tx, dropped }, handle)
+    }
+
+    /// Queue an event without waiting, dropping and counting it if the queue is full
+    pub fn record(&self, event: LiquidationEvent) {
+        if let Err(e) = self.tx.try_send(event) {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(dropped, "Dropping liquidation event, store queue unavailable: {}", e);
+        }
+    }
+
+    /// Events dropped from a full or closed queue so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::OwnerAddress;
+
+    fn create_event(symbol: &str, timestamp: i64) -> LiquidationEvent {
+        LiquidationEvent {
+            position: PositionAddress::new_unique(),
+            owner: OwnerAddress::new_unique(),
+            liquidator: Pubkey::new_unique(),
+            amount: 0.5,
+            remaining_size: 0.5,
+            remaining_margin: 1000.0,
+            liquidation_price: 50000.0,
+            timestamp,
+            signature: "sig".to_string(),
+            bad_debt: 0.0,
+            symbol: symbol.to_string(),
+            reward: 2500.0,
+            dry_run: false,
+            error: None,
+            priority_fee_micro_lamports: 1_000,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_and_position_lookup() {
+        let store = LiquidationStore::open_in_memory().unwrap();
+        let first = create_event("BTC/USD", 100);
+        let mut second = create_event("BTC/USD", 200);
+        second.position = first.position;
+        second.error = Some("Liquidation failed: rejected".to_string());
+        second.priority_fee_micro_lamports = 7_500;
+        second.metadata = HashMap::from([("external_id".to_string(), "pos-8812".to_string())]);
+        store.insert_event(&second).unwrap();
+        store.insert_event(&first).unwrap();
+        store.insert_event(&create_event("ETH/USD", 150)).unwrap();
+
+        let events = store.events_for_position(&first.position).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, 100);
+        assert_eq!(events[0].liquidator, first.liquidator);
+        assert_eq!(events[1].error.as_deref(), Some("Liquidation failed: rejected"));
+        assert_eq!(events[1].priority_fee_micro_lamports, 7_500);
+        assert_eq!(events[1].metadata, second.metadata);
+        assert!(events[0].metadata.is_empty());
+    }
+
+    #[test]
+    fn test_insert_event_once() {
+        let store = LiquidationStore::open_in_memory().unwrap();
+        let first = create_event("BTC/USD", 100);
+        assert!(store.insert_event_once(&first).unwrap());
+        assert!(!store.insert_event_once(&first).unwrap());
+
+        // Another position liquidated in the same transaction is recorded
+        let mut batched = create_event("BTC/USD", 100);
+        batched.signature = first.signature.clone();
+        assert!(store.insert_event_once(&batched).unwrap());
+        assert_eq!(store.events_between(0, i64::MAX).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_backfill_cursor() {
+        let store = LiquidationStore::open_in_memory().unwrap();
+        assert_eq!(store.backfill_cursor("program").unwrap(), None);
+        for slot in [300, 200] {
+            let cursor = BackfillCursor {
+                signature: format!("sig-{}", slot),
+                slot,
+            };
+            store.set_backfill_cursor("program", &cursor).unwrap();
+            assert_eq!(store.backfill_cursor("program").unwrap(), Some(cursor));
+        }
+        assert_eq!(store.backfill_cursor("other").unwrap(), None);
+        store.clear_backfill_cursor("program").unwrap();
+        assert_eq!(store.backfill_cursor("program").unwrap(), None);
+    }
+
+    #[test]
+    fn test_events_between() {
+        let store = LiquidationStore::open_in_memory().unwrap();
+        for timestamp in [100, 200, 300, 400] {
+            store.insert_event(&create_event("BTC/USD", timestamp)).unwrap();
+        }
+
+        let timestamps: Vec<i64> = store
+            .events_between(200, 400)
+            .unwrap()
+            .iter()
+            .map(|event| event.timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![200, 300]);
+        assert!(store.events_between(500, 600).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stats_skip_failed_and_dry_run() {
+        let store = LiquidationStore::open_in_memory().unwrap();
+        let day = 1_700_006_400; // 2023-11-15T00:00:00Z
+        store.insert_event(&create_event("BTC/USD", day)).unwrap();
+        store.insert_event(&create_event("BTC/USD", day + 86_400)).unwrap();
+        store.insert_event(&create_event("ETH/USD", day + 60)).unwrap();
+
+        let mut failed = create_event("ETH/USD", day);
+        failed.error = Some("timeout".to_string());
+        store.insert_event(&failed).unwrap();
+        let mut simulated = create_event("ETH/USD", day);
+        simulated.dry_run = true;
+        store.insert_event(&simulated).unwrap();
+
+        let by_symbol = store.stats_by_symbol().unwrap();
+        assert_eq!(by_symbol.len(), 2);
+        assert_eq!(
+            by_symbol[0],
+            LiquidationStats {
+                key: "BTC/USD".to_string(),
+                count: 2,
+                total_volume: 50000.0,
+                total_rewards: 5000.0,
+            }
+        );
+        assert_eq!(by_symbol[1].count, 1);
+
+        let by_day = store.stats_by_day().unwrap();
+        let days: Vec<(&str, u64)> = by_day.iter().map(|stats| (stats.key.as_str(), stats.count)).collect();
+        assert_eq!(days, vec![("2023-11-15", 2), ("2023-11-16", 1)]);
+    }
+
+    #[test]
+    fn test_migration_on_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("liquidations.db");
+
+        let store = LiquidationStore::open(&path).unwrap();
+        store.insert_event(&create_event("BTC/USD", 100)).unwrap();
+        drop(store);
+
+        // Reopening keeps existing data and doesn't reapply migrations
+        let store = LiquidationStore::open(&path).unwrap();
+        assert_eq!(store.events_between(0, i64::MAX).unwrap().len(), 1);
+        let version: usize = store
+            .connection()
+            .unwrap()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(LiquidationStore::open(dir.path().join("liquidations.db")).unwrap());
+        let (writer, handle) = StoreWriter::spawn(store.clone(), DEFAULT_QUEUE_CAPACITY);
+
+        // Producers queue through the writer while other tasks write directly
+        let mut tasks = Vec::new();
+        for task in 0..8 {
+            let writer = writer.clone();
+            let store = store.clone();
+            tasks.push(tokio::spawn(async move {
+                for i in 0..25 {
+                    let event = create_event("BTC/USD", task * 100 + i);
+                    if task % 2 == 0 {
+                        writer.record(event);
+                    } else {
+                        store.insert_event(&event).unwrap();
+                    }
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        drop(writer);
+        handle.await.unwrap();
+
+        assert_eq!(store.events_between(0, i64::MAX).unwrap().len(), 200);
+        assert_eq!(store.stats_by_symbol().unwrap()[0].count, 200);
+    }
+}
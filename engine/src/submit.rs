@@ -0,0 +1,518 @@
+use crate::error::{LiquidationError, RpcErrorKind};
+use crate::rate_limit::RateLimiter;
+use crate::rpc_pool::RpcPool;
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_sdk::{
+    bs58,
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+#[cfg(any(test, feature = "testing"))]
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+#[cfg(any(test, feature = "testing"))]
+use std::sync::{Mutex, PoisonError};
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How often the block engine is asked whether a bundle has landed
+const BUNDLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How liquidation transactions are submitted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmitterKind {
+    /// Send through the RPC node
+    #[default]
+    Rpc,
+    /// Send as a tipped Jito bundle through a block engine
+    Jito,
+}
+
+/// Settings for submitting liquidations as Jito bundles
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct JitoConfig {
+    /// Block engine base URL, e.g. https://mainnet.block-engine.jito.wtf
+    pub block_engine_url: String,
+    /// Tip paid to the block engine with every bundle (in lamports)
+    pub tip_lamports: u64,
+    /// Account receiving the tip
+    #[serde_as(as = "DisplayFromStr")]
+    pub tip_account: Pubkey,
+    /// Send through the RPC node instead when the block engine can't be reached
+    pub fallback_to_rpc: bool,
+    /// How long to wait for a bundle to land (in seconds)
+    pub bundle_timeout_secs: u64,
+}
+
+impl Default for JitoConfig {
+    fn default() -> Self {
+        Self {
+            block_engine_url: "https://mainnet.block-engine.jito.wtf".to_string(),
+            tip_lamports: 10_000,
+            // One of the tip accounts published by Jito
+            tip_account: Pubkey::from_str("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5").unwrap(),
+            fallback_to_rpc: true,
+            bundle_timeout_secs: 30,
+        }
+    }
+}
+
+/// Submits liquidation transactions
+///
+/// Confirmation is awaited by the engine's
+/// [`ConfirmationTracker`](crate::confirm::ConfirmationTracker) once a
+/// transaction is submitted.
+#[async_trait]
+pub trait TransactionSubmitter: Send + Sync + fmt::Debug {
+    /// Sign a transaction made of `instructions` with `payer` against `blockhash`
+    /// (a recent blockhash or durable nonce value) and submit it, returning its
+    /// signature once the node or block engine accepted it
+    async fn submit(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        blockhash: Hash,
+    ) -> Result<Signature, LiquidationError>;
+}
+
+/// Submission through an RPC node with `send_transaction`
+#[derive(Debug, Clone)]
+pub(crate) struct RpcSubmitter {
+    rpc: RpcPool,
+    rate_limiter: Arc<RateLimiter>,
+    broadcast: bool,
+}
+
+impl RpcSubmitter {
+    /// Create a submitter sending through the healthiest of the given RPC endpoints
+    pub fn new(rpc: RpcPool, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            rpc,
+            rate_limiter,
+            broadcast: false,
+        }
+    }
+
+    /// Also send every transaction to all other healthy endpoints, so it lands
+    /// even if the sending endpoint's leader forwarding is slow
+    pub fn with_broadcast(mut self, broadcast: bool) -> Self {
+        self.broadcast = broadcast;
+        self
+    }
+
+    /// Send the transaction to every healthy endpoint, returning how many
+    /// accepted it
+    ///
+    /// The client rejects an endpoint reporting a signature other than the
+    /// transaction's, so a misbehaving endpoint counts as a failed send.
+    async fn broadcast(&self, transaction: Transaction) -> usize {
+        let results = self
+            .rpc
+            .broadcast(&self.rate_limiter, move |rpc_client| {
+                // The sending endpoint already runs preflight checks
+                let config = RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    ..Default::default()
+                };
+                rpc_client
+                    .send_transaction_with_config(&transaction, config)
+                    .map_err(LiquidationError::from)
+            })
+            .await;
+        let mut accepted = 0;
+        for result in results {
+            match result {
+                Ok(_) => accepted += 1,
+                Err(e) => warn!("Failed to broadcast transaction: {}", e),
+            }
+        }
+        accepted
+    }
+}
+
+#[async_trait]
+impl TransactionSubmitter for RpcSubmitter {
+    async fn submit(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        blockhash: Hash,
+    ) -> Result<Signature, LiquidationError> {
+        let transaction =
+            Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &[payer], blockhash);
+        let send = |transaction: Transaction| {
+            self.rpc.call(&self.rate_limiter, move |rpc_client| {
+                rpc_client.send_transaction(&transaction).map_err(LiquidationError::from)
+            })
+        };
+        if !self.broadcast {
+            return send(transaction).await;
+        }
+
+        let (signature, accepted) = tokio::join!(send(transaction.clone()), self.broadcast(transaction));
+        let signature = signature?;
+        info!("Transaction {} broadcast to {} endpoints", signature, accepted);
+        Ok(signature)
+    }
+}
+
+/// Why a block engine request failed
+#[derive(Debug)]
+enum BlockEngineError {
+    /// The block engine couldn't be reached or is overloaded
+    Unreachable(String),
+    /// The block engine refused the request
+    Rejected(String),
+}
+
+impl From<BlockEngineError> for LiquidationError {
+    fn from(err: BlockEngineError) -> Self {
+        match err {
+            BlockEngineError::Unreachable(msg) => {
+                Self::rpc(RpcErrorKind::Transport, format!("block engine unreachable: {}", msg))
+            }
+            BlockEngineError::Rejected(msg) => Self::LiquidationFailed(format!("bundle rejected: {}", msg)),
+        }
+    }
+}
+
+/// Submission as a Jito bundle carrying a tip to the block engine
+pub(crate) struct JitoSubmitter {
+    http: reqwest::Client,
+    config: JitoConfig,
+    fallback: Option<RpcSubmitter>,
+}
+
+impl JitoSubmitter {
+    /// Create a submitter posting bundles per `config`, falling back to the given
+    /// RPC endpoints if enabled
+    pub fn new(rpc: RpcPool, rate_limiter: Arc<RateLimiter>, config: JitoConfig) -> Self {
+        Self {
+            fallback: config.fallback_to_rpc.then(|| RpcSubmitter::new(rpc, rate_limiter)),
+            http: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    /// Transaction for the bundle: the liquidation instructions followed by the tip
+    pub fn bundle_transaction(&self, instructions: &[Instruction], payer: &Keypair, blockhash: Hash) -> Transaction {
+        let mut instructions = instructions.to_vec();
+        instructions.push(system_instruction::transfer(
+            &payer.pubkey(),
+            &self.config.tip_account,
+            self.config.tip_lamports,
+        ));
+        Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &[payer], blockhash)
+    }
+
+    /// Call a block engine JSON-RPC method, returning its result
+    async fn call(&self, method: &str, params: Value) -> Result<Value, BlockEngineError> {
+        let url = format!("{}/api/v1/bundles", self.config.block_engine_url.trim_end_matches('/'));
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response = self
+            .http
+            .post(&url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| BlockEngineError::Unreachable(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(BlockEngineError::Unreachable(format!("{} returned {}", url, status)));
+        }
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| BlockEngineError::Rejected(format!("{} returned {}: {}", url, status, e)))?;
+        if let Some(error) = body.get("error") {
+            return Err(BlockEngineError::Rejected(error.to_string()));
+        }
+        body.get("result")
+            .cloned()
+            .ok_or_else(|| BlockEngineError::Rejected(format!("no result in response from {}", url)))
+    }
+
+    /// Post a bundle holding the transaction, returning the bundle id
+    async fn send_bundle(&self, transaction: &Transaction) -> Result<String, BlockEngineError> {
+        let serialized = bincode::serialize(transaction).map_err(|e| BlockEngineError::Rejected(e.to_string()))?;
+        let encoded = bs58::encode(serialized).into_string();
+        let result = self.call("sendBundle", json!([[encoded]])).await?;
+        result
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| BlockEngineError::Rejected(format!("unexpected bundle id {}", result)))
+    }
+
+    /// Poll the bundle's status until it lands or the timeout passes
+    async fn await_bundle(&self, bundle_id: &str) -> Result<(), LiquidationError> {
+        let deadline = Instant::now() + Duration::from_secs(self.config.bundle_timeout_secs);
+        while Instant::now() < deadline {
+            let result = self.call("getBundleStatuses", json!([[bundle_id]])).await?;
+            if let Some(status) = result["value"].get(0).filter(|status| !status.is_null()) {
+                let confirmation = status["confirmation_status"].as_str().unwrap_or_default();
+                if matches!(confirmation, "confirmed" | "finalized") {
+                    return match status.get("err") {
+                        Some(err) if err.get("Ok").is_none() && !err.is_null() => Err(
+                            LiquidationError::LiquidationFailed(format!("bundle {} failed: {}", bundle_id, err)),
+                        ),
+                        _ => Ok(()),
+                    };
+                }
+            }
+            tokio::time::sleep(BUNDLE_POLL_INTERVAL).await;
+        }
+        Err(LiquidationError::ConfirmationTimeout)
+    }
+}
+
+impl fmt::Debug for JitoSubmitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JitoSubmitter")
+            .field("config", &self.config)
+            .field("fallback", &self.fallback)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl TransactionSubmitter for JitoSubmitter {
+    async fn submit(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        blockhash: Hash,
+    ) -> Result<Signature, LiquidationError> {
+        let transaction = self.bundle_transaction(instructions, payer, blockhash);
+        let bundle_id = match self.send_bundle(&transaction).await {
+            Ok(bundle_id) => bundle_id,
+            Err(BlockEngineError::Unreachable(e)) if self.fallback.is_some() => {
+                warn!(
+                    "Block engine {} unreachable ({}), submitting through RPC",
+                    self.config.block_engine_url, e
+                );
+                return self.fallback.as_ref().unwrap().submit(instructions, payer, blockhash).await;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        info!("Submitted bundle {} for transaction {}", bundle_id, transaction.signatures[0]);
+        self.await_bundle(&bundle_id).await?;
+        Ok(transaction.signatures[0])
+    }
+}
+
+/// Transaction received by a `MockSubmitter`
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submission {
+    /// Instructions of the transaction
+    pub instructions: Vec<Instruction>,
+    /// Blockhash or nonce value the transaction was signed against
+    pub blockhash: Hash,
+}
+
+/// Mock submitter for testing, accepting every transaction unless told otherwise
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Clone, Default)]
+pub struct MockSubmitter {
+    submitted: Arc<Mutex<Vec<Submission>>>,
+    errors: Arc<Mutex<VecDeque<String>>>,
+    error: Arc<Mutex<Option<String>>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockSubmitter {
+    /// Create a mock submitter accepting every transaction
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make later submissions fail with `LiquidationFailed` and the given message
+    pub fn set_error(&self, error: Option<&str>) {
+        *self.error.lock().unwrap_or_else(PoisonError::into_inner) = error.map(str::to_string);
+    }
+
+    /// Make the next submission fail with `RpcError` and the given message, ahead
+    /// of any error set with `set_error`
+    pub fn fail_next(&self, error: &str) {
+        self.errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_back(error.to_string());
+    }
+
+    /// Every transaction submitted so far
+    pub fn submitted(&self) -> Vec<Submission> {
+        self.submitted.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[async_trait]
+impl TransactionSubmitter for MockSubmitter {
+    async fn submit(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        blockhash: Hash,
+    ) -> Result<Signature, LiquidationError> {
+        self.submitted
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Submission {
+                instructions: instructions.to_vec(),
+                blockhash,
+            });
+        if let Some(error) = self.errors.lock().unwrap_or_else(PoisonError::into_inner).pop_front() {
+            return Err(LiquidationError::rpc(RpcErrorKind::Transport, error));
+        }
+        if let Some(error) = self.error.lock().unwrap_or_else(PoisonError::into_inner).clone() {
+            return Err(LiquidationError::LiquidationFailed(error));
+        }
+        let transaction = Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &[payer], blockhash);
+        Ok(transaction.signatures[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute;
+    use crate::rpc_pool::{MockEndpoint, RpcPoolConfig};
+    use solana_client::rpc_client::RpcClient;
+    use solana_sdk::system_program;
+
+    /// Block engine URL nothing is listening on
+    fn unreachable_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    fn create_submitter(fallback_to_rpc: bool) -> JitoSubmitter {
+        let config = JitoConfig {
+            block_engine_url: unreachable_url(),
+            tip_account: Pubkey::new_unique(),
+            tip_lamports: 25_000,
+            fallback_to_rpc,
+            ..Default::default()
+        };
+        let rpc_client = Arc::new(RpcClient::new_mock("succeeds".to_string()));
+        JitoSubmitter::new(rpc_client.into(), Arc::new(RateLimiter::default()), config)
+    }
+
+    #[test]
+    fn test_tip_instruction_appended() {
+        let submitter = create_submitter(false);
+        let payer = Keypair::new();
+        let instructions = compute::budget_instructions(200_000, 1_000);
+
+        let transaction = submitter.bundle_transaction(&instructions, &payer, Hash::new_unique());
+        let message = &transaction.message;
+        assert_eq!(message.instructions.len(), 3);
+        // The liquidation instructions come first, untouched
+        for (compiled, instruction) in message.instructions.iter().zip(&instructions) {
+            assert_eq!(message.account_keys[compiled.program_id_index as usize], instruction.program_id);
+            assert_eq!(compiled.data, instruction.data);
+        }
+
+        let tip = &message.instructions[2];
+        let expected = system_instruction::transfer(&payer.pubkey(), &submitter.config.tip_account, 25_000);
+        assert_eq!(message.account_keys[tip.program_id_index as usize], system_program::id());
+        assert_eq!(tip.data, expected.data);
+        let accounts: Vec<Pubkey> = tip.accounts.iter().map(|&index| message.account_keys[index as usize]).collect();
+        assert_eq!(accounts, vec![payer.pubkey(), submitter.config.tip_account]);
+        assert!(transaction.verify().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_rpc_when_block_engine_unreachable() {
+        let submitter = create_submitter(true);
+        let payer = Keypair::new();
+
+        let signature = submitter
+            .submit(&compute::budget_instructions(200_000, 1_000), &payer, Hash::new_unique())
+            .await
+            .unwrap();
+        assert_ne!(signature, Signature::default());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_block_engine_without_fallback() {
+        let submitter = create_submitter(false);
+
+        let instructions = compute::budget_instructions(200_000, 1_000);
+        match submitter.submit(&instructions, &Keypair::new(), Hash::new_unique()).await {
+            Err(LiquidationError::RpcError { message, .. }) => {
+                assert!(message.contains("block engine unreachable"), "{}", message)
+            }
+            other => panic!("expected an RPC error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_sends_to_every_endpoint() {
+        // The last endpoint reports a bogus signature for anything it's sent
+        let endpoints = [
+            MockEndpoint::new("a", "succeeds"),
+            MockEndpoint::new("b", "succeeds"),
+            MockEndpoint::new("c", "malicious"),
+        ];
+        let clients = endpoints.iter().map(MockEndpoint::client).collect();
+        let rpc = RpcPool::new(clients, RpcPoolConfig::default()).unwrap();
+        let submitter = RpcSubmitter::new(rpc, Arc::new(RateLimiter::default())).with_broadcast(true);
+        let payer = Keypair::new();
+        let blockhash = Hash::new_unique();
+        let instructions = compute::budget_instructions(200_000, 1_000);
+
+        let signature = submitter.submit(&instructions, &payer, blockhash).await.unwrap();
+        let transaction = Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &[&payer], blockhash);
+        assert_eq!(signature, transaction.signatures[0]);
+        // Sent through one endpoint, broadcast through all of them
+        let sent: Vec<usize> = endpoints.iter().map(|endpoint| endpoint.requests("sendTransaction")).collect();
+        assert!(sent.iter().all(|&count| count >= 1));
+        assert_eq!(sent.iter().sum::<usize>(), 4);
+        // The bogus signature is rejected without failing the submission
+        assert_eq!(submitter.broadcast(transaction).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_mock_submitter() {
+        let submitter = MockSubmitter::new();
+        let payer = Keypair::new();
+        let instructions = compute::budget_instructions(200_000, 1_000);
+        let blockhash = Hash::new_unique();
+
+        assert!(submitter.submit(&instructions, &payer, blockhash).await.is_ok());
+        submitter.set_error(Some("insufficient funds"));
+        submitter.fail_next("blockhash not found");
+        assert!(matches!(
+            submitter.submit(&instructions, &payer, blockhash).await,
+            Err(LiquidationError::RpcError { .. })
+        ));
+        assert!(matches!(
+            submitter.submit(&instructions, &payer, blockhash).await,
+            Err(LiquidationError::LiquidationFailed(_))
+        ));
+        assert_eq!(submitter.submitted().len(), 3);
+        assert_eq!(
+            submitter.submitted()[0],
+            Submission {
+                instructions,
+                blockhash
+            }
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Readiness and liveness notifications to systemd
+//!
+//! Under a unit with `Type=notify` systemd passes the socket to notify in
+//! `NOTIFY_SOCKET`. The engine then reports `READY=1` once preflight passed and
+//! it warmed up, `WATCHDOG=1` after every check cycle, `STOPPING=1` when shut
+//! down and a `STATUS=` line of its mode and last cycle along the way:
+//!
+//! ```ini
+//! [Service]
+//! Type=notify
+//! ExecStart=/usr/local/bin/liquidation-engine --config /etc/liquidation-engine.toml
+//! WatchdogSec=30
+//! ```
+//!
+//! The watchdog is only pinged once a cycle completes, so a wedged cycle stops
+//! the pings and systemd restarts the engine; `WatchdogSec` should leave room for
+//! `check_interval_ms` and the slowest cycle. Without `NOTIFY_SOCKET` nothing is
+//! sent.
+
+use std::io;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+use tracing::warn;
+
+/// Environment variable systemd passes the notification socket's path in
+pub(crate) const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+
+/// Sends state changes to the service manager's notification socket
+#[derive(Debug)]
+pub struct SystemdNotifier {
+    #[cfg(unix)]
+    socket: UnixDatagram,
+    watchdog_interval: Option<Duration>,
+}
+
+impl SystemdNotifier {
+    /// Notifier of the socket systemd passed in `NOTIFY_SOCKET`, with the
+    /// watchdog interval it asked for in `WATCHDOG_USEC`
+    ///
+    /// Returns `None` when the engine doesn't run under a `Type=notify` unit, or
+    /// the socket can't be connected to.
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var(NOTIFY_SOCKET_ENV).ok().filter(|path| !path.is_empty())?;
+        let notifier = match Self::connect(&path) {
+            Ok(notifier) => notifier,
+            Err(e) => {
+                warn!("Not notifying systemd at {}: {}", path, e);
+                return None;
+            }
+        };
+        // The interval is meant for the process systemd started, not its children
+        let for_us = std::env::var("WATCHDOG_PID").ok().is_none_or(|pid| pid == std::process::id().to_string());
+        let watchdog_interval = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse().ok())
+            .filter(|_| for_us)
+            .map(Duration::from_micros);
+        Some(Self {
+            watchdog_interval,
+            ..notifier
+        })
+    }
+
+    /// Notifier of the socket at `path`, `@`-prefixed for an abstract socket
+    #[cfg(unix)]
+    pub fn connect(path: &str) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.connect_addr(&address)?;
+            }
+            _ => socket.connect(path)?,
+        }
+        // A full socket drops the notification rather than holding up a cycle
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            watchdog_interval: None,
+        })
+    }
+
+    /// Unix sockets are needed to notify systemd
+    #[cfg(not(unix))]
+    pub fn connect(_path: &str) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "notification sockets need Unix"))
+    }
+
+    /// How often systemd expects the watchdog to be pinged, if it watches
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval
+    }
+
+    /// Send `state`: newline-separated assignments such as `READY=1`
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        #[cfg(unix)]
+        self.socket.send(state.as_bytes())?;
+        #[cfg(not(unix))]
+        let _ = state;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notifies_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let listener = UnixDatagram::bind(&path).unwrap();
+        let notifier = SystemdNotifier::connect(path.to_str().unwrap()).unwrap();
+        assert_eq!(notifier.watchdog_interval(), None);
+
+        notifier.notify("READY=1\nSTATUS=running").unwrap();
+        let mut buf = [0; 64];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=running");
+    }
+
+    #[test]
+    fn test_missing_socket_fails_to_connect() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(SystemdNotifier::connect(dir.path().join("missing.sock").to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use crate::address::PositionAddress;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Liquidations admitted during one check cycle, against the per-cycle caps
+#[derive(Debug, Default)]
+pub(crate) struct CycleThrottle {
+    max_per_symbol: Option<usize>,
+    max_notional: Option<f64>,
+    /// Liquidations per symbol so far this cycle
+    liquidations: BTreeMap<String, usize>,
+    /// Notional liquidated so far this cycle (in quote currency)
+    notional: f64,
+    /// Candidates turned away this cycle
+    throttled: usize,
+}
+
+impl CycleThrottle {
+    /// Start a cycle with the given caps, each unlimited if `None`
+    pub(crate) fn new(max_per_symbol: Option<usize>, max_notional: Option<f64>) -> Self {
+        Self {
+            max_per_symbol,
+            max_notional,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a liquidation of about `notional` in `symbol` fits under the caps,
+    /// counting it as throttled if not
+    ///
+    /// The first liquidation of a cycle is admitted whatever its notional, so a
+    /// position larger than the notional cap isn't held back forever.
+    pub(crate) fn admits(&mut self, symbol: &str, notional: f64) -> bool {
+        let symbol_full = self
+            .max_per_symbol
+            .is_some_and(|max| self.liquidations.get(symbol).copied().unwrap_or_default() >= max);
+        let notional_full = self
+            .max_notional
+            .is_some_and(|max| self.notional > 0.0 && self.notional + notional > max);
+        if symbol_full || notional_full {
+            self.throttled += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Count a liquidation of `notional` in `symbol`
+    pub(crate) fn record(&mut self, symbol: &str, notional: f64) {
+        *self.liquidations.entry(symbol.to_string()).or_default() += 1;
+        self.notional += notional;
+    }
+
+    /// Liquidations per symbol, notional liquidated and candidates throttled this cycle
+    pub(crate) fn totals(&self) -> (BTreeMap<String, usize>, f64, usize) {
+        (self.liquidations.clone(), self.notional, self.throttled)
+    }
+}
+
+/// Pauses liquidation when too many fire within a window
+#[derive(Debug, Default)]
+pub(crate) struct CircuitBreaker {
+    /// Timestamps of recent liquidations, oldest first
+    recent: VecDeque<i64>,
+    /// When the breaker tripped, while liquidation is paused
+    tripped_at: Option<i64>,
+}
+
+impl CircuitBreaker {
+    /// Create a closed breaker
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a liquidation at `now`, tripping the breaker if more than `max`
+    /// have fired within `window_secs`; returns whether this liquidation tripped it
+    pub(crate) fn record(&mut self, now: i64, max: Option<usize>, window_secs: u64) -> bool {
+        let cutoff = now.saturating_sub(window_secs as i64);
+        self.recent.push_back(now);
+        while self.recent.front().is_some_and(|timestamp| *timestamp <= cutoff) {
+            self.recent.pop_front();
+        }
+        let Some(max) = max else {
+            return false;
+        };
+        if self.tripped_at.is_none() && self.recent.len() > max {
+            self.tripped_at = Some(now);
+            return true;
+        }
+        false
+    }
+
+    /// When the breaker tripped, if liquidation is paused
+    pub(crate) fn tripped_at(&self) -> Option<i64> {
+        self.tripped_at
+    }
+
+    /// Close the breaker, forgetting the liquidations that tripped it
+    pub(crate) fn reset(&mut self) {
+        self.recent.clear();
+        self.tripped_at = None;
+    }
+}
+
+/// A liquidation candidate carried over from the check cycle that first
+/// couldn't liquidate it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct QueuedCandidate {
+    pub(crate) address: PositionAddress,
+    /// When the candidate was first carried over (Unix timestamp)
+    pub(crate) queued_at: i64,
+}
+
+/// Liquidation candidates carried over between check cycles by the
+/// concurrency cap, the per-cycle caps or a liquidation already in flight,
+/// oldest first, and the concurrency raised to work through them
+#[derive(Debug, Default)]
+pub(crate) struct CandidateQueue {
+    queue: VecDeque<QueuedCandidate>,
+    /// Concurrency allowed above the configured one while the queue is overdue
+    scaled_concurrency: Option<usize>,
+}
+
+impl CandidateQueue {
+    /// Create an empty queue
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the candidates a cycle at `now` carried over, in place of the last
+    /// cycle's
+    ///
+    /// Candidates already queued keep their place and when they were first
+    /// queued. Those not carried over again, because they were liquidated or are
+    /// no longer liquidatable at the cycle's prices, leave the queue.
+    pub(crate) fn carry_over(&mut self, carried: &[PositionAddress], now: i64) {
+        self.queue.retain(|candidate| carried.contains(&candidate.address));
+        for address in carried {
+            if !self.contains(address) {
+                self.queue.push_back(QueuedCandidate {
+                    address: *address,
+                    queued_at: now,
+                });
+            }
+        }
+    }
+
+    /// Whether a position is queued
+    pub(crate) fn contains(&self, address: &PositionAddress) -> bool {
+        self.queue.iter().any(|candidate| candidate.address == *address)
+    }
+
+    /// Candidates queued
+    pub(crate) fn depth(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// How long the oldest queued candidate has waited at `now` (in seconds),
+    /// if any is queued
+    pub(crate) fn oldest_age(&self, now: i64) -> Option<u64> {
+        let oldest = self.queue.front()?;
+        Some(now.saturating_sub(oldest.queued_at).max(0) as u64)
+    }
+
+    /// Candidates queued, oldest first
+    pub(crate) fn candidates(&self) -> impl Iterator<Item = &QueuedCandidate> {
+        self.queue.iter()
+    }
+
+    /// Concurrency the next cycle liquidates at, given the configured one
+    pub(crate) fn concurrency(&self, configured: usize) -> usize {
+        self.scaled_concurrency.unwrap_or(configured).max(configured)
+    }
+
+    /// Double the concurrency, up to `ceiling`, while the oldest candidate has
+    /// waited longer than `max_age_secs` at `now`, and fall back to `configured`
+    /// once it hasn't; returns the concurrency the next cycle liquidates at
+    pub(crate) fn scale_concurrency(&mut self, configured: usize, ceiling: usize, max_age_secs: u64, now: i64) -> usize {
+        self.scaled_concurrency = if self.oldest_age(now).is_some_and(|age| age > max_age_secs) {
+            Some(self.concurrency(configured).saturating_mul(2).min(ceiling).max(configured))
+        } else {
+            None
+        };
+        self.concurrency(configured)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle_caps() {
+        let mut throttle = CycleThrottle::new(Some(2), Some(100_000.0));
+        // Oversized, but the first of the cycle
+        assert!(throttle.admits("BTC/USD", 150_000.0));
+        throttle.record("BTC/USD", 150_000.0);
+        assert!(!throttle.admits("SOL/USD", 1_000.0));
+
+        let mut throttle = CycleThrottle::new(Some(2), None);
+        for _ in 0..2 {
+            assert!(throttle.admits("BTC/USD", 50_000.0));
+            throttle.record("BTC/USD", 50_000.0);
+        }
+        assert!(!throttle.admits("BTC/USD", 50_000.0));
+        assert!(throttle.admits("SOL/USD", 1_000.0));
+        let (liquidations, notional, throttled) = throttle.totals();
+        assert_eq!(liquidations, BTreeMap::from([("BTC/USD".to_string(), 2)]));
+        assert_eq!((notional, throttled), (100_000.0, 1));
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let mut breaker = CircuitBreaker::new();
+        // Three in a minute are fine, spread out or not
+        assert!(!breaker.record(0, Some(3), 60));
+        assert!(!breaker.record(30, Some(3), 60));
+        assert!(!breaker.record(61, Some(3), 60));
+        assert!(!breaker.record(70, Some(3), 60));
+        assert!(breaker.record(80, Some(3), 60));
+        assert_eq!(breaker.tripped_at(), Some(80));
+        // Stays tripped until reset
+        assert!(!breaker.record(200, Some(3), 60));
+        assert_eq!(breaker.tripped_at(), Some(80));
+        breaker.reset();
+        assert_eq!(breaker.tripped_at(), None);
+        assert!(!breaker.record(210, None, 60));
+    }
+
+    #[test]
+    fn test_candidate_queue() {
+        let (first, second, third) =
+            (PositionAddress::new_unique(), PositionAddress::new_unique(), PositionAddress::new_unique());
+        let mut queue = CandidateQueue::new();
+        assert_eq!((queue.depth(), queue.oldest_age(0)), (0, None));
+
+        queue.carry_over(&[first, second], 100);
+        queue.carry_over(&[third, second], 130);
+        // The first left the queue; the second kept its place and age
+        let queued: Vec<(PositionAddress, i64)> =
+            queue.candidates().map(|candidate| (candidate.address, candidate.queued_at)).collect();
+        assert_eq!(queued, [(second, 100), (third, 130)]);
+        assert_eq!(queue.oldest_age(140), Some(40));
+
+        // Overdue queues double the concurrency up to the ceiling
+        assert_eq!(queue.scale_concurrency(2, 5, 60, 140), 2);
+        assert_eq!(queue.scale_concurrency(2, 5, 30, 140), 4);
+        assert_eq!(queue.scale_concurrency(2, 5, 30, 150), 5);
+        assert_eq!(queue.concurrency(2), 5);
+        // and drop back once drained
+        queue.carry_over(&[], 160);
+        assert_eq!(queue.scale_concurrency(2, 5, 30, 160), 2);
+    }
+}
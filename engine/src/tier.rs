@@ -0,0 +1,181 @@
+use crate::error::ConfigViolation;
+use crate::position::MarginParams;
+
+/// Margin requirements of positions whose notional reaches a threshold
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MarginTier {
+    /// Notional from which the tier applies (in quote currency)
+    pub notional_threshold: f64,
+    /// Maintenance margin ratio positions in the tier are held to, at least
+    /// (e.g., 0.1 for 10%)
+    pub maintenance_margin: f64,
+    /// Leverage positions in the tier may be opened at
+    pub max_leverage: f64,
+}
+
+/// Margin tiers of a symbol, ordered by notional threshold
+///
+/// Larger positions are harder to liquidate without slippage, so each tier
+/// holds positions to a maintenance margin at least that of the tier below it
+/// and allows them no more leverage. Positions below the first threshold are
+/// held to their market's margin alone.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct MarginTierSchedule {
+    pub tiers: Vec<MarginTier>,
+}
+
+impl MarginTierSchedule {
+    pub fn new(tiers: Vec<MarginTier>) -> Self {
+        Self { tiers }
+    }
+
+    /// The tier a position of the given notional falls in, if it reaches the
+    /// first threshold
+    pub fn tier(&self, notional: f64) -> Option<&MarginTier> {
+        self.tiers.iter().rev().find(|tier| notional >= tier.notional_threshold)
+    }
+
+    /// Maintenance margin ratios of a position of the given notional: `base`,
+    /// with each side raised to its tier's margin
+    pub fn params(&self, base: MarginParams, notional: f64) -> MarginParams {
+        match self.tier(notional) {
+            Some(tier) => MarginParams {
+                long: base.long.max(tier.maintenance_margin),
+                short: base.short.max(tier.maintenance_margin),
+            },
+            None => base,
+        }
+    }
+
+    /// Every inconsistency in the schedule, with fields prefixed by the
+    /// offending tier's index, e.g. `[1].maintenance_margin`
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if self.tiers.is_empty() {
+            violations.push(ConfigViolation::new("", "must have at least one tier"));
+        }
+        for (index, tier) in self.tiers.iter().enumerate() {
+            let field = |name: &str| format!("[{}].{}", index, name);
+            if !(tier.notional_threshold.is_finite() && tier.notional_threshold >= 0.0) {
+                violations.push(ConfigViolation::new(
+                    field("notional_threshold"),
+                    format!("must be non-negative, got {}", tier.notional_threshold),
+                ));
+            }
+            if !(tier.maintenance_margin > 0.0 && tier.maintenance_margin < 1.0) {
+                violations.push(ConfigViolation::new(
+                    field("maintenance_margin"),
+                    format!("must be between 0 and 1, got {}", tier.maintenance_margin),
+                ));
+            }
+            if !(tier.max_leverage.is_finite() && tier.max_leverage > 0.0) {
+                violations.push(ConfigViolation::new(
+                    field("max_leverage"),
+                    format!("must be positive, got {}", tier.max_leverage),
+                ));
+            }
+            let Some(previous) = index.checked_sub(1).map(|previous| &self.tiers[previous]) else {
+                continue;
+            };
+            if tier.notional_threshold <= previous.notional_threshold {
+                violations.push(ConfigViolation::new(
+                    field("notional_threshold"),
+                    format!(
+                        "must exceed the previous tier's {}, got {}",
+                        previous.notional_threshold, tier.notional_threshold
+                    ),
+                ));
+            }
+            if tier.maintenance_margin < previous.maintenance_margin {
+                violations.push(ConfigViolation::new(
+                    field("maintenance_margin"),
+                    format!(
+                        "must be at least the previous tier's {}, got {}",
+                        previous.maintenance_margin, tier.maintenance_margin
+                    ),
+                ));
+            }
+            if tier.max_leverage > previous.max_leverage {
+                violations.push(ConfigViolation::new(
+                    field("max_leverage"),
+                    format!(
+                        "must be at most the previous tier's {}, got {}",
+                        previous.max_leverage, tier.max_leverage
+                    ),
+                ));
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_schedule() -> MarginTierSchedule {
+        MarginTierSchedule::new(vec![
+            MarginTier {
+                notional_threshold: 0.0,
+                maintenance_margin: 0.05,
+                max_leverage: 20.0,
+            },
+            MarginTier {
+                notional_threshold: 100_000.0,
+                maintenance_margin: 0.1,
+                max_leverage: 10.0,
+            },
+            MarginTier {
+                notional_threshold: 1_000_000.0,
+                maintenance_margin: 0.2,
+                max_leverage: 5.0,
+            },
+        ])
+    }
+
+    #[test]
+    fn test_tier_lookup() {
+        let schedule = create_schedule();
+        assert_eq!(schedule.tier(50_000.0).unwrap().maintenance_margin, 0.05);
+        // Thresholds belong to the tier they start
+        assert_eq!(schedule.tier(100_000.0).unwrap().maintenance_margin, 0.1);
+        assert_eq!(schedule.tier(5_000_000.0).unwrap().maintenance_margin, 0.2);
+
+        let schedule = MarginTierSchedule::new(schedule.tiers[1..].to_vec());
+        assert!(schedule.tier(50_000.0).is_none());
+        assert_eq!(schedule.params(MarginParams::shared(0.05), 50_000.0), MarginParams::shared(0.05));
+    }
+
+    #[test]
+    fn test_params_raise_each_side() {
+        let schedule = create_schedule();
+        let base = MarginParams { long: 0.05, short: 0.15 };
+        assert_eq!(schedule.params(base, 50_000.0), base);
+        assert_eq!(schedule.params(base, 200_000.0), MarginParams { long: 0.1, short: 0.15 });
+        assert_eq!(schedule.params(base, 2_000_000.0), MarginParams::shared(0.2));
+    }
+
+    #[test]
+    fn test_violations() {
+        assert!(create_schedule().violations().is_empty());
+        assert_eq!(
+            MarginTierSchedule::default().violations(),
+            vec![ConfigViolation::new("", "must have at least one tier")]
+        );
+
+        let mut schedule = create_schedule();
+        schedule.tiers[1].notional_threshold = 0.0;
+        schedule.tiers[1].maintenance_margin = 0.04;
+        schedule.tiers[2].max_leverage = 50.0;
+        let violations: Vec<String> = schedule.violations().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            violations,
+            vec![
+                "[1].notional_threshold must exceed the previous tier's 0, got 0",
+                "[1].maintenance_margin must be at least the previous tier's 0.05, got 0.04",
+                "[2].max_leverage must be at most the previous tier's 10, got 50",
+            ]
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use crate::error::LiquidationError;
+use crate::instruction::associated_token_account;
+use crate::preflight::PreflightRpc;
+use anchor_spl::token_2022::spl_token_2022::{
+    extension::{BaseStateWithExtensions, StateWithExtensions, transfer_fee::TransferFeeConfig},
+    state::{Account as TokenAccount, Mint},
+};
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use tracing::warn;
+
+/// How often the liquidator's repayment balances are read
+pub const TOKEN_BALANCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The liquidator's associated token accounts in one program's market
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketTokenAccounts {
+    /// Mint of the market's collateral, which liquidations are rewarded in
+    pub collateral_mint: Pubkey,
+    /// Mint of the market's debt, which liquidations repay
+    pub debt_mint: Pubkey,
+    /// Token program owning the collateral's mint, classic or Token-2022
+    pub collateral_token_program: Pubkey,
+    /// Token program owning the debt's mint, classic or Token-2022
+    pub debt_token_program: Pubkey,
+    /// Token account the reward is paid into
+    pub collateral: Pubkey,
+    /// Token account the repayment is taken from
+    pub debt: Pubkey,
+}
+
+impl MarketTokenAccounts {
+    /// Derive `liquidator`'s token accounts for a market's mints, each a mint
+    /// of the token program given with it
+    pub fn derive(
+        liquidator: &Pubkey,
+        (collateral_mint, collateral_token_program): (Pubkey, Pubkey),
+        (debt_mint, debt_token_program): (Pubkey, Pubkey),
+    ) -> Self {
+        Self {
+            collateral_mint,
+            debt_mint,
+            collateral_token_program,
+            debt_token_program,
+            collateral: associated_token_account(liquidator, &collateral_mint, &collateral_token_program),
+            debt: associated_token_account(liquidator, &debt_mint, &debt_token_program),
+        }
+    }
+
+    /// Each token account with its role, mint and the mint's token program,
+    /// collateral first
+    pub fn roles(&self) -> [(&'static str, Pubkey, Pubkey, Pubkey); 2] {
+        [
+            ("collateral", self.collateral_mint, self.collateral_token_program, self.collateral),
+            ("debt", self.debt_mint, self.debt_token_program, self.debt),
+        ]
+    }
+}
+
+/// Token program owning a mint or token account, failing unless it's the
+/// classic token program or Token-2022
+pub fn token_program_of(account: &Account) -> Result<Pubkey, LiquidationError> {
+    if account.owner == anchor_spl::token::ID || account.owner == anchor_spl::token_2022::ID {
+        Ok(account.owner)
+    } else {
+        Err(LiquidationError::Other(format!("{} is not a token program", account.owner)))
+    }
+}
+
+/// Decimals of a mint of either token program
+pub(crate) fn mint_decimals(mint: &Account) -> Result<u8, LiquidationError> {
+    let mint = StateWithExtensions::<Mint>::unpack(&mint.data)
+        .map_err(|e| LiquidationError::Other(format!("Invalid mint: {}", e)))?;
+    Ok(mint.base.decimals)
+}
+
+/// Fee a mint withholds from a transfer of `amount` (in its smallest units)
+/// in `epoch`: zero unless it's a Token-2022 mint with the transfer fee
+/// extension
+pub fn transfer_fee(mint: &Account, epoch: u64, amount: u64) -> Result<u64, LiquidationError> {
+    let state = StateWithExtensions::<Mint>::unpack(&mint.data)
+        .map_err(|e| LiquidationError::Other(format!("Invalid mint: {}", e)))?;
+    match state.get_extension::<TransferFeeConfig>() {
+        Ok(config) => config
+            .calculate_epoch_fee(epoch, amount)
+            .ok_or_else(|| LiquidationError::Other(format!("Transfer fee of {} overflows", amount))),
+        Err(_) => Ok(0),
+    }
+}
+
+/// Balance of a token account of either token program (in the mint's units),
+/// given the mint's decimals
+///
+/// A Token-2022 account's balance excludes the transfer fees withheld in it,
+/// which only the mint's withdraw authority can take.
+pub(crate) fn token_balance(account: &Account, decimals: u8) -> Result<f64, LiquidationError> {
+    let token_account = StateWithExtensions::<TokenAccount>::unpack(&account.data)
+        .map_err(|e| LiquidationError::Other(format!("Invalid token account: {}", e)))?;
+    Ok(token_account.base.amount as f64 / 10f64.powi(i32::from(decimals)))
+}
+
+/// The liquidator's token accounts in every monitored program's market, and
+/// the balances it fronts repayments from
+///
+/// Addresses are derived once, by the preflight checks that load the markets
+/// and create missing accounts, for building liquidation transactions. Repayment balances are refreshed on demand, with a
+/// warning whenever one is below `min_repay_token_balance`, since liquidations
+/// fail once the liquidator can't front the repay amount.
+#[derive(Debug)]
+pub struct TokenAccountManager {
+    liquidator: Pubkey,
+    /// Token accounts per program
+    markets: BTreeMap<Pubkey, MarketTokenAccounts>,
+    /// Repayment balance below which a warning is logged (in debt tokens)
+    min_repay_token_balance: f64,
+    /// Last repayment balance read per program (in debt tokens)
+    balances: Mutex<BTreeMap<Pubkey, f64>>,
+}
+
+impl TokenAccountManager {
+    /// Manage `liquidator`'s token accounts in the given programs' markets
+    pub fn new(liquidator: Pubkey, markets: BTreeMap<Pubkey, MarketTokenAccounts>, min_repay_token_balance: f64) -> Self {
+        Self {
+            liquidator,
+            markets,
+            min_repay_token_balance,
+            balances: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The liquidator the token accounts belong to
+    pub fn liquidator(&self) -> Pubkey {
+        self.liquidator
+    }
+
+    /// Token accounts of a program's market, if it's managed
+    pub fn accounts(&self, program_id: &Pubkey) -> Option<MarketTokenAccounts> {
+        self.markets.get(program_id).copied()
+    }
+
+    /// Token accounts of every managed market, by program
+    pub fn markets(&self) -> &BTreeMap<Pubkey, MarketTokenAccounts> {
+        &self.markets
+    }
+
+    /// Record a program's repayment balance, warning if it's below the floor,
+    /// and return whether it is
+    pub fn record_balance(&self, program_id: &Pubkey, balance: f64) -> bool {
+        self.balances
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(*program_id, balance);
+        let low = balance < self.min_repay_token_balance;
+        if low {
+            warn!(
+                program = %program_id,
+                "Repayment token balance {} of program {} is below min_repay_token_balance of {}; fund the liquidator",
+                balance, program_id, self.min_repay_token_balance
+            );
+        }
+        low
+    }
+
+    /// Read every market's repayment balance, returning the programs whose
+    /// balance is below the floor
+    ///
+    /// A missing token account holds nothing; balances that can't be read are
+    /// logged and skipped.
+    pub async fn refresh_balances(&self, rpc: &dyn PreflightRpc) -> Vec<Pubkey> {
+        let mut low = Vec::new();
+        for (program_id, accounts) in &self.markets {
+            let balance = async {
+                let mint = rpc
+                    .account(&accounts.debt_mint)
+                    .await?
+                    .ok_or_else(|| LiquidationError::Other(format!("Mint {} doesn't exist", accounts.debt_mint)))?;
+                let decimals = mint_decimals(&mint)?;
+                match rpc.account(&accounts.debt).await? {
+                    Some(account) => token_balance(&account, decimals),
+                    None => Ok(0.0),
+                }
+            };
+            match balance.await {
+                Ok(balance) if self.record_balance(program_id, balance) => low.push(*program_id),
+                Ok(_) => {}
+                Err(e) => warn!("Unable to read the repayment balance of program {}: {}", program_id, e),
+            }
+        }
+        low
+    }
+
+    /// Repayment balances last read, by program (in debt tokens)
+    pub fn balances(&self) -> BTreeMap<Pubkey, f64> {
+        self.balances.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preflight::MockPreflightRpc;
+    use anchor_spl::token_2022::spl_token_2022::{
+        extension::{
+            ExtensionType, StateWithExtensionsMut,
+            transfer_fee::{TransferFee, TransferFeeAmount},
+        },
+        solana_program::program_pack::Pack,
+        state::AccountState,
+    };
+
+    fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount {
+            mint,
+            owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        }
+        .pack_into_slice(&mut data);
+        Account {
+            lamports: 2_039_280,
+            data,
+            owner: anchor_spl::token::ID,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn mint_account(decimals: u8) -> Account {
+        let mut data = vec![0; Mint::LEN];
+        Mint {
+            decimals,
+            is_initialized: true,
+            ..Mint::default()
+        }
+        .pack_into_slice(&mut data);
+        Account {
+            lamports: 1_461_600,
+            data,
+            owner: anchor_spl::token::ID,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    /// A Token-2022 mint charging `fee_bps` on transfers, with `amount` of it
+    /// held by `owner`
+    fn token_2022_accounts(decimals: u8, fee_bps: u16, owner: Pubkey, amount: u64) -> (Account, Account) {
+        let len = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferFeeConfig]).unwrap();
+        let mut mint_data = vec![0; len];
+        let mut mint = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut mint_data).unwrap();
+        let config = mint.init_extension::<TransferFeeConfig>(true).unwrap();
+        let fee = TransferFee {
+            epoch: 0.into(),
+            maximum_fee: u64::MAX.into(),
+            transfer_fee_basis_points: fee_bps.into(),
+        };
+        config.older_transfer_fee = fee;
+        config.newer_transfer_fee = fee;
+        mint.base = Mint {
+            decimals,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        mint.pack_base();
+        mint.init_account_type().unwrap();
+
+        let len = ExtensionType::try_calculate_account_len::<TokenAccount>(&[ExtensionType::TransferFeeAmount]).unwrap();
+        let mut account_data = vec![0; len];
+        let mut account = StateWithExtensionsMut::<TokenAccount>::unpack_uninitialized(&mut account_data).unwrap();
+        account.init_extension::<TransferFeeAmount>(true).unwrap().withheld_amount = 1_000.into();
+        account.base = TokenAccount {
+            owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        account.pack_base();
+        account.init_account_type().unwrap();
+
+        let account = |data| Account {
+            lamports: 2_039_280,
+            data,
+            owner: anchor_spl::token_2022::ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        (account(mint_data), account(account_data))
+    }
+
+    fn manager(floor: f64) -> (TokenAccountManager, Pubkey, MarketTokenAccounts) {
+        let liquidator = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let accounts = MarketTokenAccounts::derive(
+            &liquidator,
+            (Pubkey::new_unique(), anchor_spl::token::ID),
+            (Pubkey::new_unique(), anchor_spl::token::ID),
+        );
+        let manager = TokenAccountManager::new(liquidator, BTreeMap::from([(program_id, accounts)]), floor);
+        (manager, program_id, accounts)
+    }
+
+    #[test]
+    fn test_derivation_matches_associated_token_program() {
+        let liquidator = Pubkey::new_unique();
+        let (collateral_mint, debt_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts = MarketTokenAccounts::derive(
+            &liquidator,
+            (collateral_mint, anchor_spl::token::ID),
+            (debt_mint, anchor_spl::token_2022::ID),
+        );
+        // Associated token accounts are PDAs of the owner, token program and mint
+        let expected = |mint: &Pubkey, token_program: &Pubkey| {
+            Pubkey::find_program_address(
+                &[liquidator.as_ref(), token_program.as_ref(), mint.as_ref()],
+                &anchor_spl::associated_token::ID,
+            )
+            .0
+        };
+        assert_eq!(accounts.collateral, expected(&collateral_mint, &anchor_spl::token::ID));
+        assert_eq!(accounts.debt, expected(&debt_mint, &anchor_spl::token_2022::ID));
+        assert_ne!(accounts.debt, expected(&debt_mint, &anchor_spl::token::ID));
+        assert_eq!(
+            accounts.roles()[1],
+            ("debt", debt_mint, anchor_spl::token_2022::ID, accounts.debt)
+        );
+    }
+
+    #[test]
+    fn test_token_2022_accounts_read() {
+        let (mint, account) = token_2022_accounts(6, 100, Pubkey::new_unique(), 2_500_000_000);
+        assert_eq!(token_program_of(&mint).unwrap(), anchor_spl::token_2022::ID);
+        assert_eq!(mint_decimals(&mint).unwrap(), 6);
+        // The fees withheld in the account aren't part of its balance
+        assert_eq!(token_balance(&account, 6).unwrap(), 2_500.0);
+        // 1%, rounded up
+        assert_eq!(transfer_fee(&mint, 0, 60_000).unwrap(), 600);
+        assert_eq!(transfer_fee(&mint, 0, 60_001).unwrap(), 601);
+
+        // Classic mints charge nothing
+        assert_eq!(transfer_fee(&mint_account(6), 0, 60_000).unwrap(), 0);
+        assert_eq!(token_program_of(&mint_account(6)).unwrap(), anchor_spl::token::ID);
+        let mut not_a_mint = mint_account(6);
+        not_a_mint.owner = Pubkey::new_unique();
+        assert!(token_program_of(&not_a_mint).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_low_repayment_balance_flagged() {
+        let (manager, program_id, accounts) = manager(1_000.0);
+        let rpc = MockPreflightRpc::new();
+        rpc.set_account(accounts.debt_mint, mint_account(6));
+        rpc.set_account(accounts.debt, token_account(accounts.debt_mint, manager.liquidator(), 2_500_000_000));
+        assert!(manager.refresh_balances(&rpc).await.is_empty());
+        assert_eq!(manager.balances()[&program_id], 2_500.0);
+
+        rpc.set_account(accounts.debt, token_account(accounts.debt_mint, manager.liquidator(), 999_999_999));
+        assert_eq!(manager.refresh_balances(&rpc).await, vec![program_id]);
+        assert!((manager.balances()[&program_id] - 999.999999).abs() < 1e-9);
+
+        // An account that was never created holds nothing
+        rpc.remove_account(&accounts.debt);
+        assert_eq!(manager.refresh_balances(&rpc).await, vec![program_id]);
+        assert_eq!(manager.balances()[&program_id], 0.0);
+
+        // Balances that can't be read keep the last one
+        rpc.set_down(true);
+        assert!(manager.refresh_balances(&rpc).await.is_empty());
+        assert_eq!(manager.balances()[&program_id], 0.0);
+    }
+}
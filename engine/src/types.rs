@@ -1,42 +1,281 @@
+use crate::address::{FeedAddress, OwnerAddress, PositionAddress};
+use crate::alert::AlertConfig;
+use crate::amount::{self, Amount, AmountExt};
+use crate::error::{ConfigViolation, LiquidationError, first_violation};
+use crate::fee::PriorityFeeStrategy;
+use crate::health::PROGRAM_ID;
+use crate::ingest::IngestPolicy;
+use crate::key_pool::KeyPoolConfig;
+use crate::mark::MarkPriceConfig;
+use crate::market::MarketConfig;
+use crate::nonce::NonceConfig;
+use crate::oracle::{ConfidenceConfig, OracleConfig, PriceSource};
+use crate::position::{LIQUIDATION_HEALTH_FACTOR, MarginParams, Position};
+use crate::priority::PriorityWeights;
+use crate::rate_limit::RateLimitConfig;
+use crate::report::ReportFormat;
+use crate::rpc_pool::RpcPoolConfig;
+use crate::sanity::PriceSanityConfig;
+use crate::stats::StatsConfig;
+use crate::submit::{JitoConfig, SubmitterKind};
+use crate::rounding::SymbolSpec;
+use crate::tier::MarginTierSchedule;
+use crate::webhook::WebhookEvent;
+use serde_with::{DisplayFromStr, serde_as};
+use solana_sdk::commitment_config::CommitmentLevel;
 use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::path::Path;
 
 /// Represents a liquidation event
+#[serde_as]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct LiquidationEvent {
     /// The address of the liquidated position
-    pub position: Pubkey,
+    pub position: PositionAddress,
+    /// The owner of the liquidated position
+    pub owner: OwnerAddress,
     /// The liquidator's address
+    #[serde_as(as = "DisplayFromStr")]
     pub liquidator: Pubkey,
     /// The amount liquidated (in base currency)
-    pub amount: f64,
+    pub amount: Amount,
     /// The remaining position size after liquidation
-    pub remaining_size: f64,
+    pub remaining_size: Amount,
     /// The remaining margin after liquidation
-    pub remaining_margin: f64,
+    pub remaining_margin: Amount,
     /// The price at which liquidation occurred
-    pub liquidation_price: f64,
+    pub liquidation_price: Amount,
     /// The timestamp of the liquidation
     pub timestamp: i64,
     /// The transaction signature
     pub signature: String,
+    /// Shortfall absorbed by the insurance fund (in quote currency)
+    pub bad_debt: Amount,
+    /// The trading pair symbol of the liquidated position
+    pub symbol: String,
+    /// Expected liquidator reward (in quote currency)
+    pub reward: Amount,
+    /// Whether the liquidation was simulated rather than submitted
+    pub dry_run: bool,
+    /// Why the liquidation failed, if it did
+    pub error: Option<String>,
+    /// Priority fee paid by the final attempt (in microlamports per compute unit)
+    pub priority_fee_micro_lamports: u64,
+    /// Metadata of the position, e.g. the exchange backend's id of it
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+}
+
+/// Insurance fund drawdown tracked by the engine
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InsuranceStats {
+    /// Total bad debt absorbed since the engine started (in quote currency)
+    pub total_bad_debt: f64,
+    /// Number of liquidations that produced bad debt
+    pub bad_debt_events: u64,
+    /// Bad debt absorbed within the configured window (in quote currency)
+    pub window_bad_debt: f64,
+}
+
+/// A liquidation called off because the position's account on chain didn't
+/// back the monitored copy's verdict
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CacheDivergence {
+    /// The position's address
+    pub position: PositionAddress,
+    /// Mark price both were evaluated at
+    pub price: f64,
+    /// Health factor of the monitored copy
+    pub cached_health_factor: f64,
+    /// Health factor of the account as read from chain
+    pub chain_health_factor: f64,
+    /// Size on chain less the monitored copy's (in base currency)
+    pub size_delta: f64,
+    /// Entry price on chain less the monitored copy's
+    pub entry_price_delta: f64,
+    /// When the divergence was found (Unix timestamp)
+    pub detected_at: i64,
+}
+
+impl CacheDivergence {
+    /// How much healthier the position is on chain than the monitored copy
+    pub fn health_factor_delta(&self) -> f64 {
+        self.chain_health_factor - self.cached_health_factor
+    }
+}
+
+/// Positions read from chain before their liquidation was submitted
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VerificationStats {
+    /// Liquidations whose position was read from chain first, since the
+    /// engine started
+    pub verified: u64,
+    /// Liquidations called off because the chain didn't back them, since the
+    /// engine started
+    pub divergences: u64,
+    /// The latest divergence
+    pub last_divergence: Option<CacheDivergence>,
+}
+
+/// Per-cycle liquidation throttle, backlog and circuit breaker state
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ThrottleStats {
+    /// Liquidations per symbol in the last check cycle
+    pub cycle_liquidations: BTreeMap<String, usize>,
+    /// Notional liquidated in the last check cycle (in quote currency)
+    pub cycle_notional: f64,
+    /// Candidates throttled in the last check cycle
+    pub cycle_throttled: usize,
+    /// Candidates throttled since the engine started
+    pub total_throttled: u64,
+    /// Checks skipped since the engine started, by [`SkipReason::label`]
+    pub total_skipped: BTreeMap<String, u64>,
+    /// Candidates carried over to the next cycle by the concurrency cap, the
+    /// per-cycle caps or a liquidation already in flight
+    pub queue_depth: usize,
+    /// How long the oldest carried over candidate has waited (in seconds)
+    pub oldest_queued_age_secs: Option<u64>,
+    /// Liquidations the next cycle admits, raised above
+    /// `max_concurrent_liquidations` while an overdue backlog is auto-scaled
+    pub concurrency: usize,
+    /// When the circuit breaker tripped, while liquidation is paused
+    pub paused_since: Option<i64>,
+}
+
+/// What the engine does with the positions it monitors, as set by operators
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum EngineMode {
+    /// Check positions and liquidate those that are undercollateralized
+    #[default]
+    Running,
+    /// Check nothing, leaving positions monitored but unjudged
+    Paused,
+    /// Check positions and report what would be liquidated, but never submit
+    /// a transaction, e.g. during exchange maintenance
+    MonitorOnly,
+}
+
+impl fmt::Display for EngineMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => write!(f, "running"),
+            Self::Paused => write!(f, "paused"),
+            Self::MonitorOnly => write!(f, "monitor_only"),
+        }
+    }
+}
+
+/// The engine's mode and its last change
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ModeStatus {
+    pub mode: EngineMode,
+    /// When the mode was last set, or the engine created
+    pub since: i64,
+    /// Who or what set the mode, e.g. "admin API (alice)" or "SIGUSR1"
+    pub source: String,
+    /// Mode changes since the engine started
+    pub changes: u64,
+}
+
+/// Where the engine is in starting up, independently of its mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnginePhase {
+    /// Not started; positions checked directly aren't held back
+    #[default]
+    Starting,
+    /// Started, and evaluating positions without liquidating until its data
+    /// is complete
+    WarmingUp,
+    /// Liquidating as its mode allows
+    Running,
+}
+
+impl fmt::Display for EnginePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Starting => write!(f, "starting"),
+            Self::WarmingUp => write!(f, "warming_up"),
+            Self::Running => write!(f, "running"),
+        }
+    }
+}
+
+/// Progress of the engine's warm-up towards liquidating
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct WarmUpStatus {
+    pub phase: EnginePhase,
+    /// Enabled markets whose positions haven't been loaded in full yet
+    pub unloaded_markets: Vec<String>,
+    /// Symbols of monitored positions the last cycle had no fresh price for
+    pub unpriced_symbols: Vec<String>,
+    /// Check cycles run on complete data
+    pub cycles_completed: u32,
+    /// Check cycles to run on complete data before liquidating
+    pub cycles_required: u32,
+}
+
+impl WarmUpStatus {
+    /// Whether the engine has warmed up, so it's ready to liquidate
+    pub fn is_ready(&self) -> bool {
+        self.phase == EnginePhase::Running
+    }
 }
 
 /// Configuration for the liquidation engine
+///
+/// Fields missing from a configuration file take their default values.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct LiquidationConfig {
     /// How often to check positions (in milliseconds)
     pub check_interval_ms: u64,
+    /// Check cycles the engine runs without liquidating once started, after
+    /// every enabled market's positions are loaded and every monitored symbol
+    /// is freshly priced
+    pub warmup_cycles: u32,
     /// Minimum time between liquidations for the same position (in seconds)
     pub liquidation_cooldown_secs: u64,
     /// Maximum number of positions to process in one batch
     pub max_batch_size: usize,
-    /// Maximum number of concurrent liquidations
+    /// Maximum number of concurrent liquidations; the highest priority
+    /// candidates of a cycle are processed and the rest wait for the next one
     pub max_concurrent_liquidations: usize,
+    /// Cap on liquidations of one symbol per check cycle (unlimited if unset);
+    /// candidates beyond it are throttled until the next cycle
+    pub max_liquidations_per_symbol_per_cycle: Option<usize>,
+    /// Cap on the notional liquidated per check cycle (in quote currency,
+    /// unlimited if unset); the first liquidation of a cycle is always admitted
+    pub max_notional_liquidated_per_cycle: Option<f64>,
+    /// How long a candidate carried over to later cycles may wait before the
+    /// backlog is warned about (in seconds)
+    pub max_queue_age_secs: u64,
+    /// Double the concurrency each cycle, up to `max_auto_scaled_concurrency`,
+    /// while the backlog is older than `max_queue_age_secs`
+    pub auto_scale_concurrency: bool,
+    /// Hard ceiling on the concurrency auto-scaling raises
+    /// `max_concurrent_liquidations` to
+    pub max_auto_scaled_concurrency: usize,
+    /// Pause liquidation once more than this many liquidations fire within
+    /// `circuit_breaker_window_secs`, until resumed (disabled if unset)
+    pub circuit_breaker_liquidation_count: Option<usize>,
+    /// Window the circuit breaker counts liquidations over (in seconds)
+    pub circuit_breaker_window_secs: u64,
     /// Maximum number of retries for failed liquidations
     pub max_retries: u8,
     /// Delay between retry attempts (in milliseconds)
     pub retry_delay_ms: u64,
+    /// How long a submitted liquidation is awaited before it's left pending
+    /// (in seconds); the position isn't liquidated again until the transaction
+    /// confirms, fails or its blockhash expires
+    pub confirmation_timeout_secs: u64,
+    /// How far back a position's transactions are searched for a competitor's
+    /// liquidation when the program refuses ours as healthy (in seconds, 0 to
+    /// not search)
+    pub front_run_window_secs: u64,
     /// Whether to enable partial liquidations
     pub enable_partial_liquidations: bool,
     /// Maximum percentage of position to liquidate in a single transaction (0-100)
@@ -45,6 +284,13 @@ pub struct LiquidationConfig {
     pub min_position_size: f64,
     /// Maximum position size to consider for liquidation (in base currency)
     pub max_position_size: f64,
+    /// Most leverage a position may carry at entry, its notional over its
+    /// margin, to be monitored (uncapped if unset); positions modelled from
+    /// program accounts have no margin of their own and aren't held to it
+    pub max_ingest_leverage: Option<f64>,
+    /// Whether positions breaking the ingest rules are refused or monitored as
+    /// suspect
+    pub ingest_policy: IngestPolicy,
     /// Whether to enable dry run mode (no actual transactions)
     pub dry_run: bool,
     /// List of symbols to monitor (empty for all)
@@ -53,71 +299,811 @@ pub struct LiquidationConfig {
     pub blacklisted_symbols: Vec<String>,
     /// Maximum slippage allowed for liquidations (in basis points)
     pub max_slippage_bps: u16,
-    /// Priority fee in microlamports per compute unit
-    pub priority_fee_micro_lamports: u64,
+    /// Read a market position's account from chain before liquidating it, and
+    /// only go ahead if the position as the chain holds it is liquidatable too;
+    /// dry runs never read it
+    pub verify_before_submit: bool,
+    /// Commitment the account is read at before a liquidation
+    pub verify_commitment: CommitmentLevel,
+    /// How the priority fee of liquidation transactions is chosen
+    pub priority_fee_strategy: PriorityFeeStrategy,
+    /// Upper bound on the priority fee of any liquidation transaction, whatever the
+    /// strategy (in microlamports per compute unit)
+    pub max_priority_fee_micro_lamports: u64,
     /// Maintenance margin ratio (e.g., 0.05 for 5%)
     pub maintenance_margin: f64,
+    /// Health factor below which positions are reported at risk; they're
+    /// liquidatable below 1.0
+    pub at_risk_health_factor: f64,
     /// Minimum time between liquidations (in seconds)
     pub min_liquidation_interval_secs: u64,
+    /// Fall in margin ratio since a position's last liquidation (e.g., 0.01 for
+    /// 1 percentage point) past which it may be liquidated again before
+    /// `min_liquidation_interval_secs` have passed, as may positions that grew
+    /// since; unset, the interval always holds
+    pub cooldown_bypass_delta: Option<f64>,
+    /// Grace period of the program's margin call (in seconds): liquidatable
+    /// positions are flagged first and liquidated once it's over; zero
+    /// liquidates them at once, as markets without a margin call do
+    pub margin_call_grace_secs: u64,
+    /// Health factor below which positions are liquidated without waiting out
+    /// the margin call's grace period
+    pub instant_liquidation_health_factor: f64,
     /// Maximum confidence interval for oracle prices
     pub max_confidence_interval: u64,
+    /// Most slots an oracle price may be published behind the cluster's
+    /// current slot, on top of the oracle's check of its age in seconds
+    /// (unchecked if unset, or until a slot is seen)
+    pub max_price_age_slots: Option<u64>,
+    /// Longest the cluster's slot may go without advancing before the RPC
+    /// node is treated as unhealthy and liquidations are held back (in
+    /// seconds, never if unset)
+    pub max_slot_silence_secs: Option<u64>,
     /// Whether to use mainnet RPC endpoints
     pub use_mainnet: bool,
+    /// SOL each liquidator key has to hold for the engine to start submitting
+    /// liquidations, checked by the preflight checks; keys falling below it
+    /// are left out of rotation until funded
+    pub min_sol_balance: f64,
+    /// Balance of a key's repayment token account below which a warning is
+    /// logged for each market and the key is left out of rotation (in debt
+    /// tokens, 0 to not warn); liquidations fail once the liquidator can't
+    /// front the repay amount
+    pub min_repay_token_balance: f64,
+    /// Pyth price account of each symbol
+    pub price_accounts: HashMap<String, FeedAddress>,
+    /// Markets whose parameters take precedence over the global ones for
+    /// positions in their symbol; positions in other symbols use the global
+    /// parameters and are followed in the default program
+    pub markets: Vec<MarketConfig>,
+    /// Share of a collateral asset's value counted towards margin, by the
+    /// symbol it's priced by (e.g., 0.9 for SOL/USD); assets without a weight
+    /// count in full
+    pub collateral_weights: HashMap<String, f64>,
+    /// Units of each symbol that would move its price by 100% if traded at once,
+    /// for sizing liquidations within `max_slippage_bps` when no depth provider
+    /// is set; liquidations of other symbols aren't split
+    pub market_liquidity: HashMap<String, f64>,
+    /// Margin tiers of each symbol, raising the maintenance margin of positions
+    /// by their notional; positions in other symbols are held to their
+    /// market's margin whatever their size
+    pub margin_tiers: HashMap<String, MarginTierSchedule>,
+    /// Exchange grid of each symbol, which displayed liquidation prices and
+    /// liquidation amounts are rounded to; other symbols' aren't rounded
+    pub symbol_specs: HashMap<String, SymbolSpec>,
+    /// Require both the spot and EMA prices to indicate liquidation before acting
+    pub require_twap_confirmation: bool,
+    /// Rejection of oracle prices that jump away from recent history
+    pub price_sanity: PriceSanityConfig,
+    /// Handling of oracle prices with wide confidence intervals
+    pub oracle_confidence: ConfidenceConfig,
+    /// Derivation of the mark prices positions' health is evaluated at from
+    /// oracle index prices
+    pub mark_price: MarkPriceConfig,
+    /// Book statistics served to dashboards
+    pub stats: StatsConfig,
+    /// Liquidator reward as a share of the repaid value (in basis points)
+    pub liquidation_fee_bps: u16,
+    /// Compute units assumed for a liquidation transaction when estimating profit and
+    /// in dry-run mode, where transactions aren't simulated
+    pub estimated_compute_units: u32,
+    /// Compute units requested on top of what a simulated liquidation consumed (in percent)
+    pub compute_unit_headroom_percent: u16,
+    /// Consecutive failed liquidations after which a cached compute estimate is
+    /// discarded and the next liquidation simulated again
+    pub compute_estimate_max_failures: u32,
+    /// Pack liquidations of the same market into shared transactions
+    pub enable_instruction_batching: bool,
+    /// Most liquidate instructions packed into one transaction
+    pub max_instructions_per_tx: usize,
+    /// Compute units above which a liquidation is always submitted on its own
+    pub batch_candidate_max_compute: u32,
+    /// Minimum expected profit (in quote currency) to attempt a liquidation
+    pub min_profit_quote: f64,
+    /// Weights scoring liquidation candidates for the order they're processed in
+    pub priority_weights: PriorityWeights,
+    /// Oracle symbol used to price network fees
+    pub fee_price_symbol: String,
+    /// Window over which bad debt is accumulated for the limit below (in seconds)
+    pub bad_debt_window_secs: u64,
+    /// Refuse further bad-debt liquidations once the window total would exceed this
+    /// amount (in quote currency), forcing human intervention
+    pub max_window_bad_debt: Option<f64>,
+    /// Insurance fund available to absorb bad debt before profitable counterparties
+    /// would be auto-deleveraged (in quote currency)
+    pub insurance_fund_balance: f64,
+    /// SQLite database recording every liquidation attempt (requires the `storage` feature)
+    pub database_path: Option<String>,
+    /// JSON file remembering cooldowns and unconfirmed liquidations across restarts
+    pub state_path: Option<String>,
+    /// How long the keys of published liquidation events are remembered, so
+    /// one reported again by the program's logs or after a restart isn't
+    /// published twice (in seconds)
+    pub event_dedup_retention_secs: u64,
+    /// How long positions stay cached after they're fully liquidated or
+    /// closed, before they're evicted along with their cooldowns and history
+    /// (in seconds)
+    pub closed_retention_secs: u64,
+    /// File every liquidation a dry run would have made is appended to, rotated
+    /// daily; `.csv` for CSV, `.json` or `.jsonl` for JSON lines
+    pub dry_run_report_path: Option<String>,
+    /// JSON lines file every liquidation attempt's audit record is appended
+    /// to, rotated daily
+    pub audit_log_path: Option<String>,
+    /// Minimum change in margin ratio (in percentage points) before a new position
+    /// update is pushed to subscribers; status changes are always pushed
+    pub position_update_min_delta: f64,
+    /// Endpoint owners are notified at before and after liquidation, unless they
+    /// have one of their own below
+    pub webhook_url: Option<String>,
+    /// Endpoint of particular owners, by owner
+    pub webhook_owner_urls: HashMap<OwnerAddress, String>,
+    /// Notifications sent to webhooks
+    pub webhook_events: Vec<WebhookEvent>,
+    /// Secret every webhook notification is signed with (HMAC-SHA256)
+    pub webhook_secret: Option<String>,
+    /// Alerts to operators about the engine itself
+    pub alerts: AlertConfig,
+    /// How liquidation transactions are submitted
+    pub submitter: SubmitterKind,
+    /// Bundle settings used by the Jito submitter
+    pub jito: JitoConfig,
+    /// Durable nonce account to sign liquidations against instead of recent
+    /// blockhashes, so retries survive blockhash expiry
+    pub nonce: Option<NonceConfig>,
+    /// Pacing of RPC requests and backoff when the node throttles us
+    pub rate_limit: RateLimitConfig,
+    /// Health tracking and failover between RPC endpoints
+    pub rpc_pool: RpcPoolConfig,
+    /// Liquidator keys paying for and signing transactions
+    pub key_pool: KeyPoolConfig,
+    /// Send each liquidation to every healthy RPC endpoint rather than just the
+    /// healthiest one (RPC submitter only)
+    pub broadcast_transactions: bool,
 }
 
 impl Default for LiquidationConfig {
     fn default() -> Self {
         Self {
             check_interval_ms: 1000,
+            warmup_cycles: 2,
             liquidation_cooldown_secs: 300, // 5 minutes
             max_batch_size: 100,
             max_concurrent_liquidations: 10,
+            max_liquidations_per_symbol_per_cycle: None,
+            max_notional_liquidated_per_cycle: None,
+            max_queue_age_secs: 30,
+            auto_scale_concurrency: false,
+            max_auto_scaled_concurrency: 50,
+            circuit_breaker_liquidation_count: None,
+            circuit_breaker_window_secs: 60,
             max_retries: 3,
             retry_delay_ms: 1000,
+            confirmation_timeout_secs: 60,
+            front_run_window_secs: 300, // 5 minutes
             enable_partial_liquidations: true,
             max_liquidation_percent: 50, // 50% of position
             min_position_size: 0.001,     // 0.001 BTC
             max_position_size: 1000.0,    // 1000 BTC
+            max_ingest_leverage: Some(1000.0),
+            ingest_policy: IngestPolicy::Reject,
             dry_run: true,
             whitelisted_symbols: vec!["BTC/USD".to_string(), "ETH/USD".to_string()],
             blacklisted_symbols: vec![],
             max_slippage_bps: 50, // 0.5%
-            priority_fee_micro_lamports: 1_000, // 0.000001 SOL per CU
+            verify_before_submit: true,
+            verify_commitment: CommitmentLevel::Processed,
+            priority_fee_strategy: PriorityFeeStrategy::Static(1_000), // 0.000001 SOL per CU
+            max_priority_fee_micro_lamports: 100_000, // 0.02 SOL at 200k CU
             maintenance_margin: 0.05, // 5%
+            at_risk_health_factor: 1.1,
             min_liquidation_interval_secs: 300, // 5 minutes
+            cooldown_bypass_delta: None,
+            margin_call_grace_secs: 0,
+            instant_liquidation_health_factor: 0.9,
             max_confidence_interval: 60, // 1 minute
+            max_price_age_slots: Some(150), // about a minute of 400ms slots
+            max_slot_silence_secs: Some(30),
             use_mainnet: false,
+            min_sol_balance: 0.1,
+            min_repay_token_balance: 0.0,
+            price_accounts: HashMap::new(),
+            markets: Vec::new(),
+            collateral_weights: HashMap::new(),
+            market_liquidity: HashMap::new(),
+            margin_tiers: HashMap::new(),
+            symbol_specs: HashMap::new(),
+            require_twap_confirmation: false,
+            price_sanity: PriceSanityConfig::default(),
+            oracle_confidence: ConfidenceConfig::default(),
+            mark_price: MarkPriceConfig::default(),
+            stats: StatsConfig::default(),
+            liquidation_fee_bps: 1000, // 10%, matching the on-chain program
+            estimated_compute_units: 200_000,
+            compute_unit_headroom_percent: 20,
+            compute_estimate_max_failures: 3,
+            enable_instruction_batching: false,
+            max_instructions_per_tx: 4,
+            batch_candidate_max_compute: 300_000,
+            min_profit_quote: 0.0,
+            priority_weights: PriorityWeights::default(),
+            fee_price_symbol: "SOL/USD".to_string(),
+            bad_debt_window_secs: 3600, // 1 hour
+            max_window_bad_debt: None,
+            insurance_fund_balance: 0.0,
+            database_path: None,
+            state_path: None,
+            event_dedup_retention_secs: 86_400, // 1 day
+            closed_retention_secs: 3600, // 1 hour
+            dry_run_report_path: None,
+            audit_log_path: None,
+            position_update_min_delta: 0.1,
+            webhook_url: None,
+            webhook_owner_urls: HashMap::new(),
+            webhook_events: WebhookEvent::ALL.to_vec(),
+            webhook_secret: None,
+            alerts: AlertConfig::default(),
+            submitter: SubmitterKind::Rpc,
+            jito: JitoConfig::default(),
+            nonce: None,
+            rate_limit: RateLimitConfig::default(),
+            rpc_pool: RpcPoolConfig::default(),
+            key_pool: KeyPoolConfig::default(),
+            broadcast_transactions: false,
+        }
+    }
+}
+
+impl LiquidationConfig {
+    /// Check that the configuration is internally consistent
+    pub fn validate(&self) -> Result<(), LiquidationError> {
+        first_violation(self.violations())
+    }
+
+    /// Every inconsistency in the configuration, with nested fields named by
+    /// their dotted path
+    pub fn violations(&self) -> Vec<ConfigViolation> {
+        let mut violations = Vec::new();
+        if self.check_interval_ms == 0 {
+            violations.push(ConfigViolation::new("check_interval_ms", "must be positive"));
+        }
+        if !(self.maintenance_margin > 0.0 && self.maintenance_margin < 1.0) {
+            violations.push(ConfigViolation::new(
+                "maintenance_margin",
+                format!("must be between 0 and 1, got {}", self.maintenance_margin),
+            ));
+        }
+        if !(self.at_risk_health_factor.is_finite() && self.at_risk_health_factor >= LIQUIDATION_HEALTH_FACTOR) {
+            violations.push(ConfigViolation::new(
+                "at_risk_health_factor",
+                format!("must be at least {}, got {}", LIQUIDATION_HEALTH_FACTOR, self.at_risk_health_factor),
+            ));
+        }
+        if !(self.insurance_fund_balance.is_finite() && self.insurance_fund_balance >= 0.0) {
+            violations.push(ConfigViolation::new(
+                "insurance_fund_balance",
+                format!("must not be negative, got {}", self.insurance_fund_balance),
+            ));
+        }
+        if self.max_concurrent_liquidations == 0 {
+            violations.push(ConfigViolation::new("max_concurrent_liquidations", "must be at least 1"));
+        }
+        if self.max_queue_age_secs == 0 {
+            violations.push(ConfigViolation::new("max_queue_age_secs", "must be at least 1"));
+        }
+        if self.max_slot_silence_secs == Some(0) {
+            violations.push(ConfigViolation::new("max_slot_silence_secs", "must be at least 1"));
+        }
+        if self.auto_scale_concurrency && self.max_auto_scaled_concurrency < self.max_concurrent_liquidations {
+            violations.push(ConfigViolation::new(
+                "max_auto_scaled_concurrency",
+                format!(
+                    "must be at least max_concurrent_liquidations ({}), got {}",
+                    self.max_concurrent_liquidations, self.max_auto_scaled_concurrency
+                ),
+            ));
+        }
+        if self.max_instructions_per_tx == 0 {
+            violations.push(ConfigViolation::new("max_instructions_per_tx", "must be at least 1"));
+        }
+        if self.max_liquidations_per_symbol_per_cycle == Some(0) {
+            violations.push(ConfigViolation::new("max_liquidations_per_symbol_per_cycle", "must be at least 1"));
+        }
+        if let Some(max) = self.max_notional_liquidated_per_cycle
+            && !(max.is_finite() && max > 0.0)
+        {
+            violations.push(ConfigViolation::new(
+                "max_notional_liquidated_per_cycle",
+                format!("must be positive, got {}", max),
+            ));
+        }
+        if let Some(max) = self.max_ingest_leverage
+            && !(max.is_finite() && max > 0.0)
+        {
+            violations.push(ConfigViolation::new("max_ingest_leverage", format!("must be positive, got {}", max)));
+        }
+        if let Some(path) = &self.dry_run_report_path
+            && ReportFormat::from_path(Path::new(path)).is_err()
+        {
+            violations.push(ConfigViolation::new(
+                "dry_run_report_path",
+                format!("must end in .csv, .json or .jsonl, got {}", path),
+            ));
+        }
+        if let Some(path) = &self.audit_log_path
+            && !matches!(ReportFormat::from_path(Path::new(path)), Ok(ReportFormat::JsonLines))
+        {
+            violations.push(ConfigViolation::new(
+                "audit_log_path",
+                format!("must end in .json or .jsonl, got {}", path),
+            ));
+        }
+        if self.webhook_secret.as_ref().is_some_and(String::is_empty) {
+            violations.push(ConfigViolation::new("webhook_secret", "must not be empty"));
+        }
+        if self.confirmation_timeout_secs == 0 {
+            violations.push(ConfigViolation::new("confirmation_timeout_secs", "must be positive"));
+        }
+        if self.circuit_breaker_liquidation_count.is_some() && self.circuit_breaker_window_secs == 0 {
+            violations.push(ConfigViolation::new("circuit_breaker_window_secs", "must be positive"));
+        }
+        if self.max_liquidation_percent == 0 || self.max_liquidation_percent > 100 {
+            violations.push(ConfigViolation::new(
+                "max_liquidation_percent",
+                format!("must be between 1 and 100, got {}", self.max_liquidation_percent),
+            ));
+        }
+        if self.min_position_size > self.max_position_size {
+            violations.push(ConfigViolation::new(
+                "min_position_size",
+                format!(
+                    "{} exceeds max_position_size {}",
+                    self.min_position_size, self.max_position_size
+                ),
+            ));
+        }
+        if let Some(delta) = self.cooldown_bypass_delta
+            && !(delta.is_finite() && delta > 0.0)
+        {
+            violations.push(ConfigViolation::new(
+                "cooldown_bypass_delta",
+                format!("must be positive, got {}", delta),
+            ));
+        }
+        if !(self.instant_liquidation_health_factor >= 0.0
+            && self.instant_liquidation_health_factor <= LIQUIDATION_HEALTH_FACTOR)
+        {
+            violations.push(ConfigViolation::new(
+                "instant_liquidation_health_factor",
+                format!(
+                    "must be between 0 and {}, got {}",
+                    LIQUIDATION_HEALTH_FACTOR, self.instant_liquidation_health_factor
+                ),
+            ));
+        }
+        if !(self.min_sol_balance.is_finite() && self.min_sol_balance >= 0.0) {
+            violations.push(ConfigViolation::new(
+                "min_sol_balance",
+                format!("must be non-negative, got {}", self.min_sol_balance),
+            ));
+        }
+        if !(self.min_repay_token_balance.is_finite() && self.min_repay_token_balance >= 0.0) {
+            violations.push(ConfigViolation::new(
+                "min_repay_token_balance",
+                format!("must be non-negative, got {}", self.min_repay_token_balance),
+            ));
+        }
+        if self.liquidation_fee_bps > 10_000 {
+            violations.push(ConfigViolation::new(
+                "liquidation_fee_bps",
+                format!("must be at most 10000, got {}", self.liquidation_fee_bps),
+            ));
+        }
+        let mut weights: Vec<(&String, &f64)> = self.collateral_weights.iter().collect();
+        weights.sort_by(|a, b| a.0.cmp(b.0));
+        for (symbol, weight) in weights {
+            if !(*weight > 0.0 && *weight <= 1.0) {
+                violations.push(ConfigViolation::new(
+                    format!("collateral_weights.{}", symbol),
+                    format!("must be greater than 0 and at most 1, got {}", weight),
+                ));
+            }
+        }
+        let mut liquidity: Vec<(&String, &f64)> = self.market_liquidity.iter().collect();
+        liquidity.sort_by(|a, b| a.0.cmp(b.0));
+        for (symbol, liquidity) in liquidity {
+            if !(liquidity.is_finite() && *liquidity > 0.0) {
+                violations.push(ConfigViolation::new(
+                    format!("market_liquidity.{}", symbol),
+                    format!("must be positive, got {}", liquidity),
+                ));
+            }
+        }
+        let mut tiers: Vec<(&String, &MarginTierSchedule)> = self.margin_tiers.iter().collect();
+        tiers.sort_by(|a, b| a.0.cmp(b.0));
+        for (symbol, schedule) in tiers {
+            violations.extend(schedule.violations().into_iter().map(|violation| {
+                ConfigViolation::new(format!("margin_tiers.{}{}", symbol, violation.field), violation.message)
+            }));
+        }
+        let mut specs: Vec<(&String, &SymbolSpec)> = self.symbol_specs.iter().collect();
+        specs.sort_by(|a, b| a.0.cmp(b.0));
+        for (symbol, spec) in specs {
+            violations.extend(spec.violations().into_iter().map(|violation| {
+                ConfigViolation::new(format!("symbol_specs.{}.{}", symbol, violation.field), violation.message)
+            }));
+        }
+        for (index, market) in self.markets.iter().enumerate() {
+            let parent = format!("markets[{}]", index);
+            if self.markets[..index].iter().any(|other| other.symbol == market.symbol) {
+                violations.push(ConfigViolation::new(
+                    format!("{}.symbol", parent),
+                    format!("{} already has a market", market.symbol),
+                ));
+            }
+            violations.extend(market.violations().into_iter().map(|violation| violation.nested(&parent)));
+        }
+        let nested = [
+            ("priority_fee_strategy", self.priority_fee_strategy.violations()),
+            ("priority_weights", self.priority_weights.violations()),
+            ("price_sanity", self.price_sanity.violations()),
+            ("oracle_confidence", self.oracle_confidence.violations()),
+            ("mark_price", self.mark_price.violations()),
+            ("stats", self.stats.violations()),
+            ("rate_limit", self.rate_limit.violations()),
+            ("rpc_pool", self.rpc_pool.violations()),
+            ("alerts", self.alerts.violations()),
+        ];
+        for (parent, nested) in nested {
+            violations.extend(nested.into_iter().map(|violation| violation.nested(parent)));
+        }
+        violations
+    }
+
+    /// Parse a TOML configuration file's contents, taking defaults for missing
+    /// fields, and validate it
+    pub fn from_toml(toml: &str) -> Result<Self, LiquidationError> {
+        let config: Self = toml::from_str(toml).map_err(|e| LiquidationError::ConfigError(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Stable hash of the configuration: hex SHA-256 of its JSON with every
+    /// object's keys sorted, the same across restarts and builds for the same
+    /// settings
+    ///
+    /// The webhook secret and Slack webhook URL are left out, so the hash reveals
+    /// nothing about them.
+    pub fn config_hash(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        let mut value = serde_json::to_value(self).expect("configuration serializes to JSON");
+        value["webhook_secret"] = serde_json::Value::Null;
+        value["alerts"]["slack_webhook_url"] = serde_json::Value::Null;
+        hex::encode(Sha256::digest(canonical_json(value).to_string()))
+    }
+
+    /// Fields whose values differ in `other`, by dotted path
+    pub fn diff(&self, other: &Self) -> Vec<ConfigChange> {
+        let mut changes = Vec::new();
+        diff_values(
+            "",
+            &serde_json::to_value(self).expect("configuration serializes to JSON"),
+            &serde_json::to_value(other).expect("configuration serializes to JSON"),
+            &mut changes,
+        );
+        changes
+    }
+
+    /// Fraction of a position in `symbol` liquidated in a single transaction:
+    /// its market's close factor, or `max_liquidation_percent` outside markets
+    ///
+    /// Bankrupt positions, those with bad debt, are closed in full.
+    pub fn liquidation_fraction(&self, symbol: &str, bad_debt: f64) -> f64 {
+        if bad_debt > 0.0 || !self.enable_partial_liquidations {
+            1.0
+        } else if let Some(market) = self.market(symbol) {
+            market.close_factor
+        } else {
+            self.max_liquidation_percent.min(100) as f64 / 100.0
+        }
+    }
+
+    /// The market of a symbol, if it has one
+    pub fn market(&self, symbol: &str) -> Option<&MarketConfig> {
+        self.markets.iter().find(|market| market.symbol == symbol)
+    }
+
+    /// Maintenance margin ratios of positions in `symbol`: their market's, by
+    /// side, or the global one for both sides outside markets
+    pub fn margin_params(&self, symbol: &str) -> MarginParams {
+        self.market(symbol)
+            .map_or(MarginParams::shared(self.maintenance_margin), MarketConfig::margin_params)
+    }
+
+    /// Maintenance margin ratios of `position` at `price`: those of its
+    /// symbol, raised to the margin tier its notional at that price falls in
+    pub fn margin_params_at(&self, position: &Position, price: Amount) -> MarginParams {
+        let params = self.margin_params(&position.symbol);
+        match self.margin_tiers.get(&position.symbol) {
+            Some(schedule) => schedule.params(params, position.value(price).as_f64()),
+            None => params,
+        }
+    }
+
+    /// Whether `position` was opened at more leverage than the margin tier of
+    /// its entry notional allows
+    pub fn exceeds_max_leverage(&self, position: &Position) -> bool {
+        self.margin_tiers
+            .get(&position.symbol)
+            .and_then(|schedule| schedule.tier(position.value(position.entry_price).as_f64()))
+            .is_some_and(|tier| position.leverage(position.entry_price).as_f64() > tier.max_leverage)
+    }
+
+    /// Exchange grid of `symbol`, which is unrounded if it has no spec
+    pub fn symbol_spec(&self, symbol: &str) -> SymbolSpec {
+        self.symbol_specs.get(symbol).copied().unwrap_or_default()
+    }
+
+    /// Liquidation price of `position` as displayed, rounded to its symbol's
+    /// tick against the trader
+    pub fn displayed_liquidation_price(&self, position: &Position, price: Option<Amount>) -> Option<Amount> {
+        let spec = self.symbol_spec(&position.symbol);
+        price.map(|price| amount::from_f64_lossy(spec.liquidation_price(price.as_f64(), position.is_long)))
+    }
+
+    /// Whether positions in `symbol` are checked for liquidation, which they
+    /// are unless their market is disabled
+    pub fn market_enabled(&self, symbol: &str) -> bool {
+        self.market(symbol).is_none_or(|market| market.enabled)
+    }
+
+    /// Programs whose positions are monitored: those of the enabled markets,
+    /// plus the default program for positions outside markets
+    pub fn program_ids(&self) -> Vec<Pubkey> {
+        let mut program_ids = vec![PROGRAM_ID];
+        for market in self.markets.iter().filter(|market| market.enabled) {
+            if !program_ids.contains(&market.program_id) {
+                program_ids.push(market.program_id);
+            }
         }
+        program_ids
+    }
+
+    /// Program and id of each market liquidations are built against: the
+    /// enabled markets, plus the default program's first market for positions
+    /// outside markets
+    pub fn program_markets(&self) -> Vec<(Pubkey, u64)> {
+        let mut program_markets = vec![(PROGRAM_ID, 0)];
+        for market in self.markets.iter().filter(|market| market.enabled) {
+            if !program_markets.contains(&(market.program_id, market.market_id)) {
+                program_markets.push((market.program_id, market.market_id));
+            }
+        }
+        program_markets
+    }
+
+    /// Oracle price account of each symbol, with markets' feeds taking
+    /// precedence over `price_accounts`
+    pub fn oracle_feeds(&self) -> HashMap<String, FeedAddress> {
+        let mut feeds = self.price_accounts.clone();
+        for market in &self.markets {
+            if let Some(feed) = market.oracle_feed {
+                feeds.insert(market.symbol.clone(), feed);
+            }
+        }
+        feeds
+    }
+
+    /// Settings the engine's Pyth oracle reads prices with
+    pub fn oracle_config(&self) -> OracleConfig {
+        OracleConfig {
+            max_price_age_secs: 60, // 1 minute
+            min_confidence_interval: 0.05, // 5%
+            max_confidence_interval: 0.1, // 10% (as a decimal, not seconds)
+            use_mainnet: self.use_mainnet,
+            price_source: PriceSource::Aggregate,
+            confidence: self.oracle_confidence.clone(),
+        }
+    }
+
+    /// Apply a partial update, returning the updated configuration if it validates
+    pub fn with_update(&self, update: &ConfigUpdate) -> Result<Self, LiquidationError> {
+        let mut config = self.clone();
+        if let Some(maintenance_margin) = update.maintenance_margin {
+            config.maintenance_margin = maintenance_margin;
+        }
+        if let Some(dry_run) = update.dry_run {
+            config.dry_run = dry_run;
+        }
+        if let Some(max_concurrent_liquidations) = update.max_concurrent_liquidations {
+            config.max_concurrent_liquidations = max_concurrent_liquidations;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// `reloaded` with the fields only read at startup kept as they are here,
+    /// and the changes to those fields it had to leave out
+    ///
+    /// RPC endpoints, rate limits, storage paths, the oracle network and
+    /// confidence policy, submission, liquidator key, webhook and alert
+    /// settings are wired up once, so changing them requires a restart.
+    pub fn hot_reload(&self, reloaded: &Self) -> (Self, Vec<ConfigChange>) {
+        let config = Self {
+            use_mainnet: self.use_mainnet,
+            oracle_confidence: self.oracle_confidence.clone(),
+            database_path: self.database_path.clone(),
+            state_path: self.state_path.clone(),
+            event_dedup_retention_secs: self.event_dedup_retention_secs,
+            dry_run_report_path: self.dry_run_report_path.clone(),
+            audit_log_path: self.audit_log_path.clone(),
+            webhook_url: self.webhook_url.clone(),
+            webhook_owner_urls: self.webhook_owner_urls.clone(),
+            webhook_events: self.webhook_events.clone(),
+            webhook_secret: self.webhook_secret.clone(),
+            alerts: self.alerts.clone(),
+            submitter: self.submitter,
+            jito: self.jito.clone(),
+            nonce: self.nonce.clone(),
+            rate_limit: self.rate_limit.clone(),
+            rpc_pool: self.rpc_pool.clone(),
+            key_pool: self.key_pool.clone(),
+            broadcast_transactions: self.broadcast_transactions,
+            ..reloaded.clone()
+        };
+        let left_out = config.diff(reloaded);
+        (config, left_out)
+    }
+
+    /// Add a market, or replace the one in its symbol, returning the updated
+    /// configuration if it validates
+    pub fn with_market(&self, market: MarketConfig) -> Result<Self, LiquidationError> {
+        let mut config = self.clone();
+        match config.markets.iter_mut().find(|existing| existing.symbol == market.symbol) {
+            Some(existing) => *existing = market,
+            None => config.markets.push(market),
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Remove the market of a symbol, returning the updated configuration and
+    /// the removed market, if there was one
+    pub fn without_market(&self, symbol: &str) -> (Self, Option<MarketConfig>) {
+        let mut config = self.clone();
+        let removed = config
+            .markets
+            .iter()
+            .position(|market| market.symbol == symbol)
+            .map(|index| config.markets.remove(index));
+        (config, removed)
     }
 }
 
+/// A configuration field whose value changed
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ConfigChange {
+    /// Dotted path of the field, e.g. `rate_limit.burst`
+    pub field: String,
+    /// Value before the change, `null` when unset
+    pub old: serde_json::Value,
+    /// Value after the change, `null` when unset
+    pub new: serde_json::Value,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+/// Collect the leaves that differ between two JSON values, descending into
+/// objects present on both sides
+fn diff_values(path: &str, old: &serde_json::Value, new: &serde_json::Value, changes: &mut Vec<ConfigChange>) {
+    use serde_json::Value;
+
+    if let (Value::Object(old), Value::Object(new)) = (old, new) {
+        let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+            diff_values(&path, old.get(key).unwrap_or(&Value::Null), new.get(key).unwrap_or(&Value::Null), changes);
+        }
+    } else if old != new {
+        changes.push(ConfigChange {
+            field: path.to_string(),
+            old: old.clone(),
+            new: new.clone(),
+        });
+    }
+}
+
+/// `value` with the keys of every object in sorted order, whatever order its
+/// maps were serialized in
+fn canonical_json(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(object) => {
+            let sorted: BTreeMap<String, Value> = object.into_iter().map(|(key, value)| (key, canonical_json(value))).collect();
+            Value::Object(sorted.into_iter().collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonical_json).collect()),
+        value => value,
+    }
+}
+
+/// Configuration fields that can be changed while the engine is running
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigUpdate {
+    /// New maintenance margin requirement (as a decimal, e.g., 0.05 for 5%)
+    pub maintenance_margin: Option<f64>,
+    /// Enable or disable dry-run mode
+    pub dry_run: Option<bool>,
+    /// New maximum number of concurrent liquidations
+    pub max_concurrent_liquidations: Option<usize>,
+}
+
 /// Liquidation result
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
 pub enum LiquidationResult {
     /// Liquidation was successful
     Success {
         /// The liquidated position
-        position: Pubkey,
-        /// The amount liquidated
+        position: PositionAddress,
+        /// The amount liquidated, capped so its expected market impact stays
+        /// within the slippage bound
         amount: f64,
+        /// Expected market impact of liquidating `amount` (in basis points), if
+        /// the market's depth is known
+        estimated_impact_bps: Option<f64>,
         /// The transaction signature
         signature: String,
+        /// Compute unit limit requested by the transaction
+        compute_unit_limit: u32,
+        /// Identifier attached to every log event about this attempt
+        correlation_id: String,
+        /// Score the position was prioritized with, if it was checked as part
+        /// of a cycle
+        priority_score: Option<f64>,
+        /// Mark price the position was evaluated at
+        price: f64,
+        /// Cycle whose price snapshot the position was evaluated at, if it was
+        /// checked as part of one
+        cycle_id: Option<String>,
     },
     /// Liquidation failed
     Failure {
         /// The liquidated position
-        position: Pubkey,
+        position: PositionAddress,
         /// The error that occurred
         error: String,
+        /// What the error was traced to, e.g. `front-run by <pubkey>` when a
+        /// competitor liquidated the position first
+        sub_reason: Option<String>,
         /// The number of attempts made
         attempts: u8,
+        /// Identifier attached to every log event about this attempt
+        correlation_id: String,
+        /// Score the position was prioritized with, if it was checked as part
+        /// of a cycle
+        priority_score: Option<f64>,
+        /// Mark price the position was evaluated at
+        price: f64,
+        /// Cycle whose price snapshot the position was evaluated at, if it was
+        /// checked as part of one
+        cycle_id: Option<String>,
     },
     /// Liquidation was skipped
     Skipped {
         /// The position that was skipped
-        position: Pubkey,
+        position: PositionAddress,
         /// The reason for skipping
-        reason: String,
+        reason: SkipReason,
     },
 }
 
@@ -127,30 +1113,251 @@ impl fmt::Display for LiquidationResult {
             Self::Success {
                 position,
                 amount,
+                estimated_impact_bps,
                 signature,
-            } => write!(
-                f,
-                "Liquidated {} of position {} in tx: {}",
-                amount, position, signature
-            ),
+                compute_unit_limit,
+                correlation_id,
+                priority_score,
+                price,
+                cycle_id,
+            } => {
+                write!(f, "Liquidated {} of position {} at {}", amount, position, price)?;
+                if let Some(impact) = estimated_impact_bps {
+                    write!(f, " (~{:.1} bps impact)", impact)?;
+                }
+                write!(f, " in tx: {} ({} CU) [{}]", signature, compute_unit_limit, correlation_id)?;
+                write_priority_score(f, *priority_score)?;
+                write_cycle_id(f, cycle_id.as_deref())
+            }
             Self::Failure {
                 position,
                 error,
+                sub_reason,
                 attempts,
+                correlation_id,
+                priority_score,
+                price,
+                cycle_id,
+            } => {
+                write!(f, "Failed to liquidate position {} at {} after {} attempts: {}", position, price, attempts, error)?;
+                if let Some(sub_reason) = sub_reason {
+                    write!(f, " ({})", sub_reason)?;
+                }
+                write!(f, " [{}]", correlation_id)?;
+                write_priority_score(f, *priority_score)?;
+                write_cycle_id(f, cycle_id.as_deref())
+            }
+            Self::Skipped { position, reason } => {
+                write!(f, "Skipped position {}: {}", position, reason)
+            }
+        }
+    }
+}
+
+/// Append the priority score of a checked position, if it had one
+fn write_priority_score(f: &mut fmt::Formatter<'_>, priority_score: Option<f64>) -> fmt::Result {
+    match priority_score {
+        Some(score) => write!(f, " priority {:.2}", score),
+        None => Ok(()),
+    }
+}
+
+/// Append the cycle a checked position was evaluated in, if it was
+fn write_cycle_id(f: &mut fmt::Formatter<'_>, cycle_id: Option<&str>) -> fmt::Result {
+    match cycle_id {
+        Some(cycle_id) => write!(f, " cycle {}", cycle_id),
+        None => Ok(()),
+    }
+}
+
+/// Why a liquidatable position, or one due a check, wasn't liquidated
+///
+/// Displays as the free-form reason logged before skips were typed, and
+/// serializes as a snake_case tag, carrying the details of those that have any.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The position was liquidated too recently
+    Cooldown,
+    /// An earlier liquidation, by its signature, may still land
+    AwaitingConfirmation(String),
+    /// The position's symbol is blacklisted
+    SymbolBlacklisted,
+    /// The position's symbol isn't whitelisted
+    SymbolNotWhitelisted,
+    /// The amount to liquidate is below the symbol's step size or minimum notional
+    BelowMinSize {
+        /// Amount that was to be liquidated (in units of the symbol)
+        amount: f64,
+        /// The position's symbol
+        symbol: String,
+    },
+    /// The position is above the maximum size liquidated
+    AboveMaxSize,
+    /// The liquidation would cost more than it pays, by its profit estimate
+    Unprofitable(String),
+    /// The cycle's per-symbol or notional caps were reached
+    Throttled,
+    /// A fresh price no longer finds the position liquidatable
+    PositionRecovered {
+        /// Cycle whose snapshot found the position liquidatable
+        cycle_id: String,
+        /// Mark price in the cycle's snapshot
+        from: f64,
+        /// Fresh mark price
+        to: f64,
+    },
+    /// The position's market is disabled
+    MarketNotTrading,
+    /// Operators hold submissions back
+    MonitorOnly,
+    /// The engine is warming up
+    WarmingUp,
+    /// The position is flagged under the margin call and waits out its grace
+    /// period
+    GracePeriodActive {
+        /// When the grace period ends (Unix timestamp)
+        grace_ends_at: i64,
+    },
+    /// Another check is liquidating the position
+    InFlight,
+    /// The circuit breaker paused liquidation
+    CircuitBreaker {
+        /// When the circuit breaker tripped (Unix timestamp)
+        tripped_at: i64,
+    },
+    /// The cluster's slot stopped advancing, so the RPC node is treated as
+    /// unhealthy
+    SlotStalled {
+        /// When the slot last advanced (Unix timestamp)
+        advanced_at: i64,
+    },
+    /// The position was closed on chain
+    PositionClosed,
+    /// The position's account on chain doesn't back liquidating it, e.g.
+    /// collateral landed since the position was fetched
+    CollateralUpdated,
+    /// The position's bad debt would exceed the window's limit
+    BadDebtLimit {
+        /// Bad debt already absorbed in the window (in quote currency)
+        window_bad_debt: f64,
+        /// The position's bad debt (in quote currency)
+        bad_debt: f64,
+        /// Bad debt allowed within the window (in quote currency)
+        limit: f64,
+    },
+    /// The order book has no depth within the slippage bound
+    NoDepth {
+        /// The slippage bound (in basis points)
+        max_slippage_bps: u16,
+    },
+    /// The repayment rounds down to nothing in the debt mint's base units,
+    /// which the program rejects
+    RepayRoundsToZero,
+    /// The program would reject the repayment for leaving the position less
+    /// healthy without closing it
+    WorsensHealth {
+        /// Repayment that was to be made (in base units of the debt mint)
+        repay_amount: u64,
+    },
+    /// The position was quarantined out of check cycles, for the given reason
+    Quarantined(String),
+    /// The position broke the ingest rules and is monitored as suspect until
+    /// operators clear it
+    Suspect,
+    /// The oracle couldn't price the position's symbol this cycle, for the
+    /// given reason
+    PriceUnavailable(String),
+    /// Any other reason
+    Other(String),
+}
+
+impl SkipReason {
+    /// The snake_case tag the reason serializes as, which labels skip counters
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Cooldown => "cooldown",
+            Self::AwaitingConfirmation(_) => "awaiting_confirmation",
+            Self::SymbolBlacklisted => "symbol_blacklisted",
+            Self::SymbolNotWhitelisted => "symbol_not_whitelisted",
+            Self::BelowMinSize { .. } => "below_min_size",
+            Self::AboveMaxSize => "above_max_size",
+            Self::Unprofitable(_) => "unprofitable",
+            Self::Throttled => "throttled",
+            Self::PositionRecovered { .. } => "position_recovered",
+            Self::MarketNotTrading => "market_not_trading",
+            Self::MonitorOnly => "monitor_only",
+            Self::WarmingUp => "warming_up",
+            Self::GracePeriodActive { .. } => "grace_period_active",
+            Self::InFlight => "in_flight",
+            Self::CircuitBreaker { .. } => "circuit_breaker",
+            Self::SlotStalled { .. } => "slot_stalled",
+            Self::PositionClosed => "position_closed",
+            Self::CollateralUpdated => "collateral_updated",
+            Self::BadDebtLimit { .. } => "bad_debt_limit",
+            Self::NoDepth { .. } => "no_depth",
+            Self::RepayRoundsToZero => "repay_rounds_to_zero",
+            Self::WorsensHealth { .. } => "worsens_health",
+            Self::Quarantined(_) => "quarantined",
+            Self::Suspect => "suspect",
+            Self::PriceUnavailable(_) => "price_unavailable",
+            Self::Other(_) => "other",
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cooldown => write!(f, "cooldown"),
+            Self::AwaitingConfirmation(signature) => write!(f, "awaiting confirmation of {}", signature),
+            Self::SymbolBlacklisted => write!(f, "symbol blacklisted"),
+            Self::SymbolNotWhitelisted => write!(f, "symbol not whitelisted"),
+            Self::BelowMinSize { amount, symbol } => {
+                write!(f, "{} of {} is below its step size or minimum notional", amount, symbol)
+            }
+            Self::AboveMaxSize => write!(f, "above maximum position size"),
+            Self::Unprofitable(estimate) => write!(f, "unprofitable: {}", estimate),
+            Self::Throttled => write!(f, "throttled"),
+            Self::PositionRecovered { cycle_id, from, to } => {
+                write!(f, "price moved since cycle {}: {} to {}", cycle_id, from, to)
+            }
+            Self::MarketNotTrading => write!(f, "market disabled"),
+            Self::MonitorOnly => write!(f, "monitor-only"),
+            Self::WarmingUp => write!(f, "warming up"),
+            Self::GracePeriodActive { grace_ends_at } => write!(f, "grace period active until {}", grace_ends_at),
+            Self::InFlight => write!(f, "liquidation already in flight"),
+            Self::CircuitBreaker { tripped_at } => {
+                write!(f, "circuit breaker tripped at {}, awaiting resume", tripped_at)
+            }
+            Self::SlotStalled { advanced_at } => write!(f, "slot stalled since {}", advanced_at),
+            Self::PositionClosed => write!(f, "position closed"),
+            Self::CollateralUpdated => write!(f, "collateral updated"),
+            Self::BadDebtLimit {
+                window_bad_debt,
+                bad_debt,
+                limit,
             } => write!(
                 f,
-                "Failed to liquidate position {} after {} attempts: {}",
-                position, attempts, error
+                "bad debt limit: {:.2} in window plus {:.2} exceeds {:.2}",
+                window_bad_debt, bad_debt, limit
             ),
-            Self::Skipped { position, reason } => {
-                write!(f, "Skipped position {}: {}", position, reason)
+            Self::NoDepth { max_slippage_bps } => write!(f, "no depth within {} bps of slippage", max_slippage_bps),
+            Self::RepayRoundsToZero => write!(f, "repayment rounds to zero"),
+            Self::WorsensHealth { repay_amount } => {
+                write!(f, "repaying {} would leave the position less healthy", repay_amount)
             }
+            Self::Quarantined(reason) => write!(f, "quarantined: {}", reason),
+            Self::Suspect => write!(f, "suspect position awaiting operator review"),
+            Self::PriceUnavailable(reason) => write!(f, "price unavailable: {}", reason),
+            Self::Other(reason) => write!(f, "{}", reason),
         }
     }
 }
 
 /// Position status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PositionStatus {
     /// Position is active and healthy
     Active,
@@ -176,45 +1383,466 @@ impl fmt::Display for PositionStatus {
     }
 }
 
+/// Why a position is at risk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AtRiskReason {
+    /// Its health factor is below `at_risk_health_factor`
+    HealthFactor,
+    /// It's healthy, but funding at the current rate would carry its
+    /// liquidation price past the mark price within the last of
+    /// [`FUNDING_PROJECTION_HOURS`] even if the price doesn't move
+    Funding,
+}
+
+impl fmt::Display for AtRiskReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HealthFactor => write!(f, "health_factor"),
+            Self::Funding => write!(f, "funding"),
+        }
+    }
+}
+
+/// Hours of funding position updates project liquidation prices over
+pub(crate) const FUNDING_PROJECTION_HOURS: [u32; 3] = [1, 8, 24];
+
+/// Liquidation price of a position once some hours of funding at its symbol's
+/// current rate are paid, the price standing still
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FundingProjection {
+    /// Hours of funding projected
+    pub hours: u32,
+    /// The projected liquidation price (`None` while funding carries it away
+    /// from the market)
+    pub liquidation_price: Option<Amount>,
+}
+
 /// Position update event
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct PositionUpdate {
     /// The position's address
-    pub address: Pubkey,
+    pub address: PositionAddress,
     /// The owner's address
-    pub owner: Pubkey,
+    pub owner: OwnerAddress,
     /// The trading pair symbol
     pub symbol: String,
     /// The current size (in base currency)
-    pub size: f64,
+    pub size: Amount,
     /// The entry price
-    pub entry_price: f64,
+    pub entry_price: Amount,
     /// The current margin (in quote currency)
-    pub margin: f64,
+    pub margin: Amount,
     /// Whether the position is long
     pub is_long: bool,
     /// The current status
     pub status: PositionStatus,
+    /// Why the position is at risk, if it is
+    #[serde(default)]
+    pub at_risk_reason: Option<AtRiskReason>,
     /// The current leverage
-    pub leverage: f64,
-    /// The liquidation price
-    pub liquidation_price: f64,
+    pub leverage: Amount,
+    /// The liquidation price (if the position can be liquidated)
+    pub liquidation_price: Option<Amount>,
+    /// How far the mark price is from the liquidation price (in basis points
+    /// of the mark price), negative once past it
+    #[serde(default)]
+    pub distance_to_liquidation_bps: Option<f64>,
+    /// Seconds until the symbol's recent drift would carry the mark price to
+    /// the liquidation price, zero once past it (`None` while it drifts away)
+    #[serde(default)]
+    pub estimated_time_to_liquidation_secs: Option<f64>,
+    /// Liquidation prices projected over each of [`FUNDING_PROJECTION_HOURS`]
+    /// of funding at the symbol's current rate (empty without a funding source)
+    #[serde(default)]
+    pub funding_projections: Vec<FundingProjection>,
     /// The current mark price
-    pub mark_price: f64,
+    pub mark_price: Amount,
     /// The unrealized PnL
-    pub unrealized_pnl: f64,
+    pub unrealized_pnl: Amount,
     /// The margin ratio (as a percentage)
     pub margin_ratio: f64,
     /// The maintenance margin requirement (as a percentage)
     pub maintenance_margin: f64,
+    /// Equity over the maintenance margin requirement, liquidatable below 1.0
+    /// (`None` for positions without exposure)
+    #[serde(default)]
+    pub health_factor: Option<f64>,
+    /// Share of the position's side ranked at or behind it in the
+    /// auto-deleveraging queue, 1.0 being first in line (`None` unless profitable)
+    #[serde(default)]
+    pub adl_quantile: Option<f64>,
+    /// Whether the position was opened at more leverage than its margin tier
+    /// allows positions of its entry notional
+    #[serde(default)]
+    pub exceeds_max_leverage: bool,
+    /// Metadata of the position, e.g. the exchange backend's id of it
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
     /// The timestamp of the update
     pub timestamp: i64,
 }
 
+/// Event pushed to engine subscribers
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum EngineEvent {
+    /// A position's risk metrics changed
+    PositionUpdate(PositionUpdate),
+    /// A liquidation was attempted
+    Liquidation(LiquidationEvent),
+}
+
+impl EngineEvent {
+    /// The symbol of the position the event is about
+    pub fn symbol(&self) -> &str {
+        match self {
+            Self::PositionUpdate(update) => &update.symbol,
+            Self::Liquidation(event) => &event.symbol,
+        }
+    }
+    
+    /// The owner of the position the event is about
+    pub fn owner(&self) -> OwnerAddress {
+        match self {
+            Self::PositionUpdate(update) => update.owner,
+            Self::Liquidation(event) => event.owner,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::signature::Keypair;
+    use crate::tier::MarginTier;
+    use solana_sdk::signature::{Keypair, Signer};
+    
+    #[test]
+    fn test_config_update_is_validated() {
+        let config = LiquidationConfig::default();
+        assert!(config.validate().is_ok());
+        
+        let update = ConfigUpdate {
+            maintenance_margin: Some(0.1),
+            dry_run: Some(false),
+            ..Default::default()
+        };
+        let updated = config.with_update(&update).unwrap();
+        assert_eq!(updated.maintenance_margin, 0.1);
+        assert!(!updated.dry_run);
+        assert_eq!(updated.max_concurrent_liquidations, config.max_concurrent_liquidations);
+        
+        let invalid = ConfigUpdate {
+            maintenance_margin: Some(1.5),
+            ..Default::default()
+        };
+        assert!(matches!(config.with_update(&invalid), Err(LiquidationError::ConfigError(_))));
+        let invalid = ConfigUpdate {
+            max_concurrent_liquidations: Some(0),
+            ..Default::default()
+        };
+        assert!(config.with_update(&invalid).is_err());
+    }
+    
+    #[test]
+    fn test_config_violations() {
+        assert!(LiquidationConfig::default().violations().is_empty());
+
+        let config = LiquidationConfig {
+            maintenance_margin: 1.5,
+            at_risk_health_factor: 0.9,
+            insurance_fund_balance: -1.0,
+            max_liquidations_per_symbol_per_cycle: Some(0),
+            max_liquidation_percent: 0,
+            collateral_weights: HashMap::from([("SOL/USD".to_string(), 1.2), ("USDC/USD".to_string(), 1.0)]),
+            market_liquidity: HashMap::from([("BTC/USD".to_string(), 0.0)]),
+            margin_tiers: HashMap::from([(
+                "BTC/USD".to_string(),
+                MarginTierSchedule::new(vec![
+                    MarginTier {
+                        notional_threshold: 0.0,
+                        maintenance_margin: 0.1,
+                        max_leverage: 10.0,
+                    },
+                    MarginTier {
+                        notional_threshold: 100_000.0,
+                        maintenance_margin: 0.05,
+                        max_leverage: 10.0,
+                    },
+                ]),
+            )]),
+            symbol_specs: HashMap::from([(
+                "BTC/USD".to_string(),
+                SymbolSpec {
+                    step_size: Some(0.0),
+                    ..Default::default()
+                },
+            )]),
+            markets: vec![
+                MarketConfig::new(PROGRAM_ID, "BTC/USD", 0.05),
+                MarketConfig::new(PROGRAM_ID, "BTC/USD", 2.0),
+            ],
+            rate_limit: RateLimitConfig {
+                burst: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let violations: Vec<String> = config.violations().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            violations,
+            [
+                "maintenance_margin must be between 0 and 1, got 1.5",
+                "at_risk_health_factor must be at least 1, got 0.9",
+                "insurance_fund_balance must not be negative, got -1",
+                "max_liquidations_per_symbol_per_cycle must be at least 1",
+                "max_liquidation_percent must be between 1 and 100, got 0",
+                "collateral_weights.SOL/USD must be greater than 0 and at most 1, got 1.2",
+                "market_liquidity.BTC/USD must be positive, got 0",
+                "margin_tiers.BTC/USD[1].maintenance_margin must be at least the previous tier's 0.1, got 0.05",
+                "symbol_specs.BTC/USD.step_size must be positive, got 0",
+                "markets[1].symbol BTC/USD already has a market",
+                "markets[1].maintenance_margin must be between 0 and 1, got 2",
+                "rate_limit.burst must be at least 1",
+            ]
+        );
+        // Validation fails with the first of them
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "Configuration error: maintenance_margin must be between 0 and 1, got 1.5"
+        );
+    }
+    
+    #[test]
+    fn test_config_from_toml() {
+        let config = LiquidationConfig::from_toml(
+            r#"
+            maintenance_margin = 0.1
+            whitelisted_symbols = ["SOL/USD"]
+            webhook_events = ["liquidated"]
+
+            [price_accounts]
+            "SOL/USD" = "J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix"
+
+            [collateral_weights]
+            "SOL/USD" = 0.9
+
+            [webhook_owner_urls]
+            "J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix" = "https://example.com/hooks"
+
+            [rate_limit]
+            burst = 5
+
+            [[margin_tiers."SOL/USD"]]
+            notional_threshold = 0
+            maintenance_margin = 0.1
+            max_leverage = 10
+
+            [[margin_tiers."SOL/USD"]]
+            notional_threshold = 1000000
+            maintenance_margin = 0.2
+            max_leverage = 5
+
+            [[markets]]
+            program_id = "J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix"
+            symbol = "ETH/USD"
+            maintenance_margin = 0.08
+            close_factor = 0.25
+            enabled = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.maintenance_margin, 0.1);
+        assert_eq!(config.rate_limit.burst, 5);
+        assert_eq!(config.rate_limit.requests_per_second, RateLimitConfig::default().requests_per_second);
+        assert_eq!(
+            config.price_accounts["SOL/USD"].to_string(),
+            "J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix"
+        );
+        assert_eq!(config.collateral_weights["SOL/USD"], 0.9);
+        assert_eq!(config.webhook_events, [WebhookEvent::Liquidated]);
+        assert_eq!(config.webhook_owner_urls.values().next().unwrap(), "https://example.com/hooks");
+        assert_eq!(config.margin_params("ETH/USD"), MarginParams::shared(0.08));
+        assert_eq!(config.margin_params("SOL/USD"), MarginParams::shared(0.1));
+        assert_eq!(config.margin_tiers["SOL/USD"].tiers[1].max_leverage, 5.0);
+        assert_eq!(config.liquidation_fraction("ETH/USD", 0.0), 0.25);
+        assert!(!config.market_enabled("ETH/USD") && config.market_enabled("SOL/USD"));
+        // Disabled markets' programs aren't followed
+        assert_eq!(config.program_ids(), [PROGRAM_ID]);
+        assert_eq!(config.program_markets(), [(PROGRAM_ID, 0)]);
+
+        assert!(LiquidationConfig::from_toml("maintenance_margin = 1.5").is_err());
+        assert!(LiquidationConfig::from_toml("maintenance_margin = \"high\"").is_err());
+        assert!(LiquidationConfig::from_toml("[price_accounts]\n\"SOL/USD\" = \"not-a-pubkey\"").is_err());
+        assert!(LiquidationConfig::from_toml("webhook_events = [\"margin_call\"]").is_err());
+    }
+    
+    #[test]
+    fn test_config_diff() {
+        let default = LiquidationConfig::default();
+        assert!(default.diff(&default).is_empty());
+
+        let mut config = default.clone();
+        config.maintenance_margin = 0.1;
+        config.rate_limit.burst = 5;
+        config.max_window_bad_debt = Some(1_000.0);
+        let changes: Vec<String> = default.diff(&config).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            changes,
+            [
+                "maintenance_margin: 0.05 -> 0.1",
+                "max_window_bad_debt: null -> 1000.0",
+                "rate_limit.burst: 20 -> 5",
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_hot_reload_keeps_startup_fields() {
+        let current = LiquidationConfig::default();
+        let mut reloaded = current.clone();
+        reloaded.maintenance_margin = 0.1;
+        reloaded.whitelisted_symbols = vec!["BTC/USD".to_string()];
+        reloaded.rate_limit.burst = 5;
+        reloaded.database_path = Some("liquidations.db".to_string());
+
+        let (config, left_out) = current.hot_reload(&reloaded);
+        assert_eq!(config.maintenance_margin, 0.1);
+        assert_eq!(config.whitelisted_symbols, ["BTC/USD"]);
+        assert_eq!((config.rate_limit.burst, config.database_path), (current.rate_limit.burst, None));
+        let left_out: Vec<String> = left_out.iter().map(|change| change.field.clone()).collect();
+        assert_eq!(left_out, ["database_path", "rate_limit.burst"]);
+    }
+    
+    #[test]
+    fn test_config_hash_is_stable() {
+        let config = LiquidationConfig::default();
+        let hash = config.config_hash();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, LiquidationConfig::default().config_hash());
+
+        // Maps hash the same whatever order they were filled in
+        let owners: Vec<OwnerAddress> = (0..8).map(|_| OwnerAddress::new_unique()).collect();
+        let mut forward = config.clone();
+        let mut backward = config.clone();
+        for owner in &owners {
+            forward.webhook_owner_urls.insert(*owner, format!("https://hooks.example/{}", owner));
+        }
+        for owner in owners.iter().rev() {
+            backward.webhook_owner_urls.insert(*owner, format!("https://hooks.example/{}", owner));
+        }
+        assert_eq!(forward.config_hash(), backward.config_hash());
+        assert_ne!(forward.config_hash(), hash);
+
+        let mut changed = config.clone();
+        changed.maintenance_margin = 0.06;
+        assert_ne!(changed.config_hash(), hash);
+        let mut rotated = config.clone();
+        rotated.webhook_secret = Some("hunter2".to_string());
+        rotated.alerts.slack_webhook_url = Some("https://hooks.slack.com/services/T000/B000/XXXX".to_string());
+        assert_eq!(rotated.config_hash(), hash);
+    }
+
+    #[test]
+    fn test_audit_log_must_be_json_lines() {
+        let mut config = LiquidationConfig {
+            audit_log_path: Some("audit.jsonl".to_string()),
+            ..LiquidationConfig::default()
+        };
+        assert!(config.validate().is_ok());
+        config.audit_log_path = Some("audit.csv".to_string());
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("audit_log_path"), "{}", error);
+    }
+
+    #[test]
+    fn test_symbol_specs_round_displayed_prices() {
+        let config: LiquidationConfig = toml::from_str(
+            r#"
+            [symbol_specs]
+            "BTC/USD" = { tick_size = 0.5, step_size = 0.001 }
+            "#,
+        )
+        .unwrap();
+        assert!(config.violations().is_empty());
+        assert_eq!(config.symbol_spec("BTC/USD").min_notional, None);
+
+        // Longs are shown liquidated at the tick above, shorts at the tick below
+        let owner = Keypair::new().pubkey();
+        let long = Position::new(Pubkey::new_unique(), owner, "BTC/USD", 2.0, 60_000.0, 10_000.0, true);
+        let short = Position::new(Pubkey::new_unique(), owner, "BTC/USD", 2.0, 48_000.0, 10_000.0, false);
+        assert_eq!(config.displayed_liquidation_price(&long, Some(56_893.234817)), Some(56_893.5));
+        assert_eq!(config.displayed_liquidation_price(&short, Some(56_893.234817)), Some(56_893.0));
+        assert_eq!(config.displayed_liquidation_price(&short, None), None);
+
+        // Symbols without a spec are shown as computed
+        let eth = Position::new(Pubkey::new_unique(), owner, "ETH/USD", 1.0, 3_000.0, 1_000.0, true);
+        assert_eq!(config.displayed_liquidation_price(&eth, Some(2105.2631)), Some(2105.2631));
+    }
+    
+    #[test]
+    fn test_margin_tiers_follow_notional() {
+        let config = LiquidationConfig {
+            margin_tiers: HashMap::from([(
+                "BTC/USD".to_string(),
+                MarginTierSchedule::new(vec![
+                    MarginTier {
+                        notional_threshold: 0.0,
+                        maintenance_margin: 0.05,
+                        max_leverage: 20.0,
+                    },
+                    MarginTier {
+                        notional_threshold: 100_000.0,
+                        maintenance_margin: 0.1,
+                        max_leverage: 10.0,
+                    },
+                ]),
+            )]),
+            ..Default::default()
+        };
+        let owner = Keypair::new().pubkey();
+        // A 2 BTC short opened 96,000 notional, in the first tier
+        let short = Position::new(Pubkey::new_unique(), owner, "BTC/USD", 2.0, 48_000.0, 10_000.0, false);
+
+        // Just below the boundary it's held to 5% and healthy
+        let params = config.margin_params_at(&short, 49_999.0);
+        assert_eq!(params, MarginParams::shared(0.05));
+        assert!(!short.is_undercollateralized(49_999.0, params));
+        // Just above it, the same position is held to 10% and liquidatable,
+        // though it would still be healthy at 5%
+        let params = config.margin_params_at(&short, 50_001.0);
+        assert_eq!(params, MarginParams::shared(0.1));
+        assert!(short.is_undercollateralized(50_001.0, params));
+        assert!(!short.is_undercollateralized(50_001.0, MarginParams::shared(0.05)));
+
+        // The liquidation price is derived from the tier of the current notional
+        let update = short.update(49_000.0, PositionStatus::Active, config.margin_params_at(&short, 49_000.0), 0);
+        assert_eq!(update.maintenance_margin, 5.0);
+        assert!((update.liquidation_price.unwrap() - 50_476.2).abs() < 0.1);
+        let update = short.update(50_001.0, PositionStatus::AtRisk, config.margin_params_at(&short, 50_001.0), 0);
+        assert_eq!(update.maintenance_margin, 10.0);
+        assert!((update.liquidation_price.unwrap() - 48_181.8).abs() < 0.1);
+
+        // Symbols without tiers keep their flat margin
+        let eth = Position::new(Pubkey::new_unique(), owner, "ETH/USD", 100.0, 3_000.0, 10_000.0, true);
+        assert_eq!(config.margin_params_at(&eth, 3_000.0), MarginParams::shared(0.05));
+        assert!(!config.exceeds_max_leverage(&eth));
+
+        // 9.6x at entry is within the first tier's 20x, but 120,000 notional
+        // opened at 12x exceeds the second tier's 10x
+        assert!(!config.exceeds_max_leverage(&short));
+        let long = Position::new(Pubkey::new_unique(), owner, "BTC/USD", 2.0, 60_000.0, 10_000.0, true);
+        assert!(config.exceeds_max_leverage(&long));
+    }
+    
+    #[test]
+    fn test_liquidation_fraction() {
+        let mut config = LiquidationConfig::default();
+        assert_eq!(config.liquidation_fraction("BTC/USD", 0.0), 0.5);
+        assert_eq!(config.liquidation_fraction("BTC/USD", 10.0), 1.0);
+        config.enable_partial_liquidations = false;
+        assert_eq!(config.liquidation_fraction("BTC/USD", 0.0), 1.0);
+    }
     
     #[test]
     fn test_liquidation_result_display() {
@@ -223,22 +1851,49 @@ mod tests {
         let success = LiquidationResult::Success {
             position,
             amount: 1.5,
+            estimated_impact_bps: Some(12.25),
             signature: "test_sig".to_string(),
+            compute_unit_limit: 120_000,
+            correlation_id: "abc".to_string(),
+            priority_score: Some(12.345),
+            price: 50000.0,
+            cycle_id: Some("c1".to_string()),
         };
         assert!(success.to_string().contains("Liquidated 1.5"));
+        assert!(success.to_string().contains("at 50000 (~12.2 bps impact) in tx: test_sig"));
+        assert!(success.to_string().contains("(120000 CU)"));
+        assert!(success.to_string().ends_with("[abc] priority 12.35 cycle c1"));
         
         let failure = LiquidationResult::Failure {
             position,
             error: "test error".to_string(),
+            sub_reason: None,
             attempts: 3,
+            correlation_id: "abc".to_string(),
+            priority_score: None,
+            price: 50000.0,
+            cycle_id: None,
         };
         assert!(failure.to_string().contains("Failed to liquidate"));
+        assert!(failure.to_string().ends_with("attempts: test error [abc]"));
+        
+        let front_run = LiquidationResult::Failure {
+            position,
+            error: "test error".to_string(),
+            sub_reason: Some("front-run by Comp".to_string()),
+            attempts: 1,
+            correlation_id: "abc".to_string(),
+            priority_score: None,
+            price: 50000.0,
+            cycle_id: None,
+        };
+        assert!(front_run.to_string().ends_with("test error (front-run by Comp) [abc]"));
         
         let skipped = LiquidationResult::Skipped {
             position,
-            reason: "test reason".to_string(),
+            reason: SkipReason::InFlight,
         };
-        assert!(skipped.to_string().contains("Skipped position"));
+        assert!(skipped.to_string().ends_with(": liquidation already in flight"));
     }
     
     #[test]
@@ -249,4 +1904,35 @@ mod tests {
         assert_eq!(PositionStatus::Liquidated.to_string(), "liquidated");
         assert_eq!(PositionStatus::Closed.to_string(), "closed");
     }
+    
+    #[test]
+    fn test_skip_reasons_tagged_by_label() {
+        let reasons = [
+            SkipReason::Cooldown,
+            SkipReason::MarketNotTrading,
+            SkipReason::Unprofitable("reward 0.50 < cost 1.00".to_string()),
+            SkipReason::BelowMinSize {
+                amount: 0.0004,
+                symbol: "BTC/USD".to_string(),
+            },
+            SkipReason::Other("maintenance".to_string()),
+            SkipReason::RepayRoundsToZero,
+            SkipReason::WorsensHealth { repay_amount: 47_619 },
+        ];
+        for reason in &reasons {
+            let tag = match serde_json::to_value(reason).unwrap() {
+                serde_json::Value::String(tag) => tag,
+                serde_json::Value::Object(tagged) => tagged.keys().next().unwrap().clone(),
+                other => panic!("untagged {}", other),
+            };
+            assert_eq!(tag, reason.label());
+        }
+        
+        // The old free-form reasons carry on in logs
+        assert_eq!(reasons[1].to_string(), "market disabled");
+        assert_eq!(reasons[3].to_string(), "0.0004 of BTC/USD is below its step size or minimum notional");
+        let json = r#"{"unprofitable":"reward 0.50 < cost 1.00"}"#;
+        assert_eq!(serde_json::to_string(&reasons[2]).unwrap(), json);
+        assert_eq!(serde_json::from_str::<SkipReason>(json).unwrap(), reasons[2]);
+    }
 }
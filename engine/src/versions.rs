@@ -0,0 +1,171 @@
+use crate::address::PositionAddress;
+use crate::error::LiquidationError;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Idempotency keys remembered, the oldest forgotten first
+pub(crate) const IDEMPOTENCY_KEYS_KEPT: usize = 10_000;
+
+/// How a write of a position is ordered against others to it
+///
+/// Versions only need to increase with recency: positions loaded from chain
+/// carry the slot their account was read at, so writes from elsewhere should
+/// use slots too for the two to reconcile. A write without a version always
+/// applies and leaves the version as it was.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PositionWrite {
+    /// Version of the position written
+    #[serde(default)]
+    pub version: Option<u64>,
+    /// Key of the request making the write, the same for each of its retries
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+impl PositionWrite {
+    /// A write of the position at `version`
+    pub fn at_version(version: u64) -> Self {
+        Self {
+            version: Some(version),
+            idempotency_key: None,
+        }
+    }
+}
+
+/// What became of a write of a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteOutcome {
+    /// The position was written
+    Applied,
+    /// A write with the same idempotency key was already applied, so this one
+    /// was ignored
+    Duplicate,
+}
+
+/// Last version applied to each position, and the idempotency keys of recent
+/// writes
+///
+/// Versions are forgotten with their position, so a position monitored again
+/// starts over.
+#[derive(Debug, Default)]
+pub(crate) struct PositionVersions {
+    applied: HashMap<PositionAddress, u64>,
+    keys: HashSet<String>,
+    key_order: VecDeque<String>,
+}
+
+impl PositionVersions {
+    /// Admit a write of the position at `address`, recording its version and
+    /// key
+    ///
+    /// Writes older than the version applied fail with
+    /// [`LiquidationError::StaleUpdate`]; retries of a write already admitted
+    /// come back [`WriteOutcome::Duplicate`] and aren't recorded again.
+    pub(crate) fn admit(
+        &mut self,
+        address: PositionAddress,
+        write: &PositionWrite,
+    ) -> Result<WriteOutcome, LiquidationError> {
+        if let Some(key) = &write.idempotency_key
+            && self.keys.contains(key)
+        {
+            return Ok(WriteOutcome::Duplicate);
+        }
+        if let Some(attempted_version) = write.version {
+            if let Some(&current_version) = self.applied.get(&address)
+                && attempted_version < current_version
+            {
+                return Err(LiquidationError::StaleUpdate {
+                    current_version,
+                    attempted_version,
+                });
+            }
+            self.applied.insert(address, attempted_version);
+        }
+        if let Some(key) = &write.idempotency_key {
+            self.remember(key.clone());
+        }
+        Ok(WriteOutcome::Applied)
+    }
+
+    /// Version last applied to a position, if any
+    pub(crate) fn version(&self, address: &PositionAddress) -> Option<u64> {
+        self.applied.get(address).copied()
+    }
+
+    /// Forget the version of a position no longer monitored
+    pub(crate) fn forget(&mut self, address: &PositionAddress) {
+        self.applied.remove(address);
+    }
+
+    /// Forget every position's version, keeping the idempotency keys
+    pub(crate) fn clear(&mut self) {
+        self.applied.clear();
+    }
+
+    fn remember(&mut self, key: String) {
+        if self.key_order.len() == IDEMPOTENCY_KEYS_KEPT
+            && let Some(oldest) = self.key_order.pop_front()
+        {
+            self.keys.remove(&oldest);
+        }
+        self.keys.insert(key.clone());
+        self.key_order.push_back(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyed(version: u64, key: &str) -> PositionWrite {
+        PositionWrite {
+            version: Some(version),
+            idempotency_key: Some(key.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_older_versions_rejected() {
+        let mut versions = PositionVersions::default();
+        let (address, other) = (PositionAddress::new_unique(), PositionAddress::new_unique());
+        assert_eq!(versions.admit(address, &PositionWrite::at_version(10)).unwrap(), WriteOutcome::Applied);
+        assert_eq!(versions.admit(address, &PositionWrite::at_version(10)).unwrap(), WriteOutcome::Applied);
+        assert!(matches!(
+            versions.admit(address, &PositionWrite::at_version(9)),
+            Err(LiquidationError::StaleUpdate { current_version: 10, attempted_version: 9 })
+        ));
+        assert_eq!(versions.version(&address), Some(10));
+        // Versions are per position, and unversioned writes always apply
+        assert_eq!(versions.admit(other, &PositionWrite::at_version(3)).unwrap(), WriteOutcome::Applied);
+        assert_eq!(versions.admit(address, &PositionWrite::default()).unwrap(), WriteOutcome::Applied);
+        assert_eq!(versions.version(&address), Some(10));
+
+        versions.forget(&address);
+        assert_eq!(versions.admit(address, &PositionWrite::at_version(1)).unwrap(), WriteOutcome::Applied);
+        versions.clear();
+        assert_eq!(versions.version(&other), None);
+    }
+
+    #[test]
+    fn test_retries_are_duplicates() {
+        let mut versions = PositionVersions::default();
+        let address = PositionAddress::new_unique();
+        assert_eq!(versions.admit(address, &keyed(5, "req-1")).unwrap(), WriteOutcome::Applied);
+        assert_eq!(versions.admit(address, &keyed(8, "req-2")).unwrap(), WriteOutcome::Applied);
+        // A late retry of the first request is ignored rather than failing
+        assert_eq!(versions.admit(address, &keyed(5, "req-1")).unwrap(), WriteOutcome::Duplicate);
+        assert_eq!(versions.version(&address), Some(8));
+        // while a new request with an old version is stale, and its key isn't kept
+        assert!(versions.admit(address, &keyed(6, "req-3")).is_err());
+        assert_eq!(versions.admit(address, &keyed(9, "req-3")).unwrap(), WriteOutcome::Applied);
+
+        // The oldest keys are forgotten first
+        for i in 0..IDEMPOTENCY_KEYS_KEPT {
+            versions.admit(address, &keyed(10, &format!("fill-{}", i))).unwrap();
+        }
+        assert_eq!(versions.keys.len(), IDEMPOTENCY_KEYS_KEPT);
+        assert_eq!(versions.admit(address, &keyed(10, "req-1")).unwrap(), WriteOutcome::Applied);
+        assert_eq!(versions.admit(address, &keyed(10, "fill-1")).unwrap(), WriteOutcome::Duplicate);
+    }
+}
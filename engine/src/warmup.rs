@@ -0,0 +1,126 @@
+use crate::types::{EnginePhase, WarmUpStatus};
+use std::collections::BTreeSet;
+
+/// Data the engine has confirmed since it started, and the evaluation cycles
+/// it has run on complete data
+///
+/// Liquidations are held back until every enabled market's positions were
+/// loaded, every monitored symbol was freshly priced and `warmup_cycles` cycles
+/// ran on that data; the engine then runs for good.
+#[derive(Debug, Default)]
+pub(crate) struct WarmUp {
+    phase: EnginePhase,
+    /// Markets whose positions were loaded in full at least once
+    loaded: BTreeSet<String>,
+    /// Enabled markets not loaded yet, as of the last cycle
+    unloaded: Vec<String>,
+    /// Monitored symbols the last cycle had no fresh price for
+    unpriced: Vec<String>,
+    /// Whether the last cycle had complete data
+    complete: bool,
+    /// Cycles run on complete data
+    cycles: u32,
+}
+
+impl WarmUp {
+    /// Start warming up, keeping markets already loaded
+    pub(crate) fn begin(&mut self) {
+        if self.phase == EnginePhase::Starting {
+            self.phase = EnginePhase::WarmingUp;
+        }
+    }
+
+    /// Record a full load of a market's positions
+    pub(crate) fn record_load(&mut self, symbol: &str) {
+        self.loaded.insert(symbol.to_string());
+    }
+
+    /// Whether liquidations are held back
+    pub(crate) fn is_warming_up(&self) -> bool {
+        self.phase == EnginePhase::WarmingUp
+    }
+
+    /// Judge the data a cycle starts with: the enabled markets and the
+    /// monitored symbols, and those priced
+    ///
+    /// Returns whether the warm-up ended, so this cycle liquidates.
+    pub(crate) fn observe<'a>(
+        &mut self,
+        markets: impl IntoIterator<Item = &'a str>,
+        symbols: impl IntoIterator<Item = &'a str>,
+        priced: impl Fn(&str) -> bool,
+        required_cycles: u32,
+    ) -> bool {
+        if !self.is_warming_up() {
+            return false;
+        }
+        self.unloaded = markets
+            .into_iter()
+            .filter(|market| !self.loaded.contains(*market))
+            .map(str::to_string)
+            .collect();
+        let unpriced: BTreeSet<&str> = symbols.into_iter().filter(|symbol| !priced(symbol)).collect();
+        self.unpriced = unpriced.into_iter().map(str::to_string).collect();
+        self.complete = self.unloaded.is_empty() && self.unpriced.is_empty();
+        if self.complete && self.cycles >= required_cycles {
+            self.phase = EnginePhase::Running;
+            return true;
+        }
+        false
+    }
+
+    /// Count a cycle run while warming up, if its data was complete
+    pub(crate) fn finish_cycle(&mut self) {
+        if self.is_warming_up() && self.complete {
+            self.cycles += 1;
+        }
+    }
+
+    /// Progress towards running, out of `required_cycles`
+    pub(crate) fn status(&self, required_cycles: u32) -> WarmUpStatus {
+        WarmUpStatus {
+            phase: self.phase,
+            unloaded_markets: self.unloaded.clone(),
+            unpriced_symbols: self.unpriced.clone(),
+            cycles_completed: self.cycles,
+            cycles_required: required_cycles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warm_up_waits_for_complete_data() {
+        let mut warm_up = WarmUp::default();
+        // Nothing is held back before the engine starts
+        assert!(!warm_up.observe(["BTC/USD"], ["BTC/USD"], |_| true, 1));
+        assert_eq!(warm_up.status(1).phase, EnginePhase::Starting);
+
+        warm_up.record_load("BTC/USD");
+        warm_up.begin();
+        assert!(warm_up.is_warming_up());
+        // ETH has no market to load, but no price either
+        assert!(!warm_up.observe(["BTC/USD", "SOL/USD"], ["ETH/USD", "BTC/USD", "ETH/USD"], |symbol| symbol == "BTC/USD", 1));
+        warm_up.finish_cycle();
+        let status = warm_up.status(1);
+        assert_eq!(status.unloaded_markets, ["SOL/USD"]);
+        assert_eq!(status.unpriced_symbols, ["ETH/USD"]);
+        assert_eq!(status.cycles_completed, 0);
+
+        // A cycle on complete data counts, and the next one runs
+        warm_up.record_load("SOL/USD");
+        assert!(!warm_up.observe(["BTC/USD", "SOL/USD"], ["ETH/USD"], |_| true, 1));
+        warm_up.finish_cycle();
+        assert_eq!(warm_up.status(1).cycles_completed, 1);
+        assert!(warm_up.observe(["BTC/USD", "SOL/USD"], ["ETH/USD"], |_| true, 1));
+        assert_eq!(warm_up.status(1).phase, EnginePhase::Running);
+
+        // For good, whatever the data later
+        assert!(!warm_up.observe(["BTC/USD"], ["ETH/USD"], |_| false, 1));
+        warm_up.begin();
+        assert!(!warm_up.is_warming_up());
+    }
+}
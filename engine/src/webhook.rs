@@ -0,0 +1,437 @@
+//! Webhook notifications to position owners before and after liquidation
+//!
+//! Each notification is POSTed as a JSON object naming the event and carrying
+//! the position update or liquidation event behind it:
+//!
+//! ```json
+//! {"event": "liquidated", "data": {"position": "...", "owner": "...", "amount": 0.5, ...}}
+//! ```
+//!
+//! With a secret configured, every request carries `sha256=` followed by the hex
+//! HMAC-SHA256 of its body in the [`SIGNATURE_HEADER`] header. Notifications
+//! are queued and delivered by a dedicated task, so a slow or failing endpoint
+//! never holds up a liquidation; once the queue is full the oldest
+//! notification is dropped.
+
+use crate::address::OwnerAddress;
+use crate::types::{LiquidationConfig, LiquidationEvent, PositionUpdate};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Header carrying the signature of a notification's body
+pub(crate) const SIGNATURE_HEADER: &str = "X-Liquidation-Signature";
+
+/// Default number of notifications waiting for delivery before the oldest is dropped
+pub const DEFAULT_WEBHOOK_QUEUE_CAPACITY: usize = 256;
+
+/// Default delay before retrying a failed delivery, doubled on every retry
+pub const DEFAULT_WEBHOOK_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Retries of a failed delivery before it's given up on
+pub(crate) const WEBHOOK_MAX_RETRIES: u32 = 3;
+
+/// Time allowed for one delivery attempt
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Kinds of notification a webhook can be sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A position's health fell below `at_risk_health_factor`, or a day's
+    /// funding at the current rate would carry it to liquidation
+    AtRisk,
+    /// A liquidation of the position is being submitted
+    Liquidating,
+    /// The position was liquidated
+    Liquidated,
+}
+
+impl WebhookEvent {
+    /// Every kind of notification
+    pub const ALL: [WebhookEvent; 3] = [Self::AtRisk, Self::Liquidating, Self::Liquidated];
+}
+
+/// Body of a webhook notification
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum WebhookPayload {
+    AtRisk(PositionUpdate),
+    Liquidating(PositionUpdate),
+    Liquidated(LiquidationEvent),
+}
+
+impl WebhookPayload {
+    /// Kind of notification
+    pub fn event(&self) -> WebhookEvent {
+        match self {
+            Self::AtRisk(_) => WebhookEvent::AtRisk,
+            Self::Liquidating(_) => WebhookEvent::Liquidating,
+            Self::Liquidated(_) => WebhookEvent::Liquidated,
+        }
+    }
+
+    /// Owner of the position the notification is about
+    pub fn owner(&self) -> OwnerAddress {
+        match self {
+            Self::AtRisk(update) | Self::Liquidating(update) => update.owner,
+            Self::Liquidated(event) => event.owner,
+        }
+    }
+}
+
+/// Value of the [`SIGNATURE_HEADER`] header for a body signed with `secret`
+pub(crate) fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Where notifications are delivered and how they're signed
+#[derive(Debug, Clone, Default)]
+pub struct WebhookTargets {
+    /// Endpoint of owners without one of their own
+    pub url: Option<String>,
+    /// Endpoint of particular owners
+    pub owner_urls: HashMap<OwnerAddress, String>,
+    /// Secret signing every body, if any
+    pub secret: Option<String>,
+}
+
+impl WebhookTargets {
+    /// Targets set in a configuration
+    pub fn from_config(config: &LiquidationConfig) -> Self {
+        Self {
+            url: config.webhook_url.clone(),
+            owner_urls: config.webhook_owner_urls.clone(),
+            secret: config.webhook_secret.clone(),
+        }
+    }
+
+    /// Whether no notification has anywhere to go
+    pub fn is_empty(&self) -> bool {
+        self.url.is_none() && self.owner_urls.is_empty()
+    }
+
+    /// Endpoint notifications about an owner's positions are delivered to
+    pub fn url_for(&self, owner: &OwnerAddress) -> Option<&str> {
+        self.owner_urls.get(owner).or(self.url.as_ref()).map(String::as_str)
+    }
+}
+
+/// Counts of notifications by how they ended up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct WebhookStats {
+    /// Notifications the endpoint accepted
+    pub delivered: u64,
+    /// Notifications given up on after exhausting their retries
+    pub failed: u64,
+    /// Notifications dropped from a full queue before delivery was attempted
+    pub dropped: u64,
+}
+
+/// State shared between the notifier handles and the delivery task
+#[derive(Debug)]
+struct Shared {
+    targets: WebhookTargets,
+    queue: Mutex<VecDeque<WebhookPayload>>,
+    capacity: usize,
+    queued: Notify,
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Shared {
+    fn pop(&self) -> Option<WebhookPayload> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner).pop_front()
+    }
+}
+
+/// Handle for queueing webhook notifications to the delivery task
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    shared: Arc<Shared>,
+}
+
+impl WebhookNotifier {
+    /// Start a task delivering notifications to `targets`, keeping up to
+    /// `capacity` waiting and first retrying failures after `retry_delay`
+    pub fn spawn(targets: WebhookTargets, capacity: usize, retry_delay: Duration) -> (Self, JoinHandle<()>) {
+        let shared = Arc::new(Shared {
+            targets,
+            queue: Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+            queued: Notify::new(),
+            delivered: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        });
+        let handle = tokio::spawn(deliver_queued(shared.clone(), retry_delay));
+        (Self { shared }, handle)
+    }
+
+    /// Queue a notification without waiting, dropping the oldest waiting one if
+    /// the queue is full
+    ///
+    /// Notifications about owners without an endpoint are ignored.
+    pub fn notify(&self, payload: WebhookPayload) {
+        if self.shared.targets.url_for(&payload.owner()).is_none() {
+            return;
+        }
+        let mut queue = self.shared.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        if queue.len() >= self.shared.capacity
+            && let Some(oldest) = queue.pop_front()
+        {
+            let dropped = self.shared.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(
+                dropped,
+                "Webhook queue full, dropping {:?} notification for {}",
+                oldest.event(),
+                oldest.owner()
+            );
+        }
+        queue.push_back(payload);
+        drop(queue);
+        self.shared.queued.notify_one();
+    }
+
+    /// Counts of delivered, failed and dropped notifications so far
+    pub fn stats(&self) -> WebhookStats {
+        WebhookStats {
+            delivered: self.shared.delivered.load(Ordering::Relaxed),
+            failed: self.shared.failed.load(Ordering::Relaxed),
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Deliver queued notifications one at a time, for as long as the process runs
+async fn deliver_queued(shared: Arc<Shared>, retry_delay: Duration) {
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    loop {
+        let Some(payload) = shared.pop() else {
+            shared.queued.notified().await;
+            continue;
+        };
+        let Some(url) = shared.targets.url_for(&payload.owner()) else {
+            continue;
+        };
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to serialize {:?} notification: {}", payload.event(), e);
+                continue;
+            }
+        };
+        let signature = shared.targets.secret.as_deref().map(|secret| sign(secret, &body));
+
+        let mut delay = retry_delay;
+        let mut attempt = 0;
+        loop {
+            let mut request = client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            match request.send().await.and_then(|response| response.error_for_status()) {
+                Ok(_) => {
+                    shared.delivered.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+                Err(e) if attempt < WEBHOOK_MAX_RETRIES => {
+                    warn!("Webhook delivery to {} failed, retrying in {:?}: {}", url, delay, e);
+                }
+                Err(e) => {
+                    shared.failed.fetch_add(1, Ordering::Relaxed);
+                    error!(
+                        "Giving up on {:?} notification for {} after {} attempts: {}",
+                        payload.event(),
+                        payload.owner(),
+                        attempt + 1,
+                        e
+                    );
+                    break;
+                }
+            }
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::PositionAddress;
+    use crate::position::{MarginParams, Position};
+    use crate::types::PositionStatus;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A request received by the mock server
+    #[derive(Debug, Clone)]
+    struct Received {
+        path: String,
+        signature: Option<String>,
+        body: Vec<u8>,
+    }
+
+    /// HTTP server answering requests with the given statuses in turn, then 200
+    async fn mock_server(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let requests = received.clone();
+        tokio::spawn(async move {
+            let mut statuses = statuses.into_iter();
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut stream).await;
+                requests.lock().unwrap().push(request);
+                let status = statuses.next().unwrap_or(200);
+                let response = format!("HTTP/1.1 {} Mock\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> Received {
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 4096];
+        loop {
+            let read = stream.read(&mut chunk).await.unwrap();
+            buffer.extend_from_slice(&chunk[..read]);
+            let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") else {
+                continue;
+            };
+            let head = String::from_utf8_lossy(&buffer[..end]).to_string();
+            let header = |name: &str| {
+                head.lines().find_map(|line| {
+                    let (key, value) = line.split_once(':')?;
+                    key.eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+                })
+            };
+            let length: usize = header("content-length").map_or(0, |length| length.parse().unwrap());
+            if buffer.len() >= end + 4 + length || read == 0 {
+                return Received {
+                    path: head.split_whitespace().nth(1).unwrap_or_default().to_string(),
+                    signature: header(SIGNATURE_HEADER),
+                    body: buffer[end + 4..].to_vec(),
+                };
+            }
+        }
+    }
+
+    fn at_risk(owner: OwnerAddress) -> WebhookPayload {
+        let position = Position::new(PositionAddress::new_unique(), owner, "BTC/USD", 1.0, 60000.0, 6000.0, true);
+        WebhookPayload::AtRisk(position.update(57500.0, PositionStatus::AtRisk, MarginParams::shared(0.05), 1_700_000_000))
+    }
+
+    async fn wait_for(notifier: &WebhookNotifier, settled: u64) -> WebhookStats {
+        for _ in 0..500 {
+            let stats = notifier.stats();
+            if stats.delivered + stats.failed >= settled {
+                return stats;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("notifications not settled: {:?}", notifier.stats());
+    }
+
+    #[test]
+    fn test_signature() {
+        // The well-known HMAC-SHA256 example
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_signed_payload_retried_after_server_error() {
+        let (url, received) = mock_server(vec![500, 500]).await;
+        let targets = WebhookTargets {
+            url: Some(format!("{}/hooks", url)),
+            secret: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let (notifier, _) = WebhookNotifier::spawn(targets, DEFAULT_WEBHOOK_QUEUE_CAPACITY, Duration::from_millis(10));
+        let payload = at_risk(OwnerAddress::new_unique());
+        notifier.notify(payload.clone());
+
+        assert_eq!(wait_for(&notifier, 1).await, WebhookStats { delivered: 1, ..Default::default() });
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 3);
+        for request in &received {
+            assert_eq!(request.path, "/hooks");
+            assert_eq!(request.body, received[0].body);
+            assert_eq!(request.signature.as_deref(), Some(sign("s3cret", &request.body).as_str()));
+        }
+        let json: serde_json::Value = serde_json::from_slice(&received[0].body).unwrap();
+        assert_eq!(json["event"], "at_risk");
+        assert_eq!(json["data"]["owner"], payload.owner().to_string());
+        assert_eq!(json["data"]["status"], "at_risk");
+        assert_eq!(json["data"]["mark_price"], 57500.0);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_given_up_after_retries() {
+        let (url, received) = mock_server(vec![500; 10]).await;
+        let targets = WebhookTargets {
+            url: Some(url),
+            ..Default::default()
+        };
+        let (notifier, _) = WebhookNotifier::spawn(targets, DEFAULT_WEBHOOK_QUEUE_CAPACITY, Duration::from_millis(10));
+        notifier.notify(at_risk(OwnerAddress::new_unique()));
+
+        assert_eq!(wait_for(&notifier, 1).await, WebhookStats { failed: 1, ..Default::default() });
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1 + WEBHOOK_MAX_RETRIES as usize);
+        assert!(received.iter().all(|request| request.signature.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_oldest_and_owners_routed() {
+        let (url, received) = mock_server(vec![]).await;
+        let vip = OwnerAddress::new_unique();
+        let targets = WebhookTargets {
+            url: Some(format!("{}/default", url)),
+            owner_urls: HashMap::from([(vip, format!("{}/vip", url))]),
+            secret: None,
+        };
+        let (notifier, _) = WebhookNotifier::spawn(targets, 2, Duration::from_millis(10));
+        // Nothing is delivered until this test yields, so the first is dropped
+        let owners = [OwnerAddress::new_unique(), vip, OwnerAddress::new_unique()];
+        for owner in owners {
+            notifier.notify(at_risk(owner));
+        }
+        assert_eq!(notifier.stats().dropped, 1);
+
+        assert_eq!(wait_for(&notifier, 2).await.delivered, 2);
+        let paths: Vec<String> = received.lock().unwrap().iter().map(|request| request.path.clone()).collect();
+        assert_eq!(paths, ["/vip", "/default"]);
+
+        // Without a default endpoint, other owners aren't notified at all
+        let targets = WebhookTargets {
+            owner_urls: HashMap::from([(vip, url)]),
+            ..Default::default()
+        };
+        let (notifier, _) = WebhookNotifier::spawn(targets, 2, Duration::from_millis(10));
+        notifier.notify(at_risk(OwnerAddress::new_unique()));
+        assert!(notifier.shared.queue.lock().unwrap().is_empty());
+    }
+}
@@ -1,83 +1,547 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
-
+// Anchor's derives expand to `borsh::` paths, which must name its own borsh
+// rather than the crate dependency
+use anchor_lang::prelude::borsh;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use pyth_sdk_solana::state::{load_price_account, PriceStatus};
 
 declare_id!("Liqd8UyMVwSYFsETMWhJWEQ7DnDjEYwETaAh6hFkwxv");
 
+/// Share of the collateral's value counted against debt, in basis points: a
+/// position is liquidatable once its debt is more than 85% of the collateral's
+/// value, a margin above the `MAX_LOAN_TO_VALUE_BPS` it may borrow up to.
+pub const LIQUIDATION_THRESHOLD_BPS: u64 = 8_500;
+
+/// Largest debt that may be borrowed against the collateral's value, in basis
+/// points.
+pub const MAX_LOAN_TO_VALUE_BPS: u64 = 8_000;
+
+/// Oldest oracle price accepted for a liquidation, in seconds.
+pub const MAX_PRICE_AGE_SECS: i64 = 60;
+
+/// Close factor a new market starts with, in basis points of debt.
+pub const DEFAULT_CLOSE_FACTOR_BPS: u16 = 5_000;
+
+/// Liquidation bonus a new market starts with, in basis points.
+pub const DEFAULT_LIQUIDATION_BONUS_BPS: u16 = 500;
+
+/// Largest liquidation bonus the authority may set, in basis points.
+pub const MAX_LIQUIDATION_BONUS_BPS: u16 = 5_000;
+
+/// Most keepers the market's whitelist can hold.
+pub const MAX_KEEPERS: usize = 16;
+
+/// Health below which a position is liquidatable, in basis points: collateral
+/// value counted at `LIQUIDATION_THRESHOLD_BPS`, over debt.
+pub const LIQUIDATION_HEALTH_BPS: u16 = 10_000;
+
+/// Grace period a new market gives flagged positions, in seconds; zero
+/// liquidates positions as soon as they're liquidatable, without a margin call.
+pub const DEFAULT_GRACE_PERIOD_SECS: u32 = 0;
+
+/// Health below which a new market's positions may be flagged, in basis points.
+pub const DEFAULT_WARNING_HEALTH_BPS: u16 = 10_500;
+
+/// Health below which a new market's positions are liquidated without waiting
+/// out a grace period, in basis points.
+pub const DEFAULT_INSTANT_HEALTH_BPS: u16 = 9_000;
+
 #[program]
 pub mod liquidation_program {
     use super::*;
 
-    /// Initialize a user's position account.
+    /// Initialize a user's position account in the market.
     pub fn initialize_position(ctx: Context<InitializePosition>) -> Result<()> {
         let position = &mut ctx.accounts.position;
         position.owner = ctx.accounts.user.key();
         position.bump = ctx.bumps.position;
         position.collateral = 0;
         position.debt = 0;
+        position.closed = false;
+        position.clear_flag();
         Ok(())
     }
 
-    /// Deposit collateral into the position.
+    /// Create the market config and its collateral, debt and insurance fund
+    /// vaults, all held by the vault authority PDA, recording the mints, their
+    /// decimals and the price feed of the collateral. The market and its vault
+    /// authority are PDAs of `market_id`, so the program can hold several
+    /// markets. Only the program's upgrade authority may create a market, and
+    /// only whitelisted keepers may liquidate until it opens it up.
+    pub fn initialize_market(ctx: Context<InitializeMarket>, market_id: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.market_id = market_id;
+        market.authority = ctx.accounts.authority.key();
+        market.oracle = ctx.accounts.oracle.key();
+        market.insurance_fund_vault = ctx.accounts.insurance_fund_vault.key();
+        market.vault = ctx.accounts.vault.key();
+        market.debt_vault = ctx.accounts.debt_vault.key();
+        market.collateral_mint = ctx.accounts.collateral_mint.key();
+        market.debt_mint = ctx.accounts.debt_mint.key();
+        market.close_factor_bps = DEFAULT_CLOSE_FACTOR_BPS;
+        market.liquidation_bonus_bps = DEFAULT_LIQUIDATION_BONUS_BPS;
+        market.liquidation_mode = LiquidationMode::Whitelisted;
+        market.keepers = Vec::new();
+        market.bump = ctx.bumps.market;
+        market.vault_authority_bump = ctx.bumps.vault_authority;
+        market.grace_period_secs = DEFAULT_GRACE_PERIOD_SECS;
+        market.warning_health_bps = DEFAULT_WARNING_HEALTH_BPS;
+        market.instant_health_bps = DEFAULT_INSTANT_HEALTH_BPS;
+        market.vault_bump = ctx.bumps.vault;
+        market.debt_vault_bump = ctx.bumps.debt_vault;
+        market.insurance_fund_vault_bump = ctx.bumps.insurance_fund_vault;
+        market.collateral_decimals = ctx.accounts.collateral_mint.decimals;
+        market.debt_decimals = ctx.accounts.debt_mint.decimals;
+        Ok(())
+    }
+
+    /// Set the share of debt a single liquidation may repay and the bonus paid
+    /// on top of the repaid value, both in basis points.
+    pub fn set_liquidation_params(
+        ctx: Context<SetLiquidationParams>,
+        close_factor_bps: u16,
+        liquidation_bonus_bps: u16,
+    ) -> Result<()> {
+        require!(
+            (1..=10_000).contains(&close_factor_bps),
+            LiquidationError::InvalidParameter
+        );
+        require!(
+            liquidation_bonus_bps <= MAX_LIQUIDATION_BONUS_BPS,
+            LiquidationError::InvalidParameter
+        );
+        let market = &mut ctx.accounts.market;
+        market.close_factor_bps = close_factor_bps;
+        market.liquidation_bonus_bps = liquidation_bonus_bps;
+        Ok(())
+    }
+
+    /// Set the margin call: the grace period a flagged position has to recover
+    /// before it may be liquidated, in seconds, and the health below which
+    /// positions may be flagged and below which they're liquidated without
+    /// waiting, both in basis points. A grace period of zero turns the margin
+    /// call off.
+    pub fn set_margin_call_params(
+        ctx: Context<SetLiquidationParams>,
+        grace_period_secs: u32,
+        warning_health_bps: u16,
+        instant_health_bps: u16,
+    ) -> Result<()> {
+        require!(
+            warning_health_bps >= LIQUIDATION_HEALTH_BPS,
+            LiquidationError::InvalidParameter
+        );
+        require!(
+            instant_health_bps <= LIQUIDATION_HEALTH_BPS,
+            LiquidationError::InvalidParameter
+        );
+        let market = &mut ctx.accounts.market;
+        market.grace_period_secs = grace_period_secs;
+        market.warning_health_bps = warning_health_bps;
+        market.instant_health_bps = instant_health_bps;
+        Ok(())
+    }
+
+    /// Set whether anyone may liquidate or only whitelisted keepers.
+    pub fn set_liquidation_mode(ctx: Context<SetLiquidationParams>, mode: LiquidationMode) -> Result<()> {
+        ctx.accounts.market.liquidation_mode = mode;
+        Ok(())
+    }
+
+    /// Whitelist a keeper to liquidate while the market is permissioned.
+    pub fn add_keeper(ctx: Context<SetLiquidationParams>, keeper: Pubkey) -> Result<()> {
+        let keepers = &mut ctx.accounts.market.keepers;
+        require!(!keepers.contains(&keeper), LiquidationError::KeeperAlreadyWhitelisted);
+        require!(keepers.len() < MAX_KEEPERS, LiquidationError::KeeperWhitelistFull);
+        keepers.push(keeper);
+        Ok(())
+    }
+
+    /// Take a keeper off the whitelist.
+    pub fn remove_keeper(ctx: Context<SetLiquidationParams>, keeper: Pubkey) -> Result<()> {
+        let keepers = &mut ctx.accounts.market.keepers;
+        let index = keepers
+            .iter()
+            .position(|whitelisted| *whitelisted == keeper)
+            .ok_or(LiquidationError::KeeperNotWhitelisted)?;
+        keepers.swap_remove(index);
+        Ok(())
+    }
+
+    /// Move tokens from the authority into the insurance fund covering bad
+    /// debt.
+    pub fn fund_insurance(ctx: Context<FundInsurance>, amount: u64) -> Result<()> {
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.debt_mint,
+            &ctx.accounts.authority_token_account.to_account_info(),
+            &ctx.accounts.insurance_fund_vault.to_account_info(),
+            &ctx.accounts.authority.to_account_info(),
+            &[],
+            amount,
+        )?;
+        Ok(())
+    }
+
+    /// Deposit collateral into the position, which is credited what the
+    /// vault receives net of any transfer fee.
     pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.position.closed, LiquidationError::PositionClosed);
+        let fee = transfer_fee(&ctx.accounts.collateral_mint.to_account_info(), amount)?;
+        let received = amount.checked_sub(fee).ok_or(LiquidationError::MathOverflow)?;
+        let collateral = ctx
+            .accounts
+            .position
+            .collateral
+            .checked_add(received)
+            .ok_or(LiquidationError::MathOverflow)?;
         // Transfer tokens from user to vault
         transfer_tokens(
             &ctx.accounts.token_program,
+            &ctx.accounts.collateral_mint,
             &ctx.accounts.user_token_account.to_account_info(),
             &ctx.accounts.vault.to_account_info(),
             &ctx.accounts.user.to_account_info(),
+            &[],
+            amount,
+        )?;
+
+        let position = &mut ctx.accounts.position;
+        position.collateral = collateral;
+        emit!(PositionDeposited {
+            position: position.key(),
+            owner: position.owner,
+            amount: received,
+            new_collateral: collateral,
+        });
+        Ok(())
+    }
+
+    /// Borrow from the debt vault against the position's collateral, up to
+    /// `MAX_LOAN_TO_VALUE_BPS` of its value. The position owes all of
+    /// `amount`; a transfer fee is the borrower's to bear.
+    pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let price = load_collateral_price(&ctx.accounts.market, &ctx.accounts.oracle, now)?;
+        let decimals = ctx.accounts.market.mint_decimals();
+
+        let position = &mut ctx.accounts.position;
+        require!(!position.closed, LiquidationError::PositionClosed);
+        let debt = position.debt.checked_add(amount).ok_or(LiquidationError::MathOverflow)?;
+        require!(
+            within_loan_to_value(position.collateral, debt, price, decimals)?,
+            LiquidationError::LoanToValueExceeded
+        );
+
+        let market_id = ctx.accounts.market.market_id.to_le_bytes();
+        let bump = [ctx.accounts.market.vault_authority_bump];
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.debt_mint,
+            &ctx.accounts.debt_vault.to_account_info(),
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.vault_authority.to_account_info(),
+            &[&[b"vault-authority", &market_id, &bump]],
             amount,
         )?;
 
-        ctx.accounts.position.collateral += amount;
+        position.debt = debt;
+        emit!(Borrowed {
+            position: position.key(),
+            owner: position.owner,
+            amount,
+            debt,
+        });
         Ok(())
     }
 
-    /// Liquidate an undercollateralized position.
+    /// Withdraw collateral from the position, as long as its debt stays within
+    /// `MAX_LOAN_TO_VALUE_BPS` of what's left. A position flagged for
+    /// liquidation can't withdraw until the flag is cleared. All of `amount`
+    /// leaves the position; a transfer fee is the owner's to bear.
+    pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let price = load_collateral_price(&ctx.accounts.market, &ctx.accounts.oracle, now)?;
+        let decimals = ctx.accounts.market.mint_decimals();
+
+        let position = &mut ctx.accounts.position;
+        require!(!position.closed, LiquidationError::PositionClosed);
+        require!(!position.is_flagged(), LiquidationError::PositionFlagged);
+        let collateral = position
+            .collateral
+            .checked_sub(amount)
+            .ok_or(LiquidationError::InsufficientCollateral)?;
+        require!(
+            within_loan_to_value(collateral, position.debt, price, decimals)?,
+            LiquidationError::WithdrawalUnhealthy
+        );
+
+        let market_id = ctx.accounts.market.market_id.to_le_bytes();
+        let bump = [ctx.accounts.market.vault_authority_bump];
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.collateral_mint,
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.vault_authority.to_account_info(),
+            &[&[b"vault-authority", &market_id, &bump]],
+            amount,
+        )?;
+
+        position.collateral = collateral;
+        emit!(CollateralWithdrawn {
+            position: position.key(),
+            owner: position.owner,
+            amount,
+            collateral,
+        });
+        Ok(())
+    }
+
+    /// Close a position without debt, returning any collateral it still holds
+    /// and the account's rent to the owner.
+    pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+        let position = &ctx.accounts.position;
+        require!(position.debt == 0, LiquidationError::DebtOutstanding);
+
+        if position.collateral > 0 {
+            let market_id = ctx.accounts.market.market_id.to_le_bytes();
+            let bump = [ctx.accounts.market.vault_authority_bump];
+            transfer_tokens(
+                &ctx.accounts.token_program,
+                &ctx.accounts.collateral_mint,
+                &ctx.accounts.vault.to_account_info(),
+                &ctx.accounts.user_token_account.to_account_info(),
+                &ctx.accounts.vault_authority.to_account_info(),
+                &[&[b"vault-authority", &market_id, &bump]],
+                position.collateral,
+            )?;
+        }
+
+        emit!(PositionClosed {
+            position: position.key(),
+            owner: position.owner,
+            collateral_returned: position.collateral,
+        });
+        Ok(())
+    }
+
+    /// Close a position a liquidation left without collateral, sending the
+    /// account's rent to the insurance fund. Anyone may crank it.
+    pub fn crank_close_position(ctx: Context<CrankClosePosition>) -> Result<()> {
+        let position = &ctx.accounts.position;
+        require!(position.closed, LiquidationError::PositionNotClosed);
+
+        emit!(PositionClosed {
+            position: position.key(),
+            owner: position.owner,
+            collateral_returned: 0,
+        });
+        Ok(())
+    }
+
+    /// Flag a position whose health is below the market's warning threshold,
+    /// recording when and at what price, which starts its grace period.
+    pub fn flag_for_liquidation(ctx: Context<FlagForLiquidation>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let price = load_collateral_price(&ctx.accounts.market, &ctx.accounts.oracle, now)?;
+
+        let market = &ctx.accounts.market;
+        let decimals = market.mint_decimals();
+        let position = &mut ctx.accounts.position;
+        require!(
+            market.allows_liquidator(ctx.accounts.liquidator.key),
+            LiquidationError::KeeperNotWhitelisted
+        );
+        require!(!position.closed, LiquidationError::PositionClosed);
+        require!(!position.is_flagged(), LiquidationError::AlreadyFlagged);
+        require!(
+            health_below(position.collateral, position.debt, price, decimals, market.warning_health_bps)?,
+            LiquidationError::PositionHealthy
+        );
+
+        position.flagged_at = now;
+        position.flag_price = price.price;
+        position.flag_price_expo = price.expo;
+        emit!(PositionFlagged {
+            position: position.key(),
+            owner: position.owner,
+            liquidator: ctx.accounts.liquidator.key(),
+            price: price.price,
+            expo: price.expo,
+            flagged_at: now,
+            grace_ends_at: position.grace_ends_at(market.grace_period_secs)?,
+        });
+        Ok(())
+    }
+
+    /// Clear the flag of a position back at or above the market's warning
+    /// threshold, ending its margin call. Anyone may crank it.
+    pub fn clear_liquidation_flag(ctx: Context<ClearLiquidationFlag>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let price = load_collateral_price(&ctx.accounts.market, &ctx.accounts.oracle, now)?;
+
+        let market = &ctx.accounts.market;
+        let decimals = market.mint_decimals();
+        let position = &mut ctx.accounts.position;
+        require!(position.is_flagged(), LiquidationError::NotFlagged);
+        require!(
+            !health_below(position.collateral, position.debt, price, decimals, market.warning_health_bps)?,
+            LiquidationError::PositionBelowWarning
+        );
+
+        position.clear_flag();
+        emit!(LiquidationFlagCleared {
+            position: position.key(),
+            owner: position.owner,
+        });
+        Ok(())
+    }
+
+    /// Liquidate an undercollateralized position, repaying up to the market's
+    /// close factor of its debt, or all of it once the position is underwater
+    /// (see `max_liquidation_repay`).
     pub fn liquidate(ctx: Context<LiquidatePosition>, repay_amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let price = load_collateral_price(&ctx.accounts.market, &ctx.accounts.oracle, now)?;
 
+        let market = &ctx.accounts.market;
+        let decimals = market.mint_decimals();
         let position = &mut ctx.accounts.position;
 
         // Check if liquidation is allowed
         require!(
-            position.collateral < position.debt,
+            market.allows_liquidator(ctx.accounts.liquidator.key),
+            LiquidationError::KeeperNotWhitelisted
+        );
+        require!(repay_amount > 0, LiquidationError::InvalidAmount);
+        require!(
+            is_liquidatable(position.collateral, position.debt, price, decimals)?,
             LiquidationError::PositionHealthy
         );
+        let max_repay = max_liquidation_repay(
+            position.collateral,
+            position.debt,
+            price,
+            decimals,
+            market.close_factor_bps,
+            market.liquidation_bonus_bps,
+        )?;
+        require!(repay_amount <= max_repay, LiquidationError::CloseFactorExceeded);
+        // Under a margin call only flagged positions whose grace period is over
+        // may be liquidated, unless their health fell below the instant threshold
+        if market.grace_period_secs > 0
+            && !health_below(position.collateral, position.debt, price, decimals, market.instant_health_bps)?
+        {
+            require!(position.is_flagged(), LiquidationError::NotFlagged);
+            require!(
+                now >= position.grace_ends_at(market.grace_period_secs)?,
+                LiquidationError::GracePeriodActive
+            );
+        }
 
-        // Transfer repayment from liquidator to vault
+        // Work the whole liquidation out before moving any tokens: only what the
+        // debt vault receives of the repayment, net of any transfer fee, repays
+        // debt and buys collateral at the price plus the bonus
+        let debt_mint = ctx.accounts.debt_mint.to_account_info();
+        let repaid = repay_amount
+            .checked_sub(transfer_fee(&debt_mint, repay_amount)?)
+            .ok_or(LiquidationError::MathOverflow)?;
+        let seized = seized_collateral(repaid, position.collateral, price, decimals, market.liquidation_bonus_bps)?;
+        let before = PositionBalances::of(position);
+        let (after, bad_debt) = liquidated_balances(before, repaid, seized, price)?;
+        // Grossed up so the debt vault receives all of the bad debt
+        let bad_debt_transfer = transfer_amount_for(&debt_mint, bad_debt)?;
+        require!(
+            ctx.accounts.insurance_fund_vault.amount >= bad_debt_transfer,
+            LiquidationError::InsuranceFundInsufficient
+        );
+
+        // Update the position before any CPI, so nothing the token programs
+        // do can observe it half liquidated; it's closed once all its
+        // collateral is seized, with the debt left covered by the insurance fund
+        position.collateral = after.collateral;
+        position.debt = after.debt;
+        position.closed = position.collateral == 0;
+        // The margin call ends once the liquidation brings the position back
+        // to the warning threshold; until then later liquidations needn't wait
+        if !health_below(position.collateral, position.debt, price, decimals, market.warning_health_bps)? {
+            position.clear_flag();
+        }
+        require!(
+            !health_worsened(before, PositionBalances::of(position)),
+            LiquidationError::LiquidationWorsenedHealth
+        );
+
+        // Transfer repayment from liquidator to the debt vault
         transfer_tokens(
-            &ctx.accounts.token_program,
+            &ctx.accounts.debt_token_program,
+            &ctx.accounts.debt_mint,
             &ctx.accounts.liquidator_token_account.to_account_info(),
-            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.debt_vault.to_account_info(),
             &ctx.accounts.liquidator.to_account_info(),
+            &[],
             repay_amount,
         )?;
 
-        // Give liquidator a reward
-        let reward = repay_amount / 10; // 10% reward
+        // Pay the liquidator the repaid value in collateral plus the bonus,
+        // signed for by the vault authority PDA
+        let market_id = market.market_id.to_le_bytes();
+        let bump = [market.vault_authority_bump];
         transfer_tokens(
-            &ctx.accounts.token_program,
+            &ctx.accounts.collateral_token_program,
+            &ctx.accounts.collateral_mint,
             &ctx.accounts.vault.to_account_info(),
-            &ctx.accounts.liquidator_token_account.to_account_info(),
+            &ctx.accounts.liquidator_collateral_account.to_account_info(),
             &ctx.accounts.vault_authority.to_account_info(),
-            reward,
+            &[&[b"vault-authority", &market_id, &bump]],
+            seized,
         )?;
 
-        // Adjust position
-        position.debt = position.debt.saturating_sub(repay_amount);
-        position.collateral = position.collateral.saturating_sub(reward);
+        // Debt left without collateral behind it is repaid to the debt vault
+        // by the insurance fund
+        if bad_debt > 0 {
+            transfer_tokens(
+                &ctx.accounts.debt_token_program,
+                &ctx.accounts.debt_mint,
+                &ctx.accounts.insurance_fund_vault.to_account_info(),
+                &ctx.accounts.debt_vault.to_account_info(),
+                &ctx.accounts.vault_authority.to_account_info(),
+                &[&[b"vault-authority", &market_id, &bump]],
+                bad_debt_transfer,
+            )?;
+            emit!(BadDebtCovered {
+                position: position.key(),
+                owner: position.owner,
+                amount: bad_debt,
+            });
+        }
 
+        emit!(PositionLiquidated {
+            position: position.key(),
+            liquidator: ctx.accounts.liquidator.key(),
+            repay_amount: repaid,
+            collateral_seized: seized,
+            remaining_debt: position.debt,
+            timestamp: now,
+        });
         Ok(())
     }
 }
 
 #[derive(Accounts)]
 pub struct InitializePosition<'info> {
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
     #[account(
         init,
         payer = user,
-        space = 8 + 8 + 8 + 32 + 1,
-        seeds = [b"position", user.key().as_ref()],
+        space = Position::SPACE,
+        seeds = [b"position", market.key().as_ref(), user.key().as_ref()],
         bump
     )]
     pub position: Account<'info, Position>,
@@ -87,64 +551,1222 @@ pub struct InitializePosition<'info> {
 }
 
 #[derive(Accounts)]
-pub struct DepositCollateral<'info> {
+#[instruction(market_id: u64)]
+pub struct InitializeMarket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = Market::SPACE,
+        seeds = [b"market", market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+    /// CHECK: only its address is recorded, it's parsed as a Pyth price account
+    /// when liquidating
+    pub oracle: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"vault", market.key().as_ref()],
+        bump,
+        token::mint = collateral_mint,
+        token::authority = vault_authority,
+        token::token_program = collateral_token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"debt_vault", market.key().as_ref()],
+        bump,
+        token::mint = debt_mint,
+        token::authority = vault_authority,
+        token::token_program = debt_token_program
+    )]
+    pub debt_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"insurance_fund_vault", market.key().as_ref()],
+        bump,
+        token::mint = debt_mint,
+        token::authority = vault_authority,
+        token::token_program = debt_token_program
+    )]
+    pub insurance_fund_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = collateral_token_program)]
+    pub collateral_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = debt_token_program)]
+    pub debt_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA signing for the vaults, holding no data
+    #[account(seeds = [b"vault-authority", market_id.to_le_bytes().as_ref()], bump)]
+    pub vault_authority: AccountInfo<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::LiquidationProgram>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ LiquidationError::NotProgramAdmin
+    )]
+    pub program_data: Account<'info, ProgramData>,
     #[account(mut)]
+    pub authority: Signer<'info>,
+    /// Token program of the collateral's mint, classic or Token-2022
+    pub collateral_token_program: Interface<'info, TokenInterface>,
+    /// Token program of the debt's mint, classic or Token-2022
+    pub debt_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetLiquidationParams<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        has_one = authority
+    )]
+    pub market: Account<'info, Market>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundInsurance<'info> {
+    #[account(
+        seeds = [b"market", market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        has_one = authority,
+        has_one = insurance_fund_vault
+    )]
+    pub market: Account<'info, Market>,
+    #[account(mut, token::token_program = token_program)]
+    pub insurance_fund_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = market.debt_mint, token::token_program = token_program)]
+    pub authority_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = market.debt_mint, mint::token_program = token_program)]
+    pub debt_mint: InterfaceAccount<'info, Mint>,
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct DepositCollateral<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump
+    )]
     pub position: Account<'info, Position>,
-    #[account(mut)]
-    pub user_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub vault: Account<'info, TokenAccount>,
+    #[account(mut, token::token_program = token_program)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = market.vault, token::token_program = token_program)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = market.collateral_mint, mint::token_program = token_program)]
+    pub collateral_mint: InterfaceAccount<'info, Mint>,
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
     pub user: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct LiquidatePosition<'info> {
-    #[account(mut)]
+pub struct Borrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+        has_one = owner
+    )]
     pub position: Account<'info, Position>,
+    #[account(
+        mut,
+        seeds = [b"debt_vault", market.key().as_ref()],
+        bump = market.debt_vault_bump,
+        token::authority = vault_authority,
+        token::token_program = token_program
+    )]
+    pub debt_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = market.debt_mint, token::token_program = token_program)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = market.debt_mint, mint::token_program = token_program)]
+    pub debt_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA signing for the vaults, holding no data
+    #[account(
+        seeds = [b"vault-authority", market.market_id.to_le_bytes().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: AccountInfo<'info>,
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    /// CHECK: checked against the market's price feed and parsed as a Pyth
+    /// price account
+    pub oracle: AccountInfo<'info>,
+    pub owner: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCollateral<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+        has_one = owner
+    )]
+    pub position: Account<'info, Position>,
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump = market.vault_bump,
+        token::authority = vault_authority,
+        token::token_program = token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = market.collateral_mint, token::token_program = token_program)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = market.collateral_mint, mint::token_program = token_program)]
+    pub collateral_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA signing for the vaults, holding no data
+    #[account(
+        seeds = [b"vault-authority", market.market_id.to_le_bytes().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: AccountInfo<'info>,
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    /// CHECK: checked against the market's price feed and parsed as a Pyth
+    /// price account
+    pub oracle: AccountInfo<'info>,
+    pub owner: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub position: Account<'info, Position>,
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump = market.vault_bump,
+        token::authority = vault_authority,
+        token::token_program = token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = market.collateral_mint, token::token_program = token_program)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = market.collateral_mint, mint::token_program = token_program)]
+    pub collateral_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA signing for the vaults, holding no data
+    #[account(
+        seeds = [b"vault-authority", market.market_id.to_le_bytes().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: AccountInfo<'info>,
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
     #[account(mut)]
-    pub vault: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub liquidator_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub insurance_fund_vault: Account<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CrankClosePosition<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump,
+        close = insurance_fund_vault
+    )]
+    pub position: Account<'info, Position>,
+    #[account(mut, address = market.insurance_fund_vault)]
+    pub insurance_fund_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+}
+
+#[derive(Accounts)]
+pub struct FlagForLiquidation<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump
+    )]
+    pub position: Account<'info, Position>,
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    /// CHECK: checked against the market's price feed and parsed as a Pyth
+    /// price account
+    pub oracle: AccountInfo<'info>,
+    pub liquidator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClearLiquidationFlag<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump
+    )]
+    pub position: Account<'info, Position>,
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    /// CHECK: checked against the market's price feed and parsed as a Pyth
+    /// price account
+    pub oracle: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidatePosition<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump
+    )]
+    pub position: Account<'info, Position>,
+    #[account(
+        mut,
+        address = market.vault,
+        token::authority = vault_authority,
+        token::token_program = collateral_token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = market.debt_vault,
+        token::authority = vault_authority,
+        token::token_program = debt_token_program
+    )]
+    pub debt_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = market.debt_mint, token::token_program = debt_token_program)]
+    pub liquidator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = market.collateral_mint, token::token_program = collateral_token_program)]
+    pub liquidator_collateral_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = market.insurance_fund_vault,
+        token::mint = market.debt_mint,
+        token::authority = vault_authority,
+        token::token_program = debt_token_program
+    )]
+    pub insurance_fund_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = market.collateral_mint, mint::token_program = collateral_token_program)]
+    pub collateral_mint: InterfaceAccount<'info, Mint>,
+    #[account(address = market.debt_mint, mint::token_program = debt_token_program)]
+    pub debt_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA signing for the vaults, holding no data
+    #[account(
+        seeds = [b"vault-authority", market.market_id.to_le_bytes().as_ref()],
+        bump = market.vault_authority_bump
+    )]
     pub vault_authority: AccountInfo<'info>,
-    pub authority: Signer<'info>,
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    /// CHECK: checked against the market's price feed and parsed as a Pyth
+    /// price account
     pub oracle: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
+    /// Token program of the collateral's mint, classic or Token-2022
+    pub collateral_token_program: Interface<'info, TokenInterface>,
+    /// Token program of the debt's mint, classic or Token-2022
+    pub debt_token_program: Interface<'info, TokenInterface>,
     pub liquidator: Signer<'info>,
 }
 
 
 /// state account representing a user’s margin position.
+///
+/// Accounts created before margin calls were added are
+/// `Position::LEGACY_SPACE` bytes, without the flag fields; they must be
+/// reallocated to `Position::SPACE`, zeroing the new bytes, before the program
+/// can load them again.
 #[account]
 pub struct Position {
     pub owner: Pubkey,
     pub bump: u8,
     pub collateral: u64,
     pub debt: u64,
+    /// Set once a liquidation has seized all of the position's collateral,
+    /// with any debt left covered by the insurance fund.
+    pub closed: bool,
+    /// When the position was flagged for liquidation (unix time), or zero
+    /// while it isn't.
+    pub flagged_at: i64,
+    /// Oracle price the position was flagged at, as a mantissa scaled by
+    /// `10^flag_price_expo`.
+    pub flag_price: i64,
+    pub flag_price_expo: i32,
+}
+
+impl Position {
+    /// Space allocated for the account.
+    pub const SPACE: usize = Self::LEGACY_SPACE + 8 + 8 + 4;
+
+    /// Space of accounts created before margin calls, without the flag fields.
+    pub const LEGACY_SPACE: usize = 8 + 32 + 1 + 8 + 8 + 1;
+
+    /// Whether the position has been flagged for liquidation.
+    pub fn is_flagged(&self) -> bool {
+        self.flagged_at != 0
+    }
+
+    /// When the grace period of a position flagged under a margin call of
+    /// `grace_period_secs` ends (unix time).
+    pub fn grace_ends_at(&self, grace_period_secs: u32) -> Result<i64> {
+        Ok(self
+            .flagged_at
+            .checked_add(grace_period_secs.into())
+            .ok_or(LiquidationError::MathOverflow)?)
+    }
+
+    /// End the position's margin call.
+    pub fn clear_flag(&mut self) {
+        self.flagged_at = 0;
+        self.flag_price = 0;
+        self.flag_price_expo = 0;
+    }
+}
+
+/// Market config account recording the collateral and debt mints, the
+/// collateral's price feed and the vaults. Positions and vaults are PDAs of
+/// their market, so collateral deposited in one market backs no other.
+#[account]
+pub struct Market {
+    pub authority: Pubkey,
+    pub oracle: Pubkey,
+    pub insurance_fund_vault: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub debt_mint: Pubkey,
+    /// Largest share of debt a single liquidation may repay, in basis points.
+    pub close_factor_bps: u16,
+    /// Collateral paid to liquidators on top of the repaid value, in basis
+    /// points of it.
+    pub liquidation_bonus_bps: u16,
+    /// Who may liquidate.
+    pub liquidation_mode: LiquidationMode,
+    /// Keepers allowed to liquidate while the mode is `Whitelisted`, at most
+    /// `MAX_KEEPERS`.
+    pub keepers: Vec<Pubkey>,
+    pub bump: u8,
+    pub vault_authority_bump: u8,
+    /// How long a flagged position has to recover before it may be
+    /// liquidated, in seconds; zero turns the margin call off.
+    pub grace_period_secs: u32,
+    /// Health below which positions may be flagged, in basis points.
+    pub warning_health_bps: u16,
+    /// Health below which positions are liquidated without waiting out a grace
+    /// period, in basis points.
+    pub instant_health_bps: u16,
+    /// Collateral vault, at the PDA `[b"vault", market]`.
+    pub vault: Pubkey,
+    /// Debt vault, at the PDA `[b"debt_vault", market]`.
+    pub debt_vault: Pubkey,
+    pub vault_bump: u8,
+    pub debt_vault_bump: u8,
+    /// Bump of the insurance fund vault, at the PDA
+    /// `[b"insurance_fund_vault", market]`.
+    pub insurance_fund_vault_bump: u8,
+    /// Decimals of the collateral mint.
+    pub collateral_decimals: u8,
+    /// Decimals of the debt mint.
+    pub debt_decimals: u8,
+    /// Id the market was created with; the market is at the PDA
+    /// `[b"market", market_id]` and its vault authority at
+    /// `[b"vault-authority", market_id]`, both in little-endian bytes.
+    pub market_id: u64,
+}
+
+impl Market {
+    /// Space allocated for the account, leaving room for a full whitelist.
+    ///
+    /// Markets created before margin calls were added are 8 bytes shorter.
+    /// Their margin call fields read as zero, turning it off, unless the
+    /// whitelist is full; those must be reallocated to `Market::SPACE` first.
+    /// No market could hold its vaults before `initialize_market` created them,
+    /// so older markets must be created afresh, as must those created before
+    /// it recorded the mints' decimals, which would read as zero, or before
+    /// markets were keyed by id, which live at other addresses.
+    pub const SPACE: usize =
+        8 + 32 * 5 + 2 + 2 + 1 + 4 + 32 * MAX_KEEPERS + 1 + 1 + 4 + 2 + 2 + 32 * 2 + 1 + 1 + 1 + 1 + 1 + 8;
+
+    /// Whether `liquidator` may liquidate under the market's mode.
+    pub fn allows_liquidator(&self, liquidator: &Pubkey) -> bool {
+        self.liquidation_mode == LiquidationMode::Permissionless || self.keepers.contains(liquidator)
+    }
+
+    /// Decimals of the market's collateral and debt mints.
+    pub fn mint_decimals(&self) -> MintDecimals {
+        MintDecimals {
+            collateral: self.collateral_decimals,
+            debt: self.debt_decimals,
+        }
+    }
+}
+
+/// Who may liquidate positions in the market.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiquidationMode {
+    /// Anyone may liquidate.
+    Permissionless,
+    /// Only keepers on the market's whitelist may liquidate.
+    Whitelisted,
+}
+
+/// Emitted when collateral is deposited into a position, for the amount the
+/// vault received.
+#[event]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PositionDeposited {
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub new_collateral: u64,
+}
+
+/// Emitted when a position is liquidated, after any bad debt it left has been
+/// covered. `repay_amount` is the debt repaid, what the debt vault received
+/// net of any transfer fee.
+#[event]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PositionLiquidated {
+    pub position: Pubkey,
+    pub liquidator: Pubkey,
+    pub repay_amount: u64,
+    pub collateral_seized: u64,
+    pub remaining_debt: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted when a position account is closed, by its owner or by a crank once
+/// liquidated.
+#[event]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PositionClosed {
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub collateral_returned: u64,
+}
+
+/// Emitted when a position borrows from the debt vault.
+#[event]
+pub struct Borrowed {
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub debt: u64,
+}
+
+/// Emitted when collateral is withdrawn from a position.
+#[event]
+pub struct CollateralWithdrawn {
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub collateral: u64,
+}
+
+/// Emitted when a position is flagged for liquidation, starting its grace
+/// period.
+#[event]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PositionFlagged {
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub liquidator: Pubkey,
+    pub price: i64,
+    pub expo: i32,
+    pub flagged_at: i64,
+    pub grace_ends_at: i64,
+}
+
+/// Emitted when a flagged position's margin call is cleared once it's back at
+/// the warning threshold.
+#[event]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiquidationFlagCleared {
+    pub position: Pubkey,
+    pub owner: Pubkey,
+}
+
+/// Emitted when the insurance fund repays debt a liquidation left without
+/// collateral.
+#[event]
+pub struct BadDebtCovered {
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+/// Oracle price as a mantissa scaled by `10^expo`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OraclePrice {
+    pub price: i64,
+    pub expo: i32,
+}
+
+impl OraclePrice {
+    /// Price of a base unit of collateral in base units of debt, for an oracle
+    /// quoting whole tokens of mints with `decimals`.
+    pub fn per_base_unit(self, decimals: MintDecimals) -> Self {
+        Self {
+            price: self.price,
+            expo: self.expo + i32::from(decimals.debt) - i32::from(decimals.collateral),
+        }
+    }
+}
+
+/// Decimals of a market's collateral and debt mints, which balances are held
+/// in base units of while oracles quote whole tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MintDecimals {
+    pub collateral: u8,
+    pub debt: u8,
 }
 
 #[error_code]
 pub enum LiquidationError {
     #[msg("Position is healthy and cannot be liquidated.")]
     PositionHealthy,
+    #[msg("Oracle account is not the market's price feed.")]
+    WrongOracle,
+    #[msg("Oracle account is not a valid Pyth price account.")]
+    InvalidOracle,
+    #[msg("Oracle price is stale.")]
+    StalePrice,
+    #[msg("Oracle price is not trading.")]
+    PriceNotTrading,
+    #[msg("Oracle price is not positive.")]
+    InvalidPrice,
+    #[msg("Borrowing would exceed the maximum loan-to-value.")]
+    LoanToValueExceeded,
+    #[msg("Withdrawal would exceed the maximum loan-to-value.")]
+    WithdrawalUnhealthy,
+    #[msg("Position doesn't hold enough collateral.")]
+    InsufficientCollateral,
+    #[msg("Arithmetic overflow.")]
+    MathOverflow,
+    #[msg("Repayment exceeds the close factor of the position's debt.")]
+    CloseFactorExceeded,
+    #[msg("Liquidation parameter is out of range.")]
+    InvalidParameter,
+    #[msg("Insurance fund can't cover the position's bad debt.")]
+    InsuranceFundInsufficient,
+    #[msg("Position is closed.")]
+    PositionClosed,
+    #[msg("Liquidator is not a whitelisted keeper.")]
+    KeeperNotWhitelisted,
+    #[msg("Keeper is already whitelisted.")]
+    KeeperAlreadyWhitelisted,
+    #[msg("Keeper whitelist is full.")]
+    KeeperWhitelistFull,
+    #[msg("Amount must be greater than zero.")]
+    InvalidAmount,
+    #[msg("Position still has debt outstanding.")]
+    DebtOutstanding,
+    #[msg("Position has not been fully liquidated.")]
+    PositionNotClosed,
+    #[msg("Position's grace period is still active.")]
+    GracePeriodActive,
+    #[msg("Position has not been flagged for liquidation.")]
+    NotFlagged,
+    #[msg("Position is already flagged for liquidation.")]
+    AlreadyFlagged,
+    #[msg("Position is still below the warning threshold.")]
+    PositionBelowWarning,
+    #[msg("Signer is not the program's upgrade authority.")]
+    NotProgramAdmin,
+    #[msg("Liquidation would leave the position less healthy without closing it.")]
+    LiquidationWorsenedHealth,
+    #[msg("Position is flagged for liquidation.")]
+    PositionFlagged,
+}
+
+/// Read the collateral price from the market's price feed at unix time `now`.
+pub fn load_collateral_price(market: &Market, oracle: &AccountInfo, now: i64) -> Result<OraclePrice> {
+    require_keys_eq!(oracle.key(), market.oracle, LiquidationError::WrongOracle);
+
+    let data = oracle.try_borrow_data()?;
+    let price_account = load_price_account(&data).map_err(|_| LiquidationError::InvalidOracle)?;
+    require!(
+        price_account.agg.status == PriceStatus::Trading,
+        LiquidationError::PriceNotTrading
+    );
+    require!(
+        now.checked_sub(price_account.timestamp).ok_or(LiquidationError::MathOverflow)? <= MAX_PRICE_AGE_SECS,
+        LiquidationError::StalePrice
+    );
+    require!(price_account.agg.price > 0, LiquidationError::InvalidPrice);
+
+    Ok(OraclePrice {
+        price: price_account.agg.price,
+        expo: price_account.expo,
+    })
+}
+
+/// Value of `collateral` base units at `price`, the price of a whole token of
+/// a mint with `decimals.collateral`, in base units of debt, rounded down.
+pub fn collateral_value(collateral: u64, price: OraclePrice, decimals: MintDecimals) -> Result<u128> {
+    let price = price.per_base_unit(decimals);
+    let value = (collateral as u128)
+        .checked_mul(price.price.max(0) as u128)
+        .ok_or(LiquidationError::MathOverflow)?;
+    let scale = 10u128
+        .checked_pow(price.expo.unsigned_abs())
+        .ok_or(LiquidationError::MathOverflow)?;
+    let value = if price.expo < 0 {
+        value.checked_div(scale)
+    } else {
+        value.checked_mul(scale)
+    };
+    Ok(value.ok_or(LiquidationError::MathOverflow)?)
 }
 
-/// Utility for safe token transfers.
+/// Whether debt exceeds the collateral's value at `price`, counted at
+/// `LIQUIDATION_THRESHOLD_BPS`.
+pub fn is_liquidatable(collateral: u64, debt: u64, price: OraclePrice, decimals: MintDecimals) -> Result<bool> {
+    health_below(collateral, debt, price, decimals, LIQUIDATION_HEALTH_BPS)
+}
+
+/// Whether the position's health at `price` is below `health_bps`: the
+/// collateral's value, counted at `LIQUIDATION_THRESHOLD_BPS`, is less than
+/// `health_bps` of the debt.
+pub fn health_below(
+    collateral: u64,
+    debt: u64,
+    price: OraclePrice,
+    decimals: MintDecimals,
+    health_bps: u16,
+) -> Result<bool> {
+    let threshold = collateral_value(collateral, price, decimals)?
+        .checked_mul(LIQUIDATION_THRESHOLD_BPS as u128)
+        .ok_or(LiquidationError::MathOverflow)?;
+    let debt = (debt as u128)
+        .checked_mul(health_bps as u128)
+        .ok_or(LiquidationError::MathOverflow)?;
+    Ok(threshold < debt)
+}
+
+/// Whether debt is within `MAX_LOAN_TO_VALUE_BPS` of the collateral's value at
+/// `price`.
+pub fn within_loan_to_value(collateral: u64, debt: u64, price: OraclePrice, decimals: MintDecimals) -> Result<bool> {
+    let limit = collateral_value(collateral, price, decimals)?
+        .checked_mul(MAX_LOAN_TO_VALUE_BPS as u128)
+        .ok_or(LiquidationError::MathOverflow)?;
+    let debt = (debt as u128).checked_mul(10_000).ok_or(LiquidationError::MathOverflow)?;
+    Ok(debt <= limit)
+}
+
+/// Largest repayment of `debt` a single liquidation may make under
+/// `close_factor_bps`, rounded down.
+pub fn max_repay_amount(debt: u64, close_factor_bps: u16) -> Result<u64> {
+    let max = (debt as u128)
+        .checked_mul(close_factor_bps as u128)
+        .and_then(|value| value.checked_div(10_000))
+        .ok_or(LiquidationError::MathOverflow)?;
+    Ok(u64::try_from(max).map_err(|_| LiquidationError::MathOverflow)?)
+}
+
+/// Largest repayment a single liquidation of a position holding `collateral`
+/// and owing `debt` may make at `price`: `close_factor_bps` of the debt, or all
+/// of it once the collateral is worth less than the debt plus the liquidation
+/// bonus.
+///
+/// No partial liquidation leaves a position worth that little healthier (see
+/// `health_worsened`), so it may only be closed, by repaying enough to seize
+/// all its collateral, which the close factor alone could forbid.
+pub fn max_liquidation_repay(
+    collateral: u64,
+    debt: u64,
+    price: OraclePrice,
+    decimals: MintDecimals,
+    close_factor_bps: u16,
+    liquidation_bonus_bps: u16,
+) -> Result<u64> {
+    let value = collateral_value(collateral, price, decimals)?
+        .checked_mul(10_000)
+        .ok_or(LiquidationError::MathOverflow)?;
+    let owed = (debt as u128)
+        .checked_mul(10_000 + liquidation_bonus_bps as u128)
+        .ok_or(LiquidationError::MathOverflow)?;
+    if value < owed {
+        return Ok(debt);
+    }
+    max_repay_amount(debt, close_factor_bps)
+}
+
+/// Reward paid to a liquidator repaying `repay_amount`: `liquidation_bonus_bps`
+/// of the repayment in debt units, rounded down so the liquidator never gains
+/// from rounding.
+pub fn liquidation_reward(repay_amount: u64, liquidation_bonus_bps: u16) -> Result<u64> {
+    let reward = (repay_amount as u128)
+        .checked_mul(liquidation_bonus_bps as u128)
+        .and_then(|value| value.checked_div(10_000))
+        .ok_or(LiquidationError::MathOverflow)?;
+    Ok(u64::try_from(reward).map_err(|_| LiquidationError::MathOverflow)?)
+}
+
+/// Collateral seized by a liquidator repaying `repay_amount`: worth the
+/// repayment plus its reward at `price`, capped at the position's
+/// `collateral`, both in base units of mints with `decimals`.
+///
+/// The units are rounded down, so any fraction of a unit stays with the
+/// position.
+pub fn seized_collateral(
+    repay_amount: u64,
+    collateral: u64,
+    price: OraclePrice,
+    decimals: MintDecimals,
+    liquidation_bonus_bps: u16,
+) -> Result<u64> {
+    let price = price.per_base_unit(decimals);
+    let value = (repay_amount as u128)
+        .checked_add(liquidation_reward(repay_amount, liquidation_bonus_bps)? as u128)
+        .ok_or(LiquidationError::MathOverflow)?;
+    let price_per_unit = price.price.max(1) as u128;
+    let scale = 10u128
+        .checked_pow(price.expo.unsigned_abs())
+        .ok_or(LiquidationError::MathOverflow)?;
+    let units = if price.expo < 0 {
+        value.checked_mul(scale).and_then(|value| value.checked_div(price_per_unit))
+    } else {
+        price_per_unit.checked_mul(scale).and_then(|price| value.checked_div(price))
+    };
+    let units = units.ok_or(LiquidationError::MathOverflow)?;
+    Ok(units.min(collateral as u128) as u64)
+}
+
+/// A position's collateral and debt, in their mints' units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionBalances {
+    pub collateral: u64,
+    pub debt: u64,
+}
+
+impl PositionBalances {
+    pub fn of(position: &Position) -> Self {
+        Self {
+            collateral: position.collateral,
+            debt: position.debt,
+        }
+    }
+}
+
+/// Balances of a position left by a liquidation repaying `repaid` and seizing
+/// `seized` at `price`, with the bad debt the insurance fund covers once all
+/// the collateral is seized.
+///
+/// Fails unless the price is positive, the repayment is within the debt
+/// (`CloseFactorExceeded`), the seizure is within the collateral
+/// (`InsufficientCollateral`), and the position is left no less healthy
+/// unless it's closed (`LiquidationWorsenedHealth`, see `health_worsened`).
+pub fn liquidated_balances(
+    before: PositionBalances,
+    repaid: u64,
+    seized: u64,
+    price: OraclePrice,
+) -> Result<(PositionBalances, u64)> {
+    require!(price.price > 0, LiquidationError::InvalidPrice);
+    let debt = before.debt.checked_sub(repaid).ok_or(LiquidationError::CloseFactorExceeded)?;
+    let collateral = before
+        .collateral
+        .checked_sub(seized)
+        .ok_or(LiquidationError::InsufficientCollateral)?;
+    let bad_debt = if collateral == 0 { debt } else { 0 };
+    let after = PositionBalances {
+        collateral,
+        debt: debt - bad_debt,
+    };
+    require!(!health_worsened(before, after), LiquidationError::LiquidationWorsenedHealth);
+    Ok((after, bad_debt))
+}
+
+/// Whether a liquidation left a position that's still open less healthy than
+/// it found it. Both healths count the collateral at the same price and
+/// `LIQUIDATION_THRESHOLD_BPS`, so they're compared exactly as collateral per
+/// unit of debt.
+///
+/// Seizing the repayment's value plus the bonus only improves health while
+/// the collateral is worth at least the debt plus the bonus; a position worth
+/// less may only be liquidated by closing it, seizing all its collateral or
+/// repaying all its debt.
+pub fn health_worsened(before: PositionBalances, after: PositionBalances) -> bool {
+    if after.collateral == 0 || after.debt == 0 {
+        return false;
+    }
+    (after.collateral as u128) * (before.debt as u128) < (before.collateral as u128) * (after.debt as u128)
+}
+
+/// Transfer fee a mint withholds from a transfer of `amount` in the current
+/// epoch: zero unless it's a Token-2022 mint with the transfer fee extension.
+pub fn transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    match transfer_fee_config(mint)? {
+        Some(config) => {
+            let epoch = Clock::get()?.epoch;
+            Ok(config.calculate_epoch_fee(epoch, amount).ok_or(LiquidationError::MathOverflow)?)
+        }
+        None => Ok(0),
+    }
+}
+
+/// Amount to send for `received` to arrive once the mint's transfer fee is
+/// withheld.
+pub fn transfer_amount_for(mint: &AccountInfo, received: u64) -> Result<u64> {
+    match transfer_fee_config(mint)? {
+        Some(config) if received > 0 => {
+            let epoch = Clock::get()?.epoch;
+            Ok(config
+                .get_epoch_fee(epoch)
+                .calculate_pre_fee_amount(received)
+                .ok_or(LiquidationError::MathOverflow)?)
+        }
+        _ => Ok(received),
+    }
+}
+
+fn transfer_fee_config(mint: &AccountInfo) -> Result<Option<TransferFeeConfig>> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(None);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(mint.get_extension::<TransferFeeConfig>().ok().copied())
+}
+
+/// Utility for safe token transfers of either token program, signed with
+/// `signer_seeds` when the authority is a PDA. Returns the amount `to`
+/// receives, which is less than `amount` when the mint withholds a transfer
+/// fee.
 fn transfer_tokens<'info>(
-    token_program: &Program<'info, Token>,
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
     from: &AccountInfo<'info>,
     to: &AccountInfo<'info>,
     authority: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
     amount: u64,
-) -> Result<()> {
-    let cpi_accounts = Transfer {
+) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    let fee = transfer_fee(&mint_info, amount)?;
+    let cpi_accounts = TransferChecked {
         from: from.clone(),
+        mint: mint_info,
         to: to.clone(),
         authority: authority.clone(),
     };
-    let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
-    token::transfer(cpi_ctx, amount)?;
-    Ok(())
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer_seeds);
+    token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)?;
+    Ok(amount.checked_sub(fee).ok_or(LiquidationError::MathOverflow)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyth_sdk_solana::state::{AccountType, PriceAccount, MAGIC, VERSION_2};
+
+    const NOW: i64 = 1_700_000_000;
+
+    /// Mints of the same decimals, whose base units are priced as whole tokens
+    const DECIMALS: MintDecimals = MintDecimals { collateral: 6, debt: 6 };
+
+    /// Pyth price account data with the price given as a mantissa at an
+    /// exponent of -8
+    fn price_account_data(price: i64, status: PriceStatus, timestamp: i64) -> Vec<u8> {
+        let mut account = PriceAccount {
+            magic: MAGIC,
+            ver: VERSION_2,
+            atype: AccountType::Price as u32,
+            expo: -8,
+            timestamp,
+            ..PriceAccount::default()
+        };
+        account.agg.price = price;
+        account.agg.status = status;
+        bytemuck::bytes_of(&account).to_vec()
+    }
+
+    fn market(oracle: Pubkey) -> Market {
+        Market {
+            authority: Pubkey::new_unique(),
+            oracle,
+            insurance_fund_vault: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            debt_mint: Pubkey::new_unique(),
+            close_factor_bps: DEFAULT_CLOSE_FACTOR_BPS,
+            liquidation_bonus_bps: DEFAULT_LIQUIDATION_BONUS_BPS,
+            liquidation_mode: LiquidationMode::Whitelisted,
+            keepers: Vec::new(),
+            bump: 255,
+            vault_authority_bump: 255,
+            grace_period_secs: DEFAULT_GRACE_PERIOD_SECS,
+            warning_health_bps: DEFAULT_WARNING_HEALTH_BPS,
+            instant_health_bps: DEFAULT_INSTANT_HEALTH_BPS,
+            vault: Pubkey::new_unique(),
+            debt_vault: Pubkey::new_unique(),
+            vault_bump: 255,
+            debt_vault_bump: 255,
+            insurance_fund_vault_bump: 255,
+            collateral_decimals: DECIMALS.collateral,
+            debt_decimals: DECIMALS.debt,
+            market_id: 0,
+        }
+    }
+
+    /// Run `f` on an oracle account holding `data`
+    fn with_oracle<T>(key: Pubkey, mut data: Vec<u8>, f: impl FnOnce(&AccountInfo) -> T) -> T {
+        let owner = Pubkey::new_unique();
+        let mut lamports = 1_000_000;
+        let oracle = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        f(&oracle)
+    }
+
+    fn error_code<T>(result: Result<T>) -> u32 {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(Error::AnchorError(error)) => error.error_code_number,
+            Err(error) => panic!("unexpected error {error:?}"),
+        }
+    }
+
+    #[test]
+    fn test_health_check() {
+        // 2.00 per collateral unit, 1,000 units counting for 1,700 of debt
+        let price = OraclePrice { price: 200_000_000, expo: -8 };
+        assert!(!is_liquidatable(1_000, 1_699, price, DECIMALS).unwrap());
+        assert!(!is_liquidatable(1_000, 1_700, price, DECIMALS).unwrap());
+        assert!(is_liquidatable(1_000, 1_701, price, DECIMALS).unwrap());
+
+        // Plenty of units of collateral can't cover debt once the price collapses
+        let collapsed = OraclePrice { price: 1_000_000, expo: -8 };
+        assert!(is_liquidatable(1_000, 9, collapsed, DECIMALS).unwrap());
+        assert!(!is_liquidatable(1_000, 8, collapsed, DECIMALS).unwrap());
+
+        assert!(is_liquidatable(0, 1, price, DECIMALS).unwrap());
+        assert!(!is_liquidatable(0, 0, price, DECIMALS).unwrap());
+
+        assert!(within_loan_to_value(1_000, 1_600, price, DECIMALS).unwrap());
+        assert!(!within_loan_to_value(1_000, 1_601, price, DECIMALS).unwrap());
+    }
+
+    #[test]
+    fn test_math_at_boundaries() {
+        // The largest balances are valued without overflowing at real prices
+        let price = OraclePrice { price: 10_000_000_000, expo: -8 };
+        assert!(!is_liquidatable(u64::MAX, u64::MAX, price, DECIMALS).unwrap());
+        assert!(within_loan_to_value(u64::MAX, u64::MAX, price, DECIMALS).unwrap());
+        assert_eq!(
+            seized_collateral(u64::MAX, u64::MAX, price, DECIMALS, MAX_LIQUIDATION_BONUS_BPS).unwrap(),
+            u64::MAX / 100 * 3 / 2
+        );
+        assert_eq!(max_repay_amount(u64::MAX, 10_000).unwrap(), u64::MAX);
+
+        // but values past u128 are an error rather than a wrapped or
+        // saturated answer
+        let absurd = OraclePrice { price: i64::MAX, expo: 2 };
+        let overflow = u32::from(LiquidationError::MathOverflow);
+        assert_eq!(error_code(is_liquidatable(u64::MAX, u64::MAX, absurd, DECIMALS)), overflow);
+        assert_eq!(error_code(within_loan_to_value(u64::MAX, 0, absurd, DECIMALS)), overflow);
+        assert_eq!(error_code(collateral_value(1, OraclePrice { price: 1, expo: 39 }, DECIMALS)), overflow);
+        let tiny = OraclePrice { price: 1, expo: -30 };
+        assert_eq!(error_code(seized_collateral(u64::MAX, u64::MAX, tiny, DECIMALS, 0)), overflow);
+    }
+
+    #[test]
+    fn test_mint_decimals() {
+        // 100.00 a token of a 9-decimal collateral, borrowing a 6-decimal
+        // quote: a whole token, 10^9 base units, is worth 10^8 base units of debt
+        let price = OraclePrice { price: 10_000_000_000, expo: -8 };
+        let decimals = MintDecimals { collateral: 9, debt: 6 };
+        assert_eq!(collateral_value(1_000_000_000, price, decimals).unwrap(), 100_000_000);
+        assert_eq!(collateral_value(1_000_000_000, price, DECIMALS).unwrap(), 100_000_000_000);
+        assert!(!is_liquidatable(1_000_000_000, 85_000_000, price, decimals).unwrap());
+        assert!(is_liquidatable(1_000_000_000, 85_000_001, price, decimals).unwrap());
+        assert!(within_loan_to_value(1_000_000_000, 80_000_000, price, decimals).unwrap());
+        assert!(!within_loan_to_value(1_000_000_000, 80_000_001, price, decimals).unwrap());
+        // Repaying 20.00 plus the 5% bonus buys 0.21 of a token
+        assert_eq!(seized_collateral(20_000_000, 1_000_000_000, price, decimals, 500).unwrap(), 210_000_000);
+        assert_eq!(
+            max_liquidation_repay(1_000_000_000, 100_000_000, price, decimals, 5_000, 500).unwrap(),
+            100_000_000
+        );
+
+        // and the other way around, a whole token of a 6-decimal collateral is
+        // worth 10^11 base units of a 9-decimal debt
+        let decimals = MintDecimals { collateral: 6, debt: 9 };
+        assert_eq!(collateral_value(1_000_000, price, decimals).unwrap(), 100_000_000_000);
+        assert_eq!(seized_collateral(20_000_000_000, 1_000_000, price, decimals, 500).unwrap(), 210_000);
+    }
+
+    #[test]
+    fn test_seized_collateral() {
+        // 1,000 repaid at 2.00 a unit is worth 500 units, plus a 5% bonus
+        let price = OraclePrice { price: 200_000_000, expo: -8 };
+        assert_eq!(seized_collateral(1_000, 1_000, price, DECIMALS, 500).unwrap(), 525);
+        assert_eq!(seized_collateral(1_001, 1_000, price, DECIMALS, 500).unwrap(), 525);
+        assert_eq!(seized_collateral(1_000, 1_000, price, DECIMALS, 0).unwrap(), 500);
+        assert_eq!(seized_collateral(1_000, 20, price, DECIMALS, 500).unwrap(), 20);
+        assert_eq!(seized_collateral(1_000, 1_000, OraclePrice { price: 5, expo: 1 }, DECIMALS, 500).unwrap(), 21);
+    }
+
+    #[test]
+    fn test_liquidation_invariants() {
+        // 1,000 units at 85.00 against 80,000 of debt: a health of ~90.3%
+        let price = OraclePrice { price: 8_500_000_000, expo: -8 };
+        let before = PositionBalances { collateral: 1_000, debt: 80_000 };
+
+        // 20,000 plus the 5% bonus buys 247 units, which leaves the position
+        // healthier; seizing 250 leaves its health where it was, any more
+        // worsens it
+        let (after, bad_debt) = liquidated_balances(before, 20_000, 247, price).unwrap();
+        assert_eq!((after, bad_debt), (PositionBalances { collateral: 753, debt: 60_000 }, 0));
+        liquidated_balances(before, 20_000, 250, price).unwrap();
+        let worsened = u32::from(LiquidationError::LiquidationWorsenedHealth);
+        assert_eq!(error_code(liquidated_balances(before, 20_000, 251, price)), worsened);
+
+        // At 50.00 the collateral is worth less than the debt, so no partial
+        // liquidation leaves the position healthier: repaying as much as the
+        // close factor allows seizes 840 units and worsens it
+        let underwater = OraclePrice { price: 5_000_000_000, expo: -8 };
+        assert_eq!(seized_collateral(40_000, 1_000, underwater, DECIMALS, 500).unwrap(), 840);
+        assert_eq!(error_code(liquidated_balances(before, 40_000, 840, underwater)), worsened);
+        assert_eq!(error_code(liquidated_balances(before, 20_000, 420, underwater)), worsened);
+
+        // Repaying more than the debt or seizing more than the collateral is
+        // refused outright, as is a price at which collateral would be free
+        assert_eq!(
+            error_code(liquidated_balances(before, 80_001, 1_000, price)),
+            u32::from(LiquidationError::CloseFactorExceeded)
+        );
+        assert_eq!(
+            error_code(liquidated_balances(before, 20_000, 1_001, price)),
+            u32::from(LiquidationError::InsufficientCollateral)
+        );
+        let free = OraclePrice { price: 0, expo: -8 };
+        assert_eq!(
+            error_code(liquidated_balances(before, 20_000, 247, free)),
+            u32::from(LiquidationError::InvalidPrice)
+        );
+
+        // Seizing all of the collateral closes the position however short it
+        // is, with the debt left over to the insurance fund, as does repaying
+        // all of the debt: at 50.00 47,620 plus its bonus buys all 1,000 units
+        assert_eq!(seized_collateral(47_619, 1_000, underwater, DECIMALS, 500).unwrap(), 999);
+        assert_eq!(seized_collateral(47_620, 1_000, underwater, DECIMALS, 500).unwrap(), 1_000);
+        let (after, bad_debt) = liquidated_balances(before, 47_620, 1_000, underwater).unwrap();
+        assert_eq!((after, bad_debt), (PositionBalances { collateral: 0, debt: 0 }, 32_380));
+        assert!(!health_worsened(before, after));
+        let (after, bad_debt) = liquidated_balances(before, 80_000, 988, price).unwrap();
+        assert_eq!((after, bad_debt), (PositionBalances { collateral: 12, debt: 0 }, 0));
+    }
+
+    #[test]
+    fn test_close_factor_lifted_underwater() {
+        // 1,000 units at 85.00 are worth more than 80,000 of debt plus the 5%
+        // bonus, so a liquidation repays at most half of it
+        let price = OraclePrice { price: 8_500_000_000, expo: -8 };
+        assert_eq!(max_liquidation_repay(1_000, 80_000, price, DECIMALS, 5_000, 500).unwrap(), 40_000);
+
+        // At 84.00 they're worth exactly the debt plus the bonus, and below it
+        // all of the debt may be repaid, enough to seize all the collateral
+        let par = OraclePrice { price: 8_400_000_000, expo: -8 };
+        assert_eq!(max_liquidation_repay(1_000, 80_000, par, DECIMALS, 5_000, 500).unwrap(), 40_000);
+        let below = OraclePrice { price: 8_399_999_999, expo: -8 };
+        assert_eq!(max_liquidation_repay(1_000, 80_000, below, DECIMALS, 5_000, 500).unwrap(), 80_000);
+        let underwater = OraclePrice { price: 5_000_000_000, expo: -8 };
+        assert_eq!(max_liquidation_repay(1_000, 80_000, underwater, DECIMALS, 5_000, 500).unwrap(), 80_000);
+        assert_eq!(max_liquidation_repay(0, 80_000, underwater, DECIMALS, 5_000, 500).unwrap(), 80_000);
+    }
+
+    #[test]
+    fn test_reward_rounding() {
+        // 5% of amounts not divisible by 20 leaves a fraction, which the
+        // liquidator doesn't get
+        assert_eq!(liquidation_reward(1_000, 500).unwrap(), 50);
+        assert_eq!(liquidation_reward(1_019, 500).unwrap(), 50);
+        assert_eq!(liquidation_reward(1_039, 500).unwrap(), 51);
+        assert_eq!(liquidation_reward(19, 500).unwrap(), 0);
+        assert_eq!(liquidation_reward(7, 0).unwrap(), 0);
+
+        // At 0.50 a unit, 1,019 plus its reward of 50 (not 50.95) buys 2,138
+        // units, and at 0.30 the fraction of the 3,503.33 units 1,051 buys is
+        // dropped too
+        let half = OraclePrice { price: 50_000_000, expo: -8 };
+        assert_eq!(seized_collateral(1_019, 10_000, half, DECIMALS, 500).unwrap(), 2_138);
+        let third = OraclePrice { price: 30_000_000, expo: -8 };
+        assert_eq!(seized_collateral(1_001, 10_000, third, DECIMALS, 500).unwrap(), 3_503);
+    }
+
+    #[test]
+    fn test_load_collateral_price() {
+        let key = Pubkey::new_unique();
+        let market = market(key);
+
+        let data = price_account_data(150_000_000, PriceStatus::Trading, NOW - 10);
+        let price = with_oracle(key, data, |oracle| load_collateral_price(&market, oracle, NOW)).unwrap();
+        assert_eq!(price, OraclePrice { price: 150_000_000, expo: -8 });
+    }
+
+    #[test]
+    fn test_stale_price_rejected() {
+        let key = Pubkey::new_unique();
+        let market = market(key);
+
+        let data = price_account_data(150_000_000, PriceStatus::Trading, NOW - MAX_PRICE_AGE_SECS - 1);
+        let result = with_oracle(key, data, |oracle| load_collateral_price(&market, oracle, NOW));
+        assert_eq!(error_code(result), u32::from(LiquidationError::StalePrice));
+
+        let data = price_account_data(150_000_000, PriceStatus::Halted, NOW);
+        let result = with_oracle(key, data, |oracle| load_collateral_price(&market, oracle, NOW));
+        assert_eq!(error_code(result), u32::from(LiquidationError::PriceNotTrading));
+
+        let data = price_account_data(0, PriceStatus::Trading, NOW);
+        let result = with_oracle(key, data, |oracle| load_collateral_price(&market, oracle, NOW));
+        assert_eq!(error_code(result), u32::from(LiquidationError::InvalidPrice));
+    }
+
+    #[test]
+    fn test_wrong_oracle_rejected() {
+        let market = market(Pubkey::new_unique());
+
+        let data = price_account_data(150_000_000, PriceStatus::Trading, NOW);
+        let result = with_oracle(Pubkey::new_unique(), data, |oracle| load_collateral_price(&market, oracle, NOW));
+        assert_eq!(error_code(result), u32::from(LiquidationError::WrongOracle));
+
+        let result = with_oracle(market.oracle, vec![0; 64], |oracle| load_collateral_price(&market, oracle, NOW));
+        assert_eq!(error_code(result), u32::from(LiquidationError::InvalidOracle));
+    }
+
+    #[test]
+    fn test_position_account_fixture() {
+        // The engine decodes the same bytes, so a layout change breaks both
+        let position = Position {
+            owner: Pubkey::new_from_array([7; 32]),
+            bump: 254,
+            collateral: 567_000_000_000,
+            debt: 60_000_000_000,
+            closed: false,
+            flagged_at: 0,
+            flag_price: 0,
+            flag_price_expo: 0,
+        };
+        let mut data = Vec::new();
+        position.try_serialize(&mut data).unwrap();
+        assert_eq!(data, include_bytes!("../../../engine/fixtures/accounts/position.bin"));
+    }
+
+    #[test]
+    fn test_health_bands() {
+        // 1,000 units at 76.00, counted at 85%, against 80,000 of debt: a
+        // health of 80.75%
+        let price = OraclePrice { price: 7_600_000_000, expo: -8 };
+        assert!(health_below(1_000, 80_000, price, DECIMALS, 8_076).unwrap());
+        assert!(!health_below(1_000, 80_000, price, DECIMALS, 8_075).unwrap());
+        assert_eq!(
+            is_liquidatable(1_000, 80_000, price, DECIMALS).unwrap(),
+            health_below(1_000, 80_000, price, DECIMALS, LIQUIDATION_HEALTH_BPS).unwrap()
+        );
+    }
 }
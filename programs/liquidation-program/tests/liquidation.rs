@@ -0,0 +1,1576 @@
+//! The program's instructions run in a bank by `solana-program-test`, with the
+//! classic token and Token-2022 programs it loads by default
+
+use anchor_lang::error::ErrorCode;
+use base64::Engine;
+use anchor_lang::{
+    AccountDeserialize, AccountSerialize, AnchorDeserialize, Discriminator, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        transfer_fee::{TransferFee, TransferFeeAmount, TransferFeeConfig},
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions, StateWithExtensionsMut,
+    },
+};
+use liquidation_program::{
+    accounts, instruction, BadDebtCovered, LiquidationError, LiquidationFlagCleared, LiquidationMode, Market,
+    MintDecimals, Position, PositionClosed, PositionDeposited, PositionFlagged, PositionLiquidated,
+    DEFAULT_CLOSE_FACTOR_BPS, DEFAULT_GRACE_PERIOD_SECS, DEFAULT_INSTANT_HEALTH_BPS, DEFAULT_LIQUIDATION_BONUS_BPS,
+    DEFAULT_WARNING_HEALTH_BPS, ID, LIQUIDATION_HEALTH_BPS, MAX_KEEPERS, MAX_LIQUIDATION_BONUS_BPS,
+};
+use pyth_sdk_solana::state::{AccountType, PriceAccount, PriceStatus, MAGIC, VERSION_2};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    instruction::{Instruction, InstructionError},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::{Transaction, TransactionError},
+};
+
+const NOW: i64 = 1_700_000_000;
+
+/// Id of the test market, keying its address and its vault authority's
+const MARKET_ID: u64 = 7;
+
+/// Decimals of the mints of a test market, unless it says otherwise
+const DECIMALS: MintDecimals = MintDecimals { collateral: 6, debt: 6 };
+
+/// Run the program natively
+///
+/// Anchor's entrypoint ties the accounts' lifetime to the slice holding them,
+/// which the test runtime's doesn't, so it's handed a copy that lives on.
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    liquidation_program::entry(program_id, accounts, data)
+}
+
+/// Pyth price account data with the price given as a mantissa at an exponent
+/// of -8, published at `NOW`
+fn price_account_data(price: i64) -> Vec<u8> {
+    let mut account = PriceAccount {
+        magic: MAGIC,
+        ver: VERSION_2,
+        atype: AccountType::Price as u32,
+        expo: -8,
+        timestamp: NOW,
+        ..PriceAccount::default()
+    };
+    account.agg.price = price;
+    account.agg.status = PriceStatus::Trading;
+    bytemuck::bytes_of(&account).to_vec()
+}
+
+/// The error a transaction fails with when its instruction is rejected with
+/// `code`
+fn rejected(code: impl Into<u32>) -> Result<(), TransactionError> {
+    Err(TransactionError::InstructionError(0, InstructionError::Custom(code.into())))
+}
+
+/// A rent-exempt account of `owner` holding `data`
+fn account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn program_account<T: AccountSerialize>(state: &T) -> Account {
+    let mut data = Vec::new();
+    state.try_serialize(&mut data).unwrap();
+    account(ID, data)
+}
+
+/// A system account with enough lamports to pay for the accounts it creates
+fn wallet() -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        ..account(system_program::ID, Vec::new())
+    }
+}
+
+/// A token account of `token_program`; Token-2022 accounts carry the extension
+/// their mint's transfer fees are withheld in
+fn token_account(token_program: Pubkey, mint: Pubkey, authority: Pubkey, amount: u64) -> Account {
+    let state = spl_token_2022::state::Account {
+        mint,
+        owner: authority,
+        amount,
+        state: spl_token_2022::state::AccountState::Initialized,
+        ..Default::default()
+    };
+    if token_program == spl_token::ID {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        state.pack_into_slice(&mut data);
+        return account(token_program, data);
+    }
+    let len =
+        ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&[ExtensionType::TransferFeeAmount])
+            .unwrap();
+    let mut data = vec![0; len];
+    let mut extended =
+        StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack_uninitialized(&mut data).unwrap();
+    extended.init_extension::<TransferFeeAmount>(false).unwrap();
+    extended.base = state;
+    extended.pack_base();
+    extended.init_account_type().unwrap();
+    account(token_program, data)
+}
+
+fn mint(authority: Pubkey, kind: TestMint, decimals: u8) -> Account {
+    let state = spl_token_2022::state::Mint {
+        mint_authority: COption::Some(authority),
+        decimals,
+        is_initialized: true,
+        ..Default::default()
+    };
+    let TestMint::Token2022 { transfer_fee_bps } = kind else {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        state.pack_into_slice(&mut data);
+        return account(spl_token::ID, data);
+    };
+    let len =
+        ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[ExtensionType::TransferFeeConfig])
+            .unwrap();
+    let mut data = vec![0; len];
+    let mut extended = StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data).unwrap();
+    let config = extended.init_extension::<TransferFeeConfig>(true).unwrap();
+    let fee = TransferFee {
+        epoch: 0.into(),
+        maximum_fee: u64::MAX.into(),
+        transfer_fee_basis_points: transfer_fee_bps.into(),
+    };
+    config.older_transfer_fee = fee;
+    config.newer_transfer_fee = fee;
+    extended.base = state;
+    extended.pack_base();
+    extended.init_account_type().unwrap();
+    account(spl_token_2022::ID, data)
+}
+
+fn pda(seeds: &[&[u8]]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(seeds, &ID)
+}
+
+/// Kind of mint a test market's collateral or debt is in
+#[derive(Clone, Copy)]
+enum TestMint {
+    /// A mint of the classic token program
+    Classic,
+    /// A Token-2022 mint withholding a fee of `transfer_fee_bps` from transfers
+    Token2022 { transfer_fee_bps: u16 },
+}
+
+impl TestMint {
+    fn token_program(self) -> Pubkey {
+        match self {
+            Self::Classic => spl_token::ID,
+            Self::Token2022 { .. } => spl_token_2022::ID,
+        }
+    }
+}
+
+/// The market as `initialize_market` leaves it, holding a single position,
+/// with the accounts of its owner and a whitelisted liquidator
+struct TestMarket {
+    context: ProgramTestContext,
+    /// Data of the events emitted by the transactions that succeeded, in order
+    events: Vec<Vec<u8>>,
+    authority: Keypair,
+    authority_debt_account: Pubkey,
+    owner: Keypair,
+    liquidator: Keypair,
+    position: Pubkey,
+    vault: Pubkey,
+    debt_vault: Pubkey,
+    vault_authority: Pubkey,
+    market: Pubkey,
+    oracle: Pubkey,
+    insurance_fund_vault: Pubkey,
+    collateral_mint: Pubkey,
+    debt_mint: Pubkey,
+    collateral_token_program: Pubkey,
+    debt_token_program: Pubkey,
+    owner_collateral_account: Pubkey,
+    owner_debt_account: Pubkey,
+    liquidator_collateral_account: Pubkey,
+    liquidator_debt_account: Pubkey,
+}
+
+impl TestMarket {
+    /// An empty position, 1,000 units of collateral in the owner's hands, the
+    /// collateral priced at 100.00 and 1,000,000 in the debt vault
+    async fn new() -> Self {
+        Self::with_mints(TestMint::Classic, TestMint::Classic).await
+    }
+
+    /// `new`, with the collateral and debt in the given kinds of mint
+    async fn with_mints(collateral: TestMint, debt: TestMint) -> Self {
+        Self::start(collateral, debt, DECIMALS, true).await
+    }
+
+    /// `new`, with mints of the given decimals
+    async fn with_decimals(decimals: MintDecimals) -> Self {
+        Self::start(TestMint::Classic, TestMint::Classic, decimals, true).await
+    }
+
+    /// `new` before the owner initialized the position
+    async fn without_position() -> Self {
+        Self::start(TestMint::Classic, TestMint::Classic, DECIMALS, false).await
+    }
+
+    async fn start(collateral: TestMint, debt: TestMint, decimals: MintDecimals, with_position: bool) -> Self {
+        let mut program_test = ProgramTest::new("liquidation_program", ID, processor!(process_instruction));
+        let [authority, owner, liquidator] = [(); 3].map(|_| Keypair::new());
+        let [oracle, collateral_mint, debt_mint] = [(); 3].map(|_| Pubkey::new_unique());
+        let (market, market_bump) = pda(&[b"market", &MARKET_ID.to_le_bytes()]);
+        let (position, position_bump) = pda(&[b"position", market.as_ref(), owner.pubkey().as_ref()]);
+        let (vault_authority, vault_authority_bump) = pda(&[b"vault-authority", &MARKET_ID.to_le_bytes()]);
+        let (vault, vault_bump) = pda(&[b"vault", market.as_ref()]);
+        let (debt_vault, debt_vault_bump) = pda(&[b"debt_vault", market.as_ref()]);
+        let (insurance_fund_vault, insurance_fund_vault_bump) = pda(&[b"insurance_fund_vault", market.as_ref()]);
+        let [authority_debt_account, owner_collateral_account, owner_debt_account] =
+            [(); 3].map(|_| Pubkey::new_unique());
+        let [liquidator_collateral_account, liquidator_debt_account] = [(); 2].map(|_| Pubkey::new_unique());
+
+        let (collateral_token_program, debt_token_program) = (collateral.token_program(), debt.token_program());
+        let collateral_account =
+            |owner, amount| token_account(collateral_token_program, collateral_mint, owner, amount);
+        let debt_account = |owner, amount| token_account(debt_token_program, debt_mint, owner, amount);
+        let mut market_account = program_account(&Market {
+            authority: authority.pubkey(),
+            oracle,
+            insurance_fund_vault,
+            collateral_mint,
+            debt_mint,
+            close_factor_bps: DEFAULT_CLOSE_FACTOR_BPS,
+            liquidation_bonus_bps: DEFAULT_LIQUIDATION_BONUS_BPS,
+            liquidation_mode: LiquidationMode::Whitelisted,
+            keepers: vec![liquidator.pubkey()],
+            bump: market_bump,
+            vault_authority_bump,
+            grace_period_secs: DEFAULT_GRACE_PERIOD_SECS,
+            warning_health_bps: DEFAULT_WARNING_HEALTH_BPS,
+            instant_health_bps: DEFAULT_INSTANT_HEALTH_BPS,
+            vault,
+            debt_vault,
+            vault_bump,
+            debt_vault_bump,
+            insurance_fund_vault_bump,
+            collateral_decimals: decimals.collateral,
+            debt_decimals: decimals.debt,
+            market_id: MARKET_ID,
+        });
+        // Allocated as the program does, with room to whitelist more keepers
+        market_account.data.resize(Market::SPACE, 0);
+        market_account.lamports = Rent::default().minimum_balance(Market::SPACE);
+        let accounts = [
+            (oracle, account(Pubkey::new_unique(), price_account_data(10_000_000_000))),
+            (collateral_mint, mint(authority.pubkey(), collateral, decimals.collateral)),
+            (debt_mint, mint(authority.pubkey(), debt, decimals.debt)),
+            (market, market_account),
+            (vault, collateral_account(vault_authority, 0)),
+            (debt_vault, debt_account(vault_authority, 1_000_000)),
+            (insurance_fund_vault, debt_account(vault_authority, 0)),
+            (authority_debt_account, debt_account(authority.pubkey(), 1_000_000)),
+            (owner_collateral_account, collateral_account(owner.pubkey(), 1_000)),
+            (owner_debt_account, debt_account(owner.pubkey(), 0)),
+            (liquidator_collateral_account, collateral_account(liquidator.pubkey(), 0)),
+            (liquidator_debt_account, debt_account(liquidator.pubkey(), 1_000_000)),
+            (authority.pubkey(), wallet()),
+            (owner.pubkey(), wallet()),
+        ];
+        for (key, account) in accounts {
+            program_test.add_account(key, account);
+        }
+        if with_position {
+            let state = Position {
+                owner: owner.pubkey(),
+                bump: position_bump,
+                collateral: 0,
+                debt: 0,
+                closed: false,
+                flagged_at: 0,
+                flag_price: 0,
+                flag_price_expo: 0,
+            };
+            program_test.add_account(position, program_account(&state));
+        }
+
+        let mut context = program_test.start_with_context().await;
+        let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp = NOW;
+        context.set_sysvar(&clock);
+        Self {
+            context,
+            events: Vec::new(),
+            authority,
+            authority_debt_account,
+            owner,
+            liquidator,
+            position,
+            vault,
+            debt_vault,
+            vault_authority,
+            market,
+            oracle,
+            insurance_fund_vault,
+            collateral_mint,
+            debt_mint,
+            collateral_token_program,
+            debt_token_program,
+            owner_collateral_account,
+            owner_debt_account,
+            liquidator_collateral_account,
+            liquidator_debt_account,
+        }
+    }
+
+    fn set_price(&mut self, price: i64) {
+        self.add(self.oracle, account(Pubkey::new_unique(), price_account_data(price)));
+    }
+
+    fn add(&mut self, key: Pubkey, account: Account) {
+        self.context.set_account(&key, &account.into());
+    }
+
+    async fn account(&mut self, key: Pubkey) -> Option<Account> {
+        self.context.banks_client.get_account(key).await.unwrap()
+    }
+
+    async fn lamports(&mut self, key: Pubkey) -> u64 {
+        self.account(key).await.map_or(0, |account| account.lamports)
+    }
+
+    async fn market(&mut self) -> Market {
+        let account = self.account(self.market).await.unwrap();
+        Market::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    async fn position(&mut self) -> Position {
+        let account = self.account(self.position).await.unwrap();
+        Position::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    /// A token account of `mint`, under the mint's token program
+    async fn token_account(&mut self, mint: Pubkey, authority: Pubkey, amount: u64) -> Account {
+        let token_program = self.account(mint).await.unwrap().owner;
+        token_account(token_program, mint, authority, amount)
+    }
+
+    async fn balance(&mut self, key: Pubkey) -> u64 {
+        let account = self.account(key).await.unwrap();
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+            .unwrap()
+            .base
+            .amount
+    }
+
+    /// Transfer fees withheld in a Token-2022 account
+    async fn withheld(&mut self, key: Pubkey) -> u64 {
+        let account = self.account(key).await.unwrap();
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+            .unwrap()
+            .get_extension::<TransferFeeAmount>()
+            .unwrap()
+            .withheld_amount
+            .into()
+    }
+
+    /// Events of type `T` emitted since the last call
+    fn take_events<T: anchor_lang::Event + AnchorDeserialize>(&mut self) -> Vec<T> {
+        let (taken, rest) = std::mem::take(&mut self.events)
+            .into_iter()
+            .partition::<Vec<_>, _>(|data| data.starts_with(&T::DISCRIMINATOR));
+        self.events = rest;
+        taken.iter().map(|data| T::try_from_slice(&data[8..]).unwrap()).collect()
+    }
+
+    /// Run an instruction of the program, signed by `signers`
+    async fn process(
+        &mut self,
+        accounts: impl ToAccountMetas,
+        data: impl InstructionData,
+        signers: &[&Keypair],
+    ) -> Result<(), TransactionError> {
+        let instruction = Instruction {
+            program_id: ID,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        };
+        self.send(instruction, signers).await
+    }
+
+    /// Send `instruction` in a transaction of its own, keeping the events it
+    /// emits if it succeeds
+    async fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> Result<(), TransactionError> {
+        // A new blockhash each time, so repeating a rejected transaction runs it again
+        let blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        let payer = &self.context.payer;
+        let signers: Vec<&Keypair> = std::iter::once(payer).chain(signers.iter().copied()).collect();
+        let transaction =
+            Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &signers, blockhash);
+        let processed = self
+            .context
+            .banks_client
+            .process_transaction_with_metadata(transaction)
+            .await
+            .unwrap();
+        if processed.result.is_ok() {
+            let logs = processed.metadata.map(|metadata| metadata.log_messages).unwrap_or_default();
+            self.events.extend(
+                logs.iter()
+                    .filter_map(|log| log.strip_prefix("Program data: "))
+                    .flat_map(|fields| fields.split(' '))
+                    .map(|field| base64::engine::general_purpose::STANDARD.decode(field).unwrap()),
+            );
+        }
+        processed.result
+    }
+
+    async fn initialize_position(&mut self) -> Result<(), TransactionError> {
+        let accounts = accounts::InitializePosition {
+            market: self.market,
+            position: self.position,
+            user: self.owner.pubkey(),
+            system_program: system_program::ID,
+        };
+        let owner = self.owner.insecure_clone();
+        self.process(accounts, instruction::InitializePosition {}, &[&owner]).await
+    }
+
+    /// Mint `amount` of `mint` to a token account, as the mints' authority
+    async fn mint_to(&mut self, mint: Pubkey, account: Pubkey, amount: u64) -> Result<(), TransactionError> {
+        let token_program = self.account(mint).await.unwrap().owner;
+        let authority = self.authority.insecure_clone();
+        let instruction =
+            spl_token_2022::instruction::mint_to(&token_program, &mint, &account, &authority.pubkey(), &[], amount)
+                .unwrap();
+        self.send(instruction, &[&authority]).await
+    }
+
+    async fn set_liquidation_params(
+        &mut self,
+        authority: &Keypair,
+        close_factor_bps: u16,
+        liquidation_bonus_bps: u16,
+    ) -> Result<(), TransactionError> {
+        let accounts = accounts::SetLiquidationParams {
+            market: self.market,
+            authority: authority.pubkey(),
+        };
+        let data = instruction::SetLiquidationParams {
+            close_factor_bps,
+            liquidation_bonus_bps,
+        };
+        self.process(accounts, data, &[authority]).await
+    }
+
+    async fn set_liquidation_mode(
+        &mut self,
+        authority: &Keypair,
+        mode: LiquidationMode,
+    ) -> Result<(), TransactionError> {
+        let accounts = accounts::SetLiquidationParams {
+            market: self.market,
+            authority: authority.pubkey(),
+        };
+        self.process(accounts, instruction::SetLiquidationMode { mode }, &[authority]).await
+    }
+
+    async fn add_keeper(&mut self, keeper: Pubkey) -> Result<(), TransactionError> {
+        let authority = self.authority.insecure_clone();
+        let accounts = accounts::SetLiquidationParams {
+            market: self.market,
+            authority: authority.pubkey(),
+        };
+        self.process(accounts, instruction::AddKeeper { keeper }, &[&authority]).await
+    }
+
+    async fn remove_keeper(&mut self, keeper: Pubkey) -> Result<(), TransactionError> {
+        let authority = self.authority.insecure_clone();
+        let accounts = accounts::SetLiquidationParams {
+            market: self.market,
+            authority: authority.pubkey(),
+        };
+        self.process(accounts, instruction::RemoveKeeper { keeper }, &[&authority]).await
+    }
+
+    async fn fund_insurance(&mut self, amount: u64) -> Result<(), TransactionError> {
+        let authority = self.authority.insecure_clone();
+        let accounts = accounts::FundInsurance {
+            market: self.market,
+            insurance_fund_vault: self.insurance_fund_vault,
+            authority_token_account: self.authority_debt_account,
+            debt_mint: self.debt_mint,
+            authority: authority.pubkey(),
+            token_program: self.debt_token_program,
+        };
+        self.process(accounts, instruction::FundInsurance { amount }, &[&authority]).await
+    }
+
+    async fn deposit(&mut self, amount: u64) -> Result<(), TransactionError> {
+        let owner = self.owner.insecure_clone();
+        let accounts = accounts::DepositCollateral {
+            position: self.position,
+            user_token_account: self.owner_collateral_account,
+            vault: self.vault,
+            collateral_mint: self.collateral_mint,
+            market: self.market,
+            user: owner.pubkey(),
+            token_program: self.collateral_token_program,
+        };
+        self.process(accounts, instruction::DepositCollateral { amount }, &[&owner]).await
+    }
+
+    async fn borrow(&mut self, amount: u64) -> Result<(), TransactionError> {
+        let owner = self.owner.insecure_clone();
+        let accounts = accounts::Borrow {
+            position: self.position,
+            debt_vault: self.debt_vault,
+            user_token_account: self.owner_debt_account,
+            debt_mint: self.debt_mint,
+            vault_authority: self.vault_authority,
+            market: self.market,
+            oracle: self.oracle,
+            owner: owner.pubkey(),
+            token_program: self.debt_token_program,
+        };
+        self.process(accounts, instruction::Borrow { amount }, &[&owner]).await
+    }
+
+    async fn withdraw(&mut self, amount: u64) -> Result<(), TransactionError> {
+        let owner = self.owner.insecure_clone();
+        let accounts = accounts::WithdrawCollateral {
+            position: self.position,
+            vault: self.vault,
+            user_token_account: self.owner_collateral_account,
+            collateral_mint: self.collateral_mint,
+            vault_authority: self.vault_authority,
+            market: self.market,
+            oracle: self.oracle,
+            owner: owner.pubkey(),
+            token_program: self.collateral_token_program,
+        };
+        self.process(accounts, instruction::WithdrawCollateral { amount }, &[&owner]).await
+    }
+
+    async fn close_position(&mut self) -> Result<(), TransactionError> {
+        let owner = self.owner.insecure_clone();
+        let accounts = accounts::ClosePosition {
+            position: self.position,
+            vault: self.vault,
+            user_token_account: self.owner_collateral_account,
+            collateral_mint: self.collateral_mint,
+            vault_authority: self.vault_authority,
+            market: self.market,
+            owner: owner.pubkey(),
+            token_program: self.collateral_token_program,
+        };
+        self.process(accounts, instruction::ClosePosition {}, &[&owner]).await
+    }
+
+    async fn crank_close_position(&mut self) -> Result<(), TransactionError> {
+        let accounts = accounts::CrankClosePosition {
+            position: self.position,
+            insurance_fund_vault: self.insurance_fund_vault,
+            market: self.market,
+        };
+        self.process(accounts, instruction::CrankClosePosition {}, &[]).await
+    }
+
+    /// Accounts of a liquidation that passes every constraint
+    fn liquidate_accounts(&self) -> accounts::LiquidatePosition {
+        accounts::LiquidatePosition {
+            position: self.position,
+            vault: self.vault,
+            debt_vault: self.debt_vault,
+            liquidator_token_account: self.liquidator_debt_account,
+            liquidator_collateral_account: self.liquidator_collateral_account,
+            insurance_fund_vault: self.insurance_fund_vault,
+            collateral_mint: self.collateral_mint,
+            debt_mint: self.debt_mint,
+            vault_authority: self.vault_authority,
+            market: self.market,
+            oracle: self.oracle,
+            collateral_token_program: self.collateral_token_program,
+            debt_token_program: self.debt_token_program,
+            liquidator: self.liquidator.pubkey(),
+        }
+    }
+
+    /// Liquidate as the market's liquidator
+    async fn liquidate(
+        &mut self,
+        accounts: accounts::LiquidatePosition,
+        repay_amount: u64,
+    ) -> Result<(), TransactionError> {
+        let liquidator = self.liquidator.insecure_clone();
+        self.liquidate_as(&liquidator, accounts, repay_amount).await
+    }
+
+    async fn liquidate_as(
+        &mut self,
+        liquidator: &Keypair,
+        accounts: accounts::LiquidatePosition,
+        repay_amount: u64,
+    ) -> Result<(), TransactionError> {
+        self.process(accounts, instruction::Liquidate { repay_amount }, &[liquidator]).await
+    }
+
+    async fn set_margin_call_params(
+        &mut self,
+        grace_period_secs: u32,
+        warning_health_bps: u16,
+        instant_health_bps: u16,
+    ) -> Result<(), TransactionError> {
+        let authority = self.authority.insecure_clone();
+        let accounts = accounts::SetLiquidationParams {
+            market: self.market,
+            authority: authority.pubkey(),
+        };
+        let data = instruction::SetMarginCallParams {
+            grace_period_secs,
+            warning_health_bps,
+            instant_health_bps,
+        };
+        self.process(accounts, data, &[&authority]).await
+    }
+
+    async fn flag_for_liquidation(&mut self) -> Result<(), TransactionError> {
+        let liquidator = self.liquidator.insecure_clone();
+        let accounts = accounts::FlagForLiquidation {
+            position: self.position,
+            market: self.market,
+            oracle: self.oracle,
+            liquidator: liquidator.pubkey(),
+        };
+        self.process(accounts, instruction::FlagForLiquidation {}, &[&liquidator]).await
+    }
+
+    async fn clear_liquidation_flag(&mut self) -> Result<(), TransactionError> {
+        let accounts = accounts::ClearLiquidationFlag {
+            position: self.position,
+            market: self.market,
+            oracle: self.oracle,
+        };
+        self.process(accounts, instruction::ClearLiquidationFlag {}, &[]).await
+    }
+
+    /// Move the position's flag `secs` into the past, as if it had been
+    /// flagged that much earlier
+    async fn age_flag(&mut self, secs: i64) {
+        let mut position = self.position().await;
+        position.flagged_at -= secs;
+        self.add(self.position, program_account(&position));
+    }
+
+    /// Deposit all the owner's collateral and borrow `debt` against it
+    async fn open(&mut self, debt: u64) {
+        self.deposit(1_000).await.unwrap();
+        self.borrow(debt).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_borrow_and_withdraw() {
+    let mut market = TestMarket::new().await;
+    market.deposit(1_000).await.unwrap();
+    assert_eq!(market.balance(market.vault).await, 1_000);
+
+    // 1,000 units at 100.00 support up to 80,000 of debt
+    assert_eq!(market.borrow(80_001).await, rejected(LiquidationError::LoanToValueExceeded));
+    market.borrow(60_000).await.unwrap();
+    assert_eq!(market.position().await.debt, 60_000);
+    assert_eq!(market.balance(market.owner_debt_account).await, 60_000);
+    assert_eq!(market.balance(market.debt_vault).await, 940_000);
+
+    // Withdrawals may take the position down to, but not past, the maximum
+    // loan-to-value
+    assert_eq!(market.withdraw(251).await, rejected(LiquidationError::WithdrawalUnhealthy));
+    market.withdraw(250).await.unwrap();
+    assert_eq!(market.position().await.collateral, 750);
+    assert_eq!(market.balance(market.owner_collateral_account).await, 250);
+    assert_eq!(market.withdraw(751).await, rejected(LiquidationError::InsufficientCollateral));
+}
+
+#[tokio::test]
+async fn test_withdraw_past_loan_to_value_rejected() {
+    // Borrowed up to the maximum loan-to-value, no collateral can leave, even
+    // though 999 units would still be far from liquidation
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    assert_eq!(market.withdraw(1).await, rejected(LiquidationError::WithdrawalUnhealthy));
+    assert_eq!(market.position().await.collateral, 1_000);
+    assert_eq!(market.balance(market.owner_collateral_account).await, 0);
+
+    // Nor can a flagged position withdraw collateral its debt doesn't need,
+    // until the flag is cleared
+    let mut market = TestMarket::new().await;
+    market.open(40_000).await;
+    market
+        .set_margin_call_params(600, DEFAULT_WARNING_HEALTH_BPS, DEFAULT_INSTANT_HEALTH_BPS)
+        .await
+        .unwrap();
+    market.set_price(4_800_000_000);
+    market.flag_for_liquidation().await.unwrap();
+    market.set_price(10_000_000_000);
+    assert_eq!(market.withdraw(100).await, rejected(LiquidationError::PositionFlagged));
+    market.clear_liquidation_flag().await.unwrap();
+    market.withdraw(100).await.unwrap();
+    assert_eq!(market.position().await.collateral, 900);
+}
+
+#[tokio::test]
+async fn test_borrow_for_another_owner_rejected() {
+    let mut market = TestMarket::new().await;
+    market.deposit(1_000).await.unwrap();
+
+    let thief = Keypair::new();
+    let account = market.token_account(market.debt_mint, thief.pubkey(), 0).await;
+    market.add(market.owner_debt_account, account);
+    let owner = std::mem::replace(&mut market.owner, thief);
+    assert_eq!(market.borrow(1_000).await, rejected(ErrorCode::ConstraintSeeds));
+    market.owner = owner;
+    assert_eq!(market.position().await.debt, 0);
+}
+
+#[tokio::test]
+async fn test_liquidate_after_price_drop() {
+    let mut market = TestMarket::new().await;
+    market.open(68_000).await;
+
+    // At 80.00 the collateral counts for exactly the debt, a health of 100%,
+    // which isn't liquidatable
+    market.set_price(8_000_000_000);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(LiquidationError::PositionHealthy)
+    );
+
+    market.set_price(7_600_000_000);
+    market.take_events::<PositionLiquidated>();
+    market.liquidate(market.liquidate_accounts(), 20_000).await.unwrap();
+    // 20,000 plus the 5% bonus buys 276 units at 76.00
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt), (724, 48_000));
+    assert_eq!(market.balance(market.liquidator_debt_account).await, 980_000);
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 276);
+    assert_eq!(market.balance(market.debt_vault).await, 952_000);
+    assert_eq!(market.balance(market.vault).await, 724);
+
+    let events = market.take_events::<PositionLiquidated>();
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        (events[0].position, events[0].liquidator),
+        (market.position, market.liquidator.pubkey())
+    );
+    assert_eq!(
+        (events[0].repay_amount, events[0].collateral_seized, events[0].remaining_debt, events[0].timestamp),
+        (20_000, 276, 48_000, NOW)
+    );
+}
+
+#[tokio::test]
+async fn test_mints_of_different_decimals() {
+    // The price is of a whole token: at 100.00, the owner's 1,000 base units
+    // of a 9-decimal collateral are worth 100 base units of a 6-decimal debt
+    let mut market = TestMarket::with_decimals(MintDecimals { collateral: 9, debt: 6 }).await;
+    market.deposit(1_000).await.unwrap();
+    assert_eq!(market.borrow(81).await, rejected(LiquidationError::LoanToValueExceeded));
+    market.borrow(80).await.unwrap();
+
+    // At 90.00 they count for 76.5 of debt, and 20 plus the 5% bonus buys 233
+    // base units
+    market.set_price(9_000_000_000);
+    market.liquidate(market.liquidate_accounts(), 20).await.unwrap();
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt), (767, 60));
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 233);
+}
+
+#[tokio::test]
+async fn test_deposit_emits_event() {
+    let mut market = TestMarket::new().await;
+    market.deposit(600).await.unwrap();
+    market.deposit(400).await.unwrap();
+
+    let events = market.take_events::<PositionDeposited>();
+    let deposits: Vec<_> = events.iter().map(|e| (e.position, e.owner, e.amount, e.new_collateral)).collect();
+    let owner = market.owner.pubkey();
+    assert_eq!(deposits, [(market.position, owner, 600, 600), (market.position, owner, 400, 1_000)]);
+}
+
+#[tokio::test]
+async fn test_close_factor() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+
+    // At most half of the 80,000 of debt per liquidation
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 40_001).await,
+        rejected(LiquidationError::CloseFactorExceeded)
+    );
+    market.liquidate(market.liquidate_accounts(), 40_000).await.unwrap();
+    assert_eq!(market.position().await.debt, 40_000);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_001).await,
+        rejected(LiquidationError::CloseFactorExceeded)
+    );
+}
+
+#[tokio::test]
+async fn test_zero_repay_rejected() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 0).await,
+        rejected(LiquidationError::InvalidAmount)
+    );
+    assert_eq!(market.position().await.debt, 80_000);
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 0);
+}
+
+#[tokio::test]
+async fn test_adversarial_liquidations_rejected() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    let authority = market.authority.insecure_clone();
+    market.set_liquidation_params(&authority, 10_000, 500).await.unwrap();
+
+    // Collateral isn't given away at a zero price
+    market.set_price(0);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(LiquidationError::InvalidPrice)
+    );
+    // Even with a close factor of 100%, no more than the debt is repaid
+    market.set_price(8_500_000_000);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 80_001).await,
+        rejected(LiquidationError::CloseFactorExceeded)
+    );
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt), (1_000, 80_000));
+    assert_eq!(market.balance(market.liquidator_debt_account).await, 1_000_000);
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 0);
+}
+
+#[tokio::test]
+async fn test_deposit_overflow_rejected() {
+    let mut market = TestMarket::new().await;
+    let mut position = market.position().await;
+    position.collateral = u64::MAX - 999;
+    market.add(market.position, program_account(&position));
+
+    // Topping the collateral up to u64::MAX is fine, past it isn't
+    assert_eq!(market.deposit(1_000).await, rejected(LiquidationError::MathOverflow));
+    assert_eq!(market.balance(market.owner_collateral_account).await, 1_000);
+    market.deposit(999).await.unwrap();
+    assert_eq!(market.position().await.collateral, u64::MAX);
+    assert_eq!(market.deposit(1).await, rejected(LiquidationError::MathOverflow));
+}
+
+#[tokio::test]
+async fn test_seizure_capped_at_collateral() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.fund_insurance(50_000).await.unwrap();
+    assert_eq!(market.balance(market.insurance_fund_vault).await, 50_000);
+
+    // 1,000 units at 10.00 are worth far less than the 42,000 owed for
+    // repaying 40,000
+    market.set_price(1_000_000_000);
+    market.liquidate(market.liquidate_accounts(), 40_000).await.unwrap();
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 1_000);
+    assert_eq!(market.balance(market.vault).await, 0);
+}
+
+#[tokio::test]
+async fn test_bad_debt_covered_by_insurance_fund() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.fund_insurance(50_000).await.unwrap();
+
+    // The 40,000 left owed once the collateral is gone comes out of the
+    // insurance fund, making the debt vault whole
+    market.set_price(1_000_000_000);
+    market.liquidate(market.liquidate_accounts(), 40_000).await.unwrap();
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt, position.closed), (0, 0, true));
+    assert_eq!(market.balance(market.insurance_fund_vault).await, 10_000);
+    assert_eq!(market.balance(market.debt_vault).await, 1_000_000);
+
+    let events = market.take_events::<BadDebtCovered>();
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        (events[0].position, events[0].owner, events[0].amount),
+        (market.position, market.owner.pubkey(), 40_000)
+    );
+
+    // A closed position takes no more collateral or debt, nor gives any back
+    assert_eq!(market.deposit(1).await, rejected(LiquidationError::PositionClosed));
+    assert_eq!(market.borrow(1).await, rejected(LiquidationError::PositionClosed));
+    assert_eq!(market.withdraw(0).await, rejected(LiquidationError::PositionClosed));
+}
+
+#[tokio::test]
+async fn test_underwater_position_liquidated_to_bad_debt() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.fund_insurance(50_000).await.unwrap();
+
+    // 1,000 units at 50.00 are worth less than the 80,000 owed: the 40,000 the
+    // close factor allows buys 840 units, leaving 160 against 40,000 of debt,
+    // less healthy than before, as does anything short of all the collateral
+    market.set_price(5_000_000_000);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 40_000).await,
+        rejected(LiquidationError::LiquidationWorsenedHealth)
+    );
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 47_619).await,
+        rejected(LiquidationError::LiquidationWorsenedHealth)
+    );
+
+    // so the close factor is lifted, and 47,620 plus its bonus buys all of it,
+    // closing the position with the 32,380 left over covered by the insurance
+    // fund
+    market.take_events::<PositionLiquidated>();
+    market.liquidate(market.liquidate_accounts(), 47_620).await.unwrap();
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt, position.closed), (0, 0, true));
+    assert_eq!(market.balance(market.liquidator_debt_account).await, 952_380);
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 1_000);
+    assert_eq!(market.balance(market.insurance_fund_vault).await, 17_620);
+    assert_eq!(market.balance(market.debt_vault).await, 1_000_000);
+
+    let events = market.take_events::<BadDebtCovered>();
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        (events[0].position, events[0].owner, events[0].amount),
+        (market.position, market.owner.pubkey(), 32_380)
+    );
+    let events = market.take_events::<PositionLiquidated>();
+    assert_eq!(
+        (events[0].repay_amount, events[0].collateral_seized, events[0].remaining_debt),
+        (47_620, 1_000, 0)
+    );
+}
+
+#[tokio::test]
+async fn test_token_2022_debt_with_transfer_fee() {
+    // A quote asset withholding 1% of every transfer
+    let mut market = TestMarket::with_mints(TestMint::Classic, TestMint::Token2022 { transfer_fee_bps: 100 }).await;
+    market.open(80_000).await;
+    // The position owes all it borrowed, though the owner received 99% of it
+    assert_eq!(market.position().await.debt, 80_000);
+    assert_eq!(market.balance(market.owner_debt_account).await, 79_200);
+    assert_eq!(market.withheld(market.owner_debt_account).await, 800);
+
+    // Only the 19,800 the debt vault receives of the 20,000 repaid counts
+    // against debt and is rewarded: with the 5% bonus it buys 244 units at 85.00
+    market.set_price(8_500_000_000);
+    market.take_events::<PositionLiquidated>();
+    market.liquidate(market.liquidate_accounts(), 20_000).await.unwrap();
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt), (756, 60_200));
+    assert_eq!(market.balance(market.liquidator_debt_account).await, 980_000);
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 244);
+    assert_eq!(market.balance(market.debt_vault).await, 939_800);
+    assert_eq!(market.withheld(market.debt_vault).await, 200);
+
+    let events = market.take_events::<PositionLiquidated>();
+    assert_eq!(
+        (events[0].repay_amount, events[0].collateral_seized, events[0].remaining_debt),
+        (19_800, 244, 60_200)
+    );
+}
+
+#[tokio::test]
+async fn test_token_2022_bad_debt_grossed_up() {
+    let mut market = TestMarket::with_mints(TestMint::Classic, TestMint::Token2022 { transfer_fee_bps: 100 }).await;
+    market.open(80_000).await;
+    market.fund_insurance(50_000).await.unwrap();
+    assert_eq!(market.balance(market.insurance_fund_vault).await, 49_500);
+
+    // 39,600 of the 40,000 repaid arrives, leaving 40,400 owed once the
+    // collateral is gone; the insurance fund sends 40,809 so the debt vault
+    // receives all of it
+    market.set_price(1_000_000_000);
+    market.liquidate(market.liquidate_accounts(), 40_000).await.unwrap();
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt, position.closed), (0, 0, true));
+    assert_eq!(market.balance(market.insurance_fund_vault).await, 8_691);
+    assert_eq!(market.balance(market.debt_vault).await, 1_000_000);
+    assert_eq!(market.take_events::<BadDebtCovered>()[0].amount, 40_400);
+}
+
+#[tokio::test]
+async fn test_token_2022_collateral_with_transfer_fee() {
+    let mut market = TestMarket::with_mints(TestMint::Token2022 { transfer_fee_bps: 100 }, TestMint::Classic).await;
+    // The position is credited the 990 the vault receives
+    market.deposit(1_000).await.unwrap();
+    assert_eq!(market.position().await.collateral, 990);
+    assert_eq!(market.balance(market.vault).await, 990);
+    let events = market.take_events::<PositionDeposited>();
+    assert_eq!((events[0].amount, events[0].new_collateral), (990, 990));
+
+    // The seizure leaves the position whole; the liquidator bears the fee
+    market.borrow(79_200).await.unwrap();
+    market.set_price(8_500_000_000);
+    market.liquidate(market.liquidate_accounts(), 20_000).await.unwrap();
+    assert_eq!(market.position().await.collateral, 743);
+    assert_eq!(market.balance(market.vault).await, 743);
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 244);
+    assert_eq!(market.withheld(market.liquidator_collateral_account).await, 3);
+}
+
+#[tokio::test]
+async fn test_token_program_mismatch_rejected() {
+    let mut market = TestMarket::with_mints(TestMint::Classic, TestMint::Token2022 { transfer_fee_bps: 100 }).await;
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+
+    // The debt's token program passed for the collateral
+    let mut accounts = market.liquidate_accounts();
+    accounts.collateral_token_program = spl_token_2022::ID;
+    assert_eq!(
+        market.liquidate(accounts, 20_000).await,
+        rejected(ErrorCode::ConstraintTokenTokenProgram)
+    );
+
+    // A classic token account passed for the Token-2022 debt
+    let account = token_account(spl_token::ID, market.debt_mint, market.liquidator.pubkey(), 1_000_000);
+    market.add(market.liquidator_debt_account, account);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(ErrorCode::ConstraintTokenTokenProgram)
+    );
+}
+
+#[tokio::test]
+async fn test_owner_closes_position() {
+    let mut market = TestMarket::new().await;
+    market.deposit(600).await.unwrap();
+    market.take_events::<PositionClosed>();
+
+    // The collateral left goes back to the owner along with the rent
+    let rent = market.lamports(market.position).await;
+    let lamports = market.lamports(market.owner.pubkey()).await;
+    market.close_position().await.unwrap();
+    assert_eq!(market.balance(market.owner_collateral_account).await, 1_000);
+    assert_eq!(market.balance(market.vault).await, 0);
+    assert!(market.account(market.position).await.is_none());
+    assert_eq!(market.lamports(market.owner.pubkey()).await, lamports + rent);
+    assert_eq!(
+        market.take_events::<PositionClosed>(),
+        [PositionClosed {
+            position: market.position,
+            owner: market.owner.pubkey(),
+            collateral_returned: 600,
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_close_with_debt_rejected() {
+    let mut market = TestMarket::new().await;
+    market.open(50_000).await;
+    assert_eq!(market.close_position().await, rejected(LiquidationError::DebtOutstanding));
+    assert_eq!(market.balance(market.vault).await, 1_000);
+
+    // Nor can it be cranked closed while it's live
+    assert_eq!(market.crank_close_position().await, rejected(LiquidationError::PositionNotClosed));
+    assert_eq!(market.position().await.debt, 50_000);
+}
+
+#[tokio::test]
+async fn test_crank_close_after_full_liquidation() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.fund_insurance(50_000).await.unwrap();
+    market.set_price(1_000_000_000);
+    market.liquidate(market.liquidate_accounts(), 40_000).await.unwrap();
+    market.take_events::<PositionClosed>();
+
+    // Anyone may close the emptied account, its rent going to the insurance
+    // fund
+    let rent = market.lamports(market.position).await;
+    let lamports = market.lamports(market.insurance_fund_vault).await;
+    market.crank_close_position().await.unwrap();
+    assert!(market.account(market.position).await.is_none());
+    assert_eq!(market.lamports(market.insurance_fund_vault).await, lamports + rent);
+    assert_eq!(market.balance(market.insurance_fund_vault).await, 10_000);
+    assert_eq!(market.take_events::<PositionClosed>().len(), 1);
+}
+
+#[tokio::test]
+async fn test_insurance_fund_insufficient() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.fund_insurance(39_999).await.unwrap();
+
+    market.set_price(1_000_000_000);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 40_000).await,
+        rejected(LiquidationError::InsuranceFundInsufficient)
+    );
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt, position.closed), (1_000, 80_000, false));
+    assert_eq!(market.balance(market.insurance_fund_vault).await, 39_999);
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 0);
+    assert!(market.take_events::<BadDebtCovered>().is_empty());
+
+    // Liquidations leaving collateral behind need no cover
+    market.set_price(8_500_000_000);
+    market.liquidate(market.liquidate_accounts(), 40_000).await.unwrap();
+    assert_eq!(market.position().await.debt, 40_000);
+    assert_eq!(market.balance(market.insurance_fund_vault).await, 39_999);
+}
+
+#[tokio::test]
+async fn test_fund_insurance_requires_authority() {
+    let mut market = TestMarket::new().await;
+    market.authority = Keypair::new();
+    assert_eq!(market.fund_insurance(1_000).await, rejected(ErrorCode::ConstraintHasOne));
+}
+
+#[tokio::test]
+async fn test_set_liquidation_params() {
+    let mut market = TestMarket::new().await;
+    let authority = market.authority.insecure_clone();
+    market.set_liquidation_params(&authority, 10_000, 1_000).await.unwrap();
+    let params = market.market().await;
+    assert_eq!((params.close_factor_bps, params.liquidation_bonus_bps), (10_000, 1_000));
+
+    assert_eq!(
+        market.set_liquidation_params(&authority, 0, 1_000).await,
+        rejected(LiquidationError::InvalidParameter)
+    );
+    assert_eq!(
+        market.set_liquidation_params(&authority, 10_001, 1_000).await,
+        rejected(LiquidationError::InvalidParameter)
+    );
+    assert_eq!(
+        market
+            .set_liquidation_params(&authority, 5_000, MAX_LIQUIDATION_BONUS_BPS + 1)
+            .await,
+        rejected(LiquidationError::InvalidParameter)
+    );
+
+    let intruder = Keypair::new();
+    assert_eq!(
+        market.set_liquidation_params(&intruder, 5_000, 500).await,
+        rejected(ErrorCode::ConstraintHasOne)
+    );
+
+    // The whole debt may be repaid at once with a close factor of 100%
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+    market.liquidate(market.liquidate_accounts(), 80_000).await.unwrap();
+    assert_eq!(market.position().await.debt, 0);
+}
+
+/// Liquidation accounts of a keeper other than the market's liquidator,
+/// funded to repay
+async fn other_keeper_accounts(market: &mut TestMarket, keeper: Pubkey) -> accounts::LiquidatePosition {
+    let [debt_account, collateral_account] = [(); 2].map(|_| Pubkey::new_unique());
+    let account = market.token_account(market.debt_mint, keeper, 1_000_000).await;
+    market.add(debt_account, account);
+    let account = market.token_account(market.collateral_mint, keeper, 0).await;
+    market.add(collateral_account, account);
+    accounts::LiquidatePosition {
+        liquidator_token_account: debt_account,
+        liquidator_collateral_account: collateral_account,
+        liquidator: keeper,
+        ..market.liquidate_accounts()
+    }
+}
+
+#[tokio::test]
+async fn test_keeper_whitelist() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+    let keeper = Keypair::new();
+
+    // Only whitelisted keepers may liquidate
+    let accounts = other_keeper_accounts(&mut market, keeper.pubkey()).await;
+    assert_eq!(
+        market.liquidate_as(&keeper, accounts, 10_000).await,
+        rejected(LiquidationError::KeeperNotWhitelisted)
+    );
+    market.liquidate(market.liquidate_accounts(), 10_000).await.unwrap();
+
+    market.add_keeper(keeper.pubkey()).await.unwrap();
+    assert_eq!(
+        market.add_keeper(keeper.pubkey()).await,
+        rejected(LiquidationError::KeeperAlreadyWhitelisted)
+    );
+    let accounts = other_keeper_accounts(&mut market, keeper.pubkey()).await;
+    market.liquidate_as(&keeper, accounts, 10_000).await.unwrap();
+    assert_eq!(market.position().await.debt, 60_000);
+
+    market.remove_keeper(keeper.pubkey()).await.unwrap();
+    assert_eq!(
+        market.remove_keeper(keeper.pubkey()).await,
+        rejected(LiquidationError::KeeperNotWhitelisted)
+    );
+    assert_eq!(market.market().await.keepers, [market.liquidator.pubkey()]);
+    let accounts = other_keeper_accounts(&mut market, keeper.pubkey()).await;
+    assert_eq!(
+        market.liquidate_as(&keeper, accounts, 10_000).await,
+        rejected(LiquidationError::KeeperNotWhitelisted)
+    );
+
+    // The whitelist is bounded
+    for _ in 1..MAX_KEEPERS {
+        market.add_keeper(Pubkey::new_unique()).await.unwrap();
+    }
+    assert_eq!(
+        market.add_keeper(keeper.pubkey()).await,
+        rejected(LiquidationError::KeeperWhitelistFull)
+    );
+}
+
+#[tokio::test]
+async fn test_liquidation_mode_switching() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+    let keeper = Keypair::new();
+
+    let intruder = Keypair::new();
+    assert_eq!(
+        market.set_liquidation_mode(&intruder, LiquidationMode::Permissionless).await,
+        rejected(ErrorCode::ConstraintHasOne)
+    );
+    assert_eq!(market.market().await.liquidation_mode, LiquidationMode::Whitelisted);
+
+    // Once permissionless anyone may liquidate, until the authority closes it
+    // again
+    let authority = market.authority.insecure_clone();
+    market.set_liquidation_mode(&authority, LiquidationMode::Permissionless).await.unwrap();
+    let accounts = other_keeper_accounts(&mut market, keeper.pubkey()).await;
+    market.liquidate_as(&keeper, accounts, 10_000).await.unwrap();
+    assert_eq!(market.position().await.debt, 70_000);
+
+    market.set_liquidation_mode(&authority, LiquidationMode::Whitelisted).await.unwrap();
+    let accounts = other_keeper_accounts(&mut market, keeper.pubkey()).await;
+    assert_eq!(
+        market.liquidate_as(&keeper, accounts, 10_000).await,
+        rejected(LiquidationError::KeeperNotWhitelisted)
+    );
+}
+
+#[tokio::test]
+async fn test_forged_vault_rejected() {
+    // A token account of the liquidator's own passed as the vault
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+    let forged = Pubkey::new_unique();
+    let account = market.token_account(market.collateral_mint, market.vault_authority, 1_000).await;
+    market.add(forged, account);
+    let accounts = accounts::LiquidatePosition { vault: forged, ..market.liquidate_accounts() };
+    assert_eq!(market.liquidate(accounts, 20_000).await, rejected(ErrorCode::ConstraintAddress));
+    let accounts = accounts::LiquidatePosition { debt_vault: forged, ..market.liquidate_accounts() };
+    assert_eq!(market.liquidate(accounts, 20_000).await, rejected(ErrorCode::ConstraintAddress));
+
+    // The vault's address but not under the vault authority
+    let account = market.token_account(market.collateral_mint, Pubkey::new_unique(), 1_000).await;
+    market.add(market.vault, account);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(ErrorCode::ConstraintTokenOwner)
+    );
+
+    // A reward paid out in another mint
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+    let account = token_account(spl_token::ID, Pubkey::new_unique(), market.liquidator.pubkey(), 0);
+    market.add(market.liquidator_collateral_account, account);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(ErrorCode::ConstraintTokenMint)
+    );
+}
+
+#[tokio::test]
+async fn test_positions_keyed_by_market() {
+    let mut market = TestMarket::new().await;
+    market.deposit(1_000).await.unwrap();
+
+    // Another market of the program, at the address of its own id
+    let mut state = market.market().await;
+    state.market_id = MARKET_ID + 1;
+    let (other, bump) = pda(&[b"market", &state.market_id.to_le_bytes()]);
+    state.bump = bump;
+    let mut account = program_account(&state);
+    account.data.resize(Market::SPACE, 0);
+    market.add(other, account);
+
+    // Collateral deposited in one market backs no debt in another
+    let owner = market.owner.insecure_clone();
+    let accounts = accounts::Borrow {
+        position: market.position,
+        debt_vault: market.debt_vault,
+        user_token_account: market.owner_debt_account,
+        debt_mint: market.debt_mint,
+        vault_authority: market.vault_authority,
+        market: other,
+        oracle: market.oracle,
+        owner: owner.pubkey(),
+        token_program: market.debt_token_program,
+    };
+    assert_eq!(
+        market.process(accounts, instruction::Borrow { amount: 80_000 }, &[&owner]).await,
+        rejected(ErrorCode::ConstraintSeeds)
+    );
+    market.borrow(80_000).await.unwrap();
+    assert_eq!(market.position().await.debt, 80_000);
+}
+
+#[tokio::test]
+async fn test_new_position_through_liquidation() {
+    let mut market = TestMarket::without_position().await;
+    market.initialize_position().await.unwrap();
+    assert_eq!(market.position().await.owner, market.owner.pubkey());
+
+    // Lenders' liquidity, which no instruction of the program supplies
+    let (debt_mint, debt_vault) = (market.debt_mint, market.debt_vault);
+    market.mint_to(debt_mint, debt_vault, 1_000_000).await.unwrap();
+    assert_eq!(market.balance(debt_vault).await, 2_000_000);
+
+    // Deposits only go to the market's vault
+    let forged = Pubkey::new_unique();
+    let account = market.token_account(market.collateral_mint, market.vault_authority, 0).await;
+    market.add(forged, account);
+    let owner = market.owner.insecure_clone();
+    let accounts = accounts::DepositCollateral {
+        position: market.position,
+        user_token_account: market.owner_collateral_account,
+        vault: forged,
+        collateral_mint: market.collateral_mint,
+        market: market.market,
+        user: owner.pubkey(),
+        token_program: spl_token::ID,
+    };
+    assert_eq!(
+        market.process(accounts, instruction::DepositCollateral { amount: 1_000 }, &[&owner]).await,
+        rejected(ErrorCode::ConstraintAddress)
+    );
+    market.open(80_000).await;
+    assert_eq!(market.balance(market.vault).await, 1_000);
+
+    // At 85.00, 20,000 repaid plus a 5% bonus is worth 247 units
+    market.set_price(8_500_000_000);
+    market.liquidate(market.liquidate_accounts(), 20_000).await.unwrap();
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt), (753, 60_000));
+    assert_eq!(market.balance(market.vault).await, 753);
+    assert_eq!(market.balance(market.liquidator_collateral_account).await, 247);
+    assert_eq!(market.balance(market.debt_vault).await, 1_940_000);
+}
+
+#[tokio::test]
+async fn test_wrong_pda_position_rejected() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+
+    // A copy of the position at an address that isn't its owner's PDA
+    let forged = Pubkey::new_unique();
+    let account = market.account(market.position).await.unwrap();
+    market.add(forged, account);
+    let accounts = accounts::LiquidatePosition { position: forged, ..market.liquidate_accounts() };
+    assert_eq!(market.liquidate(accounts, 20_000).await, rejected(ErrorCode::ConstraintSeeds));
+}
+
+#[tokio::test]
+async fn test_wrong_insurance_fund_and_authority_rejected() {
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.set_price(8_500_000_000);
+
+    let forged = Pubkey::new_unique();
+    let account = market.token_account(market.debt_mint, market.liquidator.pubkey(), 0).await;
+    market.add(forged, account);
+    let accounts = accounts::LiquidatePosition { insurance_fund_vault: forged, ..market.liquidate_accounts() };
+    assert_eq!(market.liquidate(accounts, 20_000).await, rejected(ErrorCode::ConstraintAddress));
+
+    // A vault authority of the liquidator's own, even one holding the vaults
+    let forged = Pubkey::new_unique();
+    let vaults = [
+        (market.vault, market.collateral_mint),
+        (market.debt_vault, market.debt_mint),
+        (market.insurance_fund_vault, market.debt_mint),
+    ];
+    for (vault, mint) in vaults {
+        let account = market.token_account(mint, forged, 1_000_000).await;
+        market.add(vault, account);
+    }
+    let accounts = accounts::LiquidatePosition { vault_authority: forged, ..market.liquidate_accounts() };
+    assert_eq!(market.liquidate(accounts, 20_000).await, rejected(ErrorCode::ConstraintSeeds));
+}
+
+#[tokio::test]
+async fn test_set_margin_call_params() {
+    let mut market = TestMarket::new().await;
+    market.set_margin_call_params(600, 11_000, 8_000).await.unwrap();
+    let params = market.market().await;
+    assert_eq!(
+        (params.grace_period_secs, params.warning_health_bps, params.instant_health_bps),
+        (600, 11_000, 8_000)
+    );
+
+    // Flagging starts above the liquidation threshold, instant liquidation below
+    assert_eq!(
+        market.set_margin_call_params(600, LIQUIDATION_HEALTH_BPS - 1, 8_000).await,
+        rejected(LiquidationError::InvalidParameter)
+    );
+    assert_eq!(
+        market.set_margin_call_params(600, 11_000, LIQUIDATION_HEALTH_BPS + 1).await,
+        rejected(LiquidationError::InvalidParameter)
+    );
+}
+
+#[tokio::test]
+async fn test_margin_call_above_warning() {
+    // Borrowed up to the maximum loan-to-value at 100.00, a health of 106.25%,
+    // above the 105% warning threshold
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market
+        .set_margin_call_params(600, DEFAULT_WARNING_HEALTH_BPS, DEFAULT_INSTANT_HEALTH_BPS)
+        .await
+        .unwrap();
+    assert_eq!(market.flag_for_liquidation().await, rejected(LiquidationError::PositionHealthy));
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(LiquidationError::PositionHealthy)
+    );
+    assert!(!market.position().await.is_flagged());
+}
+
+#[tokio::test]
+async fn test_margin_call_warning_band() {
+    // A health of 102% at 96.00 may be flagged but not liquidated
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market
+        .set_margin_call_params(600, DEFAULT_WARNING_HEALTH_BPS, DEFAULT_INSTANT_HEALTH_BPS)
+        .await
+        .unwrap();
+    market.set_price(9_600_000_000);
+    market.flag_for_liquidation().await.unwrap();
+    let position = market.position().await;
+    assert_eq!(
+        (position.flagged_at, position.flag_price, position.flag_price_expo),
+        (NOW, 9_600_000_000, -8)
+    );
+    assert_eq!(
+        market.take_events::<PositionFlagged>(),
+        [PositionFlagged {
+            position: market.position,
+            owner: market.owner.pubkey(),
+            liquidator: market.liquidator.pubkey(),
+            price: 9_600_000_000,
+            expo: -8,
+            flagged_at: NOW,
+            grace_ends_at: NOW + 600,
+        }]
+    );
+    assert_eq!(market.flag_for_liquidation().await, rejected(LiquidationError::AlreadyFlagged));
+
+    // Past its grace period it still isn't liquidatable
+    market.age_flag(600).await;
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(LiquidationError::PositionHealthy)
+    );
+}
+
+#[tokio::test]
+async fn test_margin_call_liquidation_band() {
+    // A health of ~95.6% at 90.00: liquidatable, but only once flagged and its
+    // grace period is over
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market
+        .set_margin_call_params(600, DEFAULT_WARNING_HEALTH_BPS, DEFAULT_INSTANT_HEALTH_BPS)
+        .await
+        .unwrap();
+    market.set_price(9_000_000_000);
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(LiquidationError::NotFlagged)
+    );
+    market.flag_for_liquidation().await.unwrap();
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(LiquidationError::GracePeriodActive)
+    );
+    market.age_flag(599).await;
+    assert_eq!(
+        market.liquidate(market.liquidate_accounts(), 20_000).await,
+        rejected(LiquidationError::GracePeriodActive)
+    );
+    market.age_flag(1).await;
+    market.liquidate(market.liquidate_accounts(), 20_000).await.unwrap();
+
+    // 21,000 buys 233 units, leaving a health of ~97.8%: still flagged, so the
+    // next liquidation needn't wait
+    let position = market.position().await;
+    assert_eq!((position.collateral, position.debt), (767, 60_000));
+    assert!(position.is_flagged());
+    assert_eq!(
+        market.clear_liquidation_flag().await,
+        rejected(LiquidationError::PositionBelowWarning)
+    );
+
+    // Back above the warning threshold anyone may clear the flag
+    market.set_price(10_000_000_000);
+    market.clear_liquidation_flag().await.unwrap();
+    assert!(!market.position().await.is_flagged());
+    assert_eq!(market.take_events::<LiquidationFlagCleared>().len(), 1);
+    assert_eq!(market.clear_liquidation_flag().await, rejected(LiquidationError::NotFlagged));
+}
+
+#[tokio::test]
+async fn test_margin_call_instant_band() {
+    // A health of ~89.8% at 84.50 is below the instant threshold, so it's
+    // liquidated without being flagged
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market
+        .set_margin_call_params(600, DEFAULT_WARNING_HEALTH_BPS, DEFAULT_INSTANT_HEALTH_BPS)
+        .await
+        .unwrap();
+    market.set_price(8_450_000_000);
+    market.liquidate(market.liquidate_accounts(), 20_000).await.unwrap();
+    assert_eq!(market.position().await.debt, 60_000);
+
+    // Without a grace period every liquidatable position goes at once
+    let mut market = TestMarket::new().await;
+    market.open(80_000).await;
+    market.set_price(9_000_000_000);
+    market.liquidate(market.liquidate_accounts(), 20_000).await.unwrap();
+}